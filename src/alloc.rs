@@ -10,12 +10,32 @@
 //!
 //! Call the `exit_boot_services` function before exiting UEFI boot services.
 //! Failure to do so will turn subsequent allocation into undefined behaviour.
+//!
+//! # Statistics
+//!
+//! With the `alloc-stats` feature, the allocator counts the allocations and
+//! the bytes in use, which `stats` returns. Comparing them before and after
+//! an operation finds the allocations it leaks.
+//!
+//! # Debugging
+//!
+//! With the `alloc-debug` feature, the allocator surrounds the allocations
+//! with canaries, poisons allocated and freed memory, and checks the canaries
+//! when memory is freed, when `check_heap` is called, and when boot services
+//! are exited, to catch buffer overruns. `set_debug_mode` can also place each
+//! allocation right before a guard page instead, which faults on overruns if
+//! the firmware supports the Memory Attribute protocol.
 
 use core::alloc::{GlobalAlloc, Layout};
-use core::ptr::{self, NonNull};
+#[cfg(not(feature = "alloc-debug"))]
+use core::ptr;
+use core::ptr::NonNull;
+#[cfg(feature = "alloc-stats")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::prelude::*;
-use crate::table::boot::{BootServices, MemoryType};
+use crate::table::boot::BootServices;
+#[cfg(not(feature = "alloc-debug"))]
+use crate::{prelude::*, table::boot::MemoryType};
 
 /// Reference to the boot services table, used to call the pool memory allocation functions.
 ///
@@ -42,6 +62,8 @@ fn boot_services() -> NonNull<BootServices> {
 ///
 /// You must arrange for this function to be called on exit from UEFI boot services
 pub fn exit_boot_services() {
+    #[cfg(feature = "alloc-debug")]
+    check_heap();
     unsafe {
         BOOT_SERVICES = None;
     }
@@ -55,6 +77,42 @@ pub struct Allocator;
 #[allow(clippy::cast_ptr_alignment)]
 unsafe impl GlobalAlloc for Allocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(feature = "alloc-debug")]
+        let ptr = debug::alloc(layout);
+        #[cfg(not(feature = "alloc-debug"))]
+        let ptr = Self::alloc_pool(layout);
+        #[cfg(feature = "alloc-stats")]
+        if !ptr.is_null() {
+            STATS.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "alloc-stats")]
+        STATS.record_dealloc(layout.size());
+        #[cfg(feature = "alloc-debug")]
+        debug::dealloc(ptr, layout);
+        #[cfg(not(feature = "alloc-debug"))]
+        Self::free_pool(ptr, layout);
+    }
+}
+
+#[cfg(not(feature = "alloc-debug"))]
+impl Allocator {
+    unsafe fn free_pool(mut ptr: *mut u8, layout: Layout) {
+        if layout.align() > 8 {
+            ptr = (ptr as *const *mut u8).sub(1).read();
+        }
+        boot_services()
+            .as_ref()
+            .free_pool(ptr)
+            .warning_as_error()
+            .unwrap();
+    }
+
+    #[allow(clippy::cast_ptr_alignment)]
+    unsafe fn alloc_pool(layout: Layout) -> *mut u8 {
         let mem_ty = MemoryType::LOADER_DATA;
         let size = layout.size();
         let align = layout.align();
@@ -87,16 +145,307 @@ unsafe impl GlobalAlloc for Allocator {
                 .unwrap_or(ptr::null_mut())
         }
     }
+}
 
-    unsafe fn dealloc(&self, mut ptr: *mut u8, layout: Layout) {
-        if layout.align() > 8 {
-            ptr = (ptr as *const *mut u8).sub(1).read();
+/// Statistics of the global allocator, as returned by `stats`
+#[cfg(feature = "alloc-stats")]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct AllocStats {
+    /// Bytes currently allocated
+    pub current_bytes: usize,
+    /// Largest number of bytes allocated at once, since the start or since
+    /// the last call to `reset_peak`
+    pub peak_bytes: usize,
+    /// Number of allocations so far
+    pub allocations: usize,
+    /// Number of deallocations so far
+    pub deallocations: usize,
+}
+
+#[cfg(feature = "alloc-stats")]
+impl AllocStats {
+    /// Number of allocations which are not freed yet
+    pub fn live_allocations(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+}
+
+/// Counters behind `AllocStats`
+///
+/// The counters are updated separately, so a snapshot taken while an event
+/// callback allocates may be slightly inconsistent.
+#[cfg(feature = "alloc-stats")]
+struct Counters {
+    current_bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+}
+
+#[cfg(feature = "alloc-stats")]
+impl Counters {
+    fn record_alloc(&self, size: usize) {
+        let current = self.current_bytes.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes.fetch_max(current, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(&self, size: usize) {
+        self.current_bytes.fetch_sub(size, Ordering::Relaxed);
+        self.deallocations.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+static STATS: Counters = Counters {
+    current_bytes: AtomicUsize::new(0),
+    peak_bytes: AtomicUsize::new(0),
+    allocations: AtomicUsize::new(0),
+    deallocations: AtomicUsize::new(0),
+};
+
+/// The statistics of the global allocator.
+///
+/// Sizes are those requested by the callers, without the overhead of the
+/// pool allocator.
+#[cfg(feature = "alloc-stats")]
+pub fn stats() -> AllocStats {
+    AllocStats {
+        current_bytes: STATS.current_bytes.load(Ordering::Relaxed),
+        peak_bytes: STATS.peak_bytes.load(Ordering::Relaxed),
+        allocations: STATS.allocations.load(Ordering::Relaxed),
+        deallocations: STATS.deallocations.load(Ordering::Relaxed),
+    }
+}
+
+/// Start measuring the peak of the allocated bytes from the current value.
+#[cfg(feature = "alloc-stats")]
+pub fn reset_peak() {
+    let current = STATS.current_bytes.load(Ordering::Relaxed);
+    STATS.peak_bytes.store(current, Ordering::Relaxed);
+}
+
+#[cfg(feature = "alloc-debug")]
+pub use self::debug::{check_heap, set_debug_mode, DebugMode};
+
+#[cfg(feature = "alloc-debug")]
+mod debug {
+    use super::boot_services;
+    use crate::prelude::*;
+    use crate::proto::memory_protection::MemoryProtection;
+    use crate::table::boot::{
+        size_to_pages, AllocateType, MemoryAttribute, MemoryType, Tpl, PAGE_SIZE,
+    };
+    use core::alloc::Layout;
+    use core::mem;
+    use core::ptr;
+    use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+    /// Layout of the debug allocations
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum DebugMode {
+        /// Allocate from the pool, with canaries around the allocations.
+        Canaries,
+        /// Allocate pages, and place the allocations right before a page
+        /// which cannot be read nor written, so that overruns fault at once.
+        ///
+        /// The pages are only protected if the firmware supports the Memory
+        /// Attribute protocol, otherwise overruns are caught by the canaries
+        /// in the padding before the page. Each allocation uses at least two
+        /// pages, so this mode is only suited to small programs.
+        GuardPages,
+    }
+
+    /// Whether `DebugMode::GuardPages` is selected
+    static GUARD_PAGES: AtomicBool = AtomicBool::new(false);
+
+    /// Select the layout of the next allocations.
+    ///
+    /// Allocations made before keep their layout, and are freed accordingly.
+    pub fn set_debug_mode(mode: DebugMode) {
+        GUARD_PAGES.store(mode == DebugMode::GuardPages, Ordering::Relaxed);
+    }
+
+    /// Pattern of the canaries and of the padding around allocations
+    const CANARY: u8 = 0xa5;
+    /// Pattern of newly allocated memory
+    const ALLOCATED: u8 = 0xcd;
+    /// Pattern of freed memory
+    const FREED: u8 = 0xdd;
+    /// Bytes of canary after each pool allocation
+    const TRAILER_SIZE: usize = 16;
+
+    /// Bookkeeping stored right before each allocation
+    #[repr(C, align(16))]
+    struct Header {
+        prev: *mut Header,
+        next: *mut Header,
+        /// Start of the pool allocation or of the pages
+        base: *mut u8,
+        /// Number of pages, or 0 for pool allocations
+        pages: usize,
+        size: usize,
+        /// End of the canaries after the allocation
+        end: *mut u8,
+        canary: [u8; 16],
+    }
+
+    const HEADER_SIZE: usize = mem::size_of::<Header>();
+
+    /// Live allocations, which are linked to be checked by `check_heap`
+    static LIVE: AtomicPtr<Header> = AtomicPtr::new(ptr::null_mut());
+
+    /// Raise the TPL, to protect the list of allocations from the event
+    /// callbacks which allocate
+    unsafe fn lock() -> impl Drop {
+        boot_services().as_ref().raise_tpl(Tpl::NOTIFY)
+    }
+
+    pub(super) unsafe fn alloc(layout: Layout) -> *mut u8 {
+        let align = layout.align().max(mem::align_of::<Header>());
+        let size = layout.size();
+        let (base, pages, object, end) = if GUARD_PAGES.load(Ordering::Relaxed) {
+            let pages = size_to_pages(HEADER_SIZE + size + align);
+            let base = match boot_services().as_ref().allocate_pages(
+                AllocateType::AnyPages,
+                MemoryType::LOADER_DATA,
+                pages + 1,
+            ) {
+                Ok(base) => base.log() as *mut u8,
+                Err(_) => return ptr::null_mut(),
+            };
+            let guard = base.add(pages * PAGE_SIZE);
+            protect(guard, true);
+            let object = guard.sub(size);
+            let object = object.sub(object as usize % align);
+            (base, pages, object, guard)
+        } else {
+            let total = HEADER_SIZE + align + size + TRAILER_SIZE;
+            let base = match boot_services()
+                .as_ref()
+                .allocate_pool(MemoryType::LOADER_DATA, total)
+            {
+                Ok(base) => base.log(),
+                Err(_) => return ptr::null_mut(),
+            };
+            let object = base.add(HEADER_SIZE);
+            let object = object.add(object.align_offset(align));
+            (base, 0, object, object.add(size + TRAILER_SIZE))
+        };
+
+        let header = object.sub(HEADER_SIZE) as *mut Header;
+        header.write(Header {
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+            base,
+            pages,
+            size,
+            end,
+            canary: [CANARY; 16],
+        });
+        object.write_bytes(ALLOCATED, size);
+        let padding = object.add(size);
+        padding.write_bytes(CANARY, end as usize - padding as usize);
+
+        let _tpl = lock();
+        let head = LIVE.load(Ordering::Relaxed);
+        (*header).next = head;
+        if !head.is_null() {
+            (*head).prev = header;
         }
-        boot_services()
-            .as_ref()
-            .free_pool(ptr)
-            .warning_as_error()
-            .unwrap();
+        LIVE.store(header, Ordering::Relaxed);
+        object
+    }
+
+    pub(super) unsafe fn dealloc(object: *mut u8, layout: Layout) {
+        let header = object.sub(HEADER_SIZE) as *mut Header;
+        {
+            let _tpl = lock();
+            check(header);
+            assert_eq!(
+                (*header).size,
+                layout.size(),
+                "Memory at {:?} is freed with the wrong layout",
+                object
+            );
+            let Header { prev, next, .. } = *header;
+            if prev.is_null() {
+                LIVE.store(next, Ordering::Relaxed);
+            } else {
+                (*prev).next = next;
+            }
+            if !next.is_null() {
+                (*next).prev = prev;
+            }
+        }
+
+        let Header {
+            base, pages, end, ..
+        } = *header;
+        base.write_bytes(FREED, end as usize - base as usize);
+        let bt = boot_services();
+        if pages == 0 {
+            bt.as_ref().free_pool(base).warning_as_error().unwrap();
+        } else {
+            protect(end, false);
+            bt.as_ref()
+                .free_pages(base as u64, pages + 1)
+                .warning_as_error()
+                .unwrap();
+        }
+    }
+
+    /// Check the canaries of all the live allocations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a canary was overwritten, since the heap is corrupted.
+    pub fn check_heap() {
+        unsafe {
+            let _tpl = lock();
+            let mut header = LIVE.load(Ordering::Relaxed);
+            while !header.is_null() {
+                check(header);
+                header = (*header).next;
+            }
+        }
+    }
+
+    /// Check the canaries around the allocation of `header`
+    unsafe fn check(header: *mut Header) {
+        let object = (header as *mut u8).add(HEADER_SIZE);
+        assert!(
+            (*header).canary == [CANARY; 16],
+            "Memory before the allocation at {:?} was overwritten, or it was freed twice",
+            object
+        );
+        let padding = object.add((*header).size);
+        let len = (*header).end as usize - padding as usize;
+        let intact = core::slice::from_raw_parts(padding, len)
+            .iter()
+            .all(|&b| b == CANARY);
+        assert!(
+            intact,
+            "Memory after the allocation at {:?} of {} bytes was overwritten",
+            object,
+            (*header).size
+        );
+    }
+
+    /// Make the guard page at `page` inaccessible, or accessible again
+    unsafe fn protect(page: *mut u8, protect: bool) {
+        let bt = boot_services();
+        let protection = match bt.as_ref().locate_protocol::<MemoryProtection>() {
+            Ok(protection) => &*protection.log().get(),
+            Err(_) => return,
+        };
+        let range = page as u64..(page as u64 + PAGE_SIZE as u64);
+        // A page which cannot be protected is still checked by the canaries
+        let _ = if protect {
+            protection.set_memory_attributes(range, MemoryAttribute::READ_PROTECT)
+        } else {
+            protection.clear_memory_attributes(range, MemoryAttribute::READ_PROTECT)
+        };
     }
 }
 
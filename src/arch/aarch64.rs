@@ -0,0 +1,115 @@
+//! AArch64 intrinsics.
+//!
+//! UEFI runs at EL1 or EL2, which may access all the registers used here.
+
+use core::arch::asm;
+
+/// IRQ mask bit of the DAIF register
+const DAIF_I: u64 = 1 << 7;
+
+/// Mask IRQs (`msr daifset, #2`)
+pub fn disable_interrupts() {
+    unsafe { asm!("msr daifset, #2", options(nomem, nostack)) };
+}
+
+/// Unmask IRQs (`msr daifclr, #2`)
+///
+/// # Safety
+///
+/// Interrupt handlers may run as soon as this returns, so the current
+/// exception vectors must be usable, and the caller must not rely on interrupts
+/// being masked.
+pub unsafe fn enable_interrupts() {
+    asm!("msr daifclr, #2", options(nomem, nostack));
+}
+
+/// Whether IRQs are unmasked
+pub fn are_interrupts_enabled() -> bool {
+    let daif: u64;
+    unsafe { asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack, preserves_flags)) };
+    daif & DAIF_I == 0
+}
+
+/// Read the current exception level, from 0 to 3
+pub fn current_el() -> u8 {
+    let el: u64;
+    unsafe { asm!("mrs {}, currentel", out(reg) el, options(nomem, nostack, preserves_flags)) };
+    ((el >> 2) & 0b11) as u8
+}
+
+/// Size of the smallest data cache line, in bytes
+pub fn cache_line_size() -> usize {
+    let ctr: u64;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack, preserves_flags)) };
+    4 << ((ctr >> 16) & 0xf)
+}
+
+/// Clean and invalidate the data cache lines which hold `data` to the point
+/// of coherency (`dc civac`), and invalidate the instruction cache
+///
+/// This makes code which was just written executable, and makes code or data
+/// visible to code which runs with caches disabled.
+pub fn flush_cache_range(data: &[u8]) {
+    let line = cache_line_size();
+    let start = data.as_ptr() as usize & !(line - 1);
+    let end = data.as_ptr() as usize + data.len();
+    for addr in (start..end).step_by(line) {
+        unsafe { asm!("dc civac, {}", in(reg) addr, options(nostack, preserves_flags)) };
+    }
+    unsafe {
+        asm!(
+            "dsb sy",
+            "ic iallu",
+            "dsb sy",
+            "isb",
+            options(nostack, preserves_flags)
+        )
+    };
+}
+
+/// Read the virtual counter (`cntvct_el0`)
+pub fn read_timestamp() -> u64 {
+    let count: u64;
+    unsafe {
+        asm!("isb", "mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack, preserves_flags))
+    };
+    count
+}
+
+/// Read the frame pointer (`x29`)
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let frame: usize;
+    unsafe { asm!("mov {}, x29", out(reg) frame, options(nomem, nostack, preserves_flags)) };
+    frame
+}
+
+/// A random number from the generator of the CPU (`rndr`), or `None` if it
+/// has none or keeps failing
+pub fn hardware_random() -> Option<u64> {
+    let isar0: u64;
+    unsafe {
+        asm!("mrs {}, id_aa64isar0_el1", out(reg) isar0, options(nomem, nostack, preserves_flags))
+    };
+    if (isar0 >> 60) & 0xf == 0 {
+        return None;
+    }
+    (0..10).find_map(|_| {
+        let (value, ok): (u64, u64);
+        // `rndr` is only known to assemblers by its encoding
+        unsafe {
+            asm!(
+                "mrs {}, s3_3_c2_c4_0",
+                "cset {}, ne",
+                out(reg) value,
+                out(reg) ok,
+                options(nomem, nostack)
+            )
+        };
+        if ok != 0 {
+            Some(value)
+        } else {
+            None
+        }
+    })
+}
@@ -0,0 +1,82 @@
+//! CPU architecture intrinsics.
+//!
+//! OS loaders have to perform a few privileged operations right before
+//! handing the machine over to a kernel, such as masking interrupts or
+//! flushing the caches after copying code. This module wraps the instructions
+//! needed for them, so that applications do not need inline assembly.
+//!
+//! The [`interrupts`] module is architecture-independent, while registers
+//! live in the submodule named after the architecture.
+
+#[cfg(target_arch = "aarch64")]
+pub mod aarch64;
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub mod x86;
+
+#[cfg(target_arch = "aarch64")]
+use self::aarch64 as native;
+#[cfg(target_arch = "riscv64")]
+use self::riscv64 as native;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use self::x86 as native;
+
+/// Make the caches coherent with memory for the range holding `data`, after
+/// it was written to
+///
+/// This should be used after copying code into memory, or before handing a
+/// buffer over to code which runs with caches disabled.
+pub fn flush_cache_range(data: &[u8]) {
+    native::flush_cache_range(data);
+}
+
+/// Read a counter which increases at a constant rate, such as the time-stamp
+/// counter of x86
+///
+/// Its frequency depends on the platform, and it is only meant to measure
+/// short intervals.
+pub fn read_timestamp() -> u64 {
+    native::read_timestamp()
+}
+
+/// Read the frame pointer, which links the stack frames of code built with
+/// frame pointers, as `backtrace` walks them
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    native::frame_pointer()
+}
+
+/// A random number from the generator built into the CPU, or `None` if it has
+/// none, or if it failed
+pub fn hardware_random() -> Option<u64> {
+    native::hardware_random()
+}
+
+pub mod interrupts {
+    //! Masking of maskable interrupts on the current CPU.
+    //!
+    //! UEFI does not use interrupts for anything but its timer, so disabling
+    //! them stops timer events from being signaled, as well as the watchdog
+    //! timer. They should only be disabled for short periods, or right before
+    //! exiting boot services.
+
+    pub use super::native::{
+        are_interrupts_enabled as are_enabled, disable_interrupts as disable,
+        enable_interrupts as enable,
+    };
+
+    /// Run `f` with interrupts disabled, then restore the previous state
+    pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+        let enabled = are_enabled();
+        if enabled {
+            disable();
+        }
+        let result = f();
+        if enabled {
+            // Interrupts were enabled when this was called
+            unsafe { enable() };
+        }
+        result
+    }
+}
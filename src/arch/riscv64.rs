@@ -0,0 +1,70 @@
+//! RISC-V 64-bit intrinsics.
+//!
+//! UEFI runs in supervisor mode, so the supervisor CSRs are used here.
+
+use core::arch::asm;
+
+/// Supervisor interrupt enable bit of the `sstatus` CSR, which is also
+/// hardcoded in the `csrci` and `csrsi` instructions
+const SSTATUS_SIE: usize = 1 << 1;
+
+/// Disable supervisor interrupts
+pub fn disable_interrupts() {
+    unsafe { asm!("csrci sstatus, 2", options(nomem, nostack)) };
+}
+
+/// Enable supervisor interrupts
+///
+/// # Safety
+///
+/// Interrupt handlers may run as soon as this returns, so the current
+/// trap vector must be usable, and the caller must not rely on interrupts
+/// being masked.
+pub unsafe fn enable_interrupts() {
+    asm!("csrsi sstatus, 2", options(nomem, nostack));
+}
+
+/// Whether supervisor interrupts are enabled
+pub fn are_interrupts_enabled() -> bool {
+    let sstatus: usize;
+    unsafe { asm!("csrr {}, sstatus", out(reg) sstatus, options(nomem, nostack)) };
+    sstatus & SSTATUS_SIE != 0
+}
+
+/// Read the `satp` CSR, which selects the paging mode and holds the physical
+/// page number of the top-level page table
+pub fn read_satp() -> usize {
+    let satp: usize;
+    unsafe { asm!("csrr {}, satp", out(reg) satp, options(nomem, nostack)) };
+    satp
+}
+
+/// Make memory writes visible to instruction fetches and to other harts
+/// (`fence` and `fence.i`)
+///
+/// The base ISA has no way to flush a given range, so `data` is only taken for
+/// consistency with the other architectures.
+pub fn flush_cache_range(_data: &[u8]) {
+    unsafe { asm!("fence", "fence.i", options(nostack)) };
+}
+
+/// Read the `time` CSR (`rdtime`)
+pub fn read_timestamp() -> u64 {
+    let time: u64;
+    unsafe { asm!("rdtime {}", out(reg) time, options(nomem, nostack)) };
+    time
+}
+
+/// Read the frame pointer (`s0`)
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let frame: usize;
+    unsafe { asm!("mv {}, s0", out(reg) frame, options(nomem, nostack)) };
+    frame
+}
+
+/// Always `None`, as the `seed` CSR of the Zkr extension is usually not
+/// accessible in supervisor mode
+pub fn hardware_random() -> Option<u64> {
+    None
+}
@@ -0,0 +1,503 @@
+//! x86 and x86_64 intrinsics.
+//!
+//! UEFI runs in ring 0, so all of these instructions are allowed. Functions
+//! which can only misbehave by trapping, such as reading control registers,
+//! are safe. Those which can change how memory is accessed are unsafe.
+
+use bitflags::bitflags;
+use core::arch::asm;
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid, __cpuid_count};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+/// Interrupt flag of the FLAGS register
+const FLAGS_IF: usize = 1 << 9;
+
+bitflags! {
+    /// Flags of the CR0 control register.
+    pub struct Cr0: usize {
+        /// Protected mode is enabled.
+        const PROTECTED_MODE = 1 << 0;
+        /// Monitor the coprocessor on `wait` instructions.
+        const MONITOR_COPROCESSOR = 1 << 1;
+        /// Floating-point instructions are emulated.
+        const EMULATION = 1 << 2;
+        /// A task switch occurred, and the FPU state was not saved yet.
+        const TASK_SWITCHED = 1 << 3;
+        /// Floating-point errors are reported natively.
+        const NUMERIC_ERROR = 1 << 5;
+        /// Read-only pages are also write-protected in ring 0.
+        const WRITE_PROTECT = 1 << 16;
+        /// Alignment checks are enabled in ring 3.
+        const ALIGNMENT_MASK = 1 << 18;
+        /// Write-through caching is disabled.
+        const NOT_WRITE_THROUGH = 1 << 29;
+        /// Caching is disabled.
+        const CACHE_DISABLE = 1 << 30;
+        /// Paging is enabled.
+        const PAGING = 1 << 31;
+    }
+}
+
+bitflags! {
+    /// Flags of the CR4 control register.
+    pub struct Cr4: usize {
+        /// Virtual-8086 mode extensions are enabled.
+        const VIRTUAL_8086_EXTENSIONS = 1 << 0;
+        /// Virtual interrupts are enabled in protected mode.
+        const PROTECTED_MODE_VIRTUAL_INTERRUPTS = 1 << 1;
+        /// `rdtsc` is restricted to ring 0.
+        const TIMESTAMP_DISABLE = 1 << 2;
+        /// Debug registers may not refer to I/O ports.
+        const DEBUGGING_EXTENSIONS = 1 << 3;
+        /// 4 MiB pages are enabled in 32-bit paging.
+        const PAGE_SIZE_EXTENSION = 1 << 4;
+        /// Physical addresses are larger than 32 bits.
+        const PHYSICAL_ADDRESS_EXTENSION = 1 << 5;
+        /// Machine check exceptions are enabled.
+        const MACHINE_CHECK = 1 << 6;
+        /// Global pages are enabled.
+        const PAGE_GLOBAL = 1 << 7;
+        /// `rdpmc` is allowed in ring 3.
+        const PERFORMANCE_COUNTER = 1 << 8;
+        /// `fxsave` and `fxrstor` save the SSE state.
+        const OSFXSR = 1 << 9;
+        /// Unmasked SSE exceptions are supported.
+        const OSXMMEXCPT = 1 << 10;
+        /// 5-level paging is enabled.
+        const LA57 = 1 << 12;
+        /// VMX is enabled.
+        const VMX = 1 << 13;
+        /// `rdfsbase` and friends are enabled.
+        const FSGSBASE = 1 << 16;
+        /// Process-context identifiers are enabled.
+        const PCID = 1 << 17;
+        /// `xsave` is enabled.
+        const OSXSAVE = 1 << 18;
+        /// Ring 0 can not execute code of ring 3 pages.
+        const SMEP = 1 << 20;
+        /// Ring 0 can not access data of ring 3 pages.
+        const SMAP = 1 << 21;
+    }
+}
+
+bitflags! {
+    /// State components enabled in the XCR0 register, which `xsave` saves and
+    /// whose instructions may be used.
+    pub struct Xcr0: u64 {
+        /// x87 state, which is always enabled.
+        const X87 = 1 << 0;
+        /// SSE state (`xmm` registers).
+        const SSE = 1 << 1;
+        /// AVX state (upper halves of the `ymm` registers).
+        const AVX = 1 << 2;
+        /// MPX bound registers.
+        const BNDREGS = 1 << 3;
+        /// MPX bound configuration.
+        const BNDCSR = 1 << 4;
+        /// AVX-512 mask registers.
+        const OPMASK = 1 << 5;
+        /// Upper halves of the first 16 `zmm` registers.
+        const ZMM_HI256 = 1 << 6;
+        /// Last 16 `zmm` registers.
+        const HI16_ZMM = 1 << 7;
+    }
+}
+
+bitflags! {
+    /// Instruction set extensions of the CPU, as reported by `cpuid`.
+    pub struct CpuFeatures: u32 {
+        /// SSE
+        const SSE = 1 << 0;
+        /// SSE2
+        const SSE2 = 1 << 1;
+        /// SSE3
+        const SSE3 = 1 << 2;
+        /// Supplemental SSE3
+        const SSSE3 = 1 << 3;
+        /// SSE4.1
+        const SSE4_1 = 1 << 4;
+        /// SSE4.2
+        const SSE4_2 = 1 << 5;
+        /// AES-NI instructions
+        const AES = 1 << 6;
+        /// Carry-less multiplication (`pclmulqdq`)
+        const PCLMULQDQ = 1 << 7;
+        /// SHA extensions
+        const SHA = 1 << 8;
+        /// AVX
+        const AVX = 1 << 9;
+        /// AVX2
+        const AVX2 = 1 << 10;
+        /// `xsave` and `xgetbv`, if they are also enabled in CR4
+        const XSAVE = 1 << 11;
+        /// Bit manipulation instructions 1
+        const BMI1 = 1 << 12;
+        /// Bit manipulation instructions 2
+        const BMI2 = 1 << 13;
+        /// Multi-precision add-carry instructions (`adcx`, `adox`)
+        const ADX = 1 << 14;
+        /// `rdrand`
+        const RDRAND = 1 << 15;
+        /// `rdseed`
+        const RDSEED = 1 << 16;
+    }
+}
+
+/// Well-known model-specific registers
+pub mod msr {
+    /// Extended feature enable register, which controls long mode and NX.
+    pub const IA32_EFER: u32 = 0xc000_0080;
+    /// Page attribute table.
+    pub const IA32_PAT: u32 = 0x277;
+    /// Base address of the local APIC.
+    pub const IA32_APIC_BASE: u32 = 0x1b;
+    /// Base address of the FS segment.
+    pub const IA32_FS_BASE: u32 = 0xc000_0100;
+    /// Base address of the GS segment.
+    pub const IA32_GS_BASE: u32 = 0xc000_0101;
+}
+
+/// Disable maskable interrupts (`cli`)
+pub fn disable_interrupts() {
+    unsafe { asm!("cli", options(nomem, nostack)) };
+}
+
+/// Enable maskable interrupts (`sti`)
+///
+/// # Safety
+///
+/// Interrupt handlers may run as soon as this returns, so it must not be
+/// called while the firmware's handlers are unusable, such as after exiting
+/// boot services and before installing an own interrupt table, or while the
+/// caller is in a section which relies on interrupts being masked.
+pub unsafe fn enable_interrupts() {
+    asm!("sti", options(nomem, nostack));
+}
+
+/// Whether maskable interrupts are enabled
+pub fn are_interrupts_enabled() -> bool {
+    let flags: usize;
+    unsafe { asm!("pushf", "pop {}", out(reg) flags, options(nomem, preserves_flags)) };
+    flags & FLAGS_IF != 0
+}
+
+/// Read the CR0 control register
+pub fn read_cr0() -> Cr0 {
+    let value: usize;
+    unsafe { asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    Cr0::from_bits_truncate(value)
+}
+
+/// Read the CR2 control register, which holds the address of the last page
+/// fault
+pub fn read_cr2() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// Read the CR3 control register, which holds the physical address of the
+/// top-level page table, and the PCID or caching flags in its low 12 bits
+pub fn read_cr3() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// Read the CR4 control register
+pub fn read_cr4() -> Cr4 {
+    let value: usize;
+    unsafe { asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    Cr4::from_bits_truncate(value)
+}
+
+/// Switch to another top-level page table
+///
+/// # Safety
+///
+/// The new page tables must map the currently running code and stack, as
+/// well as all the memory that is used afterwards.
+pub unsafe fn write_cr3(value: usize) {
+    asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Read a model-specific register (`rdmsr`)
+///
+/// # Safety
+///
+/// Reading a register which the CPU does not implement raises a general
+/// protection fault. Some registers have side effects when read.
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    u64::from(high) << 32 | u64::from(low)
+}
+
+/// Write a model-specific register (`wrmsr`)
+///
+/// # Safety
+///
+/// Model-specific registers control how the CPU accesses memory and runs
+/// code, and writing an invalid value raises a general protection fault.
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    let (low, high) = (value as u32, (value >> 32) as u32);
+    asm!("wrmsr", in("ecx") msr, in("eax") low, in("edx") high, options(nostack, preserves_flags));
+}
+
+/// Write back and invalidate all the caches (`wbinvd`)
+///
+/// This is very slow, and should only be used right before handing the
+/// machine over to code which runs with caches disabled.
+///
+/// # Safety
+///
+/// Dirty cache lines are written back, which overwrites whatever devices
+/// wrote to the same memory behind the caches of the CPU. No DMA may target
+/// memory which is cached in a non-coherent way while this runs.
+pub unsafe fn flush_all_caches() {
+    asm!("wbinvd", options(nostack, preserves_flags));
+}
+
+/// Size of the cache lines flushed by `clflush`, in bytes
+// `__cpuid` is only safe to call on recent compilers
+#[allow(unused_unsafe)]
+pub fn cache_line_size() -> usize {
+    let info = unsafe { __cpuid(1) };
+    ((info.ebx >> 8) & 0xff) as usize * 8
+}
+
+/// Write back the cache lines which hold `data` to memory (`clflush`)
+///
+/// This makes code or data which was just written visible to devices and to
+/// other CPUs which run with caches disabled.
+pub fn flush_cache_range(data: &[u8]) {
+    let line = cache_line_size().max(1);
+    let start = data.as_ptr() as usize & !(line - 1);
+    let end = data.as_ptr() as usize + data.len();
+    for addr in (start..end).step_by(line) {
+        unsafe { asm!("clflush [{}]", in(reg) addr, options(nostack, preserves_flags)) };
+    }
+    unsafe { asm!("mfence", options(nostack, preserves_flags)) };
+}
+
+/// Read the time-stamp counter (`rdtsc`)
+pub fn read_timestamp() -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags))
+    };
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// Read the frame pointer (`rbp`, or `ebp` on IA-32)
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let frame: usize;
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack, preserves_flags))
+    };
+    #[cfg(target_arch = "x86")]
+    unsafe {
+        asm!("mov {}, ebp", out(reg) frame, options(nomem, nostack, preserves_flags))
+    };
+    frame
+}
+
+/// The instruction set extensions which the CPU implements.
+///
+/// Instructions which use registers of the SSE or AVX state also need the
+/// state to be enabled, which firmware does not always do: `usable_features`
+/// only returns those which can run.
+// `__cpuid` is only safe to call on recent compilers
+#[allow(unused_unsafe)]
+pub fn cpu_features() -> CpuFeatures {
+    // (register bit, feature) for leaf 1
+    const LEAF_1_EDX: [(u32, CpuFeatures); 2] = [(25, CpuFeatures::SSE), (26, CpuFeatures::SSE2)];
+    const LEAF_1_ECX: [(u32, CpuFeatures); 9] = [
+        (0, CpuFeatures::SSE3),
+        (1, CpuFeatures::PCLMULQDQ),
+        (9, CpuFeatures::SSSE3),
+        (19, CpuFeatures::SSE4_1),
+        (20, CpuFeatures::SSE4_2),
+        (25, CpuFeatures::AES),
+        (26, CpuFeatures::XSAVE),
+        (28, CpuFeatures::AVX),
+        (30, CpuFeatures::RDRAND),
+    ];
+    // (register bit, feature) for leaf 7, sub-leaf 0
+    const LEAF_7_EBX: [(u32, CpuFeatures); 6] = [
+        (3, CpuFeatures::BMI1),
+        (5, CpuFeatures::AVX2),
+        (8, CpuFeatures::BMI2),
+        (18, CpuFeatures::RDSEED),
+        (19, CpuFeatures::ADX),
+        (29, CpuFeatures::SHA),
+    ];
+
+    let collect = |register: u32, bits: &[(u32, CpuFeatures)]| {
+        bits.iter()
+            .filter(|(bit, _)| register & (1 << bit) != 0)
+            .fold(CpuFeatures::empty(), |all, (_, feature)| all | *feature)
+    };
+    let leaf_1 = unsafe { __cpuid(1) };
+    let mut features = collect(leaf_1.edx, &LEAF_1_EDX) | collect(leaf_1.ecx, &LEAF_1_ECX);
+    if unsafe { __cpuid(0) }.eax >= 7 {
+        features |= collect(unsafe { __cpuid_count(7, 0) }.ebx, &LEAF_7_EBX);
+    }
+    features
+}
+
+/// Read the XCR0 register (`xgetbv`), or return `None` if `xsave` is not
+/// enabled, in which case only the x87 and SSE states are available.
+pub fn read_xcr0() -> Option<Xcr0> {
+    if !cpu_features().contains(CpuFeatures::XSAVE) || !read_cr4().contains(Cr4::OSXSAVE) {
+        return None;
+    }
+    Some(Xcr0::from_bits_truncate(unsafe { xgetbv() }))
+}
+
+/// All the bits of XCR0, including the state components not described by
+/// `Xcr0`
+///
+/// # Safety
+///
+/// `xsave` must be enabled in CR4.
+unsafe fn xgetbv() -> u64 {
+    let (low, high): (u32, u32);
+    asm!("xgetbv", in("ecx") 0, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// The instruction set extensions which the CPU implements and are enabled,
+/// so that they do not raise an invalid opcode exception.
+///
+/// SSE instructions need CR4.OSFXSR, and AVX ones need the SSE and AVX
+/// states in XCR0. Code which selects an implementation at runtime should
+/// check these rather than `cpu_features`.
+pub fn usable_features() -> CpuFeatures {
+    let sse = CpuFeatures::SSE
+        | CpuFeatures::SSE2
+        | CpuFeatures::SSE3
+        | CpuFeatures::SSSE3
+        | CpuFeatures::SSE4_1
+        | CpuFeatures::SSE4_2
+        | CpuFeatures::AES
+        | CpuFeatures::PCLMULQDQ
+        | CpuFeatures::SHA;
+    let avx = CpuFeatures::AVX | CpuFeatures::AVX2;
+
+    let mut features = cpu_features();
+    let sse_enabled = read_cr4().contains(Cr4::OSFXSR) && !read_cr0().contains(Cr0::EMULATION);
+    if !sse_enabled {
+        features -= sse | avx;
+    }
+    let avx_state = Xcr0::SSE | Xcr0::AVX;
+    if !matches!(read_xcr0(), Some(xcr0) if xcr0.contains(avx_state)) {
+        features -= avx;
+    }
+    features
+}
+
+/// Enable the SSE and AVX states, so that the instructions of all the
+/// extensions which the CPU implements can be used, and return the
+/// extensions which are usable afterwards.
+///
+/// Firmware must enable SSE on x86_64, but leaves the AVX states of XCR0
+/// and the `xsave` support of CR4 as they were at reset. Code compiled with
+/// `target-feature=+avx2`, or which enables AVX after a runtime check of
+/// `cpu_features`, then raises an invalid opcode exception. This clears
+/// CR0.EM and sets CR0.MP, sets CR4.OSFXSR and CR4.OSXMMEXCPT, and if the
+/// CPU implements `xsave`, sets CR4.OSXSAVE and enables in XCR0 the AVX and
+/// AVX-512 states which the CPU supports. The `enable_simd` flag of the
+/// `#[entry]` attribute calls this before the entry point runs.
+///
+/// # Safety
+///
+/// Enabling states in XCR0 makes `xsave` areas larger, so this must run
+/// before any code which sized them, such as firmware drivers which save
+/// the state of the application in interrupt handlers, has looked at XCR0.
+/// In practice, this must be called early on the bootstrap processor.
+#[inline(never)]
+pub unsafe fn enable_simd() -> CpuFeatures {
+    // Bits which are not described by `Cr0` and `Cr4` are preserved
+    let mut cr0: usize;
+    asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+    cr0 &= !Cr0::EMULATION.bits();
+    cr0 |= Cr0::MONITOR_COPROCESSOR.bits();
+    asm!("mov cr0, {}", in(reg) cr0, options(nostack, preserves_flags));
+
+    let features = cpu_features();
+    let mut cr4: usize;
+    asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    cr4 |= (Cr4::OSFXSR | Cr4::OSXMMEXCPT).bits();
+    if features.contains(CpuFeatures::XSAVE) {
+        cr4 |= Cr4::OSXSAVE.bits();
+    }
+    asm!("mov cr4, {}", in(reg) cr4, options(nostack, preserves_flags));
+
+    if features.contains(CpuFeatures::XSAVE) {
+        // The state components which XCR0 accepts
+        let leaf_d = __cpuid_count(0xd, 0);
+        let supported = (u64::from(leaf_d.edx) << 32) | u64::from(leaf_d.eax);
+        let mut xcr0 = xgetbv() | (Xcr0::X87 | Xcr0::SSE).bits();
+        if features.contains(CpuFeatures::AVX) && supported & Xcr0::AVX.bits() != 0 {
+            xcr0 |= Xcr0::AVX.bits();
+            // The AVX-512 states can only be enabled together
+            let avx512 = (Xcr0::OPMASK | Xcr0::ZMM_HI256 | Xcr0::HI16_ZMM).bits();
+            if supported & avx512 == avx512 {
+                xcr0 |= avx512;
+            }
+        }
+        asm!("xsetbv", in("ecx") 0, in("eax") xcr0 as u32, in("edx") (xcr0 >> 32) as u32, options(nostack, preserves_flags));
+    }
+    usable_features()
+}
+
+/// Which of `rdseed` and `rdrand` the CPU supports
+fn random_instructions() -> (bool, bool) {
+    let features = cpu_features();
+    (
+        features.contains(CpuFeatures::RDSEED),
+        features.contains(CpuFeatures::RDRAND),
+    )
+}
+
+/// Run `rdseed`, or `rdrand` if `seed` is `false`, which fail when the
+/// generator runs out of entropy
+fn random_step(seed: bool) -> Option<usize> {
+    let value: usize;
+    let ok: u8;
+    unsafe {
+        if seed {
+            asm!("rdseed {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+        } else {
+            asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+        }
+    }
+    // Some CPUs return all ones instead of failing
+    if ok != 0 && value != usize::MAX {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// A random number from the generator of the CPU (`rdseed`, or `rdrand` if
+/// the CPU lacks it), or `None` if it has none or keeps failing
+pub fn hardware_random() -> Option<u64> {
+    let seed = match random_instructions() {
+        (true, _) => true,
+        (false, true) => false,
+        (false, false) => return None,
+    };
+    // Intel recommends 10 retries before assuming that the generator broke
+    let next = || (0..10).find_map(|_| random_step(seed));
+    if cfg!(target_pointer_width = "64") {
+        next().map(|value| value as u64)
+    } else {
+        Some((next()? as u64) << 32 | next()? as u64)
+    }
+}
@@ -0,0 +1,251 @@
+//! Backtraces, from the chain of frame pointers.
+//!
+//! UEFI images carry no unwind tables at run time, but code built with frame
+//! pointers links each stack frame to the one of its caller, next to the
+//! return address. Build with `-C force-frame-pointers=yes`, which also
+//! applies to `core` and `alloc` when they are built with `build-std`, to
+//! have complete backtraces.
+//!
+//! The panic handler of `uefi-services`, with its `backtrace` feature, and
+//! `CpuArch::panic_on_faults` print the return addresses. Once the image is
+//! registered with `register_image`, they are also printed as offsets in the
+//! image, which stay the same across runs: adding the preferred image base
+//! from the PE header gives the addresses to look up with `addr2line` or
+//! `llvm-symbolizer`.
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # fn f(bt: &BootServices, image: Handle) -> uefi::Result {
+//! uefi::backtrace::register_image(bt, image)?.log();
+//! // ...
+//! log::error!("Backtrace:\n{}", unsafe { uefi::backtrace::Backtrace::capture() });
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::loaded_image::LoadedImage;
+use crate::table::boot::BootServices;
+use crate::{Handle, Result, Status};
+use core::fmt;
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Maximum number of frames which are walked
+const MAX_FRAMES: usize = 64;
+/// Size of the stack above the first frame in which the frames are looked
+/// for, which is larger than the stacks of firmware
+const STACK_WINDOW: usize = 1024 * 1024;
+
+/// Offset of the frame record, which holds the frame pointer and the return
+/// address of the caller, from the frame pointer
+#[cfg(target_arch = "riscv64")]
+const RECORD_OFFSET: isize = -2;
+#[cfg(not(target_arch = "riscv64"))]
+const RECORD_OFFSET: isize = 0;
+
+/// Base and size of the running image
+static IMAGE_BASE: AtomicUsize = AtomicUsize::new(0);
+static IMAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Record the location of `image` from its `LoadedImage` protocol, so that
+/// backtraces print the offsets of the addresses in it
+pub fn register_image(bt: &BootServices, image: Handle) -> Result {
+    let loaded_image = bt.handle_protocol::<LoadedImage>(image)?.log();
+    let (base, size) = unsafe { &*loaded_image.get() }.info();
+    set_image(base as usize, size as usize);
+    Status::SUCCESS.into()
+}
+
+/// Record the location of the running image, as `register_image` does
+pub fn set_image(base: usize, size: usize) {
+    IMAGE_SIZE.store(size, Ordering::Relaxed);
+    IMAGE_BASE.store(base, Ordering::Relaxed);
+}
+
+/// Base and size of the registered image, if any
+pub fn image() -> Option<(usize, usize)> {
+    let base = IMAGE_BASE.load(Ordering::Relaxed);
+    let size = IMAGE_SIZE.load(Ordering::Relaxed);
+    if size == 0 {
+        return None;
+    }
+    Some((base, size))
+}
+
+/// The chain of stack frames from a frame pointer
+///
+/// The walk stops at the first frame which does not look valid: not aligned,
+/// not above the previous one, or too far away. Frames of code built without
+/// frame pointers are skipped or end the walk early.
+#[derive(Debug, Copy, Clone)]
+pub struct Backtrace {
+    frame_pointer: usize,
+    /// Address of the instruction which was running, for exceptions
+    address: Option<usize>,
+}
+
+impl Backtrace {
+    /// The backtrace of the caller
+    ///
+    /// # Safety
+    ///
+    /// The frame pointer register must hold the frame pointer of the caller,
+    /// as when it is built with frame pointers, or else the memory up to
+    /// 1 MiB above the stack pointer must be readable, as UEFI maps it.
+    #[inline(always)]
+    pub unsafe fn capture() -> Self {
+        Backtrace {
+            frame_pointer: crate::arch::frame_pointer(),
+            address: None,
+        }
+    }
+
+    /// The backtrace from `frame_pointer`, as saved in the context of an
+    /// exception, optionally after the `address` of the instruction which was
+    /// running
+    ///
+    /// # Safety
+    ///
+    /// The frame pointer must be null, or a frame pointer of a stack, as
+    /// required by `capture`.
+    pub unsafe fn from_frame_pointer(frame_pointer: usize, address: Option<usize>) -> Self {
+        Backtrace {
+            frame_pointer,
+            address,
+        }
+    }
+
+    /// The addresses of the backtrace, from the innermost: the running
+    /// instruction, if known, and then the return addresses of the frames,
+    /// which follow the calls
+    pub fn frames(&self) -> Frames {
+        let start = record_of(self.frame_pointer);
+        Frames {
+            frame_pointer: self.frame_pointer,
+            low: start,
+            high: start.saturating_add(STACK_WINDOW),
+            address: self.address,
+            remaining: MAX_FRAMES,
+        }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    /// Print one address per line, with its offset in the registered image
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let image = image();
+        for (index, address) in self.frames().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:>4}: {:#018x}", index, address)?;
+            if let Some((base, size)) = image {
+                if address >= base && address - base < size {
+                    write!(f, " (image + {:#x})", address - base)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the addresses of a `Backtrace`
+#[derive(Debug, Clone)]
+pub struct Frames {
+    frame_pointer: usize,
+    /// Bounds of the next frame record
+    low: usize,
+    high: usize,
+    address: Option<usize>,
+    remaining: usize,
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if let Some(address) = self.address.take() {
+            return Some(address);
+        }
+        if self.remaining == 0 || self.frame_pointer == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let word = mem::size_of::<usize>();
+        let record = record_of(self.frame_pointer);
+        if record & (word - 1) != 0 || record < self.low || record >= self.high - 2 * word {
+            return None;
+        }
+        let (caller, address) = unsafe {
+            let record = record as *const usize;
+            (record.read_volatile(), record.add(1).read_volatile())
+        };
+        // The stack grows down, so the frames of the callers are above
+        self.low = record + 2 * word;
+        self.frame_pointer = caller;
+        if address == 0 {
+            return None;
+        }
+        Some(address)
+    }
+}
+
+/// Address of the frame record of `frame_pointer`
+fn record_of(frame_pointer: usize) -> usize {
+    let offset = RECORD_OFFSET * mem::size_of::<usize>() as isize;
+    (frame_pointer as isize).wrapping_add(offset) as usize
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::string::ToString;
+    use crate::alloc_api::vec::Vec;
+
+    #[test]
+    fn walk() {
+        let mut stack = [0usize; 8];
+        let word = mem::size_of::<usize>();
+        let offset = (-RECORD_OFFSET) as usize * word;
+        let base = stack.as_mut_ptr() as usize;
+        // The walk reads the stack through addresses, which the compiler
+        // does not see
+        let set = |index: usize, value: usize| unsafe {
+            (base as *mut usize).add(index).write_volatile(value)
+        };
+        // Three frames, the last of which has no caller
+        set(0, base + 2 * word + offset);
+        set(1, 0x1000);
+        set(2, base + 4 * word + offset);
+        set(3, 0x2000);
+        set(4, 0);
+        set(5, 0x3000);
+        let backtrace = unsafe { Backtrace::from_frame_pointer(base + offset, Some(0x500)) };
+        let frames: Vec<_> = backtrace.frames().collect();
+        assert_eq!(frames, [0x500, 0x1000, 0x2000, 0x3000]);
+
+        // A frame which points below the previous one ends the walk
+        set(2, base + offset);
+        let backtrace = unsafe { Backtrace::from_frame_pointer(base + offset, None) };
+        let frames: Vec<_> = backtrace.frames().collect();
+        assert_eq!(frames, [0x1000, 0x2000]);
+
+        let backtrace = unsafe { Backtrace::from_frame_pointer(0, Some(0x500)) };
+        assert_eq!(backtrace.frames().count(), 1);
+    }
+
+    #[test]
+    fn image_offsets() {
+        set_image(0x1000, 0x800);
+        let stack = [0usize, 0x1234];
+        let base = stack.as_ptr() as usize;
+        let offset = (-RECORD_OFFSET) as usize * mem::size_of::<usize>();
+        let backtrace = unsafe { Backtrace::from_frame_pointer(base + offset, Some(0x2000)) };
+        let lines = backtrace.to_string();
+        let lines: Vec<_> = lines.lines().collect();
+        assert_eq!(lines[0], "   0: 0x0000000000002000");
+        assert_eq!(lines[1], "   1: 0x0000000000001234 (image + 0x234)");
+        set_image(0, 0);
+    }
+}
@@ -0,0 +1,617 @@
+//! Flattened device trees.
+//!
+//! On ARM and RISC-V, the firmware describes the hardware with a device tree
+//! blob (DTB), published in the configuration table. Before starting a
+//! kernel, the boot loader patches the `/chosen` node with the command line
+//! and the location of the initrd, and reserves the memory the kernel must
+//! not touch:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::boot::fdt::{DeviceTree, Fdt};
+//! # fn f(st: &SystemTable<Boot>, initrd: &[u8]) -> Option<()> {
+//! let fdt = unsafe { Fdt::from_config_table(st.config_table()) }?;
+//! let mut tree = DeviceTree::from_fdt(&fdt);
+//! tree.set_bootargs("console=ttyAMA0 root=/dev/vda2").ok()?;
+//! let start = initrd.as_ptr() as u64;
+//! tree.set_initrd(start, start + initrd.len() as u64).ok()?;
+//! let dtb = tree.to_bytes();
+//! // Copy `dtb` to memory which outlives the loader, and install it with
+//! // `BootServices::install_configuration_table`
+//! # Some(())
+//! # }
+//! ```
+
+use crate::table::cfg::{ConfigTableEntry, DEVICE_TREE_GUID};
+use core::convert::TryInto;
+use core::slice;
+#[cfg(feature = "exts")]
+use {crate::alloc_api::vec::Vec, core::ops::Range};
+
+/// Errors that can occur when reading or editing a device tree
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FdtError {
+    /// The data does not start with a valid header.
+    InvalidHeader,
+    /// The format is older than version 16.
+    Unsupported,
+    /// The blocks of the tree point outside of the data.
+    Truncated,
+    /// The structure of the tree is invalid.
+    Malformed,
+}
+
+/// Magic number at the start of the device trees
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// Version of the format written by `DeviceTree::to_bytes`
+const VERSION: u32 = 17;
+/// Oldest version with which the format written is compatible
+const LAST_COMP_VERSION: u32 = 16;
+/// Size of the header of version 17
+const HEADER_SIZE: usize = 40;
+/// Size of the header of version 16, which lacks the size of the structure
+/// block
+const HEADER_SIZE_V16: usize = 36;
+/// Size of an entry of the memory reservation block
+const RESERVATION_SIZE: usize = 16;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// A token of the structure block
+#[derive(Debug, Copy, Clone)]
+enum Token<'a> {
+    BeginNode(&'a [u8]),
+    EndNode,
+    Prop { name_offset: u32, value: &'a [u8] },
+    Nop,
+    End,
+}
+
+/// Read the token at `offset` of the structure block, and the offset of the
+/// next one
+fn token(structure: &[u8], offset: usize) -> Option<(Token<'_>, usize)> {
+    let body = offset + 4;
+    match read_u32(structure, offset)? {
+        FDT_BEGIN_NODE => {
+            let len = structure.get(body..)?.iter().position(|&c| c == 0)?;
+            let name = &structure[body..body + len];
+            Some((Token::BeginNode(name), align4(body + len + 1)))
+        }
+        FDT_END_NODE => Some((Token::EndNode, body)),
+        FDT_PROP => {
+            let len = read_u32(structure, body)? as usize;
+            let name_offset = read_u32(structure, body + 4)?;
+            let start = body + 8;
+            let value = structure.get(start..start.checked_add(len)?)?;
+            Some((Token::Prop { name_offset, value }, align4(start + len)))
+        }
+        FDT_NOP => Some((Token::Nop, body)),
+        FDT_END => Some((Token::End, body)),
+        _ => None,
+    }
+}
+
+/// Whether the node `name` is designated by the path component `component`,
+/// which may omit the unit address
+fn name_matches(name: &[u8], component: &str) -> bool {
+    let component = component.as_bytes();
+    name == component
+        || (!component.contains(&b'@') && name.split(|&c| c == b'@').next() == Some(component))
+}
+
+/// The location of a node in the structure block
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    /// Offset of the first token after the name, where properties start
+    props: usize,
+    /// Offset of the `FDT_END_NODE` token
+    #[cfg_attr(not(feature = "exts"), allow(dead_code))]
+    end: usize,
+}
+
+/// Find the node at `path`, like `/chosen`, in the structure block
+fn find_node(structure: &[u8], path: &str) -> Option<Node> {
+    let components = path.split('/').filter(|c| !c.is_empty());
+    let target = components.clone().count();
+    let mut offset = 0;
+    // Number of open nodes, and number of them which are on the path
+    let mut depth = 0;
+    let mut matched = 0;
+    let mut found = None;
+    loop {
+        let (token, next) = token(structure, offset)?;
+        match token {
+            Token::BeginNode(name) => {
+                if depth > 0 && matched == depth - 1 {
+                    let component = components.clone().nth(matched);
+                    if matches!(component, Some(component) if name_matches(name, component)) {
+                        matched = depth;
+                    }
+                }
+                if matched == depth && depth == target && found.is_none() {
+                    found = Some(next);
+                }
+                depth += 1;
+            }
+            Token::EndNode => {
+                depth = depth.checked_sub(1)?;
+                if matched == depth {
+                    if let Some(props) = found {
+                        return Some(Node { props, end: offset });
+                    }
+                    // The path leaves the tree
+                    matched = depth.checked_sub(1)?;
+                }
+            }
+            Token::End => return None,
+            _ => {}
+        }
+        offset = next;
+    }
+}
+
+/// Read the nul-terminated string at `offset` of the strings block
+fn string(strings: &[u8], offset: u32) -> Option<&[u8]> {
+    let strings = strings.get(offset as usize..)?;
+    let len = strings.iter().position(|&c| c == 0)?;
+    Some(&strings[..len])
+}
+
+/// Find the property `name` of `node`, returning the offset of its token, the
+/// offset of the next token, and its value
+fn find_property<'a>(
+    structure: &'a [u8],
+    strings: &[u8],
+    node: Node,
+    name: &str,
+) -> Option<(usize, usize, &'a [u8])> {
+    let mut offset = node.props;
+    loop {
+        match token(structure, offset)? {
+            (Token::Prop { name_offset, value }, next) => {
+                if string(strings, name_offset)? == name.as_bytes() {
+                    return Some((offset, next, value));
+                }
+                offset = next;
+            }
+            (Token::Nop, next) => offset = next,
+            // The properties come before the subnodes
+            _ => return None,
+        }
+    }
+}
+
+/// A device tree blob, borrowing its data
+#[derive(Debug, Copy, Clone)]
+pub struct Fdt<'a> {
+    data: &'a [u8],
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Parse the header of a device tree blob
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, FdtError> {
+        let field = |index: usize| read_u32(data, 4 * index).ok_or(FdtError::Truncated);
+        if field(0)? != FDT_MAGIC {
+            return Err(FdtError::InvalidHeader);
+        }
+        let version = field(5)?;
+        if version < LAST_COMP_VERSION || field(6)? > VERSION {
+            return Err(FdtError::Unsupported);
+        }
+        let header_size = if version >= VERSION {
+            HEADER_SIZE
+        } else {
+            HEADER_SIZE_V16
+        };
+        let total_size = field(1)? as usize;
+        if total_size < header_size {
+            return Err(FdtError::InvalidHeader);
+        }
+        let data = data.get(..total_size).ok_or(FdtError::Truncated)?;
+        // The blocks follow the header, and must fit in the tree
+        let block = |offset: u32, size: usize| {
+            let start = offset as usize;
+            if start < header_size {
+                return Err(FdtError::InvalidHeader);
+            }
+            start
+                .checked_add(size)
+                .and_then(|end| data.get(start..end))
+                .ok_or(FdtError::Truncated)
+        };
+        // Version 16 does not give the size of the structure block
+        let structure_size = if version >= VERSION {
+            field(9)?
+        } else {
+            (total_size as u32).saturating_sub(field(2)?)
+        };
+        let structure = block(field(2)?, structure_size as usize)?;
+        let strings = block(field(3)?, field(8)? as usize)?;
+        // At least the entry which terminates the reservations
+        block(field(4)?, RESERVATION_SIZE)?;
+        Ok(Fdt {
+            data,
+            structure,
+            strings,
+        })
+    }
+
+    /// Find the device tree published in the configuration table.
+    ///
+    /// # Safety
+    ///
+    /// The tree must be valid and identity-mapped, which is the case until
+    /// boot services are exited.
+    pub unsafe fn from_config_table(config_table: &[ConfigTableEntry]) -> Option<Fdt<'a>> {
+        let entry = config_table
+            .iter()
+            .find(|entry| entry.guid == DEVICE_TREE_GUID)?;
+        let base = entry.address as *const u8;
+        let total_size = u32::from_be((base.add(4) as *const u32).read_unaligned());
+        Self::from_bytes(slice::from_raw_parts(base, total_size as usize)).ok()
+    }
+
+    /// All the bytes of the tree
+    pub fn bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The physical ID of the CPU which boots
+    pub fn boot_cpuid(&self) -> u32 {
+        read_u32(self.data, 28).unwrap()
+    }
+
+    /// Iterate over the memory reservations, as `(address, size)` pairs
+    pub fn reservations(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let data = self.data;
+        let mut offset = read_u32(data, 16).unwrap() as usize;
+        core::iter::from_fn(move || {
+            let address = read_u64(data, offset)?;
+            let size = read_u64(data, offset + 8)?;
+            offset += RESERVATION_SIZE;
+            if address == 0 && size == 0 {
+                None
+            } else {
+                Some((address, size))
+            }
+        })
+    }
+
+    /// Whether the tree has a node at `path`, like `/chosen`
+    pub fn has_node(&self, path: &str) -> bool {
+        find_node(self.structure, path).is_some()
+    }
+
+    /// The value of the property `name` of the node at `path`
+    pub fn property(&self, path: &str, name: &str) -> Option<&'a [u8]> {
+        let node = find_node(self.structure, path)?;
+        find_property(self.structure, self.strings, node, name).map(|(_, _, value)| value)
+    }
+}
+
+/// An editable copy of a device tree
+#[cfg(feature = "exts")]
+#[derive(Debug, Clone)]
+pub struct DeviceTree {
+    boot_cpuid: u32,
+    reservations: Vec<(u64, u64)>,
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+}
+
+#[cfg(feature = "exts")]
+impl DeviceTree {
+    /// Copy the tree of `fdt`
+    pub fn from_fdt(fdt: &Fdt) -> Self {
+        // The structure block is cut after the end token, so that nodes can be
+        // appended before it
+        let mut end = 0;
+        while let Some((token, next)) = token(fdt.structure, end) {
+            end = next;
+            if let Token::End = token {
+                break;
+            }
+        }
+        DeviceTree {
+            boot_cpuid: fdt.boot_cpuid(),
+            reservations: fdt.reservations().collect(),
+            structure: fdt.structure[..end].to_vec(),
+            strings: fdt.strings.to_vec(),
+        }
+    }
+
+    /// A view of the tree
+    fn fdt(&self) -> Fdt<'_> {
+        Fdt {
+            data: &[],
+            structure: &self.structure,
+            strings: &self.strings,
+        }
+    }
+
+    /// The value of the property `name` of the node at `path`
+    pub fn property(&self, path: &str, name: &str) -> Option<&[u8]> {
+        self.fdt().property(path, name)
+    }
+
+    /// The offset of `name` in the strings block, which is added if needed
+    fn string_offset(&mut self, name: &str) -> u32 {
+        let mut offset = 0;
+        while offset < self.strings.len() {
+            let len = self.strings[offset..]
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(self.strings.len() - offset);
+            if &self.strings[offset..offset + len] == name.as_bytes() {
+                return offset as u32;
+            }
+            offset += len + 1;
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        offset
+    }
+
+    /// Replace `range` of the structure block with `bytes`
+    fn splice(&mut self, range: Range<usize>, bytes: &[u8]) {
+        self.structure.splice(range, bytes.iter().copied());
+    }
+
+    /// Find the node at `path`, creating it and its missing parents
+    fn node(&mut self, path: &str) -> Result<Node, FdtError> {
+        if let Some(node) = find_node(&self.structure, path) {
+            return Ok(node);
+        }
+        let path = path.trim_end_matches('/');
+        let (parent, name) = match path.rfind('/') {
+            Some(slash) if !path[slash + 1..].is_empty() => (&path[..slash], &path[slash + 1..]),
+            _ => return Err(FdtError::Malformed),
+        };
+        let parent = self.node(parent)?;
+        let mut node = Vec::new();
+        node.extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+        node.extend_from_slice(name.as_bytes());
+        node.resize(align4(node.len() + 1), 0);
+        node.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+        self.splice(parent.end..parent.end, &node);
+        find_node(&self.structure, path).ok_or(FdtError::Malformed)
+    }
+
+    /// Set the property `name` of the node at `path` to `value`, creating the
+    /// node if needed
+    pub fn set_property(&mut self, path: &str, name: &str, value: &[u8]) -> Result<(), FdtError> {
+        let node = self.node(path)?;
+        let name_offset = self.string_offset(name);
+        let mut prop = Vec::with_capacity(12 + value.len());
+        prop.extend_from_slice(&FDT_PROP.to_be_bytes());
+        prop.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        prop.extend_from_slice(&name_offset.to_be_bytes());
+        prop.extend_from_slice(value);
+        prop.resize(align4(prop.len()), 0);
+        let range = match find_property(&self.structure, &self.strings, node, name) {
+            Some((start, end, _)) => start..end,
+            None => node.props..node.props,
+        };
+        self.splice(range, &prop);
+        Ok(())
+    }
+
+    /// Remove the property `name` of the node at `path`, if it exists
+    pub fn remove_property(&mut self, path: &str, name: &str) {
+        if let Some(node) = find_node(&self.structure, path) {
+            if let Some((start, end, _)) = find_property(&self.structure, &self.strings, node, name)
+            {
+                self.splice(start..end, &[]);
+            }
+        }
+    }
+
+    /// Set the kernel command line, in `/chosen/bootargs`
+    pub fn set_bootargs(&mut self, bootargs: &str) -> Result<(), FdtError> {
+        let mut value = Vec::with_capacity(bootargs.len() + 1);
+        value.extend_from_slice(bootargs.as_bytes());
+        value.push(0);
+        self.set_property("/chosen", "bootargs", &value)
+    }
+
+    /// Set the location of the initrd, from `start` to `end` excluded, in
+    /// `/chosen`
+    pub fn set_initrd(&mut self, start: u64, end: u64) -> Result<(), FdtError> {
+        self.set_property("/chosen", "linux,initrd-start", &start.to_be_bytes())?;
+        self.set_property("/chosen", "linux,initrd-end", &end.to_be_bytes())
+    }
+
+    /// Add a reservation of `size` bytes at `address` to the memory
+    /// reservation block
+    pub fn add_reservation(&mut self, address: u64, size: u64) {
+        self.reservations.push((address, size));
+    }
+
+    /// Describe a reserved range with a node of `/reserved-memory`, which
+    /// the kernel does not map at all if `no_map` is `true`
+    pub fn add_reserved_memory(
+        &mut self,
+        name: &str,
+        address: u64,
+        size: u64,
+        no_map: bool,
+    ) -> Result<(), FdtError> {
+        // The cells of the node are the ones of the root, 2 and 1 by default
+        let cells = |name| {
+            self.property("/", name)
+                .and_then(|value| read_u32(value, 0))
+                .unwrap_or(if name == "#address-cells" { 2 } else { 1 })
+        };
+        let (address_cells, size_cells) = (cells("#address-cells"), cells("#size-cells"));
+        if address_cells > 2 || size_cells > 2 {
+            return Err(FdtError::Unsupported);
+        }
+        if find_node(&self.structure, "/reserved-memory").is_none() {
+            let path = "/reserved-memory";
+            self.set_property(path, "#address-cells", &address_cells.to_be_bytes())?;
+            self.set_property(path, "#size-cells", &size_cells.to_be_bytes())?;
+            self.set_property(path, "ranges", &[])?;
+        }
+
+        let mut reg = Vec::new();
+        for (value, cells) in [(address, address_cells), (size, size_cells)].iter() {
+            let bytes = value.to_be_bytes();
+            reg.extend_from_slice(&bytes[8 - 4 * *cells as usize..]);
+        }
+        let mut path = Vec::new();
+        path.extend_from_slice(b"/reserved-memory/");
+        path.extend_from_slice(name.as_bytes());
+        path.extend_from_slice(crate::alloc_api::format!("@{:x}", address).as_bytes());
+        let path = core::str::from_utf8(&path).unwrap();
+        self.set_property(path, "reg", &reg)?;
+        if no_map {
+            self.set_property(path, "no-map", &[])?;
+        }
+        Ok(())
+    }
+
+    /// Serialize the tree into a device tree blob
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let reservations_offset = HEADER_SIZE;
+        let structure_offset =
+            reservations_offset + RESERVATION_SIZE * (self.reservations.len() + 1);
+        let strings_offset = structure_offset + self.structure.len();
+        let total_size = strings_offset + self.strings.len();
+
+        let mut data = Vec::with_capacity(total_size);
+        for field in [
+            FDT_MAGIC,
+            total_size as u32,
+            structure_offset as u32,
+            strings_offset as u32,
+            reservations_offset as u32,
+            VERSION,
+            LAST_COMP_VERSION,
+            self.boot_cpuid,
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ]
+        .iter()
+        {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+        for &(address, size) in self.reservations.iter().chain(Some(&(0, 0))) {
+            data.extend_from_slice(&address.to_be_bytes());
+            data.extend_from_slice(&size.to_be_bytes());
+        }
+        data.extend_from_slice(&self.structure);
+        data.extend_from_slice(&self.strings);
+        data
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    /// Build a tree with a root, which has `#address-cells`, and a `/memory`
+    /// node
+    fn build_tree() -> Vec<u8> {
+        let mut tree = DeviceTree {
+            boot_cpuid: 0,
+            reservations: Vec::new(),
+            structure: Vec::new(),
+            strings: Vec::new(),
+        };
+        for word in [FDT_BEGIN_NODE, 0, FDT_END_NODE, FDT_END].iter() {
+            tree.structure.extend_from_slice(&word.to_be_bytes());
+        }
+        tree.set_property("/", "#address-cells", &2u32.to_be_bytes())
+            .unwrap();
+        tree.set_property("/memory@40000000", "device_type", b"memory\0")
+            .unwrap();
+        tree.to_bytes()
+    }
+
+    #[test]
+    fn edit_tree() {
+        let data = build_tree();
+        let fdt = Fdt::from_bytes(&data).unwrap();
+        assert!(fdt.has_node("/memory") && fdt.has_node("/memory@40000000"));
+        assert!(!fdt.has_node("/chosen") && !fdt.has_node("/memory@0"));
+        assert_eq!(
+            fdt.property("/memory", "device_type"),
+            Some(&b"memory\0"[..])
+        );
+
+        let mut tree = DeviceTree::from_fdt(&fdt);
+        tree.set_bootargs("quiet").unwrap();
+        tree.set_bootargs("console=ttyS0").unwrap();
+        tree.set_initrd(0x4800_0000, 0x4900_0000).unwrap();
+        tree.add_reservation(0x5000_0000, 0x1000);
+        tree.add_reserved_memory("pstore", 0x6000_0000, 0x10_0000, true)
+            .unwrap();
+        tree.remove_property("/memory", "device_type");
+
+        let data = tree.to_bytes();
+        let fdt = Fdt::from_bytes(&data).unwrap();
+        assert_eq!(
+            fdt.property("/chosen", "bootargs"),
+            Some(&b"console=ttyS0\0"[..])
+        );
+        assert_eq!(
+            fdt.property("/chosen", "linux,initrd-end"),
+            Some(&0x4900_0000u64.to_be_bytes()[..])
+        );
+        assert_eq!(fdt.property("/memory", "device_type"), None);
+        assert!(fdt.reservations().eq(Some((0x5000_0000, 0x1000))));
+        let reg = fdt.property("/reserved-memory/pstore", "reg").unwrap();
+        assert_eq!(reg, &[0, 0, 0, 0, 0x60, 0, 0, 0, 0, 0x10, 0, 0]);
+        assert_eq!(
+            fdt.property("/reserved-memory/pstore@60000000", "no-map"),
+            Some(&[][..])
+        );
+        assert_eq!(
+            fdt.property("/reserved-memory", "#size-cells"),
+            Some(&1u32.to_be_bytes()[..])
+        );
+
+        assert_eq!(
+            Fdt::from_bytes(&data[..20]).err(),
+            Some(FdtError::Truncated)
+        );
+        let mut bad = data.clone();
+        bad[0] = 0;
+        assert_eq!(Fdt::from_bytes(&bad).err(), Some(FdtError::InvalidHeader));
+        let mut bad = data.clone();
+        bad[4..8].copy_from_slice(&32u32.to_be_bytes());
+        assert_eq!(Fdt::from_bytes(&bad).err(), Some(FdtError::InvalidHeader));
+        let mut bad = data.clone();
+        bad[8..12].copy_from_slice(&8u32.to_be_bytes());
+        assert_eq!(Fdt::from_bytes(&bad).err(), Some(FdtError::InvalidHeader));
+        let mut bad = data.clone();
+        bad[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(Fdt::from_bytes(&bad).err(), Some(FdtError::Truncated));
+        let mut bad = data;
+        let total_size = bad.len() as u32;
+        bad[16..20].copy_from_slice(&(total_size - 8).to_be_bytes());
+        assert_eq!(Fdt::from_bytes(&bad).err(), Some(FdtError::Truncated));
+    }
+}
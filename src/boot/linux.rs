@@ -0,0 +1,536 @@
+//! Booting Linux.
+//!
+//! Linux kernels built with `CONFIG_EFI_STUB` are bzImage files which are at
+//! the same time PE images, and can be booted in two ways:
+//!
+//! - [`LinuxBoot::boot_stub`] starts the kernel as a regular UEFI image, with
+//!   the command line as load options. The kernel then sets itself up, and
+//!   loads the initrd through a `LoadFile2` protocol installed for it.
+//! - [`LinuxBoot::boot_handover`] (x86_64 only) uses the EFI handover
+//!   protocol: the boot loader fills the `boot_params` structure ("zero page")
+//!   itself, including the location of the initrd, and jumps to the handover
+//!   entry point of the kernel.
+//!
+//! The kernel and initrd are passed as byte slices. They can be read from a
+//! file with `Directory::read_to_vec`, or from a
+//! [`LoadFile2`](crate::proto::media::load_file::LoadFile2) device with
+//! `load_file_to_vec`.
+//!
+//! See the [Linux boot protocol] for the meaning of the header fields.
+//!
+//! [Linux boot protocol]: https://www.kernel.org/doc/html/latest/x86/boot.html
+
+use crate::proto::device_path::{DevicePath, DevicePathHeader, DeviceSubType, DeviceType};
+use crate::proto::loaded_image::LoadedImage;
+use crate::proto::media::load_file::MemoryFile;
+use crate::proto::ProtocolImpl;
+use crate::table::boot::{size_to_pages, BootServices, MemoryType, PAGE_SIZE};
+use crate::table::{Boot, SystemTable};
+use crate::{Char16, Guid, Handle, Identify, Result, ResultExt, Status};
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::mem;
+
+/// Errors that can occur when parsing a bzImage
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LinuxError {
+    /// The data does not start with a valid setup header.
+    InvalidHeader,
+    /// The kernel uses a boot protocol older than 2.06.
+    Unsupported,
+    /// The headers point outside of the image data.
+    Truncated,
+}
+
+/// Size of the `boot_params` structure
+pub const BOOT_PARAMS_SIZE: usize = 4096;
+
+/// Offset of the setup header in the image and in `boot_params`
+const SETUP_HEADER_OFFSET: usize = 0x1f1;
+/// Oldest supported boot protocol, which introduced `cmdline_size`
+const MIN_PROTOCOL_VERSION: u16 = 0x206;
+/// Boot protocol which introduced the EFI handover protocol
+const HANDOVER_PROTOCOL_VERSION: u16 = 0x20b;
+/// Size of a setup sector
+const SECTOR_SIZE: usize = 512;
+/// Boot loader ID for loaders which have no assigned ID
+const LOADER_TYPE_UNDEFINED: u8 = 0xff;
+/// `loadflags`: the protected-mode code is loaded at 0x100000
+const LOADED_HIGH: u8 = 0x01;
+/// `xloadflags`: the kernel has a 64-bit EFI handover entry point
+const XLF_EFI_HANDOVER_64: u16 = 0x08;
+
+/// Device path on which the EFI stub looks for the `LoadFile2` protocol which
+/// loads the initrd: a vendor media node with `LINUX_EFI_INITRD_MEDIA_GUID`
+#[repr(C, packed)]
+struct InitrdDevicePath {
+    vendor: DevicePathHeader,
+    guid: Guid,
+    end: DevicePathHeader,
+}
+
+static INITRD_DEVICE_PATH: InitrdDevicePath = InitrdDevicePath {
+    vendor: DevicePathHeader {
+        device_type: DeviceType::MEDIA,
+        sub_type: DeviceSubType::MEDIA_VENDOR,
+        length: 20,
+    },
+    guid: Guid::from_values(0x5568e427, 0x68fc, 0x4f3d, 0xac74, 0xca55_5231_cc68),
+    end: DevicePathHeader {
+        device_type: DeviceType::END,
+        sub_type: DeviceSubType::END_ENTIRE,
+        length: 4,
+    },
+};
+
+// Offsets of the fields of `boot_params`, including the setup header
+const EXT_RAMDISK_IMAGE: usize = 0x0c0;
+const EXT_RAMDISK_SIZE: usize = 0x0c4;
+const EXT_CMD_LINE_PTR: usize = 0x0c8;
+const SETUP_SECTS: usize = 0x1f1;
+const BOOT_FLAG: usize = 0x1fe;
+const JUMP: usize = 0x200;
+const HEADER: usize = 0x202;
+const VERSION: usize = 0x206;
+const TYPE_OF_LOADER: usize = 0x210;
+const LOADFLAGS: usize = 0x211;
+const CODE32_START: usize = 0x214;
+const RAMDISK_IMAGE: usize = 0x218;
+const RAMDISK_SIZE: usize = 0x21c;
+const CMD_LINE_PTR: usize = 0x228;
+const INITRD_ADDR_MAX: usize = 0x22c;
+const KERNEL_ALIGNMENT: usize = 0x230;
+const XLOADFLAGS: usize = 0x236;
+const CMDLINE_SIZE: usize = 0x238;
+const INIT_SIZE: usize = 0x260;
+const HANDOVER_OFFSET: usize = 0x264;
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// A parsed bzImage file, borrowing the file contents
+#[derive(Debug, Copy, Clone)]
+pub struct BzImage<'data> {
+    data: &'data [u8],
+    /// End of the setup header
+    header_end: usize,
+    /// Offset of the protected-mode kernel
+    kernel_offset: usize,
+}
+
+impl<'data> BzImage<'data> {
+    /// Parse the setup header of a bzImage file
+    pub fn parse(data: &'data [u8]) -> core::result::Result<Self, LinuxError> {
+        if data.len() < HANDOVER_OFFSET + 4 {
+            return Err(LinuxError::Truncated);
+        }
+        if read_u16(data, BOOT_FLAG) != 0xaa55 || &data[HEADER..HEADER + 4] != b"HdrS" {
+            return Err(LinuxError::InvalidHeader);
+        }
+        if read_u16(data, VERSION) < MIN_PROTOCOL_VERSION {
+            return Err(LinuxError::Unsupported);
+        }
+        // The setup header ends where the jump at its start leads to
+        let header_end = HEADER + data[JUMP + 1] as usize;
+        let setup_sects = match data[SETUP_SECTS] {
+            0 => 4,
+            sects => sects as usize,
+        };
+        let kernel_offset = (setup_sects + 1) * SECTOR_SIZE;
+        if header_end > BOOT_PARAMS_SIZE || kernel_offset > data.len() {
+            return Err(LinuxError::Truncated);
+        }
+        Ok(BzImage {
+            data,
+            header_end,
+            kernel_offset,
+        })
+    }
+
+    /// Version of the boot protocol implemented by the kernel, such as
+    /// `0x20f` for 2.15
+    pub fn protocol_version(&self) -> u16 {
+        read_u16(self.data, VERSION)
+    }
+
+    /// Whole contents of the image, as loaded by `boot_stub`
+    pub fn data(&self) -> &'data [u8] {
+        self.data
+    }
+
+    /// Protected-mode part of the kernel, which follows the setup code
+    pub fn kernel(&self) -> &'data [u8] {
+        &self.data[self.kernel_offset..]
+    }
+
+    /// Whether the image is also a PE image, which can be started with
+    /// `boot_stub`
+    pub fn has_efi_stub(&self) -> bool {
+        self.data.starts_with(b"MZ")
+    }
+
+    /// Whether the kernel supports the 64-bit EFI handover protocol
+    pub fn supports_handover(&self) -> bool {
+        self.protocol_version() >= HANDOVER_PROTOCOL_VERSION
+            && read_u16(self.data, XLOADFLAGS) & XLF_EFI_HANDOVER_64 != 0
+    }
+
+    /// Maximum length of the command line, without the null terminator
+    pub fn cmdline_size(&self) -> usize {
+        read_u32(self.data, CMDLINE_SIZE) as usize
+    }
+
+    /// Highest address that the initrd may occupy
+    pub fn initrd_addr_max(&self) -> u32 {
+        read_u32(self.data, INITRD_ADDR_MAX)
+    }
+
+    /// Alignment that the protected-mode kernel must be loaded at
+    pub fn kernel_alignment(&self) -> usize {
+        read_u32(self.data, KERNEL_ALIGNMENT) as usize
+    }
+
+    /// Amount of memory that the kernel needs at its load address, before it
+    /// relocates itself
+    pub fn init_size(&self) -> usize {
+        if self.protocol_version() >= 0x20a {
+            read_u32(self.data, INIT_SIZE) as usize
+        } else {
+            self.kernel().len()
+        }
+    }
+}
+
+/// Location in memory of the parts of a kernel which is being booted
+#[derive(Debug, Copy, Clone)]
+pub struct BootParamsLayout {
+    /// Address of the protected-mode kernel
+    pub kernel: u64,
+    /// Address of the null-terminated command line
+    pub cmdline: u64,
+    /// Address and size of the initrd, if any
+    pub initrd: Option<(u64, u32)>,
+}
+
+/// Linux kernel which is about to be booted
+#[derive(Debug, Copy, Clone)]
+pub struct LinuxBoot<'data> {
+    image: BzImage<'data>,
+    cmdline: &'data [u8],
+    initrd: Option<&'data [u8]>,
+}
+
+impl<'data> LinuxBoot<'data> {
+    /// Prepare to boot a kernel, with an empty command line and no initrd
+    pub fn new(image: BzImage<'data>) -> Self {
+        LinuxBoot {
+            image,
+            cmdline: b"",
+            initrd: None,
+        }
+    }
+
+    /// Set the kernel command line, which must be ASCII text
+    pub fn cmdline(mut self, cmdline: &'data [u8]) -> Self {
+        self.cmdline = cmdline;
+        self
+    }
+
+    /// Set the contents of the initrd
+    pub fn initrd(mut self, initrd: &'data [u8]) -> Self {
+        self.initrd = Some(initrd);
+        self
+    }
+
+    /// Fill a `boot_params` structure for booting the kernel with the given
+    /// memory layout
+    ///
+    /// The setup header is copied from the image, and the rest of the
+    /// structure is zeroed.
+    pub fn write_boot_params(
+        &self,
+        params: &mut [u8; BOOT_PARAMS_SIZE],
+        layout: &BootParamsLayout,
+    ) {
+        params.iter_mut().for_each(|byte| *byte = 0);
+        let header = SETUP_HEADER_OFFSET..self.image.header_end;
+        params[header.clone()].copy_from_slice(&self.image.data[header]);
+
+        params[TYPE_OF_LOADER] = LOADER_TYPE_UNDEFINED;
+        params[LOADFLAGS] |= LOADED_HIGH;
+        write_u32(params, CODE32_START, layout.kernel as u32);
+        write_u32(params, CMD_LINE_PTR, layout.cmdline as u32);
+        write_u32(params, EXT_CMD_LINE_PTR, (layout.cmdline >> 32) as u32);
+        if let Some((address, size)) = layout.initrd {
+            write_u32(params, RAMDISK_IMAGE, address as u32);
+            write_u32(params, EXT_RAMDISK_IMAGE, (address >> 32) as u32);
+            write_u32(params, RAMDISK_SIZE, size);
+            write_u32(params, EXT_RAMDISK_SIZE, 0);
+        }
+    }
+
+    /// Start the kernel as a UEFI image through its EFI stub, passing the
+    /// command line as load options
+    ///
+    /// The initrd is given through a `LoadFile2` protocol, installed on the
+    /// device path which the EFI stub of Linux 5.8 and later looks for.
+    /// Older kernels ignore it. This only returns if the kernel fails to
+    /// start or exits, and the protocol is then uninstalled.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`        The image has no EFI stub
+    /// * Errors of `load_image`, `start_image` and
+    ///   `install_protocol_interface` are passed through
+    pub fn boot_stub(&self, bt: &BootServices, parent: Handle) -> Result {
+        if !self.image.has_efi_stub() {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        let mut options = bt
+            .allocate_pool_slice(MemoryType::LOADER_DATA, self.cmdline.len() + 1, 0u16)
+            .log_warning()?;
+        for (dest, &c) in options.iter_mut().zip(self.cmdline) {
+            *dest = u16::from(c);
+        }
+
+        let handle = bt
+            .load_image_from_buffer(parent, self.image.data)
+            .log_warning()?;
+        let loaded_image = bt.handle_protocol::<LoadedImage>(handle).log_warning()?;
+        unsafe {
+            let loaded_image = &mut *loaded_image.get();
+            let size = mem::size_of_val(&*options) as u32;
+            loaded_image.set_load_options(options.as_ptr() as *const Char16, size);
+        }
+
+        // Stays in place until it is uninstalled below
+        let mut initrd = self.initrd.map(MemoryFile::new);
+        let initrd_handle = match &mut initrd {
+            Some(file) => match unsafe { install_initrd(bt, file) } {
+                Ok(initrd_handle) => Some(initrd_handle.log()),
+                Err(error) => {
+                    let _ = bt.unload_image(handle);
+                    return Err(error);
+                }
+            },
+            None => None,
+        };
+        let result = bt.start_image(handle);
+        if result.is_err() {
+            let _ = bt.unload_image(handle);
+        }
+        if let (Some(file), Some(initrd_handle)) = (&mut initrd, initrd_handle) {
+            unsafe { uninstall_initrd(bt, file, initrd_handle) };
+        }
+        result
+    }
+
+    /// Boot the kernel through the 64-bit EFI handover protocol
+    ///
+    /// The kernel, command line and initrd are copied to newly allocated
+    /// pages below 4 GiB, and the handover entry point is called with a
+    /// `boot_params` structure describing them. This does not return on
+    /// success.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`        The kernel does not support the handover protocol
+    /// * `uefi::Status::INVALID_PARAMETER`  The command line is too long
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The pages could not be allocated
+    ///
+    /// # Safety
+    ///
+    /// The kernel takes over the machine, and calls `exit_boot_services`
+    /// itself. Nothing may rely on the current application running anymore.
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn boot_handover(&self, image: Handle, st: &SystemTable<Boot>) -> Result {
+        if !self.image.supports_handover() {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        if self.cmdline.len() > self.image.cmdline_size() {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        let bt = st.boot_services();
+        let below_4g = u32::MAX as usize;
+
+        let params = Pages::below(bt, below_4g, BOOT_PARAMS_SIZE)?;
+        let mut cmdline = Pages::below(bt, below_4g, self.cmdline.len() + 1)?;
+        let cmdline_bytes = cmdline.bytes(self.cmdline.len() + 1);
+        cmdline_bytes[..self.cmdline.len()].copy_from_slice(self.cmdline);
+        cmdline_bytes[self.cmdline.len()] = 0;
+
+        let initrd = match self.initrd {
+            Some(data) => {
+                let max = self.image.initrd_addr_max() as usize;
+                let size = data.len().try_into().map_err(|_| Status::BAD_BUFFER_SIZE)?;
+                let mut pages = Pages::below(bt, max, data.len())?;
+                pages.bytes(data.len()).copy_from_slice(data);
+                Some((pages, size))
+            }
+            None => None,
+        };
+
+        // Leave room to align the kernel, which may need more memory than its
+        // size while it decompresses itself
+        let kernel = self.image.kernel();
+        let alignment = self.image.kernel_alignment().max(PAGE_SIZE);
+        let size = self.image.init_size().max(kernel.len());
+        let kernel_pages = Pages::below(bt, below_4g, size + alignment)?;
+        let kernel_addr = (kernel_pages.addr + alignment as u64 - 1) & !(alignment as u64 - 1);
+        core::slice::from_raw_parts_mut(kernel_addr as *mut u8, kernel.len())
+            .copy_from_slice(kernel);
+
+        let layout = BootParamsLayout {
+            kernel: kernel_addr,
+            cmdline: cmdline.addr,
+            initrd: initrd.as_ref().map(|(pages, size)| (pages.addr, *size)),
+        };
+        let params = &mut *(params.addr as *mut [u8; BOOT_PARAMS_SIZE]);
+        self.write_boot_params(params, &layout);
+
+        let entry = kernel_addr + 0x200 + u64::from(read_u32(params, HANDOVER_OFFSET));
+        let entry: extern "sysv64" fn(Handle, *const core::ffi::c_void, *mut u8) -> ! =
+            mem::transmute(entry as usize);
+        entry(image, st.as_ptr(), params.as_mut_ptr())
+    }
+}
+
+/// Pages allocated for the handover protocol, which are freed if booting
+/// fails before the kernel is entered
+#[cfg(target_arch = "x86_64")]
+struct Pages<'bt> {
+    bt: &'bt BootServices,
+    addr: u64,
+    count: usize,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl<'bt> Pages<'bt> {
+    /// Allocate pages holding `size` bytes below `max_addr`
+    fn below(
+        bt: &'bt BootServices,
+        max_addr: usize,
+        size: usize,
+    ) -> core::result::Result<Self, crate::result::Error> {
+        let count = size_to_pages(size);
+        let addr = bt.allocate_pages_below(max_addr, count).log_warning()?;
+        Ok(Pages { bt, addr, count })
+    }
+
+    /// First `size` bytes of the pages
+    unsafe fn bytes(&mut self, size: usize) -> &mut [u8] {
+        core::slice::from_raw_parts_mut(self.addr as *mut u8, size)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Drop for Pages<'_> {
+    fn drop(&mut self) {
+        let _ = self.bt.free_pages(self.addr, self.count);
+    }
+}
+
+/// Install `file` on a new handle with the initrd device path
+unsafe fn install_initrd(bt: &BootServices, file: &mut MemoryFile) -> Result<Handle> {
+    let path = &INITRD_DEVICE_PATH as *const InitrdDevicePath as *mut c_void;
+    let handle = bt
+        .install_protocol_interface(None, &DevicePath::GUID, path)
+        .log_warning()?;
+    let result = file.install(bt, Some(handle));
+    if result.is_err() {
+        let _ = bt.uninstall_protocol_interface(handle, &DevicePath::GUID, path);
+    }
+    result
+}
+
+/// Remove what `install_initrd` installed on `handle`
+unsafe fn uninstall_initrd(bt: &BootServices, file: &mut MemoryFile, handle: Handle) {
+    let path = &INITRD_DEVICE_PATH as *const InitrdDevicePath as *mut c_void;
+    let _ = file.uninstall(bt, handle);
+    let _ = bt.uninstall_protocol_interface(handle, &DevicePath::GUID, path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a bzImage with 1 setup sector and a 16-byte kernel
+    fn build_image() -> [u8; 0x410] {
+        let mut data = [0; 0x410];
+        data[SETUP_SECTS] = 1;
+        data[BOOT_FLAG..BOOT_FLAG + 2].copy_from_slice(&0xaa55u16.to_le_bytes());
+        data[JUMP] = 0xeb;
+        data[JUMP + 1] = 0x6a;
+        data[HEADER..HEADER + 4].copy_from_slice(b"HdrS");
+        data[VERSION..VERSION + 2].copy_from_slice(&0x20fu16.to_le_bytes());
+        data[XLOADFLAGS..XLOADFLAGS + 2].copy_from_slice(&0x0bu16.to_le_bytes());
+        write_u32(&mut data, CMDLINE_SIZE, 2047);
+        write_u32(&mut data, INITRD_ADDR_MAX, 0x7fff_ffff);
+        write_u32(&mut data, HANDOVER_OFFSET, 0x190);
+        data[0x400..].copy_from_slice(&[0x90; 0x10]);
+        data
+    }
+
+    #[test]
+    fn initrd_device_path() {
+        assert_eq!(mem::size_of::<InitrdDevicePath>(), 24);
+        let path = &INITRD_DEVICE_PATH as *const InitrdDevicePath as *const DevicePath;
+        let path = unsafe { &*path };
+        assert_eq!(path.device_type(), DeviceType::MEDIA);
+        assert_eq!(path.sub_type(), DeviceSubType::MEDIA_VENDOR);
+        assert_eq!(path.iter().count(), 1);
+    }
+
+    #[test]
+    fn parse_header() {
+        let data = build_image();
+        let image = BzImage::parse(&data).unwrap();
+        assert_eq!(image.protocol_version(), 0x20f);
+        assert_eq!(image.kernel(), &[0x90; 0x10]);
+        assert!(image.supports_handover());
+        assert!(!image.has_efi_stub());
+        assert_eq!(image.cmdline_size(), 2047);
+
+        let mut old = data;
+        old[VERSION..VERSION + 2].copy_from_slice(&0x204u16.to_le_bytes());
+        assert_eq!(BzImage::parse(&old).err(), Some(LinuxError::Unsupported));
+        let mut bad = data;
+        bad[HEADER] = b'X';
+        assert_eq!(BzImage::parse(&bad).err(), Some(LinuxError::InvalidHeader));
+    }
+
+    #[test]
+    fn boot_params() {
+        let data = build_image();
+        let image = BzImage::parse(&data).unwrap();
+        let boot = LinuxBoot::new(image).cmdline(b"console=ttyS0");
+        let layout = BootParamsLayout {
+            kernel: 0x100_0000,
+            cmdline: 0x1_2345_6000,
+            initrd: Some((0x3000_0000, 0x1000)),
+        };
+        let mut params = [0xff; BOOT_PARAMS_SIZE];
+        boot.write_boot_params(&mut params, &layout);
+
+        assert_eq!(params[0], 0);
+        assert_eq!(&params[HEADER..HEADER + 4], b"HdrS");
+        assert_eq!(read_u32(&params, HANDOVER_OFFSET), 0x190);
+        // Past the end of the setup header
+        assert_eq!(params[0x26c], 0);
+        assert_eq!(params[TYPE_OF_LOADER], 0xff);
+        assert_eq!(read_u32(&params, CODE32_START), 0x100_0000);
+        assert_eq!(read_u32(&params, CMD_LINE_PTR), 0x2345_6000);
+        assert_eq!(read_u32(&params, EXT_CMD_LINE_PTR), 0x1);
+        assert_eq!(read_u32(&params, RAMDISK_IMAGE), 0x3000_0000);
+        assert_eq!(read_u32(&params, RAMDISK_SIZE), 0x1000);
+    }
+}
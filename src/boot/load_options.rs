@@ -0,0 +1,214 @@
+//! Load options of chain-loaded images.
+//!
+//! An image started with `BootServices::start_image` finds its arguments in
+//! the load options of its `LoadedImage` protocol: a UCS-2 command line,
+//! terminated by a null character, optionally followed by binary data. The
+//! Linux EFI stub reads its command line there, the shell passes the command
+//! which started an application, and the Windows Boot Manager finds its
+//! boot entry in the binary data:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::boot::load_options::LoadOptionsBuilder;
+//! # fn f(bt: &BootServices, image: Handle, kernel: &[u8]) -> uefi::Result {
+//! let options = LoadOptionsBuilder::new()
+//!     .command_line("root=/dev/sda2 quiet")
+//!     .build();
+//! let kernel = bt.load_image_from_buffer(image, kernel)?.log();
+//! options.start_image(bt, kernel)?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::alloc_api::vec::Vec;
+use crate::proto::loaded_image::LoadedImage;
+use crate::table::boot::BootServices;
+use crate::{CStr16, Char16, Handle, Result, Status};
+use core::convert::TryFrom;
+use core::mem;
+
+/// Builder of the load options of an image
+///
+/// The command line is built either from a raw string with `command_line`,
+/// which suits kernels, or from arguments with `arg`, which are quoted as
+/// the shell does, for applications which parse their arguments like the
+/// shell. Binary data added with `data` follows the command line.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptionsBuilder {
+    command_line: Vec<u16>,
+    data: Vec<u8>,
+}
+
+impl LoadOptionsBuilder {
+    /// Create a builder of empty load options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `text` to the command line, as is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `text` contains a character which is not UCS-2, or a null
+    /// character.
+    pub fn command_line(mut self, text: &str) -> Self {
+        self.push_str(text);
+        self
+    }
+
+    /// Append an argument to the command line, separated from the previous
+    /// one by a space.
+    ///
+    /// Arguments which are empty or contain spaces are put between double
+    /// quotes, and double quotes and `^` are escaped with `^`, so that the
+    /// shell and `uefi::shell::Args` split them back. Programs which expect
+    /// their name as first argument, as the shell passes it, must be given
+    /// it first.
+    ///
+    /// # Panics
+    ///
+    /// See `command_line`.
+    pub fn arg(mut self, arg: &str) -> Self {
+        if !self.command_line.is_empty() {
+            self.push_str(" ");
+        }
+        let quoted = arg.is_empty() || arg.contains([' ', '\t']);
+        if quoted {
+            self.push_str("\"");
+        }
+        for c in arg.chars() {
+            if c == '"' || c == '^' {
+                self.push_str("^");
+            }
+            self.push_char(c);
+        }
+        if quoted {
+            self.push_str("\"");
+        }
+        self
+    }
+
+    /// Append binary data after the command line
+    pub fn data(mut self, data: &[u8]) -> Self {
+        self.data.extend_from_slice(data);
+        self
+    }
+
+    /// Lay out the load options
+    pub fn build(&self) -> LoadOptions {
+        let size = (self.command_line.len() + 1) * mem::size_of::<u16>() + self.data.len();
+        // Stored as UCS-2 characters, so that the command line is aligned
+        let mut buffer = Vec::with_capacity(size / 2 + size % 2);
+        buffer.extend_from_slice(&self.command_line);
+        buffer.push(0);
+        for pair in self.data.chunks(2) {
+            buffer.push(u16::from_ne_bytes([pair[0], *pair.get(1).unwrap_or(&0)]));
+        }
+        LoadOptions { buffer, size }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        text.chars().for_each(|c| self.push_char(c));
+    }
+
+    fn push_char(&mut self, c: char) {
+        let c = u16::try_from(u32::from(c))
+            .ok()
+            .filter(|&c| c != 0 && Char16::try_from(c).is_ok())
+            .expect("Load options must be non-null UCS-2 characters");
+        self.command_line.push(c);
+    }
+}
+
+/// Load options built by a `LoadOptionsBuilder`
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    buffer: Vec<u16>,
+    /// Size in bytes, which excludes the padding of odd-sized data
+    size: usize,
+}
+
+impl LoadOptions {
+    /// The command line
+    pub fn command_line(&self) -> &CStr16 {
+        let end = self.buffer.iter().position(|&c| c == 0).unwrap();
+        CStr16::from_u16_with_nul(&self.buffer[..=end]).unwrap()
+    }
+
+    /// The load options, as passed to the image
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.buffer.as_ptr().cast(), self.size) }
+    }
+
+    /// Make these the load options of `loaded_image`.
+    ///
+    /// # Safety
+    ///
+    /// The options must not be dropped while the image may use them, which
+    /// is until it exits for applications, and forever for drivers.
+    pub unsafe fn apply(&self, loaded_image: &mut LoadedImage) {
+        loaded_image.set_load_options(self.buffer.as_ptr().cast(), self.size as u32);
+    }
+
+    /// Start the loaded image `image` with these load options, and return
+    /// its exit status.
+    ///
+    /// The load options of the image are cleared when it exits, since they
+    /// may be dropped afterwards.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`  The options are larger than 4 GiB
+    /// * Errors of `handle_protocol` and `start_image` are passed through
+    pub fn start_image(&self, bt: &BootServices, image: Handle) -> Result {
+        if u32::try_from(self.size).is_err() {
+            return Err(Status::BAD_BUFFER_SIZE.into());
+        }
+        let loaded_image = bt.handle_protocol::<LoadedImage>(image)?.log();
+        unsafe { self.apply(&mut *loaded_image.get()) };
+        let result = bt.start_image(image);
+        // Drivers stay loaded, and may outlive the options
+        if let Ok(loaded_image) = bt.handle_protocol::<LoadedImage>(image) {
+            unsafe { (*loaded_image.log().get()).set_load_options(core::ptr::null(), 0) };
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_api::string::String;
+    use crate::shell::Args;
+
+    #[test]
+    fn layout() {
+        let options = LoadOptionsBuilder::new()
+            .command_line("quiet")
+            .data(b"abc")
+            .build();
+        assert_eq!(options.as_bytes(), b"q\0u\0i\0e\0t\0\0\0abc" as &[u8]);
+        assert_eq!(options.command_line().to_u16_slice().len(), 5);
+
+        let options = LoadOptionsBuilder::new().build();
+        assert_eq!(options.as_bytes(), &[0, 0]);
+    }
+
+    #[test]
+    fn quoted_args() {
+        let options = LoadOptionsBuilder::new()
+            .arg("app.efi")
+            .arg("-f")
+            .arg("my file")
+            .arg("")
+            .arg("a\"b^c")
+            .build();
+        let line = String::from_utf16(options.command_line().to_u16_slice()).unwrap();
+        assert_eq!(line, r#"app.efi -f "my file" "" a^"b^^c"#);
+
+        let mut args = Args::from_command_line(&line).unwrap();
+        assert_eq!(args.program(), "app.efi");
+        assert!(args.flag(&["-f"]));
+        assert_eq!(args.finish().unwrap(), ["my file", "", "a\"b^c"]);
+    }
+}
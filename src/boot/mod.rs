@@ -0,0 +1,12 @@
+//! Helpers for booting operating systems.
+//!
+//! These modules implement the boot protocols of specific kernels, on top of
+//! the generic image loaders in [`uefi::pe`](crate::pe) and
+//! [`uefi::elf`](crate::elf).
+
+pub mod fdt;
+pub mod linux;
+#[cfg(feature = "exts")]
+pub mod load_options;
+#[cfg(feature = "exts")]
+pub mod multiboot2;
@@ -0,0 +1,298 @@
+//! Multiboot2 boot information.
+//!
+//! Kernels which follow the [Multiboot2 specification] expect the boot loader
+//! to describe the machine in a boot information structure, made of tags.
+//! [`InfoBuilder`] creates this structure from the data provided by UEFI:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::boot::multiboot2::InfoBuilder;
+//! # fn boot(st: &SystemTable<Boot>, mode: &uefi::proto::console::gop::ModeInfo, fb: u64) -> uefi::Result {
+//! let bt = st.boot_services();
+//! let memory_map = bt.memory_map_owned().log_warning()?;
+//! let info = InfoBuilder::new()
+//!     .cmdline("root=/dev/sda1")
+//!     .memory_map(&memory_map)
+//!     .framebuffer(mode, fb)
+//!     .acpi_rsdp(st)
+//!     .install(bt)
+//!     .log_warning()?;
+//! // Jump to the kernel with `info` in EBX, and 0x36d76289 in EAX
+//! # Status::SUCCESS.into()
+//! # }
+//! ```
+//!
+//! Allocating the pages of the structure changes the memory map, so a kernel
+//! which has to know exactly which memory is free should be given a memory map
+//! retrieved after [`InfoBuilder::install`], by building the structure twice.
+//!
+//! [Multiboot2 specification]: https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+
+use crate::alloc_api::vec::Vec;
+use crate::proto::console::gop::{ModeInfo, PixelFormat};
+use crate::table::boot::{
+    size_to_pages, BootServices, MemoryDescriptor, MemoryMap, MemoryType, PAGE_SIZE,
+};
+use crate::table::cfg::{ACPI2_GUID, ACPI_GUID};
+use crate::table::{Boot, SystemTable};
+use crate::{Result, ResultExt};
+use core::slice;
+
+/// Value passed by the boot loader in EAX, to tell the kernel that it was
+/// loaded by a Multiboot2-compliant boot loader
+pub const BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+const TAG_END: u32 = 0;
+const TAG_CMDLINE: u32 = 1;
+const TAG_BOOT_LOADER_NAME: u32 = 2;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_EFI32_SYSTEM_TABLE: u32 = 11;
+const TAG_EFI64_SYSTEM_TABLE: u32 = 12;
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+
+const MEMORY_AVAILABLE: u32 = 1;
+const MEMORY_RESERVED: u32 = 2;
+const MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+const MEMORY_NVS: u32 = 4;
+const MEMORY_BAD: u32 = 5;
+/// Size of the entries of the memory map tag
+const MEMORY_MAP_ENTRY_SIZE: u32 = 24;
+
+/// Framebuffer type of direct RGB color
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+/// Size of an ACPI 1.0 RSDP
+const RSDP_V1_SIZE: usize = 20;
+/// Offset of the length of the RSDP, since ACPI 2.0
+const RSDP_LENGTH_OFFSET: usize = 20;
+
+/// Builder for a Multiboot2 boot information structure
+#[derive(Debug, Clone)]
+pub struct InfoBuilder {
+    /// The structure, which starts with the total size and a reserved field
+    buffer: Vec<u8>,
+}
+
+impl InfoBuilder {
+    /// Start an empty boot information structure
+    pub fn new() -> Self {
+        InfoBuilder {
+            buffer: crate::alloc_api::vec![0; 8],
+        }
+    }
+
+    /// Add a tag, padding it to an 8-byte boundary
+    fn tag(mut self, ty: u32, parts: &[&[u8]]) -> Self {
+        let size = 8 + parts.iter().map(|part| part.len()).sum::<usize>();
+        self.buffer.extend_from_slice(&ty.to_le_bytes());
+        self.buffer.extend_from_slice(&(size as u32).to_le_bytes());
+        for part in parts {
+            self.buffer.extend_from_slice(part);
+        }
+        let padded = (self.buffer.len() + 7) & !7;
+        self.buffer.resize(padded, 0);
+        self
+    }
+
+    /// Add the kernel command line
+    pub fn cmdline(self, cmdline: &str) -> Self {
+        self.tag(TAG_CMDLINE, &[cmdline.as_bytes(), &[0]])
+    }
+
+    /// Add the name of the boot loader
+    pub fn boot_loader_name(self, name: &str) -> Self {
+        self.tag(TAG_BOOT_LOADER_NAME, &[name.as_bytes(), &[0]])
+    }
+
+    /// Add a memory map built from the UEFI memory map
+    ///
+    /// Memory which is free once boot services are exited is reported as
+    /// available. Memory allocated by the boot loader is reported as reserved,
+    /// as it holds the kernel and this structure.
+    pub fn memory_map(self, memory_map: &MemoryMap) -> Self {
+        self.memory_descriptors(memory_map.entries())
+    }
+
+    /// Add a memory map built from UEFI memory descriptors
+    ///
+    /// See `memory_map`.
+    pub fn memory_descriptors<'a>(
+        self,
+        descriptors: impl IntoIterator<Item = &'a MemoryDescriptor>,
+    ) -> Self {
+        let mut entries = Vec::new();
+        entries.extend_from_slice(&MEMORY_MAP_ENTRY_SIZE.to_le_bytes());
+        entries.extend_from_slice(&0u32.to_le_bytes());
+        for desc in descriptors {
+            let ty = match desc.ty {
+                MemoryType::CONVENTIONAL
+                | MemoryType::BOOT_SERVICES_CODE
+                | MemoryType::BOOT_SERVICES_DATA => MEMORY_AVAILABLE,
+                MemoryType::ACPI_RECLAIM => MEMORY_ACPI_RECLAIMABLE,
+                MemoryType::ACPI_NON_VOLATILE => MEMORY_NVS,
+                MemoryType::UNUSABLE => MEMORY_BAD,
+                _ => MEMORY_RESERVED,
+            };
+            entries.extend_from_slice(&desc.phys_start.to_le_bytes());
+            entries.extend_from_slice(&(desc.page_count * PAGE_SIZE as u64).to_le_bytes());
+            entries.extend_from_slice(&ty.to_le_bytes());
+            entries.extend_from_slice(&0u32.to_le_bytes());
+        }
+        self.tag(TAG_MEMORY_MAP, &[&entries])
+    }
+
+    /// Add a framebuffer in the given graphics mode, located at `base`
+    ///
+    /// Nothing is added for modes which have no frame buffer.
+    pub fn framebuffer(self, info: &ModeInfo, base: u64) -> Self {
+        let mask = match info.pixel_format() {
+            PixelFormat::Rgb => (0xff, 0xff00, 0xff_0000),
+            PixelFormat::Bgr => (0xff_0000, 0xff00, 0xff),
+            PixelFormat::Bitmask => match info.pixel_bitmask() {
+                Some(mask) => (mask.red, mask.green, mask.blue),
+                None => return self,
+            },
+            PixelFormat::BltOnly => return self,
+        };
+        let (width, height) = info.resolution();
+        let pitch = info.stride() as u32 * 4;
+        let field = |mask: u32| [mask.trailing_zeros() as u8, mask.count_ones() as u8];
+        self.tag(
+            TAG_FRAMEBUFFER,
+            &[
+                &base.to_le_bytes(),
+                &pitch.to_le_bytes(),
+                &(width as u32).to_le_bytes(),
+                &(height as u32).to_le_bytes(),
+                &[32, FRAMEBUFFER_TYPE_RGB, 0, 0],
+                &field(mask.0),
+                &field(mask.1),
+                &field(mask.2),
+            ],
+        )
+    }
+
+    /// Add a copy of the ACPI RSDP found in the configuration table
+    ///
+    /// The ACPI 2.0 RSDP is preferred over the ACPI 1.0 one. Nothing is added
+    /// if the firmware provides neither.
+    pub fn acpi_rsdp(self, st: &SystemTable<Boot>) -> Self {
+        let table = |guid| {
+            st.config_table()
+                .iter()
+                .find(|entry| entry.guid == guid)
+                .map(|entry| entry.address as *const u8)
+        };
+        // The firmware guarantees that the tables it installs are valid
+        unsafe {
+            if let Some(rsdp) = table(ACPI2_GUID) {
+                let length = (rsdp.add(RSDP_LENGTH_OFFSET) as *const u32).read_unaligned();
+                self.tag(
+                    TAG_ACPI_NEW,
+                    &[slice::from_raw_parts(rsdp, length as usize)],
+                )
+            } else if let Some(rsdp) = table(ACPI_GUID) {
+                self.tag(TAG_ACPI_OLD, &[slice::from_raw_parts(rsdp, RSDP_V1_SIZE)])
+            } else {
+                self
+            }
+        }
+    }
+
+    /// Add a pointer to the UEFI system table, for kernels which keep using
+    /// runtime services
+    pub fn efi_system_table(self, st: &SystemTable<Boot>) -> Self {
+        let address = st.as_ptr() as usize;
+        if cfg!(target_pointer_width = "64") {
+            self.tag(TAG_EFI64_SYSTEM_TABLE, &[&(address as u64).to_le_bytes()])
+        } else {
+            self.tag(TAG_EFI32_SYSTEM_TABLE, &[&(address as u32).to_le_bytes()])
+        }
+    }
+
+    /// Terminate the structure, and return its contents
+    pub fn finish(self) -> Vec<u8> {
+        let mut buffer = self.tag(TAG_END, &[]).buffer;
+        let size = buffer.len() as u32;
+        buffer[..4].copy_from_slice(&size.to_le_bytes());
+        buffer
+    }
+
+    /// Terminate the structure, and copy it to newly allocated pages below
+    /// 4 GiB, returning their address
+    ///
+    /// # Errors
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The pages could not be allocated
+    pub fn install(self, bt: &BootServices) -> Result<u64> {
+        let info = self.finish();
+        let addr = bt
+            .allocate_pages_below(u32::MAX as usize, size_to_pages(info.len()))
+            .log_warning()?;
+        unsafe { slice::from_raw_parts_mut(addr as *mut u8, info.len()) }.copy_from_slice(&info);
+        Ok(addr.into())
+    }
+}
+
+impl Default for InfoBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryInto;
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Find the tags of the structure, as (type, contents)
+    fn tags(info: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut tags = Vec::new();
+        let mut offset = 8;
+        while offset < info.len() {
+            let size = read_u32(info, offset + 4) as usize;
+            tags.push((read_u32(info, offset), &info[offset + 8..offset + size]));
+            offset += (size + 7) & !7;
+        }
+        tags
+    }
+
+    #[test]
+    fn build_info() {
+        let descriptor = |ty, phys_start, page_count| {
+            let mut desc = MemoryDescriptor::default();
+            desc.ty = ty;
+            desc.phys_start = phys_start;
+            desc.page_count = page_count;
+            desc
+        };
+        let descriptors = [
+            descriptor(MemoryType::CONVENTIONAL, 0x10_0000, 16),
+            descriptor(MemoryType::LOADER_DATA, 0x20_0000, 1),
+        ];
+        let info = InfoBuilder::new()
+            .cmdline("quiet")
+            .memory_descriptors(&descriptors)
+            .finish();
+
+        assert_eq!(read_u32(&info, 0) as usize, info.len());
+        assert_eq!(info.len() % 8, 0);
+        let tags = tags(&info);
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags[0], (TAG_CMDLINE, &b"quiet\0"[..]));
+
+        let (ty, map) = tags[1];
+        assert_eq!(ty, TAG_MEMORY_MAP);
+        assert_eq!(map.len(), 8 + 2 * MEMORY_MAP_ENTRY_SIZE as usize);
+        assert_eq!(read_u32(map, 8), 0x10_0000);
+        assert_eq!(read_u32(map, 16), 0x1_0000);
+        assert_eq!(read_u32(map, 24), MEMORY_AVAILABLE);
+        assert_eq!(read_u32(map, 24 + 24), MEMORY_RESERVED);
+
+        assert_eq!(tags[2], (TAG_END, &[][..]));
+    }
+}
@@ -0,0 +1,147 @@
+//! Checksums of firmware data structures.
+//!
+//! UEFI tables, GPT headers and the EDK2 shell use CRC-32, which
+//! `BootServices::calculate_crc32` computes as well; the functions here work
+//! without boot services. ACPI tables use an 8-bit sum instead.
+
+/// Lookup table of the CRC-32 of all the bytes
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+};
+
+/// Incremental computation of a CRC-32, for data which is not contiguous
+#[derive(Debug, Copy, Clone)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    /// Start a computation
+    pub const fn new() -> Self {
+        Crc32 { state: !0 }
+    }
+
+    /// Add `data` to the checksummed data
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.state =
+                (self.state >> 8) ^ TABLE[((self.state ^ u32::from(byte)) & 0xff) as usize];
+        }
+    }
+
+    /// The checksum of the data added so far
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CRC-32 checksum (IEEE 802.3 polynomial, reflected), as used by gzip, GPT
+/// and the EDK2 shell
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Sum of the bytes of `data`, modulo 256, which is zero for valid ACPI tables
+pub fn sum8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Offset of the size in the common header of the UEFI tables
+const HEADER_SIZE_OFFSET: usize = 12;
+/// Offset of the CRC-32 in the common header of the UEFI tables
+const HEADER_CRC_OFFSET: usize = 16;
+
+/// The CRC-32 of a table which starts with a header like `table::Header`, or
+/// of a GPT header, computed with the field of the checksum set to zero
+///
+/// Only the bytes counted in the size of the header are checksummed. `None`
+/// is returned if the table is smaller than this size.
+pub fn table_crc32(table: &[u8]) -> Option<u32> {
+    let size = table.get(HEADER_SIZE_OFFSET..HEADER_CRC_OFFSET)?;
+    let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+    if size < HEADER_CRC_OFFSET + 4 {
+        return None;
+    }
+    let table = table.get(..size)?;
+    let mut crc = Crc32::new();
+    crc.update(&table[..HEADER_CRC_OFFSET]);
+    crc.update(&[0; 4]);
+    crc.update(&table[HEADER_CRC_OFFSET + 4..]);
+    Some(crc.finish())
+}
+
+/// Whether the CRC-32 in the header of `table` matches its contents
+///
+/// See `table_crc32` for the tables which are supported.
+pub fn table_crc32_is_valid(table: &[u8]) -> bool {
+    let crc = match table.get(HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4) {
+        Some(crc) => u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]),
+        None => return false,
+    };
+    table_crc32(table) == Some(crc)
+}
+
+/// Update the CRC-32 in the header of `table` after it has been modified.
+///
+/// # Panics
+///
+/// Panics if `table` is smaller than the size given by its header.
+pub fn update_table_crc32(table: &mut [u8]) {
+    let crc = table_crc32(table).expect("truncated table");
+    table[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn table_header() {
+        let mut table = [0xa5; 32];
+        table[HEADER_SIZE_OFFSET..HEADER_CRC_OFFSET].copy_from_slice(&24u32.to_le_bytes());
+        assert!(!table_crc32_is_valid(&table));
+        update_table_crc32(&mut table);
+        assert!(table_crc32_is_valid(&table));
+        // Bytes after the size of the table are not checksummed
+        table[30] = 0;
+        assert!(table_crc32_is_valid(&table));
+        table[20] = 0;
+        assert!(!table_crc32_is_valid(&table));
+        assert_eq!(table_crc32(&table[..20]), None);
+        assert_eq!(sum8(&[0x80, 0x7f, 2]), 1);
+    }
+}
@@ -10,6 +10,12 @@ use core::fmt;
 #[derive(Clone, Copy, Debug)]
 pub struct CharConversionError;
 
+impl fmt::Display for CharConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the character cannot be represented in Latin-1 or UCS-2")
+    }
+}
+
 /// A Latin-1 character
 #[derive(Clone, Copy, Default, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(transparent)]
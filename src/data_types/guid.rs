@@ -8,7 +8,7 @@ use core::fmt;
 ///
 /// The `Display` formatter prints GUIDs in the canonical format defined by
 /// RFC 4122, which is also used by UEFI.
-#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[derive(Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 #[repr(C)]
 pub struct Guid {
     /// The low field of the timestamp.
@@ -56,6 +56,13 @@ impl Guid {
     }
 }
 
+impl fmt::Debug for Guid {
+    /// Print the GUID in the canonical format, like `Display`
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
 impl fmt::Display for Guid {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let d = {
@@ -123,6 +130,10 @@ mod tests {
             ),
             "12345678-9abc-def0-1234-56789abcdef0"
         );
+        assert_eq!(
+            alloc::format!("{:?}", Guid::from_values(0, 0, 0, 0, 1)),
+            "00000000-0000-0000-0000-000000000001"
+        );
     }
 
     #[test]
@@ -13,6 +13,20 @@ impl Handle {
     pub(crate) unsafe fn uninitialized() -> Self {
         MaybeUninit::zeroed().assume_init()
     }
+
+    /// Wraps a handle received from the firmware, which may be null
+    pub(crate) fn from_ptr(ptr: *mut c_void) -> Option<Self> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Self(ptr))
+        }
+    }
+
+    /// The pointer which the firmware uses to designate the handle
+    pub(crate) fn as_ptr(self) -> *mut c_void {
+        self.0
+    }
 }
 
 /// Handle to an event structure
@@ -2,6 +2,7 @@ use super::chars::{Char16, NUL_16};
 use super::strs::CStr16;
 use crate::alloc_api::vec::Vec;
 use core::convert::TryFrom;
+use core::fmt;
 use core::ops;
 
 /// Error returned by [`CString16::try_from::<&str>`].
@@ -13,6 +14,15 @@ pub enum FromStrError {
     InteriorNul,
 }
 
+impl fmt::Display for FromStrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidChar => f.write_str("the string contains a character which is not UCS-2"),
+            Self::InteriorNul => f.write_str("the string contains a null character"),
+        }
+    }
+}
+
 /// An owned UCS-2 null-terminated string.
 ///
 /// # Examples
@@ -57,6 +67,12 @@ impl TryFrom<&str> for CString16 {
     }
 }
 
+impl From<&CStr16> for CString16 {
+    fn from(input: &CStr16) -> Self {
+        CString16(input.iter().copied().chain(Some(NUL_16)).collect())
+    }
+}
+
 impl ops::Deref for CString16 {
     type Target = CStr16;
 
@@ -87,4 +103,10 @@ mod tests {
 
         assert_eq!(CString16::try_from("x\0"), Err(FromStrError::InteriorNul));
     }
+
+    #[test]
+    fn test_cstring16_from_cstr16() {
+        let s = CStr16::from_u16_with_nul(&[0x61, 0x62, 0]).unwrap();
+        assert_eq!(CString16::from(s), CString16::try_from("ab").unwrap());
+    }
 }
@@ -1,6 +1,8 @@
 use super::chars::{Char16, Char8, NUL_16, NUL_8};
 #[cfg(feature = "exts")]
 use crate::alloc_api::string::String;
+use crate::proto::string::unicode_collation::UnicodeCollation;
+use core::cmp::Ordering;
 use core::convert::TryInto;
 use core::fmt;
 use core::iter::Iterator;
@@ -20,6 +22,16 @@ pub enum FromSliceWithNulError {
     NotNulTerminated,
 }
 
+impl fmt::Display for FromSliceWithNulError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::InvalidChar(index) => write!(f, "invalid character at index {}", index),
+            Self::InteriorNul(index) => write!(f, "null character at index {}", index),
+            Self::NotNulTerminated => f.write_str("the string is not null-terminated"),
+        }
+    }
+}
+
 /// A Latin-1 null-terminated string
 ///
 /// This type is largely inspired by `std::ffi::CStr`, see the documentation of
@@ -170,6 +182,14 @@ impl CStr16 {
         self.0.len() * 2
     }
 
+    /// Compare this string with `other`, ignoring case, using the rules of
+    /// the given Unicode Collation protocol.
+    ///
+    /// This is how FAT file names are matched.
+    pub fn eq_ignore_case_via(&self, collation: &UnicodeCollation, other: &CStr16) -> bool {
+        collation.stri_coll(self, other) == Ordering::Equal
+    }
+
     /// Writes each [`Char16`] as a [´char´] (4 bytes long in Rust language) into the buffer.
     /// It is up the the implementer of [`core::fmt::Write`] to convert the char to a string
     /// with proper encoding/charset. For example, in the case of [`alloc::string::String`]
@@ -0,0 +1,554 @@
+//! Decoder of the deflate format (RFC 1951), and of the zlib (RFC 1950) and
+//! gzip (RFC 1952) containers around it.
+//!
+//! This is a straightforward bit-by-bit implementation, which favors size
+//! over speed: the Huffman codes are decoded one bit at a time, using the
+//! canonical code counts rather than lookup tables.
+
+use crate::crc::crc32;
+use alloc_api::vec::Vec;
+
+/// Maximum length of a Huffman code
+const MAX_BITS: usize = 15;
+/// Number of literal/length codes in a dynamic block
+const MAX_LIT_CODES: usize = 286;
+/// Number of distance codes in a dynamic block
+const MAX_DIST_CODES: usize = 30;
+/// Number of literal/length codes of the fixed Huffman code
+const FIXED_LIT_CODES: usize = 288;
+
+/// Base lengths of the length symbols 257 to 285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+/// Number of extra bits of the length symbols 257 to 285
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances of the distance symbols
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+/// Number of extra bits of the distance symbols
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Errors which can occur while decompressing data
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InflateError {
+    /// The input ended before the end of the compressed data
+    UnexpectedEnd,
+    /// The zlib or gzip header is invalid, or uses an unsupported feature
+    InvalidHeader,
+    /// A block uses the reserved block type
+    InvalidBlockType,
+    /// The length of a stored block does not match its complement
+    InvalidStoredLength,
+    /// A Huffman code, or a symbol decoded with it, is invalid
+    InvalidCode,
+    /// A back-reference points before the start of the output
+    InvalidDistance,
+    /// The checksum or the length of the decompressed data is wrong
+    ChecksumMismatch,
+}
+
+type Result<T> = core::result::Result<T, InflateError>;
+
+/// Decompress raw deflate data.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    inflate_into(data, &mut output)?;
+    Ok(output)
+}
+
+/// Decompress data in the zlib format.
+///
+/// Streams which need a preset dictionary are not supported.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let (cmf, flg) = match data {
+        [cmf, flg, ..] => (*cmf, *flg),
+        _ => return Err(InflateError::UnexpectedEnd),
+    };
+    let valid = cmf & 0x0f == 8
+        && cmf >> 4 <= 7
+        && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0
+        && flg & 0x20 == 0;
+    if !valid {
+        return Err(InflateError::InvalidHeader);
+    }
+
+    let mut output = Vec::new();
+    let consumed = 2 + inflate_into(&data[2..], &mut output)?;
+    let checksum = data
+        .get(consumed..consumed + 4)
+        .ok_or(InflateError::UnexpectedEnd)?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&output)
+    {
+        return Err(InflateError::ChecksumMismatch);
+    }
+    Ok(output)
+}
+
+/// Decompress data in the gzip format.
+///
+/// Concatenated gzip members are decompressed one after the other, as the
+/// `gzip` tool does. Trailing data after the last member is ignored.
+pub fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut rest = data;
+    loop {
+        rest = gzip_member(rest, &mut output)?;
+        if !rest.starts_with(&[0x1f, 0x8b]) {
+            return Ok(output);
+        }
+    }
+}
+
+/// Whether some data starts like a gzip member
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b, 8])
+}
+
+/// Decompress one gzip member, and return the data which follows it
+fn gzip_member<'a>(data: &'a [u8], output: &mut Vec<u8>) -> Result<&'a [u8]> {
+    const FHCRC: u8 = 1 << 1;
+    const FEXTRA: u8 = 1 << 2;
+    const FNAME: u8 = 1 << 3;
+    const FCOMMENT: u8 = 1 << 4;
+
+    if data.len() < 10 {
+        return Err(InflateError::UnexpectedEnd);
+    }
+    let flags = data[3];
+    if !is_gzip(data) || flags & 0xe0 != 0 {
+        return Err(InflateError::InvalidHeader);
+    }
+
+    // Skip the optional fields of the header
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = data.get(pos..pos + 2).ok_or(InflateError::UnexpectedEnd)?;
+        pos += 2 + usize::from(u16::from_le_bytes([len[0], len[1]]));
+    }
+    for &field in &[FNAME, FCOMMENT] {
+        if flags & field != 0 {
+            let len = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&c| c == 0))
+                .ok_or(InflateError::UnexpectedEnd)?;
+            pos += len + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    let body = data.get(pos..).ok_or(InflateError::UnexpectedEnd)?;
+
+    let start = output.len();
+    let consumed = inflate_into(body, output)?;
+    let trailer = body
+        .get(consumed..consumed + 8)
+        .ok_or(InflateError::UnexpectedEnd)?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    let member = &output[start..];
+    if crc != crc32(member) || size != member.len() as u32 {
+        return Err(InflateError::ChecksumMismatch);
+    }
+    Ok(&body[consumed + 8..])
+}
+
+/// Decompress raw deflate data at the end of `output`, and return the number
+/// of input bytes which were used
+fn inflate_into(data: &[u8], output: &mut Vec<u8>) -> Result<usize> {
+    let mut state = State {
+        input: data,
+        pos: 0,
+        bit_buf: 0,
+        bit_count: 0,
+        start: output.len(),
+        output,
+    };
+    loop {
+        let last = state.bits(1)? == 1;
+        match state.bits(2)? {
+            0 => state.stored()?,
+            1 => state.fixed()?,
+            2 => state.dynamic()?,
+            _ => return Err(InflateError::InvalidBlockType),
+        }
+        if last {
+            return Ok(state.pos);
+        }
+    }
+}
+
+/// Canonical Huffman code
+struct Huffman {
+    /// Number of symbols of each code length
+    counts: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code
+    symbols: [u16; FIXED_LIT_CODES],
+    /// Whether some codes are unused
+    incomplete: bool,
+}
+
+impl Huffman {
+    /// Build the code from the code length of each symbol
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut code = Huffman {
+            counts: [0; MAX_BITS + 1],
+            symbols: [0; FIXED_LIT_CODES],
+            incomplete: false,
+        };
+        for &len in lengths {
+            code.counts[usize::from(len)] += 1;
+        }
+        if usize::from(code.counts[0]) == lengths.len() {
+            code.incomplete = true;
+            return Ok(code);
+        }
+
+        // Check that the code is not over-subscribed
+        let mut left = 1i32;
+        for len in 1..=MAX_BITS {
+            left = (left << 1) - i32::from(code.counts[len]);
+            if left < 0 {
+                return Err(InflateError::InvalidCode);
+            }
+        }
+        code.incomplete = left > 0;
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + code.counts[len];
+        }
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                code.symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Ok(code)
+    }
+
+    /// Incomplete literal/length and distance codes are only allowed when
+    /// they contain a single code
+    fn check_incomplete(&self, symbol_count: usize) -> Result<()> {
+        if self.incomplete && symbol_count != usize::from(self.counts[0] + self.counts[1]) {
+            Err(InflateError::InvalidCode)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Decoder state
+struct State<'a> {
+    input: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+    output: &'a mut Vec<u8>,
+    /// Start of the output of this stream
+    start: usize,
+}
+
+impl State<'_> {
+    /// Read `count` bits, least significant first
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = self.bit_buf;
+        while self.bit_count < count {
+            let byte = *self
+                .input
+                .get(self.pos)
+                .ok_or(InflateError::UnexpectedEnd)?;
+            self.pos += 1;
+            value |= u32::from(byte) << self.bit_count;
+            self.bit_count += 8;
+        }
+        self.bit_buf = value >> count;
+        self.bit_count -= count;
+        Ok(value & ((1 << count) - 1))
+    }
+
+    /// Decode one symbol
+    fn decode(&mut self, code: &Huffman) -> Result<usize> {
+        // The codes of each length are consecutive, and start right after
+        // the shorter codes, shifted by one bit.
+        let mut value = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for len in 1..=MAX_BITS {
+            value |= self.bits(1)? as usize;
+            let count = usize::from(code.counts[len]);
+            if value < first + count {
+                return Ok(usize::from(code.symbols[index + value - first]));
+            }
+            index += count;
+            first = (first + count) << 1;
+            value <<= 1;
+        }
+        Err(InflateError::InvalidCode)
+    }
+
+    fn stored(&mut self) -> Result<()> {
+        // Stored blocks start on a byte boundary
+        self.bit_buf = 0;
+        self.bit_count = 0;
+
+        let header = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or(InflateError::UnexpectedEnd)?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        let complement = u16::from_le_bytes([header[2], header[3]]);
+        if len != !complement {
+            return Err(InflateError::InvalidStoredLength);
+        }
+        self.pos += 4;
+
+        let len = usize::from(len);
+        let data = self
+            .input
+            .get(self.pos..self.pos + len)
+            .ok_or(InflateError::UnexpectedEnd)?;
+        self.output.extend_from_slice(data);
+        self.pos += len;
+        Ok(())
+    }
+
+    fn fixed(&mut self) -> Result<()> {
+        let mut lengths = [0; FIXED_LIT_CODES];
+        lengths[..144].iter_mut().for_each(|len| *len = 8);
+        lengths[144..256].iter_mut().for_each(|len| *len = 9);
+        lengths[256..280].iter_mut().for_each(|len| *len = 7);
+        lengths[280..].iter_mut().for_each(|len| *len = 8);
+        let lit_code = Huffman::new(&lengths)?;
+        let dist_code = Huffman::new(&[5; MAX_DIST_CODES])?;
+        self.codes(&lit_code, &dist_code)
+    }
+
+    fn dynamic(&mut self) -> Result<()> {
+        let lit_count = self.bits(5)? as usize + 257;
+        let dist_count = self.bits(5)? as usize + 1;
+        let code_count = self.bits(4)? as usize + 4;
+        if lit_count > MAX_LIT_CODES || dist_count > MAX_DIST_CODES {
+            return Err(InflateError::InvalidCode);
+        }
+
+        // The code lengths are themselves Huffman-coded
+        let mut lengths = [0; MAX_LIT_CODES + MAX_DIST_CODES];
+        for &symbol in &CODE_LENGTH_ORDER[..code_count] {
+            lengths[symbol] = self.bits(3)? as u8;
+        }
+        let length_code = Huffman::new(&lengths[..19])?;
+        if length_code.incomplete {
+            return Err(InflateError::InvalidCode);
+        }
+
+        let total = lit_count + dist_count;
+        let mut index = 0;
+        while index < total {
+            let symbol = self.decode(&length_code)?;
+            if symbol < 16 {
+                lengths[index] = symbol as u8;
+                index += 1;
+                continue;
+            }
+            let (len, repeat) = match symbol {
+                16 => {
+                    let previous = *index
+                        .checked_sub(1)
+                        .and_then(|i| lengths.get(i))
+                        .ok_or(InflateError::InvalidCode)?;
+                    (previous, 3 + self.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.bits(3)? as usize),
+                _ => (0, 11 + self.bits(7)? as usize),
+            };
+            if index + repeat > total {
+                return Err(InflateError::InvalidCode);
+            }
+            lengths[index..index + repeat]
+                .iter_mut()
+                .for_each(|l| *l = len);
+            index += repeat;
+        }
+
+        // The end-of-block symbol must be encodable
+        if lengths[256] == 0 {
+            return Err(InflateError::InvalidCode);
+        }
+        let lit_code = Huffman::new(&lengths[..lit_count])?;
+        lit_code.check_incomplete(lit_count)?;
+        let dist_code = Huffman::new(&lengths[lit_count..total])?;
+        dist_code.check_incomplete(dist_count)?;
+        self.codes(&lit_code, &dist_code)
+    }
+
+    /// Decode the content of a compressed block
+    fn codes(&mut self, lit_code: &Huffman, dist_code: &Huffman) -> Result<()> {
+        loop {
+            let symbol = self.decode(lit_code)?;
+            match symbol {
+                0..=255 => self.output.push(symbol as u8),
+                256 => return Ok(()),
+                _ => {
+                    let symbol = symbol - 257;
+                    if symbol >= LENGTH_BASE.len() {
+                        return Err(InflateError::InvalidCode);
+                    }
+                    let len = usize::from(LENGTH_BASE[symbol])
+                        + self.bits(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+
+                    let symbol = self.decode(dist_code)?;
+                    if symbol >= DIST_BASE.len() {
+                        return Err(InflateError::InvalidCode);
+                    }
+                    let dist = usize::from(DIST_BASE[symbol])
+                        + self.bits(u32::from(DIST_EXTRA[symbol]))? as usize;
+                    if dist > self.output.len() - self.start {
+                        return Err(InflateError::InvalidDistance);
+                    }
+
+                    // The source and the destination may overlap
+                    let from = self.output.len() - dist;
+                    self.output.reserve(len);
+                    for i in from..from + len {
+                        let byte = self.output[i];
+                        self.output.push(byte);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Adler-32 checksum of the zlib format
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // Reduce before the sums can overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_block() {
+        let data = [
+            0x01, 0x07, 0x00, 0xf8, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x21,
+        ];
+        assert_eq!(inflate(&data).unwrap(), b"stored!");
+
+        let mut corrupted = data;
+        corrupted[3] = 0;
+        assert_eq!(inflate(&corrupted), Err(InflateError::InvalidStoredLength));
+        assert_eq!(inflate(&data[..8]), Err(InflateError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn fixed_block() {
+        let data = [
+            0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x22, 0xcb, 0xf3, 0x8b, 0x72, 0x52,
+            0x00,
+        ];
+        assert_eq!(inflate(&data).unwrap(), b"hello hello hello world");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        let data = [
+            0x9d, 0xd2, 0x5d, 0x16, 0x42, 0x50, 0x18, 0x85, 0xe1, 0x7b, 0xa3, 0xf8, 0x86, 0x60,
+            0x4b, 0x3f, 0x9a, 0x8d, 0x38, 0x4a, 0x0e, 0x27, 0x0a, 0x65, 0xf4, 0x96, 0x66, 0xe0,
+            0xbd, 0xde, 0xeb, 0xbd, 0xda, 0x8f, 0xaf, 0x3b, 0x67, 0xf1, 0xd5, 0x3e, 0x0f, 0x67,
+            0xfd, 0x58, 0x17, 0x8d, 0xdd, 0x86, 0x30, 0x77, 0x56, 0x85, 0xaf, 0x3d, 0xc7, 0xf6,
+            0xf5, 0xb6, 0x30, 0xb9, 0xe1, 0x3f, 0xfb, 0x7c, 0xf9, 0x59, 0x19, 0xee, 0x91, 0xdf,
+            0x1a, 0x81, 0x26, 0x01, 0xcd, 0x01, 0x34, 0x29, 0x68, 0x8e, 0xa0, 0x39, 0x81, 0xe6,
+            0x0c, 0x9a, 0x0b, 0x68, 0x32, 0xf2, 0x29, 0x82, 0x40, 0x24, 0x88, 0x50, 0x10, 0xb1,
+            0x20, 0x82, 0x41, 0x44, 0x83, 0x08, 0x07, 0x11, 0x0f, 0x22, 0x20, 0xb4, 0x53, 0xc4,
+            0x0a,
+        ];
+        let expected: Vec<u8> = (0..20)
+            .flat_map(|i| {
+                alloc_api::format!("line {}: the quick brown fox jumps over the lazy dog\n", i)
+                    .into_bytes()
+            })
+            .collect();
+        assert_eq!(inflate(&data).unwrap(), expected);
+    }
+
+    #[test]
+    fn reserved_block_type() {
+        assert_eq!(inflate(&[0x07]), Err(InflateError::InvalidBlockType));
+    }
+
+    #[test]
+    fn zlib() {
+        let data = [
+            0x78, 0x9c, 0xab, 0xca, 0xc9, 0x4c, 0x52, 0x48, 0xce, 0xcf, 0x2b, 0x49, 0xcc, 0xcc,
+            0x4b, 0x2d, 0x02, 0x00, 0x29, 0x54, 0x05, 0x95,
+        ];
+        assert_eq!(zlib_decompress(&data).unwrap(), b"zlib container");
+
+        let mut corrupted = data;
+        corrupted[21] ^= 1;
+        assert_eq!(
+            zlib_decompress(&corrupted),
+            Err(InflateError::ChecksumMismatch)
+        );
+        assert_eq!(
+            zlib_decompress(&[0x78, 0x9d]),
+            Err(InflateError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn gzip() {
+        let data = [
+            0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x69, 0x6e, 0x69, 0x74,
+            0x72, 0x64, 0x00, 0x4b, 0xaf, 0xca, 0x2c, 0x50, 0x48, 0xce, 0xcf, 0x2b, 0x49, 0xcc,
+            0xcc, 0x4b, 0x2d, 0x02, 0x00, 0x83, 0xb9, 0x2f, 0x9d, 0x0e, 0x00, 0x00, 0x00,
+        ];
+        assert!(is_gzip(&data));
+        assert_eq!(gzip_decompress(&data).unwrap(), b"gzip container");
+
+        // Concatenated members
+        let twice: Vec<u8> = data.iter().chain(data.iter()).copied().collect();
+        assert_eq!(
+            gzip_decompress(&twice).unwrap(),
+            b"gzip containergzip container"
+        );
+
+        let mut corrupted = data;
+        corrupted[37] ^= 1;
+        assert_eq!(
+            gzip_decompress(&corrupted),
+            Err(InflateError::ChecksumMismatch)
+        );
+        assert_eq!(
+            gzip_decompress(&data[..30]),
+            Err(InflateError::UnexpectedEnd)
+        );
+    }
+}
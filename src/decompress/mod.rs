@@ -0,0 +1,27 @@
+//! Decompression of compressed payloads.
+//!
+//! Firmware volumes use the UEFI compression algorithm, which the firmware can
+//! decode through the [`Decompress`] protocol. Kernels and initial ramdisks
+//! are more often compressed with gzip, which the firmware knows nothing
+//! about: the `inflate` feature adds a small decoder for the deflate format
+//! and its gzip and zlib containers, which only needs an allocator.
+//!
+//! ```no_run
+//! # #[cfg(feature = "inflate")]
+//! # fn f(initrd: &[u8]) {
+//! use uefi::decompress;
+//!
+//! let initrd = if decompress::is_gzip(initrd) {
+//!     decompress::gzip_decompress(initrd).expect("Corrupted initrd")
+//! } else {
+//!     initrd.to_vec()
+//! };
+//! # }
+//! ```
+
+pub use crate::proto::decompress::{Decompress, DecompressInfo};
+
+#[cfg(feature = "inflate")]
+mod inflate;
+#[cfg(feature = "inflate")]
+pub use self::inflate::{gzip_decompress, inflate, is_gzip, zlib_decompress, InflateError};
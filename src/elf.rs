@@ -0,0 +1,424 @@
+//! ELF kernel loading.
+//!
+//! Most kernels are not UEFI images, but ELF executables which expect to be
+//! loaded at a fixed address by the boot loader. This module provides a
+//! minimal loader for such executables, which only looks at the program
+//! headers:
+//!
+//! - [`ElfImage::parse`] checks the headers of an ELF64 file read to memory.
+//! - [`ElfImage::load`] allocates the pages covering the loadable segments at
+//!   the addresses they were linked for, using
+//!   [`BootServices::allocate_pages_at`], then copies the segments there and
+//!   zeroes their uninitialized part (BSS).
+//!
+//! The loaded pages are `LOADER_DATA` memory, and show up as such in the
+//! memory map passed to the kernel. No relocations are applied, so the entry
+//! point can be jumped to once the kernel's expected environment has been set
+//! up.
+
+use crate::table::boot::{size_to_pages, BootServices, PAGE_SIZE};
+use crate::{Result, ResultExt, Status};
+use core::convert::TryInto;
+use core::ops::Range;
+use core::slice;
+
+/// Errors that can occur when parsing an ELF image
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ElfError {
+    /// The data does not start with a valid ELF header.
+    InvalidHeader,
+    /// The image is not a little-endian ELF64 executable for this
+    /// architecture.
+    Unsupported,
+    /// The headers point outside of the image data.
+    Truncated,
+}
+
+/// Machine type of x86_64 images
+pub const MACHINE_X86_64: u16 = 62;
+/// Machine type of AArch64 images
+pub const MACHINE_AARCH64: u16 = 183;
+/// Machine type of RISC-V images
+pub const MACHINE_RISCV: u16 = 243;
+
+/// ELF class of 64-bit images
+const ELFCLASS64: u8 = 2;
+/// ELF data encoding of little-endian images
+const ELFDATA2LSB: u8 = 1;
+/// Type of executable images
+const ET_EXEC: u16 = 2;
+/// Type of position-independent images
+const ET_DYN: u16 = 3;
+/// Type of the program headers which describe loadable segments
+const PT_LOAD: u32 = 1;
+/// Size of the ELF64 file header
+const HEADER_SIZE: usize = 64;
+/// Size of an ELF64 program header
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Whether images of the given machine type run on the current CPU
+fn is_native_machine(machine: u16) -> bool {
+    (cfg!(any(target_arch = "x86", target_arch = "x86_64")) && machine == MACHINE_X86_64)
+        || (cfg!(target_arch = "aarch64") && machine == MACHINE_AARCH64)
+        || (cfg!(target_arch = "riscv64") && machine == MACHINE_RISCV)
+}
+
+/// Which address of the program headers segments are loaded at
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LoadAddress {
+    /// Load segments at their physical address (`p_paddr`), which is what
+    /// kernels that set up their own paging, such as Linux, expect.
+    Physical,
+    /// Load segments at their virtual address (`p_vaddr`), for kernels which
+    /// are identity-mapped.
+    Virtual,
+}
+
+/// A loadable segment of an ELF image
+#[derive(Debug, Copy, Clone)]
+pub struct Segment {
+    /// Permissions of the segment (`PF_X`, `PF_W`, `PF_R`).
+    pub flags: u32,
+    /// Offset of the segment contents in the image file.
+    pub offset: u64,
+    /// Virtual address of the segment.
+    pub vaddr: u64,
+    /// Physical address of the segment.
+    pub paddr: u64,
+    /// Size of the segment contents in the image file.
+    pub file_size: u64,
+    /// Size of the segment in memory. The part past `file_size` is zeroed.
+    pub mem_size: u64,
+}
+
+impl Segment {
+    /// Address of the segment in the given address space
+    pub fn address(&self, kind: LoadAddress) -> u64 {
+        match kind {
+            LoadAddress::Physical => self.paddr,
+            LoadAddress::Virtual => self.vaddr,
+        }
+    }
+}
+
+/// A parsed ELF64 image file, borrowing the file contents
+#[derive(Debug, Copy, Clone)]
+pub struct ElfImage<'data> {
+    data: &'data [u8],
+    machine: u16,
+    entry_point: u64,
+    program_headers: usize,
+    program_header_size: usize,
+    program_header_count: usize,
+}
+
+impl<'data> ElfImage<'data> {
+    /// Parse the headers of an ELF64 image file
+    ///
+    /// Images built for another machine than the one this code runs on are
+    /// rejected as `Unsupported`. On IA-32, x86_64 images are accepted, for
+    /// loaders which switch to long mode themselves.
+    pub fn parse(data: &'data [u8]) -> core::result::Result<Self, ElfError> {
+        if data.get(0..4) != Some(b"\x7fELF") {
+            return Err(ElfError::InvalidHeader);
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(ElfError::Truncated);
+        }
+        if data[4] != ELFCLASS64 || data[5] != ELFDATA2LSB {
+            return Err(ElfError::Unsupported);
+        }
+        let ty = read_u16(data, 16).unwrap();
+        if ty != ET_EXEC && ty != ET_DYN {
+            return Err(ElfError::Unsupported);
+        }
+        if !is_native_machine(read_u16(data, 18).unwrap()) {
+            return Err(ElfError::Unsupported);
+        }
+        let image = ElfImage {
+            data,
+            machine: read_u16(data, 18).unwrap(),
+            entry_point: read_u64(data, 24).unwrap(),
+            program_headers: read_u64(data, 32).unwrap() as usize,
+            program_header_size: read_u16(data, 54).unwrap() as usize,
+            program_header_count: read_u16(data, 56).unwrap() as usize,
+        };
+
+        // Check that everything the loader will access is in bounds
+        if image.program_header_size < PROGRAM_HEADER_SIZE {
+            return Err(ElfError::InvalidHeader);
+        }
+        let headers_size = image.program_header_size * image.program_header_count;
+        match image.program_headers.checked_add(headers_size) {
+            Some(end) if end <= data.len() => {}
+            _ => return Err(ElfError::Truncated),
+        }
+        for segment in image.segments() {
+            if segment.file_size > segment.mem_size {
+                return Err(ElfError::InvalidHeader);
+            }
+            match segment.offset.checked_add(segment.file_size) {
+                Some(end) if end <= data.len() as u64 => {}
+                _ => return Err(ElfError::Truncated),
+            }
+            if segment.paddr.checked_add(segment.mem_size).is_none()
+                || segment.vaddr.checked_add(segment.mem_size).is_none()
+            {
+                return Err(ElfError::InvalidHeader);
+            }
+        }
+        Ok(image)
+    }
+
+    /// Machine type that the image was built for (`MACHINE_*`)
+    pub fn machine(&self) -> u16 {
+        self.machine
+    }
+
+    /// Virtual address of the entry point
+    pub fn entry_point(&self) -> u64 {
+        self.entry_point
+    }
+
+    /// Loadable segments of the image, which are not empty in memory
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + 'data {
+        let data = self.data;
+        let (start, size) = (self.program_headers, self.program_header_size);
+        (0..self.program_header_count)
+            .map(move |i| &data[start + i * size..][..PROGRAM_HEADER_SIZE])
+            .filter(|header| read_u32(header, 0) == Some(PT_LOAD))
+            .map(|header| {
+                let field = |offset| read_u64(header, offset).unwrap();
+                Segment {
+                    flags: read_u32(header, 4).unwrap(),
+                    offset: field(8),
+                    vaddr: field(16),
+                    paddr: field(24),
+                    file_size: field(32),
+                    mem_size: field(40),
+                }
+            })
+            .filter(|segment| segment.mem_size != 0)
+    }
+
+    /// Page-aligned range of addresses covered by the loadable segments, or
+    /// `None` if there is no such segment or the range does not fit in the
+    /// address space
+    pub fn memory_range(&self, kind: LoadAddress) -> Option<Range<u64>> {
+        let page_mask = PAGE_SIZE as u64 - 1;
+        let mut range: Option<Range<u64>> = None;
+        for segment in self.segments() {
+            let start = segment.address(kind);
+            let end = start.checked_add(segment.mem_size)?;
+            range = Some(match range {
+                Some(range) => range.start.min(start)..range.end.max(end),
+                None => start..end,
+            });
+        }
+        let range = range?;
+        let end = range.end.checked_add(page_mask)? & !page_mask;
+        Some(range.start & !page_mask..end)
+    }
+
+    /// Copy the segments of the image to `dest`, which holds the memory at
+    /// address `base`, and zero their BSS
+    ///
+    /// # Panics
+    ///
+    /// Panics if a segment does not fit in `dest`.
+    pub fn load_into(&self, dest: &mut [u8], base: u64, kind: LoadAddress) {
+        for segment in self.segments() {
+            let start = (segment.address(kind) - base) as usize;
+            let dest = &mut dest[start..][..segment.mem_size as usize];
+            let (contents, bss) = dest.split_at_mut(segment.file_size as usize);
+            contents.copy_from_slice(&self.data[segment.offset as usize..][..contents.len()]);
+            bss.iter_mut().for_each(|byte| *byte = 0);
+        }
+    }
+
+    /// Load the segments of the image at the addresses they were linked for
+    ///
+    /// The pages between the lowest and the highest segment are allocated as
+    /// a single range, so that segments may share pages.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::LOAD_ERROR`         The image has no loadable segment
+    /// * `uefi::Status::NOT_FOUND`          The pages are already in use
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The pages could not be allocated
+    pub fn load(&self, bt: &BootServices, kind: LoadAddress) -> Result<LoadedElf> {
+        let range = self.memory_range(kind).ok_or(Status::LOAD_ERROR)?;
+        let size: usize = (range.end - range.start)
+            .try_into()
+            .map_err(|_| Status::OUT_OF_RESOURCES)?;
+        let start = range
+            .start
+            .try_into()
+            .map_err(|_| Status::OUT_OF_RESOURCES)?;
+        let base = bt
+            .allocate_pages_at(start, size_to_pages(size))
+            .log_warning()?;
+        let dest = unsafe { slice::from_raw_parts_mut(base as *mut u8, size) };
+        self.load_into(dest, base, kind);
+        Ok(LoadedElf {
+            range,
+            entry_point: self.entry_point,
+        }
+        .into())
+    }
+}
+
+/// An ELF image which was loaded to memory by [`ElfImage::load`]
+#[derive(Debug)]
+pub struct LoadedElf {
+    range: Range<u64>,
+    entry_point: u64,
+}
+
+impl LoadedElf {
+    /// Page-aligned range of memory holding the segments of the image
+    pub fn memory_range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Virtual address of the entry point of the loaded image
+    pub fn entry_point(&self) -> u64 {
+        self.entry_point
+    }
+
+    /// Free the memory of the loaded image
+    ///
+    /// # Safety
+    ///
+    /// The image must not be running, and nothing may refer to its memory.
+    pub unsafe fn free(self, bt: &BootServices) -> Result {
+        let size = (self.range.end - self.range.start) as usize;
+        bt.free_pages(self.range.start, size_to_pages(size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Machine type of the test images, which must be native
+    const MACHINE_NATIVE: u16 = if cfg!(target_arch = "aarch64") {
+        MACHINE_AARCH64
+    } else if cfg!(target_arch = "riscv64") {
+        MACHINE_RISCV
+    } else {
+        MACHINE_X86_64
+    };
+
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Build an image with a code segment at 0x10_0000, in the first page,
+    /// and a data segment with a BSS at 0x10_1800
+    fn build_image() -> [u8; 0x200] {
+        let mut data = [0; 0x200];
+        put(&mut data, 0, b"\x7fELF");
+        data[4] = ELFCLASS64;
+        data[5] = ELFDATA2LSB;
+        data[6] = 1;
+        put(&mut data, 16, &ET_EXEC.to_le_bytes());
+        put(&mut data, 18, &MACHINE_NATIVE.to_le_bytes());
+        put(&mut data, 24, &0xffff_8000_0010_0000u64.to_le_bytes());
+        put(&mut data, 32, &(HEADER_SIZE as u64).to_le_bytes());
+        put(&mut data, 54, &(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        put(&mut data, 56, &3u16.to_le_bytes());
+
+        let segments = [
+            (PT_LOAD, 0x180, 0x10_0000, 0x10, 0x10),
+            // Not loadable, such as PT_GNU_STACK
+            (0x6474_e551, 0, 0, 0, 0),
+            (PT_LOAD, 0x190, 0x10_1800, 0x8, 0x20),
+        ];
+        for (i, &(ty, offset, paddr, file_size, mem_size)) in segments.iter().enumerate() {
+            let header = HEADER_SIZE + i * PROGRAM_HEADER_SIZE;
+            put(&mut data, header, &(ty as u32).to_le_bytes());
+            put(&mut data, header + 8, &(offset as u64).to_le_bytes());
+            let vaddr = 0xffff_8000_0000_0000u64 + paddr;
+            put(&mut data, header + 16, &vaddr.to_le_bytes());
+            put(&mut data, header + 24, &(paddr as u64).to_le_bytes());
+            put(&mut data, header + 32, &(file_size as u64).to_le_bytes());
+            put(&mut data, header + 40, &(mem_size as u64).to_le_bytes());
+        }
+        put(&mut data, 0x180, &[0xaa; 0x10]);
+        put(&mut data, 0x190, &[0xbb; 0x8]);
+        data
+    }
+
+    #[test]
+    fn parse_headers() {
+        let data = build_image();
+        let image = ElfImage::parse(&data).unwrap();
+        assert_eq!(image.machine(), MACHINE_NATIVE);
+        assert_eq!(image.entry_point(), 0xffff_8000_0010_0000);
+        assert_eq!(image.segments().count(), 2);
+        assert_eq!(
+            image.memory_range(LoadAddress::Physical),
+            Some(0x10_0000..0x10_2000)
+        );
+
+        assert_eq!(
+            ElfImage::parse(&data[..0x100]).err(),
+            Some(ElfError::Truncated)
+        );
+        let mut elf32 = data;
+        elf32[4] = 1;
+        assert_eq!(ElfImage::parse(&elf32).err(), Some(ElfError::Unsupported));
+        let mut foreign = data;
+        let machine = if MACHINE_NATIVE == MACHINE_AARCH64 {
+            MACHINE_RISCV
+        } else {
+            MACHINE_AARCH64
+        };
+        put(&mut foreign, 18, &machine.to_le_bytes());
+        assert_eq!(ElfImage::parse(&foreign).err(), Some(ElfError::Unsupported));
+        let mut bad_bss = data;
+        put(&mut bad_bss, HEADER_SIZE + 32, &0x100u64.to_le_bytes());
+        assert_eq!(
+            ElfImage::parse(&bad_bss).err(),
+            Some(ElfError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn range_at_top_of_memory() {
+        let mut data = build_image();
+        let header = HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+        put(&mut data, header + 24, &(u64::MAX - 0x20).to_le_bytes());
+        let image = ElfImage::parse(&data).unwrap();
+        assert_eq!(image.memory_range(LoadAddress::Physical), None);
+    }
+
+    #[test]
+    fn load_segments() {
+        let data = build_image();
+        let image = ElfImage::parse(&data).unwrap();
+        let mut memory = [0xff; 0x2000];
+        image.load_into(&mut memory, 0x10_0000, LoadAddress::Physical);
+        assert_eq!(&memory[..0x10], &[0xaa; 0x10]);
+        assert_eq!(memory[0x10], 0xff);
+        assert_eq!(&memory[0x1800..0x1808], &[0xbb; 8]);
+        assert_eq!(&memory[0x1808..0x1820], &[0; 0x18]);
+        assert_eq!(memory[0x1820], 0xff);
+    }
+}
@@ -0,0 +1,207 @@
+//! `embedded-graphics` draw targets for the screen and for surfaces.
+//!
+//! Colors are `Rgb888`, which converts to `BltPixel` without loss.
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::console::gop::GraphicsOutput;
+//! use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+//! use embedded_graphics_core::prelude::*;
+//! use uefi::embedded::graphics::GopDisplay;
+//!
+//! # fn f(gop: &mut GraphicsOutput) -> core::result::Result<(), uefi::Error> {
+//! let mut display = GopDisplay::new(gop);
+//! display.clear(Rgb888::BLUE)?;
+//! display.draw_iter([Pixel(Point::new(10, 10), Rgb888::WHITE)])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::graphics::native_pixel;
+#[cfg(feature = "exts")]
+use crate::graphics::surface::Surface;
+use crate::proto::console::gop::{BltOp, BltPixel, GraphicsOutput, ModeInfo};
+use crate::result::Error;
+#[cfg(feature = "exts")]
+use core::convert::Infallible;
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{Dimensions, OriginDimensions, Point, Size};
+use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+use embedded_graphics_core::primitives::Rectangle;
+use embedded_graphics_core::Pixel;
+
+impl From<Rgb888> for BltPixel {
+    fn from(color: Rgb888) -> Self {
+        BltPixel::new(color.r(), color.g(), color.b())
+    }
+}
+
+/// The screen of a graphics output, in its current mode
+///
+/// Pixels are written to the frame buffer when the mode has one, and with
+/// `blt` otherwise. Rectangles are always filled with `blt`.
+pub struct GopDisplay<'a, 'boot> {
+    gop: &'a mut GraphicsOutput<'boot>,
+    info: ModeInfo,
+}
+
+impl<'a, 'boot> GopDisplay<'a, 'boot> {
+    /// Draw on the screen of `gop`
+    ///
+    /// The mode must not be changed while the display is in use.
+    pub fn new(gop: &'a mut GraphicsOutput<'boot>) -> Self {
+        let info = gop.current_mode_info();
+        GopDisplay { gop, info }
+    }
+
+    /// Fill a rectangle, which must lie on the screen
+    fn fill(&mut self, area: &Rectangle, color: Rgb888) -> Result<(), Error> {
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+        let op = BltOp::VideoFill {
+            color: color.into(),
+            dest: (area.top_left.x as usize, area.top_left.y as usize),
+            dims: (area.size.width as usize, area.size.height as usize),
+        };
+        self.gop.blt(op)?.log();
+        Ok(())
+    }
+}
+
+impl OriginDimensions for GopDisplay<'_, '_> {
+    fn size(&self) -> Size {
+        let (width, height) = self.info.resolution();
+        Size::new(width as u32, height as u32)
+    }
+}
+
+impl DrawTarget for GopDisplay<'_, '_> {
+    type Color = Rgb888;
+    type Error = Error;
+
+    /// Pixels outside of the screen are ignored
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Pixel<Rgb888>>,
+    {
+        let bounds = self.bounding_box();
+        let mut fb = self.gop.frame_buffer();
+        for Pixel(point, color) in pixels {
+            if !bounds.contains(point) {
+                continue;
+            }
+            let (x, y) = (point.x as usize, point.y as usize);
+            let native = native_pixel(&self.info, color.into());
+            match (native, self.info.pixel_offset(x, y)) {
+                (Some(native), Some(offset)) if offset + native.len() <= fb.size() => unsafe {
+                    fb.write_value(offset, native)
+                },
+                _ => {
+                    // Blt-only modes have no frame buffer
+                    let area = Rectangle::new(point, Size::new(1, 1));
+                    self.fill(&area, color)?;
+                    fb = self.gop.frame_buffer();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parts of the rectangle outside of the screen are clipped
+    fn fill_solid(&mut self, area: &Rectangle, color: Rgb888) -> Result<(), Error> {
+        let area = area.intersection(&self.bounding_box());
+        self.fill(&area, color)
+    }
+
+    fn clear(&mut self, color: Rgb888) -> Result<(), Error> {
+        self.fill(&self.bounding_box(), color)
+    }
+}
+
+#[cfg(feature = "exts")]
+impl OriginDimensions for Surface {
+    fn size(&self) -> Size {
+        let (width, height) = self.resolution();
+        Size::new(width as u32, height as u32)
+    }
+}
+
+/// Drawing marks the pixels as dirty, for the next `flush`
+#[cfg(feature = "exts")]
+impl DrawTarget for Surface {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    /// Pixels outside of the surface are ignored
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<Rgb888>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some((x, y)) = coordinates(point) {
+                self.set_pixel(x, y, color.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Rgb888) -> Result<(), Infallible> {
+        let area = area.intersection(&self.bounding_box());
+        if let Some(dest) = coordinates(area.top_left) {
+            let dims = (area.size.width as usize, area.size.height as usize);
+            self.fill_rect(dest, dims, color.into());
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Rgb888) -> Result<(), Infallible> {
+        Surface::clear(self, color.into());
+        Ok(())
+    }
+}
+
+/// Coordinates of a point, if they are not negative
+#[cfg(feature = "exts")]
+fn coordinates(point: Point) -> Option<(usize, usize)> {
+    if point.x < 0 || point.y < 0 {
+        return None;
+    }
+    Some((point.x as usize, point.y as usize))
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    fn color(px: Option<BltPixel>) -> Option<(u8, u8, u8)> {
+        px.map(|px| (px.red, px.green, px.blue))
+    }
+
+    #[test]
+    fn draw_on_surface() {
+        let mut surface = Surface::new(4, 3);
+        assert_eq!(surface.size(), Size::new(4, 3));
+
+        surface
+            .draw_iter([
+                Pixel(Point::new(1, 2), Rgb888::new(1, 2, 3)),
+                Pixel(Point::new(-1, 0), Rgb888::RED),
+                Pixel(Point::new(4, 0), Rgb888::RED),
+            ])
+            .unwrap();
+        assert_eq!(color(surface.pixel(1, 2)), Some((1, 2, 3)));
+        assert!(surface.pixels().iter().all(|px| px.red != 255));
+
+        // The rectangle is clipped to the surface
+        let area = Rectangle::new(Point::new(-1, -1), Size::new(3, 2));
+        surface.fill_solid(&area, Rgb888::new(9, 9, 9)).unwrap();
+        assert_eq!(color(surface.pixel(0, 0)), Some((9, 9, 9)));
+        assert_eq!(color(surface.pixel(1, 0)), Some((9, 9, 9)));
+        assert_eq!(color(surface.pixel(2, 0)), Some((0, 0, 0)));
+        assert_eq!(color(surface.pixel(0, 1)), Some((0, 0, 0)));
+
+        DrawTarget::clear(&mut surface, Rgb888::RED).unwrap();
+        assert!(surface.pixels().iter().all(|px| px.red == 255));
+    }
+}
@@ -0,0 +1,199 @@
+//! `embedded-io` traits for the serial port, files and TCP streams.
+//!
+//! The errors are the statuses of the UEFI calls, with an `ErrorKind` chosen
+//! from the status. Warnings are logged and otherwise ignored, as with
+//! `ResultExt::log`.
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::console::serial::Serial;
+//! use embedded_io::Write;
+//!
+//! # fn f(serial: &mut Serial) -> core::result::Result<(), uefi::Error> {
+//! Write::write_all(serial, b"Hello over serial\r\n")?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "exts")]
+use crate::net::TcpStream;
+use crate::proto::console::serial::Serial;
+use crate::proto::media::file::{File, RegularFile};
+use crate::result::Error;
+use crate::Status;
+use embedded_io::{ErrorKind, ErrorType, Read, Seek, SeekFrom, Write};
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self.status() {
+            Status::NOT_FOUND => ErrorKind::NotFound,
+            Status::ACCESS_DENIED | Status::WRITE_PROTECTED | Status::SECURITY_VIOLATION => {
+                ErrorKind::PermissionDenied
+            }
+            Status::CONNECTION_REFUSED => ErrorKind::ConnectionRefused,
+            Status::CONNECTION_RESET => ErrorKind::ConnectionReset,
+            Status::CONNECTION_FIN => ErrorKind::BrokenPipe,
+            Status::NOT_STARTED => ErrorKind::NotConnected,
+            Status::IP_ADDRESS_CONFLICT => ErrorKind::AddrInUse,
+            Status::NO_MAPPING => ErrorKind::AddrNotAvailable,
+            Status::INVALID_PARAMETER | Status::BAD_BUFFER_SIZE => ErrorKind::InvalidInput,
+            Status::VOLUME_CORRUPTED | Status::CRC_ERROR | Status::COMPROMISED_DATA => {
+                ErrorKind::InvalidData
+            }
+            Status::TIMEOUT => ErrorKind::TimedOut,
+            Status::ABORTED => ErrorKind::Interrupted,
+            Status::UNSUPPORTED => ErrorKind::Unsupported,
+            Status::OUT_OF_RESOURCES => ErrorKind::OutOfMemory,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl ErrorType for Serial<'_> {
+    type Error = Error;
+}
+
+impl Read for Serial<'_> {
+    /// Wait for data, and read what arrives until the buffer is full or the
+    /// receive timeout of the port expires
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match Serial::read(self, buf) {
+                Ok(completion) => {
+                    completion.log();
+                    return Ok(buf.len());
+                }
+                Err(error) if error.status() == Status::TIMEOUT => {
+                    // Nothing arrived yet
+                    if *error.data() > 0 {
+                        return Ok(*error.data());
+                    }
+                }
+                Err(error) => return Err(error.status().into()),
+            }
+        }
+    }
+}
+
+impl Write for Serial<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        match Serial::write(self, buf) {
+            Ok(completion) => {
+                completion.log();
+                Ok(buf.len())
+            }
+            // Report the data which was written, and the error on the next call
+            Err(error) if *error.data() > 0 => Ok(*error.data()),
+            Err(error) => Err(error.status().into()),
+        }
+    }
+
+    /// The data is sent as it is written
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ErrorType for RegularFile {
+    type Error = Error;
+}
+
+impl Read for RegularFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        match RegularFile::read(self, buf) {
+            Ok(completion) => Ok(completion.log()),
+            Err(error) => Err(error.status().into()),
+        }
+    }
+}
+
+impl Write for RegularFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        match RegularFile::write(self, buf) {
+            Ok(completion) => {
+                completion.log();
+                Ok(buf.len())
+            }
+            Err(error) if *error.data() > 0 => Ok(*error.data()),
+            Err(error) => Err(error.status().into()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        File::flush(self)?.log();
+        Ok(())
+    }
+}
+
+impl Seek for RegularFile {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => (0, position as i64),
+            SeekFrom::Current(offset) => (self.get_position()?.log(), offset),
+            SeekFrom::End(offset) => {
+                // Seeking to the end of the file gives its size
+                self.set_position(RegularFile::END_OF_FILE)?.log();
+                (self.get_position()?.log(), offset)
+            }
+        };
+        let position = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.unsigned_abs())
+        }
+        .ok_or_else(|| Error::from(Status::INVALID_PARAMETER))?;
+        self.set_position(position)?.log();
+        Ok(position)
+    }
+}
+
+#[cfg(feature = "exts")]
+impl ErrorType for TcpStream<'_> {
+    type Error = Error;
+}
+
+#[cfg(feature = "exts")]
+impl Read for TcpStream<'_> {
+    /// Receive data, or return 0 once the peer closed the connection
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match TcpStream::read(self, buf) {
+            Ok(completion) => Ok(completion.log()),
+            Err(error) if error.status() == Status::CONNECTION_FIN => Ok(0),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(feature = "exts")]
+impl Write for TcpStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        Ok(TcpStream::write(self, buf)?.log())
+    }
+
+    /// The data is sent as it is written
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_io::Error as _;
+
+    #[test]
+    fn error_kinds() {
+        let kind = |status: Status| Error::from(status).kind();
+        assert_eq!(kind(Status::NOT_FOUND), ErrorKind::NotFound);
+        assert_eq!(kind(Status::WRITE_PROTECTED), ErrorKind::PermissionDenied);
+        assert_eq!(kind(Status::CONNECTION_RESET), ErrorKind::ConnectionReset);
+        assert_eq!(kind(Status::TIMEOUT), ErrorKind::TimedOut);
+        assert_eq!(kind(Status::DEVICE_ERROR), ErrorKind::Other);
+    }
+}
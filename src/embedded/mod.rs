@@ -0,0 +1,12 @@
+//! Adapters to the traits of the rust-embedded ecosystem.
+//!
+//! Many `no_std` crates, such as text user interfaces, image decoders and
+//! protocol implementations, are written against the I/O traits of
+//! `embedded-io` or the drawing traits of `embedded-graphics`. The features
+//! of the same names implement those traits for the wrappers of this crate,
+//! so that such crates work in UEFI applications without further glue.
+
+#[cfg(feature = "embedded-graphics-core")]
+pub mod graphics;
+#[cfg(feature = "embedded-io")]
+pub mod io;
@@ -0,0 +1,169 @@
+//! Entropy for seeding random number generators.
+//!
+//! The Random Number Generator protocol is the preferred source of entropy,
+//! but many firmwares do not provide it. `fill_random` then falls back to the
+//! generator built into the CPU (`rdseed` or `rdrand` on x86, `rndr` on
+//! AArch64), and as a last resort to the jitter of the CPU timings, which is
+//! slow and weaker, but available everywhere:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # fn f(bt: &BootServices) -> uefi::Result {
+//! let mut seed = [0; 32];
+//! uefi::entropy::fill_random(bt, &mut seed)?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::arch;
+use crate::proto::rng::Rng;
+use crate::table::boot::BootServices;
+use crate::{Result, Status};
+use core::ptr;
+
+/// Number of timing samples mixed into each 64 bits of jitter entropy, which
+/// assumes that each sample holds at least half a bit of entropy
+const SAMPLES_PER_WORD: usize = 128;
+
+/// Number of reads after which the counter is considered to be stuck
+const MAX_SPINS: u64 = 1 << 16;
+
+/// Fill `buffer` with random bytes from the best source available.
+///
+/// # Errors
+///
+/// * `uefi::Status::DEVICE_ERROR`  There is no RNG protocol, the CPU has no
+///                                 generator, and its counter is too coarse
+///                                 to measure timing jitter
+pub fn fill_random(bt: &BootServices, buffer: &mut [u8]) -> Result {
+    if let Ok(rng) = bt.locate_protocol::<Rng>() {
+        let rng = unsafe { &mut *rng.log().get() };
+        // Generators which are not ready yet, or broken, are skipped
+        if rng.get_rng(None, buffer).is_ok() {
+            return Status::SUCCESS.into();
+        }
+    }
+    fill_random_from_cpu(buffer)
+}
+
+/// Fill `buffer` with random bytes from the CPU, without firmware services.
+///
+/// This also works after boot services have been exited.
+///
+/// # Errors
+///
+/// * `uefi::Status::DEVICE_ERROR`  The CPU has no generator, and its counter
+///                                 is too coarse to measure timing jitter
+pub fn fill_random_from_cpu(buffer: &mut [u8]) -> Result {
+    for chunk in buffer.chunks_mut(8) {
+        let word = arch::hardware_random()
+            .or_else(jitter_random)
+            .ok_or(Status::DEVICE_ERROR)?;
+        chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+    }
+    Status::SUCCESS.into()
+}
+
+/// Combines samples of low entropy into well-distributed 64-bit words
+#[derive(Debug, Clone)]
+struct Mixer(u64);
+
+impl Mixer {
+    fn new() -> Self {
+        Mixer(0x243f_6a88_85a3_08d3)
+    }
+
+    fn add(&mut self, sample: u64) {
+        self.0 = (self.0 ^ sample)
+            .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            .rotate_left(29);
+    }
+
+    /// The mixed word, through the finalizer of SplitMix64
+    fn finish(&self) -> u64 {
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Measure how long the counter takes to change, returning the increment
+/// and the number of reads which saw the same value, or `None` if it is stuck
+fn timing_sample(scratch: &mut [u8]) -> Option<(u64, u64)> {
+    let start = arch::read_timestamp();
+    let mut spins = 0;
+    loop {
+        // Memory accesses whose duration depends on the state of the caches
+        let index = (start as usize ^ spins as usize) % scratch.len();
+        unsafe {
+            let value = ptr::read_volatile(&scratch[index]);
+            ptr::write_volatile(&mut scratch[index], value.wrapping_add(start as u8));
+        }
+        let now = arch::read_timestamp();
+        if now != start {
+            return Some((now.wrapping_sub(start), spins));
+        }
+        spins += 1;
+        if spins > MAX_SPINS {
+            return None;
+        }
+    }
+}
+
+/// 64 bits of entropy gathered from the jitter of the CPU timings, or `None`
+/// if the samples do not vary enough
+fn jitter_random() -> Option<u64> {
+    let mut mixer = Mixer::new();
+    let mut scratch = [0; 64];
+    let mut previous = None;
+    let mut changes = 0;
+    for _ in 0..SAMPLES_PER_WORD {
+        let sample = timing_sample(&mut scratch)?;
+        if previous != Some(sample) {
+            changes += 1;
+        }
+        previous = Some(sample);
+        mixer.add(sample.0);
+        mixer.add(sample.1);
+    }
+    // A counter ticking with a fixed number of reads gives no entropy
+    if changes < SAMPLES_PER_WORD / 4 {
+        return None;
+    }
+    Some(mixer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResultExt;
+
+    #[test]
+    fn mixer() {
+        let mix = |samples: &[u64]| {
+            let mut mixer = Mixer::new();
+            for &sample in samples {
+                mixer.add(sample);
+            }
+            mixer.finish()
+        };
+        assert_eq!(mix(&[1, 2, 3]), mix(&[1, 2, 3]));
+        assert_ne!(mix(&[1, 2, 3]), mix(&[1, 2, 4]));
+        assert_ne!(mix(&[1, 2, 3]), mix(&[3, 2, 1]));
+        // A single bit of difference changes about half of the output
+        let diff = (mix(&[0]) ^ mix(&[1])).count_ones();
+        assert!((16..=48).contains(&diff));
+    }
+
+    #[test]
+    fn cpu_entropy() {
+        let (mut a, mut b) = ([0; 20], [0; 20]);
+        fill_random_from_cpu(&mut a).unwrap_success();
+        fill_random_from_cpu(&mut b).unwrap_success();
+        assert_ne!(a, b);
+        if let Some(word) = jitter_random() {
+            assert_ne!(Some(word), jitter_random());
+        }
+    }
+}
@@ -1,11 +1,13 @@
 //! Utility functions for the most common UEFI patterns.
 
+use crate::data_types::Align;
+use crate::{Completion, Result, Status};
 use alloc_api::{
     alloc::{alloc, handle_alloc_error},
     boxed::Box,
 };
 use core::alloc::Layout;
-use core::slice;
+use core::{mem, slice};
 
 /// Creates a boxed byte buffer using the standard allocator.
 ///
@@ -26,3 +28,106 @@ pub fn allocate_buffer(layout: Layout) -> Box<[u8]> {
         Box::from_raw(slice)
     }
 }
+
+/// Boxes a variable-sized structure which is written by `fetch` into a
+/// caller-provided buffer.
+///
+/// `fetch` is first called with an empty buffer, and must fail with the size
+/// it needs, like most UEFI functions do with `BUFFER_TOO_SMALL`. It is then
+/// called again with a buffer of this size, aligned for `T`, and must return
+/// a reference to the start of the buffer. The call is repeated if the size
+/// grew in the meantime.
+///
+/// ```no_run
+/// # use uefi::prelude::*;
+/// # use uefi::proto::media::file::{File, FileInfo, RegularFile};
+/// # fn f(file: &mut RegularFile) -> uefi::Result {
+/// let info = uefi::exts::make_boxed::<FileInfo, _>(|buf| file.get_info(buf))?.log();
+/// # Ok(().into())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// The errors of `fetch` are returned, as well as:
+///
+/// * `uefi::Status::BAD_BUFFER_SIZE`  The structure returned by `fetch` does
+///                                    not fill the buffer
+///
+/// # Panics
+///
+/// Panics if `fetch` succeeds with an empty buffer.
+pub fn make_boxed<T, F>(mut fetch: F) -> Result<Box<T>>
+where
+    T: Align + ?Sized,
+    F: FnMut(&mut [u8]) -> Result<&mut T, Option<usize>>,
+{
+    let mut size = match fetch(&mut []) {
+        Ok(_) => panic!("fetching into an empty buffer unexpectedly succeeded"),
+        Err(err) => match err.split() {
+            (_, Some(size)) => size,
+            (status, None) => return Err(status.into()),
+        },
+    };
+    loop {
+        // The size of a Rust structure is a multiple of its alignment
+        let layout = Layout::from_size_align(size, T::alignment())
+            .unwrap()
+            .pad_to_align();
+        let mut buffer = allocate_buffer(layout);
+        let start = buffer.as_mut_ptr();
+        match fetch(&mut buffer) {
+            Ok(completion) => {
+                let (status, value) = completion.split();
+                // The box takes over the buffer, so it must be freed with the
+                // layout it was allocated with
+                if value as *mut T as *mut u8 != start || Layout::for_value(value) != layout {
+                    return Err(Status::BAD_BUFFER_SIZE.into());
+                }
+                let value = value as *mut T;
+                mem::forget(buffer);
+                return Ok(Completion::new(status, unsafe { Box::from_raw(value) }));
+            }
+            Err(err) => match err.split() {
+                // The structure grew since the size was queried
+                (_, Some(new_size)) if new_size > size => size = new_size,
+                (status, _) => return Err(status.into()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::Error;
+    use crate::ResultExt;
+
+    /// A variable-sized structure, which is a slice of bytes
+    struct Bytes([u8]);
+
+    impl Align for Bytes {
+        fn alignment() -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn make_boxed_retries() {
+        // The required size grows from 5 to 12 bytes after the first call
+        let mut sizes = [5, 12].iter();
+        let boxed = make_boxed::<Bytes, _>(|buf| match sizes.next() {
+            Some(&needed) => Err(Error::new(Status::BUFFER_TOO_SMALL, Some(needed))),
+            None => {
+                buf.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+                Ok(unsafe { &mut *(buf as *mut [u8] as *mut Bytes) }.into())
+            }
+        })
+        .unwrap_success();
+        assert_eq!(boxed.0.len(), 12);
+        assert_eq!(boxed.0[11], 11);
+
+        let status = make_boxed::<Bytes, _>(|_| Err(Error::new(Status::NOT_FOUND, None))).status();
+        assert_eq!(status, Status::NOT_FOUND);
+    }
+}
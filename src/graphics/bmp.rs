@@ -0,0 +1,438 @@
+//! Uncompressed BMP images, as used for boot splash screens and vendor logos.
+//!
+//! Only the uncompressed variants of the format are supported, with 1, 4 or 8
+//! bits per pixel (palettized) or with 24 or 32 bits per pixel (BGR). This
+//! covers the images that firmware vendors ship, including the boot logo
+//! referenced by the ACPI BGRT table.
+//!
+//! Images are written with 24 bits per pixel, which is how screenshots of the
+//! screen are saved:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::console::gop::GraphicsOutput;
+//! # use uefi::proto::media::file::Directory;
+//! # use uefi::CString16;
+//! # use core::convert::TryFrom;
+//! # fn f(gop: &mut GraphicsOutput, esp: &mut Directory) -> uefi::Result {
+//! let path = CString16::try_from("screenshot.bmp").unwrap();
+//! uefi::graphics::bmp::save_screenshot(gop, esp, &path)?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::console::gop::{BltOp, BltPixel, BltRegion, GraphicsOutput};
+#[cfg(feature = "exts")]
+use crate::{alloc_api::vec, alloc_api::vec::Vec, proto::media::file::Directory, CStr16};
+use crate::{Result, ResultExt, Status};
+
+/// Errors that can occur when parsing a BMP image
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BmpError {
+    /// The data does not start with a valid BMP header.
+    InvalidHeader,
+    /// The image uses compression or an unsupported number of bits per pixel.
+    Unsupported,
+    /// The image data ends before the end of the pixel array.
+    Truncated,
+}
+
+/// A parsed BMP image, borrowing its pixel data from the original buffer
+#[derive(Debug, Copy, Clone)]
+pub struct Bmp<'data> {
+    pixels: &'data [u8],
+    palette: &'data [u8],
+    width: usize,
+    height: usize,
+    top_down: bool,
+    bits_per_pixel: usize,
+    row_size: usize,
+}
+
+/// Size of the BITMAPFILEHEADER structure
+const FILE_HEADER_SIZE: usize = 14;
+/// Size of the smallest supported info header (BITMAPINFOHEADER)
+const INFO_HEADER_SIZE: usize = 40;
+/// BI_RGB compression (no compression)
+const COMPRESSION_RGB: u32 = 0;
+/// BI_BITFIELDS compression, only accepted for the standard 32-bit layout
+const COMPRESSION_BITFIELDS: u32 = 3;
+
+/// Width in pixels of the row fragments that are sent to the GOP at once
+const CHUNK_WIDTH: usize = 64;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl<'data> Bmp<'data> {
+    /// Parse a BMP image from the contents of a `.bmp` file
+    pub fn parse(data: &'data [u8]) -> core::result::Result<Self, BmpError> {
+        let header = |offset| read_u32(data, offset).ok_or(BmpError::InvalidHeader);
+        if data.get(0..2) != Some(b"BM") {
+            return Err(BmpError::InvalidHeader);
+        }
+        let pixels_offset = header(10)? as usize;
+        let info_size = header(FILE_HEADER_SIZE)? as usize;
+        if info_size < INFO_HEADER_SIZE {
+            // OS/2 BITMAPCOREHEADER images are not worth supporting
+            return Err(BmpError::Unsupported);
+        }
+        let width = header(FILE_HEADER_SIZE + 4)? as i32;
+        let height = header(FILE_HEADER_SIZE + 8)? as i32;
+        let bits_per_pixel =
+            read_u16(data, FILE_HEADER_SIZE + 14).ok_or(BmpError::InvalidHeader)? as usize;
+        let compression = header(FILE_HEADER_SIZE + 16)?;
+        let colors_used = header(FILE_HEADER_SIZE + 32)? as usize;
+
+        if width <= 0 || height == 0 {
+            return Err(BmpError::InvalidHeader);
+        }
+        match (bits_per_pixel, compression) {
+            (1, COMPRESSION_RGB)
+            | (4, COMPRESSION_RGB)
+            | (8, COMPRESSION_RGB)
+            | (24, COMPRESSION_RGB)
+            | (32, COMPRESSION_RGB)
+            | (32, COMPRESSION_BITFIELDS) => {}
+            _ => return Err(BmpError::Unsupported),
+        }
+
+        // Palettized images are followed by a table of BGRA colors
+        let palette = if bits_per_pixel <= 8 {
+            let entries = if colors_used == 0 {
+                1 << bits_per_pixel
+            } else {
+                colors_used
+            };
+            let start = FILE_HEADER_SIZE + info_size;
+            entries
+                .checked_mul(4)
+                .and_then(|size| data.get(start..start.checked_add(size)?))
+                .ok_or(BmpError::Truncated)?
+        } else {
+            &[]
+        };
+
+        let width = width as usize;
+        let top_down = height < 0;
+        let height = height.unsigned_abs() as usize;
+        // Rows are padded to a multiple of 4 bytes. Sizes which overflow could
+        // not be held by `data` anyway.
+        let row_size = bits_per_pixel
+            .checked_mul(width)
+            .and_then(|bits| bits.checked_add(31))
+            .map(|bits| bits / 32 * 4)
+            .ok_or(BmpError::Truncated)?;
+        let pixels = data
+            .get(pixels_offset..)
+            .and_then(|pixels| pixels.get(..row_size.checked_mul(height)?))
+            .ok_or(BmpError::Truncated)?;
+
+        Ok(Bmp {
+            pixels,
+            palette,
+            width,
+            height,
+            top_down,
+            bits_per_pixel,
+            row_size,
+        })
+    }
+
+    /// Width of the image in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the image in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Color of the pixel at coordinates (`x`, `y`), where (0, 0) is the
+    /// top-left corner of the image
+    ///
+    /// Palette indices which are out of range are rendered as black.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the coordinates are outside of the image.
+    pub fn pixel(&self, x: usize, y: usize) -> BltPixel {
+        assert!(x < self.width && y < self.height, "Pixel out of bounds");
+        let row = if self.top_down {
+            y
+        } else {
+            self.height - 1 - y
+        };
+        let row = &self.pixels[row * self.row_size..][..self.row_size];
+        match self.bits_per_pixel {
+            24 | 32 => {
+                let bytes_per_pixel = self.bits_per_pixel / 8;
+                let px = &row[x * bytes_per_pixel..];
+                BltPixel::new(px[2], px[1], px[0])
+            }
+            bpp => {
+                let bit = x * bpp;
+                let shift = 8 - bpp - bit % 8;
+                let index = (row[bit / 8] >> shift) as usize & ((1 << bpp) - 1);
+                match self.palette.get(4 * index..4 * index + 3) {
+                    Some(color) => BltPixel::new(color[2], color[1], color[0]),
+                    None => BltPixel::new(0, 0, 0),
+                }
+            }
+        }
+    }
+
+    /// Draw the image with its top-left corner at `dest`
+    ///
+    /// Parts of the image which do not fit on screen are clipped.
+    pub fn draw(&self, gop: &mut GraphicsOutput, dest: (usize, usize)) -> Result {
+        self.draw_scaled(gop, dest, (self.width, self.height))
+    }
+
+    /// Draw the image at the center of the screen
+    ///
+    /// Parts of the image which do not fit on screen are clipped.
+    pub fn draw_centered(&self, gop: &mut GraphicsOutput) -> Result {
+        let (screen_width, screen_height) = gop.current_mode_info().resolution();
+        let x = screen_width.saturating_sub(self.width) / 2;
+        let y = screen_height.saturating_sub(self.height) / 2;
+        self.draw(gop, (x, y))
+    }
+
+    /// Draw the image stretched to the `dims` rectangle at `dest`
+    ///
+    /// Scaling uses the nearest neighbor, which keeps pixel art and logos
+    /// sharp. Parts of the rectangle which do not fit on screen are clipped.
+    pub fn draw_scaled(
+        &self,
+        gop: &mut GraphicsOutput,
+        dest: (usize, usize),
+        dims: (usize, usize),
+    ) -> Result {
+        draw_with(gop, dest, dims, |x, y| {
+            self.pixel(x * self.width / dims.0, y * self.height / dims.1)
+        })
+    }
+}
+
+/// Draw the `dims` rectangle at `dest`, taking the color of each of its
+/// pixels from `pixel`
+///
+/// Parts of the rectangle which do not fit on screen are clipped.
+pub(crate) fn draw_with(
+    gop: &mut GraphicsOutput,
+    dest: (usize, usize),
+    dims: (usize, usize),
+    pixel: impl Fn(usize, usize) -> BltPixel,
+) -> Result {
+    let (screen_width, screen_height) = gop.current_mode_info().resolution();
+    if dest.0 >= screen_width || dest.1 >= screen_height {
+        return Status::SUCCESS.into();
+    }
+    let visible_width = dims.0.min(screen_width - dest.0);
+    let visible_height = dims.1.min(screen_height - dest.1);
+
+    let mut chunk = [BltPixel::new(0, 0, 0); CHUNK_WIDTH];
+    for y in 0..visible_height {
+        for chunk_x in (0..visible_width).step_by(CHUNK_WIDTH) {
+            let chunk_width = CHUNK_WIDTH.min(visible_width - chunk_x);
+            for (i, px) in chunk[..chunk_width].iter_mut().enumerate() {
+                *px = pixel(chunk_x + i, y);
+            }
+            gop.blt(BltOp::BufferToVideo {
+                buffer: &chunk[..chunk_width],
+                src: BltRegion::Full,
+                dest: (dest.0 + chunk_x, dest.1 + y),
+                dims: (chunk_width, 1),
+            })
+            .log_warning()?;
+        }
+    }
+    Status::SUCCESS.into()
+}
+
+/// Size of the headers of the images written by `encode`
+#[cfg(feature = "exts")]
+const WRITTEN_HEADER_SIZE: usize = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+
+/// Size of a row of 24-bit pixels, which is padded to 4 bytes
+#[cfg(feature = "exts")]
+fn row_size_24(width: usize) -> usize {
+    (3 * width + 3) & !3
+}
+
+/// A 24-bit BMP image of `width` by `height` pixels, all black
+#[cfg(feature = "exts")]
+fn blank_24(width: usize, height: usize) -> Vec<u8> {
+    let pixels_size = row_size_24(width) * height;
+    let file_size = WRITTEN_HEADER_SIZE + pixels_size;
+    let mut data = vec![0; file_size];
+    data[0..2].copy_from_slice(b"BM");
+    data[2..6].copy_from_slice(&(file_size as u32).to_le_bytes());
+    data[10..14].copy_from_slice(&(WRITTEN_HEADER_SIZE as u32).to_le_bytes());
+    data[14..18].copy_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    data[18..22].copy_from_slice(&(width as i32).to_le_bytes());
+    // A positive height means that rows are stored bottom-up
+    data[22..26].copy_from_slice(&(height as i32).to_le_bytes());
+    data[26..28].copy_from_slice(&1u16.to_le_bytes());
+    data[28..30].copy_from_slice(&24u16.to_le_bytes());
+    data[34..38].copy_from_slice(&(pixels_size as u32).to_le_bytes());
+    data
+}
+
+/// Write `pixels` as the row `y` of an image created by `blank_24`
+#[cfg(feature = "exts")]
+fn set_row_24(data: &mut [u8], height: usize, y: usize, pixels: &[BltPixel]) {
+    let row_size = row_size_24(pixels.len());
+    let start = WRITTEN_HEADER_SIZE + (height - 1 - y) * row_size;
+    for (px, out) in pixels.iter().zip(data[start..].chunks_exact_mut(3)) {
+        out.copy_from_slice(&[px.blue, px.green, px.red]);
+    }
+}
+
+/// Encode an image of `width` by `height` pixels, given row by row from the
+/// top, as a 24-bit BMP file
+///
+/// # Panics
+///
+/// Panics if `pixels` does not hold `width * height` pixels.
+#[cfg(feature = "exts")]
+pub fn encode(width: usize, height: usize, pixels: &[BltPixel]) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height, "wrong number of pixels");
+    let mut data = blank_24(width, height);
+    if width > 0 {
+        for (y, row) in pixels.chunks_exact(width).enumerate() {
+            set_row_24(&mut data, height, y, row);
+        }
+    }
+    data
+}
+
+/// Capture the screen as a 24-bit BMP file
+///
+/// The pixels are read with the `VideoToBltBuffer` operation, which also
+/// works in modes without a frame buffer.
+#[cfg(feature = "exts")]
+pub fn screenshot(gop: &mut GraphicsOutput) -> Result<Vec<u8>> {
+    let (width, height) = gop.current_mode_info().resolution();
+    let mut data = blank_24(width, height);
+    // The screen is read one row at a time, to avoid a second copy of it
+    let mut row = vec![BltPixel::new(0, 0, 0); width];
+    for y in 0..height {
+        gop.blt(BltOp::VideoToBltBuffer {
+            buffer: &mut row,
+            src: (0, y),
+            dest: BltRegion::Full,
+            dims: (width, 1),
+        })
+        .log_warning()?;
+        set_row_24(&mut data, height, y, &row);
+    }
+    Ok(data.into())
+}
+
+/// Capture the screen, and save it as a BMP file at `path` in `dir`
+#[cfg(feature = "exts")]
+pub fn save_screenshot(gop: &mut GraphicsOutput, dir: &mut Directory, path: &CStr16) -> Result {
+    let data = screenshot(gop).log_warning()?;
+    dir.write_file(path, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a bottom-up 24-bit BMP from rows of (r, g, b) pixels given top to bottom
+    fn bmp_24(rows: &[&[(u8, u8, u8)]], data: &mut [u8; 128]) -> usize {
+        let width = rows[0].len();
+        let row_size = (24 * width + 31) / 32 * 4;
+        data[0..2].copy_from_slice(b"BM");
+        data[10..14].copy_from_slice(&54u32.to_le_bytes());
+        data[14..18].copy_from_slice(&40u32.to_le_bytes());
+        data[18..22].copy_from_slice(&(width as i32).to_le_bytes());
+        data[22..26].copy_from_slice(&(rows.len() as i32).to_le_bytes());
+        data[28..30].copy_from_slice(&24u16.to_le_bytes());
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, &(r, g, b)) in row.iter().enumerate() {
+                let offset = 54 + y * row_size + 3 * x;
+                data[offset..offset + 3].copy_from_slice(&[b, g, r]);
+            }
+        }
+        54 + row_size * rows.len()
+    }
+
+    #[test]
+    fn parse_24bpp() {
+        let mut data = [0; 128];
+        let len = bmp_24(
+            &[&[(1, 2, 3), (4, 5, 6), (7, 8, 9)], &[(10, 11, 12); 3]],
+            &mut data,
+        );
+        let bmp = Bmp::parse(&data[..len]).unwrap();
+        assert_eq!((bmp.width(), bmp.height()), (3, 2));
+        let px = bmp.pixel(1, 0);
+        assert_eq!((px.red, px.green, px.blue), (4, 5, 6));
+        let px = bmp.pixel(2, 1);
+        assert_eq!((px.red, px.green, px.blue), (10, 11, 12));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut data = [0; 128];
+        let len = bmp_24(&[&[(1, 2, 3)]], &mut data);
+        assert_eq!(
+            Bmp::parse(&data[..20]).unwrap_err(),
+            BmpError::InvalidHeader
+        );
+        assert_eq!(
+            Bmp::parse(&data[..len - 1]).unwrap_err(),
+            BmpError::Truncated
+        );
+        let mut compressed = data;
+        compressed[30] = 1;
+        assert_eq!(
+            Bmp::parse(&compressed[..len]).unwrap_err(),
+            BmpError::Unsupported
+        );
+        assert_eq!(Bmp::parse(b"PNG").unwrap_err(), BmpError::InvalidHeader);
+
+        // Sizes which overflow must not wrap around to small values
+        let mut huge = data;
+        huge[18..22].copy_from_slice(&i32::MAX.to_le_bytes());
+        huge[22..26].copy_from_slice(&i32::MIN.to_le_bytes());
+        huge[28..30].copy_from_slice(&32u16.to_le_bytes());
+        assert_eq!(Bmp::parse(&huge[..len]).unwrap_err(), BmpError::Truncated);
+        let mut palette = data;
+        palette[28..30].copy_from_slice(&8u16.to_le_bytes());
+        palette[46..50].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            Bmp::parse(&palette[..len]).unwrap_err(),
+            BmpError::Truncated
+        );
+    }
+
+    #[cfg(feature = "exts")]
+    #[test]
+    fn encode_round_trip() {
+        let pixels: Vec<_> = (0..6).map(|i| BltPixel::new(i, 10 * i, 20 * i)).collect();
+        let data = encode(3, 2, &pixels);
+        assert_eq!(data.len(), 54 + 2 * 12);
+        let bmp = Bmp::parse(&data).unwrap();
+        assert_eq!((bmp.width(), bmp.height()), (3, 2));
+        for (i, px) in pixels.iter().enumerate() {
+            let parsed = bmp.pixel(i % 3, i / 3);
+            assert_eq!(
+                (parsed.red, parsed.green, parsed.blue),
+                (px.red, px.green, px.blue)
+            );
+        }
+    }
+}
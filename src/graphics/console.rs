@@ -0,0 +1,482 @@
+//! Text console rendered on the graphics output.
+//!
+//! Some machines boot with a working GOP but a broken or missing text
+//! console, for example when the firmware only sets up a serial console, or
+//! when the console driver does not support the resolution of the display.
+//! A [`GraphicsConsole`] implements the Simple Text Output protocol on top of
+//! the GOP, drawing the text with a bitmap [`Font`]:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::graphics::{console::GraphicsConsole, font::Font};
+//! # use uefi::proto::console::gop::GraphicsOutput;
+//! # use uefi::proto::ProtocolImpl;
+//! # use core::fmt::Write;
+//! # fn f(bt: &BootServices, gop: &'static mut GraphicsOutput<'static>, psf: &'static [u8]) -> uefi::Result {
+//! let font = Font::from_psf(psf).unwrap();
+//! let mut console = GraphicsConsole::new(gop, font).log_warning()?;
+//! writeln!(console.output(), "Hello from the GOP").unwrap();
+//!
+//! // Let other applications and drivers find the console
+//! let handle = unsafe { console.install(bt, None) }.log_warning()?;
+//! # Status::SUCCESS.into()
+//! # }
+//! ```
+//!
+//! The console has a single text mode, whose geometry is the number of
+//! characters that fit on the screen. Text scrolls up once the last row is
+//! full. Characters for which the font has no glyph are drawn as `?`, and
+//! reported with the `WARN_UNKNOWN_GLYPH` status.
+
+use super::font::Font;
+use crate::alloc_api::{boxed::Box, vec, vec::Vec};
+use crate::proto::console::gop::{BltOp, BltPixel, BltRegion, GraphicsOutput};
+use crate::proto::console::text::Output;
+use crate::proto::ProtocolImpl;
+use crate::{Char16, Result, Status};
+use core::ffi::c_void;
+
+/// Attribute of the text after a reset: light gray on black
+const DEFAULT_ATTRIBUTE: u8 = 0x07;
+
+/// Colors of the 16 text attributes, as defined by the EDK2 graphics console
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x98),
+    (0x00, 0x98, 0x00),
+    (0x00, 0x98, 0x98),
+    (0x98, 0x00, 0x00),
+    (0x98, 0x00, 0x98),
+    (0x98, 0x98, 0x00),
+    (0x98, 0x98, 0x98),
+    (0x30, 0x30, 0x30),
+    (0x00, 0x00, 0xff),
+    (0x00, 0xff, 0x00),
+    (0x00, 0xff, 0xff),
+    (0xff, 0x00, 0x00),
+    (0xff, 0x00, 0xff),
+    (0xff, 0xff, 0x00),
+    (0xff, 0xff, 0xff),
+];
+
+/// `SIMPLE_TEXT_OUTPUT_MODE`
+#[repr(C)]
+struct ModeData {
+    max_mode: i32,
+    mode: i32,
+    attribute: i32,
+    cursor_column: i32,
+    cursor_row: i32,
+    cursor_visible: bool,
+}
+
+/// A character of the screen, and the attribute it was written with
+#[derive(Copy, Clone)]
+struct Cell {
+    c: char,
+    attribute: u8,
+}
+
+/// A Simple Text Output protocol implementation drawing to the GOP
+///
+/// While it is installed, the console must not be used by Rust code, since
+/// the firmware may call it at any time.
+#[repr(C)]
+pub struct GraphicsConsole<'gop, 'boot> {
+    // The function table and mode pointer of `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`
+    // come first, so that a pointer to the console is a pointer to the protocol
+    reset: unsafe extern "efiapi" fn(this: *mut Self, extended: bool) -> Status,
+    output_string: unsafe extern "efiapi" fn(this: *mut Self, string: *const Char16) -> Status,
+    test_string: unsafe extern "efiapi" fn(this: *mut Self, string: *const Char16) -> Status,
+    query_mode: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        mode: usize,
+        columns: *mut usize,
+        rows: *mut usize,
+    ) -> Status,
+    set_mode: unsafe extern "efiapi" fn(this: *mut Self, mode: usize) -> Status,
+    set_attribute: unsafe extern "efiapi" fn(this: *mut Self, attribute: usize) -> Status,
+    clear_screen: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+    set_cursor_position:
+        unsafe extern "efiapi" fn(this: *mut Self, column: usize, row: usize) -> Status,
+    enable_cursor: unsafe extern "efiapi" fn(this: *mut Self, visible: bool) -> Status,
+    mode: *const ModeData,
+
+    data: ModeData,
+    gop: &'gop mut GraphicsOutput<'boot>,
+    font: Font<'gop>,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    glyph: Vec<BltPixel>,
+}
+
+unsafe impl ProtocolImpl for GraphicsConsole<'_, '_> {
+    type Protocol = Output<'static>;
+
+    fn interface(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+}
+
+impl<'gop, 'boot> GraphicsConsole<'gop, 'boot> {
+    /// Create a console covering the whole screen, in the current mode of
+    /// `gop`, and clear the screen.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`   The glyphs of the font are larger than
+    ///                                 the screen
+    /// * `uefi::Status::DEVICE_ERROR`  The screen could not be cleared
+    pub fn new(gop: &'gop mut GraphicsOutput<'boot>, font: Font<'gop>) -> Result<Box<Self>> {
+        let (width, height) = gop.current_mode_info().resolution();
+        let (glyph_width, glyph_height) = font.glyph_size();
+        let (columns, rows) = (width / glyph_width, height / glyph_height);
+        if columns == 0 || rows == 0 {
+            return Err(Status::UNSUPPORTED.into());
+        }
+
+        let blank = Cell {
+            c: ' ',
+            attribute: DEFAULT_ATTRIBUTE,
+        };
+        let mut console = Box::new(GraphicsConsole {
+            reset,
+            output_string,
+            test_string,
+            query_mode,
+            set_mode,
+            set_attribute,
+            clear_screen,
+            set_cursor_position,
+            enable_cursor,
+            mode: core::ptr::null(),
+            data: ModeData {
+                max_mode: 1,
+                mode: 0,
+                attribute: DEFAULT_ATTRIBUTE.into(),
+                cursor_column: 0,
+                cursor_row: 0,
+                cursor_visible: true,
+            },
+            gop,
+            font,
+            columns,
+            rows,
+            cells: vec![blank; columns * rows],
+            glyph: vec![BltPixel::new(0, 0, 0); glyph_width * glyph_height],
+        });
+        console.mode = &console.data;
+        console.clear().into_with_val(|| console)
+    }
+
+    /// Number of columns and rows of the console
+    pub fn size(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    /// Access the console through the Simple Text Output protocol
+    pub fn output(&mut self) -> &mut Output<'_> {
+        unsafe { &mut *(self as *mut Self as *mut Output) }
+    }
+
+    /// Foreground and background colors of an attribute
+    fn colors(attribute: u8) -> (BltPixel, BltPixel) {
+        let color = |index: u8| {
+            let (red, green, blue) = PALETTE[usize::from(index)];
+            BltPixel::new(red, green, blue)
+        };
+        (color(attribute & 0x0f), color((attribute >> 4) & 0x07))
+    }
+
+    fn cursor(&self) -> (usize, usize) {
+        (
+            self.data.cursor_column as usize,
+            self.data.cursor_row as usize,
+        )
+    }
+
+    /// Draw a cell of the screen, with its colors swapped if it holds the
+    /// visible cursor
+    fn draw_cell(&mut self, column: usize, row: usize) -> Status {
+        let cell = self.cells[row * self.columns + column];
+        let (mut fg, mut bg) = Self::colors(cell.attribute);
+        if self.data.cursor_visible && self.cursor() == (column, row) {
+            core::mem::swap(&mut fg, &mut bg);
+        }
+
+        let (width, height) = self.font.glyph_size();
+        for y in 0..height {
+            for x in 0..width {
+                self.glyph[y * width + x] = if self.font.is_set(cell.c, x, y) {
+                    fg
+                } else {
+                    bg
+                };
+            }
+        }
+        blt(
+            self.gop,
+            BltOp::BufferToVideo {
+                buffer: &self.glyph,
+                src: BltRegion::Full,
+                dest: (column * width, row * height),
+                dims: (width, height),
+            },
+        )
+    }
+
+    /// Move the cursor, redrawing the cells it leaves and enters
+    fn move_cursor(&mut self, column: usize, row: usize) -> Status {
+        let (old_column, old_row) = self.cursor();
+        self.data.cursor_column = column as i32;
+        self.data.cursor_row = row as i32;
+        if !self.data.cursor_visible || (old_column, old_row) == (column, row) {
+            return Status::SUCCESS;
+        }
+        let status = self.draw_cell(old_column, old_row);
+        if status.is_error() {
+            return status;
+        }
+        self.draw_cell(column, row)
+    }
+
+    /// Clear the screen with the background color of the current attribute,
+    /// and move the cursor to the top-left corner
+    fn clear(&mut self) -> Status {
+        let attribute = self.data.attribute as u8;
+        for cell in &mut self.cells {
+            *cell = Cell { c: ' ', attribute };
+        }
+        let (width, height) = self.font.glyph_size();
+        let status = blt(
+            self.gop,
+            BltOp::VideoFill {
+                color: Self::colors(attribute).1,
+                dest: (0, 0),
+                dims: (self.columns * width, self.rows * height),
+            },
+        );
+        if status.is_error() {
+            return status;
+        }
+        self.data.cursor_column = 0;
+        self.data.cursor_row = 0;
+        if self.data.cursor_visible {
+            self.draw_cell(0, 0)
+        } else {
+            Status::SUCCESS
+        }
+    }
+
+    /// Move the contents of the screen up by one row
+    fn scroll(&mut self) -> Status {
+        let (width, height) = self.font.glyph_size();
+        let status = blt(
+            self.gop,
+            BltOp::VideoToVideo {
+                src: (0, height),
+                dest: (0, 0),
+                dims: (self.columns * width, (self.rows - 1) * height),
+            },
+        );
+        if status.is_error() {
+            return status;
+        }
+
+        let attribute = self.data.attribute as u8;
+        self.cells.copy_within(self.columns.., 0);
+        let last_row = (self.rows - 1) * self.columns;
+        for cell in &mut self.cells[last_row..] {
+            *cell = Cell { c: ' ', attribute };
+        }
+        blt(
+            self.gop,
+            BltOp::VideoFill {
+                color: Self::colors(attribute).1,
+                dest: (0, (self.rows - 1) * height),
+                dims: (self.columns * width, height),
+            },
+        )
+    }
+
+    /// Move the cursor to the start of the next line, scrolling if needed
+    fn new_line(&mut self, column: usize) -> Status {
+        let (_, row) = self.cursor();
+        if row + 1 < self.rows {
+            return self.move_cursor(column, row + 1);
+        }
+        // Hide the cursor while the screen moves under it
+        let visible = self.data.cursor_visible;
+        self.data.cursor_visible = false;
+        let mut status = self.draw_cell(self.cursor().0, row);
+        if !status.is_error() {
+            status = self.scroll();
+        }
+        self.data.cursor_visible = visible;
+        if status.is_error() {
+            return status;
+        }
+        self.data.cursor_column = column as i32;
+        if visible {
+            self.draw_cell(column, row)
+        } else {
+            Status::SUCCESS
+        }
+    }
+
+    /// Write a character at the cursor, and advance the cursor
+    fn put_char(&mut self, c: char) -> Status {
+        let (column, row) = self.cursor();
+        match c {
+            '\n' => self.new_line(column),
+            '\r' => self.move_cursor(0, row),
+            '\u{8}' => {
+                if column == 0 {
+                    return Status::SUCCESS;
+                }
+                self.cells[row * self.columns + column - 1] = Cell {
+                    c: ' ',
+                    attribute: self.data.attribute as u8,
+                };
+                let status = self.draw_cell(column - 1, row);
+                if status.is_error() {
+                    return status;
+                }
+                self.move_cursor(column - 1, row)
+            }
+            c => {
+                self.cells[row * self.columns + column] = Cell {
+                    c,
+                    attribute: self.data.attribute as u8,
+                };
+                // Draw the character without the cursor, which moves on
+                let visible = self.data.cursor_visible;
+                self.data.cursor_visible = false;
+                let status = self.draw_cell(column, row);
+                self.data.cursor_visible = visible;
+                if status.is_error() {
+                    return status;
+                }
+                if column + 1 < self.columns {
+                    self.move_cursor(column + 1, row)
+                } else {
+                    self.new_line(0)
+                }
+            }
+        }
+    }
+}
+
+fn blt(gop: &mut GraphicsOutput, op: BltOp) -> Status {
+    match gop.blt(op) {
+        Ok(_) => Status::SUCCESS,
+        Err(_) => Status::DEVICE_ERROR,
+    }
+}
+
+/// Decode the UCS-2 characters of a null-terminated string
+unsafe fn chars(string: *const Char16) -> impl Iterator<Item = char> {
+    let string = string as *const u16;
+    let len = (0..).find(|&i| *string.add(i) == 0).unwrap();
+    core::slice::from_raw_parts(string, len)
+        .iter()
+        .map(|&c| char::from_u32(c.into()).unwrap_or('?'))
+}
+
+fn is_control(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{8}')
+}
+
+unsafe extern "efiapi" fn reset(this: *mut GraphicsConsole, _extended: bool) -> Status {
+    let this = &mut *this;
+    this.data.attribute = DEFAULT_ATTRIBUTE.into();
+    this.data.cursor_visible = true;
+    this.clear()
+}
+
+unsafe extern "efiapi" fn output_string(
+    this: *mut GraphicsConsole,
+    string: *const Char16,
+) -> Status {
+    let this = &mut *this;
+    let mut unknown_glyph = false;
+    for c in chars(string) {
+        unknown_glyph |= !is_control(c) && !this.font.has_glyph(c);
+        let status = this.put_char(c);
+        if status.is_error() {
+            return status;
+        }
+    }
+    if unknown_glyph {
+        Status::WARN_UNKNOWN_GLYPH
+    } else {
+        Status::SUCCESS
+    }
+}
+
+unsafe extern "efiapi" fn test_string(this: *mut GraphicsConsole, string: *const Char16) -> Status {
+    let this = &*this;
+    if chars(string).all(|c| is_control(c) || this.font.has_glyph(c)) {
+        Status::SUCCESS
+    } else {
+        Status::UNSUPPORTED
+    }
+}
+
+unsafe extern "efiapi" fn query_mode(
+    this: *mut GraphicsConsole,
+    mode: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> Status {
+    let this = &*this;
+    if mode != 0 {
+        return Status::UNSUPPORTED;
+    }
+    *columns = this.columns;
+    *rows = this.rows;
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_mode(this: *mut GraphicsConsole, mode: usize) -> Status {
+    if mode != 0 {
+        return Status::UNSUPPORTED;
+    }
+    (*this).clear()
+}
+
+unsafe extern "efiapi" fn set_attribute(this: *mut GraphicsConsole, attribute: usize) -> Status {
+    if attribute > 0x7f {
+        return Status::UNSUPPORTED;
+    }
+    let this = &mut *this;
+    this.data.attribute = attribute as i32;
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn clear_screen(this: *mut GraphicsConsole) -> Status {
+    (*this).clear()
+}
+
+unsafe extern "efiapi" fn set_cursor_position(
+    this: *mut GraphicsConsole,
+    column: usize,
+    row: usize,
+) -> Status {
+    let this = &mut *this;
+    if column >= this.columns || row >= this.rows {
+        return Status::UNSUPPORTED;
+    }
+    this.move_cursor(column, row)
+}
+
+unsafe extern "efiapi" fn enable_cursor(this: *mut GraphicsConsole, visible: bool) -> Status {
+    let this = &mut *this;
+    if this.data.cursor_visible == visible {
+        return Status::SUCCESS;
+    }
+    this.data.cursor_visible = visible;
+    let (column, row) = this.cursor();
+    this.draw_cell(column, row)
+}
@@ -0,0 +1,307 @@
+//! Monospaced bitmap fonts, drawn directly to the frame buffer.
+//!
+//! Since text is written to the frame buffer without going through the GOP
+//! or the text output protocol, it keeps working after boot services have
+//! been exited, provided the frame buffer was saved beforehand (see
+//! [`FrameBuffer::from_raw_parts`]).
+//!
+//! Fonts can either be loaded from PC Screen Font files (PSF version 1 or 2,
+//! as shipped in the console-setup package of most Linux distributions and
+//! easily embedded with `include_bytes!`), or built from a user-supplied
+//! glyph table.
+
+use super::native_pixel;
+use crate::proto::console::gop::{BltPixel, FrameBuffer, ModeInfo};
+use crate::{Result, Status};
+
+/// Errors that can occur when loading a font
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FontError {
+    /// The data does not start with a valid PSF header.
+    InvalidHeader,
+    /// The glyph dimensions are not usable.
+    Unsupported,
+    /// The data ends before the last glyph.
+    Truncated,
+}
+
+/// A monospaced bitmap font
+///
+/// Each glyph is a bitmap of `height` rows, each row being padded to a whole
+/// number of bytes, with the most significant bit of a byte corresponding to
+/// the leftmost pixel. Glyphs are looked up by Unicode code point, which
+/// matches the ASCII and Latin-1 ranges of most fonts. Characters for which
+/// the font has no glyph are drawn as `?`.
+#[derive(Debug, Copy, Clone)]
+pub struct Font<'data> {
+    glyphs: &'data [u8],
+    glyph_count: usize,
+    glyph_size: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Magic number of PSF version 1 fonts
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+/// PSF1 flag indicating that the font contains 512 glyphs instead of 256
+const PSF1_MODE_512: u8 = 0x01;
+/// Magic number of PSF version 2 fonts
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+/// Size of the PSF2 header fields that we need to read
+const PSF2_HEADER_SIZE: usize = 32;
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+impl<'data> Font<'data> {
+    /// Load a font from the contents of a PSF file
+    ///
+    /// The Unicode mapping table of the font, if any, is ignored.
+    pub fn from_psf(data: &'data [u8]) -> core::result::Result<Self, FontError> {
+        if data.get(0..2) == Some(&PSF1_MAGIC) {
+            let mode = *data.get(2).ok_or(FontError::InvalidHeader)?;
+            let height = *data.get(3).ok_or(FontError::InvalidHeader)? as usize;
+            let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+            Self::with_count(&data[4.min(data.len())..], glyph_count, 8, height)
+        } else if data.get(0..4) == Some(&PSF2_MAGIC) {
+            let header = |offset| read_u32(data, offset).ok_or(FontError::InvalidHeader);
+            let header_size = header(8)?;
+            let glyph_count = header(16)?;
+            let glyph_size = header(20)?;
+            let height = header(24)?;
+            let width = header(28)?;
+            if header_size < PSF2_HEADER_SIZE {
+                return Err(FontError::InvalidHeader);
+            }
+            let font = Self::with_count(
+                data.get(header_size..).ok_or(FontError::Truncated)?,
+                glyph_count,
+                width,
+                height,
+            )?;
+            if font.glyph_size != glyph_size {
+                return Err(FontError::Unsupported);
+            }
+            Ok(font)
+        } else {
+            Err(FontError::InvalidHeader)
+        }
+    }
+
+    /// Build a font from a table of glyphs of `width` by `height` pixels,
+    /// stored one after the other in the format described above
+    ///
+    /// The first glyph of the table corresponds to code point 0.
+    pub fn from_glyphs(
+        glyphs: &'data [u8],
+        width: usize,
+        height: usize,
+    ) -> core::result::Result<Self, FontError> {
+        let glyph_size = Self::glyph_bytes(width, height)?;
+        Self::with_count(glyphs, glyphs.len() / glyph_size, width, height)
+    }
+
+    fn with_count(
+        glyphs: &'data [u8],
+        glyph_count: usize,
+        width: usize,
+        height: usize,
+    ) -> core::result::Result<Self, FontError> {
+        let glyph_size = Self::glyph_bytes(width, height)?;
+        // Characters without a glyph are drawn with one of the glyphs
+        if glyph_count == 0 {
+            return Err(FontError::Truncated);
+        }
+        let glyphs = glyph_size
+            .checked_mul(glyph_count)
+            .and_then(|len| glyphs.get(..len))
+            .ok_or(FontError::Truncated)?;
+        Ok(Font {
+            glyphs,
+            glyph_count,
+            glyph_size,
+            width,
+            height,
+        })
+    }
+
+    /// Number of bytes of a glyph of `width` by `height` pixels
+    fn glyph_bytes(width: usize, height: usize) -> core::result::Result<usize, FontError> {
+        if width == 0 || height == 0 {
+            return Err(FontError::Unsupported);
+        }
+        ((width + 7) / 8)
+            .checked_mul(height)
+            .ok_or(FontError::Unsupported)
+    }
+
+    /// Size of a character cell, as (width, height) in pixels
+    pub fn glyph_size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Number of glyphs in the font
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    /// Whether the font has a glyph for `c`, rather than drawing it as `?`
+    pub fn has_glyph(&self, c: char) -> bool {
+        (c as usize) < self.glyph_count
+    }
+
+    /// Bitmap of the glyph used to draw `c`
+    fn glyph(&self, c: char) -> &'data [u8] {
+        let index = match c as usize {
+            index if index < self.glyph_count => index,
+            _ if ('?' as usize) < self.glyph_count => '?' as usize,
+            _ => 0,
+        };
+        &self.glyphs[index * self.glyph_size..][..self.glyph_size]
+    }
+
+    /// Whether the pixel (`x`, `y`) of the glyph used to draw `c` is set
+    pub fn is_set(&self, c: char, x: usize, y: usize) -> bool {
+        let bytes_per_row = (self.width + 7) / 8;
+        let byte = self.glyph(c)[y * bytes_per_row + x / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Draw one character with its top-left corner at `pos`
+    ///
+    /// Parts of the character which do not fit on screen are clipped. Fails
+    /// with `UNSUPPORTED` if the mode has no frame buffer.
+    pub fn draw_char(
+        &self,
+        fb: &mut FrameBuffer,
+        info: &ModeInfo,
+        pos: (usize, usize),
+        c: char,
+        fg: BltPixel,
+        bg: BltPixel,
+    ) -> Result {
+        self.draw_str(fb, info, pos, c.encode_utf8(&mut [0; 4]), fg, bg)
+            .map(|completion| completion.map(|_| ()))
+    }
+
+    /// Draw a string with the top-left corner of its first character at `pos`
+    ///
+    /// A `'\n'` moves to the start of the next line, below `pos`. On success,
+    /// returns the position at which the next character would be drawn, so
+    /// that several strings can be chained. Parts of the text which do not fit
+    /// on screen are clipped. Fails with `UNSUPPORTED` if the mode has no frame
+    /// buffer.
+    pub fn draw_str(
+        &self,
+        fb: &mut FrameBuffer,
+        info: &ModeInfo,
+        pos: (usize, usize),
+        text: &str,
+        fg: BltPixel,
+        bg: BltPixel,
+    ) -> Result<(usize, usize)> {
+        let (fg, bg) = match (native_pixel(info, fg), native_pixel(info, bg)) {
+            (Some(fg), Some(bg)) => (fg, bg),
+            _ => return Err(Status::UNSUPPORTED.into()),
+        };
+        let (mut x, mut y) = pos;
+        for c in text.chars() {
+            if c == '\n' {
+                x = pos.0;
+                y += self.height;
+                continue;
+            }
+            self.draw_glyph(fb, info, (x, y), c, fg, bg);
+            x += self.width;
+        }
+        Ok((x, y).into())
+    }
+
+    fn draw_glyph(
+        &self,
+        fb: &mut FrameBuffer,
+        info: &ModeInfo,
+        pos: (usize, usize),
+        c: char,
+        fg: [u8; 4],
+        bg: [u8; 4],
+    ) {
+        let (screen_width, screen_height) = info.resolution();
+        let width = self.width.min(screen_width.saturating_sub(pos.0));
+        let height = self.height.min(screen_height.saturating_sub(pos.1));
+        for y in 0..height {
+            for x in 0..width {
+                let color = if self.is_set(c, x, y) { fg } else { bg };
+                // Only fails in Blt-only modes, which were rejected above
+                let offset = info.pixel_offset(pos.0 + x, pos.1 + y).unwrap();
+                if offset + color.len() <= fb.size() {
+                    unsafe { fb.write_value(offset, color) };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn psf1() {
+        let mut data = [0; 4 + 256 * 2];
+        data[..4].copy_from_slice(&[0x36, 0x04, 0, 2]);
+        // Glyph for 'A': left pixel on the first row, right pixel on the second
+        let a = 4 + 2 * b'A' as usize;
+        data[a] = 0x80;
+        data[a + 1] = 0x01;
+        let font = Font::from_psf(&data).unwrap();
+        assert_eq!(font.glyph_size(), (8, 2));
+        assert_eq!(font.glyph_count(), 256);
+        assert!(font.is_set('A', 0, 0));
+        assert!(!font.is_set('A', 1, 0));
+        assert!(font.is_set('A', 7, 1));
+        assert!(!font.is_set('B', 0, 0));
+        assert_eq!(
+            Font::from_psf(&data[..100]).unwrap_err(),
+            FontError::Truncated
+        );
+    }
+
+    #[test]
+    fn psf2() {
+        // Two glyphs of 10x1 pixels, taking 2 bytes each
+        let mut data = [0; 32 + 4];
+        data[..4].copy_from_slice(&[0x72, 0xb5, 0x4a, 0x86]);
+        for (offset, value) in [(8, 32), (16, 2), (20, 2), (24, 1), (28, 10)] {
+            data[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+        }
+        data[32..].copy_from_slice(&[0, 0, 0x00, 0x40]);
+        let font = Font::from_psf(&data).unwrap();
+        assert_eq!(font.glyph_size(), (10, 1));
+        assert!(font.is_set('\u{1}', 9, 0));
+        // Missing glyphs fall back to the first one when there is no '?'
+        assert!(!font.is_set('x', 9, 0));
+        assert_eq!(
+            Font::from_psf(b"PSF").unwrap_err(),
+            FontError::InvalidHeader
+        );
+    }
+
+    #[test]
+    fn glyph_table() {
+        let glyphs = [0xff; 16 * 128];
+        let font = Font::from_glyphs(&glyphs, 8, 16).unwrap();
+        assert_eq!(font.glyph_count(), 128);
+        assert!(font.is_set('\u{e9}', 3, 15));
+        assert_eq!(
+            Font::from_glyphs(&glyphs, 0, 16).unwrap_err(),
+            FontError::Unsupported
+        );
+        assert_eq!(
+            Font::from_glyphs(&glyphs[..15], 8, 16).unwrap_err(),
+            FontError::Truncated
+        );
+    }
+}
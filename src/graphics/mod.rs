@@ -0,0 +1,84 @@
+//! Drawing utilities built on top of the graphics output protocol.
+//!
+//! The GOP only knows how to move rectangles of pixels around. This module
+//! provides the higher-level building blocks which boot-time user interfaces
+//! commonly need on top of it.
+
+use crate::proto::console::gop::{BltPixel, ModeInfo, PixelBitmask, PixelFormat};
+
+pub mod bmp;
+#[cfg(feature = "exts")]
+pub mod console;
+pub mod font;
+#[cfg(feature = "exts")]
+pub mod surface;
+
+/// Converts a color to the in-memory representation of a frame buffer pixel
+/// in the given mode, or returns `None` in a Blt-only mode.
+pub(crate) fn native_pixel(info: &ModeInfo, color: BltPixel) -> Option<[u8; 4]> {
+    encode_pixel(info.pixel_format(), info.pixel_bitmask(), color)
+}
+
+fn encode_pixel(
+    format: PixelFormat,
+    mask: Option<PixelBitmask>,
+    color: BltPixel,
+) -> Option<[u8; 4]> {
+    match format {
+        PixelFormat::Rgb => Some([color.red, color.green, color.blue, 0]),
+        PixelFormat::Bgr => Some([color.blue, color.green, color.red, 0]),
+        PixelFormat::Bitmask => {
+            let mask = mask?;
+            let value = pack_channel(color.red, mask.red)
+                | pack_channel(color.green, mask.green)
+                | pack_channel(color.blue, mask.blue);
+            Some(value.to_le_bytes())
+        }
+        PixelFormat::BltOnly => None,
+    }
+}
+
+/// Scales an 8-bit color channel to the width of `mask` and moves it in place
+fn pack_channel(channel: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).count_ones();
+    let value = if bits >= 8 {
+        u32::from(channel) << (bits - 8)
+    } else {
+        u32::from(channel) >> (8 - bits)
+    };
+    (value << shift) & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_formats() {
+        let color = BltPixel::new(0xff, 0x80, 0x10);
+        assert_eq!(
+            encode_pixel(PixelFormat::Rgb, None, color),
+            Some([0xff, 0x80, 0x10, 0])
+        );
+        assert_eq!(
+            encode_pixel(PixelFormat::Bgr, None, color),
+            Some([0x10, 0x80, 0xff, 0])
+        );
+        assert_eq!(encode_pixel(PixelFormat::BltOnly, None, color), None);
+
+        // RGB565
+        let mask = PixelBitmask {
+            red: 0xf800,
+            green: 0x07e0,
+            blue: 0x001f,
+            reserved: 0,
+        };
+        let value =
+            u32::from_le_bytes(encode_pixel(PixelFormat::Bitmask, Some(mask), color).unwrap());
+        assert_eq!(value, (0x1f << 11) | (0x20 << 5) | 0x02);
+    }
+}
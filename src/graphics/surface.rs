@@ -0,0 +1,244 @@
+//! Double-buffered drawing.
+//!
+//! Drawing directly to the frame buffer is slow, since every pixel is an
+//! uncached write to video memory, and causes flicker when a picture is built
+//! in several passes. A [`Surface`] is a back buffer in regular memory which is
+//! drawn to instead, and which keeps track of the region that was modified so
+//! that only this region is copied to the screen when it is flushed.
+
+use super::native_pixel;
+use crate::proto::console::gop::{
+    BltOp, BltPixel, BltRegion, FrameBuffer, GraphicsOutput, ModeInfo,
+};
+use crate::{Result, ResultExt, Status};
+use alloc_api::vec;
+use alloc_api::vec::Vec;
+
+/// A back buffer, which is mapped to the top-left corner of the screen
+pub struct Surface {
+    width: usize,
+    height: usize,
+    pixels: Vec<BltPixel>,
+    /// Top-left (inclusive) and bottom-right (exclusive) corners of the
+    /// region which was modified since the last flush
+    dirty: Option<((usize, usize), (usize, usize))>,
+}
+
+impl Surface {
+    /// Create a black surface of `width` by `height` pixels
+    pub fn new(width: usize, height: usize) -> Self {
+        Surface {
+            width,
+            height,
+            pixels: vec![BltPixel::new(0, 0, 0); width * height],
+            dirty: None,
+        }
+    }
+
+    /// Create a black surface covering the whole screen in the given mode
+    pub fn for_mode(info: &ModeInfo) -> Self {
+        let (width, height) = info.resolution();
+        Self::new(width, height)
+    }
+
+    /// Size of the surface, as (width, height) in pixels
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Contents of the back buffer, row by row
+    pub fn pixels(&self) -> &[BltPixel] {
+        &self.pixels
+    }
+
+    /// Color of the pixel at (`x`, `y`), or `None` if it lies outside
+    pub fn pixel(&self, x: usize, y: usize) -> Option<BltPixel> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Set the color of the pixel at (`x`, `y`)
+    ///
+    /// Pixels outside of the surface are ignored.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: BltPixel) {
+        self.fill_rect((x, y), (1, 1), color);
+    }
+
+    /// Fill the `dims` rectangle at `dest` with a color
+    ///
+    /// Parts of the rectangle which lie outside of the surface are clipped.
+    pub fn fill_rect(&mut self, dest: (usize, usize), dims: (usize, usize), color: BltPixel) {
+        if let Some((start, end)) = self.clip(dest, dims) {
+            for y in start.1..end.1 {
+                self.pixels[y * self.width + start.0..y * self.width + end.0]
+                    .iter_mut()
+                    .for_each(|px| *px = color);
+            }
+            self.add_dirty(start, end);
+        }
+    }
+
+    /// Fill the whole surface with a color
+    pub fn clear(&mut self, color: BltPixel) {
+        self.fill_rect((0, 0), (self.width, self.height), color);
+    }
+
+    /// Copy a `dims` rectangle of pixels, stored row by row in `src`, to `dest`
+    ///
+    /// Parts of the rectangle which lie outside of the surface are clipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` holds less than `dims.0 * dims.1` pixels.
+    pub fn draw_pixels(&mut self, dest: (usize, usize), dims: (usize, usize), src: &[BltPixel]) {
+        assert!(src.len() >= dims.0 * dims.1, "Source buffer is too small");
+        if let Some((start, end)) = self.clip(dest, dims) {
+            let visible_width = end.0 - start.0;
+            for y in start.1..end.1 {
+                let src_row = &src[(y - dest.1) * dims.0..][..visible_width];
+                self.pixels[y * self.width + start.0..][..visible_width].copy_from_slice(src_row);
+            }
+            self.add_dirty(start, end);
+        }
+    }
+
+    /// Mark the `dims` rectangle at `dest` as modified, so that it is copied
+    /// to the screen on the next flush
+    pub fn mark_dirty(&mut self, dest: (usize, usize), dims: (usize, usize)) {
+        if let Some((start, end)) = self.clip(dest, dims) {
+            self.add_dirty(start, end);
+        }
+    }
+
+    /// Region which will be copied on the next flush, as (position, dims)
+    pub fn dirty_rect(&self) -> Option<((usize, usize), (usize, usize))> {
+        self.dirty
+            .map(|(start, end)| (start, (end.0 - start.0, end.1 - start.1)))
+    }
+
+    /// Copy the modified region of the surface to the screen using `blt`
+    ///
+    /// Pixels which lie outside of the screen are skipped.
+    pub fn flush(&mut self, gop: &mut GraphicsOutput) -> Result {
+        let (screen_width, screen_height) = gop.current_mode_info().resolution();
+        if let Some((dest, dims)) = self.dirty_rect() {
+            if dest.0 >= screen_width || dest.1 >= screen_height {
+                self.dirty = None;
+                return Status::SUCCESS.into();
+            }
+            let dims = (
+                dims.0.min(screen_width - dest.0),
+                dims.1.min(screen_height - dest.1),
+            );
+            gop.blt(BltOp::BufferToVideo {
+                buffer: &self.pixels,
+                src: BltRegion::SubRectangle {
+                    coords: dest,
+                    px_stride: self.width,
+                },
+                dest,
+                dims,
+            })
+            .log_warning()?;
+            self.dirty = None;
+        }
+        Status::SUCCESS.into()
+    }
+
+    /// Copy the modified region of the surface to the screen by writing to
+    /// the frame buffer directly
+    ///
+    /// Unlike `flush`, this keeps working after boot services have been
+    /// exited. Fails with `UNSUPPORTED` if the mode has no frame buffer.
+    /// Pixels which lie outside of the screen or of the frame buffer are
+    /// skipped.
+    pub fn flush_to_framebuffer(&mut self, fb: &mut FrameBuffer, info: &ModeInfo) -> Result {
+        if info.bytes_per_row().is_none() {
+            return Status::UNSUPPORTED.into();
+        }
+        if let Some((start, end)) = self.dirty {
+            let (screen_width, screen_height) = info.resolution();
+            for y in start.1..end.1.min(screen_height) {
+                for x in start.0..end.0.min(screen_width) {
+                    // Neither can fail, as Blt-only modes were rejected above
+                    let color = native_pixel(info, self.pixels[y * self.width + x]).unwrap();
+                    let offset = info.pixel_offset(x, y).unwrap();
+                    if offset + color.len() <= fb.size() {
+                        unsafe { fb.write_value(offset, color) };
+                    }
+                }
+            }
+            self.dirty = None;
+        }
+        Status::SUCCESS.into()
+    }
+
+    /// Intersect a rectangle with the surface, returning its corners
+    fn clip(
+        &self,
+        dest: (usize, usize),
+        dims: (usize, usize),
+    ) -> Option<((usize, usize), (usize, usize))> {
+        let end = (
+            dest.0.saturating_add(dims.0).min(self.width),
+            dest.1.saturating_add(dims.1).min(self.height),
+        );
+        if dest.0 < end.0 && dest.1 < end.1 {
+            Some((dest, end))
+        } else {
+            None
+        }
+    }
+
+    /// Grow the dirty region so that it also covers a rectangle
+    fn add_dirty(&mut self, start: (usize, usize), end: (usize, usize)) {
+        self.dirty = Some(match self.dirty {
+            None => (start, end),
+            Some((old_start, old_end)) => (
+                (old_start.0.min(start.0), old_start.1.min(start.1)),
+                (old_end.0.max(end.0), old_end.1.max(end.1)),
+            ),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(px: Option<BltPixel>) -> Option<(u8, u8, u8)> {
+        px.map(|px| (px.red, px.green, px.blue))
+    }
+
+    #[test]
+    fn drawing_is_clipped() {
+        let mut surface = Surface::new(4, 3);
+        surface.fill_rect((2, 1), (10, 10), BltPixel::new(1, 2, 3));
+        assert_eq!(color(surface.pixel(1, 1)), Some((0, 0, 0)));
+        assert_eq!(color(surface.pixel(3, 2)), Some((1, 2, 3)));
+        assert_eq!(color(surface.pixel(4, 2)), None);
+
+        let src = [BltPixel::new(9, 9, 9); 4];
+        surface.draw_pixels((3, 0), (2, 2), &src);
+        assert_eq!(color(surface.pixel(3, 0)), Some((9, 9, 9)));
+        assert_eq!(color(surface.pixel(2, 0)), Some((0, 0, 0)));
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut surface = Surface::new(8, 8);
+        assert_eq!(surface.dirty_rect(), None);
+        surface.set_pixel(1, 2, BltPixel::new(255, 0, 0));
+        assert_eq!(surface.dirty_rect(), Some(((1, 2), (1, 1))));
+        surface.fill_rect((4, 0), (2, 1), BltPixel::new(0, 255, 0));
+        assert_eq!(surface.dirty_rect(), Some(((1, 0), (5, 3))));
+        // Drawing outside of the surface does not dirty anything
+        surface.mark_dirty((8, 8), (1, 1));
+        assert_eq!(surface.dirty_rect(), Some(((1, 0), (5, 3))));
+        surface.clear(BltPixel::new(0, 0, 0));
+        assert_eq!(surface.dirty_rect(), Some(((0, 0), (8, 8))));
+    }
+}
@@ -15,6 +15,9 @@
 //! The `proto` module contains the standard UEFI protocols, which are normally provided
 //! by the various UEFI drivers and firmware layers.
 //!
+//! The `raw` module contains the underlying definitions of the tables and of
+//! the most common protocols, for calling services which have no safe wrapper.
+//!
 //! ## Adapting to local conditions
 //!
 //! Unlike system tables, which are present on *all* UEFI implementations,
@@ -24,11 +27,14 @@
 //! therefore all the network protocols will be unavailable.
 
 #![cfg_attr(feature = "exts", feature(allocator_api, alloc_layout_extra))]
+#![cfg_attr(all(feature = "exts", not(target_os = "uefi")), feature(thread_local))]
 #![feature(auto_traits)]
 #![feature(control_flow_enum)]
 #![feature(try_trait_v2)]
 #![feature(abi_efiapi)]
+#![feature(asm)]
 #![feature(negative_impls)]
+#![feature(const_ptr_offset_from, const_maybe_uninit_as_ptr, const_raw_ptr_deref)]
 #![no_std]
 // Enable some additional warnings and lints.
 #![warn(missing_docs, unused)]
@@ -50,12 +56,72 @@ pub use self::data_types::{unsafe_guid, Identify};
 pub use self::data_types::{CStr16, CStr8, Char16, Char8, Event, Guid, Handle};
 
 mod result;
-pub use self::result::{Completion, Result, ResultExt, Status};
+pub use self::result::{
+    Completion, Context, Error, Result, ResultContext, ResultExt, Status, StatusExt, WarningPolicy,
+};
 
 pub mod table;
 
 pub mod proto;
 
+pub mod raw;
+
+pub mod graphics;
+
+pub mod pe;
+
+pub mod elf;
+
+pub mod boot;
+
+pub mod decompress;
+
+pub mod progress;
+
+#[cfg(feature = "exts")]
+pub mod shell;
+
+#[cfg(feature = "exts")]
+pub mod net;
+
+pub mod crc;
+
+pub mod digest;
+
+pub mod sync;
+
+pub mod stack;
+
+#[cfg(feature = "tui")]
+pub mod tui;
+
+#[cfg(any(feature = "embedded-io", feature = "embedded-graphics-core"))]
+pub mod embedded;
+
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+pub mod arch;
+
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+pub mod backtrace;
+
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+pub mod entropy;
+
 pub mod prelude;
 
 #[cfg(feature = "alloc")]
@@ -64,5 +130,8 @@ pub mod alloc;
 #[cfg(feature = "exts")]
 pub mod exts;
 
+#[cfg(all(feature = "exts", not(target_os = "uefi")))]
+pub mod mock;
+
 #[cfg(feature = "logger")]
 pub mod logger;
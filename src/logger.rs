@@ -2,7 +2,8 @@
 //! a custom logger implementation which writes to a UEFI text output protocol.
 //!
 //! The main export of this module is the `Logger` structure,
-//! which implements the `log` crate's trait `Log`.
+//! which implements the `log` crate's trait `Log`. The `OutputMux` and
+//! `RingBuffer` structures can send the log to other destinations.
 //!
 //! # Implementation details
 //!
@@ -12,10 +13,17 @@
 //! The last part also means that some Unicode characters might not be
 //! supported by the UEFI console. Don't expect emoji output support.
 
+use crate::proto::console::serial::Serial;
 use crate::proto::console::text::Output;
+use crate::proto::media::file::RegularFile;
+use crate::result::Error;
+use crate::table::runtime::{RuntimeServices, VariableAttributes, VariableVendor};
+use crate::{CStr16, Result, Status};
 
+use core::cell::UnsafeCell;
 use core::fmt::{self, Write};
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 /// Logging implementation which writes to a UEFI output stream.
 ///
@@ -92,6 +100,283 @@ impl<'boot> log::Log for Logger {
 unsafe impl Sync for Logger {}
 unsafe impl Send for Logger {}
 
+/// A destination of an `OutputMux`, which only receives the text logged at
+/// or above a certain level
+pub struct Sink<'sink> {
+    writer: &'sink mut dyn fmt::Write,
+    filter: log::LevelFilter,
+}
+
+impl<'sink> Sink<'sink> {
+    /// Creates a sink which receives the text whose level passes `filter`
+    pub fn new(writer: &'sink mut dyn fmt::Write, filter: log::LevelFilter) -> Self {
+        Sink { writer, filter }
+    }
+
+    /// Returns the filter of the sink
+    pub fn filter(&self) -> log::LevelFilter {
+        self.filter
+    }
+
+    /// Changes the filter of the sink
+    ///
+    /// `LevelFilter::Off` disables the sink entirely.
+    pub fn set_filter(&mut self, filter: log::LevelFilter) {
+        self.filter = filter;
+    }
+}
+
+/// Writer which copies its output to several sinks
+///
+/// This mirrors the ConSplitter driver of EDK2, which merges all the consoles
+/// into the `ConOut` of the system table, but lets each sink, such as the
+/// console, a serial port, a file or an in-memory buffer, decide how verbose
+/// it should be:
+///
+/// ```no_run
+/// # use core::fmt::Write;
+/// # use log::{Level, LevelFilter};
+/// # use uefi::logger::{OutputMux, Sink};
+/// # fn f(stdout: &mut uefi::proto::console::text::Output, serial: &mut uefi::proto::console::serial::Serial) {
+/// let mut sinks = [
+///     Sink::new(stdout, LevelFilter::Info),
+///     Sink::new(serial, LevelFilter::Trace),
+/// ];
+/// let mut mux = OutputMux::new(&mut sinks);
+/// mux.set_level(Level::Debug);
+/// // Only written to the serial port
+/// writeln!(mux, "Found {} handles", 3).unwrap();
+/// # }
+/// ```
+///
+/// Writing goes on with the other sinks when one of them fails, and the error
+/// is reported once all sinks have been written to.
+pub struct OutputMux<'mux, 'sink> {
+    sinks: &'mux mut [Sink<'sink>],
+    level: log::Level,
+}
+
+impl<'mux, 'sink> OutputMux<'mux, 'sink> {
+    /// Creates a multiplexer writing to `sinks`
+    ///
+    /// The text is initially written at the `Error` level, so that it reaches
+    /// every sink which is not disabled.
+    pub fn new(sinks: &'mux mut [Sink<'sink>]) -> Self {
+        OutputMux {
+            sinks,
+            level: log::Level::Error,
+        }
+    }
+
+    /// Returns the sinks, to change their filters
+    pub fn sinks(&mut self) -> &mut [Sink<'sink>] {
+        self.sinks
+    }
+
+    /// Sets the level of the text written from now on
+    pub fn set_level(&mut self, level: log::Level) {
+        self.level = level;
+    }
+
+    /// Writes a log record, prefixed by its level and location like `Logger`
+    /// does, to the sinks which accept its level
+    pub fn log(&mut self, record: &log::Record) -> fmt::Result {
+        self.set_level(record.level());
+        DecoratedLog::write(
+            self,
+            record.level(),
+            record.args(),
+            record.file().unwrap_or("<unknown file>"),
+            record.line().unwrap_or(0),
+        )
+    }
+}
+
+impl<'mux, 'sink> fmt::Write for OutputMux<'mux, 'sink> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut result = Ok(());
+        for sink in self.sinks.iter_mut() {
+            if self.level <= sink.filter {
+                result = result.and(sink.writer.write_str(s));
+            }
+        }
+        result
+    }
+}
+
+/// Fixed-size buffer keeping the most recent log messages in memory
+///
+/// When the console is not visible, or is gone because boot services were
+/// exited, the log can still be kept in memory and saved once something goes
+/// wrong, typically from the panic handler:
+///
+/// ```no_run
+/// # use uefi::logger::RingBuffer;
+/// # use uefi::ResultExt;
+/// static LOG: RingBuffer<16384> = RingBuffer::new();
+///
+/// # fn f(serial: &mut uefi::proto::console::serial::Serial) {
+/// log::set_logger(&LOG).unwrap();
+/// log::set_max_level(log::LevelFilter::Debug);
+/// // ... and in the panic handler:
+/// LOG.dump_to_serial(serial).unwrap_success();
+/// # }
+/// ```
+///
+/// Once the buffer is full, the oldest data is overwritten. Writing only
+/// reserves its range of the buffer with an atomic operation, so messages can
+/// be logged from event callbacks which interrupt other messages. Reading,
+/// however, should not happen while messages are being written, or the last
+/// ones may be torn.
+///
+/// The buffer does not use any firmware service, so it keeps working after
+/// boot services are exited.
+pub struct RingBuffer<const N: usize> {
+    data: UnsafeCell<[u8; N]>,
+    /// Number of bytes written since the creation of the buffer
+    written: AtomicUsize,
+}
+
+// Writers only access the range they reserved
+unsafe impl<const N: usize> Sync for RingBuffer<N> {}
+
+impl<const N: usize> RingBuffer<N> {
+    /// Creates an empty buffer
+    pub const fn new() -> Self {
+        RingBuffer {
+            data: UnsafeCell::new([0; N]),
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    /// Append bytes to the buffer, overwriting the oldest ones if needed
+    pub fn write_bytes(&self, bytes: &[u8]) {
+        // Only the last N bytes would survive anyway
+        let bytes = &bytes[bytes.len().saturating_sub(N)..];
+        let start = self.written.fetch_add(bytes.len(), Ordering::SeqCst);
+        let data = self.data.get() as *mut u8;
+        for (i, &byte) in bytes.iter().enumerate() {
+            unsafe { data.add((start + i) % N).write_volatile(byte) };
+        }
+    }
+
+    /// Forget the contents of the buffer
+    pub fn clear(&self) {
+        self.written.store(0, Ordering::SeqCst);
+    }
+
+    /// Returns the contents of the buffer, from the oldest to the most recent
+    /// byte, as two slices which follow each other
+    ///
+    /// When the buffer has wrapped around, the first byte may be in the middle
+    /// of a UTF-8 sequence.
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        let written = self.written.load(Ordering::SeqCst);
+        let data = unsafe { &*self.data.get() };
+        if written <= N {
+            (&data[..written], &[])
+        } else {
+            let start = written % N;
+            (&data[start..], &data[..start])
+        }
+    }
+
+    /// Total number of bytes in the buffer
+    pub fn len(&self) -> usize {
+        self.written.load(Ordering::SeqCst).min(N)
+    }
+
+    /// Whether nothing was written to the buffer
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the contents of the buffer to a serial port.
+    ///
+    /// See `Serial::write` for the errors.
+    pub fn dump_to_serial(&self, serial: &mut Serial) -> Result<(), usize> {
+        let (first, second) = self.contents();
+        let status = serial.write(first)?.status();
+        match serial.write(second) {
+            Ok(completion) => Ok(completion.with_status(status)),
+            Err(err) => Err(Error::new(err.status(), first.len() + *err.data())),
+        }
+    }
+
+    /// Write the contents of the buffer to a file, at its current position.
+    ///
+    /// See `RegularFile::write` for the errors.
+    pub fn dump_to_file(&self, file: &mut RegularFile) -> Result<(), usize> {
+        let (first, second) = self.contents();
+        let status = file.write(first)?.status();
+        match file.write(second) {
+            Ok(completion) => Ok(completion.with_status(status)),
+            Err(err) => Err(Error::new(err.status(), first.len() + *err.data())),
+        }
+    }
+
+    /// Save the contents of the buffer in a non-volatile variable, which can
+    /// be read back after a reset.
+    ///
+    /// This also works after boot services are exited. Keep in mind that
+    /// firmwares limit the size of variables, often to a few kilobytes.
+    ///
+    /// See `RuntimeServices::set_variable` for the errors.
+    pub fn dump_to_variable(
+        &self,
+        rt: &RuntimeServices,
+        name: &CStr16,
+        vendor: &VariableVendor,
+    ) -> Result {
+        let attributes = VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS;
+        let (first, second) = self.contents();
+        if first.is_empty() {
+            return Status::SUCCESS.into();
+        }
+        let status = rt.set_variable(name, vendor, attributes, first)?.status();
+        if second.is_empty() {
+            return status.into();
+        }
+        let append = attributes | VariableAttributes::APPEND_WRITE;
+        rt.set_variable(name, vendor, append, second)
+            .map(|completion| completion.with_status(status))
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for &RingBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<const N: usize> log::Log for RingBuffer<N> {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        // Writing to the buffer never fails
+        let _ = DecoratedLog::write(
+            &mut &*self,
+            record.level(),
+            record.args(),
+            record.file().unwrap_or("<unknown file>"),
+            record.line().unwrap_or(0),
+        );
+    }
+
+    fn flush(&self) {}
+}
+
 /// Writer wrapper which prints a log level in front of every line of text
 ///
 /// This is less easy than it sounds because...
@@ -166,3 +451,74 @@ impl<'writer, 'a, W: fmt::Write> fmt::Write for DecoratedLog<'writer, 'a, W> {
         Ok(())
     }
 }
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::string::String;
+    use log::{Level, LevelFilter};
+
+    /// Writer which always fails
+    struct Broken;
+
+    impl fmt::Write for Broken {
+        fn write_str(&mut self, _s: &str) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let ring = RingBuffer::<8>::new();
+        assert!(ring.is_empty());
+        write!(&ring, "hello").unwrap();
+        assert_eq!(ring.contents(), (&b"hello"[..], &b""[..]));
+        write!(&ring, "world!").unwrap();
+        assert_eq!(ring.len(), 8);
+        assert_eq!(ring.contents(), (&b"lowor"[..], &b"ld!"[..]));
+        ring.write_bytes(b"0123456789");
+        assert_eq!(ring.contents(), (&b"23456"[..], &b"789"[..]));
+
+        ring.clear();
+        log::Log::log(
+            &ring,
+            &log::Record::builder()
+                .level(Level::Info)
+                .args(format_args!("x"))
+                .build(),
+        );
+        let (first, second) = ring.contents();
+        assert_eq!([first, second].concat(), b"@000: x\n");
+    }
+
+    #[test]
+    fn level_filtering() {
+        let (mut verbose, mut quiet, mut broken) = (String::new(), String::new(), Broken);
+        let mut sinks = [
+            Sink::new(&mut verbose, LevelFilter::Trace),
+            Sink::new(&mut quiet, LevelFilter::Warn),
+            Sink::new(&mut broken, LevelFilter::Off),
+        ];
+        let mut mux = OutputMux::new(&mut sinks);
+        write!(mux, "a").unwrap();
+        mux.set_level(Level::Debug);
+        write!(mux, "b").unwrap();
+        mux.log(
+            &log::Record::builder()
+                .level(Level::Warn)
+                .args(format_args!("c"))
+                .file(Some("f.rs"))
+                .line(Some(7))
+                .build(),
+        )
+        .unwrap();
+
+        mux.sinks()[2].set_filter(LevelFilter::Error);
+        mux.set_level(Level::Error);
+        assert!(write!(mux, "d").is_err());
+        drop(mux);
+
+        assert_eq!(verbose, "ab[ WARN]:         f.rs@007: c\nd");
+        assert_eq!(quiet, "a[ WARN]:         f.rs@007: c\nd");
+    }
+}
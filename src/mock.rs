@@ -0,0 +1,891 @@
+//! Fake UEFI firmware tables for unit testing on the host.
+//!
+//! Code which consumes the UEFI services through a `SystemTable<Boot>` can
+//! usually only be exercised under QEMU, which makes for a slow edit-test
+//! cycle. This module provides a `MockSystemTable`, which lays out a system
+//! table, boot services, runtime services and text output protocols with the
+//! definitions of [`raw`](crate::raw), but backs them with plain Rust
+//! implementations:
+//!
+//! - Pool and page allocations are forwarded to the global allocator.
+//! - The memory map is made of a single conventional memory descriptor.
+//! - Variables are kept in an in-memory store.
+//! - Text sent to standard output or standard error is captured and can be
+//!   read back.
+//!
+//! The other services fail with `UNSUPPORTED`.
+//!
+//! Each mock table has its own state, so that tests running in parallel do
+//! not see each other's variables. Since the services do not receive the
+//! table, they act on the table which was last created, or last handed out
+//! by `system_table`, on the calling thread.
+
+use crate::proto::device_path::DevicePath;
+use crate::raw;
+use crate::raw::boot::EventNotifyFn;
+use crate::raw::protocol::console::{SimpleTextOutput, SimpleTextOutputMode};
+use crate::table::boot::{MemoryDescriptor, MemoryType, Tpl, MEMORY_DESCRIPTOR_VERSION, PAGE_SIZE};
+use crate::table::runtime::{Daylight, Time, TimeCapabilities};
+use crate::table::{Boot, Header, Revision, SystemTable, Table};
+use crate::{Char16, Event, Guid, Handle, Status};
+use alloc_api::alloc::{alloc, alloc_zeroed, dealloc};
+use alloc_api::boxed::Box;
+use alloc_api::string::String;
+use alloc_api::vec::Vec;
+use core::alloc::Layout;
+use core::cell::{Cell, RefCell, UnsafeCell};
+use core::ffi::c_void;
+use core::mem;
+use core::ptr;
+
+/// A set of fake UEFI tables which can be used in host-side unit tests.
+///
+/// The tables are leaked on creation, so that the `SystemTable<Boot>` views
+/// handed out by `system_table` may live for the rest of the program, as they
+/// would when provided by a real firmware.
+#[repr(C)]
+pub struct MockSystemTable {
+    st: raw::SystemTable,
+    bt: raw::BootServices,
+    rt: raw::RuntimeServices,
+    stdout: UnsafeCell<MockOutput>,
+    stderr: UnsafeCell<MockOutput>,
+    tpl: Cell<Tpl>,
+    time: Cell<Option<Time>>,
+    variables: RefCell<Vec<MockVariable>>,
+    text: RefCell<Vec<u16>>,
+}
+
+impl MockSystemTable {
+    /// Create a new set of fake UEFI tables, which the services called on
+    /// this thread act on from now on.
+    pub fn new() -> &'static MockSystemTable {
+        let mock = Box::into_raw(Box::new(MockSystemTable {
+            st: raw::SystemTable {
+                header: header::<raw::SystemTable>(SystemTable::<Boot>::SIGNATURE),
+                firmware_vendor: FW_VENDOR.as_ptr() as *const Char16,
+                firmware_revision: Revision::new(1, 0),
+                stdin_handle: unsafe { Handle::uninitialized() },
+                stdin: ptr::null_mut(),
+                stdout_handle: unsafe { Handle::uninitialized() },
+                stdout: ptr::null_mut(),
+                stderr_handle: unsafe { Handle::uninitialized() },
+                stderr: ptr::null_mut(),
+                runtime_services: ptr::null_mut(),
+                boot_services: ptr::null_mut(),
+                number_of_configuration_table_entries: 0,
+                configuration_table: ptr::null_mut(),
+            },
+            bt: boot_services(),
+            rt: runtime_services(),
+            stdout: UnsafeCell::new(MockOutput::new()),
+            stderr: UnsafeCell::new(MockOutput::new()),
+            tpl: Cell::new(Tpl::APPLICATION),
+            time: Cell::new(None),
+            variables: RefCell::new(Vec::new()),
+            text: RefCell::new(Vec::new()),
+        }));
+
+        // Now that the tables have reached their final location, link them
+        unsafe {
+            for output in &[(*mock).stdout.get(), (*mock).stderr.get()] {
+                let output = *output;
+                (*output).proto.mode = (*output).mode.get();
+            }
+            (*mock).st.stdout = (*mock).stdout.get().cast();
+            (*mock).st.stderr = (*mock).stderr.get().cast();
+            (*mock).st.runtime_services = &mut (*mock).rt;
+            (*mock).st.boot_services = &mut (*mock).bt;
+            &*mock
+        }
+        .activate()
+    }
+
+    /// Get a boot view of the fake system table, whose services act on this
+    /// table from now on when they are called on this thread.
+    pub fn system_table(&'static self) -> SystemTable<Boot> {
+        self.activate();
+        let ptr = &self.st as *const raw::SystemTable as *mut c_void;
+        unsafe { SystemTable::from_ptr(ptr) }.expect("Invalid mock system table")
+    }
+
+    /// Text which was written to standard output or standard error since the
+    /// console was last reset.
+    pub fn stdout_text(&self) -> String {
+        core::char::decode_utf16(self.text.borrow().iter().copied())
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+
+    /// Make the services of the calling thread act on this table
+    fn activate(&'static self) -> &'static Self {
+        CURRENT.set(Some(self));
+        self
+    }
+
+    /// The mock output whose protocol is `this`
+    fn output(&self, this: *mut SimpleTextOutput) -> &MockOutput {
+        [&self.stdout, &self.stderr]
+            .iter()
+            .map(|output| unsafe { &*output.get() })
+            .find(|output| ptr::eq(&output.proto, this))
+            .expect("Not a protocol of the current mock system table")
+    }
+}
+
+/// The table which the services called on this thread act on
+#[thread_local]
+static CURRENT: Cell<Option<&'static MockSystemTable>> = Cell::new(None);
+
+/// The table which the services called on this thread act on
+fn current() -> &'static MockSystemTable {
+    CURRENT
+        .get()
+        .expect("No mock system table was created on this thread")
+}
+
+/// Null-terminated UCS-2 firmware vendor string
+static FW_VENDOR: [u16; 5] = [b'M' as u16, b'o' as u16, b'c' as u16, b'k' as u16, 0];
+
+/// Header of a table of type `T`
+fn header<T>(signature: u64) -> Header {
+    // The reserved field must be zero
+    let mut header: Header = unsafe { mem::zeroed() };
+    header.signature = signature;
+    header.revision = Revision::new(2, 70);
+    header.size = mem::size_of::<T>() as u32;
+    header
+}
+
+/// Define services which are not mocked, and fail with `UNSUPPORTED`
+macro_rules! unsupported {
+    ($($name:ident($($arg:ty),*);)*) => {
+        $(
+            unsafe extern "efiapi" fn $name($(_: $arg),*) -> Status {
+                Status::UNSUPPORTED
+            }
+        )*
+    };
+}
+
+// Boot services
+
+fn boot_services() -> raw::BootServices {
+    raw::BootServices {
+        header: header::<raw::BootServices>(crate::table::boot::BootServices::SIGNATURE),
+        raise_tpl,
+        restore_tpl,
+        allocate_pages,
+        free_pages,
+        get_memory_map,
+        allocate_pool,
+        free_pool,
+        create_event,
+        set_timer,
+        wait_for_event,
+        signal_event,
+        close_event,
+        check_event,
+        install_protocol_interface,
+        reinstall_protocol_interface,
+        uninstall_protocol_interface,
+        handle_protocol,
+        reserved: ptr::null_mut(),
+        register_protocol_notify,
+        locate_handle,
+        locate_device_path,
+        install_configuration_table,
+        load_image,
+        start_image,
+        exit,
+        unload_image,
+        exit_boot_services,
+        get_next_monotonic_count,
+        stall,
+        set_watchdog_timer,
+        connect_controller: None,
+        disconnect_controller: None,
+        open_protocol: None,
+        close_protocol: None,
+        open_protocol_information: None,
+        protocols_per_handle: None,
+        locate_handle_buffer: None,
+        locate_protocol: None,
+        install_multiple_protocol_interfaces: None,
+        uninstall_multiple_protocol_interfaces: None,
+        calculate_crc32: Some(calculate_crc32),
+        copy_mem: Some(copy_mem),
+        set_mem: Some(set_mem),
+        create_event_ex: None,
+    }
+}
+
+unsafe extern "efiapi" fn raise_tpl(new_tpl: Tpl) -> Tpl {
+    current().tpl.replace(new_tpl)
+}
+
+unsafe extern "efiapi" fn restore_tpl(old_tpl: Tpl) {
+    current().tpl.set(old_tpl);
+}
+
+fn page_layout(count: usize) -> Option<Layout> {
+    let size = count.checked_mul(PAGE_SIZE)?;
+    Layout::from_size_align(size, PAGE_SIZE).ok()
+}
+
+unsafe extern "efiapi" fn allocate_pages(
+    alloc_ty: u32,
+    _mem_ty: MemoryType,
+    count: usize,
+    addr: *mut u64,
+) -> Status {
+    // Only AllocateAnyPages can be honored by the host allocator
+    if alloc_ty != 0 {
+        return Status::UNSUPPORTED;
+    }
+    match page_layout(count) {
+        Some(layout) if count != 0 => {
+            let pages = alloc_zeroed(layout);
+            if pages.is_null() {
+                return Status::OUT_OF_RESOURCES;
+            }
+            *addr = pages as u64;
+            Status::SUCCESS
+        }
+        _ => Status::INVALID_PARAMETER,
+    }
+}
+
+unsafe extern "efiapi" fn free_pages(addr: u64, pages: usize) -> Status {
+    match page_layout(pages) {
+        Some(layout) if addr % PAGE_SIZE as u64 == 0 && pages != 0 => {
+            dealloc(addr as *mut u8, layout);
+            Status::SUCCESS
+        }
+        _ => Status::INVALID_PARAMETER,
+    }
+}
+
+/// Key of the (never changing) mock memory map
+const MEMORY_MAP_KEY: usize = 1;
+
+unsafe extern "efiapi" fn get_memory_map(
+    size: *mut usize,
+    map: *mut MemoryDescriptor,
+    key: *mut usize,
+    desc_size: *mut usize,
+    desc_version: *mut u32,
+) -> Status {
+    let entry_size = mem::size_of::<MemoryDescriptor>();
+    *desc_size = entry_size;
+    *desc_version = MEMORY_DESCRIPTOR_VERSION;
+    if *size < entry_size || map.is_null() {
+        *size = entry_size;
+        return Status::BUFFER_TOO_SMALL;
+    }
+
+    // Advertise 16 MiB of free memory above the first megabyte
+    let mut desc = MemoryDescriptor::default();
+    desc.ty = MemoryType::CONVENTIONAL;
+    desc.phys_start = 0x10_0000;
+    desc.page_count = (16 << 20) / PAGE_SIZE as u64;
+    map.write(desc);
+    *size = entry_size;
+    *key = MEMORY_MAP_KEY;
+    Status::SUCCESS
+}
+
+/// Size of the header which pool allocations use to remember their size.
+/// This also keeps the returned pointers 8-byte aligned, as UEFI requires.
+const POOL_HEADER_SIZE: usize = 8;
+
+fn pool_layout(size: usize) -> Option<Layout> {
+    let size = size.checked_add(POOL_HEADER_SIZE)?;
+    Layout::from_size_align(size, POOL_HEADER_SIZE).ok()
+}
+
+unsafe extern "efiapi" fn allocate_pool(
+    _pool_type: MemoryType,
+    size: usize,
+    buffer: *mut *mut u8,
+) -> Status {
+    let layout = match pool_layout(size) {
+        Some(layout) => layout,
+        None => return Status::OUT_OF_RESOURCES,
+    };
+    let block = alloc(layout);
+    if block.is_null() {
+        return Status::OUT_OF_RESOURCES;
+    }
+    (block as *mut usize).write(size);
+    *buffer = block.add(POOL_HEADER_SIZE);
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn free_pool(buffer: *mut u8) -> Status {
+    if buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let block = buffer.sub(POOL_HEADER_SIZE);
+    let size = (block as *const usize).read();
+    dealloc(block, pool_layout(size).unwrap());
+    Status::SUCCESS
+}
+
+unsupported! {
+    create_event(u32, Tpl, Option<EventNotifyFn>, *mut c_void, *mut Event);
+    set_timer(Event, u32, u64);
+    wait_for_event(usize, *mut Event, *mut usize);
+    signal_event(Event);
+    close_event(Event);
+    check_event(Event);
+    install_protocol_interface(*mut Handle, *const Guid, u32, *mut c_void);
+    reinstall_protocol_interface(Handle, *const Guid, *mut c_void, *mut c_void);
+    uninstall_protocol_interface(Handle, *const Guid, *mut c_void);
+    handle_protocol(Handle, *const Guid, *mut *mut c_void);
+    register_protocol_notify(*const Guid, Event, *mut *mut c_void);
+    locate_handle(u32, *const Guid, *mut c_void, *mut usize, *mut Handle);
+    locate_device_path(*const Guid, *mut *mut DevicePath, *mut Handle);
+    install_configuration_table(*const Guid, *mut c_void);
+    load_image(bool, Handle, *const DevicePath, *const u8, usize, *mut Handle);
+    start_image(Handle, *mut usize, *mut *mut Char16);
+    unload_image(Handle);
+    get_next_monotonic_count(*mut u64);
+}
+
+unsafe extern "efiapi" fn exit(
+    _image_handle: Handle,
+    exit_status: Status,
+    _exit_data_size: usize,
+    _exit_data: *mut Char16,
+) -> ! {
+    // There is no image to return from, so end the test instead
+    panic!("Mock image exit ({:?})", exit_status);
+}
+
+unsafe extern "efiapi" fn exit_boot_services(_image_handle: Handle, map_key: usize) -> Status {
+    if map_key == MEMORY_MAP_KEY {
+        Status::SUCCESS
+    } else {
+        Status::INVALID_PARAMETER
+    }
+}
+
+unsafe extern "efiapi" fn stall(_microseconds: usize) -> Status {
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_watchdog_timer(
+    _timeout: usize,
+    watchdog_code: u64,
+    _data_size: usize,
+    _watchdog_data: *const Char16,
+) -> Status {
+    // Codes below 0x10000 are reserved for the firmware
+    if watchdog_code <= 0xffff {
+        Status::INVALID_PARAMETER
+    } else {
+        Status::SUCCESS
+    }
+}
+
+unsafe extern "efiapi" fn copy_mem(dest: *mut u8, src: *const u8, len: usize) {
+    ptr::copy(src, dest, len);
+}
+
+unsafe extern "efiapi" fn set_mem(buffer: *mut u8, len: usize, value: u8) {
+    ptr::write_bytes(buffer, value, len);
+}
+
+unsafe extern "efiapi" fn calculate_crc32(
+    data: *const c_void,
+    data_size: usize,
+    crc32: *mut u32,
+) -> Status {
+    if data.is_null() || data_size == 0 || crc32.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    *crc32 = crate::crc::crc32(core::slice::from_raw_parts(data.cast(), data_size));
+    Status::SUCCESS
+}
+
+// Runtime services
+
+fn runtime_services() -> raw::RuntimeServices {
+    raw::RuntimeServices {
+        header: header::<raw::RuntimeServices>(crate::table::runtime::RuntimeServices::SIGNATURE),
+        get_time: Some(get_time),
+        set_time: Some(set_time),
+        get_wakeup_time: None,
+        set_wakeup_time: None,
+        set_virtual_address_map: None,
+        convert_pointer: None,
+        get_variable: Some(get_variable),
+        get_next_variable_name: Some(get_next_variable_name),
+        set_variable: Some(set_variable),
+        get_next_high_monotonic_count: None,
+        reset_system: Some(reset_system),
+        update_capsule: None,
+        query_capsule_capabilities: None,
+        query_variable_info: None,
+    }
+}
+
+unsafe extern "efiapi" fn get_time(time: *mut Time, capabilities: *mut TimeCapabilities) -> Status {
+    if time.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let stored = &current().time;
+    let now = stored
+        .get()
+        .unwrap_or_else(|| Time::new(2000, 1, 1, 0, 0, 0, 0, 2047, Daylight::empty()));
+    stored.set(Some(now));
+    time.write(now);
+    if !capabilities.is_null() {
+        capabilities.write(TimeCapabilities {
+            resolution: 1,
+            accuracy: 50_000_000,
+            sets_to_zero: false,
+        });
+    }
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_time(time: *const Time) -> Status {
+    current().time.set(Some(*time));
+    Status::SUCCESS
+}
+
+struct MockVariable {
+    /// Null-terminated name of the variable
+    name: Vec<u16>,
+    vendor: Guid,
+    attributes: u32,
+    data: Vec<u8>,
+}
+
+/// Reads a null-terminated UCS-2 string, including its terminator
+unsafe fn read_name(name: *const Char16) -> Vec<u16> {
+    let name = name as *const u16;
+    let mut len = 0;
+    while *name.add(len) != 0 {
+        len += 1;
+    }
+    core::slice::from_raw_parts(name, len + 1).to_vec()
+}
+
+unsafe extern "efiapi" fn get_variable(
+    variable_name: *const Char16,
+    vendor_guid: *const Guid,
+    attributes: *mut u32,
+    data_size: *mut usize,
+    data: *mut u8,
+) -> Status {
+    if variable_name.is_null() || vendor_guid.is_null() || data_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let name = read_name(variable_name);
+    let vars = current().variables.borrow();
+    let var = match vars
+        .iter()
+        .find(|var| var.name == name && var.vendor == *vendor_guid)
+    {
+        Some(var) => var,
+        None => return Status::NOT_FOUND,
+    };
+    if !attributes.is_null() {
+        *attributes = var.attributes;
+    }
+    let buffer_size = *data_size;
+    *data_size = var.data.len();
+    if buffer_size < var.data.len() || data.is_null() {
+        return Status::BUFFER_TOO_SMALL;
+    }
+    ptr::copy_nonoverlapping(var.data.as_ptr(), data, var.data.len());
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn get_next_variable_name(
+    variable_name_size: *mut usize,
+    variable_name: *mut Char16,
+    vendor_guid: *mut Guid,
+) -> Status {
+    if variable_name_size.is_null() || variable_name.is_null() || vendor_guid.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let name = read_name(variable_name);
+    let vars = current().variables.borrow();
+    // An empty name starts the enumeration, otherwise continue after the
+    // variable which was returned last.
+    let next = if name.len() == 1 {
+        0
+    } else {
+        match vars
+            .iter()
+            .position(|var| var.name == name && var.vendor == *vendor_guid)
+        {
+            Some(index) => index + 1,
+            None => return Status::INVALID_PARAMETER,
+        }
+    };
+    let var = match vars.get(next) {
+        Some(var) => var,
+        None => return Status::NOT_FOUND,
+    };
+    let name_size = var.name.len() * mem::size_of::<u16>();
+    let buffer_size = *variable_name_size;
+    *variable_name_size = name_size;
+    if buffer_size < name_size {
+        return Status::BUFFER_TOO_SMALL;
+    }
+    ptr::copy_nonoverlapping(var.name.as_ptr(), variable_name.cast(), var.name.len());
+    *vendor_guid = var.vendor;
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_variable(
+    variable_name: *const Char16,
+    vendor_guid: *const Guid,
+    attributes: u32,
+    data_size: usize,
+    data: *const u8,
+) -> Status {
+    if variable_name.is_null() || vendor_guid.is_null() || (data_size != 0 && data.is_null()) {
+        return Status::INVALID_PARAMETER;
+    }
+    let name = read_name(variable_name);
+    if name.len() == 1 {
+        return Status::INVALID_PARAMETER;
+    }
+    let vendor = *vendor_guid;
+    let mut vars = current().variables.borrow_mut();
+    let index = vars
+        .iter()
+        .position(|var| var.name == name && var.vendor == vendor);
+
+    // Writing an empty value deletes the variable
+    if data_size == 0 {
+        return match index {
+            Some(index) => {
+                vars.remove(index);
+                Status::SUCCESS
+            }
+            None => Status::NOT_FOUND,
+        };
+    }
+
+    let data = core::slice::from_raw_parts(data, data_size).to_vec();
+    match index {
+        Some(index) => {
+            vars[index].attributes = attributes;
+            vars[index].data = data;
+        }
+        None => vars.push(MockVariable {
+            name,
+            vendor,
+            attributes,
+            data,
+        }),
+    }
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn reset_system(
+    reset_type: u32,
+    status: Status,
+    _data_size: usize,
+    _data: *const u8,
+) -> ! {
+    // There is no machine to reset, so end the test process instead
+    panic!("Mock system reset ({}, {:?})", reset_type, status);
+}
+
+// Text output protocol
+
+/// Text output protocol which records the text written to it
+#[repr(C)]
+struct MockOutput {
+    proto: SimpleTextOutput,
+    mode: UnsafeCell<SimpleTextOutputMode>,
+}
+
+/// Dimensions of the single text mode of the mock console.
+const MOCK_TEXT_MODE: (usize, usize) = (80, 25);
+
+impl MockOutput {
+    fn new() -> Self {
+        Self {
+            proto: SimpleTextOutput {
+                reset: output_reset,
+                output_string,
+                test_string,
+                query_mode,
+                set_mode,
+                set_attribute,
+                clear_screen,
+                set_cursor_position,
+                enable_cursor,
+                mode: ptr::null_mut(),
+            },
+            mode: UnsafeCell::new(SimpleTextOutputMode {
+                max_mode: 1,
+                mode: 0,
+                attribute: 0x07,
+                cursor_column: 0,
+                cursor_row: 0,
+                cursor_visible: true,
+            }),
+        }
+    }
+
+    /// Update the mode, which the caller may hold a reference to
+    fn update_mode(&self, f: impl FnOnce(&mut SimpleTextOutputMode)) {
+        let mut mode = unsafe { self.mode.get().read() };
+        f(&mut mode);
+        unsafe { self.mode.get().write(mode) };
+    }
+
+    fn move_cursor(&self, column: usize, row: usize) {
+        self.update_mode(|mode| {
+            mode.cursor_column = column as i32;
+            mode.cursor_row = row as i32;
+        });
+    }
+}
+
+unsafe extern "efiapi" fn output_reset(this: *mut SimpleTextOutput, _extended: bool) -> Status {
+    let mock = current();
+    mock.text.borrow_mut().clear();
+    mock.output(this).move_cursor(0, 0);
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn output_string(
+    _this: *mut SimpleTextOutput,
+    string: *const Char16,
+) -> Status {
+    let string = read_name(string);
+    current()
+        .text
+        .borrow_mut()
+        .extend_from_slice(&string[..string.len() - 1]);
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn test_string(
+    _this: *mut SimpleTextOutput,
+    _string: *const Char16,
+) -> Status {
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn query_mode(
+    _this: *mut SimpleTextOutput,
+    mode: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> Status {
+    if mode != 0 {
+        return Status::UNSUPPORTED;
+    }
+    *columns = MOCK_TEXT_MODE.0;
+    *rows = MOCK_TEXT_MODE.1;
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_mode(_this: *mut SimpleTextOutput, mode: usize) -> Status {
+    if mode == 0 {
+        Status::SUCCESS
+    } else {
+        Status::UNSUPPORTED
+    }
+}
+
+unsafe extern "efiapi" fn set_attribute(this: *mut SimpleTextOutput, attribute: usize) -> Status {
+    current()
+        .output(this)
+        .update_mode(|mode| mode.attribute = attribute as i32);
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn clear_screen(this: *mut SimpleTextOutput) -> Status {
+    current().output(this).move_cursor(0, 0);
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_cursor_position(
+    this: *mut SimpleTextOutput,
+    column: usize,
+    row: usize,
+) -> Status {
+    if column >= MOCK_TEXT_MODE.0 || row >= MOCK_TEXT_MODE.1 {
+        return Status::UNSUPPORTED;
+    }
+    current().output(this).move_cursor(column, row);
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn enable_cursor(this: *mut SimpleTextOutput, visible: bool) -> Status {
+    current()
+        .output(this)
+        .update_mode(|mode| mode.cursor_visible = visible);
+    Status::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::table::boot::AllocateType;
+    use crate::table::runtime::{VariableAttributes, VariableVendor};
+    use crate::CString16;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn boot_services() {
+        let st = MockSystemTable::new().system_table();
+        let bt = st.boot_services();
+
+        let pool = bt
+            .allocate_pool(MemoryType::LOADER_DATA, 100)
+            .unwrap_success();
+        assert_eq!(pool as usize % 8, 0);
+        unsafe { bt.set_mem(pool, 100, 0x42) };
+        assert_eq!(unsafe { *pool.add(99) }, 0x42);
+        bt.free_pool(pool).unwrap_success();
+
+        assert_eq!(
+            bt.calculate_crc32(b"123456789").unwrap_success(),
+            0xcbf4_3926
+        );
+
+        let pages = bt
+            .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, 2)
+            .unwrap_success();
+        assert_eq!(pages % PAGE_SIZE as u64, 0);
+        bt.free_pages(pages, 2).unwrap_success();
+
+        let mmap = bt.memory_map_owned().unwrap_success();
+        assert_eq!(mmap.conventional_bytes(), 16 << 20);
+    }
+
+    #[test]
+    fn stdout_capture() {
+        let mock = MockSystemTable::new();
+        let mut st = mock.system_table();
+        let hello = CString16::try_from("Hello").unwrap();
+        st.stdout().output_string(&hello).unwrap_success();
+        st.stderr().output_string(&hello).unwrap_success();
+        assert_eq!(mock.stdout_text(), "HelloHello");
+        st.stdout().reset(false).unwrap_success();
+        assert_eq!(mock.stdout_text(), "");
+
+        // Both consoles have their own cursor
+        st.stdout().set_cursor_position(3, 4).unwrap_success();
+        assert_eq!(st.stdout().cursor_position(), (3, 4));
+        assert_eq!(st.stderr().cursor_position(), (0, 0));
+    }
+
+    #[test]
+    fn separate_tables() {
+        let vendor = VariableVendor(Guid::from_values(11, 12, 13, 14, 15));
+        let name = CString16::try_from("MockVar").unwrap();
+        let attrs = VariableAttributes::BOOTSERVICE_ACCESS;
+        let first = MockSystemTable::new().system_table();
+        first
+            .runtime_services()
+            .set_variable(&name, &vendor, attrs, b"data")
+            .unwrap_success();
+
+        let second = MockSystemTable::new().system_table();
+        assert_eq!(
+            second
+                .runtime_services()
+                .get_variable_size(&name, &vendor)
+                .status(),
+            Status::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn variables() {
+        let st = MockSystemTable::new().system_table();
+        let rt = st.runtime_services();
+        let vendor = VariableVendor(Guid::from_values(1, 2, 3, 4, 5));
+        let name = CString16::try_from("MockVar").unwrap();
+        let name = &*name;
+        let attrs = VariableAttributes::BOOTSERVICE_ACCESS;
+
+        rt.set_variable(name, &vendor, attrs, b"data")
+            .unwrap_success();
+        assert_eq!(rt.get_variable_size(name, &vendor).unwrap_success(), 4);
+        let mut data = [0; 4];
+        let (value, value_attrs) = rt.get_variable(name, &vendor, &mut data).unwrap_success();
+        assert_eq!(value, b"data");
+        assert_eq!(value_attrs, attrs);
+        assert!(rt
+            .variable_keys()
+            .unwrap_success()
+            .iter()
+            .any(|key| key.vendor == vendor
+                && key.name().unwrap().to_u16_slice() == name.to_u16_slice()));
+
+        rt.set_variable(name, &vendor, attrs, &[]).unwrap_success();
+        assert_eq!(
+            rt.get_variable_size(name, &vendor).status(),
+            Status::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn variable_keys_iterator() {
+        let st = MockSystemTable::new().system_table();
+        let rt = st.runtime_services();
+        let vendor = VariableVendor(Guid::from_values(6, 7, 8, 9, 10));
+        let attrs = VariableAttributes::BOOTSERVICE_ACCESS;
+        // The last name does not fit in the initial name buffer
+        let names = ["First", "Second", "ThisNameIsLongerThanThirtyTwoCharacters"];
+        for name in &names {
+            let name = CString16::try_from(*name).unwrap();
+            rt.set_variable(&name, &vendor, attrs, b"data")
+                .unwrap_success();
+        }
+
+        // Delete the first variable once it has been returned, which forces
+        // the enumeration to start over.
+        let mut found = Vec::new();
+        for key in rt.iter_variable_keys() {
+            let (name, guid) = key.unwrap_success();
+            if guid != vendor.0 {
+                continue;
+            }
+            if found.is_empty() {
+                rt.set_variable(&name, &vendor, attrs, &[]).unwrap_success();
+            }
+            found.push(name.as_string());
+        }
+        assert_eq!(found, names);
+
+        for name in &names[1..] {
+            let name = CString16::try_from(*name).unwrap();
+            rt.set_variable(&name, &vendor, attrs, &[]).unwrap_success();
+        }
+    }
+
+    #[test]
+    fn exit_boot_services() {
+        let st = MockSystemTable::new().system_table();
+        let mut mmap_buf = [0u64; 16];
+        let mmap_buf = unsafe {
+            core::slice::from_raw_parts_mut(
+                mmap_buf.as_mut_ptr() as *mut u8,
+                mem::size_of_val(&mmap_buf),
+            )
+        };
+        let image = unsafe { crate::Handle::uninitialized() };
+        let (st, mut mmap) = st.exit_boot_services(image, mmap_buf).unwrap_success();
+        assert_eq!(mmap.next().unwrap().ty, MemoryType::CONVENTIONAL);
+        let time = unsafe { st.runtime_services() }.get_time().unwrap_success();
+        assert_eq!(time.year(), 2000);
+    }
+}
@@ -0,0 +1,320 @@
+//! Socket-like access to the network stack of the firmware.
+//!
+//! The network protocols are spread over many handles: each interface has a
+//! Simple Network protocol describing the hardware, an IPv4 Configuration II
+//! protocol for its address, and service bindings creating instances of the
+//! transport protocols. This module looks them up, so that applications can
+//! list the interfaces and open connections without dealing with the
+//! individual protocols:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # fn f(bt: &BootServices) -> uefi::Result {
+//! let mut stream = uefi::net::connect_tcp(bt, [10, 0, 2, 2], 80)?.log();
+//! stream.write(b"GET / HTTP/1.0\r\n\r\n")?.log();
+//! let mut response = [0; 512];
+//! let len = stream.read(&mut response)?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! Operations block until they complete. Sockets destroy their instance of
+//! the protocol when they are dropped.
+
+use crate::alloc_api::vec::Vec;
+use crate::proto::network::ip4_config2::Ip4Config2;
+use crate::proto::network::service_binding::ServiceBinding;
+use crate::proto::network::snp::{MacAddress, NetworkState, SimpleNetwork};
+use crate::proto::network::tcp4::{Tcp4, Tcp4AccessPoint, Tcp4ConfigData, Tcp4ServiceBinding};
+use crate::proto::network::udp4::{Udp4, Udp4ConfigData, Udp4ServiceBinding};
+use crate::proto::Protocol;
+use crate::table::boot::BootServices;
+use crate::{Handle, Result, ResultExt, Status};
+use core::cell::UnsafeCell;
+use core::ops::DerefMut;
+
+/// Number of times the configuration of a socket is retried while the
+/// interface waits for its address
+const CONFIGURE_RETRIES: usize = 50;
+
+/// Delay between the retries, in microseconds
+const CONFIGURE_DELAY: usize = 100_000;
+
+/// Description of a network interface
+#[derive(Debug, Clone)]
+pub struct Interface {
+    /// Handle of the interface
+    pub handle: Handle,
+    mac_address: MacAddress,
+    mac_address_size: usize,
+    /// ARP hardware type of the interface, like 1 for Ethernet
+    pub if_type: u8,
+    /// State of the interface
+    pub state: NetworkState,
+    /// Whether a cable is connected, if the interface can tell
+    pub media_present: Option<bool>,
+    /// Address and subnet mask of the interface, if it has one
+    pub ipv4: Option<([u8; 4], [u8; 4])>,
+}
+
+impl Interface {
+    /// The current MAC address
+    pub fn mac_address(&self) -> &[u8] {
+        &self.mac_address[..self.mac_address_size]
+    }
+
+    /// Whether the link is up, assuming so if the interface cannot tell
+    pub fn is_link_up(&self) -> bool {
+        self.media_present.unwrap_or(true)
+    }
+}
+
+/// List the network interfaces.
+///
+/// The link state is read again from each interface, and the address of the
+/// interfaces which have an IPv4 stack is reported.
+///
+/// # Errors
+///
+/// * `uefi::Status::NOT_FOUND`  There is no network interface
+pub fn interfaces(bt: &BootServices) -> Result<Vec<Interface>> {
+    let handles = bt.find_handles::<SimpleNetwork>()?.log();
+    let interfaces: Vec<Interface> = handles
+        .into_iter()
+        .filter_map(|handle| {
+            let snp = bt.handle_protocol::<SimpleNetwork>(handle).ok()?.log();
+            let snp = unsafe { &mut *snp.get() };
+            // The status can only be read from started interfaces
+            let media_present = snp.mode().media_present_supported
+                && snp.refresh_status().is_ok()
+                && snp.mode().media_present;
+            let mode = snp.mode();
+            let ipv4 = bt
+                .handle_protocol::<Ip4Config2>(handle)
+                .ok()
+                .and_then(|config| unsafe { &mut *config.log().get() }.interface_info().ok())
+                .map(|info| info.log())
+                .filter(|info| info.is_configured())
+                .map(|info| (info.station_address, info.subnet_mask));
+            Some(Interface {
+                handle,
+                mac_address: mode.current_address,
+                mac_address_size: mode.mac_address().len(),
+                if_type: mode.if_type,
+                state: mode.state,
+                media_present: if mode.media_present_supported {
+                    Some(media_present)
+                } else {
+                    None
+                },
+                ipv4,
+            })
+        })
+        .collect();
+    Ok(interfaces.into())
+}
+
+/// An instance of a transport protocol, created by the service binding `S`
+/// and destroyed when dropped
+struct Child<'boot, S: Protocol + DerefMut<Target = ServiceBinding>, P: Protocol> {
+    service: &'boot UnsafeCell<S>,
+    handle: Handle,
+    protocol: &'boot UnsafeCell<P>,
+}
+
+impl<'boot, S: Protocol + DerefMut<Target = ServiceBinding>, P: Protocol> Child<'boot, S, P> {
+    fn create(bt: &'boot BootServices, interface: Handle) -> Result<Self> {
+        let service = bt.handle_protocol::<S>(interface)?.log();
+        let handle = unsafe { &mut *service.get() }.create_child()?.log();
+        match bt.handle_protocol::<P>(handle) {
+            Ok(protocol) => Ok(Child {
+                service,
+                handle,
+                protocol: protocol.log(),
+            }
+            .into()),
+            Err(err) => {
+                let _ = unsafe { &mut *service.get() }.destroy_child(handle);
+                Err(err)
+            }
+        }
+    }
+
+    fn get(&mut self) -> &mut P {
+        unsafe { &mut *self.protocol.get() }
+    }
+}
+
+impl<S: Protocol + DerefMut<Target = ServiceBinding>, P: Protocol> Drop for Child<'_, S, P> {
+    fn drop(&mut self) {
+        let _ = unsafe { &mut *self.service.get() }.destroy_child(self.handle);
+    }
+}
+
+/// Call `configure` until the interface has an address
+fn configure_when_mapped(bt: &BootServices, mut configure: impl FnMut() -> Result) -> Result {
+    let mut result = configure();
+    for _ in 0..CONFIGURE_RETRIES {
+        match &result {
+            Err(err) if err.status() == Status::NO_MAPPING => {
+                bt.stall(CONFIGURE_DELAY);
+                result = configure();
+            }
+            _ => break,
+        }
+    }
+    result
+}
+
+/// Call `open` on the handles of the service binding `S`, until one of them
+/// succeeds
+fn open_on_any<S: Protocol, T>(
+    bt: &BootServices,
+    mut open: impl FnMut(Handle) -> Result<T>,
+) -> Result<T> {
+    let mut last = Err(Status::NOT_FOUND.into());
+    for handle in bt.find_handles::<S>()?.log() {
+        last = open(handle);
+        if last.is_ok() {
+            break;
+        }
+    }
+    last
+}
+
+/// Open a TCP connection to `address` and `port`, through the first
+/// interface which can reach it.
+///
+/// This waits for the interfaces configured by DHCP to get their address.
+///
+/// # Errors
+///
+/// * `uefi::Status::NOT_FOUND`             There is no TCPv4 stack
+/// * `uefi::Status::NO_MAPPING`            No interface has an address
+/// * `uefi::Status::CONNECTION_REFUSED`    The peer refused the connection
+/// * `uefi::Status::TIMEOUT`               The peer did not answer
+pub fn connect_tcp(bt: &BootServices, address: [u8; 4], port: u16) -> Result<TcpStream<'_>> {
+    open_on_any::<Tcp4ServiceBinding, _>(bt, |interface| {
+        let mut child = Child::<Tcp4ServiceBinding, Tcp4>::create(bt, interface)?.log();
+        let config = Tcp4ConfigData::new(Tcp4AccessPoint::client(address, port));
+        configure_when_mapped(bt, || child.get().configure(Some(&config)))?.log();
+        child.get().connect(bt)?.log();
+        Ok(TcpStream { bt, child }.into())
+    })
+}
+
+/// A TCP connection, opened with [`connect_tcp`]
+///
+/// The connection is reset if it was not closed when the stream is dropped.
+pub struct TcpStream<'boot> {
+    bt: &'boot BootServices,
+    child: Child<'boot, Tcp4ServiceBinding, Tcp4>,
+}
+
+impl TcpStream<'_> {
+    /// Receive data into `buffer`, and return its size.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::CONNECTION_FIN`    The peer closed the connection,
+    ///                                     and all its data was received
+    /// * `uefi::Status::CONNECTION_RESET`  The peer reset the connection
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.child.get().receive(self.bt, buffer)
+    }
+
+    /// Send `data`, and return the number of bytes which were sent.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::CONNECTION_FIN`    The connection is closing
+    /// * `uefi::Status::CONNECTION_RESET`  The peer closed the connection
+    pub fn write(&mut self, data: &[u8]) -> Result<usize> {
+        self.child.get().transmit(self.bt, data)
+    }
+
+    /// Send all of `data`.
+    ///
+    /// # Errors
+    ///
+    /// See `write`, and:
+    ///
+    /// * `uefi::Status::ABORTED`           The connection accepted no data
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result {
+        while !data.is_empty() {
+            let len = self.write(data)?.log();
+            if len == 0 {
+                return Err(Status::ABORTED.into());
+            }
+            data = &data[len.min(data.len())..];
+        }
+        Ok(().into())
+    }
+
+    /// Close the connection gracefully.
+    pub fn close(mut self) -> Result {
+        self.child.get().close(self.bt, false)
+    }
+}
+
+impl Drop for TcpStream<'_> {
+    fn drop(&mut self) {
+        // Resetting an instance aborts its connection
+        let _ = self.child.get().configure(None);
+    }
+}
+
+/// Open a UDP socket, on any local port of the first interface with an
+/// address.
+///
+/// This waits for the interfaces configured by DHCP to get their address.
+///
+/// # Errors
+///
+/// * `uefi::Status::NOT_FOUND`     There is no UDPv4 stack
+/// * `uefi::Status::NO_MAPPING`    No interface has an address
+pub fn udp_socket(bt: &BootServices) -> Result<UdpSocket<'_>> {
+    open_on_any::<Udp4ServiceBinding, _>(bt, |interface| {
+        let mut child = Child::<Udp4ServiceBinding, Udp4>::create(bt, interface)?.log();
+        let config = Udp4ConfigData::any_peer();
+        configure_when_mapped(bt, || child.get().configure(Some(&config)))?.log();
+        Ok(UdpSocket { bt, child }.into())
+    })
+}
+
+/// A UDP socket, opened with [`udp_socket`]
+pub struct UdpSocket<'boot> {
+    bt: &'boot BootServices,
+    child: Child<'boot, Udp4ServiceBinding, Udp4>,
+}
+
+impl UdpSocket<'_> {
+    /// Send `data` in a datagram to `address` and `port`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`   `data` does not fit in a datagram
+    /// * `uefi::Status::NO_MAPPING`        There is no route to `address`
+    pub fn send_to(&mut self, data: &[u8], address: [u8; 4], port: u16) -> Result {
+        self.child
+            .get()
+            .transmit(self.bt, Some((address, port)), data)
+    }
+
+    /// Receive a datagram into `buffer`, and return its size and the address
+    /// and port of its sender.
+    ///
+    /// Datagrams larger than `buffer` are truncated.
+    pub fn recv_from(&mut self, buffer: &mut [u8]) -> Result<(usize, [u8; 4], u16)> {
+        self.child
+            .get()
+            .receive(self.bt, buffer)
+            .map_inner(|(len, session)| (len, session.source_address, session.source_port))
+    }
+}
+
+impl Drop for UdpSocket<'_> {
+    fn drop(&mut self) {
+        let _ = self.child.get().configure(None);
+    }
+}
@@ -0,0 +1,962 @@
+//! PE/COFF image loading.
+//!
+//! UEFI applications and drivers are PE32+ images (PE32 on IA-32), which the
+//! firmware loads with `LoadImage`. This module does the same job by hand, for
+//! loaders which can not go through the firmware, for example because the
+//! image must be patched or measured before it runs, or because Secure Boot
+//! would reject it.
+//!
+//! Loading an image takes three steps:
+//!
+//! - [`PeImage::parse`] checks the headers of an image file read to memory,
+//!   for example with `Directory::read_to_vec`.
+//! - [`PeImage::load`] copies the sections of the image to newly allocated
+//!   pages and applies the base relocations, so that the image can run at its
+//!   new address.
+//! - [`LoadedPe::protect`] optionally makes the code read-only and the data
+//!   non-executable, using the Memory Attribute protocol.
+//!
+//! [`PeImage::authenticode_hash`] computes the digest which identifies the
+//! image in signatures and in the TPM event log, for the loaders which check
+//! or measure the image themselves; see `proto::security::tcg2` for the
+//! latter.
+//!
+//! The entry point of the loaded image can then be called. Note that images
+//! loaded this way are unknown to the firmware, so they do not have an image
+//! handle of their own nor a `LoadedImage` protocol.
+//!
+//! [`RunningImage`] gives the same view of the image which is running, as
+//! the firmware loaded it. Applications and drivers which stay around for a
+//! long time can use it to protect their own sections:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::memory_protection::MemoryProtection;
+//! # use uefi::pe::RunningImage;
+//! # fn f(bt: &BootServices, image: Handle) -> uefi::Result {
+//! let running = RunningImage::new(bt, image)?.log();
+//! if let Some(text) = running.section(".text") {
+//!     log::info!("Code at {:#x?}", running.section_range(&text));
+//! }
+//! if let Ok(protection) = bt.locate_protocol::<MemoryProtection>() {
+//!     let protection = protection.log();
+//!     running.protect(unsafe { &*protection.get() })?.log();
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::digest::{Digest, Sha256};
+use crate::proto::loaded_image::LoadedImage;
+use crate::proto::memory_protection::MemoryProtection;
+use crate::table::boot::{
+    size_to_pages, AllocateType, BootServices, MemoryAttribute, MemoryType, PAGE_SIZE,
+};
+use crate::{Handle, Result, ResultExt, Status};
+use core::convert::TryInto;
+use core::ops::Range;
+use core::slice;
+
+/// Errors that can occur when parsing or relocating a PE image
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PeError {
+    /// The data does not start with valid DOS and PE headers.
+    InvalidHeader,
+    /// The image is not a PE32 or PE32+ image, or uses unsupported relocations.
+    Unsupported,
+    /// The headers point outside of the image data.
+    Truncated,
+}
+
+/// Machine type of IA-32 images
+pub const MACHINE_I386: u16 = 0x14c;
+/// Machine type of x86_64 images
+pub const MACHINE_X86_64: u16 = 0x8664;
+/// Machine type of AArch64 images
+pub const MACHINE_AARCH64: u16 = 0xaa64;
+/// Machine type of RISC-V 64-bit images
+pub const MACHINE_RISCV64: u16 = 0x5064;
+
+/// Offset of the PE header offset in the DOS header
+const DOS_LFANEW_OFFSET: usize = 0x3c;
+/// Size of the PE signature and COFF file header
+const COFF_HEADER_SIZE: usize = 4 + 20;
+/// Magic number of the PE32 optional header
+const PE32_MAGIC: u16 = 0x10b;
+/// Magic number of the PE32+ optional header
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+/// Size of the fields of the PE32 optional header which precede the data
+/// directories
+const PE32_OPTIONAL_HEADER_SIZE: usize = 96;
+/// Size of the fields of the PE32+ optional header which precede the data
+/// directories
+const OPTIONAL_HEADER_SIZE: usize = 112;
+/// Offset of the checksum in the optional header
+const CHECKSUM_OFFSET: usize = 64;
+/// Index of the certificate table in the data directories
+const SECURITY_DIRECTORY: usize = 4;
+/// Index of the base relocation table in the data directories
+const BASE_RELOCATION_DIRECTORY: usize = 5;
+/// Size of a section header
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// Relocation which does nothing, used for padding
+const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+/// Relocation of a 32-bit address
+const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+/// Relocation of a 64-bit address
+const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+/// Section flag: the section contains executable code
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+/// Section flag: the section can be written to
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// A parsed PE32 or PE32+ image file, borrowing the file contents
+#[derive(Debug, Copy, Clone)]
+pub struct PeImage<'data> {
+    data: &'data [u8],
+    machine: u16,
+    entry_point: u32,
+    image_base: u64,
+    size_of_image: usize,
+    size_of_headers: usize,
+    section_alignment: u32,
+    subsystem: u16,
+    relocations: (u32, u32),
+    security: (u32, u32),
+    checksum_offset: usize,
+    security_offset: Option<usize>,
+    sections_offset: usize,
+    section_count: usize,
+}
+
+/// A section of a PE image
+#[derive(Debug, Copy, Clone)]
+pub struct Section {
+    /// Name of the section, padded with null bytes.
+    pub name: [u8; 8],
+    /// Offset of the section from the base of the loaded image.
+    pub virtual_address: u32,
+    /// Size of the section once loaded.
+    pub virtual_size: u32,
+    /// Offset of the section contents in the image file.
+    pub raw_offset: u32,
+    /// Size of the section contents in the image file. If it is smaller than
+    /// the virtual size, the rest of the section is filled with zeros.
+    pub raw_size: u32,
+    /// Flags of the section (`IMAGE_SCN_*`).
+    pub characteristics: u32,
+}
+
+impl Section {
+    /// Whether the section contains executable code
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_EXECUTE != 0
+    }
+
+    /// Whether the section can be written to
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_WRITE != 0
+    }
+
+    /// Size of the section once loaded, accounting for linkers which leave
+    /// the virtual size empty
+    fn loaded_size(&self) -> u32 {
+        if self.virtual_size == 0 {
+            self.raw_size
+        } else {
+            self.virtual_size
+        }
+    }
+}
+
+impl<'data> PeImage<'data> {
+    /// Parse the headers of a PE32 or PE32+ image file
+    pub fn parse(data: &'data [u8]) -> core::result::Result<Self, PeError> {
+        let image = Self::parse_headers(data)?;
+        for section in image.sections() {
+            let raw_end = (section.raw_offset as usize).checked_add(section.raw_size as usize);
+            if !matches!(raw_end, Some(end) if end <= data.len()) {
+                return Err(PeError::Truncated);
+            }
+        }
+        // The security directory holds a file offset, not an address
+        let security_end = (image.security.0 as usize).checked_add(image.security.1 as usize);
+        if !matches!(security_end, Some(end) if end <= data.len()) {
+            return Err(PeError::Truncated);
+        }
+        Ok(image)
+    }
+
+    /// Parse the headers of an image which was loaded to memory, where the
+    /// sections are at their addresses rather than at their file offsets
+    ///
+    /// Only the headers and the sections of the result may be used: the
+    /// methods which read the file contents do not apply to loaded images.
+    fn parse_loaded(image: &'data [u8]) -> core::result::Result<Self, PeError> {
+        let parsed = Self::parse_headers(image)?;
+        if parsed.size_of_image > image.len() {
+            return Err(PeError::Truncated);
+        }
+        Ok(parsed)
+    }
+
+    /// Parse the headers, and check those which are the same in image files
+    /// and in loaded images
+    fn parse_headers(data: &'data [u8]) -> core::result::Result<Self, PeError> {
+        if data.get(0..2) != Some(b"MZ") {
+            return Err(PeError::InvalidHeader);
+        }
+        let pe = read_u32(data, DOS_LFANEW_OFFSET).ok_or(PeError::InvalidHeader)? as usize;
+        let signature_end = pe.checked_add(4).ok_or(PeError::InvalidHeader)?;
+        if data.get(pe..signature_end) != Some(b"PE\0\0") {
+            return Err(PeError::InvalidHeader);
+        }
+        let coff = |offset| read_u16(data, pe + 4 + offset).ok_or(PeError::Truncated);
+        let machine = coff(0)?;
+        let section_count = coff(2)? as usize;
+        let optional_header_size = coff(16)? as usize;
+
+        let opt = pe + COFF_HEADER_SIZE;
+        let field = |offset| read_u32(data, opt + offset).ok_or(PeError::Truncated);
+        // PE32 headers have a 32-bit image base, and an extra BaseOfData field
+        let (image_base, directories) = match read_u16(data, opt) {
+            Some(PE32_MAGIC) => (u64::from(field(28)?), PE32_OPTIONAL_HEADER_SIZE),
+            Some(PE32_PLUS_MAGIC) => (
+                read_u64(data, opt + 24).ok_or(PeError::Truncated)?,
+                OPTIONAL_HEADER_SIZE,
+            ),
+            _ => return Err(PeError::Unsupported),
+        };
+        let entry_point = field(16)?;
+        let section_alignment = field(32)?;
+        let size_of_image = field(56)? as usize;
+        let size_of_headers = field(60)? as usize;
+        let subsystem = read_u16(data, opt + 68).ok_or(PeError::Truncated)?;
+        let directory_count = field(directories - 4)? as usize;
+        let relocations = if directory_count > BASE_RELOCATION_DIRECTORY {
+            let directory = directories + 8 * BASE_RELOCATION_DIRECTORY;
+            (field(directory)?, field(directory + 4)?)
+        } else {
+            (0, 0)
+        };
+        let security_offset = if directory_count > SECURITY_DIRECTORY {
+            Some(opt + directories + 8 * SECURITY_DIRECTORY)
+        } else {
+            None
+        };
+        let security = match security_offset {
+            Some(offset) => (
+                read_u32(data, offset).ok_or(PeError::Truncated)?,
+                read_u32(data, offset + 4).ok_or(PeError::Truncated)?,
+            ),
+            None => (0, 0),
+        };
+
+        let image = PeImage {
+            data,
+            machine,
+            entry_point,
+            image_base,
+            size_of_image,
+            size_of_headers,
+            section_alignment,
+            subsystem,
+            relocations,
+            security,
+            checksum_offset: opt + CHECKSUM_OFFSET,
+            security_offset,
+            sections_offset: opt + optional_header_size,
+            section_count,
+        };
+
+        // Check that everything the loader will access is in bounds
+        let sections_end = image.sections_offset + section_count * SECTION_HEADER_SIZE;
+        if sections_end > data.len() || size_of_headers > data.len() {
+            return Err(PeError::Truncated);
+        }
+        if size_of_headers > size_of_image || entry_point as usize >= size_of_image {
+            return Err(PeError::InvalidHeader);
+        }
+        for section in image.sections() {
+            let loaded_end =
+                (section.virtual_address as usize).checked_add(section.loaded_size() as usize);
+            if !matches!(loaded_end, Some(end) if end <= size_of_image) {
+                return Err(PeError::InvalidHeader);
+            }
+        }
+        let relocations_end = (relocations.0 as usize).checked_add(relocations.1 as usize);
+        if !matches!(relocations_end, Some(end) if end <= size_of_image) {
+            return Err(PeError::InvalidHeader);
+        }
+        Ok(image)
+    }
+
+    /// Machine type that the image was built for (`MACHINE_*`)
+    pub fn machine(&self) -> u16 {
+        self.machine
+    }
+
+    /// Subsystem of the image, such as 10 for UEFI applications
+    pub fn subsystem(&self) -> u16 {
+        self.subsystem
+    }
+
+    /// Address at which the image was linked
+    pub fn image_base(&self) -> u64 {
+        self.image_base
+    }
+
+    /// Size of the image once loaded, in bytes
+    pub fn size_of_image(&self) -> usize {
+        self.size_of_image
+    }
+
+    /// Offset of the entry point from the base of the loaded image
+    pub fn entry_point(&self) -> u32 {
+        self.entry_point
+    }
+
+    /// Whether the image contains base relocations, which are required to
+    /// load it at another address than its image base
+    pub fn is_relocatable(&self) -> bool {
+        self.relocations.1 != 0
+    }
+
+    /// Sections of the image
+    pub fn sections(&self) -> impl ExactSizeIterator<Item = Section> + 'data {
+        let headers = &self.data[self.sections_offset..];
+        (0..self.section_count).map(move |i| {
+            let header = &headers[i * SECTION_HEADER_SIZE..][..SECTION_HEADER_SIZE];
+            let field = |offset| read_u32(header, offset).unwrap();
+            Section {
+                name: header[..8].try_into().unwrap(),
+                virtual_size: field(8),
+                virtual_address: field(12),
+                raw_size: field(16),
+                raw_offset: field(20),
+                characteristics: field(36),
+            }
+        })
+    }
+
+    /// Whether the image file has a certificate table, which holds its
+    /// Authenticode signatures
+    pub fn is_signed(&self) -> bool {
+        self.security.1 != 0
+    }
+
+    /// Feed the data covered by the Authenticode signature of the image to
+    /// `digest`, in the order in which it is hashed.
+    ///
+    /// This is the PE/COFF hash used by Secure Boot and by measured boot: the
+    /// headers without the checksum and the certificate table entry, then the
+    /// sections in file order, then the data which follows them except the
+    /// certificate table. Callers can check the digest against an allow-list,
+    /// or extend it into a PCR, before loading the image.
+    pub fn authenticode_hash(&self, digest: &mut impl Digest) -> core::result::Result<(), PeError> {
+        let headers = &self.data[..self.size_of_headers];
+        let checksum_end = self.checksum_offset + 4;
+        let (security, security_end) = match self.security_offset {
+            Some(offset) => (offset, offset + 8),
+            None => (checksum_end, checksum_end),
+        };
+        if security_end > headers.len() {
+            return Err(PeError::InvalidHeader);
+        }
+        digest.update(&headers[..self.checksum_offset]);
+        digest.update(&headers[checksum_end..security]);
+        digest.update(&headers[security_end..]);
+
+        // Sections are hashed by increasing file offset, which is not
+        // necessarily the order of their headers
+        let mut hashed = headers.len();
+        let mut previous = None;
+        for _ in 0..self.section_count {
+            let next = self
+                .sections()
+                .enumerate()
+                .map(|(i, section)| ((section.raw_offset, i), section))
+                .filter(|(key, _)| Some(*key) > previous)
+                .min_by_key(|(key, _)| *key);
+            let (key, section) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            previous = Some(key);
+            if section.raw_size == 0 {
+                continue;
+            }
+            let start = section.raw_offset as usize;
+            digest.update(&self.data[start..start + section.raw_size as usize]);
+            hashed += section.raw_size as usize;
+        }
+
+        let certificates = self.security.1 as usize;
+        if self.data.len() > hashed + certificates {
+            digest.update(&self.data[hashed..self.data.len() - certificates]);
+        }
+        Ok(())
+    }
+
+    /// SHA-256 Authenticode hash of the image, as computed by
+    /// `authenticode_hash`
+    pub fn authenticode_sha256(&self) -> core::result::Result<[u8; 32], PeError> {
+        let mut digest = Sha256::new();
+        self.authenticode_hash(&mut digest)?;
+        Ok(digest.finish())
+    }
+
+    /// Copy the headers and sections of the image to `dest`, which must be the
+    /// size of the loaded image, and zero the rest
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dest` is not `size_of_image` bytes long.
+    pub fn load_into(&self, dest: &mut [u8]) {
+        assert_eq!(dest.len(), self.size_of_image, "Wrong image size");
+        dest.iter_mut().for_each(|byte| *byte = 0);
+        dest[..self.size_of_headers].copy_from_slice(&self.data[..self.size_of_headers]);
+        for section in self.sections() {
+            let size = section.raw_size.min(section.loaded_size()) as usize;
+            let src = &self.data[section.raw_offset as usize..][..size];
+            dest[section.virtual_address as usize..][..size].copy_from_slice(src);
+        }
+    }
+
+    /// Apply the base relocations to an image which was loaded into `image`
+    /// with `load_into`, so that it can run at address `new_base`
+    pub fn relocate(&self, image: &mut [u8], new_base: u64) -> core::result::Result<(), PeError> {
+        let delta = new_base.wrapping_sub(self.image_base);
+        let mut offset = self.relocations.0 as usize;
+        let end = offset + self.relocations.1 as usize;
+        // The relocation table is part of the image, so it is walked by
+        // offsets rather than borrowed while the image is patched
+        while offset < end {
+            let page = read_u32(image, offset).ok_or(PeError::Truncated)? as usize;
+            let block_size = read_u32(image, offset + 4).ok_or(PeError::Truncated)? as usize;
+            if block_size < 8 || offset + block_size > end {
+                return Err(PeError::Truncated);
+            }
+            for entry in (offset + 8..offset + block_size - 1).step_by(2) {
+                let entry = read_u16(image, entry).ok_or(PeError::Truncated)?;
+                apply_fixup(image, page + (entry & 0xfff) as usize, entry >> 12, delta)?;
+            }
+            offset += block_size;
+        }
+        Ok(())
+    }
+
+    /// Load the image to newly allocated pages, and relocate it there
+    ///
+    /// Fails with `UNSUPPORTED` if the image does not run on the current CPU
+    /// or can not be relocated, and with `LOAD_ERROR` if its relocations are
+    /// invalid.
+    pub fn load(&self, bt: &BootServices) -> Result<LoadedPe<'data>> {
+        if !is_native_machine(self.machine) {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        if !self.is_relocatable() {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        let pages = size_to_pages(self.size_of_image);
+        let base = bt
+            .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_CODE, pages)
+            .log_warning()?;
+        let image = unsafe { slice::from_raw_parts_mut(base as *mut u8, self.size_of_image) };
+        self.load_into(image);
+        if self.relocate(image, base).is_err() {
+            let _ = bt.free_pages(base, pages);
+            return Err(Status::LOAD_ERROR.into());
+        }
+        Ok(LoadedPe { image: *self, base }.into())
+    }
+}
+
+/// Apply a relocation of type `ty` at `offset` in an image
+fn apply_fixup(
+    image: &mut [u8],
+    offset: usize,
+    ty: u16,
+    delta: u64,
+) -> core::result::Result<(), PeError> {
+    match ty {
+        IMAGE_REL_BASED_ABSOLUTE => {}
+        IMAGE_REL_BASED_HIGHLOW => {
+            let value = read_u32(image, offset).ok_or(PeError::Truncated)?;
+            let value = value.wrapping_add(delta as u32);
+            image[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        IMAGE_REL_BASED_DIR64 => {
+            let value = read_u64(image, offset).ok_or(PeError::Truncated)?;
+            let value = value.wrapping_add(delta);
+            image[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        _ => return Err(PeError::Unsupported),
+    }
+    Ok(())
+}
+
+/// Whether images of the given machine type run on the current CPU
+fn is_native_machine(machine: u16) -> bool {
+    (cfg!(target_arch = "x86") && machine == MACHINE_I386)
+        || (cfg!(target_arch = "x86_64") && machine == MACHINE_X86_64)
+        || (cfg!(target_arch = "aarch64") && machine == MACHINE_AARCH64)
+        || (cfg!(target_arch = "riscv64") && machine == MACHINE_RISCV64)
+}
+
+/// A PE image which was loaded to memory by [`PeImage::load`]
+#[derive(Debug)]
+pub struct LoadedPe<'data> {
+    image: PeImage<'data>,
+    base: u64,
+}
+
+impl<'data> LoadedPe<'data> {
+    /// Address at which the image was loaded
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Size of the loaded image, in bytes
+    pub fn size(&self) -> usize {
+        self.image.size_of_image
+    }
+
+    /// Address of the entry point of the loaded image
+    ///
+    /// For UEFI images, this is an `extern "efiapi" fn(Handle, SystemTable<Boot>) -> Status`.
+    pub fn entry_point(&self) -> u64 {
+        self.base + u64::from(self.image.entry_point)
+    }
+
+    /// Headers of the image
+    pub fn image(&self) -> &PeImage<'data> {
+        &self.image
+    }
+
+    /// Protect the sections of the loaded image: code is made read-only, and
+    /// data is made non-executable
+    ///
+    /// Fails with `UNSUPPORTED` if the sections of the image are not aligned
+    /// to pages.
+    pub fn protect(&self, protection: &MemoryProtection) -> Result {
+        protect_sections(&self.image, self.base, protection)
+    }
+
+    /// Free the memory of the loaded image
+    ///
+    /// # Safety
+    ///
+    /// The image must not be running, and nothing may refer to its memory,
+    /// such as protocols that it installed or callbacks that it registered.
+    pub unsafe fn free(self, bt: &BootServices) -> Result {
+        bt.free_pages(self.base, size_to_pages(self.image.size_of_image))
+    }
+}
+
+/// Make the code of an image read-only and its data non-executable
+fn protect_sections(image: &PeImage, base: u64, protection: &MemoryProtection) -> Result {
+    if image.section_alignment as usize % PAGE_SIZE != 0 {
+        return Err(Status::UNSUPPORTED.into());
+    }
+    for section in image.sections() {
+        let start = base + u64::from(section.virtual_address);
+        let size = size_to_pages(section.loaded_size() as usize) * PAGE_SIZE;
+        let range = start..start + size as u64;
+        if range.is_empty() {
+            continue;
+        }
+        let (set, clear) = match (section.is_writable(), section.is_executable()) {
+            (true, true) => (
+                MemoryAttribute::empty(),
+                MemoryAttribute::READ_ONLY | MemoryAttribute::EXECUTE_PROTECT,
+            ),
+            (true, false) => (MemoryAttribute::EXECUTE_PROTECT, MemoryAttribute::READ_ONLY),
+            (false, true) => (MemoryAttribute::READ_ONLY, MemoryAttribute::EXECUTE_PROTECT),
+            (false, false) => (
+                MemoryAttribute::READ_ONLY | MemoryAttribute::EXECUTE_PROTECT,
+                MemoryAttribute::empty(),
+            ),
+        };
+        if !set.is_empty() {
+            protection
+                .set_memory_attributes(range.clone(), set)
+                .log_warning()?;
+        }
+        if !clear.is_empty() {
+            protection
+                .clear_memory_attributes(range, clear)
+                .log_warning()?;
+        }
+    }
+    Status::SUCCESS.into()
+}
+
+/// The image which holds this code, as the firmware loaded it
+///
+/// The firmware leaves the headers of an image at its base, so the sections
+/// can be found without reading the image file again. This lets long-running
+/// applications and drivers check where they live in memory, and harden
+/// themselves with [`RunningImage::protect`].
+#[derive(Debug)]
+pub struct RunningImage {
+    image: PeImage<'static>,
+    base: u64,
+}
+
+impl RunningImage {
+    /// Find the running image from its image handle, as passed to the entry
+    /// point
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `image` is the handle of another
+    ///                                      image
+    /// * `uefi::Status::LOAD_ERROR`         The headers in memory are invalid
+    /// * Any error of `BootServices::handle_protocol` for `LoadedImage`
+    pub fn new(bt: &BootServices, image: Handle) -> Result<Self> {
+        let loaded_image = bt.handle_protocol::<LoadedImage>(image)?.log();
+        let (base, size) = unsafe { &*loaded_image.get() }.info();
+        let (base, size) = (base as u64, size as usize);
+
+        // Other images may be unloaded while the headers are in use, but not
+        // the one which holds this code
+        static MARKER: u8 = 0;
+        let here = &MARKER as *const u8 as u64;
+        if here < base || here - base >= size as u64 {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        let memory = unsafe { slice::from_raw_parts(base as *const u8, size) };
+        let image = PeImage::parse_loaded(memory).map_err(|_| Status::LOAD_ERROR)?;
+        Ok(RunningImage { image, base }.into())
+    }
+
+    /// Address at which the image was loaded
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Size of the loaded image, in bytes
+    pub fn size(&self) -> usize {
+        self.image.size_of_image
+    }
+
+    /// Address of the entry point of the image
+    pub fn entry_point(&self) -> u64 {
+        self.base + u64::from(self.image.entry_point)
+    }
+
+    /// Sections of the image
+    ///
+    /// Only the addresses and the flags of the sections apply: their file
+    /// offsets do not point into the loaded image.
+    pub fn sections(&self) -> impl ExactSizeIterator<Item = Section> {
+        self.image.sections()
+    }
+
+    /// The section of the given name, such as `.text`, if there is one
+    pub fn section(&self, name: &str) -> Option<Section> {
+        self.sections()
+            .find(|section| section.name.split(|&b| b == 0).next() == Some(name.as_bytes()))
+    }
+
+    /// Addresses of a section of the image
+    pub fn section_range(&self, section: &Section) -> Range<u64> {
+        let start = self.base + u64::from(section.virtual_address);
+        start..start + u64::from(section.loaded_size())
+    }
+
+    /// The section which holds the address `addr`, if it is in one
+    pub fn section_of(&self, addr: u64) -> Option<Section> {
+        self.sections()
+            .find(|section| self.section_range(section).contains(&addr))
+    }
+
+    /// Protect the sections of the image: code is made read-only, and data
+    /// is made non-executable
+    ///
+    /// This should be done early, before the image installs protocols or
+    /// registers callbacks. Fails with `UNSUPPORTED` if the sections of the
+    /// image are not aligned to pages.
+    pub fn protect(&self, protection: &MemoryProtection) -> Result {
+        protect_sections(&self.image, self.base, protection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_BASE: u64 = 0x1_0000;
+    const PE_OFFSET: usize = 0x40;
+    const SECTIONS_OFFSET: usize = PE_OFFSET + COFF_HEADER_SIZE + OPTIONAL_HEADER_SIZE + 6 * 8;
+
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Build an image with a code section at 0x1000, holding a pointer to
+    /// itself, and a relocation section at 0x2000
+    fn build_image() -> [u8; 0x600] {
+        let mut data = [0; 0x600];
+        put(&mut data, 0, b"MZ");
+        put(
+            &mut data,
+            DOS_LFANEW_OFFSET,
+            &(PE_OFFSET as u32).to_le_bytes(),
+        );
+        put(&mut data, PE_OFFSET, b"PE\0\0");
+
+        let coff = PE_OFFSET + 4;
+        put(&mut data, coff, &MACHINE_X86_64.to_le_bytes());
+        put(&mut data, coff + 2, &2u16.to_le_bytes());
+        let optional_size = (OPTIONAL_HEADER_SIZE + 6 * 8) as u16;
+        put(&mut data, coff + 16, &optional_size.to_le_bytes());
+
+        let opt = PE_OFFSET + COFF_HEADER_SIZE;
+        put(&mut data, opt, &PE32_PLUS_MAGIC.to_le_bytes());
+        put(&mut data, opt + 16, &0x1010u32.to_le_bytes());
+        put(&mut data, opt + 24, &IMAGE_BASE.to_le_bytes());
+        put(&mut data, opt + 32, &0x1000u32.to_le_bytes());
+        put(&mut data, opt + 56, &0x3000u32.to_le_bytes());
+        put(&mut data, opt + 60, &0x200u32.to_le_bytes());
+        put(&mut data, opt + 68, &10u16.to_le_bytes());
+        put(&mut data, opt + 108, &6u32.to_le_bytes());
+        let relocations = opt + OPTIONAL_HEADER_SIZE + 8 * BASE_RELOCATION_DIRECTORY;
+        put(&mut data, relocations, &0x2000u32.to_le_bytes());
+        put(&mut data, relocations + 4, &12u32.to_le_bytes());
+
+        let text = SECTIONS_OFFSET;
+        put(&mut data, text, b".text\0\0\0");
+        put(&mut data, text + 8, &0x20u32.to_le_bytes());
+        put(&mut data, text + 12, &0x1000u32.to_le_bytes());
+        put(&mut data, text + 16, &0x200u32.to_le_bytes());
+        put(&mut data, text + 20, &0x200u32.to_le_bytes());
+        put(&mut data, text + 36, &0x6000_0020u32.to_le_bytes());
+        put(&mut data, 0x200, &(IMAGE_BASE + 0x1000).to_le_bytes());
+
+        let reloc = SECTIONS_OFFSET + SECTION_HEADER_SIZE;
+        put(&mut data, reloc, b".reloc\0\0");
+        put(&mut data, reloc + 8, &12u32.to_le_bytes());
+        put(&mut data, reloc + 12, &0x2000u32.to_le_bytes());
+        put(&mut data, reloc + 16, &0x200u32.to_le_bytes());
+        put(&mut data, reloc + 20, &0x400u32.to_le_bytes());
+        put(&mut data, reloc + 36, &0x4200_0040u32.to_le_bytes());
+        put(&mut data, 0x400, &0x1000u32.to_le_bytes());
+        put(&mut data, 0x404, &12u32.to_le_bytes());
+        put(
+            &mut data,
+            0x408,
+            &((IMAGE_REL_BASED_DIR64 << 12) as u16).to_le_bytes(),
+        );
+        data
+    }
+
+    #[test]
+    fn parse_headers() {
+        let data = build_image();
+        let image = PeImage::parse(&data).unwrap();
+        assert_eq!(image.machine(), MACHINE_X86_64);
+        assert_eq!(image.subsystem(), 10);
+        assert_eq!(image.image_base(), IMAGE_BASE);
+        assert_eq!(image.size_of_image(), 0x3000);
+        assert_eq!(image.entry_point(), 0x1010);
+        assert!(image.is_relocatable());
+
+        let sections: [Section; 2] = [
+            image.sections().next().unwrap(),
+            image.sections().nth(1).unwrap(),
+        ];
+        assert_eq!(&sections[0].name, b".text\0\0\0");
+        assert!(sections[0].is_executable() && !sections[0].is_writable());
+        assert!(!sections[1].is_executable() && !sections[1].is_writable());
+
+        assert_eq!(
+            PeImage::parse(&data[..0x300]).err(),
+            Some(PeError::Truncated)
+        );
+        let mut bad = data;
+        bad[0] = b'X';
+        assert_eq!(PeImage::parse(&bad).err(), Some(PeError::InvalidHeader));
+        // The end of a section past the end of the file, or of the address
+        // space of a 32-bit target
+        let mut beyond = data;
+        put(
+            &mut beyond,
+            SECTIONS_OFFSET + 20,
+            &0xffff_ff00u32.to_le_bytes(),
+        );
+        assert_eq!(PeImage::parse(&beyond).err(), Some(PeError::Truncated));
+        let mut rom = data;
+        put(
+            &mut rom,
+            PE_OFFSET + COFF_HEADER_SIZE,
+            &0x107u16.to_le_bytes(),
+        );
+        assert_eq!(PeImage::parse(&rom).err(), Some(PeError::Unsupported));
+    }
+
+    #[test]
+    fn parse_pe32_headers() {
+        let mut data = build_image();
+        put(&mut data, PE_OFFSET + 4, &MACHINE_I386.to_le_bytes());
+        let opt = PE_OFFSET + COFF_HEADER_SIZE;
+        put(&mut data, opt, &PE32_MAGIC.to_le_bytes());
+        put(&mut data, opt + 28, &0x40_0000u32.to_le_bytes());
+        put(&mut data, opt + 92, &6u32.to_le_bytes());
+        let relocations = opt + PE32_OPTIONAL_HEADER_SIZE + 8 * BASE_RELOCATION_DIRECTORY;
+        put(&mut data, relocations, &0x2000u32.to_le_bytes());
+        put(&mut data, relocations + 4, &12u32.to_le_bytes());
+
+        let image = PeImage::parse(&data).unwrap();
+        assert_eq!(image.machine(), MACHINE_I386);
+        assert_eq!(image.image_base(), 0x40_0000);
+        assert_eq!(image.size_of_image(), 0x3000);
+        assert_eq!(image.entry_point(), 0x1010);
+        assert!(image.is_relocatable());
+    }
+
+    #[test]
+    fn load_and_relocate() {
+        let data = build_image();
+        let image = PeImage::parse(&data).unwrap();
+        let mut loaded = [0xff; 0x3000];
+        image.load_into(&mut loaded);
+        assert_eq!(&loaded[..2], b"MZ");
+        assert_eq!(read_u64(&loaded, 0x1000), Some(IMAGE_BASE + 0x1000));
+        // The section is zero-filled past the file contents
+        assert_eq!(loaded[0x1200], 0);
+        assert_eq!(loaded[0x2fff], 0);
+
+        let new_base = 0x8000_0000;
+        image.relocate(&mut loaded, new_base).unwrap();
+        assert_eq!(read_u64(&loaded, 0x1000), Some(new_base + 0x1000));
+        assert_eq!(read_u64(&loaded, 0x1008), Some(0));
+    }
+
+    #[test]
+    #[cfg(feature = "exts")]
+    fn loaded_headers() {
+        use crate::alloc_api::boxed::Box;
+
+        let data = build_image();
+        let image = PeImage::parse(&data).unwrap();
+        let loaded: &'static mut [u8] = Box::leak(Box::new([0; 0x3000]));
+        image.load_into(loaded);
+        let loaded = &*loaded;
+        assert!(matches!(
+            PeImage::parse_loaded(&loaded[..0x2000]),
+            Err(PeError::Truncated)
+        ));
+
+        let base = loaded.as_ptr() as u64;
+        let running = RunningImage {
+            image: PeImage::parse_loaded(loaded).unwrap(),
+            base,
+        };
+        assert_eq!(running.size(), 0x3000);
+        assert_eq!(running.entry_point(), base + 0x1010);
+        assert_eq!(running.sections().len(), 2);
+        let text = running.section(".text").unwrap();
+        assert!(text.is_executable());
+        assert_eq!(running.section_range(&text), base + 0x1000..base + 0x1020);
+        assert!(running.section(".tex").is_none());
+        let section = running.section_of(base + 0x2004).unwrap();
+        assert_eq!(&section.name, b".reloc\0\0");
+        assert!(running.section_of(base + 0x1020).is_none());
+        assert!(running.section_of(base).is_none());
+    }
+
+    /// Records the digested data
+    struct Recorder {
+        data: [u8; 0x800],
+        len: usize,
+    }
+
+    impl Digest for Recorder {
+        fn update(&mut self, data: &[u8]) {
+            self.data[self.len..self.len + data.len()].copy_from_slice(data);
+            self.len += data.len();
+        }
+    }
+
+    #[test]
+    fn authenticode_hash() {
+        let mut data = [0; 0x700];
+        data[..0x600].copy_from_slice(&build_image());
+        // The sections are stored in the opposite order of their headers
+        put(&mut data, SECTIONS_OFFSET + 20, &0x400u32.to_le_bytes());
+        put(
+            &mut data,
+            SECTIONS_OFFSET + SECTION_HEADER_SIZE + 20,
+            &0x200u32.to_le_bytes(),
+        );
+        // Extra data, followed by the certificate table
+        put(&mut data, 0x600, &[0xa5; 0x80]);
+        put(&mut data, 0x680, &[0x5a; 0x80]);
+        let opt = PE_OFFSET + COFF_HEADER_SIZE;
+        let security = opt + OPTIONAL_HEADER_SIZE + 8 * SECURITY_DIRECTORY;
+        put(&mut data, security, &0x680u32.to_le_bytes());
+        put(&mut data, security + 4, &0x80u32.to_le_bytes());
+        put(
+            &mut data,
+            opt + CHECKSUM_OFFSET,
+            &0x1234_5678u32.to_le_bytes(),
+        );
+
+        let image = PeImage::parse(&data).unwrap();
+        assert!(image.is_signed());
+        let mut recorder = Recorder {
+            data: [0; 0x800],
+            len: 0,
+        };
+        image.authenticode_hash(&mut recorder).unwrap();
+
+        let checksum = opt + CHECKSUM_OFFSET;
+        let mut expected = [0; 0x800];
+        let mut len = 0;
+        for range in &[
+            0..checksum,
+            checksum + 4..security,
+            security + 8..0x200,
+            0x200..0x400,
+            0x400..0x600,
+            0x600..0x680,
+        ] {
+            expected[len..len + range.len()].copy_from_slice(&data[range.clone()]);
+            len += range.len();
+        }
+        assert_eq!(recorder.len, len);
+        assert_eq!(&recorder.data[..len], &expected[..len]);
+        assert_eq!(
+            image.authenticode_sha256(),
+            Ok(crate::digest::sha256(&expected[..len]))
+        );
+
+        // Neither the checksum nor the certificates are covered
+        let mut resigned = data;
+        put(&mut resigned, checksum, &0u32.to_le_bytes());
+        put(&mut resigned, 0x680, &[0; 0x80]);
+        assert_eq!(
+            PeImage::parse(&resigned).unwrap().authenticode_sha256(),
+            image.authenticode_sha256()
+        );
+    }
+}
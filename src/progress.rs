@@ -0,0 +1,309 @@
+//! Progress reporting for long operations.
+//!
+//! Operations such as firmware updates or file copies take long enough that
+//! the user should be shown they are progressing. The `ProgressReporter`
+//! trait lets these operations report their progress without knowing how it
+//! is displayed:
+//!
+//! - `TextProgress` prints a percentage, or a spinner when the amount of work
+//!   is unknown, to a text output such as the console.
+//! - `GopProgress` draws a progress bar with the graphics output protocol.
+//! - `FmpProgress` forwards the progress to the callback which is given to
+//!   the `SetImage` function of the Firmware Management Protocol.
+
+use crate::proto::console::gop::{BltOp, BltPixel, GraphicsOutput};
+use crate::{Result, Status};
+use core::fmt::{self, Write};
+
+/// Destination of the progress of an operation
+pub trait ProgressReporter {
+    /// Report that `done` units of work out of `total` have been done.
+    ///
+    /// A `total` of zero means that the amount of work is unknown.
+    fn update(&mut self, done: u64, total: u64) -> Result;
+
+    /// Report that the operation is over, successfully or not.
+    fn finish(&mut self) -> Result {
+        Status::SUCCESS.into()
+    }
+}
+
+impl<R: ProgressReporter + ?Sized> ProgressReporter for &mut R {
+    fn update(&mut self, done: u64, total: u64) -> Result {
+        (**self).update(done, total)
+    }
+
+    fn finish(&mut self) -> Result {
+        (**self).finish()
+    }
+}
+
+/// Percentage of the work which has been done, or `None` if the amount of
+/// work is unknown
+pub fn percent(done: u64, total: u64) -> Option<u8> {
+    if total == 0 {
+        None
+    } else {
+        Some((u128::from(done.min(total)) * 100 / u128::from(total)) as u8)
+    }
+}
+
+/// Characters of the spinner shown when the amount of work is unknown
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Progress printed as text, such as `Copying... 42%`
+///
+/// The line is redrawn with a carriage return on each change, so nothing else
+/// should be printed until `finish` is called.
+pub struct TextProgress<'label, W: Write> {
+    writer: W,
+    label: &'label str,
+    /// Last percentage or spinner position which was printed
+    last: Option<usize>,
+    spinner: usize,
+}
+
+impl<'label, W: Write> TextProgress<'label, W> {
+    /// Print the progress to `writer`, after `label`
+    pub fn new(writer: W, label: &'label str) -> Self {
+        TextProgress {
+            writer,
+            label,
+            last: None,
+            spinner: 0,
+        }
+    }
+
+    fn draw(&mut self, done: u64, total: u64) -> fmt::Result {
+        match percent(done, total) {
+            Some(percent) => {
+                if self.last == Some(percent.into()) {
+                    return Ok(());
+                }
+                self.last = Some(percent.into());
+                write!(self.writer, "\r{} {:3}%", self.label, percent)
+            }
+            None => {
+                // Only turn the spinner when some work was done
+                if self.last == Some(done as usize) {
+                    return Ok(());
+                }
+                self.last = Some(done as usize);
+                self.spinner = (self.spinner + 1) % SPINNER.len();
+                write!(self.writer, "\r{} {}", self.label, SPINNER[self.spinner])
+            }
+        }
+    }
+}
+
+impl<'label, W: Write> ProgressReporter for TextProgress<'label, W> {
+    fn update(&mut self, done: u64, total: u64) -> Result {
+        fmt_status(self.draw(done, total))
+    }
+
+    fn finish(&mut self) -> Result {
+        fmt_status(self.writer.write_str("\n"))
+    }
+}
+
+fn fmt_status(result: fmt::Result) -> Result {
+    match result {
+        Ok(()) => Status::SUCCESS,
+        Err(fmt::Error) => Status::DEVICE_ERROR,
+    }
+    .into()
+}
+
+/// Progress bar drawn on the screen
+///
+/// The bar is a frame of the foreground color, which fills up from the left
+/// as the work is done. It stays empty while the amount of work is unknown.
+pub struct GopProgress<'gop, 'boot> {
+    gop: &'gop mut GraphicsOutput<'boot>,
+    origin: (usize, usize),
+    dims: (usize, usize),
+    foreground: BltPixel,
+    background: BltPixel,
+    /// Width of the filled part of the bar, or `None` before the first draw
+    filled: Option<usize>,
+}
+
+impl<'gop, 'boot> GopProgress<'gop, 'boot> {
+    /// Draw a white progress bar, whose top-left corner is at `origin`,
+    /// on a black background
+    pub fn new(
+        gop: &'gop mut GraphicsOutput<'boot>,
+        origin: (usize, usize),
+        dims: (usize, usize),
+    ) -> Self {
+        GopProgress {
+            gop,
+            origin,
+            dims,
+            foreground: BltPixel::new(0xff, 0xff, 0xff),
+            background: BltPixel::new(0, 0, 0),
+            filled: None,
+        }
+    }
+
+    /// Draw a progress bar, centered horizontally in the lower quarter of
+    /// the screen, which is where boot logos usually leave free space
+    pub fn centered(gop: &'gop mut GraphicsOutput<'boot>, dims: (usize, usize)) -> Self {
+        let (width, height) = gop.current_mode_info().resolution();
+        let dims = (dims.0.min(width), dims.1.min(height));
+        let origin = ((width - dims.0) / 2, (height * 3 / 4).min(height - dims.1));
+        Self::new(gop, origin, dims)
+    }
+
+    /// Change the colors of the bar and of its background, before it is
+    /// drawn
+    pub fn with_colors(mut self, foreground: BltPixel, background: BltPixel) -> Self {
+        self.foreground = foreground;
+        self.background = background;
+        self
+    }
+
+    fn fill(&mut self, color: BltPixel, dest: (usize, usize), dims: (usize, usize)) -> Result {
+        if dims.0 == 0 || dims.1 == 0 {
+            return Status::SUCCESS.into();
+        }
+        self.gop.blt(BltOp::VideoFill { color, dest, dims })
+    }
+}
+
+impl<'gop, 'boot> ProgressReporter for GopProgress<'gop, 'boot> {
+    fn update(&mut self, done: u64, total: u64) -> Result {
+        let (x, y) = self.origin;
+        let (width, height) = self.dims;
+        // The inside of the frame, with a gap of one pixel around the fill
+        let inner = (width.saturating_sub(4), height.saturating_sub(4));
+
+        if self.filled.is_none() {
+            let frame = (width.saturating_sub(2), height.saturating_sub(2));
+            self.fill(self.foreground, (x, y), (width, height))?.log();
+            self.fill(self.background, (x + 1, y + 1), frame)?.log();
+            self.filled = Some(0);
+        }
+        let filled = filled_width(inner.0, done, total);
+        let previous = self.filled.unwrap_or(0);
+        if filled > previous {
+            self.fill(
+                self.foreground,
+                (x + 2 + previous, y + 2),
+                (filled - previous, inner.1),
+            )?
+            .log();
+            self.filled = Some(filled);
+        }
+        Status::SUCCESS.into()
+    }
+}
+
+/// Width of the filled part of a progress bar of `width` pixels
+fn filled_width(width: usize, done: u64, total: u64) -> usize {
+    if total == 0 {
+        0
+    } else {
+        (u128::from(done.min(total)) * width as u128 / u128::from(total)) as usize
+    }
+}
+
+/// Callback reporting the progress of a firmware update
+/// (`EFI_FIRMWARE_MANAGEMENT_UPDATE_IMAGE_PROGRESS`)
+///
+/// `completion` goes from 1 to 100. The update is aborted if the callback
+/// returns an error.
+pub type FmpProgressCallback = extern "efiapi" fn(completion: usize) -> Status;
+
+/// Progress forwarded to the progress callback of the Firmware Management
+/// Protocol
+///
+/// Drivers implementing `SetImage` receive such a callback, which they must
+/// call as the update progresses. The callback is only called when the
+/// percentage changes, and never with a percentage of 0, which the
+/// specification does not allow.
+pub struct FmpProgress {
+    callback: FmpProgressCallback,
+    last: usize,
+}
+
+impl FmpProgress {
+    /// Forward the progress to `callback`
+    pub fn new(callback: FmpProgressCallback) -> Self {
+        FmpProgress { callback, last: 0 }
+    }
+}
+
+impl ProgressReporter for FmpProgress {
+    fn update(&mut self, done: u64, total: u64) -> Result {
+        let completion = match percent(done, total) {
+            Some(percent) => usize::from(percent).max(1),
+            None => return Status::SUCCESS.into(),
+        };
+        if completion == self.last {
+            return Status::SUCCESS.into();
+        }
+        self.last = completion;
+        (self.callback)(completion).into()
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::string::String;
+    use crate::ResultExt;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn percentages() {
+        assert_eq!(percent(0, 0), None);
+        assert_eq!(percent(1, 3), Some(33));
+        assert_eq!(percent(5, 4), Some(100));
+        assert_eq!(percent(u64::MAX - 1, u64::MAX), Some(99));
+        assert_eq!(filled_width(100, 1, 2), 50);
+        assert_eq!(filled_width(100, 1, 0), 0);
+    }
+
+    #[test]
+    fn text_progress() {
+        let mut out = String::new();
+        let mut progress = TextProgress::new(&mut out, "Copying");
+        progress.update(1, 4).unwrap_success();
+        progress.update(1, 4).unwrap_success();
+        progress.update(4, 4).unwrap_success();
+        progress.update(10, 0).unwrap_success();
+        progress.update(10, 0).unwrap_success();
+        progress.update(20, 0).unwrap_success();
+        progress.finish().unwrap_success();
+        assert_eq!(out, "\rCopying  25%\rCopying 100%\rCopying /\rCopying -\n");
+    }
+
+    static LAST_COMPLETION: AtomicUsize = AtomicUsize::new(0);
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    extern "efiapi" fn callback(completion: usize) -> Status {
+        LAST_COMPLETION.store(completion, Ordering::Relaxed);
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        if completion > 50 {
+            Status::ABORTED
+        } else {
+            Status::SUCCESS
+        }
+    }
+
+    #[test]
+    fn fmp_progress() {
+        let mut progress = FmpProgress::new(callback);
+        progress.update(0, 10).unwrap_success();
+        assert_eq!(LAST_COMPLETION.load(Ordering::Relaxed), 1);
+        progress.update(0, 10).unwrap_success();
+        progress.update(5, 10).unwrap_success();
+        assert_eq!(LAST_COMPLETION.load(Ordering::Relaxed), 50);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            progress.update(6, 10).unwrap_err().status(),
+            Status::ABORTED
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! ACPI Table protocol.
+//!
+//! This protocol adds tables to the ACPI tables published by the firmware,
+//! before the OS reads them, such as an SSDT overlay describing extra
+//! devices:
+//!
+//! ```no_run
+//! # use uefi::proto::acpi::AcpiTable;
+//! # fn f(acpi: &mut AcpiTable, ssdt: &[u8]) -> uefi::Result {
+//! let key = acpi.install_table(ssdt)?.log();
+//! // The table can be removed again, as long as the key is kept
+//! acpi.uninstall_table(key)?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! To alter a table of the firmware, copy it from `table::acpi`, modify the
+//! copy, and install it after fixing it with `table::acpi::update_checksum`.
+
+use crate::proto::Protocol;
+use crate::table::acpi::SDT_HEADER_SIZE;
+use crate::{unsafe_guid, Result, Status};
+use core::convert::TryInto;
+use core::ffi::c_void;
+
+/// The key of a table installed by `AcpiTable::install_table`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct TableKey(usize);
+
+/// The ACPI Table protocol.
+#[repr(C)]
+#[unsafe_guid("ffe06bdd-6107-46a6-7bb2-5a9c7ec5275c")]
+#[derive(Protocol)]
+pub struct AcpiTable {
+    install_acpi_table: unsafe extern "efiapi" fn(
+        this: *const AcpiTable,
+        acpi_table_buffer: *const c_void,
+        acpi_table_buffer_size: usize,
+        table_key: *mut usize,
+    ) -> Status,
+    uninstall_acpi_table:
+        unsafe extern "efiapi" fn(this: *const AcpiTable, table_key: usize) -> Status,
+}
+
+impl AcpiTable {
+    /// Install a copy of the table in `table`, and list it in the root tables.
+    ///
+    /// The checksum of the copy is updated by the firmware. Installing a FADT
+    /// or a FACS replaces the existing one.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The length in the header of the
+    ///                                      table is not the size of `table`
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The copy could not be allocated
+    /// * `uefi::Status::ACCESS_DENIED`      The table cannot be installed, as
+    ///                                      its signature is reserved
+    pub fn install_table(&mut self, table: &[u8]) -> Result<TableKey> {
+        let length = table
+            .get(4..8)
+            .map(|length| u32::from_le_bytes(length.try_into().unwrap()));
+        if table.len() < SDT_HEADER_SIZE || length != Some(table.len() as u32) {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        let mut key = 0;
+        unsafe { (self.install_acpi_table)(self, table.as_ptr().cast(), table.len(), &mut key) }
+            .into_with_val(|| TableKey(key))
+    }
+
+    /// Remove a table installed by `install_table`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  No table has this key
+    pub fn uninstall_table(&mut self, key: TableKey) -> Result {
+        unsafe { (self.uninstall_acpi_table)(self, key.0) }.into()
+    }
+}
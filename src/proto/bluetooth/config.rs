@@ -0,0 +1,208 @@
+//! Bluetooth Config protocol.
+
+#[cfg(feature = "exts")]
+use super::{get_data_vec, read_items};
+use super::{read_result, trim_name, BluetoothAddress, ClassOfDevice, ConfigDataType};
+use crate::proto::Protocol;
+#[cfg(feature = "exts")]
+use crate::{alloc_api::vec::Vec, ResultExt};
+use crate::{unsafe_guid, Result, Status};
+use core::ffi::c_void;
+use core::{fmt, ptr};
+
+/// Largest size of the name of a device
+pub const MAX_NAME_SIZE: usize = 248;
+
+/// A device found by a scan (`EFI_BLUETOOTH_SCAN_CALLBACK_INFORMATION`)
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ScanInfo {
+    /// Address of the device
+    pub address: BluetoothAddress,
+    /// State of the device, as defined by the Bluetooth stack
+    pub remote_device_state: u8,
+    /// Class of the device
+    pub class_of_device: ClassOfDevice,
+    name: [u8; MAX_NAME_SIZE],
+}
+
+impl ScanInfo {
+    /// The name of the device, which is usually UTF-8
+    pub fn name(&self) -> &[u8] {
+        trim_name(&self.name)
+    }
+}
+
+impl fmt::Debug for ScanInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScanInfo")
+            .field("address", &self.address)
+            .field("remote_device_state", &self.remote_device_state)
+            .field("class_of_device", &self.class_of_device)
+            .field("name", &core::str::from_utf8(self.name()))
+            .finish()
+    }
+}
+
+type ScanCallback = unsafe extern "efiapi" fn(
+    this: *mut BluetoothConfig,
+    context: *mut c_void,
+    callback_info: *const ScanInfo,
+) -> Status;
+
+/// The Bluetooth Config protocol, which configures a classic (BR/EDR)
+/// controller.
+#[repr(C)]
+#[unsafe_guid("62960cf3-40ff-4263-a77c-dfde9e37d7c3")]
+#[derive(Protocol)]
+pub struct BluetoothConfig {
+    init: unsafe extern "efiapi" fn(this: *mut BluetoothConfig) -> Status,
+    scan: unsafe extern "efiapi" fn(
+        this: *mut BluetoothConfig,
+        rescan: bool,
+        scan_type: u8,
+        callback: ScanCallback,
+        context: *mut c_void,
+    ) -> Status,
+    connect: unsafe extern "efiapi" fn(
+        this: *mut BluetoothConfig,
+        bd_addr: *const BluetoothAddress,
+    ) -> Status,
+    disconnect: unsafe extern "efiapi" fn(
+        this: *mut BluetoothConfig,
+        bd_addr: *const BluetoothAddress,
+        reason: u8,
+    ) -> Status,
+    get_data: unsafe extern "efiapi" fn(
+        this: *mut BluetoothConfig,
+        data_type: ConfigDataType,
+        data_size: *mut usize,
+        data: *mut c_void,
+    ) -> Status,
+    set_data: usize,
+    get_remote_data: unsafe extern "efiapi" fn(
+        this: *mut BluetoothConfig,
+        data_type: ConfigDataType,
+        bd_addr: *const BluetoothAddress,
+        data_size: *mut usize,
+        data: *mut c_void,
+    ) -> Status,
+    register_pin_callback: usize,
+    register_get_link_key_callback: usize,
+    register_set_link_key_callback: usize,
+    register_link_connect_complete_callback: usize,
+}
+
+impl BluetoothConfig {
+    /// Initialize the controller.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The controller could not be initialized
+    pub fn init(&mut self) -> Result {
+        unsafe { (self.init)(self) }.into()
+    }
+
+    /// Scan for devices, or only return the devices which are already known
+    /// if `rescan` is `false`.
+    ///
+    /// The devices which are found are afterwards listed by
+    /// `available_devices`. `scan_type` is 0 for the default scan.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The scan failed
+    pub fn scan(&mut self, rescan: bool, scan_type: u8) -> Result {
+        unsafe { (self.scan)(self, rescan, scan_type, ignore_device, ptr::null_mut()) }.into()
+    }
+
+    /// Connect to the device at `address`, pairing with it if necessary.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`     The device is not known
+    /// * `uefi::Status::DEVICE_ERROR`  The connection failed
+    pub fn connect(&mut self, address: &BluetoothAddress) -> Result {
+        unsafe { (self.connect)(self, address) }.into()
+    }
+
+    /// Disconnect from the device at `address`, for the HCI `reason`, like
+    /// 0x13 when the user ended the connection.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`     The device is not connected
+    pub fn disconnect(&mut self, address: &BluetoothAddress, reason: u8) -> Result {
+        unsafe { (self.disconnect)(self, address, reason) }.into()
+    }
+
+    /// Read data of the controller into `buffer`, and return it.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  `buffer` is too small; the required
+    ///                                     size is returned
+    /// * `uefi::Status::UNSUPPORTED`       The data is not available
+    pub fn get_data<'buf>(
+        &mut self,
+        data_type: ConfigDataType,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Option<usize>> {
+        let mut size = buffer.len();
+        let status =
+            unsafe { (self.get_data)(self, data_type, &mut size, buffer.as_mut_ptr().cast()) };
+        read_result(status, buffer, size)
+    }
+
+    /// Read data of the device at `address` into `buffer`, and return it.
+    ///
+    /// # Errors
+    ///
+    /// See `get_data`.
+    pub fn get_remote_data<'buf>(
+        &mut self,
+        data_type: ConfigDataType,
+        address: &BluetoothAddress,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Option<usize>> {
+        let mut size = buffer.len();
+        let status = unsafe {
+            (self.get_remote_data)(
+                self,
+                data_type,
+                address,
+                &mut size,
+                buffer.as_mut_ptr().cast(),
+            )
+        };
+        read_result(status, buffer, size)
+    }
+
+    /// The addresses of the devices paired with the controller
+    #[cfg(feature = "exts")]
+    pub fn paired_devices(&mut self) -> Result<Vec<BluetoothAddress>> {
+        self.get_data_vec(ConfigDataType::CONTROLLER_STORED_PAIRED_DEVICE_LIST)
+            .map_inner(|bytes| read_items(&bytes))
+    }
+
+    /// The devices found by the last scan
+    #[cfg(feature = "exts")]
+    pub fn available_devices(&mut self) -> Result<Vec<ScanInfo>> {
+        self.get_data_vec(ConfigDataType::AVAILABLE_DEVICE_LIST)
+            .map_inner(|bytes| read_items(&bytes))
+    }
+
+    #[cfg(feature = "exts")]
+    fn get_data_vec(&mut self, data_type: ConfigDataType) -> Result<Vec<u8>> {
+        get_data_vec(|size, data| unsafe { (self.get_data)(self, data_type, size, data) })
+    }
+}
+
+/// Scan callback for the devices which are listed after the scan
+unsafe extern "efiapi" fn ignore_device(
+    _this: *mut BluetoothConfig,
+    _context: *mut c_void,
+    _callback_info: *const ScanInfo,
+) -> Status {
+    Status::SUCCESS
+}
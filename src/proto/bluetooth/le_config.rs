@@ -0,0 +1,209 @@
+//! Bluetooth LE Config protocol.
+
+use super::{advertised_name, read_result, BluetoothLeAddress, ConfigDataType};
+#[cfg(feature = "exts")]
+use super::{get_data_vec, read_items};
+use crate::proto::Protocol;
+use crate::table::boot::BootServices;
+#[cfg(feature = "exts")]
+use crate::{alloc_api::vec::Vec, ResultExt};
+use crate::{unsafe_guid, Result, Status};
+use core::ffi::c_void;
+use core::{fmt, ptr, slice};
+
+/// A device found by a scan (`EFI_BLUETOOTH_LE_SCAN_CALLBACK_INFORMATION`)
+#[repr(C)]
+pub struct LeScanInfo {
+    /// Address of the device
+    pub address: BluetoothLeAddress,
+    /// Address to which the advertisement was directed, if any
+    pub direct_address: BluetoothLeAddress,
+    /// State of the device, as defined by the Bluetooth stack
+    pub remote_device_state: u8,
+    /// Strength of the signal, in dBm
+    pub rssi: i8,
+    advertisement_data_size: usize,
+    advertisement_data: *const u8,
+}
+
+impl LeScanInfo {
+    /// The advertising data of the device, which can be parsed with
+    /// `advertising_structures`
+    pub fn advertisement_data(&self) -> &[u8] {
+        if self.advertisement_data.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.advertisement_data, self.advertisement_data_size) }
+        }
+    }
+
+    /// The name which the device advertises
+    pub fn name(&self) -> Option<&[u8]> {
+        advertised_name(self.advertisement_data())
+    }
+}
+
+impl fmt::Debug for LeScanInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LeScanInfo")
+            .field("address", &self.address)
+            .field("direct_address", &self.direct_address)
+            .field("remote_device_state", &self.remote_device_state)
+            .field("rssi", &self.rssi)
+            .field("name", &self.name().map(core::str::from_utf8))
+            .finish()
+    }
+}
+
+type LeScanCallback = unsafe extern "efiapi" fn(
+    this: *mut BluetoothLeConfig,
+    context: *mut c_void,
+    callback_info: *const LeScanInfo,
+) -> Status;
+
+/// The Bluetooth LE Config protocol, which configures a Low Energy
+/// controller.
+#[repr(C)]
+#[unsafe_guid("8f76da58-1f99-4275-a4ec-4756515b1ce8")]
+#[derive(Protocol)]
+pub struct BluetoothLeConfig {
+    init: unsafe extern "efiapi" fn(this: *mut BluetoothLeConfig) -> Status,
+    scan: unsafe extern "efiapi" fn(
+        this: *mut BluetoothLeConfig,
+        rescan: bool,
+        timeout: u32,
+        scan_parameter: *const c_void,
+        callback: LeScanCallback,
+        context: *mut c_void,
+    ) -> Status,
+    connect: unsafe extern "efiapi" fn(
+        this: *mut BluetoothLeConfig,
+        auto_reconnect: bool,
+        do_bonding: bool,
+        connect_parameter: *const c_void,
+        bd_addr: *const BluetoothLeAddress,
+    ) -> Status,
+    disconnect: unsafe extern "efiapi" fn(
+        this: *mut BluetoothLeConfig,
+        bd_addr: *const BluetoothLeAddress,
+        reason: u8,
+    ) -> Status,
+    get_data: unsafe extern "efiapi" fn(
+        this: *mut BluetoothLeConfig,
+        data_type: ConfigDataType,
+        data_size: *mut usize,
+        data: *mut c_void,
+    ) -> Status,
+    set_data: usize,
+    get_remote_data: usize,
+    register_smp_auth_callback: usize,
+    send_smp_auth_data: usize,
+    register_smp_get_data_callback: usize,
+    register_smp_set_data_callback: usize,
+    register_link_connect_complete_callback: usize,
+}
+
+impl BluetoothLeConfig {
+    /// Initialize the controller.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The controller could not be initialized
+    pub fn init(&mut self) -> Result {
+        unsafe { (self.init)(self) }.into()
+    }
+
+    /// Scan for devices during `timeout` milliseconds with the default
+    /// parameters, calling `found` for each device which advertises itself,
+    /// or only for the devices which are already known if `rescan` is
+    /// `false`.
+    ///
+    /// The scan runs in the background of the firmware, and this function
+    /// waits for it to end before returning.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The scan failed
+    pub fn scan(
+        &mut self,
+        bt: &BootServices,
+        rescan: bool,
+        timeout: u32,
+        found: &mut dyn FnMut(&LeScanInfo),
+    ) -> Result {
+        let mut found = found;
+        let context = &mut found as *mut &mut dyn FnMut(&LeScanInfo) as *mut c_void;
+        let status = unsafe { (self.scan)(self, rescan, timeout, ptr::null(), report, context) };
+        if status.is_success() {
+            // The callback must not be called once `found` is gone
+            bt.stall(timeout as usize * 1000);
+        }
+        status.into()
+    }
+
+    /// Connect to the device at `address`, pairing with it if `bond` is
+    /// `true`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`     The device is not known
+    /// * `uefi::Status::DEVICE_ERROR`  The connection failed
+    pub fn connect(&mut self, address: &BluetoothLeAddress, bond: bool) -> Result {
+        unsafe { (self.connect)(self, false, bond, ptr::null(), address) }.into()
+    }
+
+    /// Disconnect from the device at `address`, for the HCI `reason`, like
+    /// 0x13 when the user ended the connection.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`     The device is not connected
+    pub fn disconnect(&mut self, address: &BluetoothLeAddress, reason: u8) -> Result {
+        unsafe { (self.disconnect)(self, address, reason) }.into()
+    }
+
+    /// Read data of the controller into `buffer`, and return it.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  `buffer` is too small; the required
+    ///                                     size is returned
+    /// * `uefi::Status::UNSUPPORTED`       The data is not available
+    pub fn get_data<'buf>(
+        &mut self,
+        data_type: ConfigDataType,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Option<usize>> {
+        let mut size = buffer.len();
+        let status =
+            unsafe { (self.get_data)(self, data_type, &mut size, buffer.as_mut_ptr().cast()) };
+        read_result(status, buffer, size)
+    }
+
+    /// The addresses of the devices paired with the controller
+    #[cfg(feature = "exts")]
+    pub fn paired_devices(&mut self) -> Result<Vec<BluetoothLeAddress>> {
+        get_data_vec(|size, data| unsafe {
+            (self.get_data)(
+                self,
+                ConfigDataType::CONTROLLER_STORED_PAIRED_DEVICE_LIST,
+                size,
+                data,
+            )
+        })
+        .map_inner(|bytes| read_items(&bytes))
+    }
+}
+
+unsafe extern "efiapi" fn report(
+    _this: *mut BluetoothLeConfig,
+    context: *mut c_void,
+    callback_info: *const LeScanInfo,
+) -> Status {
+    if context.is_null() || callback_info.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let found = &mut *(context as *mut &mut dyn FnMut(&LeScanInfo));
+    found(&*callback_info);
+    Status::SUCCESS
+}
@@ -0,0 +1,243 @@
+//! Bluetooth protocols.
+//!
+//! The Bluetooth stack of the firmware exposes a configuration protocol for
+//! each kind of controller: [`BluetoothConfig`] for classic (BR/EDR)
+//! controllers, and [`BluetoothLeConfig`] for Low Energy controllers. Both
+//! are installed on the handle of the controller, and list the devices which
+//! are paired with it or which were found by a scan.
+//!
+//! [`BluetoothConfig`]: config::BluetoothConfig
+//! [`BluetoothLeConfig`]: le_config::BluetoothLeConfig
+
+#[cfg(feature = "exts")]
+use crate::alloc_api::vec::Vec;
+use crate::result::Error;
+use crate::{Result, Status};
+#[cfg(feature = "exts")]
+use core::ffi::c_void;
+use core::fmt;
+
+pub mod config;
+pub mod le_config;
+
+/// Address of a device (`BLUETOOTH_ADDRESS`)
+///
+/// The bytes are stored from the least significant to the most significant,
+/// which is the reverse of the usual notation.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Eq, PartialEq)]
+pub struct BluetoothAddress(pub [u8; 6]);
+
+impl fmt::Debug for BluetoothAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for BluetoothAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            g, e, d, c, b, a
+        )
+    }
+}
+
+/// Address of a Low Energy device, with its type (`BLUETOOTH_LE_ADDRESS`)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct BluetoothLeAddress {
+    /// The address
+    pub address: BluetoothAddress,
+    /// 0 for a public address, 1 for a random address
+    pub address_type: u8,
+}
+
+/// Class of a classic device (`BLUETOOTH_CLASS_OF_DEVICE`)
+///
+/// The 24 bits of the class are stored from the least significant byte.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClassOfDevice(pub [u8; 3]);
+
+impl ClassOfDevice {
+    fn bits(self) -> u32 {
+        let [low, middle, high] = self.0;
+        u32::from_le_bytes([low, middle, high, 0])
+    }
+
+    /// The services which the device provides, like audio or networking
+    pub fn major_service_class(self) -> u16 {
+        (self.bits() >> 13) as u16
+    }
+
+    /// The kind of device, like a computer (1), a phone (2) or a peripheral
+    /// such as a keyboard (5)
+    pub fn major_device_class(self) -> u8 {
+        ((self.bits() >> 8) & 0x1f) as u8
+    }
+
+    /// The kind of device within its major class
+    pub fn minor_device_class(self) -> u8 {
+        ((self.bits() >> 2) & 0x3f) as u8
+    }
+}
+
+newtype_enum! {
+    /// Kind of data of a controller, or of a remote device
+    /// (`EFI_BLUETOOTH_CONFIG_DATA_TYPE`)
+    pub enum ConfigDataType: u32 => {
+        /// Unknown data
+        UNKNOWN = 0,
+        /// Name of the device
+        DEVICE_NAME = 1,
+        /// Class of the device
+        CLASS_OF_DEVICE = 2,
+        /// State of a remote device
+        REMOTE_DEVICE_STATE = 3,
+        /// Service discovery records of a remote device
+        SDP_INFO = 4,
+        /// Address of the controller
+        BD_ADDR = 5,
+        /// Whether the controller can be discovered
+        DISCOVERABLE_STATE = 6,
+        /// Addresses of the devices paired with the controller
+        CONTROLLER_STORED_PAIRED_DEVICE_LIST = 7,
+        /// Devices found by the last scan
+        AVAILABLE_DEVICE_LIST = 8,
+        /// Random address of the controller
+        RANDOM_ADDRESS = 9,
+        /// Signal strength of a remote device
+        RSSI = 10,
+        /// Advertisement data of a remote device
+        ADVERTISEMENT_DATA = 11,
+        /// Input and output capabilities used to pair
+        IO_CAPABILITY = 12,
+        /// Whether out-of-band pairing data is available
+        OOB_DATA_FLAG = 13,
+        /// Kind of the pairing key
+        KEY_TYPE = 14,
+        /// Size of the encryption key
+        ENC_KEY_SIZE = 15,
+    }
+}
+
+/// Advertising data type of the shortened name of a device
+pub const AD_TYPE_SHORTENED_LOCAL_NAME: u8 = 0x08;
+
+/// Advertising data type of the complete name of a device
+pub const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+
+/// Iterate over the structures of Low Energy advertising data, as
+/// `(type, data)` pairs
+///
+/// The iteration stops at the first malformed structure.
+pub fn advertising_structures(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut rest = data;
+    core::iter::from_fn(move || {
+        let (&len, tail) = rest.split_first()?;
+        let len = usize::from(len);
+        // A zero length ends the significant part of the data
+        if len == 0 || len > tail.len() {
+            rest = &[];
+            return None;
+        }
+        let (structure, tail) = tail.split_at(len);
+        rest = tail;
+        Some((structure[0], &structure[1..]))
+    })
+}
+
+/// The name of a device in its advertising data, either complete or
+/// shortened
+pub fn advertised_name(data: &[u8]) -> Option<&[u8]> {
+    let mut shortened = None;
+    for (ad_type, value) in advertising_structures(data) {
+        match ad_type {
+            AD_TYPE_COMPLETE_LOCAL_NAME => return Some(value),
+            AD_TYPE_SHORTENED_LOCAL_NAME => shortened = Some(value),
+            _ => {}
+        }
+    }
+    shortened
+}
+
+/// The part of a nul-padded name before the first nul
+fn trim_name(name: &[u8]) -> &[u8] {
+    let len = name
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(name.len());
+    &name[..len]
+}
+
+/// The data read into `buffer`, `size` being the size given by the firmware
+fn read_result(status: Status, buffer: &[u8], size: usize) -> Result<&[u8], Option<usize>> {
+    if status == Status::BUFFER_TOO_SMALL {
+        Err(Error::new(status, Some(size)))
+    } else {
+        status.into_with(|| &buffer[..size.min(buffer.len())], |_| None)
+    }
+}
+
+/// Read data whose size is not known with `get_data`, which is given the
+/// size and the address of a buffer
+#[cfg(feature = "exts")]
+fn get_data_vec(mut get_data: impl FnMut(&mut usize, *mut c_void) -> Status) -> Result<Vec<u8>> {
+    let mut size = 0;
+    let mut buffer = Vec::<u8>::new();
+    loop {
+        let status = get_data(&mut size, buffer.as_mut_ptr().cast());
+        if status == Status::BUFFER_TOO_SMALL && size > buffer.len() {
+            buffer.resize(size, 0);
+        } else {
+            buffer.truncate(size);
+            return status.into_with_val(|| buffer);
+        }
+    }
+}
+
+/// Split `bytes` into values of type `T`, which are not aligned
+#[cfg(feature = "exts")]
+fn read_items<T: Copy>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(core::mem::size_of::<T>())
+        .map(|item| unsafe { item.as_ptr().cast::<T>().read_unaligned() })
+        .collect()
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advertising_data() {
+        let data = [
+            2, 0x01, 0x06, // Flags
+            4, 0x08, b'K', b'b', b'd', // Shortened name
+            6, 0x09, b'K', b'b', b'd', b' ', b'2', // Complete name
+            0, 0, 0, // Padding
+        ];
+        let structures: [(u8, &[u8]); 3] = [(0x01, &[0x06]), (0x08, b"Kbd"), (0x09, b"Kbd 2")];
+        assert!(advertising_structures(&data).eq(structures.iter().copied()));
+        assert_eq!(advertised_name(&data), Some(&b"Kbd 2"[..]));
+        assert_eq!(advertised_name(&data[..8]), Some(&b"Kbd"[..]));
+        // Truncated structure
+        assert_eq!(advertising_structures(&[5, 0x09, b'a']).count(), 0);
+
+        let address = BluetoothAddress([0x56, 0x34, 0x12, 0xef, 0xcd, 0xab]);
+        assert_eq!(
+            crate::alloc_api::format!("{}", address),
+            "AB:CD:EF:12:34:56"
+        );
+
+        // A keyboard: peripheral (5), with a minor class of 0x10
+        let class = ClassOfDevice([0x40, 0x05, 0x00]);
+        assert_eq!(class.major_device_class(), 5);
+        assert_eq!(class.minor_device_class(), 0x10);
+        assert_eq!(class.major_service_class(), 0);
+        assert_eq!(trim_name(b"Phone\0\0"), b"Phone");
+    }
+}
@@ -55,7 +55,7 @@
 //! avoid tearing with animations.
 
 use crate::proto::Protocol;
-use crate::{unsafe_guid, Completion, Result, Status};
+use crate::{unsafe_guid, Completion, Result, ResultExt, Status};
 use core::marker::PhantomData;
 use core::mem;
 use core::ptr;
@@ -126,6 +126,42 @@ impl<'boot> GraphicsOutput<'boot> {
         (self.set_mode)(self, mode.index).into()
     }
 
+    /// Sets the first mode with the given resolution and pixel format.
+    ///
+    /// On success, the mode which was selected is returned. If the device does
+    /// not support any such mode, the `NOT_FOUND` error is returned.
+    pub fn set_mode_matching(
+        &mut self,
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+    ) -> Result<Mode> {
+        let mode = self.modes().map(Completion::log).find(|mode| {
+            let info = mode.info();
+            info.resolution() == (width, height) && info.pixel_format() == format
+        });
+        self.set_found_mode(mode)
+    }
+
+    /// Sets the mode with the highest resolution supported by the device.
+    ///
+    /// Resolutions are compared by their pixel count. On success, the mode
+    /// which was selected is returned.
+    pub fn set_highest_resolution(&mut self) -> Result<Mode> {
+        let mode = self.modes().map(Completion::log).max_by_key(|mode| {
+            let (width, height) = mode.info().resolution();
+            width * height
+        });
+        self.set_found_mode(mode)
+    }
+
+    fn set_found_mode(&mut self, mode: Option<Mode>) -> Result<Mode> {
+        match mode {
+            Some(mode) => self.set_mode(&mode).map_inner(|()| mode),
+            None => Err(Status::NOT_FOUND.into()),
+        }
+    }
+
     /// Performs a blt (block transfer) operation on the frame buffer.
     ///
     /// Every operation requires different parameters.
@@ -353,6 +389,9 @@ pub enum PixelFormat {
     //         is safe to model this C enum as a Rust enum.
 }
 
+/// Size in bytes of a frame buffer pixel in all directly accessible formats.
+const PIXEL_SIZE: usize = 4;
+
 /// Bitmask used to indicate which bits of a pixel represent a given color.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(C)]
@@ -425,10 +464,27 @@ impl ModeInfo {
     /// Returns the number of pixels per scanline.
     ///
     /// Due to performance reasons, the stride might not be equal to the width,
-    /// instead the stride might be bigger for better alignment.
+    /// instead the stride might be bigger for better alignment. Rows of the
+    /// frame buffer must be addressed using the stride, not the width, or the
+    /// picture will come out skewed.
     pub fn stride(&self) -> usize {
         self.stride as usize
     }
+
+    /// Returns the number of bytes between the start of two consecutive rows
+    /// of the frame buffer, or `None` in a Blt-only mode.
+    pub fn bytes_per_row(&self) -> Option<usize> {
+        match self.format {
+            PixelFormat::BltOnly => None,
+            _ => Some(self.stride() * PIXEL_SIZE),
+        }
+    }
+
+    /// Returns the byte offset of pixel (`x`, `y`) inside of the frame buffer,
+    /// or `None` in a Blt-only mode.
+    pub fn pixel_offset(&self, x: usize, y: usize) -> Option<usize> {
+        self.bytes_per_row().map(|row| y * row + x * PIXEL_SIZE)
+    }
 }
 
 /// Iterator for graphics modes.
@@ -571,6 +627,23 @@ pub struct FrameBuffer<'gop> {
 }
 
 impl<'gop> FrameBuffer<'gop> {
+    /// Rebuild a frame buffer from its base address and size in bytes
+    ///
+    /// This makes it possible to keep drawing after boot services have been
+    /// exited, as long as the address and size were saved beforehand.
+    ///
+    /// # Safety
+    ///
+    /// The memory range must be a frame buffer, or at least memory which is
+    /// valid for volatile reads and writes, for the whole lifetime `'gop`.
+    pub unsafe fn from_raw_parts(base: *mut u8, size: usize) -> Self {
+        Self {
+            base,
+            size,
+            _lifetime: PhantomData,
+        }
+    }
+
     /// Access the raw framebuffer pointer
     ///
     /// To use this pointer safely and correctly, you must...
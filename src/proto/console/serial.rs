@@ -1,8 +1,9 @@
 //! Abstraction over byte stream devices, also known as serial I/O devices.
 
 use crate::proto::Protocol;
-use crate::{unsafe_guid, Result, Status};
+use crate::{unsafe_guid, Result, ResultExt, Status};
 use bitflags::bitflags;
+use core::fmt;
 
 /// Provides access to a serial I/O device.
 ///
@@ -113,6 +114,28 @@ impl<'boot> Serial<'boot> {
     }
 }
 
+impl<'boot> fmt::Write for Serial<'boot> {
+    /// Write the UTF-8 encoding of the string, with line feeds translated to
+    /// the CR LF sequence which terminals expect
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut lines = s.split('\n');
+        if let Some(first) = lines.next() {
+            self.write(first.as_bytes())
+                .warning_as_error()
+                .map_err(|_| fmt::Error)?;
+        }
+        for line in lines {
+            self.write(b"\r\n")
+                .warning_as_error()
+                .map_err(|_| fmt::Error)?;
+            self.write(line.as_bytes())
+                .warning_as_error()
+                .map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
 /// Structure representing the device's current parameters.
 ///
 /// The default values for all UART-like devices is:
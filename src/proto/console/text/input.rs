@@ -1,6 +1,16 @@
+use crate::data_types::chars::NUL_16;
 use crate::proto::Protocol;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{boxed::Box, collections::VecDeque},
+    proto::ProtocolImpl,
+    sync::TplMutex,
+    table::boot::{BootServices, EventType, Tpl},
+};
 use crate::{unsafe_guid, Char16, Event, Result, Status};
 use core::mem::MaybeUninit;
+#[cfg(feature = "exts")]
+use core::{convert::TryFrom, ffi::c_void};
 
 /// Interface for text-based input devices.
 #[repr(C)]
@@ -49,6 +59,140 @@ impl Input {
     }
 }
 
+/// A Simple Text Input protocol implementation, fed with keys by Rust code
+///
+/// It provides a console input where the firmware has none, for example
+/// with a keyboard drawn on the screen and a touch or pointer device, or
+/// with keys received from a serial or network bridge. The keys are queued
+/// until they are read through the protocol, and the key event is signaled
+/// while the queue is not empty.
+///
+/// The keys may be pushed from event callbacks of up to `Tpl::NOTIFY`.
+#[cfg(feature = "exts")]
+#[repr(C)]
+pub struct VirtualKeyboard<'boot> {
+    // First, since the functions of the protocol find the keyboard from it
+    protocol: Input,
+    bt: &'boot BootServices,
+    keys: TplMutex<VecDeque<RawKey>>,
+    capacity: usize,
+}
+
+#[cfg(feature = "exts")]
+unsafe impl ProtocolImpl for VirtualKeyboard<'_> {
+    type Protocol = Input;
+
+    fn interface(&mut self) -> *mut c_void {
+        &mut self.protocol as *mut Input as *mut c_void
+    }
+}
+
+#[cfg(feature = "exts")]
+impl<'boot> VirtualKeyboard<'boot> {
+    /// Create a keyboard which queues up to `capacity` keys.
+    ///
+    /// # Errors
+    ///
+    /// See `BootServices::create_event`.
+    pub fn new(bt: &'boot BootServices, capacity: usize) -> Result<Box<Self>> {
+        let wait_for_key = unsafe { bt.create_event(EventType::empty(), Tpl::CALLBACK, None) }?;
+        Ok(wait_for_key.map(|wait_for_key| {
+            Box::new(VirtualKeyboard {
+                protocol: Input {
+                    reset: Self::reset,
+                    read_key_stroke: Self::read_key_stroke,
+                    wait_for_key,
+                },
+                bt,
+                keys: TplMutex::new(Tpl::NOTIFY, VecDeque::with_capacity(capacity)),
+                capacity,
+            })
+        }))
+    }
+
+    /// The protocol, to use the keyboard without installing it
+    pub fn input(&mut self) -> &mut Input {
+        &mut self.protocol
+    }
+
+    /// Queue `key`, or return `false` if the queue is full.
+    pub fn push_key(&self, key: Key) -> bool {
+        let mut keys = self.keys.lock(self.bt);
+        if keys.len() >= self.capacity {
+            return false;
+        }
+        keys.push_back(key.into());
+        // Signaling an event cannot fail
+        let _ = self.bt.signal_event(self.protocol.wait_for_key);
+        true
+    }
+
+    /// Queue the characters of `text`, and return how many were queued
+    /// before the queue was full.
+    ///
+    /// Line feeds are sent as carriage returns, like the Enter key of a real
+    /// keyboard. Characters which are not in UCS-2 are skipped.
+    pub fn push_str(&self, text: &str) -> usize {
+        let mut queued = 0;
+        for c in text.chars() {
+            let c = if c == '\n' { '\r' } else { c };
+            if let Ok(c) = Char16::try_from(c) {
+                if !self.push_key(Key::Printable(c)) {
+                    break;
+                }
+                queued += 1;
+            }
+        }
+        queued
+    }
+
+    /// Number of keys which were not read yet
+    pub fn pending(&self) -> usize {
+        self.keys.lock(self.bt).len()
+    }
+
+    /// The keyboard of the protocol
+    fn from_protocol(this: &Input) -> &Self {
+        unsafe { &*(this as *const Input as *const Self) }
+    }
+
+    /// Discard the keys which were not read
+    extern "efiapi" fn reset(this: &mut Input, _extended_verification: bool) -> Status {
+        let keyboard = Self::from_protocol(this);
+        let mut keys = keyboard.keys.lock(keyboard.bt);
+        keys.clear();
+        // Checking the event clears it, now that no key is left
+        let _ = keyboard.bt.check_event(keyboard.protocol.wait_for_key);
+        Status::SUCCESS
+    }
+
+    extern "efiapi" fn read_key_stroke(this: &mut Input, key: *mut RawKey) -> Status {
+        if key.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        let keyboard = Self::from_protocol(this);
+        let mut keys = keyboard.keys.lock(keyboard.bt);
+        match keys.pop_front() {
+            Some(next) => {
+                unsafe { key.write(next) };
+                // Checking the event cleared it, while keys are left
+                if !keys.is_empty() {
+                    let _ = keyboard.bt.signal_event(keyboard.protocol.wait_for_key);
+                }
+                Status::SUCCESS
+            }
+            None => Status::NOT_READY,
+        }
+    }
+}
+
+#[cfg(feature = "exts")]
+impl Drop for VirtualKeyboard<'_> {
+    fn drop(&mut self) {
+        let _ = self.bt.close_event(self.protocol.wait_for_key);
+    }
+}
+
 /// A key read from the console (high-level version)
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Key {
@@ -69,7 +213,23 @@ impl From<RawKey> for Key {
     }
 }
 
+impl From<Key> for RawKey {
+    fn from(k: Key) -> RawKey {
+        match k {
+            Key::Printable(unicode_char) => RawKey {
+                scan_code: ScanCode::NULL,
+                unicode_char,
+            },
+            Key::Special(scan_code) => RawKey {
+                scan_code,
+                unicode_char: NUL_16,
+            },
+        }
+    }
+}
+
 /// A key read from the console (UEFI version)
+#[derive(Debug, Copy, Clone)]
 #[repr(C)]
 pub struct RawKey {
     /// The key's scan code.
@@ -0,0 +1,383 @@
+use super::{Key, RawKey};
+use crate::proto::Protocol;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{boxed::Box, vec::Vec},
+    sync::TplMutex,
+    table::boot::{system_table, BootServices, Tpl},
+};
+use crate::{unsafe_guid, Event, Result, Status};
+use bitflags::bitflags;
+use core::ffi::c_void;
+use core::mem::MaybeUninit;
+#[cfg(feature = "exts")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Extended interface for text-based input devices, which also reports the
+/// state of the modifier keys, and calls functions when given keys are
+/// pressed.
+#[repr(C)]
+#[unsafe_guid("dd9e7534-7762-4698-8c14-f58517a625aa")]
+#[derive(Protocol)]
+pub struct InputEx {
+    reset: extern "efiapi" fn(this: &mut InputEx, extended: bool) -> Status,
+    read_key_stroke_ex: extern "efiapi" fn(this: &mut InputEx, key: *mut KeyData) -> Status,
+    wait_for_key_ex: Event,
+    set_state: extern "efiapi" fn(this: &mut InputEx, state: *const ToggleState) -> Status,
+    register_key_notify: extern "efiapi" fn(
+        this: *mut InputEx,
+        key: *const KeyData,
+        notify: KeyNotifyFn,
+        handle: *mut *mut c_void,
+    ) -> Status,
+    unregister_key_notify: extern "efiapi" fn(this: *mut InputEx, handle: *mut c_void) -> Status,
+}
+
+/// Function called by the firmware when a registered key is pressed
+pub type KeyNotifyFn = extern "efiapi" fn(key: *mut KeyData) -> Status;
+
+impl InputEx {
+    /// Resets the input device hardware.
+    ///
+    /// The `extended_verification` parameter is used to request that UEFI
+    /// performs an extended check and reset of the input device.
+    ///
+    /// # Errors
+    ///
+    /// - `DeviceError` if the device is malfunctioning and cannot be reset.
+    pub fn reset(&mut self, extended_verification: bool) -> Result {
+        (self.reset)(self, extended_verification).into()
+    }
+
+    /// Reads the next keystroke from the input device, if any, with the
+    /// state of the modifier keys.
+    ///
+    /// Keystrokes which only change the state of a modifier key are
+    /// reported with a `ScanCode::NULL` key and a null character, if the
+    /// device supports it.
+    ///
+    /// # Errors
+    ///
+    /// - `DeviceError` if there was an issue with the input device
+    pub fn read_key(&mut self) -> Result<Option<KeyData>> {
+        let mut key = MaybeUninit::<KeyData>::uninit();
+
+        match (self.read_key_stroke_ex)(self, key.as_mut_ptr()) {
+            Status::NOT_READY => Ok(None.into()),
+            other => other.into_with_val(|| Some(unsafe { key.assume_init() })),
+        }
+    }
+
+    /// Event to be used with `BootServices::wait_for_event()` in order to wait
+    /// for a key to be available
+    pub fn wait_for_key_event(&self) -> Event {
+        self.wait_for_key_ex
+    }
+
+    /// Set the toggle keys, such as Caps Lock, which are on.
+    ///
+    /// `ToggleState::TOGGLE_STATE_VALID` is added to `state`.
+    ///
+    /// # Errors
+    ///
+    /// - `DeviceError` if there was an issue with the input device
+    /// - `Unsupported` if the device does not support changing the state
+    pub fn set_state(&mut self, state: ToggleState) -> Result {
+        let state = state | ToggleState::TOGGLE_STATE_VALID;
+        (self.set_state)(self, &state).into()
+    }
+
+    /// Have `notify` called whenever `key` is pressed, and return the handle
+    /// which unregisters it.
+    ///
+    /// The shift state of `key` is ignored if it has no
+    /// `ShiftState::SHIFT_STATE_VALID`. Registering the same function for
+    /// the same key twice returns the same handle. With the `exts` feature,
+    /// `register_hotkey` calls closures instead.
+    ///
+    /// # Safety
+    ///
+    /// `notify` is called at `Tpl::CALLBACK` until it is unregistered, so
+    /// it must not access data used by the interrupted code.
+    pub unsafe fn register_key_notify(
+        &self,
+        key: &KeyData,
+        notify: KeyNotifyFn,
+    ) -> Result<KeyNotifyHandle> {
+        let mut handle = core::ptr::null_mut();
+        (self.register_key_notify)(self.as_mut_ptr(), key, notify, &mut handle)
+            .into_with_val(|| KeyNotifyHandle(handle))
+    }
+
+    /// Stop calling the function registered with `handle`.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidParameter` if `handle` is not registered
+    pub fn unregister_key_notify(&self, handle: KeyNotifyHandle) -> Result {
+        (self.unregister_key_notify)(self.as_mut_ptr(), handle.0).into()
+    }
+
+    fn as_mut_ptr(&self) -> *mut InputEx {
+        // The firmware keeps the registrations in its own state
+        self as *const InputEx as *mut InputEx
+    }
+}
+
+/// Handle of a function registered with `InputEx::register_key_notify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct KeyNotifyHandle(*mut c_void);
+
+/// A key read from the console, with the state of the modifier keys
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct KeyData {
+    /// The key
+    pub key: RawKey,
+    /// State of the modifier keys when the key was pressed
+    pub state: KeyState,
+}
+
+impl KeyData {
+    /// The key `key`, with any modifier
+    pub fn new(key: Key) -> Self {
+        KeyData {
+            key: key.into(),
+            state: KeyState {
+                shift_state: ShiftState::empty(),
+                toggle_state: ToggleState::empty(),
+            },
+        }
+    }
+
+    /// The key `key`, with exactly the modifiers `shift_state`
+    pub fn with_modifiers(key: Key, shift_state: ShiftState) -> Self {
+        let mut data = KeyData::new(key);
+        data.state.shift_state = shift_state | ShiftState::SHIFT_STATE_VALID;
+        data
+    }
+
+    /// The key, in its high-level version
+    pub fn key(&self) -> Key {
+        self.key.into()
+    }
+
+    /// Whether a key pressed with this data triggers a registration of
+    /// `registered`
+    #[cfg(feature = "exts")]
+    fn matches(&self, registered: &KeyData) -> bool {
+        let shift_state = registered.state.shift_state;
+        self.key.scan_code == registered.key.scan_code
+            && self.key.unicode_char == registered.key.unicode_char
+            && (!shift_state.contains(ShiftState::SHIFT_STATE_VALID)
+                || self.state.shift_state == shift_state)
+    }
+}
+
+/// State of the modifier keys
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct KeyState {
+    /// The modifier keys which are pressed
+    pub shift_state: ShiftState,
+    /// The toggle keys which are on
+    pub toggle_state: ToggleState,
+}
+
+bitflags! {
+    /// Modifier keys which are pressed
+    pub struct ShiftState: u32 {
+        /// The other flags are valid
+        const SHIFT_STATE_VALID = 0x8000_0000;
+        /// Right Shift
+        const RIGHT_SHIFT_PRESSED = 0x0000_0001;
+        /// Left Shift
+        const LEFT_SHIFT_PRESSED = 0x0000_0002;
+        /// Right Control
+        const RIGHT_CONTROL_PRESSED = 0x0000_0004;
+        /// Left Control
+        const LEFT_CONTROL_PRESSED = 0x0000_0008;
+        /// Right Alt
+        const RIGHT_ALT_PRESSED = 0x0000_0010;
+        /// Left Alt
+        const LEFT_ALT_PRESSED = 0x0000_0020;
+        /// Right logo key
+        const RIGHT_LOGO_PRESSED = 0x0000_0040;
+        /// Left logo key
+        const LEFT_LOGO_PRESSED = 0x0000_0080;
+        /// Menu key
+        const MENU_KEY_PRESSED = 0x0000_0100;
+        /// System Request key
+        const SYS_REQ_PRESSED = 0x0000_0200;
+    }
+}
+
+bitflags! {
+    /// Toggle keys which are on
+    pub struct ToggleState: u8 {
+        /// The other flags are valid
+        const TOGGLE_STATE_VALID = 0x80;
+        /// Keystrokes which only change the state of a modifier key are
+        /// reported
+        const KEY_STATE_EXPOSED = 0x40;
+        /// Scroll Lock
+        const SCROLL_LOCK_ACTIVE = 0x01;
+        /// Num Lock
+        const NUM_LOCK_ACTIVE = 0x02;
+        /// Caps Lock
+        const CAPS_LOCK_ACTIVE = 0x04;
+    }
+}
+
+/// The closure called by a hotkey
+#[cfg(feature = "exts")]
+type HotkeyFn = dyn FnMut(&KeyData);
+
+/// A closure registered with `InputEx::register_hotkey`
+#[cfg(feature = "exts")]
+struct Hotkey {
+    id: usize,
+    key: KeyData,
+    handle: KeyNotifyHandle,
+    /// Taken out while the closure runs
+    handler: Option<Box<HotkeyFn>>,
+}
+
+/// The registered closures
+#[cfg(feature = "exts")]
+struct Hotkeys(Vec<Hotkey>);
+
+// Boot services run on a single processor
+#[cfg(feature = "exts")]
+unsafe impl Send for Hotkeys {}
+
+/// The registered closures, which the notification function dispatches to
+#[cfg(feature = "exts")]
+static HOTKEYS: TplMutex<Hotkeys> = TplMutex::new(Tpl::NOTIFY, Hotkeys(Vec::new()));
+
+/// Identifier of the next registered closure
+#[cfg(feature = "exts")]
+static NEXT_HOTKEY: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "exts")]
+impl InputEx {
+    /// Have `handler` called whenever `key` is pressed, until the returned
+    /// registration is dropped:
+    ///
+    /// ```no_run
+    /// # use uefi::prelude::*;
+    /// # use uefi::proto::console::text::{InputEx, Key, KeyData, ScanCode};
+    /// # use core::sync::atomic::{AtomicBool, Ordering};
+    /// # fn f(bt: &BootServices, input: &InputEx) -> uefi::Result {
+    /// static SHOW_MENU: AtomicBool = AtomicBool::new(false);
+    /// let f12 = KeyData::new(Key::Special(ScanCode::FUNCTION_12));
+    /// let hotkey = input
+    ///     .register_hotkey(bt, f12, |_| SHOW_MENU.store(true, Ordering::Relaxed))?
+    ///     .log();
+    /// // Boot the default entry, unless F12 is pressed in the meantime
+    /// bt.stall(3_000_000);
+    /// drop(hotkey);
+    /// # Ok(().into())
+    /// # }
+    /// ```
+    ///
+    /// `handler` is called at `Tpl::CALLBACK`, interrupting the code which
+    /// runs at `Tpl::APPLICATION`, so it should only record the key press
+    /// in a `TplCell` or an atomic. It gets boot services from
+    /// `uefi::table::boot::system_table`, which must be registered.
+    ///
+    /// `handler` runs without the lock of the registered closures, so it may
+    /// register and drop hotkeys, even its own. It is not called again for
+    /// key presses which happen while it runs.
+    ///
+    /// # Errors
+    ///
+    /// - `NotStarted` if no system table is registered
+    /// - `OutOfResources` if there is no memory for the registration
+    pub fn register_hotkey<'boot, F>(
+        &self,
+        bt: &'boot BootServices,
+        key: KeyData,
+        handler: F,
+    ) -> Result<HotkeyRegistration<'_, 'boot>>
+    where
+        F: FnMut(&KeyData) + 'static,
+    {
+        if system_table().is_none() {
+            return Err(Status::NOT_STARTED.into());
+        }
+        let (status, handle) = unsafe { self.register_key_notify(&key, notify_hotkeys) }?.split();
+        let id = NEXT_HOTKEY.fetch_add(1, Ordering::Relaxed);
+        HOTKEYS.lock(bt).0.push(Hotkey {
+            id,
+            key,
+            handle,
+            handler: Some(Box::new(handler)),
+        });
+        status.into_with_val(|| HotkeyRegistration {
+            input: self,
+            boot_services: bt,
+            id,
+            handle,
+        })
+    }
+}
+
+/// A closure registered with `InputEx::register_hotkey`, which is
+/// unregistered when this is dropped
+#[cfg(feature = "exts")]
+pub struct HotkeyRegistration<'input, 'boot> {
+    input: &'input InputEx,
+    boot_services: &'boot BootServices,
+    id: usize,
+    handle: KeyNotifyHandle,
+}
+
+#[cfg(feature = "exts")]
+impl Drop for HotkeyRegistration<'_, '_> {
+    fn drop(&mut self) {
+        let mut hotkeys = HOTKEYS.lock(self.boot_services);
+        hotkeys.0.retain(|hotkey| hotkey.id != self.id);
+        // The firmware gives the same handle to the registrations of the
+        // same key
+        if hotkeys.0.iter().all(|hotkey| hotkey.handle != self.handle) {
+            let _ = self.input.unregister_key_notify(self.handle);
+        }
+    }
+}
+
+/// Notification function of all the hotkeys, which calls the closures
+/// registered for the pressed key
+#[cfg(feature = "exts")]
+extern "efiapi" fn notify_hotkeys(key: *mut KeyData) -> Status {
+    let (bt, key) = match (system_table(), unsafe { key.as_ref() }) {
+        (Some(st), Some(key)) => (st.boot_services(), key),
+        _ => return Status::SUCCESS,
+    };
+    let ids: Vec<usize> = HOTKEYS
+        .lock(bt)
+        .0
+        .iter()
+        .filter(|hotkey| key.matches(&hotkey.key))
+        .map(|hotkey| hotkey.id)
+        .collect();
+    for id in ids {
+        // The closures are called without the lock, which they may need
+        let handler = HOTKEYS
+            .lock(bt)
+            .0
+            .iter_mut()
+            .find(|hotkey| hotkey.id == id)
+            .and_then(|hotkey| hotkey.handler.take());
+        if let Some(mut handler) = handler {
+            handler(key);
+            // Unless the closure dropped its registration
+            let mut hotkeys = HOTKEYS.lock(bt);
+            if let Some(hotkey) = hotkeys.0.iter_mut().find(|hotkey| hotkey.id == id) {
+                hotkey.handler = Some(handler);
+            }
+        }
+    }
+    Status::SUCCESS
+}
@@ -0,0 +1,375 @@
+use super::{Input, Key, Output, ScanCode};
+use crate::result::Error;
+use crate::table::boot::{BootServices, EventType, TimerTrigger, Tpl};
+#[cfg(feature = "exts")]
+use crate::{alloc_api::vec::Vec, CString16};
+use crate::{CStr16, Event, Result, ResultExt, Status};
+use core::convert::TryInto;
+use core::time::Duration;
+
+/// Carriage return, sent by the Enter key
+const CHAR_CARRIAGE_RETURN: u16 = 0x0D;
+/// Sent by the Backspace key, and moves the cursor one column to the left
+const CHAR_BACKSPACE: u16 = 0x08;
+/// Characters below this one are control characters
+const CHAR_SPACE: u16 = 0x20;
+
+/// Line editor, which reads a line of text from a text input device
+///
+/// Characters are echoed on an output device as they are typed, and the
+/// Backspace key erases the last one. The line is complete when Enter is
+/// pressed, while Escape cancels the input.
+///
+/// With the `exts` feature, the reader can also remember the last lines that
+/// were read, which can be recalled with the Up and Down arrow keys.
+///
+/// ```no_run
+/// # use uefi::prelude::*;
+/// # use uefi::proto::console::text::{Input, LineReader, Output};
+/// # fn ask(bt: &BootServices, input: &mut Input, output: &mut Output) -> uefi::Result {
+/// let mut buffer = [0; 64];
+/// let password = LineReader::new()
+///     .mask(Some('*'))
+///     .read_into(bt, input, output, &mut buffer)
+///     .log_warning()?;
+/// # Status::SUCCESS.into()
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LineReader {
+    echo: bool,
+    mask: Option<u16>,
+    timeout: Option<Duration>,
+    #[cfg(feature = "exts")]
+    history: Vec<Vec<u16>>,
+    #[cfg(feature = "exts")]
+    history_size: usize,
+}
+
+impl LineReader {
+    /// Create a line reader which echoes typed characters, without timeout
+    /// or history
+    pub fn new() -> Self {
+        LineReader {
+            echo: true,
+            mask: None,
+            timeout: None,
+            #[cfg(feature = "exts")]
+            history: Vec::new(),
+            #[cfg(feature = "exts")]
+            history_size: 0,
+        }
+    }
+
+    /// Whether typed characters are displayed on the output device
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Display `mask` instead of the typed characters, for password entry
+    ///
+    /// Masked lines are never added to the history.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mask` is not a UCS-2 character.
+    pub fn mask(mut self, mask: Option<char>) -> Self {
+        self.mask = mask.map(|mask| {
+            (mask as u32)
+                .try_into()
+                .expect("Mask must be a UCS-2 character")
+        });
+        self
+    }
+
+    /// Give up with a `TIMEOUT` error if no key is pressed for this long
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Remember up to `size` lines, which can be recalled with the arrow keys
+    #[cfg(feature = "exts")]
+    pub fn history_size(mut self, size: usize) -> Self {
+        self.history_size = size;
+        let excess = self.history.len().saturating_sub(size);
+        self.history.drain(..excess);
+        self
+    }
+
+    /// Lines remembered by the reader, from the oldest to the most recent
+    #[cfg(feature = "exts")]
+    pub fn history(&self) -> impl ExactSizeIterator<Item = &[u16]> + '_ {
+        self.history.iter().map(|line| line.as_slice())
+    }
+
+    /// Read a line into `buffer`, which must have room for the terminating
+    /// null character
+    ///
+    /// Characters which do not fit in the buffer are ignored.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ABORTED`   The user pressed Escape
+    /// * `uefi::Status::TIMEOUT`   No key was pressed during the timeout
+    /// * Errors of the input and output devices are passed through
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is empty.
+    pub fn read_into<'buf>(
+        &mut self,
+        bt: &BootServices,
+        input: &mut Input,
+        output: &mut Output,
+        buffer: &'buf mut [u16],
+    ) -> Result<&'buf CStr16> {
+        assert!(!buffer.is_empty(), "No room for the null character");
+        let mut line = SliceLine { buffer, len: 0 };
+        let completion = self.read(bt, input, output, &mut line)?;
+        let len = line.len;
+        let buffer = line.buffer;
+        buffer[len] = 0;
+        let line = unsafe { CStr16::from_u16_with_nul_unchecked(&buffer[..=len]) };
+        Ok(completion.map(|()| line))
+    }
+
+    /// Read a line into a newly allocated string
+    ///
+    /// # Errors
+    ///
+    /// See `read_into`.
+    #[cfg(feature = "exts")]
+    pub fn read_line(
+        &mut self,
+        bt: &BootServices,
+        input: &mut Input,
+        output: &mut Output,
+    ) -> Result<CString16> {
+        let mut line = Vec::new();
+        let completion = self.read(bt, input, output, &mut line)?;
+        line.push(0);
+        let line = unsafe { CStr16::from_u16_with_nul_unchecked(&line) };
+        Ok(completion.map(|()| CString16::from(line)))
+    }
+
+    fn read(
+        &mut self,
+        bt: &BootServices,
+        input: &mut Input,
+        output: &mut Output,
+        line: &mut impl LineBuffer,
+    ) -> Result {
+        let timer = match self.timeout {
+            Some(_) => Some(
+                unsafe { bt.create_event(EventType::TIMER, Tpl::APPLICATION, None) }
+                    .log_warning()?,
+            ),
+            None => None,
+        };
+        let result = self.edit(bt, input, output, line, timer);
+        if let Some(timer) = timer {
+            // Failing to close the timer must not hide the outcome of the edit
+            let _ = bt.close_event(timer);
+        }
+        let result = result?;
+
+        self.remember(line.chars());
+        Ok(result)
+    }
+
+    /// Add a line which was read to the history
+    #[cfg(feature = "exts")]
+    fn remember(&mut self, chars: &[u16]) {
+        if self.mask.is_some() || self.history_size == 0 || chars.is_empty() {
+            return;
+        }
+        if self.history.len() == self.history_size {
+            self.history.remove(0);
+        }
+        self.history.push(chars.to_vec());
+    }
+
+    #[cfg(not(feature = "exts"))]
+    fn remember(&mut self, _chars: &[u16]) {}
+
+    fn edit(
+        &mut self,
+        bt: &BootServices,
+        input: &mut Input,
+        output: &mut Output,
+        line: &mut impl LineBuffer,
+        timer: Option<Event>,
+    ) -> Result {
+        #[cfg(feature = "exts")]
+        let mut history_index = self.history.len();
+
+        loop {
+            // Wait for a key, or for the timeout to expire
+            let key_event = input.wait_for_key_event();
+            let signaled = match (timer, self.timeout) {
+                (Some(timer), Some(timeout)) => {
+                    let hundreds_ns = (timeout.as_nanos() / 100).try_into().unwrap_or(u64::MAX);
+                    bt.set_timer(timer, TimerTrigger::Relative(hundreds_ns))
+                        .log_warning()?;
+                    bt.wait_for_event(&mut [key_event, timer])
+                }
+                _ => bt.wait_for_event(&mut [key_event]),
+            };
+            if signaled.discard_errdata().log_warning()? == 1 {
+                return Err(Status::TIMEOUT.into());
+            }
+
+            let key = match input.read_key().log_warning()? {
+                Some(key) => key,
+                None => continue,
+            };
+            match key {
+                Key::Printable(c) => match u16::from(c) {
+                    CHAR_CARRIAGE_RETURN => {
+                        if self.echo {
+                            self.write(output, &[u16::from(b'\r'), u16::from(b'\n')])?;
+                        }
+                        return Status::SUCCESS.into();
+                    }
+                    CHAR_BACKSPACE => {
+                        if line.pop().is_some() {
+                            self.erase(output, 1)?;
+                        }
+                    }
+                    c if c < CHAR_SPACE => {}
+                    c => {
+                        if line.push(c) {
+                            self.echo_chars(output, &[c])?;
+                        }
+                    }
+                },
+                Key::Special(ScanCode::ESCAPE) => return Err(Status::ABORTED.into()),
+                #[cfg(feature = "exts")]
+                Key::Special(ScanCode::UP) if self.mask.is_none() && history_index > 0 => {
+                    history_index -= 1;
+                    self.replace(output, line, history_index)?;
+                }
+                #[cfg(feature = "exts")]
+                Key::Special(ScanCode::DOWN)
+                    if self.mask.is_none() && history_index < self.history.len() =>
+                {
+                    history_index += 1;
+                    self.replace(output, line, history_index)?;
+                }
+                Key::Special(_) => {}
+            }
+        }
+    }
+
+    /// Replace the line being edited with an entry of the history, or with
+    /// an empty line if `index` is past the last entry
+    #[cfg(feature = "exts")]
+    fn replace(
+        &self,
+        output: &mut Output,
+        line: &mut impl LineBuffer,
+        index: usize,
+    ) -> core::result::Result<(), Error> {
+        let erased = line.chars().len();
+        while line.pop().is_some() {}
+        self.erase(output, erased)?;
+        let entry = self
+            .history
+            .get(index)
+            .map_or(&[][..], |entry| entry.as_slice());
+        for &c in entry {
+            if !line.push(c) {
+                break;
+            }
+        }
+        self.echo_chars(output, line.chars())
+    }
+
+    /// Display characters which were added to the line
+    fn echo_chars(&self, output: &mut Output, chars: &[u16]) -> core::result::Result<(), Error> {
+        for &c in chars {
+            self.write(output, &[self.mask.unwrap_or(c)])?;
+        }
+        Ok(())
+    }
+
+    /// Erase the last `count` characters that were displayed
+    fn erase(&self, output: &mut Output, count: usize) -> core::result::Result<(), Error> {
+        for _ in 0..count {
+            self.write(output, &[CHAR_BACKSPACE, u16::from(b' '), CHAR_BACKSPACE])?;
+        }
+        Ok(())
+    }
+
+    /// Write up to 3 characters to the output device, if echo is enabled
+    fn write(&self, output: &mut Output, chars: &[u16]) -> core::result::Result<(), Error> {
+        if !self.echo {
+            return Ok(());
+        }
+        let mut buffer = [0; 4];
+        buffer[..chars.len()].copy_from_slice(chars);
+        let string = unsafe { CStr16::from_u16_with_nul_unchecked(&buffer[..=chars.len()]) };
+        output.output_string(string).log_warning()
+    }
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Storage for the characters of the line being edited
+trait LineBuffer {
+    /// Characters of the line
+    fn chars(&self) -> &[u16];
+    /// Append a character, returning `false` if there is no room left
+    fn push(&mut self, c: u16) -> bool;
+    /// Remove the last character
+    fn pop(&mut self) -> Option<u16>;
+}
+
+/// Line stored in a caller-provided buffer, keeping room for a null character
+struct SliceLine<'buf> {
+    buffer: &'buf mut [u16],
+    len: usize,
+}
+
+impl LineBuffer for SliceLine<'_> {
+    fn chars(&self) -> &[u16] {
+        &self.buffer[..self.len]
+    }
+
+    fn push(&mut self, c: u16) -> bool {
+        if self.len + 1 < self.buffer.len() {
+            self.buffer[self.len] = c;
+            self.len += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn pop(&mut self) -> Option<u16> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.buffer[self.len])
+    }
+}
+
+#[cfg(feature = "exts")]
+impl LineBuffer for Vec<u16> {
+    fn chars(&self) -> &[u16] {
+        self
+    }
+
+    fn push(&mut self, c: u16) -> bool {
+        Vec::push(self, c);
+        true
+    }
+
+    fn pop(&mut self) -> Option<u16> {
+        Vec::pop(self)
+    }
+}
@@ -1,7 +1,19 @@
 //! Text I/O.
 
 mod input;
-pub use self::input::{Input, Key, ScanCode};
+#[cfg(feature = "exts")]
+pub use self::input::VirtualKeyboard;
+pub use self::input::{Input, Key, RawKey, ScanCode};
+
+mod input_ex;
+#[cfg(feature = "exts")]
+pub use self::input_ex::HotkeyRegistration;
+pub use self::input_ex::{
+    InputEx, KeyData, KeyNotifyFn, KeyNotifyHandle, KeyState, ShiftState, ToggleState,
+};
+
+mod line;
+pub use self::line::LineReader;
 
 mod output;
 pub use self::output::{Color, Output, OutputMode};
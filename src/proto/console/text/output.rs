@@ -103,6 +103,21 @@ impl<'boot> Output<'boot> {
         (self.set_mode)(self, mode.index).into()
     }
 
+    /// Sets the supported text mode with the largest geometry as current.
+    ///
+    /// The geometry of a mode is measured by the number of characters which
+    /// fit on the screen. On success, the mode which was selected is returned.
+    pub fn set_best_mode(&mut self) -> Result<OutputMode> {
+        let best_mode = self
+            .modes()
+            .map(Completion::log)
+            .max_by_key(|mode| mode.columns() * mode.rows());
+        match best_mode {
+            Some(mode) => self.set_mode(mode).map_inner(|()| mode),
+            None => Err(Status::UNSUPPORTED.into()),
+        }
+    }
+
     /// Returns whether the cursor is currently shown or not.
     pub fn cursor_visible(&self) -> bool {
         self.data.cursor_visible
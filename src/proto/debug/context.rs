@@ -1,3 +1,5 @@
+use core::fmt;
+
 // note from the spec:
 // When the context record field is larger than the register being stored in it, the upper bits of the
 // context record field are unused and ignored
@@ -374,6 +376,79 @@ pub struct SystemContextX64 {
     r15: u64,
 }
 
+impl SystemContext {
+    /// The context of an x64 processor.
+    ///
+    /// # Safety
+    ///
+    /// The context must come from an x64 processor, and be used while the
+    /// callback which received it runs.
+    pub unsafe fn x64(&self) -> &SystemContextX64 {
+        &*self.x64
+    }
+}
+
+impl SystemContextX64 {
+    /// Address of the instruction which faulted, or which follows a trap
+    pub fn rip(&self) -> u64 {
+        self.rip
+    }
+
+    /// Stack pointer when the exception occurred
+    pub fn rsp(&self) -> u64 {
+        self.rsp
+    }
+
+    /// Frame pointer when the exception occurred
+    pub fn rbp(&self) -> u64 {
+        self.rbp
+    }
+
+    /// Error code pushed by the exception, or 0 for those which have none
+    pub fn exception_data(&self) -> u64 {
+        self.exception_data
+    }
+
+    /// Faulting address of a page fault
+    pub fn cr2(&self) -> u64 {
+        self.cr2
+    }
+}
+
+impl fmt::Display for SystemContextX64 {
+    /// Print the general-purpose registers, as a crash dump
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registers = [
+            ("rax", self.rax),
+            ("rbx", self.rbx),
+            ("rcx", self.rcx),
+            ("rdx", self.rdx),
+            ("rsi", self.rsi),
+            ("rdi", self.rdi),
+            ("rbp", self.rbp),
+            ("rsp", self.rsp),
+            ("r8", self.r8),
+            ("r9", self.r9),
+            ("r10", self.r10),
+            ("r11", self.r11),
+            ("r12", self.r12),
+            ("r13", self.r13),
+            ("r14", self.r14),
+            ("r15", self.r15),
+            ("rip", self.rip),
+            ("rflags", self.rflags),
+            ("cr2", self.cr2),
+            ("cr3", self.cr3),
+            ("error", self.exception_data),
+        ];
+        for (i, (name, value)) in registers.iter().enumerate() {
+            let separator = if i % 4 == 3 { "\n" } else { " " };
+            write!(f, "{:>6}={:016x}{}", name, value, separator)?;
+        }
+        Ok(())
+    }
+}
+
 /// FP / MMX / XMM registers for X64
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -15,7 +15,7 @@ use crate::proto::Protocol;
 use crate::{unsafe_guid, Result, Status};
 
 // re-export for ease of use
-pub use self::context::SystemContext;
+pub use self::context::{SystemContext, SystemContextX64};
 pub use self::exception::ExceptionType;
 
 mod context;
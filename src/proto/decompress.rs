@@ -0,0 +1,120 @@
+//! Decompress protocol.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Result, Status};
+#[cfg(feature = "exts")]
+use {crate::ResultExt, alloc_api::vec, alloc_api::vec::Vec};
+
+/// Protocol for decompressing data compressed with the UEFI compression
+/// algorithm (`EFI_DECOMPRESS_PROTOCOL`).
+///
+/// This is the "Tiano" variant of LZ77 with Huffman coding which is used for
+/// the compressed sections of firmware volumes. It cannot decode gzip or zlib
+/// data, see the [`decompress`](crate::decompress) module for those.
+#[repr(C)]
+#[unsafe_guid("d8117cfe-94a6-11d4-9a3a-0090273fc14d")]
+#[derive(Protocol)]
+pub struct Decompress {
+    get_info: unsafe extern "efiapi" fn(
+        this: &Self,
+        source: *const u8,
+        source_size: u32,
+        destination_size: &mut u32,
+        scratch_size: &mut u32,
+    ) -> Status,
+    decompress: unsafe extern "efiapi" fn(
+        this: &Self,
+        source: *const u8,
+        source_size: u32,
+        destination: *mut u8,
+        destination_size: u32,
+        scratch: *mut u8,
+        scratch_size: u32,
+    ) -> Status,
+}
+
+/// Buffer sizes needed to decompress some data, returned by `Decompress::get_info`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DecompressInfo {
+    /// Size of the decompressed data
+    pub destination_size: usize,
+    /// Size of the scratch buffer used during the decompression
+    pub scratch_size: usize,
+}
+
+impl Decompress {
+    /// Query the size of the buffers needed to decompress `source`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The source data is corrupted, or
+    ///                                      larger than 4 GiB
+    pub fn get_info(&self, source: &[u8]) -> Result<DecompressInfo> {
+        let source_size = Self::buffer_size(source)?;
+        let mut destination_size = 0;
+        let mut scratch_size = 0;
+        unsafe {
+            (self.get_info)(
+                self,
+                source.as_ptr(),
+                source_size,
+                &mut destination_size,
+                &mut scratch_size,
+            )
+        }
+        .into_with_val(|| DecompressInfo {
+            destination_size: destination_size as usize,
+            scratch_size: scratch_size as usize,
+        })
+    }
+
+    /// Decompress `source` into `destination`, using `scratch` as temporary
+    /// storage.
+    ///
+    /// The buffers must have at least the sizes reported by `get_info`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The source data is corrupted, or
+    ///                                      a buffer is too small
+    pub fn decompress(&self, source: &[u8], destination: &mut [u8], scratch: &mut [u8]) -> Result {
+        let source_size = Self::buffer_size(source)?;
+        let destination_size = Self::buffer_size(destination)?;
+        let scratch_size = Self::buffer_size(scratch)?;
+        unsafe {
+            (self.decompress)(
+                self,
+                source.as_ptr(),
+                source_size,
+                destination.as_mut_ptr(),
+                destination_size,
+                scratch.as_mut_ptr(),
+                scratch_size,
+            )
+        }
+        .into()
+    }
+
+    /// Decompress `source` into a newly allocated buffer.
+    ///
+    /// # Errors
+    ///
+    /// See `get_info` and `decompress`.
+    #[cfg(feature = "exts")]
+    pub fn decompress_to_vec(&self, source: &[u8]) -> Result<Vec<u8>> {
+        let info = self.get_info(source).log_warning()?;
+        let mut destination = vec![0; info.destination_size];
+        let mut scratch = vec![0; info.scratch_size];
+        self.decompress(source, &mut destination, &mut scratch)
+            .map(|completion| completion.map(|_| destination))
+    }
+
+    /// All the sizes of the protocol are 32-bit
+    fn buffer_size(buffer: &[u8]) -> core::result::Result<u32, crate::result::Error> {
+        if buffer.len() > u32::MAX as usize {
+            Err(Status::INVALID_PARAMETER.into())
+        } else {
+            Ok(buffer.len() as u32)
+        }
+    }
+}
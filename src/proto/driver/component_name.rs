@@ -0,0 +1,295 @@
+//! Component Name protocols.
+//!
+//! Drivers install these protocols to give human-readable names to
+//! themselves and to the controllers they manage, in one or more languages.
+//! This is where the names shown by the `drivers` and `devices` shell
+//! commands come from.
+
+use super::language;
+use crate::proto::Protocol;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{boxed::Box, vec::Vec},
+    proto::ProtocolImpl,
+    CStr8, CString16,
+};
+use crate::{unsafe_guid, CStr16, Char16, Char8, Handle, Result, Status};
+use core::ffi::c_void;
+use core::ptr;
+
+/// The Component Name 2 protocol.
+///
+/// Languages are designated by their RFC 4646 codes, such as `en-US`.
+#[repr(C)]
+#[unsafe_guid("6a7a5cff-e8d9-4f70-bada-75ab3025ce14")]
+#[derive(Protocol)]
+pub struct ComponentName2 {
+    get_driver_name: unsafe extern "efiapi" fn(
+        this: *const ComponentName2,
+        language: *const Char8,
+        driver_name: *mut *const Char16,
+    ) -> Status,
+    get_controller_name: unsafe extern "efiapi" fn(
+        this: *const ComponentName2,
+        controller_handle: Handle,
+        child_handle: *mut c_void,
+        language: *const Char8,
+        controller_name: *mut *const Char16,
+    ) -> Status,
+    supported_languages: *const Char8,
+}
+
+impl ComponentName2 {
+    /// Iterate over the languages in which names are available, starting with
+    /// the default one
+    pub fn supported_languages(&self) -> impl Iterator<Item = &str> {
+        unsafe { language::iter(self.supported_languages) }
+    }
+
+    /// Pick the first language of `preferred` in which names are available,
+    /// or the default one if there is none.
+    ///
+    /// A preferred language without region, like `en`, matches all the
+    /// regional variants, like `en-US`.
+    pub fn select_language(&self, preferred: &[&str]) -> Option<&str> {
+        unsafe { language::select(self.supported_languages, preferred) }
+    }
+
+    /// Get the name of the driver, in `language`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`  The name is not available in `language`
+    pub fn driver_name(&self, language: &str) -> Result<&CStr16> {
+        let mut buffer = [0; language::MAX_LENGTH + 1];
+        let language = language::to_cstr(&mut buffer, language)?;
+        let mut name = ptr::null();
+        unsafe { (self.get_driver_name)(self, language.as_ptr(), &mut name) }
+            .into_with_val(|| unsafe { CStr16::from_ptr(name) })
+    }
+
+    /// Get the name of a controller managed by the driver, or of one of its
+    /// children if `child` is not `None`, in `language`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`  The driver does not manage the
+    ///                                controller, or the name is not
+    ///                                available in `language`
+    pub fn controller_name(
+        &self,
+        controller: Handle,
+        child: Option<Handle>,
+        language: &str,
+    ) -> Result<&CStr16> {
+        let mut buffer = [0; language::MAX_LENGTH + 1];
+        let language = language::to_cstr(&mut buffer, language)?;
+        let child = child.map_or(ptr::null_mut(), Handle::as_ptr);
+        let mut name = ptr::null();
+        unsafe { (self.get_controller_name)(self, controller, child, language.as_ptr(), &mut name) }
+            .into_with_val(|| unsafe { CStr16::from_ptr(name) })
+    }
+}
+
+/// An implementation of the Component Name 2 protocol
+///
+/// The names are given for each language, and are looked up ignoring the
+/// case of the language codes.
+///
+/// It is usually installed on the image handle of the driver.
+#[cfg(feature = "exts")]
+#[repr(C)]
+pub struct ComponentNames {
+    // The protocol comes first, so that a pointer to the implementation is a
+    // pointer to the protocol
+    get_driver_name: unsafe extern "efiapi" fn(
+        this: *const ComponentNames,
+        language: *const Char8,
+        driver_name: *mut *const Char16,
+    ) -> Status,
+    get_controller_name: unsafe extern "efiapi" fn(
+        this: *const ComponentNames,
+        controller_handle: Handle,
+        child_handle: *mut c_void,
+        language: *const Char8,
+        controller_name: *mut *const Char16,
+    ) -> Status,
+    supported_languages: *const Char8,
+
+    /// The languages, separated by `;` and terminated by a nul
+    languages: Vec<u8>,
+    /// The names of the driver, by language
+    driver_names: Vec<(Vec<u8>, CString16)>,
+    /// The names of the controllers and of their children, by language
+    controller_names: Vec<(Handle, Option<Handle>, Vec<u8>, CString16)>,
+}
+
+#[cfg(feature = "exts")]
+unsafe impl ProtocolImpl for ComponentNames {
+    type Protocol = ComponentName2;
+
+    fn interface(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+}
+
+#[cfg(feature = "exts")]
+impl ComponentNames {
+    /// Create a protocol implementation which gives names in `languages`,
+    /// the first one being the default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a language is empty, or contains a `;` or a nul.
+    pub fn new(languages: &[&str]) -> Box<Self> {
+        let list = language::build_list(languages);
+        Box::new(ComponentNames {
+            get_driver_name: Self::get_driver_name,
+            get_controller_name: Self::get_controller_name,
+            // The buffer of the list is never reallocated
+            supported_languages: list.as_ptr().cast(),
+            languages: list,
+            driver_names: Vec::new(),
+            controller_names: Vec::new(),
+        })
+    }
+
+    /// Set the name of the driver in `language`
+    pub fn set_driver_name(&mut self, language: &str, name: &CStr16) {
+        self.driver_names
+            .retain(|(lang, _)| !lang.eq_ignore_ascii_case(language.as_bytes()));
+        self.driver_names
+            .push((language.as_bytes().to_vec(), name.into()));
+    }
+
+    /// Set the name of `controller`, or of its `child` if it is not `None`,
+    /// in `language`
+    pub fn set_controller_name(
+        &mut self,
+        controller: Handle,
+        child: Option<Handle>,
+        language: &str,
+        name: &CStr16,
+    ) {
+        let child_ptr = child.map_or(ptr::null_mut(), Handle::as_ptr);
+        self.controller_names.retain(|(ctrl, chld, lang, _)| {
+            !(ctrl.as_ptr() == controller.as_ptr()
+                && chld.map_or(ptr::null_mut(), Handle::as_ptr) == child_ptr
+                && lang.eq_ignore_ascii_case(language.as_bytes()))
+        });
+        self.controller_names
+            .push((controller, child, language.as_bytes().to_vec(), name.into()));
+    }
+
+    /// Forget the names of `controller` and of its children, once the driver
+    /// stops managing it
+    pub fn remove_controller(&mut self, controller: Handle) {
+        self.controller_names
+            .retain(|(ctrl, _, _, _)| ctrl.as_ptr() != controller.as_ptr());
+    }
+
+    /// The languages, separated by `;`
+    pub fn languages(&self) -> &str {
+        // Only built from `&str`s
+        core::str::from_utf8(&self.languages[..self.languages.len() - 1]).unwrap()
+    }
+
+    unsafe extern "efiapi" fn get_driver_name(
+        this: *const Self,
+        language: *const Char8,
+        driver_name: *mut *const Char16,
+    ) -> Status {
+        if language.is_null() || driver_name.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        let language = CStr8::from_ptr(language).to_bytes();
+        match (*this)
+            .driver_names
+            .iter()
+            .find(|(lang, _)| lang.eq_ignore_ascii_case(language))
+        {
+            Some((_, name)) => {
+                *driver_name = name.as_ptr();
+                Status::SUCCESS
+            }
+            None => Status::UNSUPPORTED,
+        }
+    }
+
+    unsafe extern "efiapi" fn get_controller_name(
+        this: *const Self,
+        controller_handle: Handle,
+        child_handle: *mut c_void,
+        language: *const Char8,
+        controller_name: *mut *const Char16,
+    ) -> Status {
+        if language.is_null() || controller_name.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        let language = CStr8::from_ptr(language).to_bytes();
+        match (*this)
+            .controller_names
+            .iter()
+            .find(|(ctrl, child, lang, _)| {
+                ctrl.as_ptr() == controller_handle.as_ptr()
+                    && child.map_or(ptr::null_mut(), Handle::as_ptr) == child_handle
+                    && lang.eq_ignore_ascii_case(language)
+            }) {
+            Some((_, _, _, name)) => {
+                *controller_name = name.as_ptr();
+                Status::SUCCESS
+            }
+            None => Status::UNSUPPORTED,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        use core::convert::TryFrom;
+
+        let handle = |value: usize| Handle::from_ptr(value as *mut c_void).unwrap();
+        let mut names = ComponentNames::new(&["en-US", "fr"]);
+        names.set_driver_name("en-US", &CString16::try_from("Disk driver").unwrap());
+        names.set_controller_name(
+            handle(1),
+            None,
+            "fr",
+            &CString16::try_from("Contrôleur").unwrap(),
+        );
+        names.set_controller_name(
+            handle(1),
+            Some(handle(2)),
+            "fr",
+            &CString16::try_from("Disque").unwrap(),
+        );
+        assert_eq!(names.languages(), "en-US;fr");
+
+        // Look the names up through the protocol
+        let protocol = unsafe { &*(&*names as *const ComponentNames as *const ComponentName2) };
+        let languages: Vec<_> = protocol.supported_languages().collect();
+        assert_eq!(languages, ["en-US", "fr"]);
+        assert_eq!(protocol.select_language(&["de", "fr-FR", "fr"]), Some("fr"));
+        assert_eq!(protocol.select_language(&["de"]), Some("en-US"));
+
+        let name = protocol.driver_name("en-us").unwrap().unwrap();
+        assert_eq!(name.as_string(), "Disk driver");
+        let status = protocol.driver_name("fr").unwrap_err().status();
+        assert_eq!(status, Status::UNSUPPORTED);
+
+        let name = protocol.controller_name(handle(1), None, "fr").unwrap();
+        assert_eq!(name.unwrap().as_string(), "Contrôleur");
+        let name = protocol
+            .controller_name(handle(1), Some(handle(2)), "fr")
+            .unwrap();
+        assert_eq!(name.unwrap().as_string(), "Disque");
+
+        names.remove_controller(handle(1));
+        let protocol = unsafe { &*(&*names as *const ComponentNames as *const ComponentName2) };
+        assert!(protocol.controller_name(handle(1), None, "fr").is_err());
+    }
+}
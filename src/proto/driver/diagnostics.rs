@@ -0,0 +1,328 @@
+//! Driver Diagnostics 2 protocol.
+//!
+//! Drivers install this protocol to run self-tests on the controllers they
+//! manage. It is what the `drvdiag` shell command uses. With the `exts`
+//! feature, the [`Diagnostics`] type implements the protocol for drivers
+//! written in Rust.
+
+use super::language;
+use crate::proto::Protocol;
+use crate::result::Error;
+use crate::table::boot::BootServices;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{boxed::Box, vec::Vec},
+    proto::ProtocolImpl,
+    table::boot::MemoryType,
+    CStr8, CString16,
+};
+use crate::{unsafe_guid, CStr16, Char16, Char8, Completion, Guid, Handle, Result, Status};
+use core::ffi::c_void;
+use core::fmt::{self, Debug, Formatter};
+use core::ptr;
+use core::slice;
+
+newtype_enum! {
+    /// Kind of diagnostics to run (`EFI_DRIVER_DIAGNOSTIC_TYPE`)
+    pub enum DiagnosticType: u32 => {
+        /// Quick tests, which need no interaction
+        STANDARD      = 0,
+        /// Thorough tests, which may take a long time or need interaction
+        EXTENDED      = 1,
+        /// Tests meant for the manufacturing line
+        MANUFACTURING = 2,
+        /// Cancel the diagnostics running in the background
+        CANCEL        = 3,
+    }
+}
+
+/// The Driver Diagnostics 2 protocol.
+///
+/// Languages are designated by their RFC 4646 codes, such as `en-US`.
+#[repr(C)]
+#[unsafe_guid("4d330321-025f-4aac-90d8-5ed900173b63")]
+#[derive(Protocol)]
+pub struct DriverDiagnostics2 {
+    run_diagnostics: unsafe extern "efiapi" fn(
+        this: *mut DriverDiagnostics2,
+        controller_handle: Handle,
+        child_handle: *mut c_void,
+        diagnostic_type: DiagnosticType,
+        language: *const Char8,
+        error_type: *mut *const Guid,
+        buffer_size: *mut usize,
+        buffer: *mut *mut Char16,
+    ) -> Status,
+    supported_languages: *const Char8,
+}
+
+impl DriverDiagnostics2 {
+    /// Iterate over the languages in which the results can be described,
+    /// starting with the default one
+    pub fn supported_languages(&self) -> impl Iterator<Item = &str> {
+        unsafe { language::iter(self.supported_languages) }
+    }
+
+    /// Pick the first language of `preferred` in which the results can be
+    /// described, or the default one if there is none.
+    pub fn select_language(&self, preferred: &[&str]) -> Option<&str> {
+        unsafe { language::select(self.supported_languages, preferred) }
+    }
+
+    /// Run diagnostics on `controller`, or on its `child` if it is not
+    /// `None`, and return their results described in `language`.
+    ///
+    /// The results are also returned when the diagnostics fail.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The controller failed the diagnostics
+    /// * `uefi::Status::UNSUPPORTED`   The driver does not manage the
+    ///                                 controller, does not support this type
+    ///                                 of diagnostics, or `language`
+    pub fn run<'boot>(
+        &mut self,
+        bt: &'boot BootServices,
+        controller: Handle,
+        child: Option<Handle>,
+        diagnostic_type: DiagnosticType,
+        language: &str,
+    ) -> Result<DiagnosticsReport<'boot>, Option<DiagnosticsReport<'boot>>> {
+        let mut buffer = [0; language::MAX_LENGTH + 1];
+        let language = language::to_cstr(&mut buffer, language)
+            .map_err(|err| Error::new(err.status(), None))?;
+        let child = child.map_or(ptr::null_mut(), Handle::as_ptr);
+        let mut error_type = ptr::null();
+        let mut size = 0;
+        let mut message = ptr::null_mut();
+        let status = unsafe {
+            (self.run_diagnostics)(
+                self,
+                controller,
+                child,
+                diagnostic_type,
+                language.as_ptr(),
+                &mut error_type,
+                &mut size,
+                &mut message,
+            )
+        };
+        let report = DiagnosticsReport {
+            boot_services: bt,
+            error_type: unsafe { error_type.as_ref() }.copied(),
+            message,
+            // The size is in bytes
+            len: if message.is_null() { 0 } else { size / 2 },
+        };
+        // The results are only returned by a successful run, or by a failed one
+        if !status.is_error() {
+            Ok(Completion::new(status, report))
+        } else if status == Status::DEVICE_ERROR {
+            Err(Error::new(status, Some(report)))
+        } else {
+            Err(Error::new(status, None))
+        }
+    }
+}
+
+/// Results of diagnostics, returned by `DriverDiagnostics2::run`
+///
+/// The description of the results is freed when the report is dropped.
+pub struct DiagnosticsReport<'boot> {
+    boot_services: &'boot BootServices,
+    error_type: Option<Guid>,
+    message: *mut Char16,
+    len: usize,
+}
+
+impl DiagnosticsReport<'_> {
+    /// The GUID which defines the format of the results
+    pub fn error_type(&self) -> Option<Guid> {
+        self.error_type
+    }
+
+    /// The description of the results, if there is one and it is
+    /// nul-terminated
+    pub fn message(&self) -> Option<&CStr16> {
+        if self.message.is_null() {
+            return None;
+        }
+        let codes = unsafe { slice::from_raw_parts(self.message as *const u16, self.len) };
+        let nul = codes.iter().position(|&code| code == 0)?;
+        Some(unsafe { CStr16::from_u16_with_nul_unchecked(&codes[..=nul]) })
+    }
+}
+
+impl Debug for DiagnosticsReport<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiagnosticsReport")
+            .field("error_type", &self.error_type)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl Drop for DiagnosticsReport<'_> {
+    fn drop(&mut self) {
+        if !self.message.is_null() {
+            // Ignore the result, we can't do anything about an error here.
+            let _ = self.boot_services.free_pool(self.message.cast());
+        }
+    }
+}
+
+/// Outcome of diagnostics, returned by the tests of a [`Diagnostics`]
+/// implementation
+#[cfg(feature = "exts")]
+#[derive(Debug, Clone)]
+pub struct Diagnosis {
+    /// Whether the controller passed the diagnostics
+    pub passed: bool,
+    /// The GUID which defines the format of the results
+    pub error_type: Option<Guid>,
+    /// The description of the results
+    pub message: Option<CString16>,
+}
+
+/// The tests run by a [`Diagnostics`] implementation
+///
+/// They are given the controller, its child or `None`, the type of
+/// diagnostics and the language of the description. They fail with
+/// `UNSUPPORTED` if the driver does not manage the controller, or if the type
+/// of diagnostics is not supported.
+#[cfg(feature = "exts")]
+pub type DiagnosticsFn<'boot> = dyn FnMut(Handle, Option<Handle>, DiagnosticType, &str) -> core::result::Result<Diagnosis, Status>
+    + 'boot;
+
+/// An implementation of the Driver Diagnostics 2 protocol
+///
+/// It is usually installed on the image handle of the driver.
+#[cfg(feature = "exts")]
+#[repr(C)]
+pub struct Diagnostics<'boot> {
+    // The protocol comes first, so that a pointer to the implementation is a
+    // pointer to the protocol
+    run_diagnostics: unsafe extern "efiapi" fn(
+        this: *mut Diagnostics<'boot>,
+        controller_handle: Handle,
+        child_handle: *mut c_void,
+        diagnostic_type: DiagnosticType,
+        language: *const Char8,
+        error_type: *mut *const Guid,
+        buffer_size: *mut usize,
+        buffer: *mut *mut Char16,
+    ) -> Status,
+    supported_languages: *const Char8,
+
+    /// The languages, separated by `;` and terminated by a nul
+    languages: Vec<u8>,
+    /// Used to allocate the descriptions of the results
+    boot_services: &'boot BootServices,
+    /// Error type of the last run, to which the firmware is given a pointer
+    error_type: Guid,
+    tests: Box<DiagnosticsFn<'boot>>,
+}
+
+#[cfg(feature = "exts")]
+unsafe impl ProtocolImpl for Diagnostics<'_> {
+    type Protocol = DriverDiagnostics2;
+
+    fn interface(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+}
+
+#[cfg(feature = "exts")]
+impl<'boot> Diagnostics<'boot> {
+    /// Create a protocol implementation which runs `tests`, and describes
+    /// their results in `languages`, the first one being the default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a language is empty, or contains a `;` or a nul.
+    pub fn new<F>(bt: &'boot BootServices, languages: &[&str], tests: F) -> Box<Self>
+    where
+        F: FnMut(
+                Handle,
+                Option<Handle>,
+                DiagnosticType,
+                &str,
+            ) -> core::result::Result<Diagnosis, Status>
+            + 'boot,
+    {
+        let list = language::build_list(languages);
+        Box::new(Diagnostics {
+            run_diagnostics: Self::run_diagnostics,
+            // The buffer of the list is never reallocated
+            supported_languages: list.as_ptr().cast(),
+            languages: list,
+            boot_services: bt,
+            error_type: Guid::default(),
+            tests: Box::new(tests),
+        })
+    }
+
+    /// Copy `message` to pool memory, from which the caller frees it
+    fn pool_copy(&self, message: &CStr16) -> core::result::Result<(*mut Char16, usize), Status> {
+        let codes = message.to_u16_slice_with_nul();
+        let size = codes.len() * 2;
+        let buffer = self
+            .boot_services
+            .allocate_pool(MemoryType::BOOT_SERVICES_DATA, size)
+            .map_err(|err| err.status())?
+            .log()
+            .cast::<u16>();
+        unsafe { buffer.copy_from_nonoverlapping(codes.as_ptr(), codes.len()) };
+        Ok((buffer.cast(), size))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "efiapi" fn run_diagnostics(
+        this: *mut Self,
+        controller_handle: Handle,
+        child_handle: *mut c_void,
+        diagnostic_type: DiagnosticType,
+        language: *const Char8,
+        error_type: *mut *const Guid,
+        buffer_size: *mut usize,
+        buffer: *mut *mut Char16,
+    ) -> Status {
+        if language.is_null() || error_type.is_null() || buffer_size.is_null() || buffer.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        let this = &mut *this;
+        let language = CStr8::from_ptr(language).to_bytes();
+        if !language::is_listed(&this.languages, language) {
+            return Status::UNSUPPORTED;
+        }
+        // The listed languages come from `&str`s
+        let language = core::str::from_utf8_unchecked(language);
+        let child = Handle::from_ptr(child_handle);
+        let diagnosis = match (this.tests)(controller_handle, child, diagnostic_type, language) {
+            Ok(diagnosis) => diagnosis,
+            Err(status) => return status,
+        };
+
+        let (message, size) = match &diagnosis.message {
+            Some(message) => match this.pool_copy(message) {
+                Ok(copy) => copy,
+                Err(status) => return status,
+            },
+            None => (ptr::null_mut(), 0),
+        };
+        *buffer = message;
+        *buffer_size = size;
+        *error_type = match diagnosis.error_type {
+            Some(guid) => {
+                this.error_type = guid;
+                &this.error_type
+            }
+            None => ptr::null(),
+        };
+        if diagnosis.passed {
+            Status::SUCCESS
+        } else {
+            Status::DEVICE_ERROR
+        }
+    }
+}
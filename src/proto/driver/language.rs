@@ -0,0 +1,125 @@
+//! Language lists of the driver model protocols.
+//!
+//! The protocols which return text for several languages advertise them as
+//! an ASCII string of RFC 4646 codes separated by `;`, like `en-US;fr`.
+
+#[cfg(feature = "exts")]
+use crate::alloc_api::vec::Vec;
+use crate::result::Error;
+use crate::{CStr8, Char8, Status};
+
+/// Longest language code accepted by the functions taking a `&str`
+pub(super) const MAX_LENGTH: usize = 63;
+
+/// Iterate over the languages of the list at `list`, which may be null
+///
+/// # Safety
+///
+/// `list` must point to a nul-terminated string which lives for `'list`.
+pub(super) unsafe fn iter<'list>(list: *const Char8) -> impl Iterator<Item = &'list str> {
+    let list = if list.is_null() {
+        &[]
+    } else {
+        CStr8::from_ptr(list).to_bytes()
+    };
+    list.split(|&c| c == b';')
+        .filter_map(|language| core::str::from_utf8(language).ok())
+        .filter(|language| !language.is_empty())
+}
+
+/// Pick the first language of `preferred` which is in the list at `list`, or
+/// the first language of the list if there is none
+///
+/// # Safety
+///
+/// See `iter`.
+pub(super) unsafe fn select<'list>(list: *const Char8, preferred: &[&str]) -> Option<&'list str> {
+    preferred
+        .iter()
+        .find_map(|wanted| iter(list).find(|language| matches(language, wanted)))
+        .or_else(|| iter(list).next())
+}
+
+/// Whether the `supported` language satisfies the `wanted` one
+///
+/// A language without region, like `en`, matches all the regional variants,
+/// like `en-US`.
+pub(super) fn matches(supported: &str, wanted: &str) -> bool {
+    if supported.eq_ignore_ascii_case(wanted) {
+        return true;
+    }
+    match supported.get(..wanted.len()) {
+        Some(prefix) => {
+            prefix.eq_ignore_ascii_case(wanted) && supported.as_bytes()[wanted.len()] == b'-'
+        }
+        None => false,
+    }
+}
+
+/// Copy a language code to `buffer`, with a terminating nul
+pub(super) fn to_cstr<'buf>(
+    buffer: &'buf mut [u8; MAX_LENGTH + 1],
+    language: &str,
+) -> core::result::Result<&'buf CStr8, Error> {
+    let bytes = language.as_bytes();
+    if bytes.len() > MAX_LENGTH || bytes.contains(&0) {
+        return Err(Status::INVALID_PARAMETER.into());
+    }
+    buffer[..bytes.len()].copy_from_slice(bytes);
+    buffer[bytes.len()] = 0;
+    Ok(unsafe { CStr8::from_bytes_with_nul_unchecked(&buffer[..=bytes.len()]) })
+}
+
+/// Build the nul-terminated list of `languages`
+///
+/// # Panics
+///
+/// Panics if a language is empty, or contains a `;` or a nul.
+#[cfg(feature = "exts")]
+pub(super) fn build_list(languages: &[&str]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for language in languages {
+        assert!(
+            !language.is_empty() && !language.contains(&[';', '\0'][..]),
+            "invalid language code"
+        );
+        if !list.is_empty() {
+            list.push(b';');
+        }
+        list.extend_from_slice(language.as_bytes());
+    }
+    list.push(0);
+    list
+}
+
+/// Whether `language`, received from the firmware, is in `list`
+#[cfg(feature = "exts")]
+pub(super) fn is_listed(list: &[u8], language: &[u8]) -> bool {
+    // The list is terminated by a nul
+    list[..list.len() - 1]
+        .split(|&c| c == b';')
+        .any(|listed| listed.eq_ignore_ascii_case(language))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_selection() {
+        assert!(matches("en-US", "en"));
+        assert!(matches("EN-us", "en-US"));
+        assert!(!matches("eng", "en"));
+        assert!(!matches("en", "en-US"));
+
+        let list = b"en-US;fr\0";
+        let list = list.as_ptr().cast();
+        assert_eq!(unsafe { select(list, &["de", "fr-FR", "fr"]) }, Some("fr"));
+        assert_eq!(unsafe { select(list, &["de"]) }, Some("en-US"));
+        assert_eq!(unsafe { iter(core::ptr::null()) }.count(), 0);
+
+        let mut buffer = [0; MAX_LENGTH + 1];
+        assert!(to_cstr(&mut buffer, "fr").is_ok());
+        assert!(to_cstr(&mut buffer, &"x".repeat(MAX_LENGTH + 1)).is_err());
+    }
+}
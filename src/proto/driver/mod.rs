@@ -0,0 +1,11 @@
+//! Driver model protocols.
+//!
+//! These protocols are installed with the drivers which follow the UEFI
+//! driver model, or by the platform, to describe the drivers and control
+//! which controllers they manage.
+
+pub mod component_name;
+pub mod diagnostics;
+mod language;
+pub mod overrides;
+pub mod version;
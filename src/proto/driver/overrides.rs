@@ -0,0 +1,290 @@
+//! Driver override protocols.
+//!
+//! When a controller is connected, the firmware tries the drivers that may
+//! manage it in a defined order: first the drivers returned by the Platform
+//! Driver Override protocol, then the ones returned by the Bus Specific Driver
+//! Override protocol of the controller, and then all the other drivers by
+//! decreasing version. The first driver which supports the controller is used.
+//!
+//! Looking at these protocols tells why a given driver manages a controller.
+//! With the `exts` feature, the [`DriverOverrides`] type implements the
+//! platform protocol, so that drivers can be tested with a given priority.
+
+use crate::proto::device_path::DevicePath;
+use crate::proto::Protocol;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{boxed::Box, vec::Vec},
+    proto::ProtocolImpl,
+};
+use crate::{unsafe_guid, Handle, Result, Status};
+use core::ffi::c_void;
+use core::ptr;
+
+/// Protocol through which the platform overrides the drivers of the
+/// controllers.
+///
+/// It is usually implemented with a table of the firmware configuration,
+/// and has at most one instance.
+#[repr(C)]
+#[unsafe_guid("6b30c738-a391-11d4-9a3b-0090273fc14d")]
+#[derive(Protocol)]
+pub struct PlatformDriverOverride {
+    get_driver: unsafe extern "efiapi" fn(
+        this: *mut PlatformDriverOverride,
+        controller_handle: Handle,
+        driver_image_handle: *mut *mut c_void,
+    ) -> Status,
+    get_driver_path: unsafe extern "efiapi" fn(
+        this: *mut PlatformDriverOverride,
+        controller_handle: Handle,
+        driver_image_path: *mut *const DevicePath,
+    ) -> Status,
+    driver_loaded: unsafe extern "efiapi" fn(
+        this: *mut PlatformDriverOverride,
+        controller_handle: Handle,
+        driver_image_path: *const DevicePath,
+        driver_image_handle: Handle,
+    ) -> Status,
+}
+
+impl PlatformDriverOverride {
+    /// Iterate over the image handles of the drivers which should manage
+    /// `controller`
+    ///
+    /// The drivers are returned from the highest priority to the lowest.
+    pub fn drivers(&mut self, controller: Handle) -> impl Iterator<Item = Handle> + '_ {
+        let mut driver = ptr::null_mut();
+        core::iter::from_fn(move || {
+            let status = unsafe { (self.get_driver)(self, controller, &mut driver) };
+            if status.is_success() {
+                Handle::from_ptr(driver)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Iterate over the device paths of the drivers which should manage
+    /// `controller`, but are not loaded yet
+    ///
+    /// Once such a driver has been loaded, its image handle must be given to
+    /// `driver_loaded`, so that `drivers` returns it.
+    pub fn driver_paths(&mut self, controller: Handle) -> impl Iterator<Item = &DevicePath> + '_ {
+        let mut path = ptr::null();
+        core::iter::from_fn(move || {
+            let status = unsafe { (self.get_driver_path)(self, controller, &mut path) };
+            if status.is_success() {
+                unsafe { path.as_ref() }
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Tell the platform that the driver at `path`, returned by
+    /// `driver_paths`, was loaded as `image`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`          `path` was not returned for
+    ///                                      `controller`
+    /// * `uefi::Status::ALREADY_STARTED`    The image is already known
+    /// * `uefi::Status::UNSUPPORTED`        The platform does not load drivers
+    ///                                      by their device paths
+    pub fn driver_loaded(
+        &mut self,
+        controller: Handle,
+        path: &DevicePath,
+        image: Handle,
+    ) -> Result {
+        unsafe { (self.driver_loaded)(self, controller, path, image) }.into()
+    }
+}
+
+/// Protocol through which a bus driver overrides the drivers of the
+/// controllers it creates, for example to use the option ROM of a PCI card.
+///
+/// It is installed on the handles of the controllers.
+#[repr(C)]
+#[unsafe_guid("3bc1b285-8a15-4a82-aabf-4d7d13fb3265")]
+#[derive(Protocol)]
+pub struct BusSpecificDriverOverride {
+    get_driver: unsafe extern "efiapi" fn(
+        this: *mut BusSpecificDriverOverride,
+        driver_image_handle: *mut *mut c_void,
+    ) -> Status,
+}
+
+impl BusSpecificDriverOverride {
+    /// Iterate over the image handles of the drivers which should manage the
+    /// controller, from the highest priority to the lowest
+    pub fn drivers(&mut self) -> impl Iterator<Item = Handle> + '_ {
+        let mut driver = ptr::null_mut();
+        core::iter::from_fn(move || {
+            let status = unsafe { (self.get_driver)(self, &mut driver) };
+            if status.is_success() {
+                Handle::from_ptr(driver)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// An implementation of the Platform Driver Override protocol
+///
+/// The drivers of each controller are given as image handles. Loading the
+/// drivers by their device paths is not supported.
+///
+/// The rules take effect when controllers are next connected.
+#[cfg(feature = "exts")]
+#[repr(C)]
+pub struct DriverOverrides {
+    // The function table of the protocol comes first, so that a pointer to
+    // the implementation is a pointer to the protocol
+    get_driver: unsafe extern "efiapi" fn(
+        this: *mut DriverOverrides,
+        controller_handle: Handle,
+        driver_image_handle: *mut *mut c_void,
+    ) -> Status,
+    get_driver_path: unsafe extern "efiapi" fn(
+        this: *mut DriverOverrides,
+        controller_handle: Handle,
+        driver_image_path: *mut *const DevicePath,
+    ) -> Status,
+    driver_loaded: unsafe extern "efiapi" fn(
+        this: *mut DriverOverrides,
+        controller_handle: Handle,
+        driver_image_path: *const DevicePath,
+        driver_image_handle: Handle,
+    ) -> Status,
+
+    /// The controllers, and their drivers in order of priority
+    rules: Vec<(Handle, Vec<Handle>)>,
+}
+
+#[cfg(feature = "exts")]
+unsafe impl ProtocolImpl for DriverOverrides {
+    type Protocol = PlatformDriverOverride;
+
+    fn interface(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+}
+
+#[cfg(feature = "exts")]
+impl DriverOverrides {
+    /// Create a protocol implementation which overrides no driver
+    pub fn new() -> Box<Self> {
+        Box::new(DriverOverrides {
+            get_driver: Self::get_driver,
+            get_driver_path: Self::get_driver_path,
+            driver_loaded: Self::driver_loaded,
+            rules: Vec::new(),
+        })
+    }
+
+    /// Make the `drivers` manage `controller`, in this order of priority,
+    /// instead of the drivers it had been given before
+    pub fn set_drivers(&mut self, controller: Handle, drivers: &[Handle]) {
+        self.rules
+            .retain(|(handle, _)| handle.as_ptr() != controller.as_ptr());
+        self.rules.push((controller, drivers.to_vec()));
+    }
+
+    /// The drivers which should manage `controller`
+    pub fn drivers(&self, controller: Handle) -> &[Handle] {
+        self.rules
+            .iter()
+            .find(|(handle, _)| handle.as_ptr() == controller.as_ptr())
+            .map_or(&[], |(_, drivers)| drivers)
+    }
+
+    /// The driver following `previous` among the drivers of `controller`, or
+    /// the first one if `previous` is null
+    fn next_driver(
+        &self,
+        controller: Handle,
+        previous: *mut c_void,
+    ) -> core::result::Result<Handle, Status> {
+        let drivers = self.drivers(controller);
+        let next = if previous.is_null() {
+            0
+        } else {
+            match drivers
+                .iter()
+                .position(|driver| driver.as_ptr() == previous)
+            {
+                Some(index) => index + 1,
+                None => return Err(Status::INVALID_PARAMETER),
+            }
+        };
+        drivers.get(next).copied().ok_or(Status::NOT_FOUND)
+    }
+
+    unsafe extern "efiapi" fn get_driver(
+        this: *mut Self,
+        controller_handle: Handle,
+        driver_image_handle: *mut *mut c_void,
+    ) -> Status {
+        if driver_image_handle.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        match (*this).next_driver(controller_handle, *driver_image_handle) {
+            Ok(driver) => {
+                *driver_image_handle = driver.as_ptr();
+                Status::SUCCESS
+            }
+            Err(status) => status,
+        }
+    }
+
+    unsafe extern "efiapi" fn get_driver_path(
+        _this: *mut Self,
+        _controller_handle: Handle,
+        _driver_image_path: *mut *const DevicePath,
+    ) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    unsafe extern "efiapi" fn driver_loaded(
+        _this: *mut Self,
+        _controller_handle: Handle,
+        _driver_image_path: *const DevicePath,
+        _driver_image_handle: Handle,
+    ) -> Status {
+        Status::UNSUPPORTED
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    fn handle(value: usize) -> Handle {
+        Handle::from_ptr(value as *mut c_void).unwrap()
+    }
+
+    #[test]
+    fn driver_order() {
+        let mut overrides = DriverOverrides::new();
+        overrides.set_drivers(handle(1), &[handle(10), handle(11)]);
+        overrides.set_drivers(handle(2), &[handle(20)]);
+        overrides.set_drivers(handle(1), &[handle(12), handle(10)]);
+
+        // Walk the drivers of the first controller through the protocol
+        let protocol = &mut *overrides as *mut DriverOverrides as *mut PlatformDriverOverride;
+        let drivers: Vec<_> = unsafe { &mut *protocol }
+            .drivers(handle(1))
+            .map(Handle::as_ptr)
+            .collect();
+        assert_eq!(drivers, [12 as *mut c_void, 10 as *mut c_void]);
+        assert_eq!(unsafe { &mut *protocol }.drivers(handle(3)).count(), 0);
+
+        let mut unknown = 11 as *mut c_void;
+        let status =
+            unsafe { DriverOverrides::get_driver(&mut *overrides, handle(1), &mut unknown) };
+        assert_eq!(status, Status::INVALID_PARAMETER);
+    }
+}
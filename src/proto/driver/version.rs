@@ -0,0 +1,62 @@
+//! Driver Supported EFI Version protocol.
+
+use crate::proto::Protocol;
+use crate::table::boot::BootServices;
+use crate::table::Revision;
+use crate::{unsafe_guid, Handle, Identify, Result};
+use core::ffi::c_void;
+use core::mem;
+
+/// The Driver Supported EFI Version protocol.
+///
+/// Drivers install it on their image handle to tell which version of the
+/// UEFI specification they follow. Since it is only data, it can be declared
+/// as a `static` and installed as is.
+#[repr(C)]
+#[unsafe_guid("5c198761-16a8-4e69-972c-89d67954f81d")]
+#[derive(Protocol)]
+pub struct DriverSupportedEfiVersion {
+    length: u32,
+    firmware_version: Revision,
+}
+
+impl DriverSupportedEfiVersion {
+    /// Create the protocol for drivers following `version` of the
+    /// specification
+    pub fn new(version: Revision) -> Self {
+        DriverSupportedEfiVersion {
+            length: mem::size_of::<Self>() as u32,
+            firmware_version: version,
+        }
+    }
+
+    /// The version of the specification followed by the driver
+    pub fn firmware_version(&self) -> Revision {
+        self.firmware_version
+    }
+
+    /// Install the protocol on `handle`, usually the image handle of the
+    /// driver, or on a new handle if `handle` is `None`, and return the
+    /// handle.
+    ///
+    /// # Safety
+    ///
+    /// The protocol must not be dropped or moved before it is uninstalled.
+    pub unsafe fn install(&self, bt: &BootServices, handle: Option<Handle>) -> Result<Handle> {
+        bt.install_protocol_interface(handle, &Self::GUID, self.interface())
+    }
+
+    /// Uninstall the protocol from `handle`.
+    ///
+    /// # Safety
+    ///
+    /// See `BootServices::uninstall_protocol_interface`.
+    pub unsafe fn uninstall(&self, bt: &BootServices, handle: Handle) -> Result {
+        bt.uninstall_protocol_interface(handle, &Self::GUID, self.interface())
+    }
+
+    fn interface(&self) -> *mut c_void {
+        // The firmware does not write to the protocol
+        self as *const Self as *mut c_void
+    }
+}
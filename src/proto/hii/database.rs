@@ -0,0 +1,109 @@
+//! HII database protocol.
+
+use super::keyboard::KeyboardLayout;
+use crate::proto::Protocol;
+use crate::result::Error;
+use crate::{unsafe_guid, Guid, Result, Status};
+use core::convert::TryInto;
+use core::mem;
+use core::ptr;
+
+/// The HII database, which holds the HII packages registered by the firmware
+/// and by drivers
+///
+/// Only the keyboard layout functions of the protocol are exposed for now.
+#[repr(C)]
+#[unsafe_guid("ef9fc172-a1b2-4693-b327-6d32fc416042")]
+#[derive(Protocol)]
+pub struct HiiDatabase {
+    new_package_list: usize,
+    remove_package_list: usize,
+    update_package_list: usize,
+    list_package_lists: usize,
+    export_package_lists: usize,
+    register_package_notify: usize,
+    unregister_package_notify: usize,
+    find_keyboard_layouts: extern "efiapi" fn(
+        this: &HiiDatabase,
+        key_guid_buffer_length: &mut u16,
+        key_guid_buffer: *mut Guid,
+    ) -> Status,
+    get_keyboard_layout: extern "efiapi" fn(
+        this: &HiiDatabase,
+        key_guid: *const Guid,
+        keyboard_layout_length: &mut u16,
+        keyboard_layout: *mut u8,
+    ) -> Status,
+    set_keyboard_layout: extern "efiapi" fn(this: &HiiDatabase, key_guid: &Guid) -> Status,
+    get_package_list_handle: usize,
+}
+
+impl HiiDatabase {
+    /// Fills `buffer` with the GUIDs of the keyboard layouts in the database,
+    /// and returns how many there are
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`   The buffer is too small, the number
+    ///                                      of layouts is returned in the error
+    /// * `uefi::Status::NOT_FOUND`          There are no keyboard layouts
+    pub fn find_keyboard_layouts(&self, buffer: &mut [Guid]) -> Result<usize, Option<usize>> {
+        let guid_size = mem::size_of::<Guid>();
+        let mut size = (buffer.len() * guid_size).try_into().unwrap_or(u16::MAX);
+        (self.find_keyboard_layouts)(self, &mut size, buffer.as_mut_ptr()).into_with(
+            || usize::from(size) / guid_size,
+            |status| {
+                if status == Status::BUFFER_TOO_SMALL {
+                    Some(usize::from(size) / guid_size)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    /// Reads a keyboard layout into `buffer`
+    ///
+    /// Passing `None` as the GUID retrieves the current keyboard layout.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`   The buffer is too small, the required
+    ///                                      size is returned in the error
+    /// * `uefi::Status::NOT_FOUND`          The layout does not exist, or there
+    ///                                      is no current layout
+    /// * `uefi::Status::VOLUME_CORRUPTED`   The layout returned by the firmware
+    ///                                      is inconsistent
+    pub fn keyboard_layout<'buf>(
+        &self,
+        guid: Option<&Guid>,
+        buffer: &'buf mut [u8],
+    ) -> Result<KeyboardLayout<'buf>, Option<usize>> {
+        let guid = guid.map_or(ptr::null(), |guid| guid as *const Guid);
+        let mut size = buffer.len().try_into().unwrap_or(u16::MAX);
+        let completion = (self.get_keyboard_layout)(self, guid, &mut size, buffer.as_mut_ptr())
+            .into_with(
+                || (),
+                |status| {
+                    if status == Status::BUFFER_TOO_SMALL {
+                        Some(usize::from(size))
+                    } else {
+                        None
+                    }
+                },
+            )?;
+        match KeyboardLayout::from_bytes(buffer) {
+            Some(layout) => Ok(completion.map(|()| layout)),
+            None => Err(Error::new(Status::VOLUME_CORRUPTED, None)),
+        }
+    }
+
+    /// Makes a keyboard layout the current one
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`          The layout does not exist
+    pub fn set_keyboard_layout(&mut self, guid: &Guid) -> Result {
+        (self.set_keyboard_layout)(self, guid).into()
+    }
+}
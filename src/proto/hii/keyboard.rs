@@ -0,0 +1,380 @@
+//! Keyboard layouts, as stored in the HII database.
+//!
+//! A keyboard layout describes which character each physical key produces,
+//! depending on the state of the shift, AltGr, caps lock and num lock keys.
+//! Decoding keys with a layout rather than relying on the characters which
+//! the firmware reports makes it possible to support non-US keyboards.
+
+use crate::{Char16, Guid};
+use bitflags::bitflags;
+use core::convert::TryFrom;
+use core::mem;
+use core::ptr;
+
+newtype_enum! {
+/// Physical location of a key (`EFI_KEY`)
+///
+/// Rows are named from the bottom of the keyboard (A) to the top (E), and
+/// keys are numbered from left to right within a row, following ISO/IEC 9995.
+pub enum EfiKey: u32 => #[allow(missing_docs)] {
+    LCTRL       = 0,
+    A0          = 1,
+    LALT        = 2,
+    SPACE_BAR   = 3,
+    A2          = 4,
+    A3          = 5,
+    A4          = 6,
+    RCTRL       = 7,
+    LEFT_ARROW  = 8,
+    DOWN_ARROW  = 9,
+    RIGHT_ARROW = 10,
+    ZERO        = 11,
+    PERIOD      = 12,
+    ENTER       = 13,
+    LSHIFT      = 14,
+    B0          = 15,
+    B1          = 16,
+    B2          = 17,
+    B3          = 18,
+    B4          = 19,
+    B5          = 20,
+    B6          = 21,
+    B7          = 22,
+    B8          = 23,
+    B9          = 24,
+    B10         = 25,
+    RSHIFT      = 26,
+    UP_ARROW    = 27,
+    ONE         = 28,
+    TWO         = 29,
+    THREE       = 30,
+    CAPS_LOCK   = 31,
+    C1          = 32,
+    C2          = 33,
+    C3          = 34,
+    C4          = 35,
+    C5          = 36,
+    C6          = 37,
+    C7          = 38,
+    C8          = 39,
+    C9          = 40,
+    C10         = 41,
+    C11         = 42,
+    C12         = 43,
+    FOUR        = 44,
+    FIVE        = 45,
+    SIX         = 46,
+    PLUS        = 47,
+    TAB         = 48,
+    D1          = 49,
+    D2          = 50,
+    D3          = 51,
+    D4          = 52,
+    D5          = 53,
+    D6          = 54,
+    D7          = 55,
+    D8          = 56,
+    D9          = 57,
+    D10         = 58,
+    D11         = 59,
+    D12         = 60,
+    D13         = 61,
+    DEL         = 62,
+    END         = 63,
+    PG_DN       = 64,
+    SEVEN       = 65,
+    EIGHT       = 66,
+    NINE        = 67,
+    E0          = 68,
+    E1          = 69,
+    E2          = 70,
+    E3          = 71,
+    E4          = 72,
+    E5          = 73,
+    E6          = 74,
+    E7          = 75,
+    E8          = 76,
+    E9          = 77,
+    E10         = 78,
+    E11         = 79,
+    E12         = 80,
+    BACK_SPACE  = 81,
+    INS         = 82,
+    HOME        = 83,
+    PG_UP       = 84,
+    NUM_LOCK    = 85,
+    SLASH       = 86,
+    ASTERISK    = 87,
+    MINUS       = 88,
+    ESC         = 89,
+    F1          = 90,
+    F2          = 91,
+    F3          = 92,
+    F4          = 93,
+    F5          = 94,
+    F6          = 95,
+    F7          = 96,
+    F8          = 97,
+    F9          = 98,
+    F10         = 99,
+    F11         = 100,
+    F12         = 101,
+    PRINT       = 102,
+    SCROLL_LOCK = 103,
+    PAUSE       = 104,
+}}
+
+newtype_enum! {
+/// Special function of a key, if it does not simply produce a character
+pub enum KeyModifier: u16 => #[allow(missing_docs)] {
+    /// The key produces a character.
+    NULL                = 0x00,
+    LEFT_CONTROL        = 0x01,
+    RIGHT_CONTROL       = 0x02,
+    LEFT_ALT            = 0x03,
+    RIGHT_ALT           = 0x04,
+    ALT_GR              = 0x05,
+    INSERT              = 0x06,
+    DELETE              = 0x07,
+    PAGE_DOWN           = 0x08,
+    PAGE_UP             = 0x09,
+    HOME                = 0x0A,
+    END                 = 0x0B,
+    LEFT_SHIFT          = 0x0C,
+    RIGHT_SHIFT         = 0x0D,
+    CAPS_LOCK           = 0x0E,
+    NUM_LOCK            = 0x0F,
+    LEFT_ARROW          = 0x10,
+    RIGHT_ARROW         = 0x11,
+    DOWN_ARROW          = 0x12,
+    UP_ARROW            = 0x13,
+    /// Dead key, which modifies the character produced by the next key.
+    NS_KEY              = 0x14,
+    /// Character produced by a preceding dead key.
+    NS_KEY_DEPENDENCY   = 0x15,
+    FUNCTION_KEY_ONE    = 0x16,
+    FUNCTION_KEY_TWO    = 0x17,
+    FUNCTION_KEY_THREE  = 0x18,
+    FUNCTION_KEY_FOUR   = 0x19,
+    FUNCTION_KEY_FIVE   = 0x1A,
+    FUNCTION_KEY_SIX    = 0x1B,
+    FUNCTION_KEY_SEVEN  = 0x1C,
+    FUNCTION_KEY_EIGHT  = 0x1D,
+    FUNCTION_KEY_NINE   = 0x1E,
+    FUNCTION_KEY_TEN    = 0x1F,
+    FUNCTION_KEY_ELEVEN = 0x20,
+    FUNCTION_KEY_TWELVE = 0x21,
+    PRINT               = 0x22,
+    SYS_REQUEST         = 0x23,
+    SCROLL_LOCK         = 0x24,
+    PAUSE               = 0x25,
+    BREAK               = 0x26,
+    LEFT_LOGO           = 0x27,
+    RIGHT_LOGO          = 0x28,
+    MENU                = 0x29,
+}}
+
+bitflags! {
+    /// Lock and modifier keys which change the character produced by a key
+    #[derive(Default)]
+    pub struct ModifierState: u8 {
+        /// One of the shift keys is held down.
+        const SHIFT = 1;
+        /// The AltGr key is held down.
+        const ALT_GR = 1 << 1;
+        /// Caps lock is active.
+        const CAPS_LOCK = 1 << 2;
+        /// Num lock is active.
+        const NUM_LOCK = 1 << 3;
+    }
+}
+
+bitflags! {
+    /// Which lock and modifier keys affect the character produced by a key
+    pub struct AffectedAttribute: u16 {
+        /// The key is affected by the shift keys.
+        const STANDARD_SHIFT = 0x0001;
+        /// The key is affected by caps lock, which acts like shift.
+        const CAPS_LOCK = 0x0002;
+        /// The key only produces a character when num lock is active.
+        const NUM_LOCK = 0x0004;
+    }
+}
+
+/// Description of what a physical key does (`EFI_KEY_DESCRIPTOR`)
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct KeyDescriptor {
+    /// Physical location of the key.
+    pub key: EfiKey,
+    /// Character produced without modifiers, or 0.
+    pub unicode: u16,
+    /// Character produced with shift, or 0.
+    pub shifted_unicode: u16,
+    /// Character produced with AltGr, or 0.
+    pub alt_gr_unicode: u16,
+    /// Character produced with shift and AltGr, or 0.
+    pub shifted_alt_gr_unicode: u16,
+    /// Special function of the key.
+    pub modifier: KeyModifier,
+    /// Which modifiers affect the character produced by the key.
+    pub affected_attribute: u16,
+}
+
+impl KeyDescriptor {
+    /// Character produced by this key in the given modifier state, if any
+    pub fn decode(&self, state: ModifierState) -> Option<Char16> {
+        if self.modifier != KeyModifier::NULL {
+            return None;
+        }
+        let affected = AffectedAttribute::from_bits_truncate(self.affected_attribute);
+        if affected.contains(AffectedAttribute::NUM_LOCK)
+            && !state.contains(ModifierState::NUM_LOCK)
+        {
+            return None;
+        }
+        let mut shift = affected.contains(AffectedAttribute::STANDARD_SHIFT)
+            && state.contains(ModifierState::SHIFT);
+        if affected.contains(AffectedAttribute::CAPS_LOCK)
+            && state.contains(ModifierState::CAPS_LOCK)
+        {
+            shift = !shift;
+        }
+        let unicode = match (shift, state.contains(ModifierState::ALT_GR)) {
+            (false, false) => self.unicode,
+            (true, false) => self.shifted_unicode,
+            (false, true) => self.alt_gr_unicode,
+            (true, true) => self.shifted_alt_gr_unicode,
+        };
+        match unicode {
+            0 => None,
+            unicode => Char16::try_from(unicode).ok(),
+        }
+    }
+}
+
+/// A keyboard layout (`EFI_HII_KEYBOARD_LAYOUT`), borrowed from a buffer
+#[derive(Debug, Copy, Clone)]
+pub struct KeyboardLayout<'buf> {
+    data: &'buf [u8],
+}
+
+/// Offset of the key descriptors in a keyboard layout: the layout length,
+/// GUID, description offset and descriptor count are packed before them
+const DESCRIPTORS_OFFSET: usize = 2 + mem::size_of::<Guid>() + 4 + 1;
+
+impl<'buf> KeyboardLayout<'buf> {
+    /// Interpret a buffer as a keyboard layout, checking that it is large
+    /// enough to contain the key descriptors that it declares
+    pub fn from_bytes(data: &'buf [u8]) -> Option<Self> {
+        let length = u16::from_le_bytes([*data.get(0)?, *data.get(1)?]) as usize;
+        let count = *data.get(DESCRIPTORS_OFFSET - 1)? as usize;
+        let data = data.get(..length)?;
+        if DESCRIPTORS_OFFSET + count * mem::size_of::<KeyDescriptor>() > length {
+            return None;
+        }
+        Some(KeyboardLayout { data })
+    }
+
+    /// GUID identifying the layout
+    pub fn guid(&self) -> Guid {
+        unsafe { ptr::read_unaligned(self.data[2..].as_ptr() as *const Guid) }
+    }
+
+    /// Offset, from the start of the layout, of the descriptions of the
+    /// layout in various languages
+    pub fn description_offset(&self) -> usize {
+        let bytes = &self.data[2 + mem::size_of::<Guid>()..];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+    }
+
+    /// Raw bytes of the layout
+    pub fn as_bytes(&self) -> &'buf [u8] {
+        self.data
+    }
+
+    /// Key descriptors of the layout
+    pub fn descriptors(&self) -> impl ExactSizeIterator<Item = KeyDescriptor> + 'buf {
+        let count = self.data[DESCRIPTORS_OFFSET - 1] as usize;
+        let descriptors = &self.data[DESCRIPTORS_OFFSET..];
+        (0..count).map(move |i| unsafe {
+            // Descriptors are not aligned, due to the packed layout header
+            ptr::read_unaligned(
+                descriptors[i * mem::size_of::<KeyDescriptor>()..].as_ptr() as *const KeyDescriptor
+            )
+        })
+    }
+
+    /// Descriptor of a physical key, if the layout describes it
+    pub fn descriptor(&self, key: EfiKey) -> Option<KeyDescriptor> {
+        self.descriptors().find(|descriptor| descriptor.key == key)
+    }
+
+    /// Character produced by a physical key in the given modifier state
+    pub fn decode(&self, key: EfiKey, state: ModifierState) -> Option<Char16> {
+        self.descriptor(key)?.decode(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// French AZERTY layout, reduced to the top-left letter key, the numpad
+    /// "1" key and the left shift key
+    fn layout(data: &mut [u8; 128]) -> KeyboardLayout {
+        let descriptors: [(u32, [u16; 4], u16, u16); 3] = [
+            (EfiKey::D1.0, [0x61, 0x41, 0, 0], 0, 0x3),
+            (EfiKey::ONE.0, [0x31, 0, 0, 0], 0, 0x4),
+            (EfiKey::LSHIFT.0, [0; 4], 0x0C, 0),
+        ];
+        let length = DESCRIPTORS_OFFSET + 16 * descriptors.len();
+        data[..2].copy_from_slice(&(length as u16).to_le_bytes());
+        data[2..6].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        data[DESCRIPTORS_OFFSET - 1] = descriptors.len() as u8;
+        for (i, (key, unicode, modifier, affected)) in descriptors.iter().enumerate() {
+            let d = &mut data[DESCRIPTORS_OFFSET + 16 * i..];
+            d[..4].copy_from_slice(&key.to_le_bytes());
+            for (j, c) in unicode.iter().enumerate() {
+                d[4 + 2 * j..6 + 2 * j].copy_from_slice(&c.to_le_bytes());
+            }
+            d[12..14].copy_from_slice(&modifier.to_le_bytes());
+            d[14..16].copy_from_slice(&affected.to_le_bytes());
+        }
+        KeyboardLayout::from_bytes(&data[..length]).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let mut data = [0; 128];
+        let layout = layout(&mut data);
+        assert_eq!(layout.descriptors().len(), 3);
+        assert_eq!(layout.guid(), Guid::from_values(0x1234_5678, 0, 0, 0, 0));
+        assert_eq!(
+            layout.descriptor(EfiKey::LSHIFT).unwrap().modifier,
+            KeyModifier::LEFT_SHIFT
+        );
+        assert!(layout.descriptor(EfiKey::ENTER).is_none());
+        let truncated = &layout.as_bytes()[..layout.as_bytes().len() - 1];
+        assert!(KeyboardLayout::from_bytes(truncated).is_none());
+    }
+
+    #[test]
+    fn decode() {
+        let mut data = [0; 128];
+        let layout = layout(&mut data);
+        let char16 = |c| Char16::try_from(c).ok();
+        let decode = |key, state| layout.decode(key, state);
+        assert_eq!(decode(EfiKey::D1, ModifierState::empty()), char16('a'));
+        assert_eq!(decode(EfiKey::D1, ModifierState::SHIFT), char16('A'));
+        assert_eq!(decode(EfiKey::D1, ModifierState::CAPS_LOCK), char16('A'));
+        assert_eq!(
+            decode(EfiKey::D1, ModifierState::CAPS_LOCK | ModifierState::SHIFT),
+            char16('a')
+        );
+        assert_eq!(decode(EfiKey::D1, ModifierState::ALT_GR), None);
+        assert_eq!(decode(EfiKey::ONE, ModifierState::empty()), None);
+        assert_eq!(decode(EfiKey::ONE, ModifierState::NUM_LOCK), char16('1'));
+        assert_eq!(decode(EfiKey::LSHIFT, ModifierState::empty()), None);
+    }
+}
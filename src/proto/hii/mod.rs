@@ -0,0 +1,7 @@
+//! Human Interface Infrastructure (HII) protocols.
+//!
+//! The HII is how firmware components publish the fonts, strings, forms and
+//! keyboard layouts which make up the pre-boot user interface.
+
+pub mod database;
+pub mod keyboard;
@@ -6,7 +6,7 @@ use crate::{
     table::boot::MemoryType,
     unsafe_guid, Handle, Status,
 };
-use core::{ffi::c_void, str};
+use core::{ffi::c_void, slice, str};
 
 /// The LoadedImage protocol. This can be opened on any image handle using the `HandleProtocol` boot service.
 #[repr(C)]
@@ -65,6 +65,19 @@ impl LoadedImage {
         }
     }
 
+    /// Get the raw bytes of the load options, or `None` if there are none.
+    ///
+    /// When the image was started by a boot option, the load options are the
+    /// optional data of the boot option, which need not be text.
+    pub fn load_options_as_bytes(&self) -> Option<&[u8]> {
+        if self.load_options.is_null() {
+            None
+        } else {
+            let size = self.load_options_size as usize;
+            Some(unsafe { slice::from_raw_parts(self.load_options as *const u8, size) })
+        }
+    }
+
     /// Set the image data address and size.
     ///
     /// This is useful in the following scenario:
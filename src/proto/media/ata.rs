@@ -0,0 +1,700 @@
+//! ATA Pass Thru protocol.
+//!
+//! This protocol sends raw ATA commands to the devices attached to the ports
+//! of an ATA controller, which gives access to the features that the Block
+//! I/O protocol hides. The commands of the ATA security feature set are
+//! built by helpers, such as the ones which wipe a disk with a Secure Erase:
+//!
+//! ```no_run
+//! # use uefi::proto::media::ata::AtaPassThru;
+//! # fn f(ata: &mut AtaPassThru, port: u16, port_multiplier_port: u16) -> uefi::Result {
+//! // The device is one of `ata.devices()`
+//! let security = ata.security_status(port, port_multiplier_port)?.log();
+//! if security.supported() && !security.frozen() {
+//!     let timeout = security.erase_time(false);
+//!     ata.secure_erase(port, port_multiplier_port, b"wipe", false, timeout)?;
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::device_path::DevicePath;
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Result, ResultExt, Status};
+use bitflags::bitflags;
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr;
+use core::time::Duration;
+
+bitflags! {
+    /// Attributes of an ATA controller
+    pub struct AtaAttributes: u32 {
+        /// The devices can be addressed through their port
+        const PHYSICAL    = 0x0001;
+        /// The devices can be addressed as logical units
+        const LOGICAL     = 0x0002;
+        /// The controller supports non-blocking commands
+        const NONBLOCKIO  = 0x0004;
+    }
+}
+
+/// Mode of an ATA controller (`EFI_ATA_PASS_THRU_MODE`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AtaPassThruMode {
+    /// Attributes of the controller
+    pub attributes: AtaAttributes,
+    /// Alignment, in bytes, required for the data buffers of the commands
+    pub io_align: u32,
+}
+
+newtype_enum! {
+    /// How a command transfers its data
+    pub enum CommandProtocol: u8 => {
+        /// Hardware reset of the device
+        HARDWARE_RESET    = 0x00,
+        /// Software reset of the device
+        SOFTWARE_RESET    = 0x01,
+        /// Command without data
+        NON_DATA          = 0x02,
+        /// Command which reads data with PIO
+        PIO_DATA_IN       = 0x04,
+        /// Command which writes data with PIO
+        PIO_DATA_OUT      = 0x05,
+        /// Command which transfers data with DMA
+        DMA               = 0x06,
+        /// Command which transfers data with queued DMA
+        DMA_QUEUED        = 0x07,
+        /// EXECUTE DEVICE DIAGNOSTIC command
+        DEVICE_DIAGNOSTIC = 0x08,
+        /// DEVICE RESET command
+        DEVICE_RESET      = 0x09,
+        /// Command which reads data with Ultra DMA
+        UDMA_DATA_IN      = 0x0a,
+        /// Command which writes data with Ultra DMA
+        UDMA_DATA_OUT     = 0x0b,
+        /// Command which transfers data with first-party DMA
+        FPDMA             = 0x0c,
+        /// Only return the registers of the device
+        RETURN_RESPONSE   = 0xff,
+    }
+}
+
+/// How the length of the transfer is given, for the `Length` field of the
+/// packet: by the transfer lengths of the packet, in bytes, and by the sector
+/// count register
+const LENGTH_BYTES_SECTOR_COUNT: u8 = 0x80 | 0x20;
+
+/// Registers of a command (`EFI_ATA_COMMAND_BLOCK`)
+///
+/// The `_exp` registers are used by the 48-bit commands.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[allow(missing_docs)]
+pub struct CommandBlock {
+    reserved1: [u8; 2],
+    pub command: u8,
+    pub features: u8,
+    pub sector_number: u8,
+    pub cylinder_low: u8,
+    pub cylinder_high: u8,
+    pub device_head: u8,
+    pub sector_number_exp: u8,
+    pub cylinder_low_exp: u8,
+    pub cylinder_high_exp: u8,
+    pub features_exp: u8,
+    pub sector_count: u8,
+    pub sector_count_exp: u8,
+    reserved2: [u8; 6],
+}
+
+/// Registers of the device after a command (`EFI_ATA_STATUS_BLOCK`)
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[allow(missing_docs)]
+pub struct StatusBlock {
+    reserved1: [u8; 2],
+    pub status: u8,
+    pub error: u8,
+    pub sector_number: u8,
+    pub cylinder_low: u8,
+    pub cylinder_high: u8,
+    pub device_head: u8,
+    pub sector_number_exp: u8,
+    pub cylinder_low_exp: u8,
+    pub cylinder_high_exp: u8,
+    reserved2: u8,
+    pub sector_count: u8,
+    pub sector_count_exp: u8,
+    reserved3: [u8; 6],
+}
+
+/// The packet exchanged with the firmware
+/// (`EFI_ATA_PASS_THRU_COMMAND_PACKET`)
+#[repr(C)]
+struct CommandPacket {
+    asb: *mut StatusBlock,
+    acb: *mut CommandBlock,
+    timeout: u64,
+    in_data_buffer: *mut c_void,
+    out_data_buffer: *mut c_void,
+    in_transfer_length: u32,
+    out_transfer_length: u32,
+    protocol: CommandProtocol,
+    length: u8,
+}
+
+/// Size of a sector, and of the data of the IDENTIFY DEVICE and security
+/// commands
+pub const SECTOR_SIZE: usize = 512;
+
+/// A sector aligned for the controllers, for the data of the helpers
+#[repr(C, align(64))]
+struct Sector([u8; SECTOR_SIZE]);
+
+/// Largest size of the passwords of the security feature set
+pub const MAX_PASSWORD_LENGTH: usize = 32;
+
+/// A command, with the data it reads or writes
+///
+/// The data buffers must be aligned to `AtaPassThruMode::io_align`.
+#[derive(Debug)]
+pub struct Command<'buf> {
+    block: CommandBlock,
+    protocol: CommandProtocol,
+    timeout: u64,
+    input: *mut u8,
+    input_length: u32,
+    output: *mut u8,
+    output_length: u32,
+    _buffers: PhantomData<&'buf mut [u8]>,
+}
+
+impl<'buf> Command<'buf> {
+    /// Build a command with the registers of `block`, whose data is
+    /// transferred according to `protocol`
+    pub fn new(block: CommandBlock, protocol: CommandProtocol) -> Self {
+        Command {
+            block,
+            protocol,
+            timeout: 0,
+            input: ptr::null_mut(),
+            input_length: 0,
+            output: ptr::null_mut(),
+            output_length: 0,
+            _buffers: PhantomData,
+        }
+    }
+
+    /// Build a command without data, which only sets the command register
+    pub fn non_data(command: u8) -> Self {
+        Self::new(
+            CommandBlock {
+                command,
+                device_head: 0xe0,
+                ..CommandBlock::default()
+            },
+            CommandProtocol::NON_DATA,
+        )
+    }
+
+    /// Build a command which reads one sector with PIO into `buffer`
+    fn pio_in(command: u8, buffer: &'buf mut [u8; SECTOR_SIZE]) -> Self {
+        let mut command = Self::non_data(command).read_into(buffer);
+        command.block.sector_count = 1;
+        command.protocol = CommandProtocol::PIO_DATA_IN;
+        command
+    }
+
+    /// Build a command which writes one sector with PIO from `buffer`
+    fn pio_out(command: u8, buffer: &'buf [u8; SECTOR_SIZE]) -> Self {
+        let mut command = Self::non_data(command).write_from(buffer);
+        command.block.sector_count = 1;
+        command.protocol = CommandProtocol::PIO_DATA_OUT;
+        command
+    }
+
+    /// Read the data sent by the device into `buffer`
+    pub fn read_into(mut self, buffer: &'buf mut [u8]) -> Self {
+        self.input = buffer.as_mut_ptr();
+        self.input_length = buffer.len().try_into().unwrap();
+        self
+    }
+
+    /// Send the data of `buffer` to the device
+    pub fn write_from(mut self, buffer: &'buf [u8]) -> Self {
+        // The buffer is only read by the controller
+        self.output = buffer.as_ptr() as *mut u8;
+        self.output_length = buffer.len().try_into().unwrap();
+        self
+    }
+
+    /// Fail if the command takes longer than `timeout`, instead of waiting
+    /// for it indefinitely
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        // The timeout is expressed in units of 100ns, 0 meaning none
+        let units = (timeout.as_nanos() / 100).max(1);
+        self.timeout = units.try_into().unwrap_or(u64::MAX);
+        self
+    }
+
+    /// The registers of the command
+    pub fn block(&self) -> &CommandBlock {
+        &self.block
+    }
+
+    /// IDENTIFY DEVICE, reading the identification data of the device
+    pub fn identify_device(buffer: &'buf mut [u8; SECTOR_SIZE]) -> Self {
+        Self::pio_in(0xec, buffer)
+    }
+
+    /// SECURITY SET PASSWORD, with the data built by `security_payload`
+    ///
+    /// Setting the user password enables the security feature set.
+    pub fn security_set_password(payload: &'buf [u8; SECTOR_SIZE]) -> Self {
+        Self::pio_out(0xf1, payload)
+    }
+
+    /// SECURITY UNLOCK, with the data built by `security_payload`
+    pub fn security_unlock(payload: &'buf [u8; SECTOR_SIZE]) -> Self {
+        Self::pio_out(0xf2, payload)
+    }
+
+    /// SECURITY ERASE PREPARE, which must directly precede SECURITY ERASE
+    /// UNIT
+    pub fn security_erase_prepare() -> Self {
+        Self::non_data(0xf3)
+    }
+
+    /// SECURITY ERASE UNIT, with the data built by `security_payload`
+    ///
+    /// The erase may take hours, as told by `SecurityStatus::erase_time`, so
+    /// the timeout of the command should be set accordingly.
+    pub fn security_erase_unit(payload: &'buf [u8; SECTOR_SIZE]) -> Self {
+        Self::pio_out(0xf4, payload)
+    }
+
+    /// SECURITY FREEZE LOCK, which prevents changes to the security state
+    /// until the next power cycle
+    pub fn security_freeze_lock() -> Self {
+        Self::non_data(0xf5)
+    }
+
+    /// SECURITY DISABLE PASSWORD, with the data built by `security_payload`
+    pub fn security_disable_password(payload: &'buf [u8; SECTOR_SIZE]) -> Self {
+        Self::pio_out(0xf6, payload)
+    }
+}
+
+/// Build the data of the security commands which take a password, or return
+/// `None` if `password` is longer than `MAX_PASSWORD_LENGTH`.
+///
+/// `master` selects the master password instead of the user one, and
+/// `enhanced` requests an enhanced erase from SECURITY ERASE UNIT.
+pub fn security_payload(
+    password: &[u8],
+    master: bool,
+    enhanced: bool,
+) -> Option<[u8; SECTOR_SIZE]> {
+    if password.len() > MAX_PASSWORD_LENGTH {
+        return None;
+    }
+    let mut payload = [0; SECTOR_SIZE];
+    // Word 0 holds the control bits, the password starts at word 1
+    payload[0] = u8::from(master) | (u8::from(enhanced) << 1);
+    payload[2..2 + password.len()].copy_from_slice(password);
+    Some(payload)
+}
+
+/// The security state of a device, from its identification data
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SecurityStatus {
+    status: u16,
+    erase_time: u16,
+    enhanced_erase_time: u16,
+}
+
+impl SecurityStatus {
+    /// Read the security state from the data returned by IDENTIFY DEVICE
+    pub fn from_identify_data(data: &[u8; SECTOR_SIZE]) -> Self {
+        let word = |index: usize| u16::from_le_bytes([data[2 * index], data[2 * index + 1]]);
+        SecurityStatus {
+            status: word(128),
+            erase_time: word(89),
+            enhanced_erase_time: word(90),
+        }
+    }
+
+    /// Whether the device supports the security feature set
+    pub fn supported(&self) -> bool {
+        self.status & 0x0001 != 0
+    }
+
+    /// Whether a user password is set
+    pub fn enabled(&self) -> bool {
+        self.status & 0x0002 != 0
+    }
+
+    /// Whether the device is locked until its password is given
+    pub fn locked(&self) -> bool {
+        self.status & 0x0004 != 0
+    }
+
+    /// Whether the security state cannot be changed until the next power
+    /// cycle, as firmwares usually do before booting
+    pub fn frozen(&self) -> bool {
+        self.status & 0x0008 != 0
+    }
+
+    /// Whether too many wrong passwords were given, which must be followed by
+    /// a power cycle
+    pub fn count_expired(&self) -> bool {
+        self.status & 0x0010 != 0
+    }
+
+    /// Whether the device supports enhanced erases
+    pub fn enhanced_erase_supported(&self) -> bool {
+        self.status & 0x0020 != 0
+    }
+
+    /// The time which the device estimates for a normal or an enhanced erase,
+    /// if it reports one
+    ///
+    /// The estimation is an upper bound, and is `None` when it is unknown or
+    /// too large to be reported.
+    pub fn erase_time(&self, enhanced: bool) -> Option<Duration> {
+        let word = if enhanced {
+            self.enhanced_erase_time
+        } else {
+            self.erase_time
+        };
+        // With the extended format, the time is on 15 bits instead of 8
+        let (value, max) = if word & 0x8000 != 0 {
+            (word & 0x7fff, 0x7fff)
+        } else {
+            (word & 0x00ff, 0x00ff)
+        };
+        if value == 0 || value == max {
+            None
+        } else {
+            // The time is given in units of 2 minutes
+            Some(Duration::from_secs(u64::from(value) * 120))
+        }
+    }
+}
+
+/// The ATA Pass Thru protocol.
+///
+/// It is installed on the handles of ATA controllers, whose devices are
+/// addressed by a port and a port multiplier port, the latter being 0xffff
+/// for the devices attached directly to the port.
+#[repr(C)]
+#[unsafe_guid("1d3de7f0-0807-424f-aa69-11a54e19a46f")]
+#[derive(Protocol)]
+pub struct AtaPassThru {
+    mode: *const AtaPassThruMode,
+    pass_thru: unsafe extern "efiapi" fn(
+        this: *mut AtaPassThru,
+        port: u16,
+        port_multiplier_port: u16,
+        packet: *mut CommandPacket,
+        event: *mut c_void,
+    ) -> Status,
+    get_next_port: extern "efiapi" fn(this: &AtaPassThru, port: &mut u16) -> Status,
+    get_next_device:
+        extern "efiapi" fn(this: &AtaPassThru, port: u16, port_multiplier_port: &mut u16) -> Status,
+    build_device_path: extern "efiapi" fn(
+        this: &AtaPassThru,
+        port: u16,
+        port_multiplier_port: u16,
+        device_path: &mut *mut DevicePath,
+    ) -> Status,
+    get_device: extern "efiapi" fn(
+        this: &AtaPassThru,
+        device_path: &DevicePath,
+        port: &mut u16,
+        port_multiplier_port: &mut u16,
+    ) -> Status,
+    reset_port: extern "efiapi" fn(this: &AtaPassThru, port: u16) -> Status,
+    reset_device:
+        extern "efiapi" fn(this: &AtaPassThru, port: u16, port_multiplier_port: u16) -> Status,
+}
+
+impl AtaPassThru {
+    /// The mode of the controller
+    pub fn mode(&self) -> &AtaPassThruMode {
+        unsafe { &*self.mode }
+    }
+
+    /// Send a command to a device, and return its registers afterwards.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`    The data is too large for the
+    ///                                      controller
+    /// * `uefi::Status::INVALID_PARAMETER`  A buffer is not aligned, or the
+    ///                                      device does not exist
+    /// * `uefi::Status::UNSUPPORTED`        The command is not supported
+    /// * `uefi::Status::TIMEOUT`            The timeout of the command expired
+    /// * `uefi::Status::DEVICE_ERROR`       The device reported an error
+    pub fn pass_thru(
+        &mut self,
+        port: u16,
+        port_multiplier_port: u16,
+        command: &mut Command,
+    ) -> Result<StatusBlock> {
+        let mut status_block = StatusBlock::default();
+        let length = if command.input_length != 0 || command.output_length != 0 {
+            LENGTH_BYTES_SECTOR_COUNT
+        } else {
+            0
+        };
+        let mut packet = CommandPacket {
+            asb: &mut status_block,
+            acb: &mut command.block,
+            timeout: command.timeout,
+            in_data_buffer: command.input.cast(),
+            out_data_buffer: command.output.cast(),
+            in_transfer_length: command.input_length,
+            out_transfer_length: command.output_length,
+            protocol: command.protocol,
+            length,
+        };
+        let status = unsafe {
+            (self.pass_thru)(
+                self,
+                port,
+                port_multiplier_port,
+                &mut packet,
+                ptr::null_mut(),
+            )
+        };
+        // The lengths are updated with the amount of data actually transferred
+        command.input_length = packet.in_transfer_length;
+        command.output_length = packet.out_transfer_length;
+        status.into_with_val(|| status_block)
+    }
+
+    /// Iterate over the ports of the controller
+    pub fn ports(&self) -> Ports<'_> {
+        Ports {
+            pass_thru: self,
+            port: Some(0xffff),
+        }
+    }
+
+    /// Iterate over the devices of the controller, as pairs of a port and a
+    /// port multiplier port
+    pub fn devices(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.ports().flat_map(move |port| {
+            let mut device = Some(0xffff);
+            core::iter::from_fn(move || {
+                let mut port_multiplier_port = device?;
+                let status = (self.get_next_device)(self, port, &mut port_multiplier_port);
+                device = if status.is_success() {
+                    Some(port_multiplier_port)
+                } else {
+                    None
+                };
+                device.map(|port_multiplier_port| (port, port_multiplier_port))
+            })
+        })
+    }
+
+    /// Build the device path node of a device.
+    ///
+    /// The node is allocated from pool memory, and should be freed with
+    /// `BootServices::free_pool` once it is no longer needed.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`         The device does not exist
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The node could not be allocated
+    pub fn build_device_path(
+        &self,
+        port: u16,
+        port_multiplier_port: u16,
+    ) -> Result<*mut DevicePath> {
+        let mut device_path = ptr::null_mut();
+        (self.build_device_path)(self, port, port_multiplier_port, &mut device_path)
+            .into_with_val(|| device_path)
+    }
+
+    /// Get the port and port multiplier port of the device designated by a
+    /// device path node, as built by `build_device_path`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`  The node is not an ATA node
+    /// * `uefi::Status::NOT_FOUND`    The device does not exist
+    pub fn get_device(&self, device_path: &DevicePath) -> Result<(u16, u16)> {
+        let mut port = 0;
+        let mut port_multiplier_port = 0;
+        (self.get_device)(self, device_path, &mut port, &mut port_multiplier_port)
+            .into_with_val(|| (port, port_multiplier_port))
+    }
+
+    /// Reset a port, and the devices attached to it.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`   The controller cannot reset ports
+    /// * `uefi::Status::DEVICE_ERROR`  The port could not be reset
+    pub fn reset_port(&mut self, port: u16) -> Result {
+        (self.reset_port)(self, port).into()
+    }
+
+    /// Reset a device.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`   The controller cannot reset devices
+    /// * `uefi::Status::DEVICE_ERROR`  The device could not be reset
+    pub fn reset_device(&mut self, port: u16, port_multiplier_port: u16) -> Result {
+        (self.reset_device)(self, port, port_multiplier_port).into()
+    }
+
+    /// Read the security state of a device.
+    ///
+    /// # Errors
+    ///
+    /// See `pass_thru`.
+    pub fn security_status(
+        &mut self,
+        port: u16,
+        port_multiplier_port: u16,
+    ) -> Result<SecurityStatus> {
+        let mut data = Sector([0; SECTOR_SIZE]);
+        let mut command = Command::identify_device(&mut data.0);
+        let (status, _) = self
+            .pass_thru(port, port_multiplier_port, &mut command)?
+            .split();
+        status.into_with_val(|| SecurityStatus::from_identify_data(&data.0))
+    }
+
+    /// Erase all the data of a device with the ATA Secure Erase, and wait for
+    /// the erase to complete or for `timeout` to expire.
+    ///
+    /// If no user password is set, `password` is set first. Otherwise, it
+    /// must be the current user password. The password is cleared by the
+    /// erase.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`        The device does not support the
+    ///                                      security feature set, or the
+    ///                                      enhanced erase
+    /// * `uefi::Status::ACCESS_DENIED`      The security state is frozen, or
+    ///                                      too many wrong passwords were
+    ///                                      given
+    /// * `uefi::Status::INVALID_PARAMETER`  The password is too long
+    /// * `uefi::Status::DEVICE_ERROR`       The device rejected a command,
+    ///                                      like for a wrong password
+    ///
+    /// See also `pass_thru`.
+    pub fn secure_erase(
+        &mut self,
+        port: u16,
+        port_multiplier_port: u16,
+        password: &[u8],
+        enhanced: bool,
+        timeout: Option<Duration>,
+    ) -> Result {
+        let security = self.security_status(port, port_multiplier_port)?.log();
+        if !security.supported() || (enhanced && !security.enhanced_erase_supported()) {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        if security.frozen() || security.count_expired() {
+            return Err(Status::ACCESS_DENIED.into());
+        }
+        let mut payload = Sector([0; SECTOR_SIZE]);
+        payload.0 = match security_payload(password, false, false) {
+            Some(payload) => payload,
+            None => return Err(Status::INVALID_PARAMETER.into()),
+        };
+        if !security.enabled() {
+            let mut command = Command::security_set_password(&payload.0);
+            self.pass_thru(port, port_multiplier_port, &mut command)?
+                .log();
+        }
+
+        // The erase unit command must directly follow the prepare command
+        payload.0[0] |= u8::from(enhanced) << 1;
+        let mut command = Command::security_erase_prepare();
+        self.pass_thru(port, port_multiplier_port, &mut command)?
+            .log();
+        let mut command = Command::security_erase_unit(&payload.0);
+        if let Some(timeout) = timeout {
+            command = command.with_timeout(timeout);
+        }
+        self.pass_thru(port, port_multiplier_port, &mut command)
+            .map_inner(|_| ())
+    }
+}
+
+/// Iterator over the ports of an ATA controller
+///
+/// Returned by `AtaPassThru::ports`.
+pub struct Ports<'pass_thru> {
+    pass_thru: &'pass_thru AtaPassThru,
+    port: Option<u16>,
+}
+
+impl<'pass_thru> Iterator for Ports<'pass_thru> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        let mut port = self.port?;
+        // Starting from 0xffff returns the first port, and NOT_FOUND is
+        // returned after the last one
+        let status = (self.pass_thru.get_next_port)(self.pass_thru, &mut port);
+        self.port = if status.is_success() {
+            Some(port)
+        } else {
+            None
+        };
+        self.port
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_commands() {
+        assert_eq!(core::mem::size_of::<CommandBlock>(), 20);
+        assert_eq!(core::mem::size_of::<StatusBlock>(), 20);
+
+        let payload = security_payload(b"secret", false, true).unwrap();
+        assert_eq!(payload[0], 0b10);
+        assert_eq!(&payload[2..8], b"secret");
+        assert!(payload[8..].iter().all(|&byte| byte == 0));
+        assert!(security_payload(&[b'x'; 33], false, false).is_none());
+
+        let command = Command::security_erase_unit(&payload).with_timeout(Duration::from_secs(1));
+        assert_eq!(command.block().command, 0xf4);
+        assert_eq!(command.block().sector_count, 1);
+        assert_eq!(command.protocol, CommandProtocol::PIO_DATA_OUT);
+        assert_eq!(command.output_length, 512);
+        assert_eq!(command.timeout, 10_000_000);
+        assert_eq!(
+            Command::security_erase_prepare().protocol,
+            CommandProtocol::NON_DATA
+        );
+
+        let mut identify = [0; SECTOR_SIZE];
+        // Supported, enabled and enhanced erase supported
+        identify[256] = 0x23;
+        identify[178] = 30;
+        identify[180] = 0xff;
+        let security = SecurityStatus::from_identify_data(&identify);
+        assert!(security.supported() && security.enabled());
+        assert!(!security.locked() && !security.frozen());
+        assert!(security.enhanced_erase_supported());
+        assert_eq!(security.erase_time(false), Some(Duration::from_secs(3600)));
+        assert_eq!(security.erase_time(true), None);
+    }
+}
@@ -1,7 +1,18 @@
 //! Block I/O protocols.
+//!
+//! With the `exts` feature, [`MemoryBlockDevice`] implements the protocol
+//! with a buffer, so that the code which uses block devices can be tested
+//! without a disk.
 
 use crate::proto::Protocol;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{boxed::Box, vec::Vec},
+    proto::ProtocolImpl,
+};
 use crate::{unsafe_guid, Result, Status};
+#[cfg(feature = "exts")]
+use core::{cell::UnsafeCell, convert::TryFrom, ffi::c_void, ptr};
 
 /// The Block I/O protocol.
 #[repr(C)]
@@ -30,11 +41,52 @@ pub struct BlockIO {
 }
 
 impl BlockIO {
+    /// Revision of the protocol which added the alignment fields of the media
+    pub const REVISION2: u64 = 0x0002_0001;
+    /// Revision of the protocol which added the transfer length granularity
+    pub const REVISION3: u64 = 0x0002_001f;
+
     /// Pointer for block IO media.
     pub fn media(&self) -> &BlockIOMedia {
         unsafe { &*self.media }
     }
 
+    /// Revision of the protocol, which tells which fields of the media exist
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the first LBA that is aligned to a physical block boundary, or
+    /// `None` if the protocol predates `REVISION2`.
+    pub fn lowest_aligned_lba(&self) -> Option<Lba> {
+        self.since(Self::REVISION2, |media| media.lowest_aligned_lba)
+    }
+
+    /// Returns the number of logical blocks per physical block, or `None` if
+    /// the protocol predates `REVISION2`.
+    pub fn logical_blocks_per_physical_block(&self) -> Option<u32> {
+        self.since(Self::REVISION2, |media| {
+            media.logical_blocks_per_physical_block
+        })
+    }
+
+    /// Returns the optimal transfer length granularity as a number of logical
+    /// blocks, or `None` if the protocol predates `REVISION3`.
+    pub fn optimal_transfer_length_granularity(&self) -> Option<u32> {
+        self.since(Self::REVISION3, |media| {
+            media.optimal_transfer_length_granularity
+        })
+    }
+
+    /// Read a field of the media which was added by `revision`
+    fn since<T>(&self, revision: u64, field: impl FnOnce(&BlockIOMedia) -> T) -> Option<T> {
+        if self.revision >= revision {
+            Some(field(self.media()))
+        } else {
+            None
+        }
+    }
+
     /// Resets the block device hardware.
     ///
     /// # Arguments
@@ -175,17 +227,230 @@ impl BlockIOMedia {
     }
 
     /// Returns the first LBA that is aligned to a physical block boundary.
+    ///
+    /// Older firmware does not provide this field, see
+    /// `BlockIO::lowest_aligned_lba`.
     pub fn lowest_aligned_lba(&self) -> Lba {
         self.lowest_aligned_lba
     }
 
     /// Returns the number of logical blocks per physical block.
+    ///
+    /// Older firmware does not provide this field, see
+    /// `BlockIO::logical_blocks_per_physical_block`.
     pub fn logical_blocks_per_physical_block(&self) -> u32 {
         self.logical_blocks_per_physical_block
     }
 
     /// Returns the optimal transfer length granularity as a number of logical blocks.
+    ///
+    /// Older firmware does not provide this field, see
+    /// `BlockIO::optimal_transfer_length_granularity`.
     pub fn optimal_transfer_length_granularity(&self) -> u32 {
         self.optimal_transfer_length_granularity
     }
 }
+
+/// A block device whose blocks are stored in memory, which implements
+/// `BlockIO`
+///
+/// Installed on a new handle, it stands for a disk in tests, or serves as a
+/// RAM disk. Its media ID is always 0.
+#[cfg(feature = "exts")]
+#[repr(C)]
+pub struct MemoryBlockDevice {
+    // First, since the functions of the protocol find the device from it
+    protocol: BlockIO,
+    media: BlockIOMedia,
+    // Written through the shared references which the protocol gets
+    data: UnsafeCell<Vec<u8>>,
+}
+
+#[cfg(feature = "exts")]
+unsafe impl ProtocolImpl for MemoryBlockDevice {
+    type Protocol = BlockIO;
+
+    fn interface(&mut self) -> *mut c_void {
+        &mut self.protocol as *mut BlockIO as *mut c_void
+    }
+}
+
+#[cfg(feature = "exts")]
+impl MemoryBlockDevice {
+    /// Create a device holding `data`, split in blocks of `block_size`
+    /// bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is empty, or is not a whole number of blocks.
+    pub fn new(data: Vec<u8>, block_size: u32, read_only: bool) -> Box<Self> {
+        let blocks = data.len() / block_size as usize;
+        assert!(
+            blocks != 0 && blocks * block_size as usize == data.len(),
+            "The data must be a whole number of blocks"
+        );
+        let mut device = Box::new(MemoryBlockDevice {
+            protocol: BlockIO {
+                // Revision 3, which has all the fields of the media
+                revision: BlockIO::REVISION3,
+                media: ptr::null(),
+                reset: Self::reset,
+                read_blocks: Self::read_blocks,
+                write_blocks: Self::write_blocks,
+                flush_blocks: Self::flush_blocks,
+            },
+            media: BlockIOMedia {
+                media_id: 0,
+                removable_media: false,
+                media_present: true,
+                logical_partition: false,
+                read_only,
+                write_caching: false,
+                block_size,
+                io_align: 1,
+                last_block: blocks as Lba - 1,
+                lowest_aligned_lba: 0,
+                logical_blocks_per_physical_block: 1,
+                optimal_transfer_length_granularity: 0,
+            },
+            data: UnsafeCell::new(data),
+        });
+        device.protocol.media = &device.media;
+        device
+    }
+
+    /// The protocol, to use the device without installing it
+    pub fn block_io(&mut self) -> &mut BlockIO {
+        &mut self.protocol
+    }
+
+    /// The content of the device
+    pub fn data(&self) -> &[u8] {
+        unsafe { &*self.data.get() }
+    }
+
+    /// The content of the device, after it is uninstalled
+    pub fn into_data(self) -> Vec<u8> {
+        self.data.into_inner()
+    }
+
+    /// The device of the protocol
+    fn from_protocol(this: &BlockIO) -> &Self {
+        unsafe { &*(this as *const BlockIO as *const Self) }
+    }
+
+    /// The byte range of a transfer, after the checks of the specification
+    fn range(
+        &self,
+        media_id: u32,
+        lba: Lba,
+        size: usize,
+        buffer: *const u8,
+    ) -> core::result::Result<core::ops::Range<usize>, Status> {
+        let block_size = self.media.block_size as usize;
+        if media_id != self.media.media_id {
+            return Err(Status::MEDIA_CHANGED);
+        }
+        if size / block_size * block_size != size {
+            return Err(Status::BAD_BUFFER_SIZE);
+        }
+        let start = usize::try_from(lba)
+            .ok()
+            .and_then(|lba| lba.checked_mul(block_size));
+        match start.and_then(|start| Some(start..start.checked_add(size)?)) {
+            Some(range) if range.end <= self.data().len() && !buffer.is_null() => Ok(range),
+            _ => Err(Status::INVALID_PARAMETER),
+        }
+    }
+
+    extern "efiapi" fn reset(_this: &BlockIO, _extended_verification: bool) -> Status {
+        Status::SUCCESS
+    }
+
+    extern "efiapi" fn read_blocks(
+        this: &BlockIO,
+        media_id: u32,
+        lba: Lba,
+        buffer_size: usize,
+        buffer: *mut u8,
+    ) -> Status {
+        let device = Self::from_protocol(this);
+        match device.range(media_id, lba, buffer_size, buffer) {
+            Ok(range) => {
+                let data = &device.data()[range];
+                unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len()) };
+                Status::SUCCESS
+            }
+            Err(status) => status,
+        }
+    }
+
+    extern "efiapi" fn write_blocks(
+        this: &BlockIO,
+        media_id: u32,
+        lba: Lba,
+        buffer_size: usize,
+        buffer: *const u8,
+    ) -> Status {
+        let device = Self::from_protocol(this);
+        if device.media.read_only {
+            return Status::WRITE_PROTECTED;
+        }
+        match device.range(media_id, lba, buffer_size, buffer) {
+            Ok(range) => {
+                let data = unsafe { &mut *device.data.get() };
+                let data = &mut data[range];
+                unsafe { ptr::copy_nonoverlapping(buffer, data.as_mut_ptr(), data.len()) };
+                Status::SUCCESS
+            }
+            Err(status) => status,
+        }
+    }
+
+    extern "efiapi" fn flush_blocks(_this: &BlockIO) -> Status {
+        Status::SUCCESS
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::vec;
+    use crate::prelude::*;
+
+    #[test]
+    fn memory_block_device() {
+        let mut device = MemoryBlockDevice::new(vec![0; 4 * 512], 512, false);
+        let block_io = device.block_io();
+        assert_eq!(block_io.media().block_size(), 512);
+        assert_eq!(block_io.media().last_block(), 3);
+        assert_eq!(block_io.lowest_aligned_lba(), Some(0));
+        assert_eq!(block_io.optimal_transfer_length_granularity(), Some(0));
+
+        block_io.write_blocks(0, 2, &[0x42; 512]).unwrap_success();
+        let mut block = [0; 1024];
+        block_io.read_blocks(0, 1, &mut block).unwrap_success();
+        assert!(block[..512].iter().all(|&byte| byte == 0));
+        assert!(block[512..].iter().all(|&byte| byte == 0x42));
+
+        assert_eq!(
+            block_io.read_blocks(0, 3, &mut block).status(),
+            Status::INVALID_PARAMETER
+        );
+        assert_eq!(
+            block_io.read_blocks(0, 0, &mut block[..100]).status(),
+            Status::BAD_BUFFER_SIZE
+        );
+        assert_eq!(
+            block_io.read_blocks(1, 0, &mut block).status(),
+            Status::MEDIA_CHANGED
+        );
+        assert_eq!(device.into_data()[2 * 512], 0x42);
+
+        let mut device = MemoryBlockDevice::new(vec![0; 512], 512, true);
+        assert_eq!(
+            device.block_io().write_blocks(0, 0, &[1; 512]).status(),
+            Status::WRITE_PROTECTED
+        );
+    }
+}
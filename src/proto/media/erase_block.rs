@@ -0,0 +1,85 @@
+//! Erase Block protocol.
+//!
+//! Devices which can discard their content, like SSDs through TRIM or SD
+//! cards through their erase commands, provide this protocol next to their
+//! Block I/O protocol. Erasing is much faster than overwriting the blocks,
+//! but whether the erased data can still be recovered depends on the device.
+//! The ATA security commands of `proto::media::ata` give a stronger
+//! guarantee.
+
+use super::block::Lba;
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Result, Status};
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::ptr;
+
+/// The token of an erase (`EFI_ERASE_BLOCK_TOKEN`)
+#[repr(C)]
+struct EraseBlockToken {
+    event: *mut c_void,
+    transaction_status: Status,
+}
+
+/// The Erase Block protocol.
+#[repr(C)]
+#[unsafe_guid("95a9a93e-a86e-4926-aaef-9918e772d987")]
+#[derive(Protocol)]
+pub struct EraseBlock {
+    revision: u64,
+    erase_length_granularity: u32,
+    erase_blocks: unsafe extern "efiapi" fn(
+        this: *mut EraseBlock,
+        media_id: u32,
+        lba: Lba,
+        token: *mut EraseBlockToken,
+        size: usize,
+    ) -> Status,
+}
+
+impl EraseBlock {
+    /// Revision of the protocol
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Number of blocks which are erased together
+    ///
+    /// Erases should start at a multiple of it, and cover a multiple of it,
+    /// as the device may otherwise leave the incomplete parts untouched.
+    pub fn erase_length_granularity(&self) -> u32 {
+        self.erase_length_granularity
+    }
+
+    /// Erase `size` bytes, starting at the block `lba`, and wait for the
+    /// erase to complete.
+    ///
+    /// `media_id` is the one of the Block I/O protocol of the device, and
+    /// `size` a multiple of its block size.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::WRITE_PROTECTED`    The device cannot be written to
+    /// * `uefi::Status::NO_MEDIA`           There is no medium in the device
+    /// * `uefi::Status::MEDIA_CHANGED`      `media_id` is not the one of the
+    ///                                      current medium
+    /// * `uefi::Status::INVALID_PARAMETER`  The range is not valid
+    /// * `uefi::Status::DEVICE_ERROR`       The erase failed
+    pub fn erase_blocks(&mut self, media_id: u32, lba: Lba, size: u64) -> Result {
+        let size = match size.try_into() {
+            Ok(size) => size,
+            Err(_) => return Err(Status::INVALID_PARAMETER.into()),
+        };
+        // Without an event, the erase is done synchronously
+        let mut token = EraseBlockToken {
+            event: ptr::null_mut(),
+            transaction_status: Status::SUCCESS,
+        };
+        let status = unsafe { (self.erase_blocks)(self, media_id, lba, &mut token, size) };
+        if status.is_success() {
+            token.transaction_status.into()
+        } else {
+            status.into()
+        }
+    }
+}
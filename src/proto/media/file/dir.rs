@@ -1,8 +1,18 @@
 use super::{File, FileHandle, FileInfo, FromUefi, RegularFile};
+#[cfg(feature = "exts")]
+use super::{FileAttribute, FileMode, FileType};
 use crate::data_types::Align;
 use crate::prelude::*;
 use crate::Result;
+#[cfg(feature = "exts")]
+use crate::{CStr16, Completion, Status};
+#[cfg(feature = "exts")]
+use alloc_api::{alloc::Layout, boxed::Box, vec, vec::Vec};
+#[cfg(feature = "exts")]
+use core::convert::TryFrom;
 use core::ffi::c_void;
+#[cfg(feature = "exts")]
+use core::mem;
 
 /// A `FileHandle` that is also a directory.
 ///
@@ -61,6 +71,254 @@ impl Directory {
     pub fn reset_entry_readout(&mut self) -> Result {
         self.0.set_position(0)
     }
+
+    /// Read the next directory entry into a dynamically allocated buffer
+    ///
+    /// Returns `None` if there are no more directory entries.
+    #[cfg(feature = "exts")]
+    pub fn read_entry_boxed(&mut self) -> Result<Option<Box<FileInfo>>> {
+        // Reading into an empty buffer only succeeds at the end of the
+        // directory, otherwise it reports the size of the next entry.
+        let size = match self.read_entry(&mut []) {
+            Ok(completion) => return Ok(completion.map(|_| None)),
+            Err(error) => match error.split() {
+                (_, Some(size)) => size,
+                (s, None) => return Err(s.into()),
+            },
+        };
+
+        let layout = Layout::from_size_align(size, FileInfo::alignment())
+            .unwrap()
+            .pad_to_align();
+        let mut buffer = crate::exts::allocate_buffer(layout);
+        let buffer_start = buffer.as_ptr();
+
+        let info = self.read_entry(&mut buffer).discard_errdata()?.map(|info| {
+            // The entry uses the memory of the buffer, which is leaked below,
+            // so the box becomes its only owner.
+            // It must also fill the buffer, so that the box frees it with
+            // the layout it was allocated with.
+            info.map(|info_ref| {
+                assert_eq!(info_ref as *const FileInfo as *const u8, buffer_start);
+                assert_eq!(Layout::for_value(info_ref), layout);
+                unsafe { Box::from_raw(info_ref as *mut _) }
+            })
+        });
+        mem::forget(buffer);
+
+        Ok(info)
+    }
+
+    /// Iterate over the entries of this directory, from the current position
+    ///
+    /// Use `reset_entry_readout` first to start from the first entry. The
+    /// entries named `.` and `..` are returned like any other entry.
+    #[cfg(feature = "exts")]
+    pub fn entries(&mut self) -> DirectoryEntries<'_> {
+        DirectoryEntries {
+            dir: self,
+            done: false,
+        }
+    }
+
+    /// Recursively visit all the files below this directory
+    ///
+    /// The directory tree is walked depth-first, and `visitor` is called on
+    /// every entry with its path relative to this directory, before the
+    /// entries of a subdirectory are visited. The `.` and `..` entries are
+    /// skipped. The walk stops at the first error, including the ones
+    /// returned by `visitor`.
+    ///
+    /// The directory entries are read from the start, so the readout
+    /// position of this directory is reset.
+    #[cfg(feature = "exts")]
+    pub fn walk<F>(&mut self, mut visitor: F) -> Result
+    where
+        F: FnMut(&CStr16, &FileInfo) -> Result,
+    {
+        let mut path = Vec::new();
+        self.walk_impl(&mut path, &mut visitor)
+    }
+
+    #[cfg(feature = "exts")]
+    fn walk_impl(
+        &mut self,
+        path: &mut Vec<u16>,
+        visitor: &mut dyn FnMut(&CStr16, &FileInfo) -> Result,
+    ) -> Result {
+        self.reset_entry_readout().log_warning()?;
+        while let Some(info) = self.read_entry_boxed().log_warning()? {
+            let name = info.file_name();
+            if is_dot_entry(name) {
+                continue;
+            }
+
+            let parent_len = path.len();
+            if parent_len != 0 {
+                path.push(u16::from(b'\\'));
+            }
+            path.extend_from_slice(name.to_u16_slice());
+            path.push(0);
+            // File names never contain a null character
+            let entry_path = unsafe { CStr16::from_u16_with_nul_unchecked(path) };
+            visitor(entry_path, &info).log_warning()?;
+            path.pop();
+
+            if info.attribute().contains(FileAttribute::DIRECTORY) {
+                let handle = self
+                    .open_cstr16(name, FileMode::Read, FileAttribute::empty())
+                    .log_warning()?;
+                if let FileType::Dir(mut dir) = handle.into_type().log_warning()? {
+                    dir.walk_impl(path, visitor).log_warning()?;
+                }
+            }
+            path.truncate(parent_len);
+        }
+        Ok(().into())
+    }
+
+    /// Read the whole content of a file
+    ///
+    /// `path` is relative to this directory, with components separated by
+    /// backslashes.
+    ///
+    /// # Errors
+    /// * `uefi::Status::INVALID_PARAMETER`  The path designates a directory
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The file does not fit in memory
+    /// * Any error of `File::open`, `File::get_info` and `RegularFile::read`
+    #[cfg(feature = "exts")]
+    pub fn read_to_vec(&mut self, path: &CStr16) -> Result<Vec<u8>> {
+        let handle = self
+            .open_cstr16(path, FileMode::Read, FileAttribute::empty())
+            .log_warning()?;
+        let mut file = match handle.into_type().log_warning()? {
+            FileType::Regular(file) => file,
+            FileType::Dir(_) => return Err(Status::INVALID_PARAMETER.into()),
+        };
+
+        let info = file.get_boxed_info::<FileInfo>().log_warning()?;
+        let size = usize::try_from(info.file_size()).map_err(|_| Status::OUT_OF_RESOURCES)?;
+
+        let mut content = vec![0; size];
+        let mut filled = 0;
+        while filled < size {
+            let read = file
+                .read(&mut content[filled..])
+                .discard_errdata()
+                .log_warning()?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        content.truncate(filled);
+
+        Ok(content.into())
+    }
+
+    /// Write `data` to a file, which is created or truncated
+    ///
+    /// `path` is relative to this directory, with components separated by
+    /// backslashes. The parent directories must exist.
+    ///
+    /// # Errors
+    /// * `uefi::Status::INVALID_PARAMETER`  The path designates a directory
+    /// * Any error of `File::open`, `RegularFile::set_len` and
+    ///   `RegularFile::write`
+    #[cfg(feature = "exts")]
+    pub fn write_file(&mut self, path: &CStr16, data: &[u8]) -> Result {
+        let handle = self
+            .open_cstr16(path, FileMode::CreateReadWrite, FileAttribute::empty())
+            .log_warning()?;
+        let mut file = match handle.into_type().log_warning()? {
+            FileType::Regular(file) => file,
+            FileType::Dir(_) => return Err(Status::INVALID_PARAMETER.into()),
+        };
+        file.set_len(0).log_warning()?;
+        file.write(data).discard_errdata().log_warning()?;
+        file.flush()
+    }
+
+    /// Create a directory and all of its missing parents
+    ///
+    /// `path` is relative to this directory, with components separated by
+    /// backslashes. Directories which already exist are opened as they are.
+    /// Returns the directory designated by `path`.
+    ///
+    /// # Errors
+    /// * `uefi::Status::INVALID_PARAMETER`  The path is empty, or one of its
+    ///                                      components is a regular file
+    /// * Any error of `File::open`
+    #[cfg(feature = "exts")]
+    pub fn create_dir_all(&mut self, path: &CStr16) -> Result<Directory> {
+        let mut current: Option<Directory> = None;
+        let mut component = Vec::new();
+
+        for name in path
+            .to_u16_slice()
+            .split(|&c| c == u16::from(b'\\'))
+            .filter(|name| !name.is_empty())
+        {
+            component.clear();
+            component.extend_from_slice(name);
+            component.push(0);
+            // The components are subslices of a null-terminated string
+            let name = unsafe { CStr16::from_u16_with_nul_unchecked(&component) };
+
+            let parent = match current.as_mut() {
+                Some(dir) => dir,
+                None => &mut *self,
+            };
+            let handle = parent
+                .open_cstr16(name, FileMode::CreateReadWrite, FileAttribute::DIRECTORY)
+                .log_warning()?;
+            match handle.into_type().log_warning()? {
+                FileType::Dir(dir) => current = Some(dir),
+                FileType::Regular(_) => return Err(Status::INVALID_PARAMETER.into()),
+            }
+        }
+
+        current
+            .ok_or_else(|| Status::INVALID_PARAMETER.into())
+            .map(Into::into)
+    }
+}
+
+/// Iterator over the entries of a `Directory`, returned by `Directory::entries`
+///
+/// Iteration stops after the last entry, or after the first error.
+#[cfg(feature = "exts")]
+pub struct DirectoryEntries<'a> {
+    dir: &'a mut Directory,
+    done: bool,
+}
+
+#[cfg(feature = "exts")]
+impl Iterator for DirectoryEntries<'_> {
+    type Item = Result<Box<FileInfo>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = match self.dir.read_entry_boxed() {
+            Ok(completion) => {
+                let (status, info) = completion.split();
+                info.map(|info| Ok(Completion::new(status, info)))
+            }
+            Err(error) => Some(Err(error)),
+        };
+        self.done = !matches!(entry, Some(Ok(_)));
+        entry
+    }
+}
+
+/// Whether a directory entry is the `.` or `..` entry
+#[cfg(feature = "exts")]
+fn is_dot_entry(name: &CStr16) -> bool {
+    let dot = u16::from(b'.');
+    let name = name.to_u16_slice();
+    name == [dot] || name == [dot, dot]
 }
 
 impl File for Directory {
@@ -104,6 +104,14 @@ impl<Header> NamedFileProtocolInfo<Header> {
     }
 }
 
+impl<Header> NamedFileProtocolInfo<Header> {
+    /// Size of the header and of the null-terminated name, without the
+    /// trailing padding which Rust adds to the structure
+    fn size_of_header_and_name(&self) -> u64 {
+        (mem::size_of::<Header>() + mem::size_of_val(&self.name)) as u64
+    }
+}
+
 impl<Header> Align for NamedFileProtocolInfo<Header> {
     fn alignment() -> usize {
         cmp::max(mem::align_of::<Header>(), mem::align_of::<Char16>())
@@ -198,10 +206,15 @@ impl FileInfo {
             attribute,
         };
         let info = Self::new_impl(storage, header, file_name)?;
-        info.header.size = mem::size_of_val(&info) as u64;
+        info.header.size = info.size_of_header_and_name();
         Ok(info)
     }
 
+    /// Size of the whole structure, including the file name, in bytes
+    pub fn size(&self) -> u64 {
+        self.header.size
+    }
+
     /// File size (number of bytes stored in the file)
     pub fn file_size(&self) -> u64 {
         self.header.file_size
@@ -236,6 +249,43 @@ impl FileInfo {
     pub fn file_name(&self) -> &CStr16 {
         unsafe { CStr16::from_ptr(&self.name[0]) }
     }
+
+    /// Change the file size
+    ///
+    /// Setting this information on a regular file truncates it, or grows it
+    /// with unspecified content. It is ignored on directories.
+    pub fn set_file_size(&mut self, file_size: u64) {
+        self.header.file_size = file_size;
+    }
+
+    /// Change the creation time
+    ///
+    /// `Time::zero()` leaves the creation time of the file unchanged.
+    pub fn set_create_time(&mut self, time: Time) {
+        self.header.create_time = time;
+    }
+
+    /// Change the last access time
+    ///
+    /// `Time::zero()` leaves the last access time of the file unchanged.
+    pub fn set_last_access_time(&mut self, time: Time) {
+        self.header.last_access_time = time;
+    }
+
+    /// Change the modification time
+    ///
+    /// `Time::zero()` leaves the modification time of the file unchanged.
+    pub fn set_modification_time(&mut self, time: Time) {
+        self.header.modification_time = time;
+    }
+
+    /// Change the attribute bits
+    ///
+    /// The `FileAttribute::DIRECTORY` bit must match the actual type of the
+    /// file, and bits outside of `FileAttribute::VALID_ATTR` are rejected.
+    pub fn set_attribute(&mut self, attribute: FileAttribute) {
+        self.header.attribute = attribute;
+    }
 }
 
 impl FileProtocolInfo for FileInfo {}
@@ -287,7 +337,7 @@ impl FileSystemInfo {
             block_size,
         };
         let info = Self::new_impl(storage, header, volume_label)?;
-        info.header.size = mem::size_of_val(&info) as u64;
+        info.header.size = info.size_of_header_and_name();
         Ok(info)
     }
 
@@ -355,3 +405,47 @@ impl FileSystemVolumeLabel {
 }
 
 impl FileProtocolInfo for FileSystemVolumeLabel {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::runtime::Daylight;
+
+    #[repr(C, align(8))]
+    struct Storage([u8; 128]);
+
+    #[test]
+    fn file_info_round_trip() {
+        let mut storage = Storage([0; 128]);
+        let time = Time::new(2021, 10, 2, 12, 30, 0, 0, 2047, Daylight::empty());
+        let info = FileInfo::new(
+            &mut storage.0,
+            42,
+            512,
+            time,
+            time,
+            time,
+            FileAttribute::ARCHIVE,
+            "test.txt",
+        )
+        .unwrap_or_else(|_| panic!("FileInfo creation failed"));
+
+        // The header is followed by the null-terminated name
+        assert_eq!(
+            info.size() as usize,
+            mem::size_of::<FileInfoHeader>() + 9 * mem::size_of::<Char16>()
+        );
+        assert_eq!(info.file_name().to_u16_slice().len(), 8);
+
+        info.set_file_size(0);
+        info.set_attribute(info.attribute() | FileAttribute::HIDDEN);
+        info.set_modification_time(Time::zero());
+        assert_eq!(info.file_size(), 0);
+        assert_eq!(
+            info.attribute(),
+            FileAttribute::ARCHIVE | FileAttribute::HIDDEN
+        );
+        assert!(info.modification_time().is_zero());
+        assert!(!info.create_time().is_zero());
+    }
+}
@@ -13,17 +13,22 @@ mod regular;
 use crate::prelude::*;
 use crate::{CStr16, Char16, Guid, Result, Status};
 #[cfg(feature = "exts")]
-use alloc_api::{alloc::Layout, boxed::Box};
+use alloc_api::boxed::Box;
 use bitflags::bitflags;
 use core::ffi::c_void;
 use core::mem;
 use core::ptr;
 
+#[cfg(feature = "exts")]
+pub use self::dir::DirectoryEntries;
 pub use self::info::{
     FileInfo, FileInfoHeader, FileProtocolInfo, FileSystemInfo, FileSystemInfoHeader,
     FileSystemVolumeLabel, FileSystemVolumeLabelHeader, FromUefi, NamedFileProtocolInfo,
 };
-pub use self::{dir::Directory, regular::RegularFile};
+pub use self::{
+    dir::Directory,
+    regular::{stream_copy, RegularFile},
+};
 
 /// Common interface to `FileHandle`, `RegularFile`, and `Directory`.
 ///
@@ -63,24 +68,42 @@ pub trait File: Sized {
             Err(Status::INVALID_PARAMETER.into())
         } else {
             let mut buf = [0u16; BUF_SIZE + 1];
-            let mut ptr = ptr::null_mut();
 
             let len = ucs2::encode(filename, &mut buf)?;
             let filename = unsafe { CStr16::from_u16_with_nul_unchecked(&buf[..=len]) };
 
-            unsafe {
-                (self.imp().open)(
-                    self.imp(),
-                    &mut ptr,
-                    filename.as_ptr(),
-                    open_mode,
-                    attributes,
-                )
-            }
-            .into_with_val(|| unsafe { FileHandle::new(ptr) })
+            self.open_cstr16(filename, open_mode, attributes)
         }
     }
 
+    /// Try to open a file relative to this file, from a UCS-2 path.
+    ///
+    /// This is the same as `open`, but takes a path which is already encoded
+    /// the way the firmware expects it. Path components are separated by
+    /// backslashes.
+    ///
+    /// # Errors
+    /// See `open`.
+    fn open_cstr16(
+        &mut self,
+        filename: &CStr16,
+        open_mode: FileMode,
+        attributes: FileAttribute,
+    ) -> Result<FileHandle> {
+        let mut ptr = ptr::null_mut();
+
+        unsafe {
+            (self.imp().open)(
+                self.imp(),
+                &mut ptr,
+                filename.as_ptr(),
+                open_mode,
+                attributes,
+            )
+        }
+        .into_with_val(|| unsafe { FileHandle::new(ptr) })
+    }
+
     /// Close this file handle. Same as dropping this structure.
     fn close(self) {}
 
@@ -156,7 +179,7 @@ pub trait File: Sized {
     /// * `uefi::Status::VOLUME_FULL`       Not enough space left on the volume to change the info
     fn set_info<Info: FileProtocolInfo + ?Sized>(&mut self, info: &Info) -> Result {
         let info_ptr = info as *const Info as *const c_void;
-        let info_size = mem::size_of_val(&info);
+        let info_size = mem::size_of_val(info);
         unsafe { (self.imp().set_info)(self.imp(), &Info::GUID, info_size, info_ptr).into() }
     }
 
@@ -176,41 +199,36 @@ pub trait File: Sized {
     #[cfg(feature = "exts")]
     /// Get the dynamically allocated info for a file
     fn get_boxed_info<Info: FileProtocolInfo + ?Sized>(&mut self) -> Result<Box<Info>> {
-        // Initially try get_info with an empty array, this should always fail
-        // as all Info types at least need room for a null-terminator.
-        let size = match self
-            .get_info::<Info>(&mut [])
-            .expect_error("zero sized get_info unexpectedly succeeded")
-            .split()
-        {
-            (s, None) => return Err(s.into()),
-            (_, Some(size)) => size,
-        };
-
-        // We add trailing padding because the size of a rust structure must
-        // always be a multiple of alignment.
-        let layout = Layout::from_size_align(size, Info::alignment())
-            .unwrap()
-            .pad_to_align();
-        let mut buffer = crate::exts::allocate_buffer(layout);
-        let buffer_start = buffer.as_ptr();
-
-        let info = self
-            .get_info(&mut buffer)
-            .discard_errdata()?
-            .map(|info_ref| {
-                // This operation is safe because info uses the exact memory
-                // of the provied buffer (so no memory is leaked), and the box
-                // is created if and only if buffer is leaked (so no memory can
-                // ever be freed twice).
-
-                assert_eq!(mem::size_of_val(info_ref), layout.size());
-                assert_eq!(info_ref as *const Info as *const u8, buffer_start);
-                unsafe { Box::from_raw(info_ref as *mut _) }
-            });
-        mem::forget(buffer);
+        crate::exts::make_boxed(|buffer| self.get_info::<Info>(buffer))
+    }
 
-        Ok(info)
+    #[cfg(feature = "exts")]
+    /// Modify some information about a file
+    ///
+    /// The current information is queried, changed by `f`, and set back on
+    /// the file. For instance, this hides a file:
+    ///
+    /// ```no_run
+    /// # use uefi::prelude::*;
+    /// # use uefi::proto::media::file::{File, FileAttribute, FileInfo, RegularFile};
+    /// # fn f(file: &mut RegularFile) {
+    /// file.modify_info(|info: &mut FileInfo| {
+    ///     info.set_attribute(info.attribute() | FileAttribute::HIDDEN)
+    /// })
+    /// .expect_success("Failed to hide file");
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// See `get_info` and `set_info`.
+    fn modify_info<Info, F>(&mut self, f: F) -> Result
+    where
+        Info: FileProtocolInfo + ?Sized,
+        F: FnOnce(&mut Info),
+    {
+        let mut info = self.get_boxed_info::<Info>().log_warning()?;
+        f(&mut info);
+        self.set_info(&*info)
     }
 }
 
@@ -1,5 +1,9 @@
+#[cfg(feature = "exts")]
+use super::FileInfo;
 use super::{File, FileHandle, FileInternal};
-use crate::{Result, Status};
+use crate::result::Error;
+use crate::{Result, ResultExt, Status};
+use core::fmt;
 
 /// A `FileHandle` that is also a regular (data) file.
 ///
@@ -97,6 +101,75 @@ impl RegularFile {
     pub fn set_position(&mut self, position: u64) -> Result {
         (self.imp().set_position)(self.imp(), position).into()
     }
+
+    /// Read data from file at a given position
+    ///
+    /// Same as `read`, from `offset` instead of the current position. The
+    /// position of the file handle is restored afterwards, even if the read
+    /// fails.
+    ///
+    /// # Errors
+    /// See `read`, `get_position` and `set_position`.
+    pub fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, Option<usize>> {
+        let saved = self
+            .get_position()
+            .map_err(|e| Error::new(e.status(), None))?
+            .log();
+        let result = self
+            .set_position(offset)
+            .map_err(|e| Error::new(e.status(), None))
+            .and_then(|_| self.read(buffer));
+        self.restore_position(saved, result, |_| None)
+    }
+
+    /// Write data to file at a given position
+    ///
+    /// Same as `write`, from `offset` instead of the current position. The
+    /// position of the file handle is restored afterwards, even if the write
+    /// fails.
+    ///
+    /// # Errors
+    /// See `write`, `get_position` and `set_position`. If the position could
+    /// not be changed, no data was written.
+    pub fn write_at(&mut self, offset: u64, buffer: &[u8]) -> Result<(), usize> {
+        let saved = self
+            .get_position()
+            .map_err(|e| Error::new(e.status(), 0))?
+            .log();
+        let result = self
+            .set_position(offset)
+            .map_err(|e| Error::new(e.status(), 0))
+            .and_then(|_| self.write(buffer));
+        self.restore_position(saved, result, |_| 0)
+    }
+
+    /// Set the position back to `saved` after an operation which returned
+    /// `result`, reporting a failure to do so if the operation succeeded
+    fn restore_position<T, E: core::fmt::Debug>(
+        &mut self,
+        saved: u64,
+        result: Result<T, E>,
+        errdata: impl FnOnce(Status) -> E,
+    ) -> Result<T, E> {
+        let restored = self.set_position(saved);
+        let completion = result?;
+        match restored {
+            Ok(_) => Ok(completion),
+            Err(e) => Err(Error::new(e.status(), errdata(e.status()))),
+        }
+    }
+
+    /// Truncate or extend the file to `size` bytes
+    ///
+    /// The content of the file past its previous end is unspecified. The
+    /// position of the file handle is left unchanged.
+    ///
+    /// # Errors
+    /// See `File::get_info` and `File::set_info`.
+    #[cfg(feature = "exts")]
+    pub fn set_len(&mut self, size: u64) -> Result {
+        self.modify_info(|info: &mut FileInfo| info.set_file_size(size))
+    }
 }
 
 impl File for RegularFile {
@@ -105,3 +178,38 @@ impl File for RegularFile {
         &mut self.0
     }
 }
+
+impl fmt::Write for RegularFile {
+    /// Write the UTF-8 encoding of the string at the current position
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes())
+            .warning_as_error()
+            .map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
+
+/// Copy the content of `src` from its current position to its end into `dst`
+///
+/// The data is written from the current position of `dst`, and goes through
+/// `buffer`, which can be reused between calls. Returns the number of bytes
+/// copied.
+///
+/// # Errors
+/// See `RegularFile::read` and `RegularFile::write`. The positions of both
+/// files are unspecified after an error.
+///
+/// # Panics
+/// Panics if `buffer` is empty.
+pub fn stream_copy(src: &mut RegularFile, dst: &mut RegularFile, buffer: &mut [u8]) -> Result<u64> {
+    assert!(!buffer.is_empty(), "stream_copy needs a non-empty buffer");
+    let mut copied = 0;
+    loop {
+        let read = src.read(buffer).discard_errdata().log_warning()?;
+        if read == 0 {
+            return Ok(copied.into());
+        }
+        dst.write(&buffer[..read]).discard_errdata().log_warning()?;
+        copied += read as u64;
+    }
+}
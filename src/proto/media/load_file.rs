@@ -0,0 +1,232 @@
+//! Load File protocols.
+
+use crate::proto::device_path::DevicePath;
+use crate::proto::{Protocol, ProtocolImpl};
+#[cfg(feature = "exts")]
+use crate::{alloc_api::vec::Vec, ResultExt};
+use crate::{unsafe_guid, Result, Status};
+use core::ffi::c_void;
+use core::ptr;
+
+/// Protocol for loading files from a device which is not a file system, such
+/// as a network boot server.
+///
+/// Boot managers use this protocol to load boot options from such devices.
+#[repr(C)]
+#[unsafe_guid("56ec3091-954c-11d2-8e3f-00a0c969723b")]
+#[derive(Protocol)]
+pub struct LoadFile {
+    load_file: LoadFileFn,
+}
+
+/// Protocol for loading files which are not boot options, such as the initrd
+/// that Linux requests from its boot loader.
+///
+/// Unlike `LoadFile`, this protocol is not used by the boot manager.
+#[repr(C)]
+#[unsafe_guid("4006c0c1-fcb3-403e-996d-4a6c8724e06d")]
+#[derive(Protocol)]
+pub struct LoadFile2 {
+    load_file: LoadFileFn,
+}
+
+type LoadFileFn = extern "efiapi" fn(
+    this: *const c_void,
+    file_path: &DevicePath,
+    boot_policy: bool,
+    buffer_size: &mut usize,
+    buffer: *mut u8,
+) -> Status;
+
+/// Call a `LoadFile` implementation, returning the required buffer size if
+/// `buffer` is too small
+fn load_file(
+    this: *const c_void,
+    imp: LoadFileFn,
+    file_path: &DevicePath,
+    boot_policy: bool,
+    buffer: &mut [u8],
+) -> Result<usize, Option<usize>> {
+    let mut buffer_size = buffer.len();
+    let buffer_ptr = if buffer.is_empty() {
+        ptr::null_mut()
+    } else {
+        buffer.as_mut_ptr()
+    };
+    imp(this, file_path, boot_policy, &mut buffer_size, buffer_ptr).into_with(
+        || buffer_size,
+        |s| {
+            if s == Status::BUFFER_TOO_SMALL {
+                Some(buffer_size)
+            } else {
+                None
+            }
+        },
+    )
+}
+
+/// Load a file into a newly allocated buffer
+#[cfg(feature = "exts")]
+fn load_file_to_vec(
+    this: *const c_void,
+    imp: LoadFileFn,
+    file_path: &DevicePath,
+    boot_policy: bool,
+) -> Result<Vec<u8>> {
+    let size = match load_file(this, imp, file_path, boot_policy, &mut []) {
+        Ok(completion) => return Ok(completion.map(|_| Vec::new())),
+        Err(error) => match error.data() {
+            Some(size) => *size,
+            None => return Err(error.status().into()),
+        },
+    };
+    let mut buffer = crate::alloc_api::vec![0; size];
+    let size = load_file(this, imp, file_path, boot_policy, &mut buffer)
+        .discard_errdata()
+        .log_warning()?;
+    buffer.truncate(size);
+    Ok(buffer.into())
+}
+
+impl LoadFile {
+    /// Load the file at `file_path` into `buffer`, returning its size.
+    ///
+    /// If `boot_policy` is true, the request comes from the boot manager and
+    /// `file_path` may be inexact, for example to let a network boot server
+    /// choose the file.
+    ///
+    /// # Errors
+    /// * `uefi::Status::NOT_FOUND`          The file was not found
+    /// * `uefi::Status::NO_MEDIA`           The device has no media
+    /// * `uefi::Status::DEVICE_ERROR`       The device reported an error
+    /// * `uefi::Status::BUFFER_TOO_SMALL`   The buffer is too small to hold the file,
+    ///                                      and the required buffer size is provided as output.
+    pub fn load_file(
+        &mut self,
+        file_path: &DevicePath,
+        boot_policy: bool,
+        buffer: &mut [u8],
+    ) -> Result<usize, Option<usize>> {
+        let this = self as *const Self as *const c_void;
+        load_file(this, self.load_file, file_path, boot_policy, buffer)
+    }
+
+    /// Load the file at `file_path` into a newly allocated buffer.
+    ///
+    /// See `load_file` for the meaning of `boot_policy` and the errors.
+    #[cfg(feature = "exts")]
+    pub fn load_file_to_vec(
+        &mut self,
+        file_path: &DevicePath,
+        boot_policy: bool,
+    ) -> Result<Vec<u8>> {
+        let this = self as *const Self as *const c_void;
+        load_file_to_vec(this, self.load_file, file_path, boot_policy)
+    }
+}
+
+impl LoadFile2 {
+    /// Load the file at `file_path` into `buffer`, returning its size.
+    ///
+    /// # Errors
+    /// * `uefi::Status::NOT_FOUND`          The file was not found
+    /// * `uefi::Status::NO_MEDIA`           The device has no media
+    /// * `uefi::Status::DEVICE_ERROR`       The device reported an error
+    /// * `uefi::Status::BUFFER_TOO_SMALL`   The buffer is too small to hold the file,
+    ///                                      and the required buffer size is provided as output.
+    pub fn load_file(
+        &mut self,
+        file_path: &DevicePath,
+        buffer: &mut [u8],
+    ) -> Result<usize, Option<usize>> {
+        let this = self as *const Self as *const c_void;
+        load_file(this, self.load_file, file_path, false, buffer)
+    }
+
+    /// Load the file at `file_path` into a newly allocated buffer.
+    ///
+    /// See `load_file` for the errors.
+    #[cfg(feature = "exts")]
+    pub fn load_file_to_vec(&mut self, file_path: &DevicePath) -> Result<Vec<u8>> {
+        let this = self as *const Self as *const c_void;
+        load_file_to_vec(this, self.load_file, file_path, false)
+    }
+}
+
+/// A file stored in memory, which implements `LoadFile2`
+///
+/// The same data is loaded whatever the path, which is how boot loaders give
+/// Linux its initrd.
+#[repr(C)]
+pub struct MemoryFile<'data> {
+    // First, since the function of the protocol finds the file from it
+    protocol: LoadFile2,
+    data: &'data [u8],
+}
+
+unsafe impl ProtocolImpl for MemoryFile<'_> {
+    type Protocol = LoadFile2;
+
+    fn interface(&mut self) -> *mut c_void {
+        &mut self.protocol as *mut LoadFile2 as *mut c_void
+    }
+}
+
+impl<'data> MemoryFile<'data> {
+    /// Create a file holding `data`
+    pub fn new(data: &'data [u8]) -> Self {
+        MemoryFile {
+            protocol: LoadFile2 {
+                load_file: Self::load_file,
+            },
+            data,
+        }
+    }
+
+    /// The protocol, to load the file without installing it
+    pub fn load_file2(&mut self) -> &mut LoadFile2 {
+        &mut self.protocol
+    }
+
+    extern "efiapi" fn load_file(
+        this: *const c_void,
+        _file_path: &DevicePath,
+        boot_policy: bool,
+        buffer_size: &mut usize,
+        buffer: *mut u8,
+    ) -> Status {
+        if boot_policy {
+            return Status::UNSUPPORTED;
+        }
+        let data = unsafe { &*(this as *const Self) }.data;
+        if buffer.is_null() || *buffer_size < data.len() {
+            *buffer_size = data.len();
+            return Status::BUFFER_TOO_SMALL;
+        }
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len()) };
+        *buffer_size = data.len();
+        Status::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_file() {
+        let end = [0x7f, 0xff, 4, 0];
+        let path = unsafe { &*(end.as_ptr() as *const DevicePath) };
+        let mut file = MemoryFile::new(b"initrd");
+        let load_file2 = file.load_file2();
+
+        let mut small = [0; 4];
+        let error = load_file2.load_file(path, &mut small).unwrap_err();
+        assert_eq!(error.status(), Status::BUFFER_TOO_SMALL);
+        assert_eq!(error.data(), &Some(6));
+
+        let mut buffer = [0; 8];
+        assert_eq!(load_file2.load_file(path, &mut buffer).unwrap().unwrap(), 6);
+        assert_eq!(&buffer[..6], b"initrd");
+    }
+}
@@ -6,6 +6,12 @@
 
 pub mod file;
 
+pub mod ata;
 pub mod block;
+pub mod erase_block;
 pub mod fs;
+pub mod load_file;
+pub mod nvdimm;
 pub mod partition;
+pub mod sd_mmc;
+pub mod storage_security;
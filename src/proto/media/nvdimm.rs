@@ -0,0 +1,402 @@
+//! NVDIMM Label protocol.
+//!
+//! The persistent memory of an NVDIMM is divided into namespaces, which are
+//! described by labels kept in a dedicated storage area of the NVDIMM. The
+//! area starts with two index blocks, the newest valid one telling which
+//! label slots are in use, followed by the labels:
+//!
+//! ```no_run
+//! # use uefi::proto::media::nvdimm::{IndexBlock, NvdimmLabel};
+//! # fn f(nvdimm: &mut NvdimmLabel, area: &mut [u8]) -> uefi::Result {
+//! let info = nvdimm.storage_information()?.log();
+//! let area = &mut area[..info.size as usize];
+//! nvdimm.read(0, area)?.log();
+//! if let Some(index) = IndexBlock::current(area) {
+//!     for label in index.labels(area) {
+//!         log::info!("Namespace {:?}", core::str::from_utf8(label.name()));
+//!     }
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! The persistent memory itself appears in the memory map, see
+//! `MemoryDescriptor::is_persistent`.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Guid, Result, Status};
+use core::convert::TryInto;
+use core::mem;
+
+/// Information about the label storage area of an NVDIMM
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LabelStorageInformation {
+    /// Size of the area, in bytes
+    pub size: u32,
+    /// Largest amount of data which can be transferred at once
+    pub max_transfer_length: u32,
+}
+
+/// The NVDIMM Label protocol.
+///
+/// It is installed on the handles of the NVDIMMs.
+#[repr(C)]
+#[unsafe_guid("d40b6b80-97d5-4282-bb1d-223a16919358")]
+#[derive(Protocol)]
+pub struct NvdimmLabel {
+    label_storage_information: unsafe extern "efiapi" fn(
+        this: *mut NvdimmLabel,
+        size_of_label_storage_area: *mut u32,
+        max_transfer_length: *mut u32,
+    ) -> Status,
+    label_storage_read: unsafe extern "efiapi" fn(
+        this: *const NvdimmLabel,
+        offset: u32,
+        transfer_length: u32,
+        label_data: *mut u8,
+    ) -> Status,
+    label_storage_write: unsafe extern "efiapi" fn(
+        this: *const NvdimmLabel,
+        offset: u32,
+        transfer_length: u32,
+        label_data: *const u8,
+    ) -> Status,
+}
+
+impl NvdimmLabel {
+    /// Get the size of the label storage area and the transfer limit.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`   The NVDIMM has no label storage area
+    /// * `uefi::Status::DEVICE_ERROR`  The NVDIMM could not be queried
+    pub fn storage_information(&mut self) -> Result<LabelStorageInformation> {
+        let mut size = 0;
+        let mut max_transfer_length = 0;
+        unsafe { (self.label_storage_information)(self, &mut size, &mut max_transfer_length) }
+            .into_with_val(|| LabelStorageInformation {
+                size,
+                max_transfer_length,
+            })
+    }
+
+    /// Read the label storage area from `offset` into `buffer`, in as many
+    /// transfers as needed.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The range is outside of the area
+    /// * `uefi::Status::DEVICE_ERROR`       A transfer failed
+    pub fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result {
+        let max = self.transfer_limit()?;
+        let mut offset = offset;
+        for chunk in buffer.chunks_mut(max) {
+            let status = unsafe {
+                (self.label_storage_read)(self, offset, chunk.len() as u32, chunk.as_mut_ptr())
+            };
+            if status.is_error() {
+                return Err(status.into());
+            }
+            offset = next_offset(offset, chunk.len())?;
+        }
+        Status::SUCCESS.into()
+    }
+
+    /// Write `data` to the label storage area at `offset`, in as many
+    /// transfers as needed.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The range is outside of the area
+    /// * `uefi::Status::DEVICE_ERROR`       A transfer failed
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result {
+        let max = self.transfer_limit()?;
+        let mut offset = offset;
+        for chunk in data.chunks(max) {
+            let status = unsafe {
+                (self.label_storage_write)(self, offset, chunk.len() as u32, chunk.as_ptr())
+            };
+            if status.is_error() {
+                return Err(status.into());
+            }
+            offset = next_offset(offset, chunk.len())?;
+        }
+        Status::SUCCESS.into()
+    }
+
+    /// The largest transfer, which is never 0
+    fn transfer_limit(&mut self) -> core::result::Result<usize, crate::result::Error> {
+        let info = self.storage_information()?.log();
+        Ok((info.max_transfer_length as usize).max(1))
+    }
+}
+
+/// The offset following a transfer of `len` bytes at `offset`
+fn next_offset(offset: u32, len: usize) -> core::result::Result<u32, crate::result::Error> {
+    len.try_into()
+        .ok()
+        .and_then(|len| offset.checked_add(len))
+        .ok_or_else(|| Status::INVALID_PARAMETER.into())
+}
+
+/// The Fletcher-64 checksum of the labels, computed with the checksum field
+/// at `checksum_offset` taken as zero
+fn fletcher64(data: &[u8], checksum_offset: usize) -> u64 {
+    let (mut low, mut high) = (0u32, 0u32);
+    for (i, word) in data.chunks_exact(4).enumerate() {
+        let word = if (checksum_offset..checksum_offset + 8).contains(&(i * 4)) {
+            0
+        } else {
+            u32::from_le_bytes(word.try_into().unwrap())
+        };
+        low = low.wrapping_add(word);
+        high = high.wrapping_add(low);
+    }
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Signature of the index blocks
+pub const INDEX_SIGNATURE: &[u8; 16] = b"NAMESPACE_INDEX\0";
+
+/// Size of the fixed part of an index block, before the bitmap of free slots
+const INDEX_HEADER_SIZE: usize = 72;
+
+/// Offset of the checksum in an index block
+const INDEX_CHECKSUM_OFFSET: usize = 64;
+
+/// An index block of the label storage area (`EFI_NVDIMM_LABEL_INDEX_BLOCK`)
+#[derive(Debug, Copy, Clone)]
+pub struct IndexBlock<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> IndexBlock<'a> {
+    /// Parse the index block at the start of `data`, or return `None` if its
+    /// signature or its checksum is wrong
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let header = data.get(..INDEX_HEADER_SIZE)?;
+        if &header[..16] != INDEX_SIGNATURE {
+            return None;
+        }
+        let size = read_u64(header, 32).try_into().ok()?;
+        if size < INDEX_HEADER_SIZE {
+            return None;
+        }
+        let data = data.get(..size)?;
+        if fletcher64(data, INDEX_CHECKSUM_OFFSET) != read_u64(data, INDEX_CHECKSUM_OFFSET) {
+            return None;
+        }
+        Some(IndexBlock { data })
+    }
+
+    /// Find the current index block of the label storage `area`, which is the
+    /// newest of the two valid ones
+    pub fn current(area: &'a [u8]) -> Option<Self> {
+        let first = Self::parse(area);
+        let second = match first {
+            Some(first) => area
+                .get(first.other_offset() as usize..)
+                .and_then(Self::parse),
+            // The size of the blocks is a multiple of 256 bytes
+            None => (256..area.len())
+                .step_by(256)
+                .find_map(|offset| Self::parse(&area[offset..])),
+        };
+        match (first, second) {
+            (Some(first), Some(second)) if second.is_newer_than(&first) => Some(second),
+            (Some(first), _) => Some(first),
+            (None, second) => second,
+        }
+    }
+
+    /// The sequence number of the block, which cycles through 1, 2 and 3
+    pub fn sequence(&self) -> u32 {
+        read_u32(self.data, 20)
+    }
+
+    /// Whether the block was written after `other`
+    pub fn is_newer_than(&self, other: &IndexBlock) -> bool {
+        let (seq, other) = (self.sequence() & 3, other.sequence() & 3);
+        other != 0 && seq == other % 3 + 1
+    }
+
+    /// The size of the labels, in bytes
+    pub fn label_size(&self) -> usize {
+        // Versions before 1.2 have 128-byte labels, and no size
+        128 << self.data[19].max(1)
+    }
+
+    /// The offset of the block in the storage area
+    pub fn offset(&self) -> u64 {
+        read_u64(self.data, 24)
+    }
+
+    /// The offset of the other index block in the storage area
+    pub fn other_offset(&self) -> u64 {
+        read_u64(self.data, 40)
+    }
+
+    /// The offset of the first label in the storage area
+    pub fn label_offset(&self) -> u64 {
+        read_u64(self.data, 48)
+    }
+
+    /// The number of label slots
+    pub fn slot_count(&self) -> u32 {
+        read_u32(self.data, 56)
+    }
+
+    /// The version of the label format, as `(major, minor)`
+    pub fn version(&self) -> (u16, u16) {
+        let word = |offset: usize| u16::from_le_bytes([self.data[offset], self.data[offset + 1]]);
+        (word(60), word(62))
+    }
+
+    /// Whether the slot `slot` is free
+    pub fn is_free(&self, slot: u32) -> bool {
+        let bitmap = &self.data[INDEX_HEADER_SIZE..];
+        // The slots beyond the bitmap do not exist, so are never used
+        match bitmap.get(slot as usize / 8) {
+            Some(byte) => byte & (1 << (slot % 8)) != 0,
+            None => true,
+        }
+    }
+
+    /// Iterate over the valid labels of the slots in use, in the label
+    /// storage `area`
+    pub fn labels(&self, area: &'a [u8]) -> impl Iterator<Item = NamespaceLabel> + 'a {
+        let index = *self;
+        let start = self.label_offset() as usize;
+        let size = self.label_size();
+        (0..self.slot_count())
+            .filter(move |&slot| !index.is_free(slot))
+            .filter_map(move |slot| {
+                let offset = start.checked_add(slot as usize * size)?;
+                let data = area.get(offset..offset.checked_add(size)?)?;
+                NamespaceLabel::from_bytes(data, slot)
+            })
+    }
+}
+
+/// A namespace label (`EFI_NVDIMM_LABEL`)
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct NamespaceLabel {
+    /// Unique identifier of the namespace
+    pub uuid: Guid,
+    name: [u8; 64],
+    /// Flags of the label, like read-only (bit 0) or local (bit 1)
+    pub flags: u32,
+    /// Number of labels describing the namespace, for the namespaces which
+    /// span several NVDIMMs
+    pub label_count: u16,
+    /// Position of this label in the set of labels of the namespace
+    pub position: u16,
+    /// Identifier of the interleave set of the namespace
+    pub set_cookie: u64,
+    /// Size of the logical blocks of the namespace, 0 for byte-addressable
+    /// namespaces
+    pub lba_size: u64,
+    /// Physical address of the namespace in the NVDIMM
+    pub dpa: u64,
+    /// Size of the namespace on this NVDIMM
+    pub raw_size: u64,
+    /// Slot of the label
+    pub slot: u32,
+    /// Alignment of the namespace, as a shift of 1 GiB
+    pub alignment: u8,
+    reserved: [u8; 3],
+    /// Type of the address range of the namespace
+    pub type_guid: Guid,
+    /// Format of the namespace, like a block translation table
+    pub address_abstraction_guid: Guid,
+    reserved1: [u8; 88],
+    checksum: u64,
+}
+
+impl NamespaceLabel {
+    /// Read the label in `data`, which should be in the slot `slot`, or return
+    /// `None` if it is not valid
+    pub fn from_bytes(data: &[u8], slot: u32) -> Option<Self> {
+        let data = data.get(..mem::size_of::<Self>())?;
+        let checksum_offset = mem::size_of::<Self>() - 8;
+        if fletcher64(data, checksum_offset) != read_u64(data, checksum_offset) {
+            return None;
+        }
+        let label = unsafe { data.as_ptr().cast::<Self>().read_unaligned() };
+        if label.slot == slot {
+            Some(label)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the namespace, which is usually UTF-8
+    pub fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(64);
+        &self.name[..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_checksum(data: &mut [u8], offset: usize) {
+        let checksum = fletcher64(data, offset);
+        data[offset..offset + 8].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(mem::size_of::<NamespaceLabel>(), 256);
+
+        let mut area = [0; 1024];
+        for (offset, seq) in [(0, 1), (256, 2)].iter().copied() {
+            let index = &mut area[offset..offset + 256];
+            index[..16].copy_from_slice(INDEX_SIGNATURE);
+            index[19] = 1;
+            index[20..24].copy_from_slice(&(seq as u32).to_le_bytes());
+            index[24..32].copy_from_slice(&(offset as u64).to_le_bytes());
+            index[32..40].copy_from_slice(&256u64.to_le_bytes());
+            index[40..48].copy_from_slice(&((256 - offset) as u64).to_le_bytes());
+            index[48..56].copy_from_slice(&512u64.to_le_bytes());
+            index[56..60].copy_from_slice(&2u32.to_le_bytes());
+            index[60..64].copy_from_slice(&[1, 0, 2, 0]);
+            // Slot 1 is in use in the newest index only
+            index[72] = if seq == 2 { 0b01 } else { 0b11 };
+            set_checksum(index, INDEX_CHECKSUM_OFFSET);
+        }
+        let label = &mut area[768..1024];
+        label[16..20].copy_from_slice(b"pmem");
+        label[120..124].copy_from_slice(&1u32.to_le_bytes());
+        set_checksum(label, 248);
+
+        let index = IndexBlock::current(&area).unwrap();
+        assert_eq!(index.sequence(), 2);
+        assert_eq!(index.version(), (1, 2));
+        assert_eq!(index.label_size(), 256);
+        assert!(index.is_free(0) && !index.is_free(1));
+        let mut labels = index.labels(&area);
+        assert_eq!(labels.next().unwrap().name(), b"pmem");
+        assert!(labels.next().is_none());
+        drop(labels);
+
+        // A corrupted block is ignored
+        area[300] ^= 1;
+        assert_eq!(IndexBlock::current(&area).unwrap().sequence(), 1);
+        area[300] ^= 1;
+        area[100] ^= 1;
+        assert_eq!(IndexBlock::current(&area).unwrap().sequence(), 2);
+        area[300] ^= 1;
+        assert!(IndexBlock::current(&area).is_none());
+    }
+}
@@ -1,7 +1,9 @@
 //! Partition information protocol.
 
+use crate::crc;
 use crate::proto::Protocol;
 use crate::{unsafe_guid, Char16, Guid};
+use core::{mem, ptr};
 
 newtype_enum! {
     /// MBR OS type.
@@ -119,6 +121,87 @@ impl GptPartitionEntry {
     }
 }
 
+/// Signature of the GPT header
+pub const GPT_HEADER_SIGNATURE: [u8; 8] = *b"EFI PART";
+
+/// GPT header, in the second block of the disk, and in its last block for
+/// the backup.
+#[repr(C)]
+#[repr(packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct GptHeader {
+    /// `GPT_HEADER_SIGNATURE`.
+    pub signature: [u8; 8],
+
+    /// Revision of the format, 1.0 being `0x0001_0000`.
+    pub revision: u32,
+
+    /// Size of the header, in bytes, which is checksummed.
+    pub header_size: u32,
+
+    /// CRC-32 of the header, computed with this field set to zero.
+    pub header_crc32: u32,
+
+    /// Reserved, must be zero.
+    pub reserved: u32,
+
+    /// LBA of this header.
+    pub my_lba: u64,
+
+    /// LBA of the other copy of the header.
+    pub alternate_lba: u64,
+
+    /// First LBA which partitions may use.
+    pub first_usable_lba: u64,
+
+    /// Last LBA which partitions may use.
+    pub last_usable_lba: u64,
+
+    /// GUID of the disk.
+    pub disk_guid: Guid,
+
+    /// Starting LBA of the array of partition entries.
+    pub partition_entry_lba: u64,
+
+    /// Number of entries in the array.
+    pub number_of_partition_entries: u32,
+
+    /// Size of each entry, at least the size of `GptPartitionEntry`.
+    pub size_of_partition_entry: u32,
+
+    /// CRC-32 of the array of partition entries.
+    pub partition_entry_array_crc32: u32,
+}
+
+impl GptHeader {
+    /// Read the header at the start of `block`, returning `None` if its
+    /// signature or checksum is wrong.
+    pub fn from_block(block: &[u8]) -> Option<Self> {
+        if block.len() < mem::size_of::<Self>() || !crc::table_crc32_is_valid(block) {
+            return None;
+        }
+        let header = unsafe { ptr::read_unaligned(block.as_ptr().cast::<Self>()) };
+        if header.signature != GPT_HEADER_SIGNATURE || { header.header_size } < 92 {
+            return None;
+        }
+        Some(header)
+    }
+
+    /// Size of the array of partition entries, in bytes
+    pub fn partition_entries_size(&self) -> usize {
+        self.number_of_partition_entries as usize * self.size_of_partition_entry as usize
+    }
+
+    /// Whether the checksum of the array of partition entries at the start of
+    /// `entries` is correct
+    pub fn partition_entries_are_valid(&self, entries: &[u8]) -> bool {
+        match entries.get(..self.partition_entries_size()) {
+            Some(entries) => crc::crc32(entries) == self.partition_entry_array_crc32,
+            None => false,
+        }
+    }
+}
+
 newtype_enum! {
     /// Partition type.
     pub enum PartitionType: u32 => {
@@ -197,3 +280,28 @@ impl PartitionInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpt_header() {
+        let entries = [0x5a; 2 * 128];
+        let mut block = [0; 512];
+        block[..8].copy_from_slice(&GPT_HEADER_SIGNATURE);
+        block[12..16].copy_from_slice(&92u32.to_le_bytes());
+        block[80..84].copy_from_slice(&2u32.to_le_bytes());
+        block[84..88].copy_from_slice(&128u32.to_le_bytes());
+        block[88..92].copy_from_slice(&crc::crc32(&entries).to_le_bytes());
+        assert!(GptHeader::from_block(&block).is_none());
+
+        crc::update_table_crc32(&mut block);
+        let header = GptHeader::from_block(&block).unwrap();
+        assert_eq!(header.partition_entries_size(), 256);
+        assert!(header.partition_entries_are_valid(&entries));
+        assert!(!header.partition_entries_are_valid(&entries[..255]));
+        block[8] = 1;
+        assert!(GptHeader::from_block(&block).is_none());
+    }
+}
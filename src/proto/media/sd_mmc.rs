@@ -0,0 +1,356 @@
+//! SD/MMC Pass Thru protocol.
+//!
+//! This protocol sends raw commands to the SD cards and eMMC devices plugged
+//! in the slots of an SD host controller. It is what provisioning tools use
+//! to reach the features of eMMC devices which the Block I/O protocol hides,
+//! such as the selection of the boot partition:
+//!
+//! ```no_run
+//! # use uefi::proto::media::sd_mmc::{Command, SdMmcPassThru};
+//! # fn f(sd_mmc: &mut SdMmcPassThru) -> uefi::Result {
+//! if let Some(slot) = sd_mmc.slots().next() {
+//!     // Boot from the first boot partition, and give access to it
+//!     let mut command = Command::mmc_switch(179, 0b0100_1001);
+//!     sd_mmc.pass_thru(slot, &mut command)?;
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::device_path::DevicePath;
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Result, Status};
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr;
+use core::time::Duration;
+
+newtype_enum! {
+    /// Type of an SD/MMC command, from the specifications of SD and eMMC
+    pub enum CommandType: u32 => {
+        /// Broadcast command, without response
+        BC   = 0,
+        /// Broadcast command, with a response
+        BCR  = 1,
+        /// Addressed command, without data transfer
+        AC   = 2,
+        /// Addressed command, with a data transfer
+        ADTC = 3,
+    }
+}
+
+newtype_enum! {
+    /// Type of the response to an SD/MMC command
+    pub enum ResponseType: u32 => {
+        /// Normal response
+        R1  = 0,
+        /// Normal response, followed by a busy signal
+        R1B = 1,
+        /// CID or CSD register
+        R2  = 2,
+        /// OCR register
+        R3  = 3,
+        /// Fast I/O response, of MMC devices
+        R4  = 4,
+        /// Interrupt request response, of MMC devices, or SDIO response
+        R5  = 5,
+        /// Like `R5`, followed by a busy signal
+        R5B = 6,
+        /// Published RCA response, of SD cards
+        R6  = 7,
+        /// Card interface condition, of SD cards
+        R7  = 8,
+    }
+}
+
+/// Command sent to a device (`EFI_SD_MMC_COMMAND_BLOCK`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct CommandBlock {
+    /// Index of the command, like 6 for `CMD6`
+    pub command_index: u16,
+    /// Argument of the command
+    pub command_argument: u32,
+    /// Type of the command
+    pub command_type: CommandType,
+    /// Type of the expected response
+    pub response_type: ResponseType,
+}
+
+/// Response of a device (`EFI_SD_MMC_STATUS_BLOCK`)
+///
+/// Short responses only use the first word, 136-bit `R2` responses use the
+/// four of them.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct StatusBlock {
+    /// Words of the response, least significant first
+    pub response: [u32; 4],
+}
+
+/// The packet exchanged with the firmware
+/// (`EFI_SD_MMC_PASS_THRU_COMMAND_PACKET`)
+#[repr(C)]
+struct CommandPacket {
+    command_block: *mut CommandBlock,
+    status_block: *mut StatusBlock,
+    timeout: u64,
+    in_data_buffer: *mut c_void,
+    out_data_buffer: *mut c_void,
+    in_transfer_length: u32,
+    out_transfer_length: u32,
+    transaction_status: Status,
+}
+
+/// A command, with the data it reads or writes
+///
+/// The data buffers must be aligned to `SdMmcPassThru::io_align`.
+#[derive(Debug)]
+pub struct Command<'buf> {
+    block: CommandBlock,
+    timeout: u64,
+    input: *mut u8,
+    input_length: u32,
+    output: *mut u8,
+    output_length: u32,
+    _buffers: PhantomData<&'buf mut [u8]>,
+}
+
+impl<'buf> Command<'buf> {
+    /// Build a command which transfers no data
+    pub fn new(
+        command_index: u16,
+        command_argument: u32,
+        command_type: CommandType,
+        response_type: ResponseType,
+    ) -> Self {
+        Command {
+            block: CommandBlock {
+                command_index,
+                command_argument,
+                command_type,
+                response_type,
+            },
+            timeout: 0,
+            input: ptr::null_mut(),
+            input_length: 0,
+            output: ptr::null_mut(),
+            output_length: 0,
+            _buffers: PhantomData,
+        }
+    }
+
+    /// Read the data sent by the device into `buffer`
+    pub fn read_into(mut self, buffer: &'buf mut [u8]) -> Self {
+        self.input = buffer.as_mut_ptr();
+        self.input_length = buffer.len().try_into().unwrap();
+        self
+    }
+
+    /// Send the data of `buffer` to the device
+    pub fn write_from(mut self, buffer: &'buf [u8]) -> Self {
+        // The buffer is only read by the controller
+        self.output = buffer.as_ptr() as *mut u8;
+        self.output_length = buffer.len().try_into().unwrap();
+        self
+    }
+
+    /// Fail if the command takes longer than `timeout`, instead of waiting
+    /// for it indefinitely
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        // The timeout is expressed in units of 100ns, 0 meaning none
+        let units = (timeout.as_nanos() / 100).max(1);
+        self.timeout = units.try_into().unwrap_or(u64::MAX);
+        self
+    }
+
+    /// The command sent to the device
+    pub fn block(&self) -> &CommandBlock {
+        &self.block
+    }
+
+    /// `CMD6` (`SWITCH`) of eMMC devices, writing `value` to the byte of the
+    /// extended CSD register at `index`
+    ///
+    /// The partition used to boot, and the one accessed by the following
+    /// commands, are selected with the `PARTITION_CONFIG` byte (index 179).
+    pub fn mmc_switch(index: u8, value: u8) -> Self {
+        // Access mode 3 writes the byte
+        let argument = (3 << 24) | (u32::from(index) << 16) | (u32::from(value) << 8);
+        Self::new(6, argument, CommandType::AC, ResponseType::R1B)
+    }
+
+    /// `CMD8` (`SEND_EXT_CSD`) of eMMC devices, reading the 512 bytes of the
+    /// extended CSD register
+    pub fn mmc_send_ext_csd(buffer: &'buf mut [u8; 512]) -> Self {
+        Self::new(8, 0, CommandType::ADTC, ResponseType::R1).read_into(buffer)
+    }
+
+    /// `CMD13` (`SEND_STATUS`), reading the status of the device with the
+    /// relative address `rca`
+    pub fn send_status(rca: u16) -> Self {
+        Self::new(13, u32::from(rca) << 16, CommandType::AC, ResponseType::R1)
+    }
+}
+
+/// The SD/MMC Pass Thru protocol.
+///
+/// It is installed on the handles of SD host controllers, each of which may
+/// have several slots.
+#[repr(C)]
+#[unsafe_guid("716ef0d9-ff83-4f69-81e9-518bd39a8e70")]
+#[derive(Protocol)]
+pub struct SdMmcPassThru {
+    io_align: usize,
+    pass_thru: unsafe extern "efiapi" fn(
+        this: *mut SdMmcPassThru,
+        slot: u8,
+        packet: *mut CommandPacket,
+        event: *mut c_void,
+    ) -> Status,
+    get_next_slot: extern "efiapi" fn(this: &SdMmcPassThru, slot: &mut u8) -> Status,
+    build_device_path: extern "efiapi" fn(
+        this: &SdMmcPassThru,
+        slot: u8,
+        device_path: &mut *mut DevicePath,
+    ) -> Status,
+    get_slot_number:
+        extern "efiapi" fn(this: &SdMmcPassThru, device_path: &DevicePath, slot: &mut u8) -> Status,
+    reset_device: extern "efiapi" fn(this: &SdMmcPassThru, slot: u8) -> Status,
+}
+
+impl SdMmcPassThru {
+    /// Alignment, in bytes, required for the data buffers of the commands
+    ///
+    /// 0 and 1 mean that any alignment is fine.
+    pub fn io_align(&self) -> usize {
+        self.io_align
+    }
+
+    /// Send a command to the device in `slot`, and return its response.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`    The data is too large for the
+    ///                                      controller
+    /// * `uefi::Status::INVALID_PARAMETER`  A buffer is not aligned, or the
+    ///                                      slot does not exist
+    /// * `uefi::Status::NO_MEDIA`           The slot is empty
+    /// * `uefi::Status::TIMEOUT`            The timeout of the command expired
+    /// * `uefi::Status::DEVICE_ERROR`       The device reported an error
+    pub fn pass_thru(&mut self, slot: u8, command: &mut Command) -> Result<StatusBlock> {
+        let mut status_block = StatusBlock::default();
+        let mut packet = CommandPacket {
+            command_block: &mut command.block,
+            status_block: &mut status_block,
+            timeout: command.timeout,
+            in_data_buffer: command.input.cast(),
+            out_data_buffer: command.output.cast(),
+            in_transfer_length: command.input_length,
+            out_transfer_length: command.output_length,
+            transaction_status: Status::SUCCESS,
+        };
+        let status = unsafe { (self.pass_thru)(self, slot, &mut packet, ptr::null_mut()) };
+        // The status of the function only tells whether the command could be
+        // sent, the one of the transaction whether the device executed it
+        let status = if status.is_success() {
+            packet.transaction_status
+        } else {
+            status
+        };
+        // The lengths are updated with the amount of data actually transferred
+        command.input_length = packet.in_transfer_length;
+        command.output_length = packet.out_transfer_length;
+        status.into_with_val(|| status_block)
+    }
+
+    /// Iterate over the slots of the controller
+    pub fn slots(&self) -> Slots<'_> {
+        Slots {
+            pass_thru: self,
+            slot: Some(0xff),
+        }
+    }
+
+    /// Build the device path node of a slot.
+    ///
+    /// The node is allocated from pool memory, and should be freed with
+    /// `BootServices::free_pool` once it is no longer needed.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`         The slot does not exist
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The node could not be allocated
+    pub fn build_device_path(&self, slot: u8) -> Result<*mut DevicePath> {
+        let mut device_path = ptr::null_mut();
+        (self.build_device_path)(self, slot, &mut device_path).into_with_val(|| device_path)
+    }
+
+    /// Get the slot designated by a device path node, as built by
+    /// `build_device_path`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`  The node is not an SD or eMMC node
+    /// * `uefi::Status::NOT_FOUND`    The slot does not exist
+    pub fn get_slot_number(&self, device_path: &DevicePath) -> Result<u8> {
+        let mut slot = 0;
+        (self.get_slot_number)(self, device_path, &mut slot).into_with_val(|| slot)
+    }
+
+    /// Reset the device in `slot`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NO_MEDIA`      The slot is empty
+    /// * `uefi::Status::DEVICE_ERROR`  The device could not be reset
+    pub fn reset_device(&mut self, slot: u8) -> Result {
+        (self.reset_device)(self, slot).into()
+    }
+}
+
+/// Iterator over the slots of an SD host controller
+///
+/// Returned by `SdMmcPassThru::slots`.
+pub struct Slots<'pass_thru> {
+    pass_thru: &'pass_thru SdMmcPassThru,
+    slot: Option<u8>,
+}
+
+impl<'pass_thru> Iterator for Slots<'pass_thru> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let mut slot = self.slot?;
+        // Starting from 0xff returns the first slot, and NOT_FOUND is
+        // returned after the last one
+        let status = (self.pass_thru.get_next_slot)(self.pass_thru, &mut slot);
+        self.slot = if status.is_success() {
+            Some(slot)
+        } else {
+            None
+        };
+        self.slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mmc_commands() {
+        let switch = Command::mmc_switch(179, 0x49);
+        assert_eq!(switch.block().command_index, 6);
+        assert_eq!(switch.block().command_argument, 0x03b3_4900);
+        assert_eq!(switch.block().response_type, ResponseType::R1B);
+
+        let mut ext_csd = [0; 512];
+        let command =
+            Command::mmc_send_ext_csd(&mut ext_csd).with_timeout(Duration::from_millis(1));
+        assert_eq!(command.input_length, 512);
+        assert_eq!(command.timeout, 10_000);
+        assert_eq!(command.block().command_type, CommandType::ADTC);
+    }
+}
@@ -0,0 +1,418 @@
+//! Storage Security Command protocol.
+//!
+//! This protocol sends the SECURITY PROTOCOL IN and SECURITY PROTOCOL OUT
+//! commands of ATA, SCSI and NVMe devices, through which self-encrypting
+//! drives are managed with the TCG storage protocols, like Opal, or with
+//! IEEE 1667. For example, an unlock tool first checks which features the
+//! drive supports:
+//!
+//! ```no_run
+//! # use uefi::proto::media::storage_security::StorageSecurityCommand;
+//! # fn f(security: &mut StorageSecurityCommand, media_id: u32) -> uefi::Result {
+//! let mut buffer = [0; 512];
+//! let discovery = security.level0_discovery(media_id, &mut buffer)?.log();
+//! if let Some(locking) = discovery.locking() {
+//!     if locking.locked() {
+//!         let com_id = discovery.base_com_id();
+//!         // Authenticate and unlock through `com_id`
+//!     }
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! The media identifier is the one of the Block I/O protocol of the device.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Result, Status};
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::time::Duration;
+
+/// Security protocol which lists the supported security protocols
+pub const SECURITY_PROTOCOL_INFORMATION: u8 = 0x00;
+
+/// First TCG security protocol, used for Level 0 Discovery and sessions
+pub const SECURITY_PROTOCOL_TCG: u8 = 0x01;
+
+/// TCG security protocol used to reset the state of a ComID
+pub const SECURITY_PROTOCOL_TCG_COM_ID_MANAGEMENT: u8 = 0x02;
+
+/// IEEE 1667 security protocol
+pub const SECURITY_PROTOCOL_IEEE_1667: u8 = 0xee;
+
+/// ComID of the Level 0 Discovery
+pub const LEVEL0_DISCOVERY_COM_ID: u16 = 0x0001;
+
+/// The Storage Security Command protocol.
+///
+/// It is installed on the handles of the devices which support security
+/// commands, along with their Block I/O protocols.
+#[repr(C)]
+#[unsafe_guid("c88b0b6d-0dfc-49a7-9cb4-49074b4c3a78")]
+#[derive(Protocol)]
+pub struct StorageSecurityCommand {
+    receive_data: unsafe extern "efiapi" fn(
+        this: *mut StorageSecurityCommand,
+        media_id: u32,
+        timeout: u64,
+        security_protocol_id: u8,
+        security_protocol_specific_data: u16,
+        payload_buffer_size: usize,
+        payload_buffer: *mut c_void,
+        payload_transfer_size: *mut usize,
+    ) -> Status,
+    send_data: unsafe extern "efiapi" fn(
+        this: *mut StorageSecurityCommand,
+        media_id: u32,
+        timeout: u64,
+        security_protocol_id: u8,
+        security_protocol_specific_data: u16,
+        payload_buffer_size: usize,
+        payload_buffer: *const c_void,
+    ) -> Status,
+}
+
+/// The timeout in units of 100ns, 0 meaning none
+fn timeout_units(timeout: Option<Duration>) -> u64 {
+    timeout.map_or(0, |timeout| {
+        (timeout.as_nanos() / 100)
+            .max(1)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    })
+}
+
+impl StorageSecurityCommand {
+    /// Send a SECURITY PROTOCOL IN command, and return the part of `buffer`
+    /// which was filled.
+    ///
+    /// `protocol_specific` is given in the native byte order, like the
+    /// ComID of the TCG protocols. The command fails if it does not complete
+    /// before `timeout`, or waits for it if `timeout` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`        The device does not support
+    ///                                      security commands
+    /// * `uefi::Status::NO_MEDIA`           There is no medium in the device
+    /// * `uefi::Status::MEDIA_CHANGED`      `media_id` is not the one of the
+    ///                                      current medium
+    /// * `uefi::Status::DEVICE_ERROR`       The command failed
+    /// * `uefi::Status::TIMEOUT`            The command did not complete in
+    ///                                      time
+    pub fn receive_data<'buf>(
+        &mut self,
+        media_id: u32,
+        timeout: Option<Duration>,
+        protocol_id: u8,
+        protocol_specific: u16,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8]> {
+        let mut transferred = 0;
+        let status = unsafe {
+            (self.receive_data)(
+                self,
+                media_id,
+                timeout_units(timeout),
+                protocol_id,
+                protocol_specific.to_be(),
+                buffer.len(),
+                buffer.as_mut_ptr().cast(),
+                &mut transferred,
+            )
+        };
+        status.into_with_val(move || &buffer[..transferred.min(buffer.len())])
+    }
+
+    /// Send a SECURITY PROTOCOL OUT command, with `payload`.
+    ///
+    /// # Errors
+    ///
+    /// See `receive_data`.
+    pub fn send_data(
+        &mut self,
+        media_id: u32,
+        timeout: Option<Duration>,
+        protocol_id: u8,
+        protocol_specific: u16,
+        payload: &[u8],
+    ) -> Result {
+        unsafe {
+            (self.send_data)(
+                self,
+                media_id,
+                timeout_units(timeout),
+                protocol_id,
+                protocol_specific.to_be(),
+                payload.len(),
+                payload.as_ptr().cast(),
+            )
+        }
+        .into()
+    }
+
+    /// List the security protocols which the device supports, reading them
+    /// into `buffer`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::COMPROMISED_DATA`  The list is malformed
+    ///
+    /// See also `receive_data`.
+    pub fn supported_protocols<'buf>(
+        &mut self,
+        media_id: u32,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8]> {
+        let (status, data) = self
+            .receive_data(media_id, None, SECURITY_PROTOCOL_INFORMATION, 0, buffer)?
+            .split();
+        match supported_protocols(data) {
+            Some(list) => status.into_with_val(|| list),
+            None => Err(Status::COMPROMISED_DATA.into()),
+        }
+    }
+
+    /// Read the TCG Level 0 Discovery of the device into `buffer`, which
+    /// tells which TCG features the device supports and in which state they
+    /// are.
+    ///
+    /// A buffer of 512 bytes is usually large enough.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::COMPROMISED_DATA`  The discovery is malformed
+    ///
+    /// See also `receive_data`.
+    pub fn level0_discovery<'buf>(
+        &mut self,
+        media_id: u32,
+        buffer: &'buf mut [u8],
+    ) -> Result<Level0Discovery<'buf>> {
+        let (status, data) = self
+            .receive_data(
+                media_id,
+                None,
+                SECURITY_PROTOCOL_TCG,
+                LEVEL0_DISCOVERY_COM_ID,
+                buffer,
+            )?
+            .split();
+        match Level0Discovery::parse(data) {
+            Some(discovery) => status.into_with_val(|| discovery),
+            None => Err(Status::COMPROMISED_DATA.into()),
+        }
+    }
+}
+
+/// The security protocols listed in the data of the information protocol
+fn supported_protocols(data: &[u8]) -> Option<&[u8]> {
+    let len = u16::from_be_bytes(data.get(6..8)?.try_into().unwrap());
+    data.get(8..8 + usize::from(len))
+}
+
+/// Feature codes of the TCG Level 0 Discovery
+pub mod feature_code {
+    /// Capabilities of the TPer, the security part of the device
+    pub const TPER: u16 = 0x0001;
+    /// State of the locking
+    pub const LOCKING: u16 = 0x0002;
+    /// Alignment of the locking ranges
+    pub const GEOMETRY: u16 = 0x0003;
+    /// Enterprise SSC
+    pub const ENTERPRISE: u16 = 0x0100;
+    /// Opal SSC 1.0
+    pub const OPAL_V1: u16 = 0x0200;
+    /// Single-user mode
+    pub const SINGLE_USER_MODE: u16 = 0x0201;
+    /// Additional DataStore tables
+    pub const DATA_STORE: u16 = 0x0202;
+    /// Opal SSC 2.0
+    pub const OPAL_V2: u16 = 0x0203;
+    /// Opalite SSC
+    pub const OPALITE: u16 = 0x0301;
+    /// Pyrite SSC 1.0
+    pub const PYRITE_V1: u16 = 0x0302;
+    /// Pyrite SSC 2.0
+    pub const PYRITE_V2: u16 = 0x0303;
+    /// Ruby SSC
+    pub const RUBY: u16 = 0x0304;
+}
+
+/// A TCG Level 0 Discovery
+#[derive(Debug, Copy, Clone)]
+pub struct Level0Discovery<'a> {
+    data: &'a [u8],
+}
+
+/// Size of the header of the discovery
+const HEADER_SIZE: usize = 48;
+
+impl<'a> Level0Discovery<'a> {
+    /// Parse the discovery in `data`, or return `None` if it is malformed
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let len = u32::from_be_bytes(data.get(..4)?.try_into().unwrap());
+        // The length does not count the length field itself
+        let total = (len as usize).checked_add(4)?;
+        if total < HEADER_SIZE {
+            return None;
+        }
+        Some(Level0Discovery {
+            data: data.get(..total)?,
+        })
+    }
+
+    /// The version of the format, as `(major, minor)`
+    pub fn version(&self) -> (u16, u16) {
+        let word = |offset: usize| u16::from_be_bytes([self.data[offset], self.data[offset + 1]]);
+        (word(4), word(6))
+    }
+
+    /// The vendor-specific part of the header
+    pub fn vendor_specific(&self) -> &'a [u8] {
+        &self.data[16..HEADER_SIZE]
+    }
+
+    /// Iterate over the features of the device
+    ///
+    /// The iteration stops at the first truncated feature.
+    pub fn features(&self) -> impl Iterator<Item = Feature<'a>> {
+        let mut rest = &self.data[HEADER_SIZE..];
+        core::iter::from_fn(move || {
+            if rest.len() < 4 {
+                return None;
+            }
+            let len = usize::from(rest[3]);
+            let data = rest.get(4..4 + len)?;
+            let feature = Feature {
+                code: u16::from_be_bytes([rest[0], rest[1]]),
+                version: rest[2] >> 4,
+                data,
+            };
+            rest = &rest[4 + len..];
+            Some(feature)
+        })
+    }
+
+    /// The feature with the code `code`
+    pub fn feature(&self, code: u16) -> Option<Feature<'a>> {
+        self.features().find(|feature| feature.code == code)
+    }
+
+    /// The Locking feature, if the device supports locking
+    pub fn locking(&self) -> Option<LockingFeature> {
+        let feature = self.feature(feature_code::LOCKING)?;
+        feature.data.first().map(|&flags| LockingFeature(flags))
+    }
+
+    /// The first ComID through which the SSC of the device, like Opal, is
+    /// used, or `None` if no SSC which has one is supported
+    pub fn base_com_id(&self) -> Option<u16> {
+        const SSCS: [u16; 6] = [
+            feature_code::OPAL_V2,
+            feature_code::OPAL_V1,
+            feature_code::RUBY,
+            feature_code::PYRITE_V2,
+            feature_code::PYRITE_V1,
+            feature_code::OPALITE,
+        ];
+        SSCS.iter().find_map(|&code| {
+            let data = self.feature(code)?.data;
+            Some(u16::from_be_bytes(data.get(..2)?.try_into().unwrap()))
+        })
+    }
+}
+
+/// A feature descriptor of the TCG Level 0 Discovery
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Feature<'a> {
+    /// The code of the feature, among `feature_code`
+    pub code: u16,
+    /// The version of the descriptor
+    pub version: u8,
+    /// The data of the descriptor, which depends on the feature
+    pub data: &'a [u8],
+}
+
+/// The state of the locking of a device
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LockingFeature(pub u8);
+
+impl LockingFeature {
+    /// Whether the device supports locking
+    pub fn locking_supported(self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    /// Whether a locking range has been configured
+    pub fn locking_enabled(self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    /// Whether a locking range is locked
+    pub fn locked(self) -> bool {
+        self.0 & 0x04 != 0
+    }
+
+    /// Whether the data on the medium is encrypted
+    pub fn media_encryption(self) -> bool {
+        self.0 & 0x08 != 0
+    }
+
+    /// Whether the device presents the shadow MBR instead of the start of the
+    /// medium
+    pub fn mbr_enabled(self) -> bool {
+        self.0 & 0x10 != 0
+    }
+
+    /// Whether the shadow MBR is no longer presented, after unlocking
+    pub fn mbr_done(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovery() {
+        let mut data = [0; HEADER_SIZE + 16 + 20];
+        let len = (data.len() - 4) as u32;
+        data[..4].copy_from_slice(&len.to_be_bytes());
+        data[6..8].copy_from_slice(&1u16.to_be_bytes());
+        // Locking feature: supported, enabled and locked
+        data[48..52].copy_from_slice(&[0x00, 0x02, 0x10, 12]);
+        data[52] = 0x07;
+        // Opal 2.0 feature, with a base ComID of 0x07fe
+        data[64..68].copy_from_slice(&[0x02, 0x03, 0x20, 16]);
+        data[68..70].copy_from_slice(&0x07feu16.to_be_bytes());
+
+        let discovery = Level0Discovery::parse(&data).unwrap();
+        assert_eq!(discovery.version(), (0, 1));
+        let codes: [u16; 2] = [feature_code::LOCKING, feature_code::OPAL_V2];
+        assert!(discovery
+            .features()
+            .map(|f| f.code)
+            .eq(codes.iter().copied()));
+        assert_eq!(discovery.feature(feature_code::OPAL_V2).unwrap().version, 2);
+        let locking = discovery.locking().unwrap();
+        assert!(locking.locking_supported() && locking.locking_enabled() && locking.locked());
+        assert!(!locking.mbr_enabled());
+        assert_eq!(discovery.base_com_id(), Some(0x07fe));
+
+        assert!(Level0Discovery::parse(&data[..40]).is_none());
+        // A truncated feature ends the iteration
+        let discovery = Level0Discovery::parse(&data[..HEADER_SIZE + 20]);
+        assert!(discovery.is_none());
+        let mut short = data;
+        short[..4].copy_from_slice(&((HEADER_SIZE + 20 - 4) as u32).to_be_bytes());
+        let discovery = Level0Discovery::parse(&short).unwrap();
+        assert_eq!(discovery.features().count(), 1);
+
+        let list = [0, 0, 0, 0, 0, 0, 0, 3, 0x00, 0x01, 0xee, 0xff];
+        assert_eq!(supported_protocols(&list), Some(&[0x00, 0x01, 0xee][..]));
+        assert_eq!(supported_protocols(&list[..9]), None);
+    }
+}
@@ -0,0 +1,77 @@
+//! Memory Attribute protocol.
+
+use crate::proto::Protocol;
+use crate::table::boot::MemoryAttribute;
+use crate::{unsafe_guid, Result, Status};
+use core::ops::Range;
+
+/// Protocol for querying and changing the access attributes of memory, such
+/// as whether it is read-only or may contain executable code
+/// (`EFI_MEMORY_ATTRIBUTE_PROTOCOL`).
+///
+/// Only the `READ_PROTECT`, `EXECUTE_PROTECT` and `READ_ONLY` attributes are
+/// supported. All ranges must be page-aligned.
+#[repr(C)]
+#[unsafe_guid("f4560cf6-40ec-4b4a-a192-bf1d57d0b189")]
+#[derive(Protocol)]
+pub struct MemoryProtection {
+    get_memory_attributes:
+        extern "efiapi" fn(this: &Self, start: u64, length: u64, attributes: &mut u64) -> Status,
+    set_memory_attributes:
+        extern "efiapi" fn(this: &Self, start: u64, length: u64, attributes: u64) -> Status,
+    clear_memory_attributes:
+        extern "efiapi" fn(this: &Self, start: u64, length: u64, attributes: u64) -> Status,
+}
+
+impl MemoryProtection {
+    /// Get the attributes of a range of memory.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NO_MAPPING`         The range is not entirely mapped,
+    ///                                      or its attributes are not uniform
+    /// * `uefi::Status::INVALID_PARAMETER`  The range is not page-aligned
+    pub fn get_memory_attributes(&self, range: Range<u64>) -> Result<MemoryAttribute> {
+        let mut attributes = 0;
+        (self.get_memory_attributes)(self, range.start, range.end - range.start, &mut attributes)
+            .into_with_val(|| MemoryAttribute::from_bits_truncate(attributes))
+    }
+
+    /// Set the given attributes on a range of memory, leaving other attributes
+    /// untouched.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`        The attributes can not be set on this range
+    /// * `uefi::Status::INVALID_PARAMETER`  The range is not page-aligned, or
+    ///                                      unsupported attributes were requested
+    pub fn set_memory_attributes(&self, range: Range<u64>, attributes: MemoryAttribute) -> Result {
+        (self.set_memory_attributes)(
+            self,
+            range.start,
+            range.end - range.start,
+            attributes.bits(),
+        )
+        .into()
+    }
+
+    /// Clear the given attributes on a range of memory, leaving other
+    /// attributes untouched.
+    ///
+    /// # Errors
+    ///
+    /// See `set_memory_attributes`.
+    pub fn clear_memory_attributes(
+        &self,
+        range: Range<u64>,
+        attributes: MemoryAttribute,
+    ) -> Result {
+        (self.clear_memory_attributes)(
+            self,
+            range.start,
+            range.end - range.start,
+            attributes.bits(),
+        )
+        .into()
+    }
+}
@@ -8,7 +8,9 @@
 //! and are usually retrieved from a standard UEFI table or
 //! by querying a handle.
 
-use crate::Identify;
+use crate::table::boot::BootServices;
+use crate::{Handle, Identify, Result};
+use core::ffi::c_void;
 
 /// Common trait implemented by all standard UEFI protocols
 ///
@@ -28,10 +30,61 @@ pub trait Protocol: Identify {}
 
 pub use uefi_macros::Protocol;
 
+/// A protocol implemented in Rust, which can be installed on a handle
+///
+/// The firmware keeps a pointer to the implementation once it is installed,
+/// so implementations are boxed, and must not be dropped or moved before they
+/// are uninstalled.
+///
+/// # Safety
+///
+/// `interface` must return a pointer to a structure laid out as `Protocol`.
+pub unsafe trait ProtocolImpl {
+    /// The protocol which is implemented
+    type Protocol: Protocol;
+
+    /// The pointer given to the firmware
+    fn interface(&mut self) -> *mut c_void;
+
+    /// Install the protocol on `handle`, or on a new handle if `handle` is
+    /// `None`, and return the handle.
+    ///
+    /// # Safety
+    ///
+    /// The implementation must not be dropped or moved before it is
+    /// uninstalled.
+    unsafe fn install(&mut self, bt: &BootServices, handle: Option<Handle>) -> Result<Handle> {
+        bt.install_protocol_interface(handle, &Self::Protocol::GUID, self.interface())
+    }
+
+    /// Uninstall the protocol from `handle`.
+    ///
+    /// # Safety
+    ///
+    /// See `BootServices::uninstall_protocol_interface`.
+    unsafe fn uninstall(&mut self, bt: &BootServices, handle: Handle) -> Result {
+        bt.uninstall_protocol_interface(handle, &Self::Protocol::GUID, self.interface())
+    }
+}
+
+pub mod acpi;
+pub mod bluetooth;
 pub mod console;
 pub mod debug;
+pub mod decompress;
 pub mod device_path;
+pub mod driver;
+pub mod hii;
 pub mod loaded_image;
 pub mod media;
+pub mod memory_protection;
+pub mod network;
+pub mod pci;
+pub mod performance;
 pub mod pi;
+pub mod reset_notification;
+pub mod rng;
+pub mod security;
+pub mod shell;
 pub mod shim;
+pub mod string;
@@ -0,0 +1,249 @@
+//! Adapter Information protocol.
+//!
+//! Network interfaces describe themselves with information blocks, each
+//! identified by a GUID. The usual ones are typed here, and implement
+//! [`InformationBlock`], so that for instance the link state is checked
+//! before trying a network boot:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::network::adapter_info::{AdapterInformation, MediaState};
+//! # fn f(bt: &BootServices, adapter: &mut AdapterInformation) -> uefi::Result {
+//! let state = adapter.get::<MediaState>(bt)?.log();
+//! if !state.is_connected() {
+//!     log::warn!("No network cable");
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::Protocol;
+use crate::table::boot::{BootServices, PoolSlice};
+use crate::{unsafe_guid, Guid, Identify, Result, Status};
+use core::ffi::c_void;
+use core::{mem, ptr};
+
+/// An information block, whose GUID is its `Identify::GUID`
+///
+/// # Safety
+///
+/// The type must have the layout of the block, and be valid for all the
+/// values which the firmware may return.
+pub unsafe trait InformationBlock: Identify + Copy {}
+
+/// State of the link (`EFI_ADAPTER_INFO_MEDIA_STATE`)
+#[repr(C)]
+#[unsafe_guid("d7c74207-a831-4a26-b1f5-d193065ce8b6")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MediaState {
+    /// `SUCCESS` if the link is up, `NO_MEDIA` if it is down, and
+    /// `NOT_READY` if it is being negotiated
+    pub media_state: Status,
+}
+
+impl MediaState {
+    /// Whether the link is up
+    pub fn is_connected(&self) -> bool {
+        self.media_state.is_success()
+    }
+}
+
+unsafe impl InformationBlock for MediaState {}
+
+/// Network boot capabilities of the adapter
+/// (`EFI_ADAPTER_INFO_NETWORK_BOOT`)
+#[repr(C)]
+#[unsafe_guid("1fbd2960-4130-41e5-94ac-d2cf037fb37c")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NetworkBoot {
+    /// Whether the adapter can boot from iSCSI over IPv4
+    pub iscsi_ipv4_boot_capability: bool,
+    /// Whether the adapter can boot from iSCSI over IPv6
+    pub iscsi_ipv6_boot_capability: bool,
+    /// Whether the adapter can boot from FCoE
+    pub fcoe_boot_capability: bool,
+    /// Whether the adapter offloads the protocols
+    pub offload_capability: bool,
+    /// Whether the adapter supports iSCSI multipath
+    pub iscsi_mpio_capability: bool,
+    /// Whether booting from iSCSI over IPv4 is enabled
+    pub iscsi_ipv4_boot: bool,
+    /// Whether booting from iSCSI over IPv6 is enabled
+    pub iscsi_ipv6_boot: bool,
+    /// Whether booting from FCoE is enabled
+    pub fcoe_boot: bool,
+}
+
+unsafe impl InformationBlock for NetworkBoot {}
+
+/// MAC address used for storage area networks
+/// (`EFI_ADAPTER_INFO_SAN_MAC_ADDRESS`)
+#[repr(C)]
+#[unsafe_guid("114da5ef-2cf1-4e12-9bbb-c470b55205d9")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SanMacAddress {
+    /// The address, padded with zeroes to 32 bytes
+    pub san_mac_address: [u8; 32],
+}
+
+impl SanMacAddress {
+    /// The address of an Ethernet adapter
+    pub fn ethernet_address(&self) -> [u8; 6] {
+        let mut address = [0; 6];
+        address.copy_from_slice(&self.san_mac_address[..6]);
+        address
+    }
+}
+
+unsafe impl InformationBlock for SanMacAddress {}
+
+/// Whether the UNDI driver of the adapter supports IPv6
+/// (`EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT`)
+///
+/// The network stack only uses IPv6 on the adapter if the block is present
+/// and says so.
+#[repr(C)]
+#[unsafe_guid("4bd56be3-4975-4d8a-a0ad-c491204b5d4d")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UndiIpv6Support {
+    /// Whether IPv6 is supported
+    pub ipv6_support: bool,
+}
+
+unsafe impl InformationBlock for UndiIpv6Support {}
+
+/// Kind of medium of the adapter (`EFI_ADAPTER_INFO_MEDIA_TYPE`)
+#[repr(C)]
+#[unsafe_guid("8484472f-71ec-411a-b39c-62cd94d9916e")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MediaType {
+    /// 1 for Ethernet, 2 for Wi-Fi, 3 for cellular, 4 for Bluetooth
+    pub media_type: u8,
+}
+
+unsafe impl InformationBlock for MediaType {}
+
+/// The Adapter Information protocol.
+///
+/// It is installed on the handles of the network interfaces.
+#[repr(C)]
+#[unsafe_guid("e5dd1403-d622-c24e-8488-c71b17f5e802")]
+#[derive(Protocol)]
+pub struct AdapterInformation {
+    get_information: unsafe extern "efiapi" fn(
+        this: *mut AdapterInformation,
+        information_type: *const Guid,
+        information_block: *mut *mut c_void,
+        information_block_size: *mut usize,
+    ) -> Status,
+    set_information: unsafe extern "efiapi" fn(
+        this: *mut AdapterInformation,
+        information_type: *const Guid,
+        information_block: *const c_void,
+        information_block_size: usize,
+    ) -> Status,
+    get_supported_types: unsafe extern "efiapi" fn(
+        this: *mut AdapterInformation,
+        info_types_buffer: *mut *mut Guid,
+        info_types_buffer_count: *mut usize,
+    ) -> Status,
+}
+
+impl AdapterInformation {
+    /// The GUIDs of the information blocks which the adapter provides
+    pub fn supported_types<'boot>(
+        &mut self,
+        bt: &'boot BootServices,
+    ) -> Result<PoolSlice<'boot, Guid>> {
+        let mut types = ptr::null_mut();
+        let mut count = 0;
+        unsafe { (self.get_supported_types)(self, &mut types, &mut count) }
+            .into_with_val(|| unsafe { PoolSlice::from_raw(bt, types, count) })
+    }
+
+    /// Get the raw information block identified by `information_type`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`   The adapter does not provide the block
+    /// * `uefi::Status::DEVICE_ERROR`  The adapter could not be queried
+    pub fn get_information<'boot>(
+        &mut self,
+        bt: &'boot BootServices,
+        information_type: &Guid,
+    ) -> Result<PoolSlice<'boot, u8>> {
+        let mut block = ptr::null_mut();
+        let mut size = 0;
+        unsafe { (self.get_information)(self, information_type, &mut block, &mut size) }
+            .into_with_val(|| unsafe { PoolSlice::from_raw(bt, block.cast(), size) })
+    }
+
+    /// Get the information block `T`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       The adapter does not provide the
+    ///                                     block
+    /// * `uefi::Status::COMPROMISED_DATA`  The block is too short
+    pub fn get<T: InformationBlock>(&mut self, bt: &BootServices) -> Result<T> {
+        let (status, block) = self.get_information(bt, &T::GUID)?.split();
+        if block.len() < mem::size_of::<T>() {
+            return Err(Status::COMPROMISED_DATA.into());
+        }
+        let value = unsafe { block.as_ptr().cast::<T>().read_unaligned() };
+        status.into_with_val(|| value)
+    }
+
+    /// Set the raw information block identified by `information_type`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`        The block cannot be set
+    /// * `uefi::Status::WRITE_PROTECTED`    The block is read-only
+    /// * `uefi::Status::INVALID_PARAMETER`  The block is invalid
+    pub fn set_information(&mut self, information_type: &Guid, block: &[u8]) -> Result {
+        unsafe {
+            (self.set_information)(self, information_type, block.as_ptr().cast(), block.len())
+        }
+        .into()
+    }
+
+    /// Set the information block `T`.
+    ///
+    /// # Errors
+    ///
+    /// See `set_information`.
+    pub fn set<T: InformationBlock>(&mut self, block: &T) -> Result {
+        unsafe {
+            (self.set_information)(
+                self,
+                &T::GUID,
+                (block as *const T).cast(),
+                mem::size_of::<T>(),
+            )
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_layouts() {
+        assert_eq!(mem::size_of::<MediaState>(), mem::size_of::<usize>());
+        assert_eq!(mem::size_of::<NetworkBoot>(), 8);
+        assert_eq!(mem::size_of::<SanMacAddress>(), 32);
+        assert_eq!(mem::size_of::<UndiIpv6Support>(), 1);
+
+        let mut address = SanMacAddress {
+            san_mac_address: [0; 32],
+        };
+        address.san_mac_address[..6].copy_from_slice(&[0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+        assert_eq!(
+            address.ethernet_address(),
+            [0x52, 0x54, 0, 0x12, 0x34, 0x56]
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Completion of the asynchronous operations of the network protocols.
+//!
+//! The protocols of the transport layer do not support blocking calls: each
+//! operation takes a token with an event, which is signaled when it is done.
+//! The operation only progresses when the driver is polled, or when the
+//! timers of the firmware fire.
+
+use crate::table::boot::{BootServices, EventType, Tpl};
+use crate::{Event, Result};
+use core::ffi::c_void;
+
+/// A fragment of the data of a packet
+#[repr(C)]
+pub(super) struct FragmentData {
+    pub length: u32,
+    pub buffer: *mut c_void,
+}
+
+impl FragmentData {
+    pub fn new(buffer: *mut u8, len: usize) -> Self {
+        FragmentData {
+            length: len as u32,
+            buffer: buffer.cast(),
+        }
+    }
+}
+
+/// An event for a single operation, which is closed when it is dropped
+pub(super) struct CompletionEvent<'boot> {
+    bt: &'boot BootServices,
+    event: Event,
+}
+
+impl<'boot> CompletionEvent<'boot> {
+    pub fn new(bt: &'boot BootServices) -> Result<Self> {
+        // The event is only checked, so it does not need a notification
+        let event = unsafe { bt.create_event(EventType::empty(), Tpl::CALLBACK, None) }?.log();
+        Ok(CompletionEvent { bt, event }.into())
+    }
+
+    pub fn event(&self) -> Event {
+        self.event
+    }
+
+    /// Call `poll` until the operation completes
+    pub fn wait(&self, mut poll: impl FnMut()) {
+        // Errors only happen for events with notifications
+        while !self
+            .bt
+            .check_event(self.event)
+            .map_or(true, |c| c.split().1)
+        {
+            poll();
+        }
+    }
+}
+
+impl Drop for CompletionEvent<'_> {
+    fn drop(&mut self) {
+        let _ = self.bt.close_event(self.event);
+    }
+}
@@ -0,0 +1,374 @@
+//! HTTP message structures.
+//!
+//! These types are shared by the protocols which exchange HTTP messages with
+//! the firmware, like the REST EX protocol.
+
+use crate::table::boot::BootServices;
+use crate::{CStr16, CStr8, Char16, Char8};
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr;
+use core::slice;
+
+newtype_enum! {
+    /// HTTP request method (`EFI_HTTP_METHOD`)
+    pub enum HttpMethod: u32 => {
+        /// `GET` request
+        GET     = 0,
+        /// `POST` request
+        POST    = 1,
+        /// `PATCH` request
+        PATCH   = 2,
+        /// `OPTIONS` request
+        OPTIONS = 3,
+        /// `CONNECT` request
+        CONNECT = 4,
+        /// `HEAD` request
+        HEAD    = 5,
+        /// `PUT` request
+        PUT     = 6,
+        /// `DELETE` request
+        DELETE  = 7,
+        /// `TRACE` request
+        TRACE   = 8,
+    }
+}
+
+newtype_enum! {
+    /// HTTP version (`EFI_HTTP_VERSION`)
+    pub enum HttpVersion: u32 => {
+        /// HTTP/1.0
+        HTTP_1_0 = 0,
+        /// HTTP/1.1
+        HTTP_1_1 = 1,
+    }
+}
+
+newtype_enum! {
+    /// HTTP response status (`EFI_HTTP_STATUS_CODE`)
+    ///
+    /// The values are indices, not the codes of the HTTP specification,
+    /// which are given by `code`.
+    pub enum HttpStatusCode: u32 => {
+        /// Status not known to the firmware
+        UNSUPPORTED_STATUS              = 0,
+        /// 100 Continue
+        CONTINUE                        = 1,
+        /// 101 Switching Protocols
+        SWITCHING_PROTOCOLS             = 2,
+        /// 200 Ok
+        OK                              = 3,
+        /// 201 Created
+        CREATED                         = 4,
+        /// 202 Accepted
+        ACCEPTED                        = 5,
+        /// 203 Non-Authoritative Information
+        NON_AUTHORITATIVE_INFORMATION   = 6,
+        /// 204 No Content
+        NO_CONTENT                      = 7,
+        /// 205 Reset Content
+        RESET_CONTENT                   = 8,
+        /// 206 Partial Content
+        PARTIAL_CONTENT                 = 9,
+        /// 300 Multiple Choices
+        MULTIPLE_CHOICES                = 10,
+        /// 301 Moved Permanently
+        MOVED_PERMANENTLY               = 11,
+        /// 302 Found
+        FOUND                           = 12,
+        /// 303 See Other
+        SEE_OTHER                       = 13,
+        /// 304 Not Modified
+        NOT_MODIFIED                    = 14,
+        /// 305 Use Proxy
+        USE_PROXY                       = 15,
+        /// 307 Temporary Redirect
+        TEMPORARY_REDIRECT              = 16,
+        /// 400 Bad Request
+        BAD_REQUEST                     = 17,
+        /// 401 Unauthorized
+        UNAUTHORIZED                    = 18,
+        /// 402 Payment Required
+        PAYMENT_REQUIRED                = 19,
+        /// 403 Forbidden
+        FORBIDDEN                       = 20,
+        /// 404 Not Found
+        NOT_FOUND                       = 21,
+        /// 405 Method Not Allowed
+        METHOD_NOT_ALLOWED              = 22,
+        /// 406 Not Acceptable
+        NOT_ACCEPTABLE                  = 23,
+        /// 407 Proxy Authentication Required
+        PROXY_AUTHENTICATION_REQUIRED   = 24,
+        /// 408 Request Time-out
+        REQUEST_TIME_OUT                = 25,
+        /// 409 Conflict
+        CONFLICT                        = 26,
+        /// 410 Gone
+        GONE                            = 27,
+        /// 411 Length Required
+        LENGTH_REQUIRED                 = 28,
+        /// 412 Precondition Failed
+        PRECONDITION_FAILED             = 29,
+        /// 413 Request Entity Too Large
+        REQUEST_ENTITY_TOO_LARGE        = 30,
+        /// 414 Request URI Too Large
+        REQUEST_URI_TOO_LARGE           = 31,
+        /// 415 Unsupported Media Type
+        UNSUPPORTED_MEDIA_TYPE          = 32,
+        /// 416 Requested Range Not Satisfied
+        REQUESTED_RANGE_NOT_SATISFIED   = 33,
+        /// 417 Expectation Failed
+        EXPECTATION_FAILED              = 34,
+        /// 500 Internal Server Error
+        INTERNAL_SERVER_ERROR           = 35,
+        /// 501 Not Implemented
+        NOT_IMPLEMENTED                 = 36,
+        /// 502 Bad Gateway
+        BAD_GATEWAY                     = 37,
+        /// 503 Service Unavailable
+        SERVICE_UNAVAILABLE             = 38,
+        /// 504 Gateway Time-out
+        GATEWAY_TIME_OUT                = 39,
+        /// 505 HTTP Version Not Supported
+        HTTP_VERSION_NOT_SUPPORTED      = 40,
+        /// 308 Permanent Redirect
+        PERMANENT_REDIRECT              = 41,
+    }
+}
+
+impl HttpStatusCode {
+    /// The code of the status in the HTTP specification, like 404 for
+    /// `NOT_FOUND`
+    pub fn code(self) -> Option<u16> {
+        const CODES: [u16; 41] = [
+            100, 101, 200, 201, 202, 203, 204, 205, 206, 300, 301, 302, 303, 304, 305, 307, 400,
+            401, 402, 403, 404, 405, 406, 407, 408, 409, 410, 411, 412, 413, 414, 415, 416, 417,
+            500, 501, 502, 503, 504, 505, 308,
+        ];
+        let index = (self.0 as usize).checked_sub(1)?;
+        CODES.get(index).copied()
+    }
+
+    /// Whether the status is in the 2xx range
+    pub fn is_success(self) -> bool {
+        matches!(self.code(), Some(200..=299))
+    }
+}
+
+/// A header field of an HTTP message (`EFI_HTTP_HEADER`)
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct HttpHeader<'a> {
+    field_name: *const Char8,
+    field_value: *const Char8,
+    _strings: PhantomData<&'a CStr8>,
+}
+
+impl<'a> HttpHeader<'a> {
+    /// A header field, for a request
+    pub fn new(name: &'a CStr8, value: &'a CStr8) -> Self {
+        HttpHeader {
+            field_name: name.as_ptr(),
+            field_value: value.as_ptr(),
+            _strings: PhantomData,
+        }
+    }
+
+    /// The name of the field
+    pub fn name(&self) -> &'a [u8] {
+        unsafe { CStr8::from_ptr(self.field_name) }.to_bytes()
+    }
+
+    /// The value of the field
+    pub fn value(&self) -> &'a [u8] {
+        if self.field_value.is_null() {
+            &[]
+        } else {
+            unsafe { CStr8::from_ptr(self.field_value) }.to_bytes()
+        }
+    }
+}
+
+/// An HTTP request
+#[derive(Copy, Clone)]
+pub struct HttpRequest<'a> {
+    /// The method of the request
+    pub method: HttpMethod,
+    /// The URL of the resource, either absolute or relative to the service
+    pub url: &'a CStr16,
+    /// The header fields
+    pub headers: &'a [HttpHeader<'a>],
+    /// The body, which may be empty
+    pub body: &'a [u8],
+}
+
+/// `EFI_HTTP_REQUEST_DATA`
+#[repr(C)]
+struct RequestData {
+    method: HttpMethod,
+    url: *const Char16,
+}
+
+/// `EFI_HTTP_RESPONSE_DATA`
+#[repr(C)]
+struct ResponseData {
+    status_code: HttpStatusCode,
+}
+
+/// `EFI_HTTP_MESSAGE`
+///
+/// The data is a request or a response, depending on the direction.
+#[repr(C)]
+pub(crate) struct HttpMessage {
+    data: *mut c_void,
+    header_count: usize,
+    headers: *mut HttpHeader<'static>,
+    body_length: usize,
+    body: *mut c_void,
+}
+
+impl HttpMessage {
+    /// An empty message, to be filled by the firmware
+    pub(crate) fn empty() -> Self {
+        HttpMessage {
+            data: ptr::null_mut(),
+            header_count: 0,
+            headers: ptr::null_mut(),
+            body_length: 0,
+            body: ptr::null_mut(),
+        }
+    }
+
+    /// Call `f` with the message of `request`, which must not be kept
+    pub(crate) fn with_request<T>(request: &HttpRequest, f: impl FnOnce(&mut Self) -> T) -> T {
+        let mut data = RequestData {
+            method: request.method,
+            url: request.url.as_ptr(),
+        };
+        let mut message = HttpMessage {
+            data: (&mut data as *mut RequestData).cast(),
+            header_count: request.headers.len(),
+            // The firmware only reads the headers and body of requests
+            headers: request.headers.as_ptr() as *mut HttpHeader,
+            body_length: request.body.len(),
+            body: if request.body.is_empty() {
+                ptr::null_mut()
+            } else {
+                request.body.as_ptr() as *mut c_void
+            },
+        };
+        f(&mut message)
+    }
+}
+
+/// An HTTP response, allocated by the firmware
+///
+/// Its headers and body are freed when it is dropped.
+pub struct HttpResponse<'boot> {
+    boot_services: &'boot BootServices,
+    message: HttpMessage,
+}
+
+impl<'boot> HttpResponse<'boot> {
+    /// Take ownership of a response message filled by the firmware
+    pub(crate) fn new(boot_services: &'boot BootServices, message: HttpMessage) -> Self {
+        HttpResponse {
+            boot_services,
+            message,
+        }
+    }
+
+    /// The status of the response, if the firmware returned one
+    pub fn status_code(&self) -> Option<HttpStatusCode> {
+        let data = self.message.data as *const ResponseData;
+        unsafe { data.as_ref() }.map(|data| data.status_code)
+    }
+
+    /// The header fields of the response
+    pub fn headers(&self) -> &[HttpHeader<'_>] {
+        if self.message.headers.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.message.headers, self.message.header_count) }
+        }
+    }
+
+    /// The value of the first header field called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers()
+            .iter()
+            .find(|header| header.name().eq_ignore_ascii_case(name.as_bytes()))
+            .map(|header| header.value())
+    }
+
+    /// The body of the response
+    pub fn body(&self) -> &[u8] {
+        if self.message.body.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.message.body.cast(), self.message.body_length) }
+        }
+    }
+}
+
+impl Drop for HttpResponse<'_> {
+    fn drop(&mut self) {
+        // Ignore the results, we can't do anything about an error here.
+        let bt = self.boot_services;
+        for header in self.headers() {
+            let _ = bt.free_pool(header.field_name as *mut u8);
+            if !header.field_value.is_null() {
+                let _ = bt.free_pool(header.field_value as *mut u8);
+            }
+        }
+        for buffer in [
+            self.message.headers.cast::<u8>(),
+            self.message.body.cast(),
+            self.message.data.cast(),
+        ] {
+            if !buffer.is_null() {
+                let _ = bt.free_pool(buffer);
+            }
+        }
+    }
+}
+
+/// Local endpoint of an HTTP connection over IPv4 (`EFI_HTTPv4_ACCESS_POINT`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Ipv4AccessPoint {
+    /// Use the address obtained by DHCP, instead of the following ones
+    pub use_default_address: bool,
+    /// Local address
+    pub local_address: [u8; 4],
+    /// Subnet mask
+    pub local_subnet: [u8; 4],
+    /// Local port, or 0 to pick one
+    pub local_port: u16,
+}
+
+/// `EFI_HTTP_CONFIG_DATA`
+#[repr(C)]
+pub(crate) struct HttpConfigData {
+    pub(crate) http_version: HttpVersion,
+    pub(crate) time_out_millisec: u32,
+    pub(crate) local_address_is_ipv6: bool,
+    pub(crate) access_point: *const c_void,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(HttpStatusCode::UNSUPPORTED_STATUS.code(), None);
+        assert_eq!(HttpStatusCode::CONTINUE.code(), Some(100));
+        assert_eq!(HttpStatusCode::NOT_FOUND.code(), Some(404));
+        assert_eq!(HttpStatusCode::PERMANENT_REDIRECT.code(), Some(308));
+        assert_eq!(HttpStatusCode(42).code(), None);
+        assert!(HttpStatusCode::NO_CONTENT.is_success());
+        assert!(!HttpStatusCode::TEMPORARY_REDIRECT.is_success());
+    }
+}
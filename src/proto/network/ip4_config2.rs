@@ -0,0 +1,183 @@
+//! IPv4 Configuration II protocol.
+//!
+//! This protocol is installed on each network interface by the IPv4 driver,
+//! and gives the address of the interface, whether it was configured
+//! statically or by DHCP.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Char16, Result, Status};
+use core::ffi::c_void;
+use core::{mem, ptr};
+
+newtype_enum! {
+    /// How the interface gets its address
+    pub enum Ip4ConfigPolicy: u32 => {
+        /// The address is set by the user
+        STATIC = 0,
+        /// The address is given by a DHCP server
+        DHCP = 1,
+    }
+}
+
+/// Kinds of data of the protocol (`EFI_IP4_CONFIG2_DATA_TYPE`)
+const DATA_TYPE_INTERFACE_INFO: u32 = 0;
+const DATA_TYPE_POLICY: u32 = 2;
+
+/// An entry of the routing table of an interface
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct Ip4RouteTable {
+    /// Destination network
+    pub subnet_address: [u8; 4],
+    /// Mask of the destination network
+    pub subnet_mask: [u8; 4],
+    /// Gateway to the network, or 0.0.0.0 if it is directly reachable
+    pub gateway_address: [u8; 4],
+}
+
+/// Raw `EFI_IP4_CONFIG2_INTERFACE_INFO`
+#[repr(C)]
+struct RawInterfaceInfo {
+    name: [Char16; 32],
+    if_type: u8,
+    hw_address_size: u32,
+    hw_address: [u8; 32],
+    station_address: [u8; 4],
+    subnet_mask: [u8; 4],
+    route_table_size: u32,
+    route_table: *const Ip4RouteTable,
+}
+
+/// Maximum number of routes returned by `interface_info`
+pub const MAX_ROUTES: usize = 16;
+
+/// Storage for the interface information, followed by its routing table
+#[repr(C)]
+struct InterfaceInfoBuffer {
+    info: RawInterfaceInfo,
+    routes: [Ip4RouteTable; MAX_ROUTES],
+}
+
+/// Description of the IPv4 configuration of an interface
+#[derive(Debug, Clone)]
+pub struct Ip4InterfaceInfo {
+    name: [Char16; 32],
+    /// ARP hardware type of the interface, like 1 for Ethernet
+    pub if_type: u8,
+    /// Size of the MAC address
+    pub hw_address_size: u32,
+    /// MAC address, padded to 32 bytes
+    pub hw_address: [u8; 32],
+    /// Address of the interface, or 0.0.0.0 if it is not configured yet
+    pub station_address: [u8; 4],
+    /// Mask of the subnet of the interface
+    pub subnet_mask: [u8; 4],
+    routes: [Ip4RouteTable; MAX_ROUTES],
+    route_count: usize,
+}
+
+impl Ip4InterfaceInfo {
+    /// Name of the interface, like `eth0`
+    pub fn name(&self) -> impl Iterator<Item = char> + '_ {
+        self.name
+            .iter()
+            .take_while(|&&c| u16::from(c) != 0)
+            .map(|&c| char::from(c))
+    }
+
+    /// Whether an address is configured
+    pub fn is_configured(&self) -> bool {
+        self.station_address != [0; 4]
+    }
+
+    /// The routing table
+    pub fn routes(&self) -> &[Ip4RouteTable] {
+        &self.routes[..self.route_count]
+    }
+}
+
+/// The IPv4 Configuration II protocol.
+#[repr(C)]
+#[unsafe_guid("5b446ed1-e30b-4faa-871a-3654eca36080")]
+#[derive(Protocol)]
+pub struct Ip4Config2 {
+    set_data: unsafe extern "efiapi" fn(
+        this: *mut Ip4Config2,
+        data_type: u32,
+        data_size: usize,
+        data: *const c_void,
+    ) -> Status,
+    get_data: unsafe extern "efiapi" fn(
+        this: *mut Ip4Config2,
+        data_type: u32,
+        data_size: &mut usize,
+        data: *mut c_void,
+    ) -> Status,
+    register_data_notify: usize,
+    unregister_data_notify: usize,
+}
+
+impl Ip4Config2 {
+    /// The name, addresses and routes of the interface.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_READY`         The configuration is in progress
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  There are more than `MAX_ROUTES`
+    ///                                     routes
+    pub fn interface_info(&mut self) -> Result<Ip4InterfaceInfo> {
+        let mut buffer = mem::MaybeUninit::<InterfaceInfoBuffer>::uninit();
+        let mut size = mem::size_of::<InterfaceInfoBuffer>();
+        let status = unsafe {
+            (self.get_data)(
+                self,
+                DATA_TYPE_INTERFACE_INFO,
+                &mut size,
+                buffer.as_mut_ptr().cast(),
+            )
+        };
+        status.into_with_val(|| {
+            let raw = unsafe { &(*buffer.as_ptr()).info };
+            let mut routes = [Ip4RouteTable::default(); MAX_ROUTES];
+            let route_count = (raw.route_table_size as usize).min(MAX_ROUTES);
+            if route_count != 0 && !raw.route_table.is_null() {
+                unsafe {
+                    ptr::copy_nonoverlapping(raw.route_table, routes.as_mut_ptr(), route_count)
+                };
+            }
+            Ip4InterfaceInfo {
+                name: raw.name,
+                if_type: raw.if_type,
+                hw_address_size: raw.hw_address_size,
+                hw_address: raw.hw_address,
+                station_address: raw.station_address,
+                subnet_mask: raw.subnet_mask,
+                routes,
+                route_count,
+            }
+        })
+    }
+
+    /// How the interface gets its address
+    pub fn policy(&mut self) -> Result<Ip4ConfigPolicy> {
+        let mut policy = Ip4ConfigPolicy::STATIC;
+        let mut size = mem::size_of::<Ip4ConfigPolicy>();
+        let data = &mut policy as *mut Ip4ConfigPolicy as *mut c_void;
+        unsafe { (self.get_data)(self, DATA_TYPE_POLICY, &mut size, data) }.into_with_val(|| policy)
+    }
+
+    /// Change how the interface gets its address.
+    ///
+    /// Switching to `Ip4ConfigPolicy::DHCP` starts the configuration, which
+    /// completes asynchronously.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_READY`     The configuration is in progress
+    /// * `uefi::Status::DEVICE_ERROR`  The policy could not be set
+    pub fn set_policy(&mut self, policy: Ip4ConfigPolicy) -> Result {
+        let size = mem::size_of::<Ip4ConfigPolicy>();
+        let data = &policy as *const Ip4ConfigPolicy as *const c_void;
+        unsafe { (self.set_data)(self, DATA_TYPE_POLICY, size, data) }.into()
+    }
+}
@@ -0,0 +1,829 @@
+//! IPsec Config protocol.
+//!
+//! The IPsec driver of the network stack protects the traffic according to
+//! three databases, whose entries are identified by selectors:
+//!
+//! * the Security Policy Database (SPD), which tells which traffic is
+//!   discarded, let through, or protected, and how;
+//! * the Security Association Database (SAD), which holds the keys of the
+//!   security associations, when they are not negotiated with IKE;
+//! * the Peer Authorization Database (PAD), which tells how peers are
+//!   authenticated when security associations are negotiated.
+//!
+//! The structures of the entries point to data which they borrow, so that
+//! they can be built without allocating.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Event, Result, Status};
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::{mem, ptr};
+
+newtype_enum! {
+    /// Database of the IPsec configuration (`EFI_IPSEC_CONFIG_DATA_TYPE`)
+    pub enum IpsecConfigDataType: u32 => {
+        /// Security Policy Database
+        SPD = 0,
+        /// Security Association Database
+        SAD = 1,
+        /// Peer Authorization Database
+        PAD = 2,
+    }
+}
+
+/// An IPv4 or IPv6 address (`EFI_IP_ADDRESS`)
+///
+/// IPv4 addresses only use the first 4 bytes.
+#[repr(C, align(4))]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct IpAddress(pub [u8; 16]);
+
+impl IpAddress {
+    /// The IPv4 address `address`
+    pub fn v4(address: [u8; 4]) -> Self {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&address);
+        IpAddress(bytes)
+    }
+}
+
+/// An address, and the length of the prefix of the addresses which it
+/// stands for (`EFI_IP_ADDRESS_INFO`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct IpAddressInfo {
+    /// The address
+    pub address: IpAddress,
+    /// Length of the prefix in bits, 32 or 128 for a single host
+    pub prefix_length: u8,
+}
+
+/// Traffic to which a policy applies (`EFI_IPSEC_SPD_SELECTOR`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SpdSelector<'a> {
+    local_address_count: u32,
+    local_address: *const IpAddressInfo,
+    remote_address_count: u32,
+    remote_address: *const IpAddressInfo,
+    /// Protocol above IP, like 6 for TCP, or `0xffff` for any
+    pub next_layer_protocol: u16,
+    /// First local port, or 0 for any
+    pub local_port: u16,
+    /// Number of local ports after the first one
+    pub local_port_range: u16,
+    /// First remote port, or 0 for any
+    pub remote_port: u16,
+    /// Number of remote ports after the first one
+    pub remote_port_range: u16,
+    _addresses: PhantomData<&'a [IpAddressInfo]>,
+}
+
+impl<'a> SpdSelector<'a> {
+    /// A selector for the traffic between the `local` and the `remote`
+    /// addresses, of any protocol and any port
+    pub fn new(local: &'a [IpAddressInfo], remote: &'a [IpAddressInfo]) -> Self {
+        SpdSelector {
+            local_address_count: local.len() as u32,
+            local_address: local.as_ptr(),
+            remote_address_count: remote.len() as u32,
+            remote_address: remote.as_ptr(),
+            next_layer_protocol: 0xffff,
+            local_port: 0,
+            local_port_range: 0,
+            remote_port: 0,
+            remote_port_range: 0,
+            _addresses: PhantomData,
+        }
+    }
+
+    /// The local addresses
+    pub fn local_addresses(&self) -> &'a [IpAddressInfo] {
+        unsafe { addresses(self.local_address, self.local_address_count) }
+    }
+
+    /// The remote addresses
+    pub fn remote_addresses(&self) -> &'a [IpAddressInfo] {
+        unsafe { addresses(self.remote_address, self.remote_address_count) }
+    }
+}
+
+unsafe fn addresses<'a>(addresses: *const IpAddressInfo, count: u32) -> &'a [IpAddressInfo] {
+    if addresses.is_null() || count == 0 {
+        &[]
+    } else {
+        core::slice::from_raw_parts(addresses, count as usize)
+    }
+}
+
+newtype_enum! {
+    /// IPsec protocol (`EFI_IPSEC_PROTOCOL_TYPE`)
+    pub enum IpsecProtocolType: u32 => {
+        /// Authentication Header
+        AH = 0,
+        /// Encapsulating Security Payload
+        ESP = 1,
+    }
+}
+
+/// Identifier of a security association (`EFI_IPSEC_SA_ID`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SaId {
+    /// Security Parameter Index
+    pub spi: u32,
+    /// Protocol of the association
+    pub proto: IpsecProtocolType,
+    /// Destination of the traffic
+    pub dest_address: IpAddress,
+}
+
+/// The identity of a peer, or its address
+#[repr(C, align(4))]
+#[derive(Copy, Clone)]
+struct PeerId([u8; MAX_PEER_ID_LENGTH]);
+
+/// Largest length of the identity of a peer, or of the name of a policy
+pub const MAX_PEER_ID_LENGTH: usize = 128;
+
+/// Identifier of a peer (`EFI_IPSEC_PAD_ID`)
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PadId {
+    peer_id_valid: bool,
+    id: PeerId,
+}
+
+impl PadId {
+    /// The peers whose addresses are in `address`
+    pub fn from_address(address: IpAddressInfo) -> Self {
+        let mut id = PeerId([0; MAX_PEER_ID_LENGTH]);
+        unsafe { ptr::write((&mut id as *mut PeerId).cast(), address) };
+        PadId {
+            peer_id_valid: false,
+            id,
+        }
+    }
+
+    /// The peer whose IKE identity is `peer_id`, or `None` if it is longer
+    /// than `MAX_PEER_ID_LENGTH`
+    pub fn from_peer_id(peer_id: &[u8]) -> Option<Self> {
+        if peer_id.len() > MAX_PEER_ID_LENGTH {
+            return None;
+        }
+        let mut id = PeerId([0; MAX_PEER_ID_LENGTH]);
+        id.0[..peer_id.len()].copy_from_slice(peer_id);
+        Some(PadId {
+            peer_id_valid: true,
+            id,
+        })
+    }
+
+    /// The addresses of the peers, if they are identified by them
+    pub fn address(&self) -> Option<IpAddressInfo> {
+        if self.peer_id_valid {
+            None
+        } else {
+            Some(unsafe { ptr::read((&self.id as *const PeerId).cast()) })
+        }
+    }
+
+    /// The IKE identity of the peer, if it is identified by it
+    pub fn peer_id(&self) -> Option<&[u8]> {
+        if self.peer_id_valid {
+            let len = self.id.0.iter().position(|&b| b == 0);
+            Some(&self.id.0[..len.unwrap_or(MAX_PEER_ID_LENGTH)])
+        } else {
+            None
+        }
+    }
+}
+
+newtype_enum! {
+    /// Direction of the traffic (`EFI_IPSEC_TRAFFIC_DIR`)
+    pub enum TrafficDirection: u32 => {
+        /// Received traffic
+        INBOUND = 0,
+        /// Sent traffic
+        OUTBOUND = 1,
+    }
+}
+
+newtype_enum! {
+    /// What is done with the traffic (`EFI_IPSEC_ACTION`)
+    pub enum IpsecAction: u32 => {
+        /// The traffic is dropped
+        DISCARD = 0,
+        /// The traffic is let through unprotected
+        BYPASS = 1,
+        /// The traffic is protected
+        PROTECT = 2,
+    }
+}
+
+newtype_enum! {
+    /// How packets are protected (`EFI_IPSEC_MODE`)
+    pub enum IpsecMode: u32 => {
+        /// The payloads of the packets are protected
+        TRANSPORT = 0,
+        /// Whole packets are protected, and sent to the end of a tunnel
+        TUNNEL = 1,
+    }
+}
+
+newtype_enum! {
+    /// Handling of the Don't Fragment bit in tunnel mode
+    /// (`EFI_IPSEC_TUNNEL_DF_OPTION`)
+    pub enum TunnelDfOption: u32 => {
+        /// The bit is cleared
+        CLEAR = 0,
+        /// The bit is set
+        SET = 1,
+        /// The bit is copied from the inner packet
+        COPY = 2,
+    }
+}
+
+/// Lifetime of a security association (`EFI_IPSEC_SA_LIFETIME`)
+///
+/// A field of 0 means no limit.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct SaLifetime {
+    /// Number of bytes after which the association expires
+    pub byte_count: u64,
+    /// Seconds after which a new association is negotiated
+    pub soft_lifetime: u64,
+    /// Seconds after which the association expires
+    pub hard_lifetime: u64,
+}
+
+/// The ends of a tunnel (`EFI_IPSEC_TUNNEL_OPTION`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TunnelOption {
+    /// Local end of the tunnel
+    pub local_tunnel_address: IpAddress,
+    /// Remote end of the tunnel
+    pub remote_tunnel_address: IpAddress,
+    /// Handling of the Don't Fragment bit
+    pub df: TunnelDfOption,
+}
+
+/// How protected traffic is processed (`EFI_IPSEC_PROCESS_POLICY`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ProcessPolicy<'a> {
+    /// Whether 64-bit sequence numbers are used
+    pub ext_seq_num: bool,
+    /// Whether the sequence numbers may overflow
+    pub seq_overflow: bool,
+    /// Whether fragments are checked
+    pub frag_check: bool,
+    /// Lifetime of the security associations
+    pub sa_lifetime: SaLifetime,
+    /// Transport or tunnel mode
+    pub mode: IpsecMode,
+    tunnel_option: *const TunnelOption,
+    /// AH or ESP
+    pub proto: IpsecProtocolType,
+    /// IKEv2 identifier of the integrity algorithm
+    pub auth_algo_id: u8,
+    /// IKEv2 identifier of the encryption algorithm
+    pub enc_algo_id: u8,
+    _tunnel: PhantomData<&'a TunnelOption>,
+}
+
+impl<'a> ProcessPolicy<'a> {
+    /// A policy protecting the traffic with `proto` and the given
+    /// algorithms, in tunnel mode through `tunnel` if it is not `None`, and
+    /// in transport mode otherwise
+    pub fn new(
+        proto: IpsecProtocolType,
+        auth_algo_id: u8,
+        enc_algo_id: u8,
+        tunnel: Option<&'a TunnelOption>,
+    ) -> Self {
+        ProcessPolicy {
+            ext_seq_num: false,
+            seq_overflow: false,
+            frag_check: false,
+            sa_lifetime: SaLifetime::default(),
+            mode: if tunnel.is_some() {
+                IpsecMode::TUNNEL
+            } else {
+                IpsecMode::TRANSPORT
+            },
+            tunnel_option: tunnel.map_or(ptr::null(), |tunnel| tunnel as *const _),
+            proto,
+            auth_algo_id,
+            enc_algo_id,
+            _tunnel: PhantomData,
+        }
+    }
+
+    /// The tunnel through which the traffic goes, in tunnel mode
+    pub fn tunnel_option(&self) -> Option<&'a TunnelOption> {
+        unsafe { self.tunnel_option.as_ref() }
+    }
+}
+
+/// A policy (`EFI_IPSEC_SPD_DATA`)
+///
+/// The security associations of the policy are found by the IPsec driver,
+/// and are not given when the policy is set.
+#[repr(C)]
+pub struct SpdData<'a> {
+    name: [u8; MAX_PEER_ID_LENGTH],
+    /// Whether the selectors of the associations are taken from the packets
+    /// rather than from the policy
+    pub package_flag: u32,
+    /// Direction of the traffic
+    pub traffic_direction: TrafficDirection,
+    /// What is done with the traffic
+    pub action: IpsecAction,
+    processing_policy: *const ProcessPolicy<'a>,
+    sa_id_count: usize,
+    sa_id: [SaId; 1],
+    _policy: PhantomData<&'a ProcessPolicy<'a>>,
+}
+
+impl<'a> SpdData<'a> {
+    /// A policy named `name`, which does `action` with the traffic in
+    /// `direction`, and processes it with `policy` if it is protected
+    ///
+    /// Returns `None` if the name is longer than 127 bytes.
+    pub fn new(
+        name: &[u8],
+        direction: TrafficDirection,
+        action: IpsecAction,
+        policy: Option<&'a ProcessPolicy<'a>>,
+    ) -> Option<Self> {
+        if name.len() >= MAX_PEER_ID_LENGTH {
+            return None;
+        }
+        let mut data = SpdData {
+            name: [0; MAX_PEER_ID_LENGTH],
+            package_flag: 0,
+            traffic_direction: direction,
+            action,
+            processing_policy: policy.map_or(ptr::null(), |policy| policy as *const _),
+            sa_id_count: 0,
+            sa_id: [SaId {
+                spi: 0,
+                proto: IpsecProtocolType::ESP,
+                dest_address: IpAddress::default(),
+            }],
+            _policy: PhantomData,
+        };
+        data.name[..name.len()].copy_from_slice(name);
+        Some(data)
+    }
+
+    /// The name of the policy
+    pub fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|&b| b == 0);
+        &self.name[..len.unwrap_or(MAX_PEER_ID_LENGTH)]
+    }
+
+    /// How the traffic is processed, if it is protected
+    pub fn processing_policy(&self) -> Option<&'a ProcessPolicy<'a>> {
+        unsafe { self.processing_policy.as_ref() }
+    }
+}
+
+/// Algorithm of an AH security association (`EFI_IPSEC_AH_ALGO_INFO`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AhAlgoInfo<'a> {
+    auth_algo_id: u8,
+    auth_key_length: usize,
+    auth_key: *const u8,
+    _key: PhantomData<&'a [u8]>,
+}
+
+/// Algorithms of an ESP security association (`EFI_IPSEC_ESP_ALGO_INFO`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct EspAlgoInfo<'a> {
+    enc_algo_id: u8,
+    enc_key_length: usize,
+    enc_key: *const u8,
+    auth_algo_id: u8,
+    auth_key_length: usize,
+    auth_key: *const u8,
+    _keys: PhantomData<&'a [u8]>,
+}
+
+/// Algorithms and keys of a security association (`EFI_IPSEC_ALGO_INFO`)
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union AlgoInfo<'a> {
+    ah: AhAlgoInfo<'a>,
+    esp: EspAlgoInfo<'a>,
+}
+
+impl<'a> AlgoInfo<'a> {
+    /// Integrity protection with the IKEv2 algorithm `auth_algo_id`, keyed
+    /// by `auth_key`, for AH
+    pub fn ah(auth_algo_id: u8, auth_key: &'a [u8]) -> Self {
+        AlgoInfo {
+            ah: AhAlgoInfo {
+                auth_algo_id,
+                auth_key_length: auth_key.len(),
+                auth_key: auth_key.as_ptr(),
+                _key: PhantomData,
+            },
+        }
+    }
+
+    /// Encryption and integrity protection with the IKEv2 algorithms
+    /// `enc_algo_id` and `auth_algo_id`, for ESP
+    pub fn esp(enc_algo_id: u8, enc_key: &'a [u8], auth_algo_id: u8, auth_key: &'a [u8]) -> Self {
+        AlgoInfo {
+            esp: EspAlgoInfo {
+                enc_algo_id,
+                enc_key_length: enc_key.len(),
+                enc_key: enc_key.as_ptr(),
+                auth_algo_id,
+                auth_key_length: auth_key.len(),
+                auth_key: auth_key.as_ptr(),
+                _keys: PhantomData,
+            },
+        }
+    }
+}
+
+/// A security association (`EFI_IPSEC_SA_DATA2`)
+#[repr(C)]
+pub struct SaData<'a> {
+    /// Transport or tunnel mode
+    pub mode: IpsecMode,
+    /// Next sequence number
+    pub sn_count: u64,
+    /// Size of the anti-replay window, or 0 to disable the check
+    pub anti_replay_windows: u8,
+    /// Algorithms and keys
+    pub algo_info: AlgoInfo<'a>,
+    /// Lifetime of the association
+    pub sa_lifetime: SaLifetime,
+    /// Path MTU
+    pub path_mtu: u32,
+    spd_selector: *const SpdSelector<'a>,
+    /// Whether the association was set manually rather than negotiated
+    pub manual_set: bool,
+    /// Local end of the tunnel, in tunnel mode
+    pub tunnel_source_address: IpAddress,
+    /// Remote end of the tunnel, in tunnel mode
+    pub tunnel_destination_address: IpAddress,
+}
+
+impl<'a> SaData<'a> {
+    /// A manual association in `mode` with `algo_info`, for the traffic
+    /// selected by `selector`
+    pub fn new(mode: IpsecMode, algo_info: AlgoInfo<'a>, selector: &'a SpdSelector<'a>) -> Self {
+        SaData {
+            mode,
+            sn_count: 0,
+            anti_replay_windows: 0,
+            algo_info,
+            sa_lifetime: SaLifetime::default(),
+            path_mtu: 0,
+            spd_selector: selector,
+            manual_set: true,
+            tunnel_source_address: IpAddress::default(),
+            tunnel_destination_address: IpAddress::default(),
+        }
+    }
+
+    /// The traffic to which the association applies
+    pub fn spd_selector(&self) -> Option<&'a SpdSelector<'a>> {
+        unsafe { self.spd_selector.as_ref() }
+    }
+}
+
+newtype_enum! {
+    /// Protocol which negotiates the associations with a peer
+    /// (`EFI_IPSEC_AUTH_PROTOCOL_TYPE`)
+    pub enum AuthProtocol: u32 => {
+        /// IKEv1
+        IKEV1 = 0,
+        /// IKEv2
+        IKEV2 = 1,
+    }
+}
+
+newtype_enum! {
+    /// How a peer is authenticated (`EFI_IPSEC_AUTH_METHOD`)
+    pub enum AuthMethod: u32 => {
+        /// With a pre-shared secret
+        PRE_SHARED_SECRET = 0,
+        /// With certificates
+        CERTIFICATES = 1,
+    }
+}
+
+/// How a peer is authenticated (`EFI_IPSEC_PAD_DATA`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct PadData<'a> {
+    /// Protocol which negotiates the associations
+    pub auth_protocol: AuthProtocol,
+    /// Kind of credentials
+    pub auth_method: AuthMethod,
+    /// Whether the identity of the peer is checked against its IKE identity
+    pub ike_id_flag: bool,
+    auth_data_size: usize,
+    auth_data: *const u8,
+    revocation_data_size: usize,
+    revocation_data: *const u8,
+    _data: PhantomData<&'a [u8]>,
+}
+
+impl<'a> PadData<'a> {
+    /// Authenticate the peers with `auth_method` and `auth_data`, a
+    /// pre-shared secret or a certificate
+    pub fn new(auth_protocol: AuthProtocol, auth_method: AuthMethod, auth_data: &'a [u8]) -> Self {
+        PadData {
+            auth_protocol,
+            auth_method,
+            ike_id_flag: false,
+            auth_data_size: auth_data.len(),
+            auth_data: auth_data.as_ptr(),
+            revocation_data_size: 0,
+            revocation_data: ptr::null(),
+            _data: PhantomData,
+        }
+    }
+
+    /// Reject the certificates listed in `revocation_data`
+    pub fn with_revocation_data(self, revocation_data: &'a [u8]) -> Self {
+        PadData {
+            revocation_data_size: revocation_data.len(),
+            revocation_data: revocation_data.as_ptr(),
+            ..self
+        }
+    }
+}
+
+/// The IPsec Config protocol.
+///
+/// It has a single instance, shared by all the network interfaces.
+#[repr(C)]
+#[unsafe_guid("ce5e5929-c7a3-4602-ad9e-c9daf94ebfcf")]
+#[derive(Protocol)]
+pub struct IpsecConfig {
+    set_data: unsafe extern "efiapi" fn(
+        this: *mut IpsecConfig,
+        data_type: IpsecConfigDataType,
+        selector: *const c_void,
+        data: *const c_void,
+        insert_before: *const c_void,
+    ) -> Status,
+    get_data: unsafe extern "efiapi" fn(
+        this: *mut IpsecConfig,
+        data_type: IpsecConfigDataType,
+        selector: *const c_void,
+        data_size: *mut usize,
+        data: *mut c_void,
+    ) -> Status,
+    get_next_selector: unsafe extern "efiapi" fn(
+        this: *mut IpsecConfig,
+        data_type: IpsecConfigDataType,
+        selector_size: *mut usize,
+        selector: *mut c_void,
+    ) -> Status,
+    register_data_notify: unsafe extern "efiapi" fn(
+        this: *mut IpsecConfig,
+        data_type: IpsecConfigDataType,
+        event: Event,
+    ) -> Status,
+    unregister_data_notify: unsafe extern "efiapi" fn(
+        this: *mut IpsecConfig,
+        data_type: IpsecConfigDataType,
+        event: Event,
+    ) -> Status,
+}
+
+/// Pointer to an optional value, for the firmware
+fn as_ptr<T>(value: Option<&T>) -> *const c_void {
+    value.map_or(ptr::null(), |value| (value as *const T).cast())
+}
+
+impl IpsecConfig {
+    /// Set the policy of the traffic selected by `selector`, or remove it if
+    /// `data` is `None`.
+    ///
+    /// A new policy is inserted before the policy of `insert_before`, or
+    /// last if it is `None`, since the first matching policy applies.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The policy is invalid
+    /// * `uefi::Status::NOT_FOUND`          The policy to remove or to insert
+    ///                                      before does not exist
+    pub fn set_spd(
+        &mut self,
+        selector: &SpdSelector,
+        data: Option<&SpdData>,
+        insert_before: Option<&SpdSelector>,
+    ) -> Result {
+        self.set(
+            IpsecConfigDataType::SPD,
+            selector as *const SpdSelector as *const c_void,
+            as_ptr(data),
+            as_ptr(insert_before),
+        )
+    }
+
+    /// Set the security association `sa_id`, or remove it if `data` is
+    /// `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The association is invalid
+    /// * `uefi::Status::NOT_FOUND`          The association to remove does not
+    ///                                      exist
+    pub fn set_sad(&mut self, sa_id: &SaId, data: Option<&SaData>) -> Result {
+        self.set(
+            IpsecConfigDataType::SAD,
+            sa_id as *const SaId as *const c_void,
+            as_ptr(data),
+            ptr::null(),
+        )
+    }
+
+    /// Set how the peers of `pad_id` are authenticated, or remove the entry
+    /// if `data` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The entry is invalid
+    /// * `uefi::Status::NOT_FOUND`          The entry to remove does not exist
+    pub fn set_pad(&mut self, pad_id: &PadId, data: Option<&PadData>) -> Result {
+        self.set(
+            IpsecConfigDataType::PAD,
+            pad_id as *const PadId as *const c_void,
+            as_ptr(data),
+            ptr::null(),
+        )
+    }
+
+    /// Remove all the entries of the database `data_type`.
+    pub fn clear(&mut self, data_type: IpsecConfigDataType) -> Result {
+        self.set(data_type, ptr::null(), ptr::null(), ptr::null())
+    }
+
+    fn set(
+        &mut self,
+        data_type: IpsecConfigDataType,
+        selector: *const c_void,
+        data: *const c_void,
+        insert_before: *const c_void,
+    ) -> Result {
+        unsafe { (self.set_data)(self, data_type, selector, data, insert_before) }.into()
+    }
+
+    /// Read the entry of the database `data_type` identified by `selector`
+    /// into `buffer`, and return its size.
+    ///
+    /// `selector` is a `SpdSelector`, a `SaId` or a `PadId`, depending on the
+    /// database, and the entry is a `SpdData`, a `SaData` or a `PadData`,
+    /// followed by the data which it points to.
+    ///
+    /// # Safety
+    ///
+    /// `selector` must be a selector of the database `data_type`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  `buffer` is too small; the required
+    ///                                     size is returned
+    /// * `uefi::Status::NOT_FOUND`         The entry does not exist
+    pub unsafe fn get_data<S>(
+        &mut self,
+        data_type: IpsecConfigDataType,
+        selector: &S,
+        buffer: &mut [u64],
+    ) -> Result<usize, Option<usize>> {
+        let mut size = mem::size_of_val(buffer);
+        let status = (self.get_data)(
+            self,
+            data_type,
+            (selector as *const S).cast(),
+            &mut size,
+            buffer.as_mut_ptr().cast(),
+        );
+        status.into_with(
+            || size,
+            |status| {
+                if status == Status::BUFFER_TOO_SMALL {
+                    Some(size)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    /// Replace the selector of the database `data_type` in `selector` by
+    /// the next one, and return its size.
+    ///
+    /// The first selector is returned if `selector` only holds zeroes.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`         There is no selector after the one
+    ///                                     in `selector`
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  `selector` is too small; the
+    ///                                     required size is returned
+    pub fn next_selector(
+        &mut self,
+        data_type: IpsecConfigDataType,
+        selector: &mut [u64],
+    ) -> Result<usize, Option<usize>> {
+        let mut size = mem::size_of_val(selector);
+        let status = unsafe {
+            (self.get_next_selector)(self, data_type, &mut size, selector.as_mut_ptr().cast())
+        };
+        status.into_with(
+            || size,
+            |status| {
+                if status == Status::BUFFER_TOO_SMALL {
+                    Some(size)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    /// Signal `event` whenever the database `data_type` changes.
+    pub fn register_data_notify(&mut self, data_type: IpsecConfigDataType, event: Event) -> Result {
+        unsafe { (self.register_data_notify)(self, data_type, event) }.into()
+    }
+
+    /// Stop signaling `event` when the database `data_type` changes.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  `event` was not registered
+    pub fn unregister_data_notify(
+        &mut self,
+        data_type: IpsecConfigDataType,
+        event: Event,
+    ) -> Result {
+        unsafe { (self.unregister_data_notify)(self, data_type, event) }.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries() {
+        assert_eq!(mem::size_of::<IpAddressInfo>(), 20);
+        assert_eq!(mem::size_of::<SaId>(), 24);
+        assert_eq!(mem::size_of::<PadId>(), 132);
+        #[cfg(target_pointer_width = "64")]
+        {
+            assert_eq!(mem::size_of::<SpdSelector>(), 48);
+            assert_eq!(mem::size_of::<EspAlgoInfo>(), 48);
+        }
+
+        let local = [IpAddressInfo {
+            address: IpAddress::v4([10, 0, 0, 1]),
+            prefix_length: 32,
+        }];
+        let remote = [IpAddressInfo {
+            address: IpAddress::v4([10, 0, 0, 0]),
+            prefix_length: 24,
+        }];
+        let selector = SpdSelector::new(&local, &remote);
+        assert_eq!(selector.local_addresses(), local);
+        assert_eq!(selector.remote_addresses(), remote);
+
+        let pad = PadId::from_address(remote[0]);
+        assert_eq!(pad.address(), Some(remote[0]));
+        assert_eq!(pad.peer_id(), None);
+        let pad = PadId::from_peer_id(b"gateway.example.com").unwrap();
+        assert_eq!(pad.peer_id(), Some(&b"gateway.example.com"[..]));
+        assert!(pad.address().is_none());
+
+        let policy = ProcessPolicy::new(IpsecProtocolType::ESP, 12, 12, None);
+        assert_eq!(policy.mode, IpsecMode::TRANSPORT);
+        let data = SpdData::new(
+            b"protect",
+            TrafficDirection::OUTBOUND,
+            IpsecAction::PROTECT,
+            Some(&policy),
+        )
+        .unwrap();
+        assert_eq!(data.name(), b"protect");
+        assert_eq!(
+            data.processing_policy().unwrap().proto,
+            IpsecProtocolType::ESP
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! iSCSI Initiator Name protocol.
+//!
+//! The iSCSI driver of the firmware identifies itself to the targets with
+//! this name, an iSCSI qualified name like `iqn.2021-01.org.example:client`.
+//! The configuration it booted with is described by the iBFT, see
+//! [`crate::table::ibft`].
+
+use crate::proto::Protocol;
+use crate::result::Error;
+use crate::{unsafe_guid, Result, Status};
+use core::ffi::c_void;
+
+/// Largest size of an iSCSI name, terminating nul included
+pub const ISCSI_NAME_MAX_SIZE: usize = 224;
+
+/// The iSCSI Initiator Name protocol.
+#[repr(C)]
+#[unsafe_guid("59324945-ec44-4c0d-b1cd-9db139df070c")]
+#[derive(Protocol)]
+pub struct IscsiInitiatorName {
+    get: unsafe extern "efiapi" fn(
+        this: *const IscsiInitiatorName,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    set: unsafe extern "efiapi" fn(
+        this: *const IscsiInitiatorName,
+        buffer_size: *mut usize,
+        buffer: *const c_void,
+    ) -> Status,
+}
+
+impl IscsiInitiatorName {
+    /// Read the name of the initiator into `buffer`, and return it without
+    /// its terminating nul.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  The buffer is too small, the
+    ///                                     required size is returned
+    /// * `uefi::Status::NOT_FOUND`         No name is set
+    pub fn get<'buf>(&self, buffer: &'buf mut [u8]) -> Result<&'buf [u8], Option<usize>> {
+        let mut size = buffer.len();
+        let status = unsafe { (self.get)(self, &mut size, buffer.as_mut_ptr().cast()) };
+        if status == Status::BUFFER_TOO_SMALL {
+            return Err(Error::new(status, Some(size)));
+        }
+        status.into_with(
+            move || {
+                let name = &buffer[..size.min(buffer.len())];
+                let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                &name[..end]
+            },
+            |_| None,
+        )
+    }
+
+    /// Set the name of the initiator.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The name is not a valid iSCSI
+    ///                                      name, or is too long
+    /// * `uefi::Status::WRITE_PROTECTED`    The name cannot be changed
+    pub fn set(&self, name: &str) -> Result {
+        let mut buffer = [0; ISCSI_NAME_MAX_SIZE];
+        if name.len() >= buffer.len() || name.as_bytes().contains(&0) {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        buffer[..name.len()].copy_from_slice(name.as_bytes());
+        let mut size = name.len() + 1;
+        unsafe { (self.set)(self, &mut size, buffer.as_ptr().cast()) }.into()
+    }
+}
@@ -0,0 +1,21 @@
+//! Network protocols.
+//!
+//! These protocols are provided by the network stack of the firmware, from
+//! the drivers of the network interfaces up to the HTTP and REST clients.
+
+pub mod adapter_info;
+mod completion;
+pub mod http;
+pub mod ip4_config2;
+pub mod ipsec;
+pub mod iscsi;
+pub mod mtftp;
+pub mod nii;
+pub mod rest_ex;
+pub mod service_binding;
+pub mod snp;
+pub mod tcp4;
+pub mod udp4;
+pub mod undi;
+pub mod vlan;
+pub mod wifi;
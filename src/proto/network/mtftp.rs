@@ -0,0 +1,705 @@
+//! MTFTPv4 and MTFTPv6 protocols.
+//!
+//! These protocols are the TFTP clients of the network stack. Unlike the
+//! PXE Base Code protocol, which only reads files from the boot server, they
+//! can talk to any server, write files, and negotiate larger blocks and
+//! windows with the server, which makes transfers much faster:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::network::mtftp::{Mtftp4, TransferOptions};
+//! # fn f(mtftp: &mut Mtftp4, buffer: &mut [u8]) -> uefi::Result {
+//! let filename = uefi::CStr8::from_bytes_with_nul(b"kernel.efi\0").unwrap();
+//! let options = TransferOptions {
+//!     block_size: Some(1468),
+//!     window_size: Some(16),
+//!     ..TransferOptions::default()
+//! };
+//! let mut progress = |received| {
+//!     log::info!("{} bytes received", received);
+//!     true
+//! };
+//! let size = mtftp
+//!     .read_file(filename, &options, buffer, Some(&mut progress))
+//!     .map_err(|err| err.status())?
+//!     .log();
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! Instances are created with the [`Mtftp4ServiceBinding`] and
+//! [`Mtftp6ServiceBinding`] protocols of the network interfaces, and must be
+//! configured before the first transfer.
+
+use super::service_binding::ServiceBinding;
+#[cfg(feature = "exts")]
+use crate::alloc_api::vec::Vec;
+use crate::proto::Protocol;
+use crate::result::Error;
+use crate::{unsafe_guid, CStr8, Completion, Result, Status};
+use core::ffi::c_void;
+use core::ops::{Deref, DerefMut};
+use core::{ptr, slice};
+
+/// Options negotiated with the server when a transfer starts (RFC 2347)
+///
+/// The server may refuse options, or lower the sizes, in which case the
+/// transfer uses what the server accepted.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct TransferOptions {
+    /// Size of the data blocks, from 8 to 65464 bytes instead of 512
+    /// (RFC 2348)
+    ///
+    /// Blocks should fit in a frame of the network: 1468 bytes for Ethernet.
+    pub block_size: Option<u16>,
+    /// Number of blocks sent before waiting for an acknowledgement, instead
+    /// of 1 (RFC 7440)
+    pub window_size: Option<u16>,
+    /// Seconds to wait before retransmitting a block (RFC 2349)
+    pub timeout: Option<u8>,
+    /// Whether to ask for the size of the file being read, so that a buffer
+    /// which is too small is detected before the transfer (RFC 2349)
+    pub transfer_size: bool,
+}
+
+/// Largest number of options which are sent
+const MAX_OPTIONS: usize = 4;
+
+/// Room for a `u64` in decimal and the terminating nul
+const VALUE_LENGTH: usize = 21;
+
+/// An option as the firmware wants it (`EFI_MTFTP4_OPTION`)
+#[repr(C)]
+struct RawOption {
+    option_str: *const u8,
+    value_str: *const u8,
+}
+
+/// The options of a transfer, encoded as nul-terminated ASCII strings
+struct EncodedOptions {
+    names: [&'static [u8]; MAX_OPTIONS],
+    values: [[u8; VALUE_LENGTH]; MAX_OPTIONS],
+    count: usize,
+}
+
+impl EncodedOptions {
+    /// Encode `options`, the size of the file being `transfer_size`
+    fn new(options: &TransferOptions, transfer_size: u64) -> Self {
+        let mut encoded = EncodedOptions {
+            names: [&[]; MAX_OPTIONS],
+            values: [[0; VALUE_LENGTH]; MAX_OPTIONS],
+            count: 0,
+        };
+        if let Some(size) = options.block_size {
+            encoded.push(b"blksize\0", size.into());
+        }
+        if let Some(size) = options.window_size {
+            encoded.push(b"windowsize\0", size.into());
+        }
+        if let Some(timeout) = options.timeout {
+            encoded.push(b"timeout\0", timeout.into());
+        }
+        if options.transfer_size {
+            encoded.push(b"tsize\0", transfer_size);
+        }
+        encoded
+    }
+
+    fn push(&mut self, name: &'static [u8], value: u64) {
+        let buffer = &mut self.values[self.count];
+        let mut digits = 1;
+        let mut rest = value / 10;
+        while rest != 0 {
+            digits += 1;
+            rest /= 10;
+        }
+        let mut rest = value;
+        for digit in buffer[..digits].iter_mut().rev() {
+            *digit = b'0' + (rest % 10) as u8;
+            rest /= 10;
+        }
+        self.names[self.count] = name;
+        self.count += 1;
+    }
+
+    /// The options, pointing into `self`
+    fn list(&self) -> [RawOption; MAX_OPTIONS] {
+        let mut list = [(); MAX_OPTIONS].map(|_| RawOption {
+            option_str: ptr::null(),
+            value_str: ptr::null(),
+        });
+        for (index, option) in list.iter_mut().enumerate().take(self.count) {
+            option.option_str = self.names[index].as_ptr();
+            option.value_str = self.values[index].as_ptr();
+        }
+        list
+    }
+}
+
+/// A transfer request (`EFI_MTFTP4_TOKEN` or `EFI_MTFTP6_TOKEN`, which have
+/// the same layout)
+#[repr(C)]
+struct Token {
+    status: Status,
+    event: *mut c_void,
+    override_data: *const c_void,
+    filename: *const u8,
+    mode_str: *const u8,
+    option_count: u32,
+    option_list: *const RawOption,
+    buffer_size: u64,
+    buffer: *mut c_void,
+    context: *mut c_void,
+    check_packet: Option<
+        unsafe extern "efiapi" fn(
+            this: *mut c_void,
+            token: *mut Token,
+            packet_len: u16,
+            packet: *const u8,
+        ) -> Status,
+    >,
+    timeout_callback: usize,
+    packet_needed: usize,
+}
+
+impl Token {
+    /// A blocking transfer of `filename`, whose data is in `buffer`, in
+    /// binary mode
+    fn new(
+        filename: &CStr8,
+        options: &[RawOption],
+        count: usize,
+        buffer: *mut u8,
+        len: usize,
+    ) -> Self {
+        Token {
+            status: Status::SUCCESS,
+            event: ptr::null_mut(),
+            override_data: ptr::null(),
+            filename: filename.as_ptr().cast(),
+            mode_str: ptr::null(),
+            option_count: count as u32,
+            option_list: if count == 0 {
+                ptr::null()
+            } else {
+                options.as_ptr()
+            },
+            buffer_size: len as u64,
+            buffer: buffer.cast(),
+            context: ptr::null_mut(),
+            check_packet: None,
+            timeout_callback: 0,
+            packet_needed: 0,
+        }
+    }
+}
+
+/// Callback for the progress of a read
+type ProgressFn<'a> = &'a mut dyn FnMut(u64) -> bool;
+
+/// State of a read whose progress is reported
+struct Progress<'a> {
+    callback: ProgressFn<'a>,
+    /// The last block which was received, to ignore retransmissions
+    block: Option<u16>,
+    received: u64,
+}
+
+/// The block number and the size of the data of a DATA packet
+fn data_block(packet: &[u8]) -> Option<(u16, usize)> {
+    const DATA: u16 = 3;
+    if packet.len() < 4 || u16::from_be_bytes([packet[0], packet[1]]) != DATA {
+        return None;
+    }
+    Some((u16::from_be_bytes([packet[2], packet[3]]), packet.len() - 4))
+}
+
+unsafe extern "efiapi" fn check_packet(
+    _this: *mut c_void,
+    token: *mut Token,
+    packet_len: u16,
+    packet: *const u8,
+) -> Status {
+    if token.is_null() || (*token).context.is_null() || packet.is_null() {
+        return Status::SUCCESS;
+    }
+    let progress = &mut *((*token).context as *mut Progress);
+    let packet = slice::from_raw_parts(packet, packet_len.into());
+    if let Some((block, len)) = data_block(packet) {
+        if progress.block != Some(block) {
+            progress.block = Some(block);
+            progress.received += len as u64;
+            if !(progress.callback)(progress.received) {
+                return Status::ABORTED;
+            }
+        }
+    }
+    Status::SUCCESS
+}
+
+/// Read `filename` into `buffer` with `read`, which starts the transfer
+fn read_file(
+    read: impl FnOnce(*mut Token) -> Status,
+    filename: &CStr8,
+    options: &TransferOptions,
+    buffer: &mut [u8],
+    progress: Option<ProgressFn>,
+) -> Result<usize, Option<usize>> {
+    let encoded = EncodedOptions::new(options, 0);
+    let list = encoded.list();
+    let mut token = Token::new(
+        filename,
+        &list,
+        encoded.count,
+        buffer.as_mut_ptr(),
+        buffer.len(),
+    );
+    let mut progress = progress.map(|callback| Progress {
+        callback,
+        block: None,
+        received: 0,
+    });
+    if let Some(progress) = progress.as_mut() {
+        token.context = progress as *mut Progress as *mut c_void;
+        token.check_packet = Some(check_packet);
+    }
+    let status = read(&mut token);
+    let size = token.buffer_size as usize;
+    if status.is_error() {
+        let needed = if status == Status::BUFFER_TOO_SMALL && size > buffer.len() {
+            Some(size)
+        } else {
+            None
+        };
+        Err(Error::new(status, needed))
+    } else {
+        Ok(Completion::new(status, size))
+    }
+}
+
+/// Read a whole file with `read`, asking the server for its size first
+#[cfg(feature = "exts")]
+fn read_file_to_vec(
+    mut read: impl FnMut(&TransferOptions, &mut [u8]) -> Result<usize, Option<usize>>,
+    options: &TransferOptions,
+) -> Result<Vec<u8>> {
+    let options = TransferOptions {
+        transfer_size: true,
+        ..*options
+    };
+    let size = match read(&options, &mut []) {
+        Ok(_) => return Ok(Vec::new().into()),
+        Err(err) => match err.split() {
+            (Status::BUFFER_TOO_SMALL, Some(size)) => size,
+            (status, _) => return Err(status.into()),
+        },
+    };
+    let mut buffer = crate::alloc_api::vec![0; size];
+    let completion = read(&options, &mut buffer).map_err(|err| err.status())?;
+    let (status, len) = completion.split();
+    buffer.truncate(len);
+    Ok(Completion::new(status, buffer))
+}
+
+/// Write `data` to `filename` with `write`, which starts the transfer
+fn write_file(
+    write: impl FnOnce(*mut Token) -> Status,
+    filename: &CStr8,
+    options: &TransferOptions,
+    data: &[u8],
+) -> Result {
+    let encoded = EncodedOptions::new(options, data.len() as u64);
+    let list = encoded.list();
+    // The firmware only reads from the buffer
+    let mut token = Token::new(
+        filename,
+        &list,
+        encoded.count,
+        data.as_ptr() as *mut u8,
+        data.len(),
+    );
+    write(&mut token).into()
+}
+
+/// Configuration of an MTFTPv4 instance (`EFI_MTFTP4_CONFIG_DATA`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Mtftp4ConfigData {
+    /// Whether to use the address of the interface, given by DHCP or by the
+    /// user, instead of `station_ip` and `subnet_mask`
+    pub use_default_setting: bool,
+    /// Address of the client
+    pub station_ip: [u8; 4],
+    /// Subnet mask of the client
+    pub subnet_mask: [u8; 4],
+    /// UDP port of the client, or 0 for any
+    pub local_port: u16,
+    /// Address of the gateway, or 0.0.0.0 if the server is on the subnet
+    pub gateway_ip: [u8; 4],
+    /// Address of the server
+    pub server_ip: [u8; 4],
+    /// UDP port to which the requests are sent, usually 69
+    pub initial_server_port: u16,
+    /// Number of times a packet is sent before the transfer fails
+    pub try_count: u16,
+    /// Seconds to wait for an answer before sending a packet again
+    pub timeout_value: u16,
+}
+
+/// The MTFTPv4 protocol.
+#[repr(C)]
+#[unsafe_guid("78247c57-63db-4708-99c2-a8b4a9a61f6b")]
+#[derive(Protocol)]
+pub struct Mtftp4 {
+    get_mode_data: usize,
+    configure:
+        unsafe extern "efiapi" fn(this: *mut Mtftp4, config: *const Mtftp4ConfigData) -> Status,
+    get_info: usize,
+    parse_options: usize,
+    read_file: unsafe extern "efiapi" fn(this: *mut Mtftp4, token: *mut Token) -> Status,
+    write_file: unsafe extern "efiapi" fn(this: *mut Mtftp4, token: *mut Token) -> Status,
+    read_directory: usize,
+    poll: unsafe extern "efiapi" fn(this: *mut Mtftp4) -> Status,
+}
+
+impl Mtftp4 {
+    /// Configure the instance with `config`, or reset it to the
+    /// unconfigured state and abort the transfer in progress if `config` is
+    /// `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The configuration is invalid
+    /// * `uefi::Status::ACCESS_DENIED`      The instance is already configured,
+    ///                                      and must be reset first
+    /// * `uefi::Status::NO_MAPPING`         The default address is not
+    ///                                      available yet
+    pub fn configure(&mut self, config: Option<&Mtftp4ConfigData>) -> Result {
+        let config = config.map_or(ptr::null(), |config| config as *const _);
+        unsafe { (self.configure)(self, config) }.into()
+    }
+
+    /// Read `filename` from the server into `buffer`, and return the size of
+    /// the file.
+    ///
+    /// If `progress` is not `None`, it is called with the number of bytes
+    /// received so far every time a block arrives, and the transfer is
+    /// aborted if it returns `false`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  The file does not fit in `buffer`;
+    ///                                     its size is returned if the server
+    ///                                     gave it (see `transfer_size`)
+    /// * `uefi::Status::NOT_STARTED`       The instance is not configured
+    /// * `uefi::Status::TFTP_ERROR`        The server refused the request
+    /// * `uefi::Status::TIMEOUT`           The server stopped answering
+    /// * `uefi::Status::ABORTED`           `progress` aborted the transfer
+    pub fn read_file(
+        &mut self,
+        filename: &CStr8,
+        options: &TransferOptions,
+        buffer: &mut [u8],
+        progress: Option<&mut dyn FnMut(u64) -> bool>,
+    ) -> Result<usize, Option<usize>> {
+        read_file(
+            |token| unsafe { (self.read_file)(self, token) },
+            filename,
+            options,
+            buffer,
+            progress,
+        )
+    }
+
+    /// Read the whole of `filename` from the server.
+    ///
+    /// The size of the file is asked first, which the server must support.
+    ///
+    /// # Errors
+    ///
+    /// See `read_file`.
+    #[cfg(feature = "exts")]
+    pub fn read_file_to_vec(
+        &mut self,
+        filename: &CStr8,
+        options: &TransferOptions,
+    ) -> Result<Vec<u8>> {
+        read_file_to_vec(
+            |options, buffer| self.read_file(filename, options, buffer, None),
+            options,
+        )
+    }
+
+    /// Write `data` to `filename` on the server.
+    ///
+    /// If `options.transfer_size` is set, the size of `data` is sent to the
+    /// server, which may refuse files which are too large before it starts.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`       The instance is not configured
+    /// * `uefi::Status::TFTP_ERROR`        The server refused the request
+    /// * `uefi::Status::TIMEOUT`           The server stopped answering
+    pub fn write_file(
+        &mut self,
+        filename: &CStr8,
+        options: &TransferOptions,
+        data: &[u8],
+    ) -> Result {
+        write_file(
+            |token| unsafe { (self.write_file)(self, token) },
+            filename,
+            options,
+            data,
+        )
+    }
+
+    /// Process the packets which were received, to speed transfers up.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`       The instance is not configured
+    /// * `uefi::Status::TIMEOUT`           No packet was received
+    pub fn poll(&mut self) -> Result {
+        unsafe { (self.poll)(self) }.into()
+    }
+}
+
+/// The service binding protocol which creates instances of [`Mtftp4`].
+#[repr(transparent)]
+#[unsafe_guid("2fe800be-8f01-4aa6-946b-d71388e1833f")]
+#[derive(Protocol)]
+pub struct Mtftp4ServiceBinding(ServiceBinding);
+
+impl Deref for Mtftp4ServiceBinding {
+    type Target = ServiceBinding;
+
+    fn deref(&self) -> &ServiceBinding {
+        &self.0
+    }
+}
+
+impl DerefMut for Mtftp4ServiceBinding {
+    fn deref_mut(&mut self) -> &mut ServiceBinding {
+        &mut self.0
+    }
+}
+
+/// Configuration of an MTFTPv6 instance (`EFI_MTFTP6_CONFIG_DATA`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Mtftp6ConfigData {
+    /// Address of the client, or `::` for the address of the interface
+    pub station_ip: [u8; 16],
+    /// UDP port of the client, or 0 for any
+    pub local_port: u16,
+    /// Address of the server
+    pub server_ip: [u8; 16],
+    /// UDP port to which the requests are sent, usually 69
+    pub initial_server_port: u16,
+    /// Number of times a packet is sent before the transfer fails
+    pub try_count: u16,
+    /// Seconds to wait for an answer before sending a packet again
+    pub timeout_value: u16,
+}
+
+/// The MTFTPv6 protocol.
+#[repr(C)]
+#[unsafe_guid("bf0a78ba-ec29-49cf-a1c9-7ae54eab6a51")]
+#[derive(Protocol)]
+pub struct Mtftp6 {
+    get_mode_data: usize,
+    configure:
+        unsafe extern "efiapi" fn(this: *mut Mtftp6, config: *const Mtftp6ConfigData) -> Status,
+    get_info: usize,
+    parse_options: usize,
+    read_file: unsafe extern "efiapi" fn(this: *mut Mtftp6, token: *mut Token) -> Status,
+    write_file: unsafe extern "efiapi" fn(this: *mut Mtftp6, token: *mut Token) -> Status,
+    read_directory: usize,
+    poll: unsafe extern "efiapi" fn(this: *mut Mtftp6) -> Status,
+}
+
+impl Mtftp6 {
+    /// Configure the instance with `config`, or reset it to the
+    /// unconfigured state and abort the transfer in progress if `config` is
+    /// `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The configuration is invalid
+    /// * `uefi::Status::ACCESS_DENIED`      The instance is already configured,
+    ///                                      and must be reset first
+    /// * `uefi::Status::NO_MAPPING`         The address of the interface is
+    ///                                      not available yet
+    pub fn configure(&mut self, config: Option<&Mtftp6ConfigData>) -> Result {
+        let config = config.map_or(ptr::null(), |config| config as *const _);
+        unsafe { (self.configure)(self, config) }.into()
+    }
+
+    /// Read `filename` from the server into `buffer`, and return the size of
+    /// the file.
+    ///
+    /// See `Mtftp4::read_file`.
+    pub fn read_file(
+        &mut self,
+        filename: &CStr8,
+        options: &TransferOptions,
+        buffer: &mut [u8],
+        progress: Option<&mut dyn FnMut(u64) -> bool>,
+    ) -> Result<usize, Option<usize>> {
+        read_file(
+            |token| unsafe { (self.read_file)(self, token) },
+            filename,
+            options,
+            buffer,
+            progress,
+        )
+    }
+
+    /// Read the whole of `filename` from the server.
+    ///
+    /// See `Mtftp4::read_file_to_vec`.
+    #[cfg(feature = "exts")]
+    pub fn read_file_to_vec(
+        &mut self,
+        filename: &CStr8,
+        options: &TransferOptions,
+    ) -> Result<Vec<u8>> {
+        read_file_to_vec(
+            |options, buffer| self.read_file(filename, options, buffer, None),
+            options,
+        )
+    }
+
+    /// Write `data` to `filename` on the server.
+    ///
+    /// See `Mtftp4::write_file`.
+    pub fn write_file(
+        &mut self,
+        filename: &CStr8,
+        options: &TransferOptions,
+        data: &[u8],
+    ) -> Result {
+        write_file(
+            |token| unsafe { (self.write_file)(self, token) },
+            filename,
+            options,
+            data,
+        )
+    }
+
+    /// Process the packets which were received, to speed transfers up.
+    ///
+    /// See `Mtftp4::poll`.
+    pub fn poll(&mut self) -> Result {
+        unsafe { (self.poll)(self) }.into()
+    }
+}
+
+/// The service binding protocol which creates instances of [`Mtftp6`].
+#[repr(transparent)]
+#[unsafe_guid("d9760ff3-3cca-4267-80f9-7527fafa4223")]
+#[derive(Protocol)]
+pub struct Mtftp6ServiceBinding(ServiceBinding);
+
+impl Deref for Mtftp6ServiceBinding {
+    type Target = ServiceBinding;
+
+    fn deref(&self) -> &ServiceBinding {
+        &self.0
+    }
+}
+
+impl DerefMut for Mtftp6ServiceBinding {
+    fn deref_mut(&mut self) -> &mut ServiceBinding {
+        &mut self.0
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        let options = TransferOptions {
+            block_size: Some(1468),
+            window_size: Some(8),
+            timeout: None,
+            transfer_size: true,
+        };
+        let encoded = EncodedOptions::new(&options, 18_446_744_073_709_551_615);
+        assert_eq!(encoded.count, 3);
+        let list = encoded.list();
+        let strings: [(&[u8], &[u8]); 3] = [
+            (b"blksize", b"1468"),
+            (b"windowsize", b"8"),
+            (b"tsize", b"18446744073709551615"),
+        ];
+        for (option, (name, value)) in list.iter().zip(strings.iter()) {
+            let option_str = unsafe { CStr8::from_ptr(option.option_str.cast()) };
+            let value_str = unsafe { CStr8::from_ptr(option.value_str.cast()) };
+            assert_eq!(option_str.to_bytes(), *name);
+            assert_eq!(value_str.to_bytes(), *value);
+        }
+        assert!(list[3].option_str.is_null());
+
+        let encoded = EncodedOptions::new(&TransferOptions::default(), 0);
+        assert_eq!(encoded.count, 0);
+        let encoded = EncodedOptions::new(
+            &TransferOptions {
+                timeout: Some(0),
+                ..TransferOptions::default()
+            },
+            0,
+        );
+        let value = unsafe { CStr8::from_ptr(encoded.list()[0].value_str.cast()) };
+        assert_eq!(value.to_bytes(), b"0");
+    }
+
+    #[test]
+    fn progress() {
+        assert_eq!(data_block(&[0, 3, 0, 1, 0xaa, 0xbb]), Some((1, 2)));
+        assert_eq!(data_block(&[0, 6, 0, 1]), None);
+        assert_eq!(data_block(&[0, 3]), None);
+
+        let mut reports = crate::alloc_api::vec::Vec::new();
+        let mut callback = |received| {
+            reports.push(received);
+            received < 6
+        };
+        let mut progress = Progress {
+            callback: &mut callback,
+            block: None,
+            received: 0,
+        };
+        let filename = CStr8::from_bytes_with_nul(b"file\0").unwrap();
+        let mut token = Token::new(filename, &[], 0, ptr::null_mut(), 0);
+        token.context = &mut progress as *mut Progress as *mut c_void;
+        let packets: [&[u8]; 4] = [
+            &[0, 3, 0, 1, 1, 2, 3],
+            // Retransmitted
+            &[0, 3, 0, 1, 1, 2, 3],
+            &[0, 3, 0, 2, 4, 5, 6],
+            &[0, 3, 0, 3, 7],
+        ];
+        let statuses: [Status; 4] = packets.map(|packet| unsafe {
+            check_packet(
+                ptr::null_mut(),
+                &mut token,
+                packet.len() as u16,
+                packet.as_ptr(),
+            )
+        });
+        assert_eq!(
+            statuses,
+            [
+                Status::SUCCESS,
+                Status::SUCCESS,
+                Status::ABORTED,
+                Status::ABORTED
+            ]
+        );
+        assert_eq!(reports, [3, 6, 7]);
+    }
+}
@@ -0,0 +1,86 @@
+//! Network Interface Identifier protocol.
+//!
+//! Drivers of network interfaces which implement the UNDI interface install
+//! this protocol, through which the network stack finds their entry point.
+
+use super::undi::Undi;
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Result};
+
+newtype_enum! {
+    /// Kind of interface of a network driver
+    pub enum InterfaceType: u8 => {
+        /// Universal Network Driver Interface
+        UNDI = 1,
+    }
+}
+
+/// The Network Interface Identifier protocol, revision 3.1.
+#[repr(C)]
+#[unsafe_guid("1aced566-76ed-4218-bc81-767f1f977a89")]
+#[derive(Protocol)]
+pub struct NetworkInterfaceIdentifier {
+    revision: u64,
+    id: u64,
+    image_addr: u64,
+    image_size: u32,
+    string_id: [u8; 4],
+    interface_type: InterfaceType,
+    major_ver: u8,
+    minor_ver: u8,
+    ipv6_supported: bool,
+    if_num: u16,
+}
+
+impl NetworkInterfaceIdentifier {
+    /// Revision of the protocol
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Address of the structure describing the interface, like the `!PXE`
+    /// structure of UNDI drivers
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Address and size of the image of the driver, for interfaces loaded
+    /// from an option ROM
+    pub fn image(&self) -> (u64, u32) {
+        (self.image_addr, self.image_size)
+    }
+
+    /// Signature of the interface, like `b"UNDI"`
+    pub fn string_id(&self) -> [u8; 4] {
+        self.string_id
+    }
+
+    /// Kind of interface
+    pub fn interface_type(&self) -> InterfaceType {
+        self.interface_type
+    }
+
+    /// Version of the interface, as `(major, minor)`
+    pub fn version(&self) -> (u8, u8) {
+        (self.major_ver, self.minor_ver)
+    }
+
+    /// Whether the interface supports IPv6
+    pub fn ipv6_supported(&self) -> bool {
+        self.ipv6_supported
+    }
+
+    /// Number of the interface, among the interfaces of the driver
+    pub fn if_num(&self) -> u16 {
+        self.if_num
+    }
+
+    /// The UNDI command interface of the driver.
+    ///
+    /// # Safety
+    ///
+    /// See `Undi::from_nii`.
+    pub unsafe fn undi(&self) -> Result<Undi> {
+        Undi::from_nii(self)
+    }
+}
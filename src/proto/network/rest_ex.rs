@@ -0,0 +1,239 @@
+//! REST EX protocol.
+//!
+//! This protocol sends HTTP requests to a REST service known to the
+//! firmware, like the Redfish service of the BMC reached through the host
+//! interface, and returns the responses:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::network::http::{HttpMethod, HttpRequest};
+//! # use uefi::proto::network::rest_ex::RestEx;
+//! # fn f(bt: &BootServices, rest: &mut RestEx, url: &uefi::CStr16) -> uefi::Result {
+//! let request = HttpRequest {
+//!     method: HttpMethod::GET,
+//!     url,
+//!     headers: &[],
+//!     body: &[],
+//! };
+//! let response = rest.send_receive(bt, &request)?.log();
+//! if response.status_code().map_or(false, |status| status.is_success()) {
+//!     let json = response.body();
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! Instances are created with the [`RestExServiceBinding`] protocol.
+
+use super::http::{
+    HttpConfigData, HttpMessage, HttpRequest, HttpResponse, HttpVersion, Ipv4AccessPoint,
+};
+use super::service_binding::ServiceBinding;
+use crate::proto::Protocol;
+use crate::table::boot::BootServices;
+use crate::{unsafe_guid, Guid, Result, Status};
+use core::ffi::c_void;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+newtype_enum! {
+    /// Kind of REST service (`EFI_REST_EX_SERVICE_TYPE`)
+    pub enum RestServiceType: u32 => {
+        /// Unspecified REST service
+        UNSPECIFIC      = 1,
+        /// DMTF Redfish service
+        REDFISH         = 2,
+        /// OData service
+        ODATA           = 3,
+        /// Service defined by `RestServiceInfo::vendor_service_name`
+        VENDOR_SPECIFIC = 4,
+    }
+}
+
+newtype_enum! {
+    /// How the REST service is reached (`EFI_REST_EX_SERVICE_ACCESS_MODE`)
+    pub enum RestServiceAccessMode: u32 => {
+        /// Through a channel private to the platform, like the host
+        /// interface of a BMC
+        IN_BAND     = 1,
+        /// Through the network
+        OUT_OF_BAND = 2,
+    }
+}
+
+newtype_enum! {
+    /// Kind of configuration taken by `RestEx::configure`
+    /// (`EFI_REST_EX_CONFIG_TYPE`)
+    pub enum RestExConfigType: u8 => {
+        /// `EFI_REST_EX_HTTP_CONFIG_DATA`
+        HTTP       = 0,
+        /// Configuration defined by the driver
+        UNSPECIFIC = 1,
+    }
+}
+
+/// Description of the REST service behind an instance of the protocol
+#[derive(Debug, Copy, Clone)]
+pub struct RestServiceInfo {
+    /// The kind of service
+    pub service_type: RestServiceType,
+    /// How the service is reached
+    pub access_mode: RestServiceAccessMode,
+    /// For vendor-specific services, the GUID of the service
+    pub vendor_service_name: Guid,
+    /// The kind of configuration taken by `RestEx::configure`
+    pub config_type: RestExConfigType,
+}
+
+/// `EFI_REST_EX_SERVICE_INFO_V_1_0`
+#[repr(C)]
+struct ServiceInfoV1 {
+    length: u32,
+    major: u8,
+    minor: u8,
+    service_type: RestServiceType,
+    access_mode: RestServiceAccessMode,
+    vendor_service_name: Guid,
+    vendor_specific_data_length: u32,
+    vendor_specific_data: *mut u8,
+    config_type: RestExConfigType,
+    reserved: [u8; 3],
+}
+
+/// `EFI_REST_EX_HTTP_CONFIG_DATA`
+#[repr(C)]
+struct HttpConfig {
+    http_config_data: HttpConfigData,
+    send_receive_timeout: u32,
+}
+
+/// The REST EX protocol.
+#[repr(C)]
+#[unsafe_guid("55648b91-e7d0-40c3-a956-eed127d1b7da")]
+#[derive(Protocol)]
+pub struct RestEx {
+    send_receive: unsafe extern "efiapi" fn(
+        this: *mut RestEx,
+        request_message: *mut HttpMessage,
+        response_message: *mut HttpMessage,
+    ) -> Status,
+    get_service: unsafe extern "efiapi" fn(
+        this: *mut RestEx,
+        service_info: *mut *mut ServiceInfoV1,
+    ) -> Status,
+    get_mode_data: usize,
+    configure: unsafe extern "efiapi" fn(this: *mut RestEx, config_data: *const c_void) -> Status,
+    async_send_receive: usize,
+    event_service: usize,
+}
+
+impl RestEx {
+    /// Send `request` to the service, and wait for the response.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_READY`     The instance is not configured
+    /// * `uefi::Status::TIMEOUT`       The service did not answer in time
+    /// * `uefi::Status::DEVICE_ERROR`  The request could not be sent
+    pub fn send_receive<'boot>(
+        &mut self,
+        bt: &'boot BootServices,
+        request: &HttpRequest,
+    ) -> Result<HttpResponse<'boot>> {
+        let mut response = HttpMessage::empty();
+        let status = HttpMessage::with_request(request, |request| unsafe {
+            (self.send_receive)(self, request, &mut response)
+        });
+        // Parts of the response may have been allocated even on failure
+        let response = HttpResponse::new(bt, response);
+        status.into_with_val(|| response)
+    }
+
+    /// Describe the service behind this instance.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`  The service is not known in advance
+    pub fn service_info(&mut self, bt: &BootServices) -> Result<RestServiceInfo> {
+        let mut info = ptr::null_mut();
+        let status = unsafe { (self.get_service)(self, &mut info) };
+        if status.is_error() {
+            return Err(status.into());
+        }
+        if info.is_null() {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        let raw = unsafe { &*info };
+        let result = if raw.major >= 1 && raw.length as usize >= mem::size_of::<ServiceInfoV1>() {
+            Ok(RestServiceInfo {
+                service_type: raw.service_type,
+                access_mode: raw.access_mode,
+                vendor_service_name: raw.vendor_service_name,
+                config_type: raw.config_type,
+            })
+        } else {
+            Err(Status::INCOMPATIBLE_VERSION)
+        };
+        // The description is allocated for the caller
+        bt.free_pool(info.cast())?.log();
+        result
+            .map(|info| status.into_with_val(|| info))
+            .unwrap_or_else(|status| Err(status.into()))
+    }
+
+    /// Configure the HTTP connection to a service over IPv4, for instances
+    /// whose `config_type` is `HTTP`.
+    ///
+    /// `timeout` and `send_receive_timeout` are in milliseconds.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`  The instance takes another kind of
+    ///                                configuration
+    /// * `uefi::Status::NO_MAPPING`   The default address is not available
+    ///                                yet
+    pub fn configure_http(
+        &mut self,
+        version: HttpVersion,
+        timeout: u32,
+        access_point: &Ipv4AccessPoint,
+        send_receive_timeout: u32,
+    ) -> Result {
+        let config = HttpConfig {
+            http_config_data: HttpConfigData {
+                http_version: version,
+                time_out_millisec: timeout,
+                local_address_is_ipv6: false,
+                access_point: (access_point as *const Ipv4AccessPoint).cast(),
+            },
+            send_receive_timeout,
+        };
+        unsafe { (self.configure)(self, (&config as *const HttpConfig).cast()) }.into()
+    }
+
+    /// Reset the instance to its unconfigured state, closing its connection
+    pub fn reset(&mut self) -> Result {
+        unsafe { (self.configure)(self, ptr::null()) }.into()
+    }
+}
+
+/// The service binding protocol which creates instances of [`RestEx`].
+#[repr(transparent)]
+#[unsafe_guid("456bbe01-99d0-45ea-bb5f-16d84bedc559")]
+#[derive(Protocol)]
+pub struct RestExServiceBinding(ServiceBinding);
+
+impl Deref for RestExServiceBinding {
+    type Target = ServiceBinding;
+
+    fn deref(&self) -> &ServiceBinding {
+        &self.0
+    }
+}
+
+impl DerefMut for RestExServiceBinding {
+    fn deref_mut(&mut self) -> &mut ServiceBinding {
+        &mut self.0
+    }
+}
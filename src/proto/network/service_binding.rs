@@ -0,0 +1,60 @@
+//! Service Binding protocols.
+//!
+//! The network drivers do not install the protocols which send and receive
+//! data directly: they install a service binding protocol on the network
+//! interface, which creates a child handle with an instance of the protocol
+//! for each user. Each user then configures its own instance, like a socket.
+//!
+//! All the service binding protocols share the same function table,
+//! [`ServiceBinding`], and only differ by their GUIDs. The protocols which are
+//! created this way come with their own service binding type, like
+//! `Mtftp4ServiceBinding`, which dereferences to [`ServiceBinding`].
+
+use crate::{Handle, Result, Status};
+use core::ffi::c_void;
+use core::ptr;
+
+/// The function table shared by all the service binding protocols
+#[repr(C)]
+pub struct ServiceBinding {
+    create_child: unsafe extern "efiapi" fn(
+        this: *mut ServiceBinding,
+        child_handle: *mut *mut c_void,
+    ) -> Status,
+    destroy_child:
+        unsafe extern "efiapi" fn(this: *mut ServiceBinding, child_handle: Handle) -> Status,
+}
+
+impl ServiceBinding {
+    /// Create a new child handle, with an instance of the protocol provided
+    /// by the service, and return it.
+    ///
+    /// The child must be destroyed with `destroy_child` once it is no longer
+    /// used.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::OUT_OF_RESOURCES`  There is no room for another child
+    pub fn create_child(&mut self) -> Result<Handle> {
+        let mut child = ptr::null_mut();
+        let status = unsafe { (self.create_child)(self, &mut child) };
+        match Handle::from_ptr(child) {
+            Some(child) => status.into_with_val(|| child),
+            None if status.is_error() => Err(status.into()),
+            None => Err(Status::PROTOCOL_ERROR.into()),
+        }
+    }
+
+    /// Destroy a child created with `create_child`, aborting all the
+    /// operations in progress on it.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`        `child` was not created by this
+    ///                                      service
+    /// * `uefi::Status::ACCESS_DENIED`      The protocol of the child is still
+    ///                                      opened
+    pub fn destroy_child(&mut self, child: Handle) -> Result {
+        unsafe { (self.destroy_child)(self, child) }.into()
+    }
+}
@@ -0,0 +1,124 @@
+//! Simple Network protocol.
+//!
+//! This protocol is installed by the SNP driver on each network interface,
+//! on top of its UNDI driver. Only the description of the interface is
+//! exposed here: frames are sent and received through the protocols of the
+//! network stack, or directly through `undi`.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Event, Result, Status};
+use core::ffi::c_void;
+use core::ptr;
+
+newtype_enum! {
+    /// State of the interface
+    pub enum NetworkState: u32 => {
+        /// Not started
+        STOPPED = 0,
+        /// Started, but not initialized
+        STARTED = 1,
+        /// Ready to receive and transmit frames
+        INITIALIZED = 2,
+    }
+}
+
+/// A MAC address, padded to 32 bytes
+pub type MacAddress = [u8; 32];
+
+/// Description of an interface (`EFI_SIMPLE_NETWORK_MODE`)
+#[derive(Debug)]
+#[repr(C)]
+pub struct NetworkMode {
+    /// State of the interface
+    pub state: NetworkState,
+    /// Size of the MAC addresses
+    pub hw_address_size: u32,
+    /// Size of the media header of the frames
+    pub media_header_size: u32,
+    /// Largest frame, without the media header
+    pub max_packet_size: u32,
+    /// Size of the non-volatile storage
+    pub nv_ram_size: u32,
+    /// Width of the accesses to the non-volatile storage
+    pub nv_ram_access_size: u32,
+    /// Supported filters of received frames
+    pub receive_filter_mask: u32,
+    /// Enabled filters of received frames
+    pub receive_filter_setting: u32,
+    /// Number of multicast addresses which can be filtered
+    pub max_mcast_filter_count: u32,
+    /// Number of multicast addresses which are filtered
+    pub mcast_filter_count: u32,
+    /// The multicast addresses which are filtered
+    pub mcast_filter: [MacAddress; 16],
+    /// Current address
+    pub current_address: MacAddress,
+    /// Broadcast address
+    pub broadcast_address: MacAddress,
+    /// Address programmed in the hardware
+    pub permanent_address: MacAddress,
+    /// ARP hardware type of the interface, like 1 for Ethernet
+    pub if_type: u8,
+    /// Whether the current address can be changed
+    pub mac_address_changeable: bool,
+    /// Whether several frames can be transmitted at once
+    pub multiple_tx_supported: bool,
+    /// Whether `media_present` is meaningful
+    pub media_present_supported: bool,
+    /// Whether a cable is connected, when the interface was last initialized
+    /// or its status was last read
+    pub media_present: bool,
+}
+
+impl NetworkMode {
+    /// The current address, without its padding
+    pub fn mac_address(&self) -> &[u8] {
+        let len = (self.hw_address_size as usize).min(self.current_address.len());
+        &self.current_address[..len]
+    }
+}
+
+/// The Simple Network protocol.
+#[repr(C)]
+#[unsafe_guid("a19832b9-ac25-11d3-9a2d-0090273fc14d")]
+#[derive(Protocol)]
+pub struct SimpleNetwork {
+    revision: u64,
+    start: usize,
+    stop: usize,
+    initialize: usize,
+    reset: usize,
+    shutdown: usize,
+    receive_filters: usize,
+    station_address: usize,
+    statistics: usize,
+    mcast_ip_to_mac: usize,
+    nv_data: usize,
+    get_status: unsafe extern "efiapi" fn(
+        this: *mut SimpleNetwork,
+        interrupt_status: *mut u32,
+        tx_buf: *mut *mut c_void,
+    ) -> Status,
+    transmit: usize,
+    receive: usize,
+    wait_for_packet: Event,
+    mode: *const NetworkMode,
+}
+
+impl SimpleNetwork {
+    /// Description of the interface
+    pub fn mode(&self) -> &NetworkMode {
+        unsafe { &*self.mode }
+    }
+
+    /// Read the status of the interface again, which updates
+    /// `NetworkMode::media_present`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`   The interface is not started
+    /// * `uefi::Status::DEVICE_ERROR`  The status could not be read
+    pub fn refresh_status(&mut self) -> Result {
+        unsafe { (self.get_status)(self, ptr::null_mut(), ptr::null_mut()) }.into()
+    }
+}
@@ -0,0 +1,275 @@
+//! TCPv4 protocol.
+//!
+//! Instances are created with the [`Tcp4ServiceBinding`] protocol of the
+//! network interfaces; each one is a single connection. The operations of
+//! this wrapper block until they complete, polling the driver meanwhile.
+
+use super::completion::{CompletionEvent, FragmentData};
+use super::service_binding::ServiceBinding;
+use crate::proto::Protocol;
+use crate::table::boot::BootServices;
+use crate::{unsafe_guid, Event, Result, Status};
+use core::ffi::c_void;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+/// Addresses and ports of a connection (`EFI_TCP4_ACCESS_POINT`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Tcp4AccessPoint {
+    /// Whether to use the address of the interface, given by DHCP or by the
+    /// user, instead of `station_address` and `subnet_mask`
+    pub use_default_address: bool,
+    /// Local address
+    pub station_address: [u8; 4],
+    /// Mask of the local subnet
+    pub subnet_mask: [u8; 4],
+    /// Local port, or 0 for any
+    pub station_port: u16,
+    /// Address of the peer
+    pub remote_address: [u8; 4],
+    /// Port of the peer
+    pub remote_port: u16,
+    /// Whether the connection is opened by this side, with `connect`,
+    /// instead of accepted from the peer
+    pub active_flag: bool,
+}
+
+impl Tcp4AccessPoint {
+    /// A connection to `address` and `port`, from the address of the
+    /// interface and any port
+    pub fn client(address: [u8; 4], port: u16) -> Self {
+        Tcp4AccessPoint {
+            use_default_address: true,
+            station_address: [0; 4],
+            subnet_mask: [0; 4],
+            station_port: 0,
+            remote_address: address,
+            remote_port: port,
+            active_flag: true,
+        }
+    }
+}
+
+/// Configuration of a TCPv4 instance (`EFI_TCP4_CONFIG_DATA`)
+#[repr(C)]
+#[derive(Debug)]
+pub struct Tcp4ConfigData {
+    /// Type of service of the IP packets
+    pub type_of_service: u8,
+    /// Time to live of the IP packets
+    pub time_to_live: u8,
+    /// Addresses and ports of the connection
+    pub access_point: Tcp4AccessPoint,
+    control_option: *const c_void,
+}
+
+impl Tcp4ConfigData {
+    /// A configuration with the default TCP options of the driver
+    pub fn new(access_point: Tcp4AccessPoint) -> Self {
+        Tcp4ConfigData {
+            type_of_service: 0,
+            time_to_live: 64,
+            access_point,
+            control_option: ptr::null(),
+        }
+    }
+}
+
+/// `EFI_TCP4_COMPLETION_TOKEN`
+#[repr(C)]
+struct CompletionToken {
+    event: Event,
+    status: Status,
+}
+
+/// `EFI_TCP4_IO_TOKEN`, whose packet points to a `ReceiveData` or a
+/// `TransmitData`
+#[repr(C)]
+struct IoToken {
+    completion: CompletionToken,
+    packet: *mut c_void,
+}
+
+/// `EFI_TCP4_CLOSE_TOKEN`
+#[repr(C)]
+struct CloseToken {
+    completion: CompletionToken,
+    abort_on_close: bool,
+}
+
+/// `EFI_TCP4_RECEIVE_DATA`, with a single fragment
+#[repr(C)]
+struct ReceiveData {
+    urgent_flag: bool,
+    data_length: u32,
+    fragment_count: u32,
+    fragment_table: [FragmentData; 1],
+}
+
+/// `EFI_TCP4_TRANSMIT_DATA`, with a single fragment
+#[repr(C)]
+struct TransmitData {
+    push: bool,
+    urgent: bool,
+    data_length: u32,
+    fragment_count: u32,
+    fragment_table: [FragmentData; 1],
+}
+
+/// The TCPv4 protocol.
+#[repr(C)]
+#[unsafe_guid("65530bc7-a359-410f-b010-5aadc7ec2b62")]
+#[derive(Protocol)]
+pub struct Tcp4 {
+    get_mode_data: usize,
+    configure: unsafe extern "efiapi" fn(this: *mut Tcp4, config: *const Tcp4ConfigData) -> Status,
+    routes: usize,
+    connect: unsafe extern "efiapi" fn(this: *mut Tcp4, token: *mut CompletionToken) -> Status,
+    accept: usize,
+    transmit: unsafe extern "efiapi" fn(this: *mut Tcp4, token: *mut IoToken) -> Status,
+    receive: unsafe extern "efiapi" fn(this: *mut Tcp4, token: *mut IoToken) -> Status,
+    close: unsafe extern "efiapi" fn(this: *mut Tcp4, token: *mut CloseToken) -> Status,
+    cancel: usize,
+    poll: unsafe extern "efiapi" fn(this: *mut Tcp4) -> Status,
+}
+
+impl Tcp4 {
+    /// Configure the instance with `config`, or reset it to the
+    /// unconfigured state and abort the connection if `config` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The configuration is invalid
+    /// * `uefi::Status::ACCESS_DENIED`      The instance is already configured,
+    ///                                      and must be reset first
+    /// * `uefi::Status::NO_MAPPING`         The default address is not
+    ///                                      available yet
+    pub fn configure(&mut self, config: Option<&Tcp4ConfigData>) -> Result {
+        let config = config.map_or(ptr::null(), |config| config as *const _);
+        unsafe { (self.configure)(self, config) }.into()
+    }
+
+    /// Open the connection to the peer of the configuration.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`           The instance is not configured
+    /// * `uefi::Status::CONNECTION_REFUSED`    The peer refused the connection
+    /// * `uefi::Status::TIMEOUT`               The peer did not answer
+    pub fn connect(&mut self, bt: &BootServices) -> Result {
+        let completion = CompletionEvent::new(bt)?.log();
+        let mut token = CompletionToken {
+            event: completion.event(),
+            status: Status::SUCCESS,
+        };
+        Result::from(unsafe { (self.connect)(self, &mut token) })?.log();
+        completion.wait(|| self.poll());
+        token.status.into()
+    }
+
+    /// Queue `data` for transmission, and return the number of bytes which
+    /// were queued.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`           The instance is not configured
+    /// * `uefi::Status::CONNECTION_FIN`        The connection is closing
+    /// * `uefi::Status::CONNECTION_RESET`      The peer closed the connection
+    pub fn transmit(&mut self, bt: &BootServices, data: &[u8]) -> Result<usize> {
+        let completion = CompletionEvent::new(bt)?.log();
+        // The driver only reads from the buffer
+        let mut packet = TransmitData {
+            push: true,
+            urgent: false,
+            data_length: data.len() as u32,
+            fragment_count: 1,
+            fragment_table: [FragmentData::new(data.as_ptr() as *mut u8, data.len())],
+        };
+        let mut token = IoToken {
+            completion: CompletionToken {
+                event: completion.event(),
+                status: Status::SUCCESS,
+            },
+            packet: &mut packet as *mut TransmitData as *mut c_void,
+        };
+        Result::from(unsafe { (self.transmit)(self, &mut token) })?.log();
+        completion.wait(|| self.poll());
+        let len = packet.data_length as usize;
+        token.completion.status.into_with_val(|| len)
+    }
+
+    /// Receive data into `buffer`, and return its size.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`           The instance is not configured
+    /// * `uefi::Status::CONNECTION_FIN`        The peer closed the connection,
+    ///                                         and all its data was received
+    /// * `uefi::Status::CONNECTION_RESET`      The peer reset the connection
+    pub fn receive(&mut self, bt: &BootServices, buffer: &mut [u8]) -> Result<usize> {
+        let completion = CompletionEvent::new(bt)?.log();
+        let mut packet = ReceiveData {
+            urgent_flag: false,
+            data_length: buffer.len() as u32,
+            fragment_count: 1,
+            fragment_table: [FragmentData::new(buffer.as_mut_ptr(), buffer.len())],
+        };
+        let mut token = IoToken {
+            completion: CompletionToken {
+                event: completion.event(),
+                status: Status::SUCCESS,
+            },
+            packet: &mut packet as *mut ReceiveData as *mut c_void,
+        };
+        Result::from(unsafe { (self.receive)(self, &mut token) })?.log();
+        completion.wait(|| self.poll());
+        let len = packet.data_length as usize;
+        token.completion.status.into_with_val(|| len)
+    }
+
+    /// Close the connection, gracefully or by resetting it if `abort` is
+    /// set.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`   The instance is not configured
+    pub fn close(&mut self, bt: &BootServices, abort: bool) -> Result {
+        let completion = CompletionEvent::new(bt)?.log();
+        let mut token = CloseToken {
+            completion: CompletionToken {
+                event: completion.event(),
+                status: Status::SUCCESS,
+            },
+            abort_on_close: abort,
+        };
+        Result::from(unsafe { (self.close)(self, &mut token) })?.log();
+        completion.wait(|| self.poll());
+        token.completion.status.into()
+    }
+
+    /// Process the packets which were received and sent.
+    pub fn poll(&mut self) {
+        let _ = unsafe { (self.poll)(self) };
+    }
+}
+
+/// Service binding protocol creating [`Tcp4`] instances.
+#[repr(transparent)]
+#[unsafe_guid("00720665-67eb-4a99-baf7-d3c33a1c7cc9")]
+#[derive(Protocol)]
+pub struct Tcp4ServiceBinding(ServiceBinding);
+
+impl Deref for Tcp4ServiceBinding {
+    type Target = ServiceBinding;
+
+    fn deref(&self) -> &ServiceBinding {
+        &self.0
+    }
+}
+
+impl DerefMut for Tcp4ServiceBinding {
+    fn deref_mut(&mut self) -> &mut ServiceBinding {
+        &mut self.0
+    }
+}
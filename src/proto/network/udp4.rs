@@ -0,0 +1,268 @@
+//! UDPv4 protocol.
+//!
+//! Instances are created with the [`Udp4ServiceBinding`] protocol of the
+//! network interfaces; each one is a socket bound to a local port. The
+//! operations of this wrapper block until they complete, polling the driver
+//! meanwhile.
+
+use super::completion::{CompletionEvent, FragmentData};
+use super::service_binding::ServiceBinding;
+use crate::proto::Protocol;
+use crate::table::boot::BootServices;
+use crate::{unsafe_guid, Event, Result, Status};
+use core::ffi::c_void;
+use core::ops::{Deref, DerefMut};
+use core::{ptr, slice};
+
+/// Configuration of a UDPv4 instance (`EFI_UDP4_CONFIG_DATA`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Udp4ConfigData {
+    /// Whether to receive broadcast datagrams
+    pub accept_broadcast: bool,
+    /// Whether to receive the datagrams sent to other hosts
+    pub accept_promiscuous: bool,
+    /// Whether to receive the datagrams sent to any port
+    pub accept_any_port: bool,
+    /// Whether other instances may use the same local port
+    pub allow_duplicate_port: bool,
+    /// Type of service of the IP packets
+    pub type_of_service: u8,
+    /// Time to live of the IP packets
+    pub time_to_live: u8,
+    /// Whether the packets may not be fragmented
+    pub do_not_fragment: bool,
+    /// Microseconds after which a receive fails, or 0 to wait forever
+    pub receive_timeout: u32,
+    /// Microseconds after which a transmit fails, or 0 to wait forever
+    pub transmit_timeout: u32,
+    /// Whether to use the address of the interface, given by DHCP or by the
+    /// user, instead of `station_address` and `subnet_mask`
+    pub use_default_address: bool,
+    /// Local address
+    pub station_address: [u8; 4],
+    /// Mask of the local subnet
+    pub subnet_mask: [u8; 4],
+    /// Local port, or 0 for any
+    pub station_port: u16,
+    /// Only peer from which datagrams are received, and to which they are
+    /// sent by default, or 0.0.0.0 for any
+    pub remote_address: [u8; 4],
+    /// Port of the peer, or 0 for any
+    pub remote_port: u16,
+}
+
+impl Udp4ConfigData {
+    /// A socket on any local port, from the address of the interface, which
+    /// exchanges datagrams with any peer
+    pub fn any_peer() -> Self {
+        Udp4ConfigData {
+            accept_broadcast: false,
+            accept_promiscuous: false,
+            accept_any_port: false,
+            allow_duplicate_port: false,
+            type_of_service: 0,
+            time_to_live: 64,
+            do_not_fragment: false,
+            receive_timeout: 0,
+            transmit_timeout: 0,
+            use_default_address: true,
+            station_address: [0; 4],
+            subnet_mask: [0; 4],
+            station_port: 0,
+            remote_address: [0; 4],
+            remote_port: 0,
+        }
+    }
+}
+
+/// Source and destination of a datagram (`EFI_UDP4_SESSION_DATA`)
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Udp4SessionData {
+    /// Address of the sender
+    pub source_address: [u8; 4],
+    /// Port of the sender
+    pub source_port: u16,
+    /// Address of the receiver
+    pub destination_address: [u8; 4],
+    /// Port of the receiver
+    pub destination_port: u16,
+}
+
+/// `EFI_UDP4_COMPLETION_TOKEN`, whose packet points to a `TransmitData`, or
+/// is set to a `ReceiveData` by the driver
+#[repr(C)]
+struct CompletionToken {
+    event: Event,
+    status: Status,
+    packet: *mut c_void,
+}
+
+/// `EFI_UDP4_TRANSMIT_DATA`, with a single fragment
+#[repr(C)]
+struct TransmitData {
+    session_data: *const Udp4SessionData,
+    gateway_address: *const [u8; 4],
+    data_length: u32,
+    fragment_count: u32,
+    fragment_table: [FragmentData; 1],
+}
+
+/// `EFI_UDP4_RECEIVE_DATA`, whose fragments follow
+#[repr(C)]
+struct ReceiveData {
+    time_stamp: [u32; 4],
+    recycle_signal: Event,
+    session_data: Udp4SessionData,
+    data_length: u32,
+    fragment_count: u32,
+    fragment_table: [FragmentData; 0],
+}
+
+/// The UDPv4 protocol.
+#[repr(C)]
+#[unsafe_guid("3ad9df29-4501-478d-b1f8-7f7fe70e50f3")]
+#[derive(Protocol)]
+pub struct Udp4 {
+    get_mode_data: usize,
+    configure: unsafe extern "efiapi" fn(this: *mut Udp4, config: *const Udp4ConfigData) -> Status,
+    groups: usize,
+    routes: usize,
+    transmit: unsafe extern "efiapi" fn(this: *mut Udp4, token: *mut CompletionToken) -> Status,
+    receive: unsafe extern "efiapi" fn(this: *mut Udp4, token: *mut CompletionToken) -> Status,
+    cancel: usize,
+    poll: unsafe extern "efiapi" fn(this: *mut Udp4) -> Status,
+}
+
+impl Udp4 {
+    /// Configure the instance with `config`, or reset it to the
+    /// unconfigured state and abort the operations in progress if `config`
+    /// is `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The configuration is invalid
+    /// * `uefi::Status::ACCESS_DENIED`      The instance is already configured,
+    ///                                      and must be reset first
+    /// * `uefi::Status::NO_MAPPING`         The default address is not
+    ///                                      available yet
+    pub fn configure(&mut self, config: Option<&Udp4ConfigData>) -> Result {
+        let config = config.map_or(ptr::null(), |config| config as *const _);
+        unsafe { (self.configure)(self, config) }.into()
+    }
+
+    /// Send `data` in a datagram to `destination` and `port`, or to the
+    /// peer of the configuration if `destination` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`       The instance is not configured
+    /// * `uefi::Status::BAD_BUFFER_SIZE`   `data` does not fit in a datagram
+    /// * `uefi::Status::NO_MAPPING`        There is no route to `destination`
+    pub fn transmit(
+        &mut self,
+        bt: &BootServices,
+        destination: Option<([u8; 4], u16)>,
+        data: &[u8],
+    ) -> Result {
+        let completion = CompletionEvent::new(bt)?.log();
+        let session = destination.map(|(address, port)| Udp4SessionData {
+            destination_address: address,
+            destination_port: port,
+            ..Udp4SessionData::default()
+        });
+        // The driver only reads from the buffer
+        let mut packet = TransmitData {
+            session_data: session
+                .as_ref()
+                .map_or(ptr::null(), |session| session as *const _),
+            gateway_address: ptr::null(),
+            data_length: data.len() as u32,
+            fragment_count: 1,
+            fragment_table: [FragmentData::new(data.as_ptr() as *mut u8, data.len())],
+        };
+        let mut token = CompletionToken {
+            event: completion.event(),
+            status: Status::SUCCESS,
+            packet: &mut packet as *mut TransmitData as *mut c_void,
+        };
+        Result::from(unsafe { (self.transmit)(self, &mut token) })?.log();
+        completion.wait(|| self.poll());
+        token.status.into()
+    }
+
+    /// Receive a datagram into `buffer`, and return its size and sender.
+    ///
+    /// Datagrams larger than `buffer` are truncated.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_STARTED`   The instance is not configured
+    /// * `uefi::Status::TIMEOUT`       No datagram arrived before the timeout
+    ///                                 of the configuration
+    /// * `uefi::Status::ICMP_ERROR`    The peer is unreachable
+    pub fn receive(
+        &mut self,
+        bt: &BootServices,
+        buffer: &mut [u8],
+    ) -> Result<(usize, Udp4SessionData)> {
+        let completion = CompletionEvent::new(bt)?.log();
+        let mut token = CompletionToken {
+            event: completion.event(),
+            status: Status::SUCCESS,
+            packet: ptr::null_mut(),
+        };
+        Result::from(unsafe { (self.receive)(self, &mut token) })?.log();
+        completion.wait(|| self.poll());
+        Result::from(token.status)?.log();
+        if token.packet.is_null() {
+            return Err(Status::PROTOCOL_ERROR.into());
+        }
+        let packet = unsafe { &*(token.packet as *const ReceiveData) };
+        let fragments = unsafe {
+            slice::from_raw_parts(
+                packet.fragment_table.as_ptr(),
+                packet.fragment_count as usize,
+            )
+        };
+        let mut len = 0;
+        for fragment in fragments {
+            let data = unsafe {
+                slice::from_raw_parts(fragment.buffer as *const u8, fragment.length as usize)
+            };
+            let copied = data.len().min(buffer.len() - len);
+            buffer[len..len + copied].copy_from_slice(&data[..copied]);
+            len += copied;
+        }
+        let session = packet.session_data;
+        // The driver owns the packet until it is recycled
+        let _ = bt.signal_event(packet.recycle_signal);
+        Ok((len, session).into())
+    }
+
+    /// Process the datagrams which were received and sent.
+    pub fn poll(&mut self) {
+        let _ = unsafe { (self.poll)(self) };
+    }
+}
+
+/// Service binding protocol creating [`Udp4`] instances.
+#[repr(transparent)]
+#[unsafe_guid("83f01464-99bd-45e5-b383-af6305d8e9e6")]
+#[derive(Protocol)]
+pub struct Udp4ServiceBinding(ServiceBinding);
+
+impl Deref for Udp4ServiceBinding {
+    type Target = ServiceBinding;
+
+    fn deref(&self) -> &ServiceBinding {
+        &self.0
+    }
+}
+
+impl DerefMut for Udp4ServiceBinding {
+    fn deref_mut(&mut self) -> &mut ServiceBinding {
+        &mut self.0
+    }
+}
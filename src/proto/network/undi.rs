@@ -0,0 +1,929 @@
+//! Universal Network Driver Interface.
+//!
+//! UNDI is the interface below the Simple Network protocol: the driver of a
+//! network interface exposes a single entry point, which executes command
+//! descriptor blocks (`Cdb`). Each command takes an optional parameter block
+//! (CPB) and fills an optional data block (DB).
+//!
+//! Using it directly gives full control of the frames which are received and
+//! transmitted, but the interface must not be used by the Simple Network
+//! protocol at the same time, which usually means that the SNP driver must
+//! be disconnected from the controller first.
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::network::nii::NetworkInterfaceIdentifier;
+//! # use uefi::proto::network::undi::{StartCpb, UndiCallbacks};
+//! # fn f(bt: &BootServices, nii: &NetworkInterfaceIdentifier) -> uefi::Result {
+//! let undi = unsafe { nii.undi() }?.log();
+//! let callbacks = UndiCallbacks::new(bt);
+//! unsafe { undi.start(&StartCpb::new(&callbacks)) }.discard_errdata()?.log();
+//! let info = undi.get_init_info().discard_errdata()?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use super::nii::NetworkInterfaceIdentifier;
+use crate::proto::pci::PciIo;
+use crate::result::Error;
+use crate::table::boot::BootServices;
+use crate::{Result, ResultExt, Status};
+use bitflags::bitflags;
+use core::cell::UnsafeCell;
+use core::{mem, ptr};
+
+/// Signature of the structure describing an UNDI driver
+pub const UNDI_SIGNATURE: [u8; 4] = *b"!PXE";
+
+newtype_enum! {
+    /// Command of a `Cdb`
+    pub enum OpCode: u16 => {
+        /// Return the state of the interface
+        GET_STATE = 0x0000,
+        /// Give the callbacks to the interface
+        START = 0x0001,
+        /// Stop using the callbacks
+        STOP = 0x0002,
+        /// Return the resources needed to initialize the interface
+        GET_INIT_INFO = 0x0003,
+        /// Return the configuration of the bus of the interface
+        GET_CONFIG_INFO = 0x0004,
+        /// Initialize the interface
+        INITIALIZE = 0x0005,
+        /// Reset the interface
+        RESET = 0x0006,
+        /// Give back the memory given at initialization
+        SHUTDOWN = 0x0007,
+        /// Configure the interrupts
+        INTERRUPT_ENABLES = 0x0008,
+        /// Configure the filters of received frames
+        RECEIVE_FILTERS = 0x0009,
+        /// Read or change the MAC address
+        STATION_ADDRESS = 0x000a,
+        /// Read or reset the statistics
+        STATISTICS = 0x000b,
+        /// Translate a multicast IP address into a MAC address
+        MCAST_IP_TO_MAC = 0x000c,
+        /// Read or write the non-volatile storage
+        NVDATA = 0x000d,
+        /// Return the interrupt, transmit and media status
+        GET_STATUS = 0x000e,
+        /// Fill the media header of a frame
+        FILL_HEADER = 0x000f,
+        /// Transmit a frame
+        TRANSMIT = 0x0010,
+        /// Receive a frame
+        RECEIVE = 0x0011,
+    }
+}
+
+newtype_enum! {
+    /// Result of a command
+    pub enum StatCode: u16 => {
+        /// The command succeeded
+        SUCCESS = 0x0000,
+        /// The command descriptor block is invalid
+        INVALID_CDB = 0x0001,
+        /// The parameter block is invalid
+        INVALID_CPB = 0x0002,
+        /// The interface is busy
+        BUSY = 0x0003,
+        /// The command queue is full
+        QUEUE_FULL = 0x0004,
+        /// The interface was already started
+        ALREADY_STARTED = 0x0005,
+        /// The interface is not started
+        NOT_STARTED = 0x0006,
+        /// The interface must be shut down first
+        NOT_SHUTDOWN = 0x0007,
+        /// The interface was already initialized
+        ALREADY_INITIALIZED = 0x0008,
+        /// The interface is not initialized
+        NOT_INITIALIZED = 0x0009,
+        /// The hardware failed
+        DEVICE_FAILURE = 0x000a,
+        /// The non-volatile storage failed
+        NVDATA_FAILURE = 0x000b,
+        /// The command is not supported
+        UNSUPPORTED = 0x000c,
+        /// The buffers are full
+        BUFFER_FULL = 0x000d,
+        /// A parameter is invalid
+        INVALID_PARAMETER = 0x000e,
+        /// The interface number is invalid
+        INVALID_UNDI = 0x000f,
+        /// IPv4 is not supported
+        IPV4_NOT_SUPPORTED = 0x0010,
+        /// IPv6 is not supported
+        IPV6_NOT_SUPPORTED = 0x0011,
+        /// The memory given at initialization is too small
+        NOT_ENOUGH_MEMORY = 0x0012,
+        /// No frame was received
+        NO_DATA = 0x0013,
+    }
+}
+
+impl StatCode {
+    /// The UEFI status which is the closest to the result of the command
+    pub fn to_status(self) -> Status {
+        match self {
+            StatCode::SUCCESS => Status::SUCCESS,
+            StatCode::INVALID_CDB
+            | StatCode::INVALID_CPB
+            | StatCode::INVALID_PARAMETER
+            | StatCode::INVALID_UNDI => Status::INVALID_PARAMETER,
+            StatCode::BUSY | StatCode::QUEUE_FULL | StatCode::NO_DATA => Status::NOT_READY,
+            StatCode::ALREADY_STARTED | StatCode::ALREADY_INITIALIZED => Status::ALREADY_STARTED,
+            StatCode::NOT_STARTED | StatCode::NOT_INITIALIZED => Status::NOT_STARTED,
+            StatCode::UNSUPPORTED | StatCode::IPV4_NOT_SUPPORTED | StatCode::IPV6_NOT_SUPPORTED => {
+                Status::UNSUPPORTED
+            }
+            StatCode::BUFFER_FULL => Status::BUFFER_TOO_SMALL,
+            StatCode::NOT_ENOUGH_MEMORY => Status::OUT_OF_RESOURCES,
+            _ => Status::DEVICE_ERROR,
+        }
+    }
+}
+
+newtype_enum! {
+    /// State of the interface
+    pub enum State: u16 => {
+        /// Not started
+        STOPPED = 0,
+        /// Started, but not initialized
+        STARTED = 1,
+        /// Ready to receive and transmit frames
+        INITIALIZED = 2,
+    }
+}
+
+bitflags! {
+    /// Features of the interface (`PXE_ROMID_IMP_*`)
+    pub struct Implementation: u32 {
+        /// Commands complete with an interrupt
+        const CMD_COMPLETE_INT_SUPPORTED = 0x0000_0001;
+        /// Received frames raise an interrupt
+        const PACKET_RX_INT_SUPPORTED = 0x0000_0002;
+        /// Transmitted frames raise an interrupt
+        const TX_COMPLETE_INT_SUPPORTED = 0x0000_0004;
+        /// Software interrupts are supported
+        const SOFTWARE_INT_SUPPORTED = 0x0000_0008;
+        /// Multicast frames can be filtered
+        const FILTERED_MULTICAST_RX_SUPPORTED = 0x0000_0010;
+        /// Broadcast frames can be received
+        const BROADCAST_RX_SUPPORTED = 0x0000_0020;
+        /// All frames can be received
+        const PROMISCUOUS_RX_SUPPORTED = 0x0000_0040;
+        /// All multicast frames can be received
+        const PROMISCUOUS_MULTICAST_RX_SUPPORTED = 0x0000_0080;
+        /// The MAC address can be changed
+        const STATION_ADDR_SETTABLE = 0x0000_0100;
+        /// Statistics are supported
+        const STATISTICS_SUPPORTED = 0x0000_0200;
+        /// Mask of the kind of non-volatile storage
+        const NVDATA_SUPPORT_MASK = 0x0000_0c00;
+        /// Several frames can be transmitted by one command
+        const MULTI_FRAME_SUPPORTED = 0x0000_1000;
+        /// Commands can be queued
+        const CMD_QUEUE_SUPPORTED = 0x0000_2000;
+        /// Commands can be linked
+        const CMD_LINK_SUPPORTED = 0x0000_4000;
+        /// Fragmented frames can be transmitted
+        const FRAG_SUPPORTED = 0x0000_8000;
+        /// The device can address memory above 4 GiB
+        const DEVICE_64BIT = 0x0001_0000;
+        /// The entry point is an address instead of an offset
+        const SW_VIRT_ADDR = 0x4000_0000;
+        /// The interface is implemented in hardware
+        const HW_UNDI = 0x8000_0000;
+    }
+}
+
+/// Options of `OpCode::GET_STATUS`
+const GET_STATUS_MEDIA: u16 = 0x0004;
+/// The cable is disconnected
+const STATFLAGS_NO_MEDIA: u16 = 0x0040;
+/// Mask of the completion bits of `Cdb::stat_flags`
+const STATFLAGS_STATUS_MASK: u16 = 0xc000;
+/// The command completed successfully
+const STATFLAGS_COMMAND_COMPLETE: u16 = 0xc000;
+/// The command failed
+const STATFLAGS_COMMAND_FAILED: u16 = 0x8000;
+/// Initialize without waiting for a cable to be connected
+const INITIALIZE_DO_NOT_DETECT_CABLE: u16 = 0x0001;
+
+/// A command descriptor block (`PXE_CDB`)
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct Cdb {
+    /// Command
+    pub op_code: OpCode,
+    /// Options of the command
+    pub op_flags: u16,
+    /// Size of the parameter block
+    pub cpb_size: u16,
+    /// Size of the data block
+    pub db_size: u16,
+    /// Address of the parameter block, or 0
+    pub cpb_addr: u64,
+    /// Address of the data block, or 0
+    pub db_addr: u64,
+    /// Result of the command
+    pub stat_code: StatCode,
+    /// Completion and command-specific status flags
+    pub stat_flags: u16,
+    /// Number of the interface
+    pub if_num: u16,
+    /// Queuing and linking of commands
+    pub control: u16,
+}
+
+impl Cdb {
+    /// Command `op_code`, without options nor blocks, for interface `if_num`
+    pub fn new(op_code: OpCode, if_num: u16) -> Self {
+        Cdb {
+            op_code,
+            op_flags: 0,
+            cpb_size: 0,
+            db_size: 0,
+            cpb_addr: 0,
+            db_addr: 0,
+            stat_code: StatCode::SUCCESS,
+            stat_flags: 0,
+            if_num,
+            control: 0,
+        }
+    }
+
+    /// Set the options of the command
+    pub fn with_op_flags(mut self, op_flags: u16) -> Self {
+        self.op_flags = op_flags;
+        self
+    }
+
+    /// Give a parameter block to the command, which must stay valid until
+    /// it is issued
+    pub fn with_cpb<T>(mut self, cpb: &T) -> Self {
+        self.cpb_size = mem::size_of::<T>() as u16;
+        self.cpb_addr = cpb as *const T as u64;
+        self
+    }
+
+    /// Give a data block to the command, which must stay valid until it is
+    /// issued
+    pub fn with_db<T>(mut self, db: &mut T) -> Self {
+        self.db_size = mem::size_of::<T>() as u16;
+        self.db_addr = db as *mut T as u64;
+        self
+    }
+
+    /// Whether the command has completed, successfully or not
+    pub fn is_complete(&self) -> bool {
+        let status = self.stat_flags & STATFLAGS_STATUS_MASK;
+        status == STATFLAGS_COMMAND_COMPLETE || status == STATFLAGS_COMMAND_FAILED
+    }
+
+    /// The result of the command, as an UEFI result
+    pub fn result(&self) -> Result<(), StatCode> {
+        if self.stat_flags & STATFLAGS_STATUS_MASK == STATFLAGS_COMMAND_FAILED
+            || self.stat_code != StatCode::SUCCESS
+        {
+            let status = match self.stat_code.to_status() {
+                Status::SUCCESS => Status::DEVICE_ERROR,
+                status => status,
+            };
+            Err(Error::new(status, self.stat_code))
+        } else {
+            Ok(().into())
+        }
+    }
+}
+
+/// Structure describing a software UNDI driver (`PXE_SW_UNDI`)
+#[derive(Debug)]
+#[repr(C)]
+pub struct UndiHeader {
+    signature: [u8; 4],
+    len: u8,
+    fudge: u8,
+    rev: u8,
+    if_count: u8,
+    major_ver: u8,
+    minor_ver: u8,
+    if_count_ext: u8,
+    _reserved1: u8,
+    implementation: u32,
+    entry_point: u64,
+    _reserved2: [u8; 3],
+    bus_count: u8,
+    bus_type: [u8; 4],
+}
+
+impl UndiHeader {
+    /// Whether the signature and checksum of the header are valid
+    pub fn is_valid(&self) -> bool {
+        let len = usize::from(self.len);
+        if self.signature != UNDI_SIGNATURE || len < mem::size_of::<UndiHeader>() {
+            return false;
+        }
+        // The checksum covers all the bus types, which may follow the header
+        let bytes = unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, len) };
+        crate::crc::sum8(bytes) == 0
+    }
+
+    /// Version of the interface, as `(major, minor)`
+    pub fn version(&self) -> (u8, u8) {
+        (self.major_ver, self.minor_ver)
+    }
+
+    /// Number of interfaces of the driver
+    pub fn if_count(&self) -> u16 {
+        u16::from(self.if_count) | u16::from(self.if_count_ext) << 8
+    }
+
+    /// Features of the interface
+    pub fn implementation(&self) -> Implementation {
+        Implementation::from_bits_truncate(self.implementation)
+    }
+
+    /// Address of the entry point
+    pub fn entry_point(&self) -> u64 {
+        if self.implementation().contains(Implementation::SW_VIRT_ADDR) {
+            self.entry_point
+        } else {
+            self as *const Self as u64 + self.entry_point
+        }
+    }
+}
+
+/// An interface of a software UNDI driver
+#[derive(Debug, Copy, Clone)]
+pub struct Undi {
+    entry: unsafe extern "efiapi" fn(cdb: u64),
+    if_num: u16,
+    implementation: Implementation,
+}
+
+impl Undi {
+    /// Find the entry point of the driver described by `nii`.
+    ///
+    /// # Safety
+    ///
+    /// The protocol must describe a valid driver, which must stay loaded
+    /// while the interface is used.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       The interface is not an UNDI
+    ///                                     software interface
+    /// * `uefi::Status::VOLUME_CORRUPTED`  The description of the driver is
+    ///                                     invalid
+    pub unsafe fn from_nii(nii: &NetworkInterfaceIdentifier) -> Result<Self> {
+        if nii.string_id() != *b"UNDI" || nii.id() == 0 {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        let header = &*(nii.id() as *const UndiHeader);
+        if !header.is_valid() {
+            return Err(Status::VOLUME_CORRUPTED.into());
+        }
+        let implementation = header.implementation();
+        if implementation.contains(Implementation::HW_UNDI) {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        Ok(Undi {
+            entry: mem::transmute::<usize, unsafe extern "efiapi" fn(u64)>(
+                header.entry_point() as usize
+            ),
+            if_num: nii.if_num(),
+            implementation,
+        }
+        .into())
+    }
+
+    /// Number of the interface, among the interfaces of the driver
+    pub fn if_num(&self) -> u16 {
+        self.if_num
+    }
+
+    /// Features of the interface
+    pub fn implementation(&self) -> Implementation {
+        self.implementation
+    }
+
+    /// A command without parameters for this interface
+    pub fn cdb(&self, op_code: OpCode) -> Cdb {
+        Cdb::new(op_code, self.if_num)
+    }
+
+    /// Execute `cdb`, whose results are stored back into it.
+    ///
+    /// # Safety
+    ///
+    /// The blocks of the command must be valid for the command, and any
+    /// memory given to the driver must stay valid as long as it uses it.
+    pub unsafe fn issue(&self, cdb: &mut Cdb) -> Result<(), StatCode> {
+        (self.entry)(cdb as *mut Cdb as u64);
+        cdb.result()
+    }
+
+    /// Issue `cdb`, which only has blocks borrowed for the duration of
+    /// the call
+    fn issue_scoped(&self, mut cdb: Cdb) -> Result<Cdb, StatCode> {
+        unsafe { self.issue(&mut cdb) }.map_inner(|()| cdb)
+    }
+
+    /// State of the interface
+    pub fn get_state(&self) -> Result<State, StatCode> {
+        self.issue_scoped(self.cdb(OpCode::GET_STATE))
+            .map_inner(|cdb| State(cdb.stat_flags & 0x3))
+    }
+
+    /// Start the interface, giving it the callbacks built by
+    /// `StartCpb::new`.
+    ///
+    /// # Safety
+    ///
+    /// The callbacks must stay valid until the interface is stopped.
+    pub unsafe fn start(&self, cpb: &StartCpb) -> Result<(), StatCode> {
+        self.issue(&mut self.cdb(OpCode::START).with_cpb(cpb))
+    }
+
+    /// Stop the interface, which must be shut down.
+    pub fn stop(&self) -> Result<(), StatCode> {
+        self.issue_scoped(self.cdb(OpCode::STOP)).map_inner(|_| ())
+    }
+
+    /// Resources needed to initialize the interface
+    pub fn get_init_info(&self) -> Result<InitInfo, StatCode> {
+        let mut info = InitInfo::default();
+        self.issue_scoped(self.cdb(OpCode::GET_INIT_INFO).with_db(&mut info))
+            .map_inner(|_| info)
+    }
+
+    /// Initialize the interface, giving it the memory and the parameters of
+    /// `cpb`, and return the buffers which it allocated.
+    ///
+    /// If `detect_cable` is set, it fails when no cable is connected.
+    ///
+    /// # Safety
+    ///
+    /// The memory of `cpb` must stay valid until the interface is shut down.
+    pub unsafe fn initialize(
+        &self,
+        cpb: &InitializeCpb,
+        detect_cable: bool,
+    ) -> Result<InitializeDb, StatCode> {
+        let mut db = InitializeDb::default();
+        let op_flags = if detect_cable {
+            0
+        } else {
+            INITIALIZE_DO_NOT_DETECT_CABLE
+        };
+        let mut cdb = self
+            .cdb(OpCode::INITIALIZE)
+            .with_op_flags(op_flags)
+            .with_cpb(cpb)
+            .with_db(&mut db);
+        self.issue(&mut cdb).map_inner(|()| db)
+    }
+
+    /// Reset the interface, keeping its configuration.
+    pub fn reset(&self) -> Result<(), StatCode> {
+        self.issue_scoped(self.cdb(OpCode::RESET)).map_inner(|_| ())
+    }
+
+    /// Shut the interface down, after which the memory given at
+    /// initialization is not used anymore.
+    pub fn shutdown(&self) -> Result<(), StatCode> {
+        self.issue_scoped(self.cdb(OpCode::SHUTDOWN))
+            .map_inner(|_| ())
+    }
+
+    /// MAC addresses of the interface
+    pub fn station_address(&self) -> Result<StationAddress, StatCode> {
+        let mut db = StationAddress::default();
+        self.issue_scoped(self.cdb(OpCode::STATION_ADDRESS).with_db(&mut db))
+            .map_inner(|_| db)
+    }
+
+    /// Whether a cable is connected
+    pub fn media_present(&self) -> Result<bool, StatCode> {
+        let mut db = GetStatusDb::default();
+        let cdb = self
+            .cdb(OpCode::GET_STATUS)
+            .with_op_flags(GET_STATUS_MEDIA)
+            .with_db(&mut db);
+        self.issue_scoped(cdb)
+            .map_inner(|cdb| cdb.stat_flags & STATFLAGS_NO_MEDIA == 0)
+    }
+
+    /// Queue `frame`, which starts with a media header of `header_len`
+    /// bytes, for transmission.
+    ///
+    /// # Safety
+    ///
+    /// The frame must stay valid until `GET_STATUS` reports that it was
+    /// transmitted.
+    pub unsafe fn transmit(&self, frame: &[u8], header_len: u16) -> Result<(), StatCode> {
+        let cpb = TransmitCpb {
+            frame_addr: frame.as_ptr() as u64,
+            data_len: frame.len().saturating_sub(usize::from(header_len)) as u32,
+            media_header_len: header_len,
+            _reserved: 0,
+        };
+        self.issue(&mut self.cdb(OpCode::TRANSMIT).with_cpb(&cpb))
+    }
+
+    /// Copy a received frame into `buffer`, failing with `StatCode::NO_DATA`
+    /// if none is pending.
+    pub fn receive(&self, buffer: &mut [u8]) -> Result<ReceiveDb, StatCode> {
+        let cpb = ReceiveCpb {
+            buffer_addr: buffer.as_mut_ptr() as u64,
+            buffer_len: buffer.len() as u32,
+            _reserved: 0,
+        };
+        let mut db = ReceiveDb::default();
+        self.issue_scoped(self.cdb(OpCode::RECEIVE).with_cpb(&cpb).with_db(&mut db))
+            .map_inner(|_| db)
+    }
+}
+
+/// Resources needed by an interface (`PXE_DB_GET_INIT_INFO`)
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct InitInfo {
+    /// Size of the memory to give at initialization
+    pub memory_required: u32,
+    /// Largest frame, including the media header
+    pub frame_data_len: u32,
+    /// Supported link speeds, in Mbit/s, or 0
+    pub link_speeds: [u32; 4],
+    /// Number of words of the non-volatile storage
+    pub nv_count: u32,
+    /// Width of the words of the non-volatile storage
+    pub nv_width: u16,
+    /// Size of the media header
+    pub media_header_len: u16,
+    /// Size of the MAC addresses
+    pub hw_addr_len: u16,
+    /// Number of multicast addresses which can be filtered
+    pub mcast_filter_count: u16,
+    /// Default number of transmit buffers
+    pub tx_buf_count: u16,
+    /// Default size of the transmit buffers
+    pub tx_buf_size: u16,
+    /// Default number of receive buffers
+    pub rx_buf_count: u16,
+    /// Default size of the receive buffers
+    pub rx_buf_size: u16,
+    /// ARP hardware type of the interface, like 1 for Ethernet
+    pub if_type: u8,
+    /// Supported duplex modes
+    pub supported_duplex_modes: u8,
+    /// Supported loopback modes
+    pub supported_loopback_modes: u8,
+}
+
+/// Parameters of `OpCode::INITIALIZE` (`PXE_CPB_INITIALIZE`)
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct InitializeCpb {
+    /// Address of the memory given to the interface
+    pub memory_addr: u64,
+    /// Size of the memory, at least `InitInfo::memory_required`
+    pub memory_len: u32,
+    /// Link speed, in Mbit/s, or 0 to negotiate it
+    pub link_speed: u32,
+    /// Number of transmit buffers, or 0 for the default
+    pub tx_buf_count: u16,
+    /// Size of the transmit buffers, or 0 for the default
+    pub tx_buf_size: u16,
+    /// Number of receive buffers, or 0 for the default
+    pub rx_buf_count: u16,
+    /// Size of the receive buffers, or 0 for the default
+    pub rx_buf_size: u16,
+    /// Duplex mode
+    pub duplex_mode: u8,
+    /// Loopback mode
+    pub loopback_mode: u8,
+}
+
+/// Results of `OpCode::INITIALIZE` (`PXE_DB_INITIALIZE`)
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct InitializeDb {
+    /// Size of the memory used by the interface
+    pub memory_used: u32,
+    /// Number of transmit buffers
+    pub tx_buf_count: u16,
+    /// Size of the transmit buffers
+    pub tx_buf_size: u16,
+    /// Number of receive buffers
+    pub rx_buf_count: u16,
+    /// Size of the receive buffers
+    pub rx_buf_size: u16,
+}
+
+/// MAC addresses of an interface (`PXE_DB_STATION_ADDRESS`), padded to 32
+/// bytes
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct StationAddress {
+    /// Current address
+    pub station: [u8; 32],
+    /// Broadcast address
+    pub broadcast: [u8; 32],
+    /// Address programmed in the hardware
+    pub permanent: [u8; 32],
+}
+
+/// Results of `OpCode::GET_STATUS` (`PXE_DB_GET_STATUS`)
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+struct GetStatusDb {
+    rx_frame_len: u32,
+    _reserved: u32,
+    tx_buffers: [u64; 32],
+}
+
+/// Parameters of `OpCode::TRANSMIT` (`PXE_CPB_TRANSMIT`)
+#[repr(C)]
+struct TransmitCpb {
+    frame_addr: u64,
+    data_len: u32,
+    media_header_len: u16,
+    _reserved: u16,
+}
+
+/// Parameters of `OpCode::RECEIVE` (`PXE_CPB_RECEIVE`)
+#[repr(C)]
+struct ReceiveCpb {
+    buffer_addr: u64,
+    buffer_len: u32,
+    _reserved: u32,
+}
+
+/// Description of a received frame (`PXE_DB_RECEIVE`)
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct ReceiveDb {
+    /// Source MAC address
+    pub src_addr: [u8; 32],
+    /// Destination MAC address
+    pub dest_addr: [u8; 32],
+    /// Size of the frame, which may be larger than the buffer
+    pub frame_len: u32,
+    /// Protocol of the frame, like 0x0800 for IPv4
+    pub protocol: u16,
+    /// Size of the media header
+    pub media_header_len: u16,
+    /// Kind of destination address: 1 for unicast, 2 for broadcast, 3 for
+    /// multicast and 4 for promiscuous
+    pub ty: u8,
+    _reserved: [u8; 7],
+}
+
+/// Services used by an interface while it is started
+///
+/// Delays use the boot services. Memory is identity-mapped, which is only
+/// correct on platforms without an IOMMU. Accesses to the registers of the
+/// device go through its `PciIo` protocol, if one is given; otherwise only
+/// memory-mapped registers are supported.
+pub struct UndiCallbacks<'a> {
+    bt: &'a BootServices,
+    pci: Option<(&'a UnsafeCell<PciIo>, u8, u8)>,
+}
+
+impl<'a> UndiCallbacks<'a> {
+    /// Callbacks without a `PciIo` protocol
+    pub fn new(bt: &'a BootServices) -> Self {
+        UndiCallbacks { bt, pci: None }
+    }
+
+    /// Callbacks accessing the registers through `pci`, in the memory BAR
+    /// `mem_bar` and the I/O BAR `io_bar`
+    pub fn with_pci(
+        bt: &'a BootServices,
+        pci: &'a UnsafeCell<PciIo>,
+        mem_bar: u8,
+        io_bar: u8,
+    ) -> Self {
+        UndiCallbacks {
+            bt,
+            pci: Some((pci, mem_bar, io_bar)),
+        }
+    }
+}
+
+/// Parameters of `OpCode::START` (`PXE_CPB_START_31`)
+#[derive(Debug)]
+#[repr(C)]
+pub struct StartCpb {
+    delay: u64,
+    block: u64,
+    virt2phys: u64,
+    mem_io: u64,
+    map_mem: u64,
+    unmap_mem: u64,
+    sync_mem: u64,
+    unique_id: u64,
+}
+
+impl StartCpb {
+    /// Parameters giving `callbacks` to the interface
+    pub fn new(callbacks: &UndiCallbacks) -> Self {
+        let delay: extern "efiapi" fn(u64, u64) = undi_delay;
+        let block: extern "efiapi" fn(u64, u32) = undi_block;
+        let virt2phys: extern "efiapi" fn(u64, u64, u64) = undi_virt2phys;
+        let mem_io: extern "efiapi" fn(u64, u8, u8, u64, u64) = undi_mem_io;
+        let map_mem: MapMemFn = undi_map_mem;
+        let unmap_mem: MapMemFn = undi_unmap_mem;
+        StartCpb {
+            delay: delay as usize as u64,
+            block: block as usize as u64,
+            virt2phys: virt2phys as usize as u64,
+            mem_io: mem_io as usize as u64,
+            map_mem: map_mem as usize as u64,
+            unmap_mem: unmap_mem as usize as u64,
+            sync_mem: unmap_mem as usize as u64,
+            unique_id: callbacks as *const UndiCallbacks as u64,
+        }
+    }
+}
+
+/// Signature of the `Map_Mem`, `UnMap_Mem` and `Sync_Mem` callbacks
+type MapMemFn = extern "efiapi" fn(u64, u64, u32, u32, u64);
+
+/// The callbacks given to `StartCpb::new`
+unsafe fn callbacks_of<'a>(unique_id: u64) -> &'a UndiCallbacks<'a> {
+    &*(unique_id as *const UndiCallbacks)
+}
+
+extern "efiapi" fn undi_delay(unique_id: u64, microseconds: u64) {
+    unsafe { callbacks_of(unique_id) }
+        .bt
+        .stall(microseconds as usize);
+}
+
+extern "efiapi" fn undi_block(_unique_id: u64, _enable: u32) {
+    // Boot services code runs on a single processor, and the interface is
+    // not used from event notifications
+}
+
+extern "efiapi" fn undi_virt2phys(_unique_id: u64, virtual_addr: u64, physical_ptr: u64) {
+    unsafe { ptr::write_unaligned(physical_ptr as *mut u64, virtual_addr) };
+}
+
+extern "efiapi" fn undi_map_mem(
+    _unique_id: u64,
+    cpu_addr: u64,
+    _size: u32,
+    _direction: u32,
+    device_addr_ptr: u64,
+) {
+    unsafe { ptr::write_unaligned(device_addr_ptr as *mut u64, cpu_addr) };
+}
+
+extern "efiapi" fn undi_unmap_mem(
+    _unique_id: u64,
+    _cpu_addr: u64,
+    _size: u32,
+    _direction: u32,
+    _device_addr: u64,
+) {
+}
+
+/// Kinds of accesses of the `Mem_IO` callback
+const IO_READ: u8 = 0;
+const IO_WRITE: u8 = 1;
+const MEM_READ: u8 = 2;
+const MEM_WRITE: u8 = 3;
+
+extern "efiapi" fn undi_mem_io(unique_id: u64, read_write: u8, len: u8, port: u64, buf_addr: u64) {
+    let callbacks = unsafe { callbacks_of(unique_id) };
+    let buffer = buf_addr as *mut u8;
+    match callbacks.pci {
+        Some((pci, mem_bar, io_bar)) => {
+            let pci = unsafe { &mut *pci.get() };
+            let (bar, is_io) = match read_write {
+                IO_READ | IO_WRITE => (io_bar, true),
+                _ => (mem_bar, false),
+            };
+            let write = read_write == IO_WRITE || read_write == MEM_WRITE;
+            macro_rules! access {
+                ($ty:ty) => {{
+                    let buffer = buffer as *mut $ty;
+                    if write {
+                        let value = unsafe { ptr::read_unaligned(buffer) };
+                        let _ = if is_io {
+                            pci.write_io(bar, port, value)
+                        } else {
+                            pci.write_mem(bar, port, value)
+                        };
+                    } else {
+                        let value = if is_io {
+                            pci.read_io::<$ty>(bar, port)
+                        } else {
+                            pci.read_mem::<$ty>(bar, port)
+                        };
+                        if let Ok(value) = value {
+                            unsafe { ptr::write_unaligned(buffer, value.split().1) };
+                        }
+                    }
+                }};
+            }
+            match len {
+                1 => access!(u8),
+                2 => access!(u16),
+                4 => access!(u32),
+                8 => access!(u64),
+                _ => {}
+            }
+        }
+        None if read_write == MEM_READ || read_write == MEM_WRITE => {
+            let register = port as *mut u8;
+            let (src, dst) = if read_write == MEM_WRITE {
+                (buffer as *const u8, register)
+            } else {
+                (register as *const u8, buffer)
+            };
+            unsafe {
+                match len {
+                    1 => ptr::write_volatile(dst, ptr::read_volatile(src)),
+                    2 => {
+                        ptr::write_volatile(dst as *mut u16, ptr::read_volatile(src as *const u16))
+                    }
+                    4 => {
+                        ptr::write_volatile(dst as *mut u32, ptr::read_volatile(src as *const u32))
+                    }
+                    8 => {
+                        ptr::write_volatile(dst as *mut u64, ptr::read_volatile(src as *const u64))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cdb_result() {
+        let mut cdb = Cdb::new(OpCode::GET_STATE, 0);
+        cdb.stat_flags = STATFLAGS_COMMAND_COMPLETE | 0x2;
+        assert!(cdb.is_complete());
+        assert!(cdb.result().is_ok());
+        cdb.stat_flags = STATFLAGS_COMMAND_FAILED;
+        cdb.stat_code = StatCode::NOT_STARTED;
+        let err = cdb.result().unwrap_err();
+        assert_eq!(err.status(), Status::NOT_STARTED);
+        assert_eq!(*err.data(), StatCode::NOT_STARTED);
+        cdb.stat_code = StatCode::SUCCESS;
+        assert_eq!(cdb.result().unwrap_err().status(), Status::DEVICE_ERROR);
+        assert_eq!(mem::size_of::<Cdb>(), 32);
+    }
+
+    #[test]
+    fn header() {
+        let mut header = UndiHeader {
+            signature: UNDI_SIGNATURE,
+            len: mem::size_of::<UndiHeader>() as u8,
+            fudge: 0,
+            rev: 3,
+            if_count: 1,
+            major_ver: 3,
+            minor_ver: 1,
+            if_count_ext: 0,
+            _reserved1: 0,
+            implementation: 0x0000_2100,
+            entry_point: 0x40,
+            _reserved2: [0; 3],
+            bus_count: 1,
+            bus_type: *b"PCIR",
+        };
+        assert!(!header.is_valid());
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                &header as *const UndiHeader as *const u8,
+                mem::size_of::<UndiHeader>(),
+            )
+        };
+        header.fudge = 0u8.wrapping_sub(crate::crc::sum8(bytes));
+        assert!(header.is_valid());
+        assert_eq!(header.version(), (3, 1));
+        assert_eq!(header.if_count(), 1);
+        assert!(header
+            .implementation()
+            .contains(Implementation::STATION_ADDR_SETTABLE));
+        assert_eq!(
+            header.entry_point(),
+            &header as *const UndiHeader as u64 + 0x40
+        );
+        header.signature = *b"$PXE";
+        assert!(!header.is_valid());
+    }
+}
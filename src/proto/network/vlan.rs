@@ -0,0 +1,84 @@
+//! VLAN Config protocol.
+//!
+//! The network stack creates a child interface for each VLAN configured on
+//! a network interface, tagging the frames it sends with the VLAN identifier.
+//! The configuration is stored in a variable, and applies to all the network
+//! boots which go through the interface, like PXE and HTTP boots.
+
+use crate::proto::Protocol;
+use crate::table::boot::{BootServices, PoolSlice};
+use crate::{unsafe_guid, Result, Status};
+use core::ptr;
+
+/// Largest VLAN identifier
+pub const MAX_VLAN_ID: u16 = 4094;
+
+/// A VLAN configured on an interface (`EFI_VLAN_FIND_DATA`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct VlanFindData {
+    /// VLAN identifier
+    pub vlan_id: u16,
+    /// 802.1Q priority of the frames
+    pub priority: u8,
+}
+
+/// The VLAN Config protocol.
+///
+/// It is installed on the handles of the network interfaces which support
+/// VLANs.
+#[repr(C)]
+#[unsafe_guid("9e23d768-d2f3-4366-9fc3-3a7aba864374")]
+#[derive(Protocol)]
+pub struct VlanConfig {
+    set: unsafe extern "efiapi" fn(this: *mut VlanConfig, vlan_id: u16, priority: u8) -> Status,
+    find: unsafe extern "efiapi" fn(
+        this: *mut VlanConfig,
+        vlan_id: *const u16,
+        number_of_vlan: *mut u16,
+        entries: *mut *mut VlanFindData,
+    ) -> Status,
+    remove: unsafe extern "efiapi" fn(this: *mut VlanConfig, vlan_id: u16) -> Status,
+}
+
+impl VlanConfig {
+    /// Create the VLAN `vlan_id`, or change its priority.
+    ///
+    /// VLAN 0 stands for the untagged frames, whose priority is set.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `vlan_id` is larger than
+    ///                                      `MAX_VLAN_ID`, or `priority` than 7
+    /// * `uefi::Status::OUT_OF_RESOURCES`   There is no room for another VLAN
+    pub fn set(&mut self, vlan_id: u16, priority: u8) -> Result {
+        unsafe { (self.set)(self, vlan_id, priority) }.into()
+    }
+
+    /// List the VLANs configured on the interface, or only `vlan_id` if it
+    /// is not `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  No VLAN matches
+    pub fn find<'boot>(
+        &mut self,
+        bt: &'boot BootServices,
+        vlan_id: Option<u16>,
+    ) -> Result<PoolSlice<'boot, VlanFindData>> {
+        let vlan_id = vlan_id.as_ref().map_or(ptr::null(), |id| id as *const u16);
+        let mut count = 0;
+        let mut entries = ptr::null_mut();
+        unsafe { (self.find)(self, vlan_id, &mut count, &mut entries) }
+            .into_with_val(|| unsafe { PoolSlice::from_raw(bt, entries, count.into()) })
+    }
+
+    /// Remove the VLAN `vlan_id`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  The VLAN does not exist
+    pub fn remove(&mut self, vlan_id: u16) -> Result {
+        unsafe { (self.remove)(self, vlan_id) }.into()
+    }
+}
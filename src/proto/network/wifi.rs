@@ -0,0 +1,603 @@
+//! Wi-Fi protocols.
+//!
+//! The Wireless MAC Connection II protocol scans for networks and connects to
+//! them, while the Supplicant protocol holds the credentials used during the
+//! connection. To join a WPA2-PSK network, the password and the SSID are given
+//! to the supplicant before connecting:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::network::wifi::{Ssid, Supplicant, WirelessMacConnection2};
+//! # fn f(
+//! #     bt: &BootServices,
+//! #     wifi: &mut WirelessMacConnection2,
+//! #     supplicant: &mut Supplicant,
+//! # ) -> uefi::Result {
+//! let ssid = Ssid::new(b"Office").unwrap();
+//! let networks = wifi.get_networks(bt, &[ssid])?.log();
+//! if let Some(found) = networks.networks().iter().find(|found| found.network.ssid == ssid) {
+//!     supplicant.set_psk_password("correct horse battery")?;
+//!     supplicant.set_target_ssid(&ssid)?;
+//!     wifi.connect_network(bt, &found.network, 20)
+//!         .map_err(|err| err.status())?;
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! Both protocols are installed on the handles of the wireless interfaces.
+
+use crate::proto::Protocol;
+use crate::result::Error;
+use crate::table::boot::{BootServices, EventType, Tpl};
+use crate::{unsafe_guid, Event, Result, Status};
+use core::ffi::c_void;
+use core::{fmt, mem, ptr, slice};
+
+/// Largest length of an SSID
+pub const MAX_SSID_LENGTH: usize = 32;
+
+/// Name of a network (`EFI_80211_SSID`)
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Ssid {
+    len: u8,
+    ssid: [u8; MAX_SSID_LENGTH],
+}
+
+impl Ssid {
+    /// The SSID `ssid`, or `None` if it is longer than `MAX_SSID_LENGTH`
+    pub fn new(ssid: &[u8]) -> Option<Self> {
+        if ssid.len() > MAX_SSID_LENGTH {
+            return None;
+        }
+        let mut bytes = [0; MAX_SSID_LENGTH];
+        bytes[..ssid.len()].copy_from_slice(ssid);
+        Some(Ssid {
+            len: ssid.len() as u8,
+            ssid: bytes,
+        })
+    }
+
+    /// The bytes of the SSID, which are usually, but not always, UTF-8
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.ssid[..usize::from(self.len).min(MAX_SSID_LENGTH)]
+    }
+}
+
+impl fmt::Debug for Ssid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match core::str::from_utf8(self.as_bytes()) {
+            Ok(ssid) => write!(f, "{:?}", ssid),
+            Err(_) => write!(f, "{:x?}", self.as_bytes()),
+        }
+    }
+}
+
+newtype_enum! {
+    /// Kind of network (`EFI_80211_BSS_TYPE`)
+    pub enum BssType: u32 => {
+        /// Network with access points
+        INFRASTRUCTURE = 0,
+        /// Ad hoc network
+        INDEPENDENT = 1,
+        /// Mesh network
+        MESH = 2,
+        /// Any kind, when scanning
+        ANY = 3,
+    }
+}
+
+/// An authentication or cipher suite (`EFI_80211_SUITE_SELECTOR`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SuiteSelector {
+    /// Organization which defines the suite
+    pub oui: [u8; 3],
+    /// Number of the suite within the organization
+    pub suite_type: u8,
+}
+
+impl SuiteSelector {
+    /// OUI of the suites defined by IEEE 802.11
+    pub const IEEE_OUI: [u8; 3] = [0x00, 0x0f, 0xac];
+
+    /// Authentication with a pre-shared key, used by WPA2-Personal
+    pub const AKM_PSK: Self = Self::ieee(2);
+    /// Authentication with 802.1X, used by WPA2-Enterprise
+    pub const AKM_8021X: Self = Self::ieee(1);
+    /// Simultaneous Authentication of Equals, used by WPA3-Personal
+    pub const AKM_SAE: Self = Self::ieee(8);
+
+    /// TKIP cipher, used by WPA
+    pub const CIPHER_TKIP: Self = Self::ieee(2);
+    /// CCMP-128 cipher, used by WPA2
+    pub const CIPHER_CCMP: Self = Self::ieee(4);
+
+    const fn ieee(suite_type: u8) -> Self {
+        SuiteSelector {
+            oui: Self::IEEE_OUI,
+            suite_type,
+        }
+    }
+}
+
+/// A list of suites (`EFI_80211_AKM_SUITE_SELECTOR` or
+/// `EFI_80211_CIPHER_SUITE_SELECTOR`)
+#[repr(C)]
+struct SuiteList {
+    count: u16,
+    // Followed by the suites
+}
+
+impl SuiteList {
+    /// The suites of the list at `list`, which may be null
+    unsafe fn suites<'a>(list: *const SuiteList) -> &'a [SuiteSelector] {
+        if list.is_null() {
+            return &[];
+        }
+        let suites = list.cast::<u8>().add(mem::size_of::<u16>());
+        slice::from_raw_parts(suites.cast(), (*list).count.into())
+    }
+}
+
+/// A network (`EFI_80211_NETWORK`)
+#[repr(C)]
+pub struct Network {
+    /// Kind of network
+    pub bss_type: BssType,
+    /// Name of the network
+    pub ssid: Ssid,
+    akm_suite: *const SuiteList,
+    cipher_suite: *const SuiteList,
+}
+
+impl Network {
+    /// The authentication suites supported by the network
+    pub fn akm_suites(&self) -> &[SuiteSelector] {
+        unsafe { SuiteList::suites(self.akm_suite) }
+    }
+
+    /// The cipher suites supported by the network
+    pub fn cipher_suites(&self) -> &[SuiteSelector] {
+        unsafe { SuiteList::suites(self.cipher_suite) }
+    }
+
+    /// Whether the network can be joined with a WPA2 password
+    pub fn is_wpa2_psk(&self) -> bool {
+        self.akm_suites().contains(&SuiteSelector::AKM_PSK)
+            && self.cipher_suites().contains(&SuiteSelector::CIPHER_CCMP)
+    }
+
+    /// Whether the network does not require authentication
+    pub fn is_open(&self) -> bool {
+        self.akm_suites().is_empty()
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Network")
+            .field("bss_type", &self.bss_type)
+            .field("ssid", &self.ssid)
+            .field("akm_suites", &self.akm_suites())
+            .field("cipher_suites", &self.cipher_suites())
+            .finish()
+    }
+}
+
+/// A network found by a scan (`EFI_80211_NETWORK_DESCRIPTION`)
+#[repr(C)]
+#[derive(Debug)]
+pub struct NetworkDescription {
+    /// The network
+    pub network: Network,
+    /// Quality of the signal, from 0 to 100
+    pub network_quality: u8,
+}
+
+/// The networks found by a scan, in memory which is freed when they are
+/// dropped
+pub struct ScanResult<'boot> {
+    bt: &'boot BootServices,
+    /// `EFI_80211_GET_NETWORKS_RESULT`: the number of networks as a `u8`,
+    /// followed by the aligned array of networks
+    result: *mut u8,
+}
+
+impl<'boot> ScanResult<'boot> {
+    /// The networks which were found
+    pub fn networks(&self) -> &[NetworkDescription] {
+        if self.result.is_null() {
+            return &[];
+        }
+        unsafe {
+            let count = *self.result;
+            let networks = self.result.add(mem::align_of::<NetworkDescription>());
+            slice::from_raw_parts(networks.cast(), count.into())
+        }
+    }
+}
+
+impl fmt::Debug for ScanResult<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.networks()).finish()
+    }
+}
+
+impl Drop for ScanResult<'_> {
+    fn drop(&mut self) {
+        if !self.result.is_null() {
+            // Nothing can be done if the memory cannot be freed
+            let _ = self.bt.free_pool(self.result);
+        }
+    }
+}
+
+newtype_enum! {
+    /// Outcome of a connection (`EFI_80211_CONNECT_NETWORK_RESULT_CODE`)
+    pub enum ConnectResult: u32 => {
+        /// The interface is connected
+        SUCCESS = 0,
+        /// The network refused the connection
+        REFUSED = 1,
+        /// The connection failed
+        FAILED = 2,
+        /// The connection did not complete in time
+        FAILURE_TIMEOUT = 3,
+        /// The connection failed for an unknown reason
+        FAILED_REASON_UNSPECIFIED = 4,
+    }
+}
+
+/// `EFI_80211_GET_NETWORKS_TOKEN`
+#[repr(C)]
+struct GetNetworksToken {
+    event: Event,
+    status: Status,
+    data: *const u32,
+    result: *mut u8,
+}
+
+/// `EFI_80211_CONNECT_NETWORK_DATA`
+#[repr(C)]
+struct ConnectNetworkData {
+    network: *const Network,
+    failure_timeout: u32,
+}
+
+/// `EFI_80211_CONNECT_NETWORK_TOKEN`
+#[repr(C)]
+struct ConnectNetworkToken {
+    event: Event,
+    status: Status,
+    data: *const ConnectNetworkData,
+    result_code: ConnectResult,
+}
+
+/// `EFI_80211_DISCONNECT_NETWORK_TOKEN`
+#[repr(C)]
+struct DisconnectNetworkToken {
+    event: Event,
+    status: Status,
+}
+
+/// Largest number of SSIDs which are scanned for
+const MAX_SCANNED_SSIDS: usize = 8;
+
+/// `EFI_80211_GET_NETWORKS_DATA`
+#[repr(C)]
+struct GetNetworksData {
+    num_of_ssid: u32,
+    ssid_list: [Ssid; MAX_SCANNED_SSIDS],
+}
+
+/// Start an operation, which signals the event it is given once it is
+/// finished, and wait for it.
+fn run(bt: &BootServices, start: impl FnOnce(Event) -> Status) -> Result {
+    let event = unsafe { bt.create_event(EventType::empty(), Tpl::CALLBACK, None) }?.log();
+    let status = start(event);
+    let result = if status.is_error() {
+        Err(status.into())
+    } else {
+        bt.wait_for_event(&mut [event])
+            .map_err(|err| err.status().into())
+            .map(|completion| completion.map(|_| ()))
+    };
+    // The event is not used anymore, whatever happened
+    let _ = bt.close_event(event);
+    result
+}
+
+/// The Wireless MAC Connection II protocol.
+#[repr(C)]
+#[unsafe_guid("1b0fb9bf-699d-4fdd-a7c3-2546681bf63b")]
+#[derive(Protocol)]
+pub struct WirelessMacConnection2 {
+    get_networks: unsafe extern "efiapi" fn(
+        this: *mut WirelessMacConnection2,
+        token: *mut GetNetworksToken,
+    ) -> Status,
+    connect_network: unsafe extern "efiapi" fn(
+        this: *mut WirelessMacConnection2,
+        token: *mut ConnectNetworkToken,
+    ) -> Status,
+    disconnect_network: unsafe extern "efiapi" fn(
+        this: *mut WirelessMacConnection2,
+        token: *mut DisconnectNetworkToken,
+    ) -> Status,
+}
+
+impl WirelessMacConnection2 {
+    /// Scan for networks, and return the ones which were found.
+    ///
+    /// The networks whose SSIDs are in `ssids` are looked for even if they
+    /// hide their SSIDs. Up to 8 SSIDs can be given.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  More than 8 SSIDs were given
+    /// * `uefi::Status::NOT_FOUND`          No network was found
+    /// * `uefi::Status::ACCESS_DENIED`      Another scan is in progress
+    pub fn get_networks<'boot>(
+        &mut self,
+        bt: &'boot BootServices,
+        ssids: &[Ssid],
+    ) -> Result<ScanResult<'boot>> {
+        if ssids.len() > MAX_SCANNED_SSIDS {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        let mut data = GetNetworksData {
+            num_of_ssid: ssids.len() as u32,
+            ssid_list: [Ssid::new(&[]).unwrap(); MAX_SCANNED_SSIDS],
+        };
+        data.ssid_list[..ssids.len()].copy_from_slice(ssids);
+        let mut token = GetNetworksToken {
+            event: unsafe { mem::zeroed() },
+            status: Status::SUCCESS,
+            data: &data.num_of_ssid,
+            result: ptr::null_mut(),
+        };
+        let completion = run(bt, |event| {
+            token.event = event;
+            unsafe { (self.get_networks)(self, &mut token) }
+        })?;
+        let result = ScanResult {
+            bt,
+            result: token.result,
+        };
+        token
+            .status
+            .into_with(|| result, |_| ())
+            .map(|scan| scan.with_status(completion.status()))
+    }
+
+    /// Connect to `network`, usually one which was found by `get_networks`,
+    /// giving up after `failure_timeout` seconds.
+    ///
+    /// The credentials must have been given to the supplicant first.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::PROTOCOL_ERROR`     The connection failed, for the
+    ///                                      returned reason
+    /// * `uefi::Status::NOT_FOUND`          The network was not found
+    /// * `uefi::Status::UNSUPPORTED`        The interface does not support the
+    ///                                      suites of the network
+    pub fn connect_network(
+        &mut self,
+        bt: &BootServices,
+        network: &Network,
+        failure_timeout: u32,
+    ) -> Result<(), ConnectResult> {
+        let data = ConnectNetworkData {
+            network,
+            failure_timeout,
+        };
+        let mut token = ConnectNetworkToken {
+            event: unsafe { mem::zeroed() },
+            status: Status::SUCCESS,
+            data: &data,
+            result_code: ConnectResult::FAILED_REASON_UNSPECIFIED,
+        };
+        let completion = run(bt, |event| {
+            token.event = event;
+            unsafe { (self.connect_network)(self, &mut token) }
+        })
+        .map_err(|err| Error::new(err.status(), ConnectResult::FAILED_REASON_UNSPECIFIED))?;
+        if token.status.is_error() {
+            Err(Error::new(token.status, token.result_code))
+        } else if token.result_code != ConnectResult::SUCCESS {
+            Err(Error::new(Status::PROTOCOL_ERROR, token.result_code))
+        } else {
+            Ok(completion)
+        }
+    }
+
+    /// Disconnect from the current network.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  The interface is not connected
+    pub fn disconnect_network(&mut self, bt: &BootServices) -> Result {
+        let mut token = DisconnectNetworkToken {
+            event: unsafe { mem::zeroed() },
+            status: Status::SUCCESS,
+        };
+        let completion = run(bt, |event| {
+            token.event = event;
+            unsafe { (self.disconnect_network)(self, &mut token) }
+        })?;
+        token.status.into_with_val(|| ()).map(|_| completion)
+    }
+}
+
+newtype_enum! {
+    /// Kind of data held by the supplicant (`EFI_SUPPLICANT_DATA_TYPE`)
+    pub enum SupplicantDataType: u32 => {
+        /// Authentication suite, as a `SuiteSelector`
+        AKM_SUITE = 0,
+        /// Group cipher suite, as a `SuiteSelector`
+        GROUP_DATA_CIPHER_SUITE = 1,
+        /// Pairwise cipher suite, as a `SuiteSelector`
+        PAIRWISE_CIPHER_SUITE = 2,
+        /// Password of the network, as a nul-terminated ASCII string
+        PSK_PASSWORD = 3,
+        /// SSID of the network, as an `Ssid`
+        TARGET_SSID_NAME = 4,
+        /// MAC address of the interface
+        STATION_MAC = 5,
+        /// MAC address of the access point
+        TARGET_SSID_MAC = 6,
+        /// Pairwise transient key
+        PTK = 7,
+        /// Group temporal key
+        GTK = 8,
+        /// State of the supplicant
+        STATE = 9,
+        /// State of the link
+        LINK_STATE = 10,
+        /// Whether keys are being refreshed
+        KEY_REFRESH = 11,
+        /// Authentication suites supported by the supplicant
+        SUPPORTED_AKM_SUITES = 12,
+        /// Cipher suites implemented by the supplicant
+        SUPPORTED_SOFTWARE_CIPHER_SUITES = 13,
+        /// Cipher suites implemented by the hardware
+        SUPPORTED_HARDWARE_CIPHER_SUITES = 14,
+        /// Integrity group temporal key
+        IGTK = 15,
+        /// Pairwise master key
+        PMK = 16,
+    }
+}
+
+/// Shortest WPA2 password
+pub const MIN_PSK_PASSWORD_LENGTH: usize = 8;
+
+/// Longest WPA2 password
+pub const MAX_PSK_PASSWORD_LENGTH: usize = 63;
+
+/// The Supplicant protocol.
+#[repr(C)]
+#[unsafe_guid("54fcc43e-aa89-4333-9a85-cdea24051e9e")]
+#[derive(Protocol)]
+pub struct Supplicant {
+    build_response_packet: usize,
+    process_packet: usize,
+    set_data: unsafe extern "efiapi" fn(
+        this: *mut Supplicant,
+        data_type: SupplicantDataType,
+        data: *const c_void,
+        data_size: usize,
+    ) -> Status,
+    get_data: unsafe extern "efiapi" fn(
+        this: *mut Supplicant,
+        data_type: SupplicantDataType,
+        data: *mut c_void,
+        data_size: *mut usize,
+    ) -> Status,
+}
+
+impl Supplicant {
+    /// Set data of the supplicant, in the format of `data_type`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `data` is invalid
+    /// * `uefi::Status::UNSUPPORTED`        The data cannot be set
+    pub fn set_data(&mut self, data_type: SupplicantDataType, data: &[u8]) -> Result {
+        unsafe { (self.set_data)(self, data_type, data.as_ptr().cast(), data.len()) }.into()
+    }
+
+    /// Read data of the supplicant, in the format of `data_type`, into
+    /// `buffer`, and return it.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  `buffer` is too small; the required
+    ///                                     size is returned
+    /// * `uefi::Status::NOT_FOUND`         The data is not available
+    /// * `uefi::Status::UNSUPPORTED`       The data cannot be read
+    pub fn get_data<'buf>(
+        &mut self,
+        data_type: SupplicantDataType,
+        buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], Option<usize>> {
+        let mut size = buffer.len();
+        let status =
+            unsafe { (self.get_data)(self, data_type, buffer.as_mut_ptr().cast(), &mut size) };
+        if status == Status::BUFFER_TOO_SMALL {
+            Err(Error::new(status, Some(size)))
+        } else {
+            status.into_with(move || &buffer[..size.min(buffer.len())], |_| None)
+        }
+    }
+
+    /// Set the password of the WPA2-PSK network to connect to.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The password is not 8 to 63
+    ///                                      printable ASCII characters
+    pub fn set_psk_password(&mut self, password: &str) -> Result {
+        let len = password.len();
+        if !(MIN_PSK_PASSWORD_LENGTH..=MAX_PSK_PASSWORD_LENGTH).contains(&len)
+            || !password.bytes().all(|byte| (0x20..0x7f).contains(&byte))
+        {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        let mut buffer = [0; MAX_PSK_PASSWORD_LENGTH + 1];
+        buffer[..len].copy_from_slice(password.as_bytes());
+        self.set_data(SupplicantDataType::PSK_PASSWORD, &buffer[..=len])
+    }
+
+    /// Set the SSID of the network to connect to.
+    pub fn set_target_ssid(&mut self, ssid: &Ssid) -> Result {
+        let data = unsafe {
+            slice::from_raw_parts((ssid as *const Ssid).cast::<u8>(), mem::size_of::<Ssid>())
+        };
+        self.set_data(SupplicantDataType::TARGET_SSID_NAME, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_layout() {
+        assert_eq!(mem::size_of::<Ssid>(), 33);
+        assert_eq!(mem::size_of::<Network>(), 56);
+        assert_eq!(mem::size_of::<NetworkDescription>(), 64);
+
+        let ssid = Ssid::new(b"Office").unwrap();
+        assert_eq!(ssid.as_bytes(), b"Office");
+        assert!(Ssid::new(&[b'a'; 33]).is_none());
+
+        // A list of two authentication suites, as the firmware lays it out
+        let akm: [u8; 10] = [2, 0, 0x00, 0x0f, 0xac, 2, 0x00, 0x0f, 0xac, 8];
+        let cipher: [u8; 6] = [1, 0, 0x00, 0x0f, 0xac, 4];
+        let network = Network {
+            bss_type: BssType::INFRASTRUCTURE,
+            ssid,
+            akm_suite: akm.as_ptr().cast(),
+            cipher_suite: cipher.as_ptr().cast(),
+        };
+        assert_eq!(
+            network.akm_suites(),
+            [SuiteSelector::AKM_PSK, SuiteSelector::AKM_SAE]
+        );
+        assert_eq!(network.cipher_suites(), [SuiteSelector::CIPHER_CCMP]);
+        assert!(network.is_wpa2_psk());
+        assert!(!network.is_open());
+
+        let open = Network {
+            akm_suite: ptr::null(),
+            cipher_suite: ptr::null(),
+            ..network
+        };
+        assert!(open.is_open());
+        assert!(!open.is_wpa2_psk());
+    }
+}
@@ -0,0 +1,909 @@
+//! Typed views of the PCI configuration space.
+//!
+//! `ConfigSpace` is a snapshot of the first 256 bytes of the configuration
+//! space of a function, which decodes the common header, the BARs and the
+//! list of capabilities:
+//!
+//! ```no_run
+//! # use uefi::proto::pci::PciIo;
+//! # use uefi::proto::pci::config::ConfigSpace;
+//! # fn f(pci: &mut PciIo) -> uefi::Result {
+//! let config = ConfigSpace::read(pci)?.log();
+//! if let Some(msix) = config.msix() {
+//!     log::info!("{} MSI-X vectors in BAR {}", msix.table_size(), msix.table_bar());
+//! }
+//! let bars = uefi::proto::pci::config::probe_bars(pci, &config)?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use super::PciIo;
+use crate::Result;
+use bitflags::bitflags;
+
+/// Size of the configuration space of conventional PCI functions
+pub const CONFIG_SPACE_SIZE: usize = 256;
+
+/// Maximum number of BARs of a function
+pub const MAX_BARS: usize = 6;
+
+/// Offsets of the registers of the common header
+pub mod offset {
+    /// Vendor ID (16 bits)
+    pub const VENDOR_ID: u32 = 0x00;
+    /// Device ID (16 bits)
+    pub const DEVICE_ID: u32 = 0x02;
+    /// Command register (16 bits)
+    pub const COMMAND: u32 = 0x04;
+    /// Status register (16 bits)
+    pub const STATUS: u32 = 0x06;
+    /// Revision ID (8 bits)
+    pub const REVISION_ID: u32 = 0x08;
+    /// Programming interface, subclass and base class (24 bits)
+    pub const CLASS_CODE: u32 = 0x09;
+    /// Cache line size, in units of 32 bits (8 bits)
+    pub const CACHE_LINE_SIZE: u32 = 0x0c;
+    /// Latency timer (8 bits)
+    pub const LATENCY_TIMER: u32 = 0x0d;
+    /// Header type (8 bits)
+    pub const HEADER_TYPE: u32 = 0x0e;
+    /// Built-in self test (8 bits)
+    pub const BIST: u32 = 0x0f;
+    /// First BAR (32 bits each)
+    pub const BAR0: u32 = 0x10;
+    /// Subsystem vendor ID of endpoints (16 bits)
+    pub const SUBSYSTEM_VENDOR_ID: u32 = 0x2c;
+    /// Subsystem ID of endpoints (16 bits)
+    pub const SUBSYSTEM_ID: u32 = 0x2e;
+    /// Pointer to the first capability, for endpoints and bridges (8 bits)
+    pub const CAPABILITIES_POINTER: u32 = 0x34;
+    /// Pointer to the first capability, for CardBus bridges (8 bits)
+    pub const CARDBUS_CAPABILITIES_POINTER: u32 = 0x14;
+    /// Interrupt line (8 bits)
+    pub const INTERRUPT_LINE: u32 = 0x3c;
+    /// Interrupt pin (8 bits)
+    pub const INTERRUPT_PIN: u32 = 0x3d;
+}
+
+bitflags! {
+    /// The command register
+    #[derive(Default)]
+    pub struct Command: u16 {
+        /// I/O BARs are decoded
+        const IO_SPACE = 0x0001;
+        /// Memory BARs are decoded
+        const MEMORY_SPACE = 0x0002;
+        /// The function may perform DMA
+        const BUS_MASTER = 0x0004;
+        /// Special cycles are monitored
+        const SPECIAL_CYCLES = 0x0008;
+        /// Memory Write and Invalidate may be used
+        const MEMORY_WRITE_INVALIDATE = 0x0010;
+        /// VGA palette writes are snooped
+        const VGA_PALETTE_SNOOP = 0x0020;
+        /// Parity errors are reported
+        const PARITY_ERROR_RESPONSE = 0x0040;
+        /// System errors are reported
+        const SERR = 0x0100;
+        /// Fast back-to-back transactions may be used
+        const FAST_BACK_TO_BACK = 0x0200;
+        /// Legacy INTx interrupts are disabled
+        const INTERRUPT_DISABLE = 0x0400;
+    }
+}
+
+bitflags! {
+    /// The status register
+    #[derive(Default)]
+    pub struct StatusRegister: u16 {
+        /// A legacy INTx interrupt is pending
+        const INTERRUPT = 0x0008;
+        /// The function has a list of capabilities
+        const CAPABILITIES_LIST = 0x0010;
+        /// The function supports 66 MHz operation
+        const CAPABLE_66MHZ = 0x0020;
+        /// The function supports fast back-to-back transactions
+        const FAST_BACK_TO_BACK = 0x0080;
+        /// A parity error occurred while the function was bus master
+        const MASTER_DATA_PARITY_ERROR = 0x0100;
+        /// The function aborted a transaction as target
+        const SIGNALED_TARGET_ABORT = 0x0800;
+        /// A transaction of the function was aborted by its target
+        const RECEIVED_TARGET_ABORT = 0x1000;
+        /// A transaction of the function was aborted by the master
+        const RECEIVED_MASTER_ABORT = 0x2000;
+        /// The function signaled a system error
+        const SIGNALED_SYSTEM_ERROR = 0x4000;
+        /// The function detected a parity error
+        const DETECTED_PARITY_ERROR = 0x8000;
+    }
+}
+
+newtype_enum! {
+    /// Layout of the configuration space after the common header
+    pub enum HeaderType: u8 => {
+        /// Endpoint
+        ENDPOINT = 0,
+        /// PCI-to-PCI bridge
+        PCI_BRIDGE = 1,
+        /// CardBus bridge
+        CARDBUS_BRIDGE = 2,
+    }
+}
+
+newtype_enum! {
+    /// Identifier of a capability
+    pub enum CapabilityId: u8 => {
+        /// Power management
+        POWER_MANAGEMENT = 0x01,
+        /// Accelerated Graphics Port
+        AGP = 0x02,
+        /// Vital product data
+        VPD = 0x03,
+        /// Slot identification
+        SLOT_ID = 0x04,
+        /// Message Signaled Interrupts
+        MSI = 0x05,
+        /// CompactPCI hot swap
+        HOT_SWAP = 0x06,
+        /// PCI-X
+        PCIX = 0x07,
+        /// HyperTransport
+        HYPERTRANSPORT = 0x08,
+        /// Vendor-specific
+        VENDOR_SPECIFIC = 0x09,
+        /// Debug port
+        DEBUG_PORT = 0x0a,
+        /// Subsystem IDs of bridges
+        BRIDGE_SUBSYSTEM_VENDOR_ID = 0x0d,
+        /// PCI Express
+        PCI_EXPRESS = 0x10,
+        /// Extended Message Signaled Interrupts
+        MSIX = 0x11,
+        /// Serial ATA data and index configuration
+        SATA = 0x12,
+        /// Advanced features
+        ADVANCED_FEATURES = 0x13,
+    }
+}
+
+newtype_enum! {
+    /// Role of a PCI Express function in the hierarchy
+    pub enum PcieDeviceType: u8 => {
+        /// Endpoint
+        ENDPOINT = 0x0,
+        /// Legacy endpoint
+        LEGACY_ENDPOINT = 0x1,
+        /// Root port of a root complex
+        ROOT_PORT = 0x4,
+        /// Upstream port of a switch
+        UPSTREAM_PORT = 0x5,
+        /// Downstream port of a switch
+        DOWNSTREAM_PORT = 0x6,
+        /// PCI Express to PCI bridge
+        PCIE_TO_PCI_BRIDGE = 0x7,
+        /// PCI to PCI Express bridge
+        PCI_TO_PCIE_BRIDGE = 0x8,
+        /// Endpoint integrated in the root complex
+        ROOT_COMPLEX_INTEGRATED_ENDPOINT = 0x9,
+        /// Event collector of the root complex
+        ROOT_COMPLEX_EVENT_COLLECTOR = 0xa,
+    }
+}
+
+/// Class code of a function
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClassCode {
+    /// Base class, like 0x01 for mass storage controllers
+    pub base: u8,
+    /// Subclass, like 0x08 for NVM controllers
+    pub sub: u8,
+    /// Programming interface, like 0x02 for NVM Express
+    pub prog_if: u8,
+}
+
+/// Snapshot of the configuration space of a function
+#[derive(Clone)]
+pub struct ConfigSpace {
+    data: [u8; CONFIG_SPACE_SIZE],
+}
+
+impl ConfigSpace {
+    /// Wrap a copy of the configuration space
+    pub fn from_bytes(data: [u8; CONFIG_SPACE_SIZE]) -> Self {
+        ConfigSpace { data }
+    }
+
+    /// Read the configuration space of the function of `pci`.
+    pub fn read(pci: &mut PciIo) -> Result<Self> {
+        let mut data = [0; CONFIG_SPACE_SIZE];
+        pci.read_config_bytes(0, &mut data)?.log();
+        Ok(Self::from_bytes(data).into())
+    }
+
+    /// The raw bytes of the configuration space
+    pub fn as_bytes(&self) -> &[u8; CONFIG_SPACE_SIZE] {
+        &self.data
+    }
+
+    /// The byte at `offset`
+    pub fn read_u8(&self, offset: u8) -> u8 {
+        self.data[usize::from(offset)]
+    }
+
+    /// The 16-bit register at `offset`, which wraps around at the end of
+    /// the configuration space
+    pub fn read_u16(&self, offset: u8) -> u16 {
+        u16::from_le_bytes([self.read_u8(offset), self.read_u8(offset.wrapping_add(1))])
+    }
+
+    /// The 32-bit register at `offset`, which wraps around at the end of
+    /// the configuration space
+    pub fn read_u32(&self, offset: u8) -> u32 {
+        u32::from(self.read_u16(offset)) | u32::from(self.read_u16(offset.wrapping_add(2))) << 16
+    }
+
+    /// Vendor ID, which is 0xffff if there is no function
+    pub fn vendor_id(&self) -> u16 {
+        self.read_u16(offset::VENDOR_ID as u8)
+    }
+
+    /// Device ID
+    pub fn device_id(&self) -> u16 {
+        self.read_u16(offset::DEVICE_ID as u8)
+    }
+
+    /// The command register
+    pub fn command(&self) -> Command {
+        Command::from_bits_truncate(self.read_u16(offset::COMMAND as u8))
+    }
+
+    /// The status register
+    pub fn status(&self) -> StatusRegister {
+        StatusRegister::from_bits_truncate(self.read_u16(offset::STATUS as u8))
+    }
+
+    /// Revision ID
+    pub fn revision_id(&self) -> u8 {
+        self.read_u8(offset::REVISION_ID as u8)
+    }
+
+    /// Class code
+    pub fn class_code(&self) -> ClassCode {
+        let offset = offset::CLASS_CODE as u8;
+        ClassCode {
+            prog_if: self.read_u8(offset),
+            sub: self.read_u8(offset + 1),
+            base: self.read_u8(offset + 2),
+        }
+    }
+
+    /// Layout of the rest of the configuration space
+    pub fn header_type(&self) -> HeaderType {
+        HeaderType(self.read_u8(offset::HEADER_TYPE as u8) & 0x7f)
+    }
+
+    /// Whether the device has other functions than function 0
+    pub fn is_multi_function(&self) -> bool {
+        self.read_u8(offset::HEADER_TYPE as u8) & 0x80 != 0
+    }
+
+    /// Subsystem vendor ID and subsystem ID, for endpoints
+    pub fn subsystem(&self) -> Option<(u16, u16)> {
+        if self.header_type() == HeaderType::ENDPOINT {
+            Some((
+                self.read_u16(offset::SUBSYSTEM_VENDOR_ID as u8),
+                self.read_u16(offset::SUBSYSTEM_ID as u8),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Interrupt line and pin, the pin being 0 if legacy interrupts are not
+    /// used, and 1 to 4 for INTA# to INTD#
+    pub fn interrupt(&self) -> (u8, u8) {
+        (
+            self.read_u8(offset::INTERRUPT_LINE as u8),
+            self.read_u8(offset::INTERRUPT_PIN as u8),
+        )
+    }
+
+    /// Number of BAR registers of the layout of the header
+    pub fn bar_count(&self) -> usize {
+        match self.header_type() {
+            HeaderType::ENDPOINT => 6,
+            HeaderType::PCI_BRIDGE => 2,
+            _ => 0,
+        }
+    }
+
+    /// The raw BAR register `index`, or `None` if the layout of the header
+    /// has fewer BARs
+    pub fn raw_bar(&self, index: usize) -> Option<u32> {
+        if index < self.bar_count() {
+            Some(self.read_u32(offset::BAR0 as u8 + 4 * index as u8))
+        } else {
+            None
+        }
+    }
+
+    /// The BARs, decoded without their size
+    ///
+    /// Registers which read as zero are skipped: they are either not
+    /// implemented, or not assigned. Use `probe_bars` to tell them apart
+    /// and to know the size of the ranges.
+    pub fn bars(&self) -> impl Iterator<Item = Bar> + '_ {
+        let mut index = 0;
+        core::iter::from_fn(move || {
+            while let Some(low) = self.raw_bar(index) {
+                let high = self.raw_bar(index + 1).unwrap_or(0);
+                let bar = Bar::decode(index as u8, low, high);
+                index += bar.slots();
+                if low != 0 {
+                    return Some(bar);
+                }
+            }
+            None
+        })
+    }
+
+    /// The list of capabilities
+    pub fn capabilities(&self) -> Capabilities<'_> {
+        let pointer = if !self.status().contains(StatusRegister::CAPABILITIES_LIST) {
+            0
+        } else if self.header_type() == HeaderType::CARDBUS_BRIDGE {
+            self.read_u8(offset::CARDBUS_CAPABILITIES_POINTER as u8)
+        } else {
+            self.read_u8(offset::CAPABILITIES_POINTER as u8)
+        };
+        Capabilities {
+            config: self,
+            next: pointer,
+            remaining: MAX_CAPABILITIES,
+        }
+    }
+
+    /// The first capability with identifier `id`
+    pub fn find_capability(&self, id: CapabilityId) -> Option<Capability> {
+        self.capabilities().find(|capability| capability.id == id)
+    }
+
+    /// The MSI capability, if the function supports MSI
+    pub fn msi(&self) -> Option<MsiCapability> {
+        self.find_capability(CapabilityId::MSI)
+            .map(|capability| MsiCapability {
+                offset: capability.offset,
+                control: self.read_u16(capability.offset.wrapping_add(2)),
+            })
+    }
+
+    /// The MSI-X capability, if the function supports MSI-X
+    pub fn msix(&self) -> Option<MsixCapability> {
+        self.find_capability(CapabilityId::MSIX)
+            .map(|capability| MsixCapability {
+                offset: capability.offset,
+                control: self.read_u16(capability.offset.wrapping_add(2)),
+                table: self.read_u32(capability.offset.wrapping_add(4)),
+                pba: self.read_u32(capability.offset.wrapping_add(8)),
+            })
+    }
+
+    /// The PCI Express capability, if the function is a PCI Express function
+    pub fn pcie(&self) -> Option<PcieCapability> {
+        self.find_capability(CapabilityId::PCI_EXPRESS)
+            .map(|capability| PcieCapability {
+                offset: capability.offset,
+                capabilities: self.read_u16(capability.offset.wrapping_add(2)),
+                device_capabilities: self.read_u32(capability.offset.wrapping_add(4)),
+                link_capabilities: self.read_u32(capability.offset.wrapping_add(0x0c)),
+            })
+    }
+}
+
+impl core::fmt::Debug for ConfigSpace {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("ConfigSpace")
+            .field("vendor_id", &self.vendor_id())
+            .field("device_id", &self.device_id())
+            .field("class_code", &self.class_code())
+            .field("header_type", &self.header_type())
+            .finish()
+    }
+}
+
+/// Kind of range decoded by a BAR
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BarKind {
+    /// Range of I/O ports
+    Io,
+    /// Memory range below 4 GiB
+    Memory32,
+    /// Memory range anywhere in the 64-bit address space, whose BAR takes
+    /// two registers
+    Memory64,
+}
+
+/// A decoded BAR
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Bar {
+    /// Index of the (first) register of the BAR, which is the index to give
+    /// to the memory and I/O functions of `PciIo`
+    pub index: u8,
+    /// Kind of range
+    pub kind: BarKind,
+    /// Base address of the range
+    pub address: u64,
+    /// Whether reads of the range have no side effects
+    pub prefetchable: bool,
+    /// Size of the range, if it was probed
+    pub size: Option<u64>,
+}
+
+impl Bar {
+    /// Decode the BAR `index` from its register, `low`, and from the next
+    /// register, `high`, which is only used by 64-bit memory BARs.
+    pub fn decode(index: u8, low: u32, high: u32) -> Self {
+        if low & 1 != 0 {
+            Bar {
+                index,
+                kind: BarKind::Io,
+                address: u64::from(low & !0x3),
+                prefetchable: false,
+                size: None,
+            }
+        } else {
+            let (kind, address) = if (low >> 1) & 0x3 == 0x2 {
+                (BarKind::Memory64, u64::from(high) << 32)
+            } else {
+                (BarKind::Memory32, 0)
+            };
+            Bar {
+                index,
+                kind,
+                address: address | u64::from(low & !0xf),
+                prefetchable: low & 0x8 != 0,
+                size: None,
+            }
+        }
+    }
+
+    /// Number of registers taken by the BAR
+    pub fn slots(&self) -> usize {
+        if self.kind == BarKind::Memory64 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Size of the range, from the values read back from the registers after
+    /// all ones were written to them, or `None` if the BAR is not
+    /// implemented.
+    pub fn size_from_mask(&self, low: u32, high: u32) -> Option<u64> {
+        let (mask, implemented) = match self.kind {
+            BarKind::Io => {
+                let mask = low & !0x3;
+                // The upper 16 bits of the address are optional
+                let upper = if mask & 0xffff_0000 == 0 {
+                    0xffff_ffff_ffff_0000
+                } else {
+                    0xffff_ffff_0000_0000
+                };
+                (upper | u64::from(mask), mask != 0)
+            }
+            BarKind::Memory32 => {
+                let mask = low & !0xf;
+                (0xffff_ffff_0000_0000 | u64::from(mask), mask != 0)
+            }
+            BarKind::Memory64 => {
+                let mask = u64::from(high) << 32 | u64::from(low & !0xf);
+                (mask, mask != 0)
+            }
+        };
+        if implemented {
+            Some((!mask).wrapping_add(1))
+        } else {
+            None
+        }
+    }
+}
+
+/// Decode and size the BARs of the function of `pci`, whose configuration
+/// space is `config`.
+///
+/// All ones are written to each register to find which address bits are
+/// implemented, and the original value is restored. Decoding is disabled in
+/// the command register meanwhile, so the device must not be in use. The
+/// returned array is indexed by register; the second register of 64-bit BARs
+/// is `None`, like unimplemented ones.
+///
+/// The PCI bus driver has already assigned the ranges, so this is mostly
+/// useful for diagnostics; drivers only need the sizes from `bars` to check
+/// that the device is the expected one.
+pub fn probe_bars(pci: &mut PciIo, config: &ConfigSpace) -> Result<[Option<Bar>; MAX_BARS]> {
+    let command = pci.read_config::<u16>(offset::COMMAND)?.log();
+    let decoding = Command::IO_SPACE | Command::MEMORY_SPACE;
+    pci.write_config(offset::COMMAND, command & !decoding.bits())?
+        .log();
+    let bars = probe_registers(pci, config);
+    // The command register is restored even if the probing failed
+    let restored = pci.write_config(offset::COMMAND, command);
+    let bars = bars?.log();
+    restored?.log();
+    Ok(bars.into())
+}
+
+/// Find which address bits of the BAR registers are implemented
+fn probe_registers(pci: &mut PciIo, config: &ConfigSpace) -> Result<[Option<Bar>; MAX_BARS]> {
+    let probe = |pci: &mut PciIo, index: usize| -> Result<u32> {
+        let offset = offset::BAR0 + 4 * index as u32;
+        let original = pci.read_config::<u32>(offset)?.log();
+        pci.write_config(offset, !0u32)?.log();
+        let mask = pci.read_config::<u32>(offset)?.log();
+        pci.write_config(offset, original)?.log();
+        Ok(mask.into())
+    };
+    let mut bars = [None; MAX_BARS];
+    let mut index = 0;
+    while let Some(low) = config.raw_bar(index) {
+        let high = config.raw_bar(index + 1).unwrap_or(0);
+        let mut bar = Bar::decode(index as u8, low, high);
+        let mask_low = probe(pci, index)?.log();
+        let mask_high = if bar.kind == BarKind::Memory64 {
+            probe(pci, index + 1)?.log()
+        } else {
+            0
+        };
+        // The type bits of unassigned BARs are only known after probing
+        if low == 0 {
+            bar = Bar::decode(index as u8, mask_low, mask_high);
+            bar.address = 0;
+        }
+        bar.size = bar.size_from_mask(mask_low, mask_high);
+        if bar.size.is_some() {
+            bars[index] = Some(bar);
+        }
+        index += bar.slots();
+    }
+    Ok(bars.into())
+}
+
+/// Maximum number of capabilities in the list, which prevents looping over
+/// a corrupted list forever
+const MAX_CAPABILITIES: u8 = 48;
+
+/// A capability in the configuration space
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Capability {
+    /// Identifier of the capability
+    pub id: CapabilityId,
+    /// Offset of the capability in the configuration space
+    pub offset: u8,
+}
+
+/// Iterator over the list of capabilities of a function
+#[derive(Debug, Clone)]
+pub struct Capabilities<'a> {
+    config: &'a ConfigSpace,
+    next: u8,
+    remaining: u8,
+}
+
+impl Iterator for Capabilities<'_> {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Capability> {
+        let offset = self.next & !0x3;
+        // Capabilities are after the common header
+        if offset < 0x40 || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.next = self.config.read_u8(offset + 1);
+        Some(Capability {
+            id: CapabilityId(self.config.read_u8(offset)),
+            offset,
+        })
+    }
+}
+
+/// The MSI capability
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MsiCapability {
+    offset: u8,
+    control: u16,
+}
+
+impl MsiCapability {
+    /// Offset of the capability in the configuration space
+    pub fn offset(&self) -> u8 {
+        self.offset
+    }
+
+    /// Whether MSI is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.control & 0x1 != 0
+    }
+
+    /// Number of vectors which the function can use
+    pub fn vectors_capable(&self) -> u8 {
+        1 << ((self.control >> 1) & 0x7).min(5)
+    }
+
+    /// Number of vectors which are enabled
+    pub fn vectors_enabled(&self) -> u8 {
+        1 << ((self.control >> 4) & 0x7).min(5)
+    }
+
+    /// Whether the message address is 64-bit
+    pub fn is_64bit(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    /// Whether vectors can be masked individually
+    pub fn per_vector_masking(&self) -> bool {
+        self.control & 0x100 != 0
+    }
+
+    /// Offset of the message control register in the configuration space
+    pub fn control_offset(&self) -> u8 {
+        self.offset + 2
+    }
+
+    /// Offset of the message address register in the configuration space
+    pub fn address_offset(&self) -> u8 {
+        self.offset + 4
+    }
+
+    /// Offset of the message data register in the configuration space
+    pub fn data_offset(&self) -> u8 {
+        if self.is_64bit() {
+            self.offset + 0x0c
+        } else {
+            self.offset + 0x08
+        }
+    }
+
+    /// Offset of the mask register in the configuration space, if vectors
+    /// can be masked individually
+    pub fn mask_offset(&self) -> Option<u8> {
+        if self.per_vector_masking() {
+            Some(self.data_offset() + 4)
+        } else {
+            None
+        }
+    }
+}
+
+/// The MSI-X capability
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MsixCapability {
+    offset: u8,
+    control: u16,
+    table: u32,
+    pba: u32,
+}
+
+impl MsixCapability {
+    /// Offset of the capability in the configuration space
+    pub fn offset(&self) -> u8 {
+        self.offset
+    }
+
+    /// Offset of the message control register in the configuration space
+    pub fn control_offset(&self) -> u8 {
+        self.offset + 2
+    }
+
+    /// Whether MSI-X is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.control & 0x8000 != 0
+    }
+
+    /// Whether all the vectors are masked
+    pub fn is_function_masked(&self) -> bool {
+        self.control & 0x4000 != 0
+    }
+
+    /// Number of entries of the table of vectors
+    pub fn table_size(&self) -> u16 {
+        (self.control & 0x7ff) + 1
+    }
+
+    /// Index of the BAR which contains the table of vectors
+    pub fn table_bar(&self) -> u8 {
+        (self.table & 0x7) as u8
+    }
+
+    /// Offset of the table of vectors in its BAR
+    pub fn table_offset(&self) -> u32 {
+        self.table & !0x7
+    }
+
+    /// Index of the BAR which contains the pending bit array
+    pub fn pba_bar(&self) -> u8 {
+        (self.pba & 0x7) as u8
+    }
+
+    /// Offset of the pending bit array in its BAR
+    pub fn pba_offset(&self) -> u32 {
+        self.pba & !0x7
+    }
+}
+
+/// The PCI Express capability
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PcieCapability {
+    offset: u8,
+    capabilities: u16,
+    device_capabilities: u32,
+    link_capabilities: u32,
+}
+
+impl PcieCapability {
+    /// Offset of the capability in the configuration space
+    pub fn offset(&self) -> u8 {
+        self.offset
+    }
+
+    /// Version of the capability structure
+    pub fn version(&self) -> u8 {
+        (self.capabilities & 0xf) as u8
+    }
+
+    /// Role of the function in the hierarchy
+    pub fn device_type(&self) -> PcieDeviceType {
+        PcieDeviceType(((self.capabilities >> 4) & 0xf) as u8)
+    }
+
+    /// Whether the port is connected to a slot
+    pub fn slot_implemented(&self) -> bool {
+        self.capabilities & 0x100 != 0
+    }
+
+    /// Largest payload of the transactions which the function supports, in
+    /// bytes
+    pub fn max_payload_size(&self) -> u16 {
+        128 << (self.device_capabilities & 0x7).min(5)
+    }
+
+    /// Fastest link speed supported, as an index in the supported link
+    /// speeds vector (1 for 2.5 GT/s, 2 for 5 GT/s, 3 for 8 GT/s...)
+    pub fn max_link_speed(&self) -> u8 {
+        (self.link_capabilities & 0xf) as u8
+    }
+
+    /// Widest link supported, in lanes
+    pub fn max_link_width(&self) -> u8 {
+        ((self.link_capabilities >> 4) & 0x3f) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An NVMe controller with a 64-bit BAR, an I/O BAR and three
+    /// capabilities
+    fn nvme() -> ConfigSpace {
+        let mut data = [0; CONFIG_SPACE_SIZE];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        put(0x00, &[0x86, 0x80, 0x53, 0x09]);
+        put(0x04, &0x0006u16.to_le_bytes());
+        put(0x06, &0x0010u16.to_le_bytes());
+        put(0x08, &[0x01, 0x02, 0x08, 0x01]);
+        put(0x0e, &[0x80]);
+        put(0x10, &0xfebf_000cu32.to_le_bytes());
+        put(0x14, &0x0000_0001u32.to_le_bytes());
+        put(0x18, &0x0000_c001u32.to_le_bytes());
+        put(0x2c, &[0x86, 0x80, 0x01, 0x37]);
+        put(0x34, &[0x50]);
+        put(0x3c, &[0x0b, 0x01]);
+        // MSI, 64-bit with masking and 4 vectors
+        put(0x50, &[0x05, 0x70]);
+        put(0x52, &0x0184u16.to_le_bytes());
+        // MSI-X, 32 vectors, table and PBA in BAR 0
+        put(0x70, &[0x11, 0x80]);
+        put(0x72, &0x001fu16.to_le_bytes());
+        put(0x74, &0x0000_2000u32.to_le_bytes());
+        put(0x78, &0x0000_3000u32.to_le_bytes());
+        // PCI Express endpoint, 256-byte payloads, 8 GT/s x4
+        put(0x80, &[0x10, 0x00]);
+        put(0x82, &0x0002u16.to_le_bytes());
+        put(0x84, &0x0000_0001u32.to_le_bytes());
+        put(0x8c, &0x0000_0043u32.to_le_bytes());
+        ConfigSpace::from_bytes(data)
+    }
+
+    #[test]
+    fn header() {
+        let config = nvme();
+        assert_eq!(config.vendor_id(), 0x8086);
+        assert_eq!(config.device_id(), 0x0953);
+        assert_eq!(
+            config.command(),
+            Command::MEMORY_SPACE | Command::BUS_MASTER
+        );
+        assert!(config.status().contains(StatusRegister::CAPABILITIES_LIST));
+        assert_eq!(config.revision_id(), 1);
+        assert_eq!(
+            config.class_code(),
+            ClassCode {
+                base: 0x01,
+                sub: 0x08,
+                prog_if: 0x02
+            }
+        );
+        assert_eq!(config.header_type(), HeaderType::ENDPOINT);
+        assert!(config.is_multi_function());
+        assert_eq!(config.subsystem(), Some((0x8086, 0x3701)));
+        assert_eq!(config.interrupt(), (0x0b, 1));
+    }
+
+    #[test]
+    fn bars() {
+        let config = nvme();
+        let mut bars = config.bars();
+        let bar = bars.next().unwrap();
+        assert_eq!((bar.index, bar.kind), (0, BarKind::Memory64));
+        assert_eq!(bar.address, 0x1_febf_0000);
+        assert!(bar.prefetchable);
+        assert_eq!(bar.size_from_mask(0xffff_c00c, 0xffff_ffff), Some(0x4000));
+        let bar = bars.next().unwrap();
+        assert_eq!((bar.index, bar.kind, bar.address), (2, BarKind::Io, 0xc000));
+        assert_eq!(bar.size_from_mask(0x0000_ffe1, 0), Some(0x20));
+        assert_eq!(bars.next(), None);
+
+        let bar = Bar::decode(3, 0, 0);
+        assert_eq!(bar.kind, BarKind::Memory32);
+        assert_eq!(bar.size_from_mask(0xfff0_0000, 0), Some(0x10_0000));
+        assert_eq!(bar.size_from_mask(0, 0), None);
+    }
+
+    #[test]
+    fn capabilities() {
+        let config = nvme();
+        let ids: [_; 3] = [
+            CapabilityId::MSI,
+            CapabilityId::MSIX,
+            CapabilityId::PCI_EXPRESS,
+        ];
+        assert!(config.capabilities().map(|c| c.id).eq(ids.iter().copied()));
+        assert_eq!(config.find_capability(CapabilityId::AGP), None);
+
+        let msi = config.msi().unwrap();
+        assert_eq!(msi.offset(), 0x50);
+        assert!(!msi.is_enabled());
+        assert_eq!(msi.vectors_capable(), 4);
+        assert_eq!(msi.data_offset(), 0x5c);
+        assert_eq!(msi.mask_offset(), Some(0x60));
+
+        let msix = config.msix().unwrap();
+        assert_eq!(msix.table_size(), 32);
+        assert_eq!((msix.table_bar(), msix.table_offset()), (0, 0x2000));
+        assert_eq!((msix.pba_bar(), msix.pba_offset()), (0, 0x3000));
+
+        let pcie = config.pcie().unwrap();
+        assert_eq!(pcie.version(), 2);
+        assert_eq!(pcie.device_type(), PcieDeviceType::ENDPOINT);
+        assert_eq!(pcie.max_payload_size(), 256);
+        assert_eq!((pcie.max_link_speed(), pcie.max_link_width()), (3, 4));
+    }
+
+    #[test]
+    fn corrupted_capabilities() {
+        let mut data = *nvme().as_bytes();
+        // A capability which points to itself
+        data[0x81] = 0x80;
+        assert_eq!(
+            ConfigSpace::from_bytes(data).capabilities().count(),
+            usize::from(MAX_CAPABILITIES)
+        );
+        // No list at all
+        data[0x06] = 0;
+        assert_eq!(ConfigSpace::from_bytes(data).capabilities().count(), 0);
+    }
+}
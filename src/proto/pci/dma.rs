@@ -0,0 +1,246 @@
+//! Memory for DMA transfers.
+//!
+//! Memory must be mapped before a device can access it, and the addresses
+//! seen by the device may differ from the addresses seen by the processor,
+//! when an IOMMU or bounce buffers are used. The types of this module keep
+//! the memory mapped exactly as long as they are alive:
+//!
+//! * `DmaBuffer` owns memory which the device and the processor both access,
+//!   like rings of descriptors;
+//! * `DmaMapping` borrows a buffer for a single transfer, so it cannot be
+//!   accessed by the processor until the transfer is over.
+//!
+//! ```no_run
+//! # use core::cell::UnsafeCell;
+//! # use uefi::proto::pci::PciIo;
+//! # use uefi::proto::pci::dma::DmaBuffer;
+//! # fn f(pci: &UnsafeCell<PciIo>) -> uefi::Result {
+//! let mut ring = DmaBuffer::new(pci, 4096)?.log();
+//! ring.fill(0);
+//! let address = ring.device_address();
+//! // Give `address` to the device
+//! # Ok(().into())
+//! # }
+//! ```
+
+use super::{Attributes, MapOperation, Mapping, PciIo};
+use crate::table::boot::{size_to_pages, MemoryType};
+use crate::{Result, Status};
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::slice;
+
+/// Map `len` bytes at `host_address` entirely, or not at all
+fn map_all(
+    pci: &UnsafeCell<PciIo>,
+    operation: MapOperation,
+    host_address: *mut u8,
+    len: usize,
+) -> Result<(u64, Mapping)> {
+    let pci = unsafe { &mut *pci.get() };
+    let (device_address, mapped_len, mapping) =
+        unsafe { pci.map(operation, host_address, len) }?.log();
+    if mapped_len < len {
+        // Part of the buffer would be out of reach of the device
+        let _ = pci.unmap(mapping);
+        return Err(Status::OUT_OF_RESOURCES.into());
+    }
+    Ok((device_address, mapping).into())
+}
+
+/// Memory shared by a device and the processor, which is unmapped and freed
+/// when it is dropped
+///
+/// The memory is allocated by `PciIo::allocate_buffer` and mapped with
+/// `MapOperation::BUS_MASTER_COMMON_BUFFER`, so both sides see the writes of
+/// the other, after `flush` for the writes of the device.
+pub struct DmaBuffer<'pci> {
+    pci: &'pci UnsafeCell<PciIo>,
+    host_address: *mut u8,
+    len: usize,
+    pages: usize,
+    device_address: u64,
+    mapping: Option<Mapping>,
+}
+
+impl<'pci> DmaBuffer<'pci> {
+    /// Allocate and map a buffer of `len` bytes, below 4 GiB.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The memory could not be allocated
+    ///                                     or mapped
+    pub fn new(pci: &'pci UnsafeCell<PciIo>, len: usize) -> Result<Self> {
+        Self::with_attributes(pci, len, Attributes::empty())
+    }
+
+    /// Allocate and map a buffer of `len` bytes, with `attributes` among
+    /// `Attributes::MEMORY_WRITE_COMBINE`, `MEMORY_CACHED` and
+    /// `DUAL_ADDRESS_CYCLE`, the latter allowing memory above 4 GiB.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       An attribute is not supported
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The memory could not be allocated
+    ///                                     or mapped
+    pub fn with_attributes(
+        pci: &'pci UnsafeCell<PciIo>,
+        len: usize,
+        attributes: Attributes,
+    ) -> Result<Self> {
+        let pages = size_to_pages(len.max(1));
+        let host_address = unsafe { &mut *pci.get() }
+            .allocate_buffer(MemoryType::BOOT_SERVICES_DATA, pages, attributes)?
+            .log();
+        let mut buffer = DmaBuffer {
+            pci,
+            host_address,
+            len,
+            pages,
+            device_address: 0,
+            mapping: None,
+        };
+        // The buffer is freed by its destructor if the mapping fails
+        let (device_address, mapping) = map_all(
+            pci,
+            MapOperation::BUS_MASTER_COMMON_BUFFER,
+            host_address,
+            len,
+        )?
+        .log();
+        buffer.device_address = device_address;
+        buffer.mapping = Some(mapping);
+        Ok(buffer.into())
+    }
+
+    /// Address of the buffer for the device
+    pub fn device_address(&self) -> u64 {
+        self.device_address
+    }
+
+    /// Address of the buffer for the processor
+    pub fn host_address(&self) -> *mut u8 {
+        self.host_address
+    }
+
+    /// Wait for the writes of the device to the buffer to complete.
+    pub fn flush(&self) -> Result {
+        unsafe { &mut *self.pci.get() }.flush()
+    }
+}
+
+impl Deref for DmaBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.host_address, self.len) }
+    }
+}
+
+impl DerefMut for DmaBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.host_address, self.len) }
+    }
+}
+
+impl Drop for DmaBuffer<'_> {
+    fn drop(&mut self) {
+        let pci = unsafe { &mut *self.pci.get() };
+        if let Some(mapping) = self.mapping.take() {
+            // The memory must not be freed while the device may still use it
+            if pci.unmap(mapping).is_err() {
+                return;
+            }
+        }
+        let _ = unsafe { pci.free_buffer(self.host_address, self.pages) };
+    }
+}
+
+/// A buffer mapped for a single DMA transfer, which is unmapped when it is
+/// dropped
+///
+/// The buffer stays borrowed while it is mapped, since the processor must
+/// not access it meanwhile, and the data written by the device may only
+/// reach it when it is unmapped.
+pub struct DmaMapping<'pci, 'buf> {
+    pci: &'pci UnsafeCell<PciIo>,
+    device_address: u64,
+    mapping: Option<Mapping>,
+    _buffer: PhantomData<&'buf [u8]>,
+}
+
+impl<'pci, 'buf> DmaMapping<'pci, 'buf> {
+    /// Map `buffer`, which the device reads from.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       The memory cannot be reached by
+    ///                                     the device
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The whole buffer could not be
+    ///                                     mapped
+    pub fn for_device_read(pci: &'pci UnsafeCell<PciIo>, buffer: &'buf [u8]) -> Result<Self> {
+        // The device does not write to the buffer with this operation
+        let host_address = buffer.as_ptr() as *mut u8;
+        Self::map(
+            pci,
+            MapOperation::BUS_MASTER_READ,
+            host_address,
+            buffer.len(),
+        )
+    }
+
+    /// Map `buffer`, which the device writes to.
+    ///
+    /// # Errors
+    ///
+    /// Same as `for_device_read`.
+    pub fn for_device_write(pci: &'pci UnsafeCell<PciIo>, buffer: &'buf mut [u8]) -> Result<Self> {
+        Self::map(
+            pci,
+            MapOperation::BUS_MASTER_WRITE,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+        )
+    }
+
+    fn map(
+        pci: &'pci UnsafeCell<PciIo>,
+        operation: MapOperation,
+        host_address: *mut u8,
+        len: usize,
+    ) -> Result<Self> {
+        let (device_address, mapping) = map_all(pci, operation, host_address, len)?.log();
+        Ok(DmaMapping {
+            pci,
+            device_address,
+            mapping: Some(mapping),
+            _buffer: PhantomData,
+        }
+        .into())
+    }
+
+    /// Address of the buffer for the device
+    pub fn device_address(&self) -> u64 {
+        self.device_address
+    }
+
+    /// End the transfer, reporting whether the data written by the device
+    /// could be committed to the buffer.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The data could not be committed
+    pub fn unmap(mut self) -> Result {
+        let mapping = self.mapping.take().unwrap();
+        unsafe { &mut *self.pci.get() }.unmap(mapping)
+    }
+}
+
+impl Drop for DmaMapping<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(mapping) = self.mapping.take() {
+            let _ = unsafe { &mut *self.pci.get() }.unmap(mapping);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! PCI I/O protocol.
+
+use crate::proto::Protocol;
+use crate::table::boot::MemoryType;
+use crate::{unsafe_guid, Result, Status};
+use bitflags::bitflags;
+use core::ffi::c_void;
+use core::{mem, ptr, slice};
+
+newtype_enum! {
+    /// Width of the accesses of an I/O operation
+    pub enum IoWidth: u32 => {
+        /// 8-bit accesses, to consecutive addresses
+        U8 = 0,
+        /// 16-bit accesses, to consecutive addresses
+        U16 = 1,
+        /// 32-bit accesses, to consecutive addresses
+        U32 = 2,
+        /// 64-bit accesses, to consecutive addresses
+        U64 = 3,
+        /// 8-bit accesses, all to the same address
+        FIFO_U8 = 4,
+        /// 16-bit accesses, all to the same address
+        FIFO_U16 = 5,
+        /// 32-bit accesses, all to the same address
+        FIFO_U32 = 6,
+        /// 64-bit accesses, all to the same address
+        FIFO_U64 = 7,
+        /// 8-bit accesses, with the same value written to consecutive
+        /// addresses
+        FILL_U8 = 8,
+        /// 16-bit accesses, with the same value written to consecutive
+        /// addresses
+        FILL_U16 = 9,
+        /// 32-bit accesses, with the same value written to consecutive
+        /// addresses
+        FILL_U32 = 10,
+        /// 64-bit accesses, with the same value written to consecutive
+        /// addresses
+        FILL_U64 = 11,
+    }
+}
+
+newtype_enum! {
+    /// Kind of DMA transfer for which memory is mapped
+    pub enum MapOperation: u32 => {
+        /// The device reads from system memory
+        BUS_MASTER_READ = 0,
+        /// The device writes to system memory
+        BUS_MASTER_WRITE = 1,
+        /// The device and the processor both access the memory
+        ///
+        /// Only memory allocated by `PciIo::allocate_buffer` can be mapped
+        /// this way.
+        BUS_MASTER_COMMON_BUFFER = 2,
+    }
+}
+
+newtype_enum! {
+    /// Operation on the attributes of a PCI controller
+    pub enum AttributesOperation: u32 => {
+        /// Return the current attributes
+        GET = 0,
+        /// Replace the current attributes
+        SET = 1,
+        /// Enable the given attributes
+        ENABLE = 2,
+        /// Disable the given attributes
+        DISABLE = 3,
+        /// Return the attributes which the controller supports
+        SUPPORTED = 4,
+    }
+}
+
+bitflags! {
+    /// Attributes of a PCI controller (`EFI_PCI_IO_ATTRIBUTE_*`)
+    #[derive(Default)]
+    pub struct Attributes: u64 {
+        /// ISA I/O ports of the motherboard are decoded
+        const ISA_MOTHERBOARD_IO = 0x0001;
+        /// ISA I/O ports are decoded
+        const ISA_IO = 0x0002;
+        /// VGA palette I/O ports are decoded
+        const VGA_PALETTE_IO = 0x0004;
+        /// The VGA frame buffer is decoded
+        const VGA_MEMORY = 0x0008;
+        /// VGA I/O ports are decoded
+        const VGA_IO = 0x0010;
+        /// Primary IDE I/O ports are decoded
+        const IDE_PRIMARY_IO = 0x0020;
+        /// Secondary IDE I/O ports are decoded
+        const IDE_SECONDARY_IO = 0x0040;
+        /// Memory of the controller may be write-combined
+        const MEMORY_WRITE_COMBINE = 0x0080;
+        /// I/O ranges of the controller are decoded
+        const IO = 0x0100;
+        /// Memory ranges of the controller are decoded
+        const MEMORY = 0x0200;
+        /// The controller may perform DMA
+        const BUS_MASTER = 0x0400;
+        /// Memory of the controller may be cached
+        const MEMORY_CACHED = 0x0800;
+        /// Memory of the controller may be disabled
+        const MEMORY_DISABLE = 0x1000;
+        /// The controller is embedded in the platform
+        const EMBEDDED_DEVICE = 0x2000;
+        /// The option ROM of the controller is embedded in the firmware
+        const EMBEDDED_ROM = 0x4000;
+        /// The controller can address memory above 4 GiB
+        const DUAL_ADDRESS_CYCLE = 0x8000;
+        /// ISA I/O ports are decoded with 16 bits of address
+        const ISA_IO_16 = 0x0001_0000;
+        /// VGA palette I/O ports are decoded with 16 bits of address
+        const VGA_PALETTE_IO_16 = 0x0002_0000;
+        /// VGA I/O ports are decoded with 16 bits of address
+        const VGA_IO_16 = 0x0004_0000;
+    }
+}
+
+/// Values which can be transferred by a single access
+pub trait IoValue: Copy + Default + private::Sealed {
+    /// Width of the access
+    const WIDTH: IoWidth;
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_io_value {
+    ($($ty:ty => $width:ident),*) => {
+        $(
+            impl private::Sealed for $ty {}
+            impl IoValue for $ty {
+                const WIDTH: IoWidth = IoWidth::$width;
+            }
+        )*
+    };
+}
+
+impl_io_value!(u8 => U8, u16 => U16, u32 => U32, u64 => U64);
+
+/// Functions accessing one of the address spaces of the controller
+#[repr(C)]
+struct MemIoAccess {
+    read: unsafe extern "efiapi" fn(
+        this: *mut PciIo,
+        width: IoWidth,
+        bar_index: u8,
+        offset: u64,
+        count: usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    write: unsafe extern "efiapi" fn(
+        this: *mut PciIo,
+        width: IoWidth,
+        bar_index: u8,
+        offset: u64,
+        count: usize,
+        buffer: *const c_void,
+    ) -> Status,
+}
+
+/// Functions accessing the configuration space of the controller
+#[repr(C)]
+struct ConfigAccess {
+    read: unsafe extern "efiapi" fn(
+        this: *mut PciIo,
+        width: IoWidth,
+        offset: u32,
+        count: usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    write: unsafe extern "efiapi" fn(
+        this: *mut PciIo,
+        width: IoWidth,
+        offset: u32,
+        count: usize,
+        buffer: *const c_void,
+    ) -> Status,
+}
+
+/// Opaque handle of a DMA mapping, to be released with `PciIo::unmap`
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct Mapping(*mut c_void);
+
+/// The PCI I/O protocol.
+///
+/// It gives access to the configuration space, the memory and I/O ranges and
+/// the DMA of a single PCI function.
+#[repr(C)]
+#[unsafe_guid("4cf5b200-68b8-4ca5-9eec-b23e3f50029a")]
+#[derive(Protocol)]
+pub struct PciIo {
+    poll_mem: usize,
+    poll_io: usize,
+    mem: MemIoAccess,
+    io: MemIoAccess,
+    pci: ConfigAccess,
+    copy_mem: usize,
+    map: unsafe extern "efiapi" fn(
+        this: *mut PciIo,
+        operation: MapOperation,
+        host_address: *mut c_void,
+        number_of_bytes: &mut usize,
+        device_address: &mut u64,
+        mapping: &mut *mut c_void,
+    ) -> Status,
+    unmap: unsafe extern "efiapi" fn(this: *mut PciIo, mapping: *mut c_void) -> Status,
+    allocate_buffer: unsafe extern "efiapi" fn(
+        this: *mut PciIo,
+        ty: u32,
+        memory_type: MemoryType,
+        pages: usize,
+        host_address: &mut *mut c_void,
+        attributes: Attributes,
+    ) -> Status,
+    free_buffer: unsafe extern "efiapi" fn(
+        this: *mut PciIo,
+        pages: usize,
+        host_address: *mut c_void,
+    ) -> Status,
+    flush: extern "efiapi" fn(this: *mut PciIo) -> Status,
+    get_location: extern "efiapi" fn(
+        this: *mut PciIo,
+        segment: &mut usize,
+        bus: &mut usize,
+        device: &mut usize,
+        function: &mut usize,
+    ) -> Status,
+    attributes: extern "efiapi" fn(
+        this: *mut PciIo,
+        operation: AttributesOperation,
+        attributes: Attributes,
+        result: *mut Attributes,
+    ) -> Status,
+    get_bar_attributes: usize,
+    set_bar_attributes: usize,
+    rom_size: u64,
+    rom_image: *const u8,
+}
+
+impl PciIo {
+    /// Read a value from the configuration space, at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       `offset` is beyond the
+    ///                                     configuration space
+    /// * `uefi::Status::INVALID_PARAMETER` `offset` is not aligned
+    pub fn read_config<T: IoValue>(&mut self, offset: u32) -> Result<T> {
+        let mut value = T::default();
+        let buffer = &mut value as *mut T as *mut c_void;
+        unsafe { (self.pci.read)(self, T::WIDTH, offset, 1, buffer) }.into_with_val(|| value)
+    }
+
+    /// Write a value to the configuration space, at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Same as `read_config`.
+    pub fn write_config<T: IoValue>(&mut self, offset: u32, value: T) -> Result {
+        let buffer = &value as *const T as *const c_void;
+        unsafe { (self.pci.write)(self, T::WIDTH, offset, 1, buffer) }.into()
+    }
+
+    /// Fill `buffer` with the configuration space, starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Same as `read_config`.
+    pub fn read_config_bytes(&mut self, offset: u32, buffer: &mut [u8]) -> Result {
+        let (width, count) = bulk_width(offset, buffer.len());
+        let buffer = buffer.as_mut_ptr() as *mut c_void;
+        unsafe { (self.pci.read)(self, width, offset, count, buffer) }.into()
+    }
+
+    /// Read a value from the memory range of BAR `bar_index`, at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       `bar_index` is not a memory BAR,
+    ///                                     or `offset` is beyond its range
+    /// * `uefi::Status::INVALID_PARAMETER` `offset` is not aligned
+    pub fn read_mem<T: IoValue>(&mut self, bar_index: u8, offset: u64) -> Result<T> {
+        let mut value = T::default();
+        let buffer = &mut value as *mut T as *mut c_void;
+        unsafe { (self.mem.read)(self, T::WIDTH, bar_index, offset, 1, buffer) }
+            .into_with_val(|| value)
+    }
+
+    /// Write a value to the memory range of BAR `bar_index`, at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Same as `read_mem`.
+    pub fn write_mem<T: IoValue>(&mut self, bar_index: u8, offset: u64, value: T) -> Result {
+        let buffer = &value as *const T as *const c_void;
+        unsafe { (self.mem.write)(self, T::WIDTH, bar_index, offset, 1, buffer) }.into()
+    }
+
+    /// Read a value from the I/O range of BAR `bar_index`, at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       `bar_index` is not an I/O BAR, or
+    ///                                     `offset` is beyond its range
+    /// * `uefi::Status::INVALID_PARAMETER` `offset` is not aligned
+    pub fn read_io<T: IoValue>(&mut self, bar_index: u8, offset: u64) -> Result<T> {
+        let mut value = T::default();
+        let buffer = &mut value as *mut T as *mut c_void;
+        unsafe { (self.io.read)(self, T::WIDTH, bar_index, offset, 1, buffer) }
+            .into_with_val(|| value)
+    }
+
+    /// Write a value to the I/O range of BAR `bar_index`, at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Same as `read_io`.
+    pub fn write_io<T: IoValue>(&mut self, bar_index: u8, offset: u64, value: T) -> Result {
+        let buffer = &value as *const T as *const c_void;
+        unsafe { (self.io.write)(self, T::WIDTH, bar_index, offset, 1, buffer) }.into()
+    }
+
+    /// Map `len` bytes of system memory at `host_address` for a DMA transfer,
+    /// returning the address seen by the device, the number of bytes which
+    /// were mapped, which may be less than `len`, and the mapping.
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay valid until it is unmapped, and must not be
+    /// accessed by the processor while the device uses it, unless it was
+    /// mapped with `MapOperation::BUS_MASTER_COMMON_BUFFER`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       The memory cannot be reached by
+    ///                                     the device
+    /// * `uefi::Status::OUT_OF_RESOURCES`  There is no room for a bounce
+    ///                                     buffer
+    /// * `uefi::Status::DEVICE_ERROR`      A hardware error occurred
+    pub unsafe fn map(
+        &mut self,
+        operation: MapOperation,
+        host_address: *mut u8,
+        len: usize,
+    ) -> Result<(u64, usize, Mapping)> {
+        let mut mapped_len = len;
+        let mut device_address = 0;
+        let mut mapping = ptr::null_mut();
+        (self.map)(
+            self,
+            operation,
+            host_address as *mut c_void,
+            &mut mapped_len,
+            &mut device_address,
+            &mut mapping,
+        )
+        .into_with_val(|| (device_address, mapped_len, Mapping(mapping)))
+    }
+
+    /// Release a mapping created by `map`, copying the data written by the
+    /// device from the bounce buffer if one was used.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The data could not be committed
+    pub fn unmap(&mut self, mapping: Mapping) -> Result {
+        unsafe { (self.unmap)(self, mapping.0) }.into()
+    }
+
+    /// Allocate `pages` pages of memory suitable for
+    /// `MapOperation::BUS_MASTER_COMMON_BUFFER` mappings.
+    ///
+    /// Only `Attributes::MEMORY_WRITE_COMBINE`, `MEMORY_CACHED` and
+    /// `DUAL_ADDRESS_CYCLE` may be requested.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`       An attribute is not supported
+    /// * `uefi::Status::INVALID_PARAMETER` `memory_type` is neither
+    ///                                     `BOOT_SERVICES_DATA` nor
+    ///                                     `RUNTIME_SERVICES_DATA`
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The memory could not be allocated
+    pub fn allocate_buffer(
+        &mut self,
+        memory_type: MemoryType,
+        pages: usize,
+        attributes: Attributes,
+    ) -> Result<*mut u8> {
+        // The type of allocation is ignored by the specification
+        const ALLOCATE_ANY_PAGES: u32 = 0;
+        let mut address = ptr::null_mut();
+        unsafe {
+            (self.allocate_buffer)(
+                self,
+                ALLOCATE_ANY_PAGES,
+                memory_type,
+                pages,
+                &mut address,
+                attributes,
+            )
+        }
+        .into_with_val(|| address as *mut u8)
+    }
+
+    /// Free memory allocated by `allocate_buffer`.
+    ///
+    /// # Safety
+    ///
+    /// `address` and `pages` must come from a call to `allocate_buffer`, and
+    /// the memory must not be mapped anymore.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER` The memory was not allocated by
+    ///                                     `allocate_buffer`
+    pub unsafe fn free_buffer(&mut self, address: *mut u8, pages: usize) -> Result {
+        (self.free_buffer)(self, pages, address as *mut c_void).into()
+    }
+
+    /// Wait for the writes posted to system memory by the device to complete.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  A hardware error occurred
+    pub fn flush(&mut self) -> Result {
+        (self.flush)(self).into()
+    }
+
+    /// Address of the function, as `(segment, bus, device, function)`
+    pub fn location(&mut self) -> Result<(usize, usize, usize, usize)> {
+        let (mut segment, mut bus, mut device, mut function) = (0, 0, 0, 0);
+        (self.get_location)(self, &mut segment, &mut bus, &mut device, &mut function)
+            .into_with_val(|| (segment, bus, device, function))
+    }
+
+    /// Perform an operation on the attributes of the controller, returning
+    /// the current or supported attributes for `AttributesOperation::GET`
+    /// and `SUPPORTED`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`   An attribute is not supported
+    pub fn attributes(
+        &mut self,
+        operation: AttributesOperation,
+        attributes: Attributes,
+    ) -> Result<Attributes> {
+        let mut result = Attributes::empty();
+        let result_ptr = if operation == AttributesOperation::GET
+            || operation == AttributesOperation::SUPPORTED
+        {
+            &mut result as *mut Attributes
+        } else {
+            ptr::null_mut()
+        };
+        (self.attributes)(self, operation, attributes, result_ptr).into_with_val(|| result)
+    }
+
+    /// Copy of the option ROM of the controller, if it has one
+    pub fn rom_image(&self) -> Option<&[u8]> {
+        if self.rom_image.is_null() || self.rom_size == 0 {
+            None
+        } else {
+            Some(unsafe { slice::from_raw_parts(self.rom_image, self.rom_size as usize) })
+        }
+    }
+}
+
+/// Widest accesses which can read `len` bytes at `offset`
+fn bulk_width(offset: u32, len: usize) -> (IoWidth, usize) {
+    // Sizes are powers of two, so both are multiples of them if their low
+    // bits are clear
+    let aligned = |size: usize| (offset as usize | len) & (size - 1) == 0;
+    if aligned(mem::size_of::<u32>()) {
+        (IoWidth::U32, len / 4)
+    } else if aligned(mem::size_of::<u16>()) {
+        (IoWidth::U16, len / 2)
+    } else {
+        (IoWidth::U8, len)
+    }
+}
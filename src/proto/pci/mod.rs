@@ -0,0 +1,14 @@
+//! PCI protocols.
+//!
+//! The PCI bus driver installs a `PciIo` protocol on the handle of each PCI
+//! function it enumerates. The `config` module decodes the configuration
+//! space read through it, so that drivers do not have to compute offsets, and
+//! the `dma` module manages the memory of DMA transfers.
+
+pub mod config;
+pub mod dma;
+mod io;
+
+pub use self::io::{
+    Attributes, AttributesOperation, IoValue, IoWidth, MapOperation, Mapping, PciIo,
+};
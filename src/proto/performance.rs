@@ -0,0 +1,101 @@
+//! Performance Measurement protocol.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, CStr8, Char8, Guid, Handle, Result, Status};
+use core::ptr;
+
+newtype_enum! {
+    /// Kind of a performance measurement
+    pub enum MeasurementAttribute: u32 => {
+        /// Start of a measured operation
+        START = 0,
+        /// End of a measured operation
+        END   = 1,
+        /// A single event, which has no duration
+        ENTRY = 2,
+    }
+}
+
+/// Protocol for adding records to the performance log of the firmware
+/// (`EDKII_PERFORMANCE_MEASUREMENT_PROTOCOL`).
+///
+/// EDK2 firmwares built with performance measurement support record the
+/// duration of the boot phases and of the drivers they start. This protocol
+/// lets applications add their own operations to the same log, which is then
+/// published in the FBPT (see the [`fpdt`](crate::table::fpdt) module) and
+/// shown by tools such as the `dp` shell command.
+///
+/// Unlike most protocols, the function of this protocol takes no `this`
+/// pointer.
+#[repr(C)]
+#[unsafe_guid("c85d06be-5f75-48ce-a80f-1236ba3b87b1")]
+#[derive(Protocol)]
+pub struct PerformanceMeasurement {
+    create_performance_measurement: unsafe extern "efiapi" fn(
+        caller_identifier: Handle,
+        guid: *const Guid,
+        string: *const Char8,
+        timestamp: u64,
+        address: u64,
+        identifier: u32,
+        attribute: MeasurementAttribute,
+    ) -> Status,
+}
+
+impl PerformanceMeasurement {
+    /// Add a record to the performance log.
+    ///
+    /// `caller` identifies the measured code, usually by the handle of its
+    /// image. The record is described by the `module` GUID, the `token`
+    /// string, or both, plus a numeric `identifier` whose meaning is defined
+    /// by EDK2. A `timestamp` of zero stands for the current time.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The record is not valid, for example
+    ///                                      an `END` record without a `START`
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The performance log is full
+    pub fn create_measurement(
+        &self,
+        caller: Handle,
+        module: Option<&Guid>,
+        token: Option<&CStr8>,
+        timestamp: u64,
+        identifier: u32,
+        attribute: MeasurementAttribute,
+    ) -> Result {
+        unsafe {
+            (self.create_performance_measurement)(
+                caller,
+                module.map_or(ptr::null(), |guid| guid as *const Guid),
+                token.map_or(ptr::null(), CStr8::as_ptr),
+                timestamp,
+                0,
+                identifier,
+                attribute,
+            )
+        }
+        .into()
+    }
+
+    /// Record the start of an operation, identified by `token`
+    ///
+    /// See `create_measurement`.
+    pub fn start(&self, caller: Handle, token: &CStr8) -> Result {
+        self.create_measurement(caller, None, Some(token), 0, 0, MeasurementAttribute::START)
+    }
+
+    /// Record the end of an operation started with `start`
+    ///
+    /// See `create_measurement`.
+    pub fn end(&self, caller: Handle, token: &CStr8) -> Result {
+        self.create_measurement(caller, None, Some(token), 0, 0, MeasurementAttribute::END)
+    }
+
+    /// Record an event which has no duration
+    ///
+    /// See `create_measurement`.
+    pub fn event(&self, caller: Handle, token: &CStr8) -> Result {
+        self.create_measurement(caller, None, Some(token), 0, 0, MeasurementAttribute::ENTRY)
+    }
+}
@@ -0,0 +1,168 @@
+//! CPU Architectural Protocol.
+//!
+//! The DXE core uses this protocol to manage the interrupts and the caches
+//! of the boot processor. Applications use it to handle processor exceptions
+//! themselves, on firmware such as OVMF which only implements the Debug
+//! Support protocol for EBC:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::pi::cpu::CpuArch;
+//! # fn f(bt: &BootServices) -> uefi::Result {
+//! let cpu = bt.locate_protocol::<CpuArch>()?.log();
+//! let cpu = unsafe { &mut *cpu.get() };
+//! // An invalid opcode or a page fault now panics, with a register dump
+//! cpu.panic_on_faults()?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::debug::{ExceptionType, SystemContext};
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Result, Status};
+
+/// Handler of processor exceptions and interrupts, which runs in interrupt
+/// context
+pub type InterruptHandler = unsafe extern "efiapi" fn(ExceptionType, SystemContext);
+
+/// Protocol through which the DXE core manages the boot processor
+/// (`EFI_CPU_ARCH_PROTOCOL`)
+#[repr(C)]
+#[unsafe_guid("26baccb1-6f42-11d4-bce7-0080c73c8881")]
+#[derive(Protocol)]
+pub struct CpuArch {
+    flush_data_cache:
+        extern "efiapi" fn(this: &Self, start: u64, length: u64, flush_type: u32) -> Status,
+    enable_interrupt: extern "efiapi" fn(this: &Self) -> Status,
+    disable_interrupt: extern "efiapi" fn(this: &Self) -> Status,
+    get_interrupt_state: extern "efiapi" fn(this: &Self, state: &mut bool) -> Status,
+    init: extern "efiapi" fn(this: &Self, init_type: u32) -> Status,
+    register_interrupt_handler: unsafe extern "efiapi" fn(
+        this: &Self,
+        interrupt_type: ExceptionType,
+        handler: Option<InterruptHandler>,
+    ) -> Status,
+    get_timer_value: extern "efiapi" fn(
+        this: &Self,
+        timer_index: u32,
+        timer_value: &mut u64,
+        timer_period: *mut u64,
+    ) -> Status,
+    set_memory_attributes:
+        extern "efiapi" fn(this: &Self, start: u64, length: u64, attributes: u64) -> Status,
+    number_of_timers: u32,
+    dma_buffer_alignment: u32,
+}
+
+impl CpuArch {
+    /// Whether interrupts are enabled on the boot processor
+    pub fn interrupts_enabled(&self) -> Result<bool> {
+        let mut state = false;
+        (self.get_interrupt_state)(self, &mut state).into_with_val(|| state)
+    }
+
+    /// Number of timers of the processor, which `timer_value` reads
+    pub fn number_of_timers(&self) -> u32 {
+        self.number_of_timers
+    }
+
+    /// Read the timer `index` of the processor, such as the time-stamp
+    /// counter on x86.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  There is no such timer
+    pub fn timer_value(&self, index: u32) -> Result<u64> {
+        let mut value = 0;
+        (self.get_timer_value)(self, index, &mut value, core::ptr::null_mut())
+            .into_with_val(|| value)
+    }
+
+    /// Register the handler of an exception or an interrupt, or remove it
+    /// with `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ALREADY_STARTED`    A handler is already registered
+    /// * `uefi::Status::INVALID_PARAMETER`  The type is not supported, or
+    ///                                      there is no handler to remove
+    ///
+    /// # Safety
+    ///
+    /// The handler runs in interrupt context, and may not use boot services
+    /// which are not allowed at `Tpl::HIGH_LEVEL`. It must stay valid while
+    /// it is registered, including after the image exits.
+    pub unsafe fn register_interrupt_handler(
+        &mut self,
+        interrupt_type: ExceptionType,
+        handler: Option<InterruptHandler>,
+    ) -> Result {
+        (self.register_interrupt_handler)(self, interrupt_type, handler).into()
+    }
+
+    /// The exceptions which `panic_on_faults` handles
+    #[cfg(target_arch = "x86_64")]
+    pub const FAULTS: [ExceptionType; 7] = [
+        ExceptionType::EXCEPT_X64_DIVIDE_ERROR,
+        ExceptionType::EXCEPT_X64_INVALID_OPCODE,
+        ExceptionType::EXCEPT_X64_STACK_FAULT,
+        ExceptionType::EXCEPT_X64_GP_FAULT,
+        ExceptionType::EXCEPT_X64_PAGE_FAULT,
+        ExceptionType::EXCEPT_X64_ALIGNMENT_CHECK,
+        ExceptionType::EXCEPT_X64_SIMD,
+    ];
+
+    /// Turn the processor faults of `FAULTS` into panics, which print the
+    /// registers and the backtrace at the time of the fault.
+    ///
+    /// The firmware would otherwise print them and hang, while the panic
+    /// handler can report the failure, such as through the exit code of
+    /// QEMU. The faulting code is not resumed.
+    ///
+    /// The handlers must be removed with `restore_faults` before the image
+    /// exits, since they are part of it.
+    ///
+    /// # Errors
+    ///
+    /// See `register_interrupt_handler`. The handlers which were registered
+    /// before the error are removed.
+    #[cfg(target_arch = "x86_64")]
+    pub fn panic_on_faults(&mut self) -> Result {
+        for (i, &fault) in Self::FAULTS.iter().enumerate() {
+            let result = unsafe { self.register_interrupt_handler(fault, Some(panic_on_fault)) };
+            if let Err(error) = result {
+                for &fault in &Self::FAULTS[..i] {
+                    let _ = unsafe { self.register_interrupt_handler(fault, None) };
+                }
+                return Err(error);
+            }
+        }
+        Status::SUCCESS.into()
+    }
+
+    /// Remove the handlers registered by `panic_on_faults`
+    #[cfg(target_arch = "x86_64")]
+    pub fn restore_faults(&mut self) -> Result {
+        for &fault in &Self::FAULTS {
+            unsafe { self.register_interrupt_handler(fault, None) }?.log();
+        }
+        Status::SUCCESS.into()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe extern "efiapi" fn panic_on_fault(fault: ExceptionType, context: SystemContext) {
+    let context = context.x64();
+    // The faulting code is interrupted, so its frame pointer is in the context
+    let backtrace = crate::backtrace::Backtrace::from_frame_pointer(
+        context.rbp() as usize,
+        Some(context.rip() as usize),
+    );
+    panic!(
+        "Processor fault {:?} at {:#x}, registers:\n{}\nBacktrace of the fault:\n{}",
+        fault,
+        context.rip(),
+        context,
+        backtrace
+    );
+}
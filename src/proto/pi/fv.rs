@@ -0,0 +1,660 @@
+//! Firmware volume protocols.
+//!
+//! The firmware image is made of firmware volumes, which contain files
+//! identified by GUID, such as the DXE drivers or the boot logo. Each file is
+//! itself made of typed sections, like a PE32 image or a user interface name.
+//!
+//! * `FirmwareVolume` gives access to the files of a volume
+//! * `FirmwareVolumeBlock` gives access to the underlying storage, one block
+//!   at a time
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Guid, Handle, Result, Status};
+use bitflags::bitflags;
+use core::ffi::c_void;
+use core::ptr::{self, NonNull};
+#[cfg(feature = "exts")]
+use {crate::ResultExt, alloc_api::vec, alloc_api::vec::Vec};
+
+newtype_enum! {
+    /// Type of a firmware file.
+    pub enum FvFileType: u8 => {
+        /// Matches files of any type, when enumerating files
+        ALL                   = 0x00,
+        /// Binary data
+        RAW                   = 0x01,
+        /// Sectioned data
+        FREEFORM              = 0x02,
+        /// Platform code for the SEC phase
+        SECURITY_CORE         = 0x03,
+        /// PEI foundation
+        PEI_CORE              = 0x04,
+        /// DXE foundation
+        DXE_CORE              = 0x05,
+        /// PEI module
+        PEIM                  = 0x06,
+        /// DXE driver
+        DRIVER                = 0x07,
+        /// Combined PEI module and DXE driver
+        COMBINED_PEIM_DRIVER  = 0x08,
+        /// UEFI application
+        APPLICATION           = 0x09,
+        /// Management mode driver
+        MM                    = 0x0a,
+        /// Nested firmware volume
+        FIRMWARE_VOLUME_IMAGE = 0x0b,
+        /// Combined management mode and DXE driver
+        COMBINED_MM_DXE       = 0x0c,
+        /// Management mode foundation
+        MM_CORE               = 0x0d,
+        /// Standalone management mode driver
+        MM_STANDALONE         = 0x0e,
+        /// Standalone management mode foundation
+        MM_CORE_STANDALONE    = 0x0f,
+        /// Padding
+        FFS_PAD               = 0xf0,
+    }
+}
+
+newtype_enum! {
+    /// Type of a section of a firmware file.
+    pub enum SectionType: u8 => {
+        /// Matches sections of any type
+        ALL                   = 0x00,
+        /// Compressed sections
+        COMPRESSION           = 0x01,
+        /// Sections encapsulated in a format identified by GUID
+        GUID_DEFINED          = 0x02,
+        /// Sections which may be dropped
+        DISPOSABLE            = 0x03,
+        /// PE32+ executable image
+        PE32                  = 0x10,
+        /// Position independent code
+        PIC                   = 0x11,
+        /// Terse executable image
+        TE                    = 0x12,
+        /// Dependency expression of a DXE driver
+        DXE_DEPEX             = 0x13,
+        /// Version of the file
+        VERSION               = 0x14,
+        /// Human-readable name of the file, as a UCS-2 string
+        USER_INTERFACE        = 0x15,
+        /// 16-bit legacy image
+        COMPATIBILITY16       = 0x16,
+        /// Nested firmware volume
+        FIRMWARE_VOLUME_IMAGE = 0x17,
+        /// Raw data identified by a GUID
+        FREEFORM_SUBTYPE_GUID = 0x18,
+        /// Raw data
+        RAW                   = 0x19,
+        /// Dependency expression of a PEI module
+        PEI_DEPEX             = 0x1b,
+        /// Dependency expression of a management mode driver
+        MM_DEPEX              = 0x1c,
+    }
+}
+
+bitflags! {
+    /// Attributes of a firmware volume.
+    pub struct FvAttributes: u64 {
+        /// Reads may be disabled
+        const READ_DISABLE_CAP = 0x1;
+        /// Reads may be enabled
+        const READ_ENABLE_CAP = 0x2;
+        /// Reads are enabled
+        const READ_STATUS = 0x4;
+        /// Writes may be disabled
+        const WRITE_DISABLE_CAP = 0x8;
+        /// Writes may be enabled
+        const WRITE_ENABLE_CAP = 0x10;
+        /// Writes are enabled
+        const WRITE_STATUS = 0x20;
+        /// The attributes may be locked
+        const LOCK_CAP = 0x40;
+        /// The attributes are locked
+        const LOCK_STATUS = 0x80;
+        /// Writes are reliable, even on power loss
+        const WRITE_POLICY_RELIABLE = 0x100;
+        /// Reads may be locked
+        const READ_LOCK_CAP = 0x1000;
+        /// Reads are locked
+        const READ_LOCK_STATUS = 0x2000;
+        /// Writes may be locked
+        const WRITE_LOCK_CAP = 0x4000;
+        /// Writes are locked
+        const WRITE_LOCK_STATUS = 0x8000;
+        /// Mask of the alignment of the volume, as a power of two
+        const ALIGNMENT = 0x1f_0000;
+    }
+}
+
+bitflags! {
+    /// Attributes of a firmware file.
+    pub struct FvFileAttributes: u32 {
+        /// Mask of the alignment of the file data, as a power of two
+        const ALIGNMENT = 0x1f;
+        /// The file may not be moved
+        const FIXED = 0x100;
+        /// The file is memory-mapped
+        const MEMORY_MAPPED = 0x200;
+    }
+}
+
+impl FvFileAttributes {
+    /// Required alignment of the file data, in bytes
+    pub fn alignment(self) -> u64 {
+        1 << (self & Self::ALIGNMENT).bits()
+    }
+}
+
+/// Information about a firmware file
+#[derive(Debug, Clone, Copy)]
+pub struct FvFileInfo {
+    /// Type of the file
+    pub file_type: FvFileType,
+    /// Attributes of the file
+    pub attributes: FvFileAttributes,
+    /// Size of the file data
+    pub size: usize,
+}
+
+/// Protocol giving access to the files of a firmware volume
+/// (`EFI_FIRMWARE_VOLUME2_PROTOCOL`).
+///
+/// Files and their sections are read into buffers provided by the caller.
+/// When a buffer is too small, the data is truncated and a
+/// `WARN_BUFFER_TOO_SMALL` warning is returned along with the full size.
+#[repr(C)]
+#[unsafe_guid("220e73b6-6bdb-4413-8405-b974b108619a")]
+#[derive(Protocol)]
+pub struct FirmwareVolume {
+    get_volume_attributes: extern "efiapi" fn(this: &Self, attributes: &mut u64) -> Status,
+    set_volume_attributes: extern "efiapi" fn(this: &Self, attributes: &mut u64) -> Status,
+    read_file: unsafe extern "efiapi" fn(
+        this: &Self,
+        name: &Guid,
+        buffer: *mut *mut c_void,
+        buffer_size: &mut usize,
+        found_type: &mut FvFileType,
+        attributes: &mut u32,
+        authentication_status: &mut u32,
+    ) -> Status,
+    read_section: unsafe extern "efiapi" fn(
+        this: &Self,
+        name: &Guid,
+        section_type: SectionType,
+        section_instance: usize,
+        buffer: *mut *mut c_void,
+        buffer_size: &mut usize,
+        authentication_status: &mut u32,
+    ) -> Status,
+    write_file: unsafe extern "efiapi" fn(
+        this: &Self,
+        number_of_files: u32,
+        write_policy: u32,
+        file_data: *const c_void,
+    ) -> Status,
+    get_next_file: unsafe extern "efiapi" fn(
+        this: &Self,
+        key: *mut u8,
+        file_type: &mut FvFileType,
+        name: &mut Guid,
+        attributes: &mut u32,
+        size: &mut usize,
+    ) -> Status,
+    key_size: u32,
+    parent_handle: *mut c_void,
+    get_info: unsafe extern "efiapi" fn(
+        this: &Self,
+        information_type: &Guid,
+        buffer_size: &mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    set_info: unsafe extern "efiapi" fn(
+        this: &Self,
+        information_type: &Guid,
+        buffer_size: usize,
+        buffer: *const c_void,
+    ) -> Status,
+}
+
+impl FirmwareVolume {
+    /// Get the attributes of the volume.
+    pub fn attributes(&self) -> Result<FvAttributes> {
+        let mut attributes = 0;
+        (self.get_volume_attributes)(self, &mut attributes)
+            .into_with_val(|| FvAttributes::from_bits_truncate(attributes))
+    }
+
+    /// Change the attributes of the volume, and return the new attributes.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The attributes conflict with the
+    ///                                      capabilities of the volume
+    /// * `uefi::Status::ACCESS_DENIED`      The attributes are locked
+    pub fn set_attributes(&self, attributes: FvAttributes) -> Result<FvAttributes> {
+        let mut attributes = attributes.bits();
+        (self.set_volume_attributes)(self, &mut attributes)
+            .into_with_val(|| FvAttributes::from_bits_truncate(attributes))
+    }
+
+    /// Handle of the firmware volume block protocol of this volume, if any.
+    pub fn parent_handle(&self) -> Option<Handle> {
+        Handle::from_ptr(self.parent_handle)
+    }
+
+    /// Get the type, attributes and size of a file, without reading it.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`      The file does not exist
+    /// * `uefi::Status::ACCESS_DENIED`  The volume is not readable
+    pub fn file_info(&self, name: &Guid) -> Result<FvFileInfo> {
+        self.read_file_impl(name, ptr::null_mut(), 0)
+    }
+
+    /// Read the content of a file into `buffer`.
+    ///
+    /// If the buffer is too small, the content is truncated and a
+    /// `WARN_BUFFER_TOO_SMALL` warning is returned. In all cases, the size
+    /// of the returned info is the full size of the file.
+    ///
+    /// # Errors
+    ///
+    /// See `file_info`.
+    pub fn read_file(&self, name: &Guid, buffer: &mut [u8]) -> Result<FvFileInfo> {
+        let mut ptr = Self::buffer_ptr(buffer);
+        self.read_file_impl(name, &mut ptr, buffer.len())
+    }
+
+    fn read_file_impl(
+        &self,
+        name: &Guid,
+        buffer: *mut *mut c_void,
+        buffer_size: usize,
+    ) -> Result<FvFileInfo> {
+        let mut size = buffer_size;
+        let mut file_type = FvFileType::ALL;
+        let mut attributes = 0;
+        let mut authentication_status = 0;
+        unsafe {
+            (self.read_file)(
+                self,
+                name,
+                buffer,
+                &mut size,
+                &mut file_type,
+                &mut attributes,
+                &mut authentication_status,
+            )
+        }
+        .into_with_val(|| FvFileInfo {
+            file_type,
+            attributes: FvFileAttributes::from_bits_truncate(attributes),
+            size,
+        })
+    }
+
+    /// Read a section of a file into `buffer`, and return the full size of
+    /// the section.
+    ///
+    /// `instance` selects which one of the sections of type `section_type`
+    /// to read, starting from 0. Encapsulated sections, like compressed
+    /// ones, are searched as well.
+    ///
+    /// If the buffer is too small, the section is truncated and a
+    /// `WARN_BUFFER_TOO_SMALL` warning is returned.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`         The file or the section does not exist
+    /// * `uefi::Status::ACCESS_DENIED`     The volume is not readable
+    /// * `uefi::Status::PROTOCOL_ERROR`    An encapsulated section could not be
+    ///                                     decoded
+    pub fn read_section(
+        &self,
+        name: &Guid,
+        section_type: SectionType,
+        instance: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize> {
+        let mut ptr = Self::buffer_ptr(buffer);
+        let mut size = buffer.len();
+        let mut authentication_status = 0;
+        unsafe {
+            (self.read_section)(
+                self,
+                name,
+                section_type,
+                instance,
+                &mut ptr,
+                &mut size,
+                &mut authentication_status,
+            )
+        }
+        .into_with_val(|| size)
+    }
+
+    /// Read the content of a file into a newly allocated buffer.
+    ///
+    /// # Errors
+    ///
+    /// See `file_info`.
+    #[cfg(feature = "exts")]
+    pub fn read_file_to_vec(&self, name: &Guid) -> Result<(FvFileInfo, Vec<u8>)> {
+        let info = self.file_info(name).log_warning()?;
+        let mut buffer = vec![0; info.size];
+        self.read_file(name, &mut buffer)
+            .map(|completion| completion.map(|info| (info, buffer)))
+    }
+
+    /// Read a section of a file into a newly allocated buffer.
+    ///
+    /// # Errors
+    ///
+    /// See `read_section`.
+    #[cfg(feature = "exts")]
+    pub fn read_section_to_vec(
+        &self,
+        name: &Guid,
+        section_type: SectionType,
+        instance: usize,
+    ) -> Result<Vec<u8>> {
+        // Provide an empty buffer to only get the size of the section
+        let size = self
+            .read_section(name, section_type, instance, &mut [])
+            .map(|completion| completion.split().1)?;
+        let mut buffer = vec![0; size];
+        self.read_section(name, section_type, instance, &mut buffer)
+            .map(|completion| completion.map(|_| buffer))
+    }
+
+    /// Size of the key buffer that `get_next_file` expects.
+    pub fn key_size(&self) -> usize {
+        self.key_size as usize
+    }
+
+    /// Get the file which follows the one designated by `key`, among the
+    /// files of type `file_type`. Returns `None` after the last file.
+    ///
+    /// `key` must be `key_size()` bytes long, and zeroed to get the first
+    /// file. It is updated to designate the returned file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key buffer is too small.
+    pub fn get_next_file(
+        &self,
+        key: &mut [u8],
+        file_type: FvFileType,
+    ) -> Result<Option<(Guid, FvFileInfo)>> {
+        assert!(key.len() >= self.key_size(), "key buffer too small");
+        let mut found_type = file_type;
+        let mut name = Guid::default();
+        let mut attributes = 0;
+        let mut size = 0;
+        let status = unsafe {
+            (self.get_next_file)(
+                self,
+                key.as_mut_ptr(),
+                &mut found_type,
+                &mut name,
+                &mut attributes,
+                &mut size,
+            )
+        };
+        match status {
+            Status::NOT_FOUND => Ok(None.into()),
+            status => status.into_with_val(|| {
+                Some((
+                    name,
+                    FvFileInfo {
+                        file_type: found_type,
+                        attributes: FvFileAttributes::from_bits_truncate(attributes),
+                        size,
+                    },
+                ))
+            }),
+        }
+    }
+
+    /// Iterate over the files of type `file_type` in this volume.
+    #[cfg(feature = "exts")]
+    pub fn files(&self, file_type: FvFileType) -> FvFiles<'_> {
+        FvFiles {
+            volume: self,
+            key: vec![0; self.key_size()],
+            file_type,
+            done: false,
+        }
+    }
+
+    /// The firmware reads into the caller's buffer only if the buffer
+    /// pointer is not null, even for empty buffers
+    fn buffer_ptr(buffer: &mut [u8]) -> *mut c_void {
+        NonNull::new(buffer.as_mut_ptr())
+            .unwrap_or_else(NonNull::dangling)
+            .as_ptr()
+            .cast()
+    }
+}
+
+/// Iterator over the files of a firmware volume, returned by
+/// `FirmwareVolume::files`
+///
+/// Iteration stops after the last file, or after the first error.
+#[cfg(feature = "exts")]
+pub struct FvFiles<'a> {
+    volume: &'a FirmwareVolume,
+    key: Vec<u8>,
+    file_type: FvFileType,
+    done: bool,
+}
+
+#[cfg(feature = "exts")]
+impl Iterator for FvFiles<'_> {
+    type Item = Result<(Guid, FvFileInfo)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let file = match self.volume.get_next_file(&mut self.key, self.file_type) {
+            Ok(completion) => {
+                let (status, file) = completion.split();
+                file.map(|file| Ok(crate::Completion::new(status, file)))
+            }
+            Err(error) => Some(Err(error)),
+        };
+        self.done = !matches!(file, Some(Ok(_)));
+        file
+    }
+}
+
+bitflags! {
+    /// Attributes of a firmware volume block device.
+    pub struct FvbAttributes: u32 {
+        /// Reads may be disabled
+        const READ_DISABLED_CAP = 0x1;
+        /// Reads may be enabled
+        const READ_ENABLED_CAP = 0x2;
+        /// Reads are enabled
+        const READ_STATUS = 0x4;
+        /// Writes may be disabled
+        const WRITE_DISABLED_CAP = 0x8;
+        /// Writes may be enabled
+        const WRITE_ENABLED_CAP = 0x10;
+        /// Writes are enabled
+        const WRITE_STATUS = 0x20;
+        /// The attributes may be locked
+        const LOCK_CAP = 0x40;
+        /// The attributes are locked
+        const LOCK_STATUS = 0x80;
+        /// Sticky writes are supported
+        const STICKY_WRITE = 0x200;
+        /// The volume is memory-mapped
+        const MEMORY_MAPPED = 0x400;
+        /// Erased bits are set to 1
+        const ERASE_POLARITY = 0x800;
+        /// Reads may be locked
+        const READ_LOCK_CAP = 0x1000;
+        /// Reads are locked
+        const READ_LOCK_STATUS = 0x2000;
+        /// Writes may be locked
+        const WRITE_LOCK_CAP = 0x4000;
+        /// Writes are locked
+        const WRITE_LOCK_STATUS = 0x8000;
+        /// Mask of the alignment of the volume, as a power of two
+        const ALIGNMENT = 0x1f_0000;
+        /// The alignment is not enforced
+        const WEAK_ALIGNMENT = 0x8000_0000;
+    }
+}
+
+/// Geometry of a block of a firmware volume
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FvbBlockInfo {
+    /// Size of the block, in bytes
+    pub block_size: usize,
+    /// Number of consecutive blocks of the same size, starting from this one
+    pub block_count: usize,
+}
+
+/// Protocol giving access to the storage of a firmware volume
+/// (`EFI_FIRMWARE_VOLUME_BLOCK2_PROTOCOL`).
+///
+/// The storage is addressed by logical block, and the blocks of a volume
+/// may have different sizes.
+#[repr(C)]
+#[unsafe_guid("8f644fa9-e850-4db1-9ce2-0b44698e8da4")]
+#[derive(Protocol)]
+pub struct FirmwareVolumeBlock {
+    get_attributes: extern "efiapi" fn(this: &Self, attributes: &mut u32) -> Status,
+    set_attributes: extern "efiapi" fn(this: &Self, attributes: &mut u32) -> Status,
+    get_physical_address: extern "efiapi" fn(this: &Self, address: &mut u64) -> Status,
+    get_block_size: extern "efiapi" fn(
+        this: &Self,
+        lba: u64,
+        block_size: &mut usize,
+        number_of_blocks: &mut usize,
+    ) -> Status,
+    read: unsafe extern "efiapi" fn(
+        this: &Self,
+        lba: u64,
+        offset: usize,
+        num_bytes: &mut usize,
+        buffer: *mut u8,
+    ) -> Status,
+    write: unsafe extern "efiapi" fn(
+        this: &Self,
+        lba: u64,
+        offset: usize,
+        num_bytes: &mut usize,
+        buffer: *const u8,
+    ) -> Status,
+    // This function is variadic, but taking a fixed number of integer
+    // arguments passes them the same way.
+    erase_blocks: extern "efiapi" fn(this: &Self, lba: u64, count: u64, terminator: u64) -> Status,
+    parent_handle: *mut c_void,
+}
+
+impl FirmwareVolumeBlock {
+    /// Terminator of the list of ranges passed to `EraseBlocks()`
+    const LBA_LIST_TERMINATOR: u64 = u64::MAX;
+
+    /// Get the attributes of the volume.
+    pub fn attributes(&self) -> Result<FvbAttributes> {
+        let mut attributes = 0;
+        (self.get_attributes)(self, &mut attributes)
+            .into_with_val(|| FvbAttributes::from_bits_truncate(attributes))
+    }
+
+    /// Change the attributes of the volume, and return the new attributes.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The attributes conflict with the
+    ///                                      capabilities of the volume
+    /// * `uefi::Status::ACCESS_DENIED`      The attributes are locked
+    pub fn set_attributes(&self, attributes: FvbAttributes) -> Result<FvbAttributes> {
+        let mut attributes = attributes.bits();
+        (self.set_attributes)(self, &mut attributes)
+            .into_with_val(|| FvbAttributes::from_bits_truncate(attributes))
+    }
+
+    /// Get the base address of a memory-mapped volume.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`  The volume is not memory-mapped
+    pub fn physical_address(&self) -> Result<u64> {
+        let mut address = 0;
+        (self.get_physical_address)(self, &mut address).into_with_val(|| address)
+    }
+
+    /// Get the size of the block `lba`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  The block does not exist
+    pub fn block_info(&self, lba: u64) -> Result<FvbBlockInfo> {
+        let mut block_size = 0;
+        let mut block_count = 0;
+        (self.get_block_size)(self, lba, &mut block_size, &mut block_count).into_with_val(|| {
+            FvbBlockInfo {
+                block_size,
+                block_count,
+            }
+        })
+    }
+
+    /// Read data from the block `lba`, starting at `offset` in the block.
+    ///
+    /// Returns the number of bytes read, which is smaller than the size of
+    /// the buffer if the read was stopped at the end of the block. In that
+    /// case, the number of bytes read is also returned with the error.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`  The read crossed the end of the block
+    /// * `uefi::Status::ACCESS_DENIED`    The volume is not readable
+    /// * `uefi::Status::DEVICE_ERROR`     The hardware reported an error
+    pub fn read(&self, lba: u64, offset: usize, buffer: &mut [u8]) -> Result<usize, usize> {
+        let mut size = buffer.len();
+        unsafe { (self.read)(self, lba, offset, &mut size, buffer.as_mut_ptr()) }
+            .into_with(|| size, |_| size)
+    }
+
+    /// Write data to the block `lba`, starting at `offset` in the block.
+    ///
+    /// The block must have been erased first. Returns the number of bytes
+    /// written, like `read`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`  The write crossed the end of the block
+    /// * `uefi::Status::ACCESS_DENIED`    The volume is not writable
+    /// * `uefi::Status::DEVICE_ERROR`     The hardware reported an error
+    pub fn write(&self, lba: u64, offset: usize, buffer: &[u8]) -> Result<usize, usize> {
+        let mut size = buffer.len();
+        unsafe { (self.write)(self, lba, offset, &mut size, buffer.as_ptr()) }
+            .into_with(|| size, |_| size)
+    }
+
+    /// Erase `count` blocks, starting from the block `lba`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ACCESS_DENIED`      The volume is not writable
+    /// * `uefi::Status::INVALID_PARAMETER`  The blocks do not exist
+    /// * `uefi::Status::DEVICE_ERROR`       The hardware reported an error
+    pub fn erase_blocks(&self, lba: u64, count: u64) -> Result {
+        (self.erase_blocks)(self, lba, count, Self::LBA_LIST_TERMINATOR).into()
+    }
+
+    /// Handle of the parent firmware volume block, for nested volumes.
+    pub fn parent_handle(&self) -> Option<Handle> {
+        Handle::from_ptr(self.parent_handle)
+    }
+}
@@ -0,0 +1,357 @@
+//! I2C protocols.
+//!
+//! The PI specification splits the I2C stack in several layers. The I2C Host
+//! and Master protocols are implemented by the drivers of the I2C controllers
+//! and address the devices by their bus address, while the I2C IO protocol is
+//! installed by the I2C bus driver on a handle per device, which is the one
+//! that applications should use:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::pi::i2c::{I2cIo, Operation, RequestPacket};
+//! # fn f(i2c: &mut I2cIo) -> uefi::Result {
+//! // Read 16 bytes at offset 0x40 of an EEPROM
+//! let mut data = [0; 16];
+//! i2c.write_read(0, &[0x40], &mut data)?;
+//!
+//! // Or build the request by hand
+//! let mut packet = RequestPacket::new([Operation::write(&[0x40]), Operation::read(&mut data)]);
+//! i2c.queue_request(0, &mut packet)
+//! # }
+//! ```
+//!
+//! All the requests are executed synchronously.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Guid, Result, Status};
+use bitflags::bitflags;
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr;
+
+bitflags! {
+    /// Flags of an I2C operation
+    #[derive(Default)]
+    pub struct OperationFlags: u32 {
+        /// Read data from the device, instead of writing it.
+        const READ = 0x0000_0001;
+        /// The operation is part of an SMBus transaction.
+        const SMBUS_OPERATION = 0x0001_0000;
+        /// The operation is an SMBus block read or write, whose first byte is
+        /// the number of bytes of the block.
+        const SMBUS_BLOCK = 0x0002_0000;
+        /// The operation is part of an SMBus process call.
+        const SMBUS_PROCESS_CALL = 0x0004_0000;
+        /// Send or check a packet error code for the SMBus transaction.
+        const SMBUS_PEC = 0x0008_0000;
+    }
+}
+
+/// A read or a write of an I2C request (`EFI_I2C_OPERATION`)
+///
+/// The operations of a request are separated by repeated START conditions.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Operation<'buf> {
+    flags: OperationFlags,
+    length: u32,
+    buffer: *mut u8,
+    _buffer: PhantomData<&'buf mut [u8]>,
+}
+
+impl<'buf> Operation<'buf> {
+    /// Read `buffer.len()` bytes from the device
+    pub fn read(buffer: &'buf mut [u8]) -> Self {
+        Operation {
+            flags: OperationFlags::READ,
+            length: buffer.len().try_into().unwrap(),
+            buffer: buffer.as_mut_ptr(),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Write the bytes of `buffer` to the device
+    pub fn write(buffer: &'buf [u8]) -> Self {
+        Operation {
+            flags: OperationFlags::empty(),
+            length: buffer.len().try_into().unwrap(),
+            // The buffer is only read by the controller for writes
+            buffer: buffer.as_ptr() as *mut u8,
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Add flags to the operation, such as the SMBus ones
+    ///
+    /// `OperationFlags::READ` cannot be added, since it would let the
+    /// controller write to the buffer of a write operation.
+    pub fn with_flags(mut self, flags: OperationFlags) -> Self {
+        self.flags |= flags - OperationFlags::READ;
+        self
+    }
+
+    /// Flags of the operation
+    pub fn flags(&self) -> OperationFlags {
+        self.flags
+    }
+
+    /// Number of bytes read or written
+    pub fn len(&self) -> usize {
+        self.length as usize
+    }
+
+    /// Whether the operation transfers no data, like the quick commands of
+    /// SMBus
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+/// An I2C request, made of `N` operations (`EFI_I2C_REQUEST_PACKET`)
+///
+/// The request is executed as a single I2C transaction: the device is
+/// addressed once, and the bus is only released at the end.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RequestPacket<'buf, const N: usize> {
+    operation_count: usize,
+    operations: [Operation<'buf>; N],
+}
+
+impl<'buf, const N: usize> RequestPacket<'buf, N> {
+    /// Build a request from its operations
+    pub fn new(operations: [Operation<'buf>; N]) -> Self {
+        RequestPacket {
+            operation_count: N,
+            operations,
+        }
+    }
+
+    /// The operations of the request
+    pub fn operations(&self) -> &[Operation<'buf>] {
+        &self.operations
+    }
+
+    fn as_raw(&mut self) -> *mut RawRequestPacket {
+        (self as *mut Self).cast()
+    }
+}
+
+/// Type-erased `RequestPacket`, as seen by the firmware
+#[repr(C)]
+struct RawRequestPacket {
+    operation_count: usize,
+    operations: [Operation<'static>; 0],
+}
+
+/// The limits of an I2C controller (`EFI_I2C_CONTROLLER_CAPABILITIES`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ControllerCapabilities {
+    /// Size of this structure
+    pub structure_size: u32,
+    /// Maximum number of bytes of a read operation
+    pub maximum_receive_bytes: u32,
+    /// Maximum number of bytes of a write operation
+    pub maximum_transmit_bytes: u32,
+    /// Maximum number of bytes of all the operations of a request
+    pub maximum_total_bytes: u32,
+}
+
+/// Protocol to talk to an I2C device.
+///
+/// A device may answer at several addresses, the first being the one of
+/// index 0. They are configured by the platform, so the request functions
+/// take the index of the address rather than the address itself.
+#[repr(C)]
+#[unsafe_guid("b60a3e6b-18c4-46e5-a29a-c9a10665a28e")]
+#[derive(Protocol)]
+pub struct I2cIo {
+    queue_request: unsafe extern "efiapi" fn(
+        this: *const I2cIo,
+        slave_address_index: usize,
+        event: *mut c_void,
+        request_packet: *mut RawRequestPacket,
+        i2c_status: *mut Status,
+    ) -> Status,
+    device_guid: *const Guid,
+    device_index: u32,
+    hardware_revision: u32,
+    capabilities: *const ControllerCapabilities,
+}
+
+impl I2cIo {
+    /// Execute a request on the device.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`   An operation is larger than what
+    ///                                     the controller supports
+    /// * `uefi::Status::NO_RESPONSE`       The device did not acknowledge its
+    ///                                     address
+    /// * `uefi::Status::DEVICE_ERROR`      The device did not acknowledge a
+    ///                                     byte that was written
+    /// * `uefi::Status::NOT_FOUND`         `slave_address_index` is too large
+    /// * `uefi::Status::UNSUPPORTED`       The controller does not support
+    ///                                     the operations, like SMBus ones
+    pub fn queue_request<const N: usize>(
+        &mut self,
+        slave_address_index: usize,
+        packet: &mut RequestPacket<N>,
+    ) -> Result {
+        unsafe {
+            (self.queue_request)(
+                self,
+                slave_address_index,
+                ptr::null_mut(),
+                packet.as_raw(),
+                ptr::null_mut(),
+            )
+        }
+        .into()
+    }
+
+    /// Read bytes from the device
+    pub fn read(&mut self, slave_address_index: usize, buffer: &mut [u8]) -> Result {
+        let mut packet = RequestPacket::new([Operation::read(buffer)]);
+        self.queue_request(slave_address_index, &mut packet)
+    }
+
+    /// Write bytes to the device
+    pub fn write(&mut self, slave_address_index: usize, data: &[u8]) -> Result {
+        let mut packet = RequestPacket::new([Operation::write(data)]);
+        self.queue_request(slave_address_index, &mut packet)
+    }
+
+    /// Write bytes to the device, then read its answer in the same
+    /// transaction, as is done to read registers or EEPROMs
+    pub fn write_read(
+        &mut self,
+        slave_address_index: usize,
+        data: &[u8],
+        buffer: &mut [u8],
+    ) -> Result {
+        let mut packet = RequestPacket::new([Operation::write(data), Operation::read(buffer)]);
+        self.queue_request(slave_address_index, &mut packet)
+    }
+
+    /// GUID identifying the type of the device, as set by the platform
+    pub fn device_guid(&self) -> Option<&Guid> {
+        unsafe { self.device_guid.as_ref() }
+    }
+
+    /// Number distinguishing the devices of the same type
+    pub fn device_index(&self) -> u32 {
+        self.device_index
+    }
+
+    /// Revision of the hardware of the device, as set by the platform
+    pub fn hardware_revision(&self) -> u32 {
+        self.hardware_revision
+    }
+
+    /// Limits of the controller to which the device is connected
+    pub fn capabilities(&self) -> Option<&ControllerCapabilities> {
+        unsafe { self.capabilities.as_ref() }
+    }
+}
+
+/// Protocol to drive an I2C controller.
+///
+/// This protocol is used by the I2C host driver and bus drivers. Since the
+/// controller serves a single request at a time, using it while the bus
+/// driver is connected may make requests of the bus driver fail.
+#[repr(C)]
+#[unsafe_guid("cd72881f-45b5-4feb-98c8-313da8117462")]
+#[derive(Protocol)]
+pub struct I2cMaster {
+    set_bus_frequency:
+        unsafe extern "efiapi" fn(this: *const I2cMaster, bus_clock_hertz: *mut usize) -> Status,
+    reset: unsafe extern "efiapi" fn(this: *const I2cMaster) -> Status,
+    start_request: unsafe extern "efiapi" fn(
+        this: *const I2cMaster,
+        slave_address: usize,
+        request_packet: *mut RawRequestPacket,
+        event: *mut c_void,
+        i2c_status: *mut Status,
+    ) -> Status,
+    capabilities: *const ControllerCapabilities,
+}
+
+impl I2cMaster {
+    /// Set the frequency of the bus clock to at most `hertz`, and return the
+    /// frequency which was selected.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ALREADY_STARTED`  A request is in progress
+    /// * `uefi::Status::UNSUPPORTED`      The controller cannot run as slow
+    pub fn set_bus_frequency(&mut self, hertz: usize) -> Result<usize> {
+        let mut hertz = hertz;
+        unsafe { (self.set_bus_frequency)(self, &mut hertz) }.into_with_val(|| hertz)
+    }
+
+    /// Reset the controller, and configure it with the default frequency.
+    pub fn reset(&mut self) -> Result {
+        unsafe { (self.reset)(self) }.into()
+    }
+
+    /// Execute a request on the device at the given bus address.
+    ///
+    /// 10-bit addresses are distinguished by setting bit 31 of the address.
+    ///
+    /// See `I2cIo::queue_request` for the errors.
+    pub fn start_request<const N: usize>(
+        &mut self,
+        slave_address: usize,
+        packet: &mut RequestPacket<N>,
+    ) -> Result {
+        unsafe {
+            (self.start_request)(
+                self,
+                slave_address,
+                packet.as_raw(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        }
+        .into()
+    }
+
+    /// Limits of the controller
+    pub fn capabilities(&self) -> Option<&ControllerCapabilities> {
+        unsafe { self.capabilities.as_ref() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem;
+
+    #[test]
+    fn packet_layout() {
+        let data = [0x40];
+        let mut buffer = [0; 16];
+        let packet = RequestPacket::new([
+            Operation::write(&data),
+            Operation::read(&mut buffer).with_flags(OperationFlags::SMBUS_PEC),
+        ]);
+        assert_eq!(
+            mem::size_of_val(&packet),
+            mem::size_of::<usize>() + 2 * mem::size_of::<Operation>()
+        );
+        assert_eq!(packet.operation_count, 2);
+        let operations = packet.operations();
+        assert_eq!(operations[0].flags(), OperationFlags::empty());
+        assert_eq!(
+            operations[1].flags(),
+            OperationFlags::READ | OperationFlags::SMBUS_PEC
+        );
+        assert_eq!(operations[1].len(), 16);
+
+        let write = Operation::write(&data).with_flags(OperationFlags::READ);
+        assert_eq!(write.flags(), OperationFlags::empty());
+    }
+}
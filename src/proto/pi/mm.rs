@@ -0,0 +1,168 @@
+//! MM Communication protocol.
+//!
+//! Management mode (MM, or SMM on x86) handlers run apart from the rest of
+//! the firmware, and are reached by writing a message into a communication
+//! buffer. The message starts with a header giving the GUID of the handler
+//! and the length of the payload, which the handler replaces with its reply:
+//!
+//! ```no_run
+//! # use uefi::proto::pi::mm::{CommunicateBuffer, MmCommunication2};
+//! # use uefi::{Guid, ResultExt};
+//! # fn f(mm: &mut MmCommunication2, buffer: &mut [u8], handler: &Guid) -> uefi::Result {
+//! let mut message = CommunicateBuffer::new(buffer, handler, &[1, 0, 0, 0]).unwrap();
+//! mm.communicate(&mut message).discard_errdata()?.log();
+//! log::info!("Reply: {:?}", message.payload());
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! Firmwares usually require the buffer to be in a memory region set aside
+//! for the communication, which EDK2 describes in a configuration table.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Guid, Result, Status};
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::mem;
+
+/// Size of the header of the messages (`EFI_MM_COMMUNICATE_HEADER`)
+pub const HEADER_SIZE: usize = mem::size_of::<Guid>() + mem::size_of::<usize>();
+
+/// A message for an MM handler, written in a caller-provided buffer
+#[derive(Debug)]
+pub struct CommunicateBuffer<'buf> {
+    buffer: &'buf mut [u8],
+}
+
+impl<'buf> CommunicateBuffer<'buf> {
+    /// Write a message with `payload` for the handler identified by
+    /// `handler` into `buffer`, or return `None` if `buffer` is too small.
+    ///
+    /// The part of `buffer` after the payload is left for a longer reply.
+    pub fn new(buffer: &'buf mut [u8], handler: &Guid, payload: &[u8]) -> Option<Self> {
+        let end = HEADER_SIZE.checked_add(payload.len())?;
+        if buffer.len() < end {
+            return None;
+        }
+        let guid: [u8; 16] = unsafe { mem::transmute(*handler) };
+        buffer[..16].copy_from_slice(&guid);
+        buffer[HEADER_SIZE..end].copy_from_slice(payload);
+        let mut message = CommunicateBuffer { buffer };
+        message.set_message_length(payload.len());
+        Some(message)
+    }
+
+    /// The GUID of the handler
+    pub fn header_guid(&self) -> Guid {
+        let mut guid = [0; 16];
+        guid.copy_from_slice(&self.buffer[..16]);
+        unsafe { mem::transmute(guid) }
+    }
+
+    /// The length of the payload, as given by the header
+    pub fn message_length(&self) -> usize {
+        usize::from_ne_bytes(self.buffer[16..HEADER_SIZE].try_into().unwrap())
+    }
+
+    /// Set the length of the payload in the header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the payload does not fit in the buffer.
+    pub fn set_message_length(&mut self, len: usize) {
+        assert!(len <= self.capacity(), "payload larger than the buffer");
+        self.buffer[16..HEADER_SIZE].copy_from_slice(&len.to_ne_bytes());
+    }
+
+    /// The largest payload which fits in the buffer
+    pub fn capacity(&self) -> usize {
+        self.buffer.len() - HEADER_SIZE
+    }
+
+    /// The payload, which is the reply of the handler after `communicate`
+    ///
+    /// A length larger than the buffer, reported by a faulty handler, is
+    /// truncated.
+    pub fn payload(&self) -> &[u8] {
+        let len = self.message_length().min(self.capacity());
+        &self.buffer[HEADER_SIZE..HEADER_SIZE + len]
+    }
+
+    /// The payload, which can be modified in place
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let len = self.message_length().min(self.capacity());
+        &mut self.buffer[HEADER_SIZE..HEADER_SIZE + len]
+    }
+}
+
+/// The MM Communication 2 protocol.
+#[repr(C)]
+#[unsafe_guid("378daedc-f06b-4446-8314-40ab933c87a3")]
+#[derive(Protocol)]
+pub struct MmCommunication2 {
+    communicate: unsafe extern "efiapi" fn(
+        this: *const MmCommunication2,
+        comm_buffer_physical: *mut c_void,
+        comm_buffer_virtual: *mut c_void,
+        comm_size: *mut usize,
+    ) -> Status,
+}
+
+impl MmCommunication2 {
+    /// Send `message` to its handler, and wait for the reply, which replaces
+    /// the payload of `message`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BAD_BUFFER_SIZE`    The message is too large; the
+    ///                                      largest size supported, header
+    ///                                      included, is returned
+    /// * `uefi::Status::ACCESS_DENIED`      The buffer is not in the memory
+    ///                                      reserved for the communication
+    /// * `uefi::Status::INVALID_PARAMETER`  The message is malformed
+    /// * `uefi::Status::NOT_FOUND`          No handler is registered for the
+    ///                                      GUID of the message
+    pub fn communicate(&mut self, message: &mut CommunicateBuffer) -> Result<(), Option<usize>> {
+        // The whole buffer is given, so that the reply may be longer than the
+        // payload
+        let mut size = message.buffer.len();
+        let buffer = message.buffer.as_mut_ptr().cast();
+        // Boot services memory is identity-mapped, so both addresses match
+        let status = unsafe { (self.communicate)(self, buffer, buffer, &mut size) };
+        status.into_with(
+            || (),
+            |status| {
+                if status == Status::BAD_BUFFER_SIZE {
+                    Some(size)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn communicate_buffer() {
+        let handler = Guid::from_values(0x1234_5678, 0x9abc, 0xdef0, 0x1122, 0x3344_5566_7788);
+        let mut buffer = [0xff; HEADER_SIZE + 8];
+        assert!(CommunicateBuffer::new(&mut buffer, &handler, &[0; 9]).is_none());
+
+        let mut message = CommunicateBuffer::new(&mut buffer, &handler, &[1, 2, 3]).unwrap();
+        assert_eq!(message.header_guid(), handler);
+        assert_eq!(message.message_length(), 3);
+        assert_eq!(message.payload(), &[1, 2, 3]);
+
+        // A reply which is longer than the buffer is truncated
+        message.buffer[16..HEADER_SIZE].copy_from_slice(&100usize.to_ne_bytes());
+        assert_eq!(message.payload().len(), 8);
+        message.set_message_length(1);
+        message.payload_mut()[0] = 4;
+        assert_eq!(message.payload(), &[4]);
+        assert_eq!(&buffer[..4], &0x1234_5678u32.to_le_bytes());
+    }
+}
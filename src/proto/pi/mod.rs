@@ -3,4 +3,9 @@
 //! Contains protocols defined in UEFI's
 //! Platform Initialization (PI) Specification.
 
+pub mod cpu;
+pub mod fv;
+pub mod i2c;
+pub mod mm;
 pub mod mp;
+pub mod smbus;
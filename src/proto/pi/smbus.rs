@@ -0,0 +1,359 @@
+//! SMBus Host Controller protocol.
+//!
+//! SMBus is the I2C-based bus which connects the chipset to the small
+//! peripherals of the board, such as the SPD EEPROMs describing the memory
+//! modules or the battery controller:
+//!
+//! ```no_run
+//! # use uefi::proto::pi::smbus::{DeviceAddress, SmbusHc};
+//! # fn f(smbus: &mut SmbusHc) -> uefi::Result {
+//! // Type of the memory of the first DIMM, from its SPD
+//! let memory_type = smbus.read_byte(DeviceAddress::new(0x50), 2, false)?;
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Completion, Result, ResultExt, Status};
+use core::ffi::c_void;
+use core::mem;
+use core::ptr;
+use core::slice;
+
+/// Largest number of bytes of an SMBus block transfer
+pub const MAX_BLOCK_SIZE: usize = 32;
+
+newtype_enum! {
+    /// SMBus transaction (`EFI_SMBUS_OPERATION`)
+    pub enum Operation: u32 => {
+        /// Quick command, reading the single bit of the R/W flag
+        QUICK_READ          = 0,
+        /// Quick command, writing the single bit of the R/W flag
+        QUICK_WRITE         = 1,
+        /// Read a byte, without command code
+        RECEIVE_BYTE        = 2,
+        /// Write a byte, without command code
+        SEND_BYTE           = 3,
+        /// Read the byte designated by the command code
+        READ_BYTE           = 4,
+        /// Write the byte designated by the command code
+        WRITE_BYTE          = 5,
+        /// Read the word designated by the command code
+        READ_WORD           = 6,
+        /// Write the word designated by the command code
+        WRITE_WORD          = 7,
+        /// Read a block of up to 32 bytes
+        READ_BLOCK          = 8,
+        /// Write a block of up to 32 bytes
+        WRITE_BLOCK         = 9,
+        /// Write a word, and read a word in answer
+        PROCESS_CALL        = 10,
+        /// Write a block, and read a block in answer
+        BLOCK_PROCESS_CALL  = 11,
+    }
+}
+
+/// 7-bit address of an SMBus device (`EFI_SMBUS_DEVICE_ADDRESS`)
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DeviceAddress(usize);
+
+impl DeviceAddress {
+    /// Wrap a 7-bit address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `address` does not fit in 7 bits.
+    pub fn new(address: u8) -> Self {
+        assert!(address < 0x80, "SMBus addresses have 7 bits");
+        DeviceAddress(address.into())
+    }
+
+    /// The 7-bit address
+    pub fn address(self) -> u8 {
+        (self.0 & 0x7f) as u8
+    }
+}
+
+/// Unique device identifier, used by the Address Resolution Protocol
+/// (`EFI_SMBUS_UDID`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Udid {
+    /// Identifier defined by the vendor
+    pub vendor_specific_id: u32,
+    /// Device ID of the subsystem
+    pub subsystem_device_id: u16,
+    /// Vendor ID of the subsystem
+    pub subsystem_vendor_id: u16,
+    /// SMBus version and supported protocols
+    pub interface: u16,
+    /// Device ID
+    pub device_id: u16,
+    /// Vendor ID, of the PCI-SIG
+    pub vendor_id: u16,
+    /// Address type and PEC support
+    pub device_capabilities: u8,
+    /// Revision of the device
+    pub vendor_revision: u8,
+}
+
+/// Address assigned to a device by the Address Resolution Protocol
+/// (`EFI_SMBUS_DEVICE_MAP`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DeviceMap {
+    /// Assigned address
+    pub address: DeviceAddress,
+    /// Identifier of the device
+    pub udid: Udid,
+}
+
+/// The SMBus Host Controller protocol.
+///
+/// All the transfers can check a packet error code (PEC), if the device
+/// supports it.
+#[repr(C)]
+#[unsafe_guid("e49d33ed-513d-4634-b698-6f55aa751c1b")]
+#[derive(Protocol)]
+pub struct SmbusHc {
+    execute: unsafe extern "efiapi" fn(
+        this: *const SmbusHc,
+        slave_address: DeviceAddress,
+        command: usize,
+        operation: Operation,
+        pec_check: bool,
+        length: *mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    arp_device: unsafe extern "efiapi" fn(
+        this: *const SmbusHc,
+        arp_all: bool,
+        udid: *const Udid,
+        slave_address: *mut DeviceAddress,
+    ) -> Status,
+    get_arp_map: unsafe extern "efiapi" fn(
+        this: *const SmbusHc,
+        length: *mut usize,
+        device_map: *mut *const DeviceMap,
+    ) -> Status,
+    notify: usize,
+}
+
+impl SmbusHc {
+    /// Execute an SMBus transaction.
+    ///
+    /// `buffer` holds the data to write, and receives the data which is read.
+    /// The number of bytes read is returned.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::TIMEOUT`            The transaction did not finish in
+    ///                                      time
+    /// * `uefi::Status::DEVICE_ERROR`       The device did not acknowledge
+    ///                                      its address or command, or the
+    ///                                      packet error code did not match
+    /// * `uefi::Status::BUFFER_TOO_SMALL`   `buffer` is too small for the
+    ///                                      operation
+    /// * `uefi::Status::UNSUPPORTED`        The controller does not support
+    ///                                      the operation
+    pub fn execute(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        operation: Operation,
+        pec_check: bool,
+        buffer: &mut [u8],
+    ) -> Result<usize> {
+        let mut length = buffer.len();
+        let buffer = if buffer.is_empty() {
+            ptr::null_mut()
+        } else {
+            buffer.as_mut_ptr().cast()
+        };
+        unsafe {
+            (self.execute)(
+                self,
+                address,
+                command.into(),
+                operation,
+                pec_check,
+                &mut length,
+                buffer,
+            )
+        }
+        .into_with_val(|| length)
+    }
+
+    /// Send a quick command, whose only data is the R/W bit of the address
+    pub fn quick(&mut self, address: DeviceAddress, read: bool) -> Result {
+        let operation = if read {
+            Operation::QUICK_READ
+        } else {
+            Operation::QUICK_WRITE
+        };
+        self.execute(address, 0, operation, false, &mut [])
+            .map_inner(|_| ())
+    }
+
+    /// Read a byte, without command code
+    pub fn receive_byte(&mut self, address: DeviceAddress, pec_check: bool) -> Result<u8> {
+        let mut data = [0];
+        self.execute(address, 0, Operation::RECEIVE_BYTE, pec_check, &mut data)
+            .map_inner(|_| data[0])
+    }
+
+    /// Write a byte, without command code
+    pub fn send_byte(&mut self, address: DeviceAddress, value: u8, pec_check: bool) -> Result {
+        let mut data = [value];
+        self.execute(address, 0, Operation::SEND_BYTE, pec_check, &mut data)
+            .map_inner(|_| ())
+    }
+
+    /// Read the byte designated by `command`
+    pub fn read_byte(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        pec_check: bool,
+    ) -> Result<u8> {
+        let mut data = [0];
+        self.execute(address, command, Operation::READ_BYTE, pec_check, &mut data)
+            .map_inner(|_| data[0])
+    }
+
+    /// Write the byte designated by `command`
+    pub fn write_byte(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        value: u8,
+        pec_check: bool,
+    ) -> Result {
+        let mut data = [value];
+        self.execute(
+            address,
+            command,
+            Operation::WRITE_BYTE,
+            pec_check,
+            &mut data,
+        )
+        .map_inner(|_| ())
+    }
+
+    /// Read the word designated by `command`
+    pub fn read_word(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        pec_check: bool,
+    ) -> Result<u16> {
+        let mut data = [0; 2];
+        self.execute(address, command, Operation::READ_WORD, pec_check, &mut data)
+            .map_inner(|_| u16::from_le_bytes(data))
+    }
+
+    /// Write the word designated by `command`
+    pub fn write_word(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        value: u16,
+        pec_check: bool,
+    ) -> Result {
+        let mut data = value.to_le_bytes();
+        self.execute(
+            address,
+            command,
+            Operation::WRITE_WORD,
+            pec_check,
+            &mut data,
+        )
+        .map_inner(|_| ())
+    }
+
+    /// Write a word, and return the word sent back by the device
+    pub fn process_call(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        value: u16,
+        pec_check: bool,
+    ) -> Result<u16> {
+        let mut data = value.to_le_bytes();
+        self.execute(
+            address,
+            command,
+            Operation::PROCESS_CALL,
+            pec_check,
+            &mut data,
+        )
+        .map_inner(|_| u16::from_le_bytes(data))
+    }
+
+    /// Read a block, and return the part of `buffer` which was filled
+    pub fn read_block<'buf>(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        pec_check: bool,
+        buffer: &'buf mut [u8; MAX_BLOCK_SIZE],
+    ) -> Result<&'buf [u8]> {
+        let (status, length) = self
+            .execute(address, command, Operation::READ_BLOCK, pec_check, buffer)?
+            .split();
+        Ok(Completion::new(
+            status,
+            &buffer[..length.min(MAX_BLOCK_SIZE)],
+        ))
+    }
+
+    /// Write a block of up to `MAX_BLOCK_SIZE` bytes
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `data` is larger than a block
+    pub fn write_block(
+        &mut self,
+        address: DeviceAddress,
+        command: u8,
+        data: &[u8],
+        pec_check: bool,
+    ) -> Result {
+        if data.len() > MAX_BLOCK_SIZE {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        let mut block = [0; MAX_BLOCK_SIZE];
+        block[..data.len()].copy_from_slice(data);
+        let block = &mut block[..data.len()];
+        self.execute(address, command, Operation::WRITE_BLOCK, pec_check, block)
+            .map_inner(|_| ())
+    }
+
+    /// Assign addresses to all the devices which need one, using the Address
+    /// Resolution Protocol
+    pub fn arp_all(&mut self) -> Result {
+        unsafe { (self.arp_device)(self, true, ptr::null(), ptr::null_mut()) }.into()
+    }
+
+    /// Assign an address to the device with the given identifier, and return
+    /// it.
+    pub fn arp_device(&mut self, udid: &Udid) -> Result<DeviceAddress> {
+        let mut address = DeviceAddress(0);
+        unsafe { (self.arp_device)(self, false, udid, &mut address) }.into_with_val(|| address)
+    }
+
+    /// The addresses assigned by the Address Resolution Protocol
+    pub fn arp_map(&self) -> Result<&[DeviceMap]> {
+        let mut size = 0;
+        let mut map = ptr::null();
+        unsafe { (self.get_arp_map)(self, &mut size, &mut map) }.into_with_val(|| {
+            if map.is_null() {
+                &[][..]
+            } else {
+                // The size of the map is in bytes
+                unsafe { slice::from_raw_parts(map, size / mem::size_of::<DeviceMap>()) }
+            }
+        })
+    }
+}
@@ -0,0 +1,226 @@
+//! Reset Notification protocol.
+//!
+//! The functions registered with this protocol are called by `ResetSystem`
+//! before the platform is reset, which lets drivers save their state, like
+//! flushing a log to persistent storage:
+//!
+//! ```no_run
+//! # use uefi::proto::reset_notification::ResetNotification;
+//! # use uefi::table::runtime::ResetType;
+//! # use uefi::Status;
+//! fn flush_log(_ty: ResetType, _status: Status, _data: &[u8]) {
+//!     // Write the log to a variable
+//! }
+//!
+//! # fn f(notification: &mut ResetNotification) -> uefi::Result {
+//! unsafe { notification.register(flush_log) }?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::Protocol;
+use crate::table::runtime::ResetType;
+use crate::{unsafe_guid, Result, Status};
+use core::ffi::c_void;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A function called before a reset, with the arguments of `ResetSystem`
+/// (`EFI_RESET_SYSTEM`)
+pub type ResetSystemFn = unsafe extern "efiapi" fn(
+    reset_type: u32,
+    status: Status,
+    data_size: usize,
+    data: *const c_void,
+);
+
+/// The Reset Notification protocol.
+#[repr(C)]
+#[unsafe_guid("9da34ae0-eaf9-4bbf-8ec3-fd60226c44be")]
+#[derive(Protocol)]
+pub struct ResetNotification {
+    register_reset_notify: unsafe extern "efiapi" fn(
+        this: *mut ResetNotification,
+        reset_function: ResetSystemFn,
+    ) -> Status,
+    unregister_reset_notify: unsafe extern "efiapi" fn(
+        this: *mut ResetNotification,
+        reset_function: ResetSystemFn,
+    ) -> Status,
+}
+
+/// Largest number of functions registered with `ResetNotification::register`
+pub const MAX_NOTIFY_FUNCTIONS: usize = 8;
+
+/// The functions registered with `ResetNotification::register`, 0 being a
+/// free slot
+static NOTIFY_FUNCTIONS: [AtomicUsize; MAX_NOTIFY_FUNCTIONS] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+impl ResetNotification {
+    /// Register `notify` to be called before the platform is reset.
+    ///
+    /// `notify` is given the type of the reset, its status, and the data of
+    /// the reset, which starts with a nul-terminated UCS-2 string describing
+    /// it. Up to `MAX_NOTIFY_FUNCTIONS` functions can be registered.
+    ///
+    /// # Safety
+    ///
+    /// `notify` may be called after boot services have been exited, and must
+    /// be unregistered before the image which contains it is unloaded.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ALREADY_STARTED`   `notify` is already registered
+    /// * `uefi::Status::OUT_OF_RESOURCES`  Too many functions are registered
+    pub unsafe fn register(&mut self, notify: fn(ResetType, Status, &[u8])) -> Result {
+        let notify = notify as usize;
+        if NOTIFY_FUNCTIONS
+            .iter()
+            .any(|slot| slot.load(Ordering::Relaxed) == notify)
+        {
+            return Err(Status::ALREADY_STARTED.into());
+        }
+        let slot = NOTIFY_FUNCTIONS.iter().find(|slot| {
+            slot.compare_exchange(0, notify, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        });
+        if slot.is_none() {
+            return Err(Status::OUT_OF_RESOURCES.into());
+        }
+        // The dispatcher is registered once, for all the functions
+        match self.register_reset_notify(dispatch) {
+            Err(err) if err.status() == Status::ALREADY_STARTED => Status::SUCCESS.into(),
+            Err(err) => {
+                slot.unwrap().store(0, Ordering::Release);
+                Err(err)
+            }
+            ok => ok,
+        }
+    }
+
+    /// Unregister `notify`, which was registered by `register`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `notify` is not registered
+    pub fn unregister(&mut self, notify: fn(ResetType, Status, &[u8])) -> Result {
+        let notify = notify as usize;
+        let slot = NOTIFY_FUNCTIONS.iter().find(|slot| {
+            slot.compare_exchange(notify, 0, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        });
+        if slot.is_none() {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        let empty = NOTIFY_FUNCTIONS
+            .iter()
+            .all(|slot| slot.load(Ordering::Acquire) == 0);
+        if empty {
+            unsafe { self.unregister_reset_notify(dispatch) }
+        } else {
+            Status::SUCCESS.into()
+        }
+    }
+
+    /// Register a raw function to be called before the platform is reset.
+    ///
+    /// # Safety
+    ///
+    /// See `register`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ALREADY_STARTED`   `notify` is already registered
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The function could not be registered
+    pub unsafe fn register_reset_notify(&mut self, notify: ResetSystemFn) -> Result {
+        (self.register_reset_notify)(self, notify).into()
+    }
+
+    /// Unregister a raw function registered by `register_reset_notify`.
+    ///
+    /// # Safety
+    ///
+    /// `notify` must not be one of the functions of the other drivers.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `notify` is not registered
+    pub unsafe fn unregister_reset_notify(&mut self, notify: ResetSystemFn) -> Result {
+        (self.unregister_reset_notify)(self, notify).into()
+    }
+}
+
+/// Convert the reset type given by the firmware
+fn reset_type(raw: u32) -> Option<ResetType> {
+    match raw {
+        0 => Some(ResetType::Cold),
+        1 => Some(ResetType::Warm),
+        2 => Some(ResetType::Shutdown),
+        3 => Some(ResetType::PlatformSpecific),
+        _ => None,
+    }
+}
+
+/// The function registered with the firmware, which calls the functions
+/// registered with `ResetNotification::register`
+unsafe extern "efiapi" fn dispatch(
+    reset_type_raw: u32,
+    status: Status,
+    data_size: usize,
+    data: *const c_void,
+) {
+    // The specification defines no other reset type
+    let ty = match reset_type(reset_type_raw) {
+        Some(ty) => ty,
+        None => return,
+    };
+    let data = if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data.cast::<u8>(), data_size)
+    };
+    for slot in NOTIFY_FUNCTIONS.iter() {
+        let notify = slot.load(Ordering::Acquire);
+        if notify != 0 {
+            let notify: fn(ResetType, Status, &[u8]) = core::mem::transmute(notify);
+            notify(ty, status, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count(ty: ResetType, status: Status, data: &[u8]) {
+        assert_eq!(ty, ResetType::Warm);
+        assert_eq!(status, Status::ABORTED);
+        assert_eq!(data, &[1, 2]);
+        CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn dispatch_notify_functions() {
+        NOTIFY_FUNCTIONS[3].store(
+            count as fn(ResetType, Status, &[u8]) as usize,
+            Ordering::Relaxed,
+        );
+        unsafe {
+            dispatch(1, Status::ABORTED, 2, [1u8, 2].as_ptr().cast());
+            dispatch(7, Status::ABORTED, 0, core::ptr::null());
+        }
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        NOTIFY_FUNCTIONS[3].store(0, Ordering::Relaxed);
+    }
+}
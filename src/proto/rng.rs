@@ -0,0 +1,91 @@
+//! Random Number Generator protocol.
+//!
+//! This protocol gives access to the entropy source of the platform, either
+//! directly or through a deterministic generator seeded by it. Many firmwares
+//! do not provide it; `uefi::entropy::fill_random` falls back to the CPU when
+//! it is missing.
+
+use crate::proto::Protocol;
+use crate::{unsafe_guid, Guid, Result, Status};
+use core::{mem, ptr};
+
+/// Algorithms of the generator (`EFI_RNG_ALGORITHM`)
+pub mod algorithm {
+    use crate::Guid;
+
+    /// NIST SP 800-90 Hash_DRBG, using SHA-256
+    pub const SP800_90_HASH_256: Guid =
+        Guid::from_values(0xa7af67cb, 0x603b, 0x4d42, 0xba21, 0x70bfb6293f96);
+    /// NIST SP 800-90 HMAC_DRBG, using SHA-256
+    pub const SP800_90_HMAC_256: Guid =
+        Guid::from_values(0xc5149b43, 0xae85, 0x4f53, 0x9982, 0xb94335d3a9e7);
+    /// NIST SP 800-90 CTR_DRBG, using AES-256
+    pub const SP800_90_CTR_256: Guid =
+        Guid::from_values(0x44f0de6e, 0x4d8c, 0x4045, 0xa8c7, 0x4dd168856b9e);
+    /// ANSI X9.31, using 3DES
+    pub const X9_31_3DES: Guid =
+        Guid::from_values(0x63c4785a, 0xca34, 0x4012, 0xa3c8, 0x0b6a324f5546);
+    /// ANSI X9.31, using AES
+    pub const X9_31_AES: Guid =
+        Guid::from_values(0xacd03321, 0x777e, 0x4d3d, 0xb1c8, 0x20cfd88820c9);
+    /// The output of the entropy source, without post-processing
+    pub const RAW: Guid = Guid::from_values(0xe43176d7, 0xb6e8, 0x4827, 0xb784, 0x7ffdc4b68561);
+}
+
+/// The Random Number Generator protocol.
+#[repr(C)]
+#[unsafe_guid("3152bca5-eade-433d-862e-c01cdc291f44")]
+#[derive(Protocol)]
+pub struct Rng {
+    get_info: unsafe extern "efiapi" fn(
+        this: *mut Rng,
+        algorithm_list_size: *mut usize,
+        algorithm_list: *mut Guid,
+    ) -> Status,
+    get_rng: unsafe extern "efiapi" fn(
+        this: *mut Rng,
+        algorithm: *const Guid,
+        value_length: usize,
+        value: *mut u8,
+    ) -> Status,
+}
+
+impl Rng {
+    /// Fill `algorithms` with the algorithms supported by the generator, and
+    /// return the number of them.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  `algorithms` is too small; the
+    ///                                     number of algorithms is returned
+    /// * `uefi::Status::UNSUPPORTED`       The algorithms cannot be listed
+    /// * `uefi::Status::DEVICE_ERROR`      A hardware error occurred
+    pub fn get_info(&mut self, algorithms: &mut [Guid]) -> Result<usize, Option<usize>> {
+        let guid_size = mem::size_of::<Guid>();
+        let mut size = mem::size_of_val(algorithms);
+        let status = unsafe { (self.get_info)(self, &mut size, algorithms.as_mut_ptr()) };
+        status.into_with(
+            || size / guid_size,
+            |status| {
+                if status == Status::BUFFER_TOO_SMALL {
+                    Some(size / guid_size)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    /// Fill `buffer` with random bytes, generated by `algorithm`, or by the
+    /// default algorithm of the platform if it is `None`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::UNSUPPORTED`   `algorithm` is not supported
+    /// * `uefi::Status::DEVICE_ERROR`  The entropy source failed
+    /// * `uefi::Status::NOT_READY`     Not enough entropy is available yet
+    pub fn get_rng(&mut self, algorithm: Option<&Guid>, buffer: &mut [u8]) -> Result {
+        let algorithm = algorithm.map_or(ptr::null(), |algorithm| algorithm as *const Guid);
+        unsafe { (self.get_rng)(self, algorithm, buffer.len(), buffer.as_mut_ptr()) }.into()
+    }
+}
@@ -0,0 +1,113 @@
+//! Deferred Image Load protocol.
+//!
+//! When the security policy of the platform does not allow an image to be
+//! loaded before a user is identified, like a driver of an option ROM, the
+//! load is deferred instead of denied. This protocol lists the deferred
+//! images, so that they can be loaded again once a user is authenticated:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::security::deferred_image::DeferredImageLoad;
+//! # fn f(bt: &BootServices, image: Handle, deferred: &DeferredImageLoad) -> uefi::Result {
+//! for deferred_image in deferred.images() {
+//!     if !deferred_image.boot_option {
+//!         deferred_image.load(bt, image)?;
+//!     }
+//! }
+//! # Ok(().into())
+//! # }
+//! ```
+
+use crate::proto::device_path::DevicePath;
+use crate::proto::Protocol;
+use crate::table::boot::BootServices;
+use crate::{unsafe_guid, Handle, Result, Status};
+use core::ffi::c_void;
+use core::ptr;
+use core::slice;
+
+/// The Deferred Image Load protocol.
+#[repr(C)]
+#[unsafe_guid("15853d7c-3ddf-43e0-a1cb-ebf85b8f872c")]
+#[derive(Protocol)]
+pub struct DeferredImageLoad {
+    get_image_info: unsafe extern "efiapi" fn(
+        this: *const DeferredImageLoad,
+        image_index: usize,
+        image_device_path: *mut *const DevicePath,
+        image: *mut *const c_void,
+        image_size: *mut usize,
+        boot_option: *mut bool,
+    ) -> Status,
+}
+
+impl DeferredImageLoad {
+    /// Get the deferred image at `index`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  There are at most `index` deferred images
+    pub fn image_info(&self, index: usize) -> Result<DeferredImage<'_>> {
+        let mut device_path = ptr::null();
+        let mut image = ptr::null();
+        let mut size = 0;
+        let mut boot_option = false;
+        unsafe {
+            (self.get_image_info)(
+                self,
+                index,
+                &mut device_path,
+                &mut image,
+                &mut size,
+                &mut boot_option,
+            )
+        }
+        .into_with_val(|| DeferredImage {
+            device_path: unsafe { device_path.as_ref() },
+            image: if image.is_null() {
+                &[]
+            } else {
+                unsafe { slice::from_raw_parts(image.cast(), size) }
+            },
+            boot_option,
+        })
+    }
+
+    /// Iterate over the deferred images, in the order in which they were
+    /// deferred
+    pub fn images(&self) -> impl Iterator<Item = DeferredImage<'_>> + '_ {
+        (0..)
+            .map(move |index| self.image_info(index))
+            .take_while(|info| info.is_ok())
+            .filter_map(|info| info.ok().map(|info| info.log()))
+    }
+}
+
+/// An image whose load was deferred by the security policy
+#[derive(Copy, Clone)]
+pub struct DeferredImage<'protocol> {
+    /// The device path the image was read from, if it is known
+    pub device_path: Option<&'protocol DevicePath>,
+    /// The contents of the image
+    pub image: &'protocol [u8],
+    /// Whether the image was to be started from a boot option, rather than
+    /// loaded as a driver
+    pub boot_option: bool,
+}
+
+impl DeferredImage<'_> {
+    /// Submit the image to the security policy again, and load it if it is
+    /// now allowed.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::SECURITY_VIOLATION`  The image is still not allowed
+    /// * `uefi::Status::ACCESS_DENIED`       The image was deferred again,
+    ///                                       since no user is authenticated
+    pub fn load(&self, bt: &BootServices, parent: Handle) -> Result<Handle> {
+        match self.device_path {
+            Some(device_path) => bt.load_image_from_device_path(parent, device_path, self.image),
+            None => bt.load_image_from_buffer(parent, self.image),
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Security protocols.
+//!
+//! These protocols expose the decisions of the security policy of the
+//! platform, and let applications take part in them.
+
+pub mod deferred_image;
+pub mod tcg2;
+pub mod user;
@@ -0,0 +1,247 @@
+//! TCG2 protocol.
+//!
+//! On platforms with a TPM 2.0, measured boot extends the digest of each
+//! image into a PCR before running it, and records it in the event log. The
+//! firmware does so for the images it loads; loaders which check or run
+//! images themselves use this protocol to measure them:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::pe::PeImage;
+//! # use uefi::proto::security::tcg2::Tcg2;
+//! # fn f(tcg: &mut Tcg2, data: &[u8]) -> uefi::Result {
+//! let image = PeImage::parse(data).map_err(|_| Status::LOAD_ERROR)?;
+//! // PCR 4 holds the boot manager and the images it starts
+//! let digest = tcg.measure_pe(&image, 4).discard_errdata()?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+#[cfg(feature = "exts")]
+use crate::alloc_api::vec::Vec;
+use crate::pe::PeImage;
+use crate::proto::Protocol;
+use crate::result::Error;
+use crate::{unsafe_guid, Result, Status};
+use bitflags::bitflags;
+use core::ffi::c_void;
+#[cfg(feature = "exts")]
+use core::mem;
+
+bitflags! {
+    /// Hash algorithms of the PCR banks of the TPM
+    pub struct HashAlgorithms: u32 {
+        /// SHA-1
+        const SHA1 = 0x0000_0001;
+        /// SHA-256
+        const SHA256 = 0x0000_0002;
+        /// SHA-384
+        const SHA384 = 0x0000_0004;
+        /// SHA-512
+        const SHA512 = 0x0000_0008;
+        /// SM3-256
+        const SM3_256 = 0x0000_0010;
+    }
+}
+
+bitflags! {
+    /// Flags of `Tcg2::hash_log_extend_event`
+    pub struct ExtendFlags: u64 {
+        /// Extend the PCR without recording the event in the log
+        const EXTEND_ONLY = 0x0000_0001;
+        /// The data is a PE/COFF image, whose Authenticode hash is measured
+        /// instead of the digest of the whole data
+        const PE_COFF_IMAGE = 0x0000_0010;
+    }
+}
+
+newtype_enum! {
+    /// Type of an event of the log
+    pub enum EventType: u32 => {
+        /// The boot loader, measured by the firmware
+        IPL = 0x0000_000d,
+        /// An action of the firmware, described by a string
+        EFI_ACTION = 0x8000_0007,
+        /// An application loaded by boot services
+        EFI_BOOT_SERVICES_APPLICATION = 0x8000_0003,
+        /// A driver loaded by boot services
+        EFI_BOOT_SERVICES_DRIVER = 0x8000_0004,
+        /// A driver loaded by runtime services
+        EFI_RUNTIME_SERVICES_DRIVER = 0x8000_0005,
+    }
+}
+
+/// Size of `EFI_TCG2_EVENT_HEADER`
+#[cfg(feature = "exts")]
+const EVENT_HEADER_SIZE: usize = 14;
+
+/// Version of `EFI_TCG2_EVENT_HEADER`
+#[cfg(feature = "exts")]
+const EVENT_HEADER_VERSION: u16 = 1;
+
+/// Structure tag of commands with an authorization area (`TPM_ST_SESSIONS`)
+const TPM_ST_SESSIONS: u16 = 0x8002;
+/// Command code of `TPM2_PCR_Extend`
+const TPM_CC_PCR_EXTEND: u32 = 0x0000_0182;
+/// Password authorization session, with an empty password (`TPM_RS_PW`)
+const TPM_RS_PW: u32 = 0x4000_0009;
+/// Identifier of SHA-256 in TPM commands (`TPM_ALG_SHA256`)
+const TPM_ALG_SHA256: u16 = 0x000b;
+/// Size of a `TPM2_PCR_Extend` command with a single SHA-256 digest
+const PCR_EXTEND_SIZE: usize = 65;
+/// Size of the header of a TPM response
+const RESPONSE_HEADER_SIZE: usize = 10;
+
+/// The TCG2 protocol.
+#[repr(C)]
+#[unsafe_guid("607f766c-7455-42be-930b-e4d76db2720f")]
+#[derive(Protocol)]
+pub struct Tcg2 {
+    get_capability: usize,
+    get_event_log: usize,
+    hash_log_extend_event: unsafe extern "efiapi" fn(
+        this: *mut Tcg2,
+        flags: ExtendFlags,
+        data: u64,
+        data_len: u64,
+        event: *const c_void,
+    ) -> Status,
+    submit_command: unsafe extern "efiapi" fn(
+        this: *mut Tcg2,
+        input_size: u32,
+        input: *const u8,
+        output_size: u32,
+        output: *mut u8,
+    ) -> Status,
+    get_active_pcr_banks:
+        unsafe extern "efiapi" fn(this: *mut Tcg2, banks: *mut HashAlgorithms) -> Status,
+    set_active_pcr_banks: usize,
+    get_result_of_set_active_pcr_banks: usize,
+}
+
+impl Tcg2 {
+    /// The PCR banks which are extended
+    pub fn active_pcr_banks(&mut self) -> Result<HashAlgorithms> {
+        let mut banks = HashAlgorithms::empty();
+        unsafe { (self.get_active_pcr_banks)(self, &mut banks) }.into_with_val(|| banks)
+    }
+
+    /// Hash `data`, extend PCR `pcr` of all the active banks with the
+    /// digest, and record an event of type `event_type` with `event` as its
+    /// description.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `pcr` is not a valid PCR
+    /// * `uefi::Status::UNSUPPORTED`        `data` is not a PE/COFF image,
+    ///                                      with `PE_COFF_IMAGE`
+    /// * `uefi::Status::VOLUME_FULL`        The event log is full
+    /// * `uefi::Status::DEVICE_ERROR`       The TPM failed
+    #[cfg(feature = "exts")]
+    pub fn hash_log_extend_event(
+        &mut self,
+        flags: ExtendFlags,
+        data: &[u8],
+        pcr: u32,
+        event_type: EventType,
+        event: &[u8],
+    ) -> Result {
+        // `EFI_TCG2_EVENT` is packed, and its description follows the header
+        let size = mem::size_of::<u32>() + EVENT_HEADER_SIZE + event.len();
+        let mut buffer = Vec::with_capacity(size);
+        buffer.extend_from_slice(&(size as u32).to_le_bytes());
+        buffer.extend_from_slice(&(EVENT_HEADER_SIZE as u32).to_le_bytes());
+        buffer.extend_from_slice(&EVENT_HEADER_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&pcr.to_le_bytes());
+        buffer.extend_from_slice(&event_type.0.to_le_bytes());
+        buffer.extend_from_slice(event);
+        unsafe {
+            (self.hash_log_extend_event)(
+                self,
+                flags,
+                data.as_ptr() as u64,
+                data.len() as u64,
+                buffer.as_ptr().cast(),
+            )
+        }
+        .into()
+    }
+
+    /// Send a raw command to the TPM, and return the size of its response.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  `output` is too small for the
+    ///                                     response
+    /// * `uefi::Status::DEVICE_ERROR`      The TPM failed
+    pub fn submit_command(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize> {
+        let status = unsafe {
+            (self.submit_command)(
+                self,
+                input.len() as u32,
+                input.as_ptr(),
+                output.len() as u32,
+                output.as_mut_ptr(),
+            )
+        };
+        // The size of the response is in its header
+        status.into_with_val(|| match output.get(2..6) {
+            Some(size) => u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize,
+            None => 0,
+        })
+    }
+
+    /// Extend PCR `pcr` of the SHA-256 bank with `digest`.
+    ///
+    /// No event is recorded in the log, so verifiers which replay the log
+    /// must learn about this measurement by other means; the firmware
+    /// records it with `hash_log_extend_event`, at the cost of hashing the
+    /// data itself.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::DEVICE_ERROR`  The TPM returned the response code
+    ///                                 in the error data, or failed
+    pub fn extend_pcr_sha256(&mut self, pcr: u32, digest: &[u8; 32]) -> Result<(), Option<u32>> {
+        let mut command = [0; PCR_EXTEND_SIZE];
+        command[0..2].copy_from_slice(&TPM_ST_SESSIONS.to_be_bytes());
+        command[2..6].copy_from_slice(&(PCR_EXTEND_SIZE as u32).to_be_bytes());
+        command[6..10].copy_from_slice(&TPM_CC_PCR_EXTEND.to_be_bytes());
+        command[10..14].copy_from_slice(&pcr.to_be_bytes());
+        // Authorization area: an empty password session, without nonce,
+        // attributes nor HMAC
+        command[14..18].copy_from_slice(&9u32.to_be_bytes());
+        command[18..22].copy_from_slice(&TPM_RS_PW.to_be_bytes());
+        // A single digest
+        command[27..31].copy_from_slice(&1u32.to_be_bytes());
+        command[31..33].copy_from_slice(&TPM_ALG_SHA256.to_be_bytes());
+        command[33..].copy_from_slice(digest);
+
+        let mut response = [0; RESPONSE_HEADER_SIZE + 4];
+        let completion = self
+            .submit_command(&command, &mut response)
+            .map_err(|err| Error::new(err.status(), None))?;
+        match u32::from_be_bytes([response[6], response[7], response[8], response[9]]) {
+            0 => Ok(completion.map(|_| ())),
+            code => Err(Error::new(Status::DEVICE_ERROR, Some(code))),
+        }
+    }
+
+    /// Compute the Authenticode hash of `image`, extend PCR `pcr` of the
+    /// SHA-256 bank with it, and return it.
+    ///
+    /// Like `extend_pcr_sha256`, this does not record an event in the log.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::LOAD_ERROR`    The headers of the image are invalid
+    /// * `uefi::Status::DEVICE_ERROR`  The TPM returned the response code
+    ///                                 in the error data, or failed
+    pub fn measure_pe(&mut self, image: &PeImage, pcr: u32) -> Result<[u8; 32], Option<u32>> {
+        let digest = image
+            .authenticode_sha256()
+            .map_err(|_| Error::new(Status::LOAD_ERROR, None))?;
+        self.extend_pcr_sha256(pcr, &digest)?.log();
+        Ok(digest.into())
+    }
+}
@@ -0,0 +1,464 @@
+//! User Manager protocol.
+//!
+//! The user manager keeps the profiles of the users of the platform, made of
+//! information records such as their name or the credentials which identify
+//! them. Identifying a user runs the credential providers of the platform,
+//! like password or smart card prompts, and sets the current user, whose
+//! access policy the firmware then applies.
+
+use crate::proto::Protocol;
+use crate::result::Error;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{string::String, vec::Vec},
+    CStr16,
+};
+use crate::{unsafe_guid, Completion, Guid, Handle, Result, Status};
+use bitflags::bitflags;
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+/// A user profile (`EFI_USER_PROFILE_HANDLE`)
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UserProfile(NonNull<c_void>);
+
+/// An information record of a user profile (`EFI_USER_INFO_HANDLE`)
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UserInfoHandle(NonNull<c_void>);
+
+/// Pointer to a possibly absent profile or record
+fn as_ptr<T>(handle: Option<T>) -> *mut c_void
+where
+    T: Into<NonNull<c_void>>,
+{
+    handle.map_or(ptr::null_mut(), |handle| handle.into().as_ptr())
+}
+
+impl From<UserProfile> for NonNull<c_void> {
+    fn from(user: UserProfile) -> Self {
+        user.0
+    }
+}
+
+impl From<UserInfoHandle> for NonNull<c_void> {
+    fn from(info: UserInfoHandle) -> Self {
+        info.0
+    }
+}
+
+newtype_enum! {
+    /// Type of an information record (`EFI_USER_INFO_*_RECORD`)
+    pub enum UserInfoType: u8 => {
+        /// Record without data
+        EMPTY                    = 0x00,
+        /// Name of the user, as a nul-terminated UCS-2 string
+        NAME                     = 0x01,
+        /// Biometric data, in the CBEFF format
+        CBEFF                    = 0x02,
+        /// Date and time at which the profile was created
+        CREATE_DATE              = 0x03,
+        /// Date and time at which the profile was last used
+        USAGE_DATE               = 0x04,
+        /// Number of times the profile was used
+        USAGE_COUNT              = 0x05,
+        /// Unique identifier of the profile, 16 bytes
+        IDENTIFIER               = 0x06,
+        /// GUID of the type of a credential of the user
+        CREDENTIAL_TYPE          = 0x07,
+        /// Name of the type of a credential
+        CREDENTIAL_TYPE_NAME     = 0x08,
+        /// GUID of the provider of a credential
+        CREDENTIAL_PROVIDER      = 0x09,
+        /// Name of the provider of a credential
+        CREDENTIAL_PROVIDER_NAME = 0x0a,
+        /// PKCS#11 URI of a credential
+        PKCS11                   = 0x0b,
+        /// False acceptance rate of a biometric credential
+        FAR                      = 0x0c,
+        /// Number of failed identification attempts allowed
+        RETRY                    = 0x0d,
+        /// Rights of the user
+        ACCESS_POLICY            = 0x0e,
+        /// Credentials which identify the user
+        IDENTITY_POLICY          = 0x0f,
+        /// Data defined by a GUID
+        GUID                     = 0xff,
+    }
+}
+
+bitflags! {
+    /// Attributes of an information record
+    ///
+    /// When none of the storage flags is set, the record is volatile.
+    pub struct UserInfoAttributes: u16 {
+        /// The record is stored by the credential provider.
+        const STORAGE_CREDENTIAL_NV = 0x0001;
+        /// The record is stored by the platform.
+        const STORAGE_PLATFORM_NV = 0x0002;
+        /// Only the user manager can change the record.
+        const PROTECTED = 0x0010;
+        /// The record can be read without identifying the user.
+        const PUBLIC = 0x0020;
+        /// There is at most one record of this type per profile.
+        const EXCLUSIVE = 0x0080;
+    }
+}
+
+/// Header of an information record (`EFI_USER_INFO`)
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct UserInfoHeader {
+    credential: Guid,
+    info_type: UserInfoType,
+    reserved: u8,
+    attributes: u16,
+    info_size: u32,
+}
+
+/// An information record, read from a buffer
+#[derive(Debug, Copy, Clone)]
+pub struct UserInfo<'buf> {
+    header: UserInfoHeader,
+    data: &'buf [u8],
+}
+
+impl<'buf> UserInfo<'buf> {
+    /// Parse the record at the start of `bytes`, which need not be aligned
+    pub fn parse(bytes: &'buf [u8]) -> Option<Self> {
+        if bytes.len() < mem::size_of::<UserInfoHeader>() {
+            return None;
+        }
+        let header: UserInfoHeader =
+            unsafe { bytes.as_ptr().cast::<UserInfoHeader>().read_unaligned() };
+        // The size includes the header
+        let size: usize = header.info_size.try_into().ok()?;
+        let data = bytes.get(mem::size_of::<UserInfoHeader>()..size)?;
+        Some(UserInfo { header, data })
+    }
+
+    /// The credential provider which owns the record, or a null GUID if it
+    /// is owned by the user manager
+    pub fn credential(&self) -> Guid {
+        self.header.credential
+    }
+
+    /// The type of the record
+    pub fn info_type(&self) -> UserInfoType {
+        self.header.info_type
+    }
+
+    /// The attributes of the record
+    pub fn attributes(&self) -> UserInfoAttributes {
+        UserInfoAttributes::from_bits_truncate(self.header.attributes)
+    }
+
+    /// The data of the record, following the header
+    pub fn data(&self) -> &'buf [u8] {
+        self.data
+    }
+
+    /// The name held by a `NAME`, `CREDENTIAL_TYPE_NAME` or
+    /// `CREDENTIAL_PROVIDER_NAME` record
+    #[cfg(feature = "exts")]
+    pub fn name(&self) -> Option<String> {
+        match self.info_type() {
+            UserInfoType::NAME
+            | UserInfoType::CREDENTIAL_TYPE_NAME
+            | UserInfoType::CREDENTIAL_PROVIDER_NAME => {}
+            _ => return None,
+        }
+        let codes = self
+            .data
+            .chunks_exact(2)
+            .map(|code| u16::from_le_bytes([code[0], code[1]]))
+            .take_while(|&code| code != 0);
+        core::char::decode_utf16(codes)
+            .collect::<core::result::Result<_, _>>()
+            .ok()
+    }
+
+    /// Encode a record, to be given to `UserManager::set_info` or
+    /// `UserManager::find`
+    #[cfg(feature = "exts")]
+    pub fn encode(
+        credential: Guid,
+        info_type: UserInfoType,
+        attributes: UserInfoAttributes,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let size = mem::size_of::<UserInfoHeader>() + data.len();
+        let header = UserInfoHeader {
+            credential,
+            info_type,
+            reserved: 0,
+            attributes: attributes.bits(),
+            info_size: size.try_into().unwrap(),
+        };
+        let mut record = Vec::with_capacity(size);
+        let header = unsafe {
+            core::slice::from_raw_parts(
+                &header as *const UserInfoHeader as *const u8,
+                mem::size_of::<UserInfoHeader>(),
+            )
+        };
+        record.extend_from_slice(header);
+        record.extend_from_slice(data);
+        record
+    }
+
+    /// Encode a `NAME` record, owned by the user manager
+    #[cfg(feature = "exts")]
+    pub fn encode_name(name: &CStr16, attributes: UserInfoAttributes) -> Vec<u8> {
+        let data: Vec<u8> = name
+            .to_u16_slice_with_nul()
+            .iter()
+            .flat_map(|code| code.to_le_bytes())
+            .collect();
+        Self::encode(Guid::default(), UserInfoType::NAME, attributes, &data)
+    }
+}
+
+/// The User Manager protocol.
+///
+/// There is a single instance of this protocol.
+#[repr(C)]
+#[unsafe_guid("6fd5b00c-d426-4283-9887-6cf5cf1cb1fe")]
+#[derive(Protocol)]
+pub struct UserManager {
+    create: unsafe extern "efiapi" fn(this: *const UserManager, user: *mut *mut c_void) -> Status,
+    delete: unsafe extern "efiapi" fn(this: *const UserManager, user: *mut c_void) -> Status,
+    get_next: unsafe extern "efiapi" fn(this: *const UserManager, user: *mut *mut c_void) -> Status,
+    current: unsafe extern "efiapi" fn(this: *const UserManager, user: *mut *mut c_void) -> Status,
+    identify: unsafe extern "efiapi" fn(this: *const UserManager, user: *mut *mut c_void) -> Status,
+    find: unsafe extern "efiapi" fn(
+        this: *const UserManager,
+        user: *mut *mut c_void,
+        user_info: *mut *mut c_void,
+        info: *const u8,
+        info_size: usize,
+    ) -> Status,
+    notify: unsafe extern "efiapi" fn(this: *const UserManager, changed: Handle) -> Status,
+    get_info: unsafe extern "efiapi" fn(
+        this: *const UserManager,
+        user: *mut c_void,
+        user_info: *mut c_void,
+        info: *mut u8,
+        info_size: *mut usize,
+    ) -> Status,
+    set_info: unsafe extern "efiapi" fn(
+        this: *const UserManager,
+        user: *mut c_void,
+        user_info: *mut *mut c_void,
+        info: *const u8,
+        info_size: usize,
+    ) -> Status,
+    delete_info: unsafe extern "efiapi" fn(
+        this: *const UserManager,
+        user: *mut c_void,
+        user_info: *mut c_void,
+    ) -> Status,
+    get_next_info: unsafe extern "efiapi" fn(
+        this: *const UserManager,
+        user: *mut c_void,
+        user_info: *mut *mut c_void,
+    ) -> Status,
+}
+
+impl UserManager {
+    /// Create an empty user profile.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ACCESS_DENIED`  The current user may not create
+    ///                                  profiles
+    pub fn create(&self) -> Result<UserProfile> {
+        let mut user = ptr::null_mut();
+        unsafe { (self.create)(self, &mut user) }.into_with_val(|| Self::profile(user))
+    }
+
+    /// Delete a user profile.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ACCESS_DENIED`  The current user may not delete the
+    ///                                  profile, or it is the current profile
+    pub fn delete(&self, user: UserProfile) -> Result {
+        unsafe { (self.delete)(self, user.0.as_ptr()) }.into()
+    }
+
+    /// Iterate over the user profiles
+    pub fn users(&self) -> impl Iterator<Item = UserProfile> + '_ {
+        let mut user = ptr::null_mut();
+        core::iter::from_fn(move || {
+            let status = unsafe { (self.get_next)(self, &mut user) };
+            if status.is_success() {
+                NonNull::new(user).map(UserProfile)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The profile of the current user
+    pub fn current(&self) -> Result<UserProfile> {
+        let mut user = ptr::null_mut();
+        unsafe { (self.current)(self, &mut user) }.into_with_val(|| Self::profile(user))
+    }
+
+    /// Identify the user with the credential providers of the platform, make
+    /// them the current user and return their profile.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ACCESS_DENIED`  The user could not be identified
+    pub fn identify(&self) -> Result<UserProfile> {
+        let mut user = ptr::null_mut();
+        unsafe { (self.identify)(self, &mut user) }.into_with_val(|| Self::profile(user))
+    }
+
+    /// Find the first profile with a record matching `record`, after `after`
+    /// if it is not `None`, and return it with the matching record.
+    ///
+    /// Records match if they have the same type and data. A record without
+    /// data matches every record of its type.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  No other profile matches
+    pub fn find(
+        &self,
+        after: Option<UserProfile>,
+        record: &[u8],
+    ) -> Result<(UserProfile, UserInfoHandle)> {
+        let mut user = as_ptr(after);
+        let mut info = ptr::null_mut();
+        unsafe { (self.find)(self, &mut user, &mut info, record.as_ptr(), record.len()) }
+            .into_with_val(|| (Self::profile(user), Self::info_handle(info)))
+    }
+
+    /// Tell the user manager that the credential provider installed on
+    /// `changed` added, removed or modified credentials.
+    pub fn notify(&self, changed: Handle) -> Result {
+        unsafe { (self.notify)(self, changed) }.into()
+    }
+
+    /// Iterate over the information records of a profile
+    pub fn infos(&self, user: UserProfile) -> impl Iterator<Item = UserInfoHandle> + '_ {
+        let mut info = ptr::null_mut();
+        core::iter::from_fn(move || {
+            let status = unsafe { (self.get_next_info)(self, user.0.as_ptr(), &mut info) };
+            if status.is_success() {
+                NonNull::new(info).map(UserInfoHandle)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Read an information record of a profile into `buffer`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ACCESS_DENIED`     The record is neither public nor a
+    ///                                     record of the current user
+    /// * `uefi::Status::BUFFER_TOO_SMALL`  The buffer is too small, the
+    ///                                     required size is returned
+    /// * `uefi::Status::COMPROMISED_DATA`  The record is malformed
+    pub fn get_info<'buf>(
+        &self,
+        user: UserProfile,
+        info: UserInfoHandle,
+        buffer: &'buf mut [u8],
+    ) -> Result<UserInfo<'buf>, Option<usize>> {
+        let mut size = buffer.len();
+        let status = unsafe {
+            (self.get_info)(
+                self,
+                user.0.as_ptr(),
+                info.0.as_ptr(),
+                buffer.as_mut_ptr(),
+                &mut size,
+            )
+        };
+        if status == Status::BUFFER_TOO_SMALL {
+            return Err(Error::new(status, Some(size)));
+        } else if status.is_error() {
+            return Err(Error::new(status, None));
+        }
+        match UserInfo::parse(&buffer[..size.min(buffer.len())]) {
+            Some(info) => Ok(Completion::new(status, info)),
+            None => Err(Error::new(Status::COMPROMISED_DATA, None)),
+        }
+    }
+
+    /// Add an information record to a profile, or replace `info` if it is
+    /// not `None`, and return the handle of the record.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ACCESS_DENIED`  The current user may not change the
+    ///                                  profile, or the record is protected
+    /// * `uefi::Status::SECURITY_VIOLATION`  An exclusive record of this type
+    ///                                       already exists
+    pub fn set_info(
+        &self,
+        user: UserProfile,
+        info: Option<UserInfoHandle>,
+        record: &[u8],
+    ) -> Result<UserInfoHandle> {
+        let mut info = as_ptr(info);
+        unsafe {
+            (self.set_info)(
+                self,
+                user.0.as_ptr(),
+                &mut info,
+                record.as_ptr(),
+                record.len(),
+            )
+        }
+        .into_with_val(|| Self::info_handle(info))
+    }
+
+    /// Delete an information record of a profile
+    pub fn delete_info(&self, user: UserProfile, info: UserInfoHandle) -> Result {
+        unsafe { (self.delete_info)(self, user.0.as_ptr(), info.0.as_ptr()) }.into()
+    }
+
+    fn profile(user: *mut c_void) -> UserProfile {
+        UserProfile(NonNull::new(user).expect("the user manager returned a null profile"))
+    }
+
+    fn info_handle(info: *mut c_void) -> UserInfoHandle {
+        UserInfoHandle(NonNull::new(info).expect("the user manager returned a null record"))
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::vec;
+    use crate::CString16;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn records() {
+        let name = CString16::try_from("Alice").unwrap();
+        let record = UserInfo::encode_name(&name, UserInfoAttributes::PUBLIC);
+        assert_eq!(record.len(), 24 + 12);
+
+        // Parse the record at an odd offset, as found in a packed list
+        let mut unaligned = vec![0];
+        unaligned.extend_from_slice(&record);
+        let info = UserInfo::parse(&unaligned[1..]).unwrap();
+        assert_eq!(info.info_type(), UserInfoType::NAME);
+        assert_eq!(info.attributes(), UserInfoAttributes::PUBLIC);
+        assert_eq!(info.credential(), Guid::default());
+        assert_eq!(info.name().as_deref(), Some("Alice"));
+
+        assert!(UserInfo::parse(&record[..30]).is_none());
+    }
+}
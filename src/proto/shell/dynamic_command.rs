@@ -0,0 +1,243 @@
+#[cfg(feature = "exts")]
+use super::Shell;
+use super::ShellParameters;
+use crate::proto::Protocol;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::boxed::Box,
+    proto::ProtocolImpl,
+    table::boot::{BootServices, MemoryType},
+    CString16,
+};
+use crate::{unsafe_guid, CStr16, Char16, Char8, Status};
+use core::ffi::c_void;
+#[cfg(feature = "exts")]
+use core::ptr;
+
+newtype_enum! {
+    /// Status of a shell command (`SHELL_STATUS`), which the shell stores in
+    /// the `lasterror` variable
+    ///
+    /// The codes are those of the errors of [`Status`], without the error
+    /// bit.
+    pub enum ShellStatus: usize => {
+        /// The command succeeded
+        SUCCESS = 0,
+        /// The image of the command failed to load
+        LOAD_ERROR = 1,
+        /// The arguments are invalid
+        INVALID_PARAMETER = 2,
+        /// The operation is not supported
+        UNSUPPORTED = 3,
+        /// A buffer has the wrong size
+        BAD_BUFFER_SIZE = 4,
+        /// A buffer is too small
+        BUFFER_TOO_SMALL = 5,
+        /// There is no data pending
+        NOT_READY = 6,
+        /// A device failed
+        DEVICE_ERROR = 7,
+        /// The device is write-protected
+        WRITE_PROTECTED = 8,
+        /// Memory could not be allocated
+        OUT_OF_RESOURCES = 9,
+        /// A file system is inconsistent
+        VOLUME_CORRUPTED = 10,
+        /// There is no space left on a file system
+        VOLUME_FULL = 11,
+        /// The device has no media
+        NO_MEDIA = 12,
+        /// The media of the device changed
+        MEDIA_CHANGED = 13,
+        /// An item was not found
+        NOT_FOUND = 14,
+        /// Access was denied
+        ACCESS_DENIED = 15,
+        /// The operation timed out
+        TIMEOUT = 18,
+        /// A protocol was not started
+        NOT_STARTED = 19,
+        /// A protocol is already started
+        ALREADY_STARTED = 20,
+        /// The user aborted the command
+        ABORTED = 21,
+        /// The command was started with an incompatible version
+        INCOMPATIBLE_VERSION = 25,
+        /// A security check failed
+        SECURITY_VIOLATION = 26,
+        /// Compared items are not equal
+        NOT_EQUAL = 27,
+    }
+}
+
+impl From<Status> for ShellStatus {
+    /// The shell status of an error, or `SUCCESS` for successes and warnings
+    fn from(status: Status) -> Self {
+        if status.is_error() {
+            ShellStatus(status.0 & (usize::MAX >> 1))
+        } else {
+            ShellStatus::SUCCESS
+        }
+    }
+}
+
+/// A command which is not built into the shell
+/// (`EFI_SHELL_DYNAMIC_COMMAND_PROTOCOL`).
+///
+/// The EDK2 shell looks this protocol up when a command is not one of its
+/// own, and lists the commands it finds in `help`. With the `exts`
+/// feature, the [`DynamicCommand`] type implements the protocol with a Rust
+/// closure.
+#[repr(C)]
+#[unsafe_guid("3c7200e9-005f-4ea4-87de-a3dfac8a27c3")]
+#[derive(Protocol)]
+pub struct ShellDynamicCommand {
+    command_name: *const Char16,
+    handler: unsafe extern "efiapi" fn(
+        this: *mut ShellDynamicCommand,
+        system_table: *mut c_void,
+        parameters: *mut ShellParameters,
+        shell: *mut c_void,
+    ) -> ShellStatus,
+    get_help: unsafe extern "efiapi" fn(
+        this: *mut ShellDynamicCommand,
+        language: *const Char8,
+    ) -> *mut Char16,
+}
+
+impl ShellDynamicCommand {
+    /// Name under which the command is run
+    pub fn name(&self) -> &CStr16 {
+        unsafe { CStr16::from_ptr(self.command_name) }
+    }
+}
+
+/// The function run by a [`DynamicCommand`]
+#[cfg(feature = "exts")]
+pub type DynamicCommandFn<'boot> = dyn FnMut(&ShellParameters, &Shell) -> ShellStatus + 'boot;
+
+/// Implementation of [`ShellDynamicCommand`], which runs a closure
+///
+/// The closure is given the arguments of the command, the first one being
+/// its name, and the shell protocol. Output goes to the standard output of
+/// the system table, which the shell redirects as asked on the command line:
+///
+/// ```no_run
+/// # use uefi::prelude::*;
+/// # use uefi::proto::shell::{DynamicCommand, ShellStatus};
+/// # use uefi::proto::ProtocolImpl;
+/// # use uefi::CStr16;
+/// # fn f(bt: &BootServices, image: Handle, name: &CStr16) -> uefi::Result {
+/// let mut command = DynamicCommand::new(bt, name, |params, _shell| {
+///     if params.argc() > 1 {
+///         return ShellStatus::INVALID_PARAMETER;
+///     }
+///     log::info!("all good");
+///     ShellStatus::SUCCESS
+/// });
+/// // Driver images stay loaded, and so does the command
+/// unsafe { command.install(bt, Some(image)) }?.log();
+/// core::mem::forget(command);
+/// # Ok(().into())
+/// # }
+/// ```
+///
+/// The shell looks the commands up whenever it runs one, so a command is
+/// available as soon as it is installed.
+#[cfg(feature = "exts")]
+#[repr(C)]
+pub struct DynamicCommand<'boot> {
+    // The protocol comes first, so that a pointer to the implementation is a
+    // pointer to the protocol
+    protocol: ShellDynamicCommand,
+
+    name: CString16,
+    help: Option<CString16>,
+    /// Used to allocate the help returned to the shell
+    boot_services: &'boot BootServices,
+    run: Box<DynamicCommandFn<'boot>>,
+}
+
+#[cfg(feature = "exts")]
+unsafe impl ProtocolImpl for DynamicCommand<'_> {
+    type Protocol = ShellDynamicCommand;
+
+    fn interface(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+}
+
+#[cfg(feature = "exts")]
+impl<'boot> DynamicCommand<'boot> {
+    /// Create a protocol implementation of the command `name`, which runs
+    /// `run`.
+    pub fn new<F>(bt: &'boot BootServices, name: &CStr16, run: F) -> Box<Self>
+    where
+        F: FnMut(&ShellParameters, &Shell) -> ShellStatus + 'boot,
+    {
+        let name = CString16::from(name);
+        Box::new(DynamicCommand {
+            protocol: ShellDynamicCommand {
+                // The buffer of the name is never reallocated
+                command_name: name.as_ptr(),
+                handler: Self::handler,
+                get_help: Self::get_help,
+            },
+            name,
+            help: None,
+            boot_services: bt,
+            run: Box::new(run),
+        })
+    }
+
+    /// Name under which the command is run
+    pub fn name(&self) -> &CStr16 {
+        &self.name
+    }
+
+    /// Set the text shown by `help`, in all languages.
+    ///
+    /// The shell parses it as its own help files: sections start with
+    /// `.SH NAME`, `.SH SYNOPSIS`, `.SH OPTIONS`, `.SH DESCRIPTION` and
+    /// `.SH EXAMPLES`, and the first line is `.TH name 0 "summary"`, whose
+    /// summary is what `help` shows for the command in its list.
+    pub fn set_help(&mut self, help: &CStr16) {
+        self.help = Some(help.into());
+    }
+
+    unsafe extern "efiapi" fn handler(
+        this: *mut ShellDynamicCommand,
+        _system_table: *mut c_void,
+        parameters: *mut ShellParameters,
+        shell: *mut c_void,
+    ) -> ShellStatus {
+        if parameters.is_null() || shell.is_null() {
+            return ShellStatus::INVALID_PARAMETER;
+        }
+        let this = &mut *(this as *mut Self);
+        (this.run)(&*parameters, &*(shell as *const Shell))
+    }
+
+    unsafe extern "efiapi" fn get_help(
+        this: *mut ShellDynamicCommand,
+        _language: *const Char8,
+    ) -> *mut Char16 {
+        let this = &*(this as *const Self);
+        let help = match &this.help {
+            Some(help) => help.to_u16_slice_with_nul(),
+            None => return ptr::null_mut(),
+        };
+        // The shell frees the help once it is shown
+        match this
+            .boot_services
+            .allocate_pool(MemoryType::BOOT_SERVICES_DATA, help.len() * 2)
+        {
+            Ok(buffer) => {
+                let buffer = buffer.log().cast::<u16>();
+                buffer.copy_from_nonoverlapping(help.as_ptr(), help.len());
+                buffer.cast()
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! EFI Shell protocols.
+//!
+//! These protocols are installed by the EFI Shell, and are only available to
+//! applications started from it. The exception is [`ShellDynamicCommand`],
+//! which drivers install to add commands to the shell.
+
+mod dynamic_command;
+mod parameters;
+mod protocol;
+
+#[cfg(feature = "exts")]
+pub use self::dynamic_command::{DynamicCommand, DynamicCommandFn};
+pub use self::dynamic_command::{ShellDynamicCommand, ShellStatus};
+pub use self::parameters::ShellParameters;
+pub use self::protocol::{EnvNames, Shell};
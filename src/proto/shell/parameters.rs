@@ -0,0 +1,51 @@
+use crate::proto::Protocol;
+use crate::{unsafe_guid, CStr16, Char16};
+use core::ffi::c_void;
+use core::slice;
+
+/// The arguments and standard streams of an application started by the
+/// EFI Shell (`EFI_SHELL_PARAMETERS_PROTOCOL`).
+///
+/// The shell installs this protocol on the image handle of the application.
+/// Unlike the load options, the command line has already been split into
+/// arguments, with quotes and escape characters removed. The first argument
+/// is the path of the application.
+#[repr(C)]
+#[unsafe_guid("752f3136-4e16-4fdc-a22a-e5f46812f4ca")]
+#[derive(Protocol)]
+pub struct ShellParameters {
+    argv: *const *const Char16,
+    argc: usize,
+    std_in: *mut c_void,
+    std_out: *mut c_void,
+    std_err: *mut c_void,
+}
+
+impl ShellParameters {
+    /// Number of arguments, including the path of the application
+    pub fn argc(&self) -> usize {
+        self.argc
+    }
+
+    /// Get an argument, or `None` if `index` is not smaller than `argc()`
+    pub fn arg(&self, index: usize) -> Option<&CStr16> {
+        self.argv()
+            .get(index)
+            .map(|&arg| unsafe { CStr16::from_ptr(arg) })
+    }
+
+    /// Iterate over the arguments, starting with the path of the application
+    pub fn args(&self) -> impl Iterator<Item = &CStr16> + '_ {
+        self.argv()
+            .iter()
+            .map(|&arg| unsafe { CStr16::from_ptr(arg) })
+    }
+
+    fn argv(&self) -> &[*const Char16] {
+        if self.argv.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.argv, self.argc) }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+#[cfg(feature = "exts")]
+use crate::alloc_api::{string::String, vec::Vec};
+use crate::proto::Protocol;
+use crate::{unsafe_guid, CStr16, Char16, Event, Result, Status};
+use core::ptr;
+
+/// The services of the EFI Shell (`EFI_SHELL_PROTOCOL`).
+///
+/// Only the environment functions are currently wrapped. The strings
+/// returned by the shell belong to it, and may be freed as soon as the
+/// environment changes, which the `&self` borrow of the returned references
+/// cannot express: they must not be kept across calls to `set_env` or
+/// `set_cur_dir`, or to commands which change the environment.
+#[repr(C)]
+#[unsafe_guid("6302d008-7f9b-4f30-87ac-60c9fef5da4e")]
+#[derive(Protocol)]
+pub struct Shell {
+    execute: usize,
+    get_env: extern "efiapi" fn(name: *const Char16) -> *const Char16,
+    set_env:
+        extern "efiapi" fn(name: *const Char16, value: *const Char16, volatile: bool) -> Status,
+    get_alias: usize,
+    set_alias: usize,
+    get_help_text: usize,
+    get_device_path_from_map: usize,
+    get_map_from_device_path: usize,
+    get_device_path_from_file_path: usize,
+    get_file_path_from_device_path: usize,
+    set_map: usize,
+    get_cur_dir: extern "efiapi" fn(file_system_mapping: *const Char16) -> *const Char16,
+    set_cur_dir: extern "efiapi" fn(file_system: *const Char16, dir: *const Char16) -> Status,
+    open_file_list: usize,
+    free_file_list: usize,
+    remove_dup_in_file_list: usize,
+    batch_is_active: extern "efiapi" fn() -> bool,
+    is_root_shell: usize,
+    enable_page_break: usize,
+    disable_page_break: usize,
+    get_page_break: usize,
+    get_device_name: usize,
+    get_file_info: usize,
+    set_file_info: usize,
+    open_file_by_name: usize,
+    close_file: usize,
+    create_file: usize,
+    read_file: usize,
+    write_file: usize,
+    delete_file: usize,
+    delete_file_by_name: usize,
+    get_file_position: usize,
+    set_file_position: usize,
+    flush_file: usize,
+    find_files: usize,
+    find_files_in_dir: usize,
+    get_file_size: usize,
+    open_root: usize,
+    open_root_by_handle: usize,
+    execution_break: Event,
+    major_version: u32,
+    minor_version: u32,
+}
+
+impl Shell {
+    /// Version of the shell specification implemented by the shell, as a
+    /// `(major, minor)` pair
+    pub fn version(&self) -> (u32, u32) {
+        (self.major_version, self.minor_version)
+    }
+
+    /// Event signaled when the user asks for the running command to stop,
+    /// by pressing Ctrl-C
+    pub fn execution_break(&self) -> Event {
+        self.execution_break
+    }
+
+    /// Whether a script is being executed
+    pub fn batch_is_active(&self) -> bool {
+        (self.batch_is_active)()
+    }
+
+    /// Get the value of an environment variable, or `None` if it is not set
+    pub fn get_env(&self, name: &CStr16) -> Option<&CStr16> {
+        unsafe { from_shell_str((self.get_env)(name.as_ptr())) }
+    }
+
+    /// Set an environment variable, or delete it if `value` is empty.
+    ///
+    /// Volatile variables are lost when the shell exits, the other ones are
+    /// stored in UEFI variables.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ACCESS_DENIED`  The variable is read-only
+    pub fn set_env(&self, name: &CStr16, value: &CStr16, volatile: bool) -> Result {
+        (self.set_env)(name.as_ptr(), value.as_ptr(), volatile).into()
+    }
+
+    /// Iterate over the names of the environment variables
+    pub fn envs(&self) -> EnvNames<'_> {
+        EnvNames {
+            next: (self.get_env)(ptr::null()),
+            _shell: self,
+        }
+    }
+
+    /// Get the current directory of a file system, such as `fs0:`, or of the
+    /// current file system if `file_system` is `None`.
+    ///
+    /// The directory is returned as a full path, such as `fs0:\efi\boot`.
+    /// `None` is returned if there is no current directory, which is the case
+    /// until the user changes the current file system.
+    pub fn get_cur_dir(&self, file_system: Option<&CStr16>) -> Option<&CStr16> {
+        let file_system = file_system.map_or(ptr::null(), CStr16::as_ptr);
+        unsafe { from_shell_str((self.get_cur_dir)(file_system)) }
+    }
+
+    /// Change the current directory of a file system, or of the current one
+    /// if `file_system` is `None`.
+    ///
+    /// With a `file_system` and no directory part in `dir`, this changes the
+    /// current file system instead.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`  The directory does not exist
+    pub fn set_cur_dir(&self, file_system: Option<&CStr16>, dir: &CStr16) -> Result {
+        let file_system = file_system.map_or(ptr::null(), CStr16::as_ptr);
+        (self.set_cur_dir)(file_system, dir.as_ptr()).into()
+    }
+
+    /// Current directory, which is the `cwd` of the shell
+    pub fn cwd(&self) -> Option<&CStr16> {
+        self.get_cur_dir(None)
+    }
+
+    /// Status returned by the last command, stored by the shell in the
+    /// `lasterror` variable
+    pub fn last_error(&self) -> Option<Status> {
+        let value = self.get_env(ascii_name(&LASTERROR))?;
+        parse_hex(value.to_u16_slice()).map(Status)
+    }
+
+    /// Directories in which the shell looks for commands, from the `path`
+    /// variable
+    #[cfg(feature = "exts")]
+    pub fn path(&self) -> Vec<String> {
+        self.get_env(ascii_name(&PATH))
+            .map(|path| split_path(&path.as_string()))
+            .unwrap_or_default()
+    }
+}
+
+/// Iterator over the names of the environment variables of the shell
+///
+/// Returned by `Shell::envs`.
+pub struct EnvNames<'shell> {
+    next: *const Char16,
+    _shell: &'shell Shell,
+}
+
+impl<'shell> Iterator for EnvNames<'shell> {
+    type Item = &'shell CStr16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The names follow one another, and the list ends with an empty name
+        let name = unsafe { from_shell_str(self.next) }?;
+        if name.to_u16_slice().is_empty() {
+            self.next = ptr::null();
+            return None;
+        }
+        self.next = unsafe { self.next.add(name.to_u16_slice_with_nul().len()) };
+        Some(name)
+    }
+}
+
+const LASTERROR: [u16; 10] = ascii(b"lasterror\0");
+#[cfg(feature = "exts")]
+const PATH: [u16; 5] = ascii(b"path\0");
+
+/// Convert a nul-terminated ASCII string to UCS-2
+const fn ascii<const N: usize>(string: &[u8; N]) -> [u16; N] {
+    let mut ucs2 = [0; N];
+    let mut i = 0;
+    while i < N {
+        ucs2[i] = string[i] as u16;
+        i += 1;
+    }
+    ucs2
+}
+
+fn ascii_name(name: &[u16]) -> &CStr16 {
+    unsafe { CStr16::from_u16_with_nul_unchecked(name) }
+}
+
+unsafe fn from_shell_str<'shell>(string: *const Char16) -> Option<&'shell CStr16> {
+    if string.is_null() {
+        None
+    } else {
+        Some(CStr16::from_ptr(string))
+    }
+}
+
+/// Parse a number in the `0x` notation used by the shell for statuses
+fn parse_hex(digits: &[u16]) -> Option<usize> {
+    let digits = match digits {
+        [0x30, 0x78, rest @ ..] | [0x30, 0x58, rest @ ..] => rest,
+        _ => digits,
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value = 0usize;
+    for &digit in digits {
+        let digit = char::from_u32(digit.into())?.to_digit(16)?;
+        value = value.checked_mul(16)?.checked_add(digit as usize)?;
+    }
+    Some(value)
+}
+
+/// Split a list of directories separated by `;`, ignoring empty entries
+#[cfg(feature = "exts")]
+fn split_path(path: &str) -> Vec<String> {
+    path.split(';')
+        .filter(|dir| !dir.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    fn ucs2(s: &str) -> crate::alloc_api::vec::Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn parse_last_error() {
+        assert_eq!(parse_hex(&ucs2("0x0")), Some(0));
+        assert_eq!(parse_hex(&ucs2("0x8000000E")), Some(0x8000_000e));
+        assert_eq!(parse_hex(&ucs2("1f")), Some(0x1f));
+        assert_eq!(parse_hex(&ucs2("0x")), None);
+        assert_eq!(parse_hex(&ucs2("0xg")), None);
+    }
+
+    #[cfg(feature = "exts")]
+    #[test]
+    fn split_path_list() {
+        assert_eq!(
+            split_path(r".\;fs0:\efi\tools;;fs0:\efi\boot\"),
+            [r".\", r"fs0:\efi\tools", r"fs0:\efi\boot\"]
+        );
+        assert!(split_path("").is_empty());
+    }
+}
@@ -1,7 +1,7 @@
 //! Shim lock protocol.
 
 #![cfg(any(
-    target_arch = "i386",
+    target_arch = "x86",
     target_arch = "x86_64",
     target_arch = "arm",
     target_arch = "aarch64"
@@ -45,12 +45,12 @@ pub struct Hashes {
 
 // These macros set the correct calling convention for the Shim protocol methods.
 
-#[cfg(any(target_arch = "i386", target_arch = "x86_64"))]
+#[cfg(target_arch = "x86_64")]
 macro_rules! shim_function {
     (fn $args:tt -> $return_type:ty) => (extern "sysv64" fn $args -> $return_type)
 }
 
-#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+#[cfg(any(target_arch = "x86", target_arch = "arm", target_arch = "aarch64"))]
 macro_rules! shim_function {
     (fn $args:tt -> $return_type:ty) => (extern "C" fn $args -> $return_type)
 }
@@ -0,0 +1,371 @@
+//! FAT short file names.
+//!
+//! Besides its long name, every file of a FAT file system has an 8.3 short
+//! name: up to 8 characters, followed by an extension of up to 3 characters,
+//! all upper case and taken from a restricted character set. Some firmware
+//! and boot entries only understand these short names.
+//!
+//! The conversions use the [`UnicodeCollation`] protocol when one is given,
+//! so that characters of the firmware's OEM code page are preserved, and
+//! only keep ASCII characters otherwise.
+
+use super::unicode_collation::UnicodeCollation;
+use crate::CStr16;
+use core::convert::TryFrom;
+use core::fmt::{self, Display, Formatter, Write};
+
+/// Size of a short name stored in a directory entry
+pub const SHORT_NAME_LEN: usize = 11;
+/// Maximum length of the name part of a short name
+const BASE_LEN: usize = 8;
+/// Maximum length of a long name
+const LONG_NAME_LEN: usize = 255;
+
+/// Whether `c` may appear in a short name
+///
+/// This covers the upper case letters, the digits and the allowed ASCII
+/// punctuation. Characters above 0x7f depend on the OEM code page and are
+/// not considered valid.
+pub fn is_valid_short_name_char(c: u8) -> bool {
+    matches!(c,
+        b'A'..=b'Z' | b'0'..=b'9'
+        | b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')'
+        | b'-' | b'@' | b'^' | b'_' | b'`' | b'{' | b'}' | b'~')
+}
+
+/// Errors which can occur when handling short names
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShortNameError {
+    /// The name is empty, or only made of characters which are dropped
+    Empty,
+    /// The character at this index is not valid in a short name
+    InvalidChar(usize),
+    /// The name part is longer than 8 characters
+    NameTooLong,
+    /// The extension is longer than 3 characters
+    ExtensionTooLong,
+}
+
+/// An 8.3 short name, in the format of FAT directory entries: the name and
+/// the extension, each padded with spaces.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ShortName([u8; SHORT_NAME_LEN]);
+
+impl ShortName {
+    /// Wrap the short name of a directory entry
+    pub fn from_bytes(bytes: [u8; SHORT_NAME_LEN]) -> Self {
+        ShortName(bytes)
+    }
+
+    /// The short name, as stored in a directory entry
+    pub fn as_bytes(&self) -> &[u8; SHORT_NAME_LEN] {
+        &self.0
+    }
+
+    /// The name part, without padding
+    pub fn name(&self) -> &[u8] {
+        trim_padding(&self.0[..BASE_LEN])
+    }
+
+    /// The extension, without padding
+    pub fn extension(&self) -> &[u8] {
+        trim_padding(&self.0[BASE_LEN..])
+    }
+
+    /// Parse a name which is already in 8.3 format, such as `BOOTX64.EFI`
+    ///
+    /// Lower case ASCII letters are converted to upper case.
+    pub fn parse(name: &CStr16) -> Result<Self, ShortNameError> {
+        let name = name.to_u16_slice();
+        let dot = name.iter().position(|&c| c == u16::from(b'.'));
+        let (base, ext) = match dot {
+            Some(dot) => (&name[..dot], &name[dot + 1..]),
+            None => (name, &[][..]),
+        };
+        if base.is_empty() {
+            return Err(ShortNameError::Empty);
+        }
+        if base.len() > BASE_LEN {
+            return Err(ShortNameError::NameTooLong);
+        }
+        if ext.len() > SHORT_NAME_LEN - BASE_LEN {
+            return Err(ShortNameError::ExtensionTooLong);
+        }
+
+        let mut short = [b' '; SHORT_NAME_LEN];
+        let ext_offset = base.len() + 1;
+        let chars = base
+            .iter()
+            .enumerate()
+            .chain(ext.iter().enumerate().map(|(i, c)| (ext_offset + i, c)));
+        for (index, &c) in chars {
+            let oem = ascii_to_oem(c).ok_or(ShortNameError::InvalidChar(index))?;
+            let offset = if index < ext_offset {
+                index
+            } else {
+                BASE_LEN + index - ext_offset
+            };
+            short[offset] = oem;
+        }
+        Ok(ShortName(short))
+    }
+
+    /// Generate the short name of a file from its long name
+    ///
+    /// This follows the algorithm of the FAT specification: the name is
+    /// converted to upper case, spaces and leading periods are dropped,
+    /// invalid characters are replaced with underscores, and the name and the
+    /// extension (after the last period) are truncated.
+    ///
+    /// The boolean is `true` if the short name does not match the long name,
+    /// ignoring case. In that case, a numeric tail should be added with
+    /// `with_numeric_tail`, until the short name is unique in its directory.
+    pub fn from_long_name(
+        long_name: &CStr16,
+        collation: Option<&UnicodeCollation>,
+    ) -> Result<(Self, bool), ShortNameError> {
+        let name = long_name.to_u16_slice();
+        let dot = u16::from(b'.');
+        let leading_dots = name.iter().take_while(|&&c| c == dot).count();
+        let name = &name[leading_dots..];
+        let (base, ext) = match name.iter().rposition(|&c| c == dot) {
+            Some(dot) => (&name[..dot], &name[dot + 1..]),
+            None => (name, &[][..]),
+        };
+
+        let mut short = [b' '; SHORT_NAME_LEN];
+        let (base_len, base_lossy) = convert_part(base, collation, &mut short[..BASE_LEN]);
+        if base_len == 0 {
+            return Err(ShortNameError::Empty);
+        }
+        let (_, ext_lossy) = convert_part(ext, collation, &mut short[BASE_LEN..]);
+        Ok((
+            ShortName(short),
+            leading_dots > 0 || base_lossy || ext_lossy,
+        ))
+    }
+
+    /// Replace the end of the name part with a numeric tail, such as `~1`
+    ///
+    /// `n` must be between 1 and 999999.
+    pub fn with_numeric_tail(&self, n: u32) -> Self {
+        assert!((1..=999_999).contains(&n), "invalid numeric tail");
+        let mut tail = [0; 7];
+        let mut tail_len = 0;
+        write!(SliceWriter(&mut tail, &mut tail_len), "~{}", n).unwrap();
+
+        let mut short = self.0;
+        let start = self.name().len().min(BASE_LEN - tail_len);
+        short[start..start + tail_len].copy_from_slice(&tail[..tail_len]);
+        for c in &mut short[start + tail_len..BASE_LEN] {
+            *c = b' ';
+        }
+        ShortName(short)
+    }
+
+    /// Write the short name as a `NAME.EXT` string into `buf`
+    pub fn to_cstr16<'a>(&self, buf: &'a mut [u16; SHORT_NAME_LEN + 2]) -> &'a CStr16 {
+        let mut len = 0;
+        let mut push = |c: u8| {
+            buf[len] = u16::from(c);
+            len += 1;
+        };
+        self.name().iter().for_each(|&c| push(c));
+        if !self.extension().is_empty() {
+            push(b'.');
+            self.extension().iter().for_each(|&c| push(c));
+        }
+        push(0);
+        unsafe { CStr16::from_u16_with_nul_unchecked(&buf[..len]) }
+    }
+}
+
+impl Display for ShortName {
+    /// Display the short name as `NAME.EXT`, with characters above 0x7f
+    /// replaced as they depend on the OEM code page
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let write = |f: &mut Formatter<'_>, part: &[u8]| {
+            part.iter().try_for_each(|&c| {
+                f.write_char(if c.is_ascii() {
+                    char::from(c)
+                } else {
+                    char::REPLACEMENT_CHARACTER
+                })
+            })
+        };
+        write(f, self.name())?;
+        if !self.extension().is_empty() {
+            f.write_char('.')?;
+            write(f, self.extension())?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove the trailing spaces of a part of a short name
+fn trim_padding(part: &[u8]) -> &[u8] {
+    let len = part
+        .iter()
+        .rposition(|&c| c != b' ')
+        .map_or(0, |pos| pos + 1);
+    &part[..len]
+}
+
+/// Convert an ASCII character to its short name representation
+fn ascii_to_oem(c: u16) -> Option<u8> {
+    let c = u8::try_from(c).ok()?.to_ascii_uppercase();
+    if is_valid_short_name_char(c) {
+        Some(c)
+    } else {
+        None
+    }
+}
+
+/// Convert a part of a long name into `out`, returning the length of the
+/// converted part and whether it does not match the long name
+fn convert_part(
+    part: &[u16],
+    collation: Option<&UnicodeCollation>,
+    out: &mut [u8],
+) -> (usize, bool) {
+    let mut converted = [0; LONG_NAME_LEN];
+    let (len, mut lossy) = match collation {
+        Some(collation) => {
+            let mut buf = [0; LONG_NAME_LEN + 1];
+            let len = part.len().min(LONG_NAME_LEN);
+            buf[..len].copy_from_slice(&part[..len]);
+            let s = unsafe { CStr16::from_u16_with_nul_unchecked(&buf[..=len]) };
+            let replaced = collation.str_to_fat(s, &mut converted);
+            let converted_len = converted
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(LONG_NAME_LEN);
+            (converted_len, replaced || converted_len != part.len())
+        }
+        None => {
+            let mut len = 0;
+            let mut lossy = false;
+            for &c in part {
+                if c == u16::from(b' ') || c == u16::from(b'.') {
+                    lossy = true;
+                    continue;
+                }
+                if len == LONG_NAME_LEN {
+                    // Longer than any valid long name, and truncated anyway
+                    lossy = true;
+                    break;
+                }
+                converted[len] = ascii_to_oem(c).unwrap_or_else(|| {
+                    lossy = true;
+                    b'_'
+                });
+                len += 1;
+            }
+            (len, lossy)
+        }
+    };
+    if len > out.len() {
+        lossy = true;
+    }
+    let len = len.min(out.len());
+    out[..len].copy_from_slice(&converted[..len]);
+    (len, lossy)
+}
+
+/// Formatting sink writing into a byte slice
+struct SliceWriter<'a>(&'a mut [u8], &'a mut usize);
+
+impl Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = *self.1 + s.len();
+        self.0
+            .get_mut(*self.1..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        *self.1 = end;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::string::ToString;
+    use crate::alloc_api::vec::Vec;
+
+    fn to_ucs2(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn short_name(long_name: &str) -> (ShortName, bool) {
+        let long_name = to_ucs2(long_name);
+        ShortName::from_long_name(CStr16::from_u16_with_nul(&long_name).unwrap(), None).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let name = to_ucs2("bootx64.efi");
+        let short = ShortName::parse(CStr16::from_u16_with_nul(&name).unwrap()).unwrap();
+        assert_eq!(short.as_bytes(), b"BOOTX64 EFI");
+        assert_eq!(short.name(), b"BOOTX64");
+        assert_eq!(short.extension(), b"EFI");
+        assert_eq!(short.to_string(), "BOOTX64.EFI");
+
+        let mut buf = [0; 13];
+        assert_eq!(
+            short.to_cstr16(&mut buf).to_u16_slice_with_nul(),
+            &to_ucs2("BOOTX64.EFI")[..]
+        );
+
+        let parse = |name: &str| {
+            let name = to_ucs2(name);
+            ShortName::parse(CStr16::from_u16_with_nul(&name).unwrap())
+        };
+        assert_eq!(parse("LINUX").unwrap().as_bytes(), b"LINUX      ");
+        assert_eq!(parse(".EFI"), Err(ShortNameError::Empty));
+        assert_eq!(parse("VERYLONGNAME"), Err(ShortNameError::NameTooLong));
+        assert_eq!(parse("A.TEXT"), Err(ShortNameError::ExtensionTooLong));
+        assert_eq!(parse("A+B.TXT"), Err(ShortNameError::InvalidChar(1)));
+        assert_eq!(parse("A.T*T"), Err(ShortNameError::InvalidChar(3)));
+    }
+
+    #[test]
+    fn from_long_name() {
+        assert_eq!(short_name("grub.cfg"), (ShortName(*b"GRUB    CFG"), false));
+        assert_eq!(
+            short_name("Program Files"),
+            (ShortName(*b"PROGRAMF   "), true)
+        );
+        assert_eq!(short_name(".bashrc"), (ShortName(*b"BASHRC     "), true));
+        assert_eq!(
+            short_name("initrd.img.gz"),
+            (ShortName(*b"INITRDIMGZ "), true)
+        );
+        assert_eq!(short_name("a+b.jpeg"), (ShortName(*b"A_B     JPE"), true));
+        assert_eq!(short_name("caf\u{e9}"), (ShortName(*b"CAF_       "), true));
+
+        let long_name: Vec<u16> = core::iter::repeat(u16::from(b'x'))
+            .take(300)
+            .chain(Some(0))
+            .collect();
+        assert_eq!(
+            ShortName::from_long_name(CStr16::from_u16_with_nul(&long_name).unwrap(), None),
+            Ok((ShortName(*b"XXXXXXXX   "), true))
+        );
+
+        let long_name = to_ucs2("...");
+        assert_eq!(
+            ShortName::from_long_name(CStr16::from_u16_with_nul(&long_name).unwrap(), None),
+            Err(ShortNameError::Empty)
+        );
+    }
+
+    #[test]
+    fn numeric_tail() {
+        let (short, _) = short_name("Program Files");
+        assert_eq!(short.with_numeric_tail(1).as_bytes(), b"PROGRA~1   ");
+        assert_eq!(short.with_numeric_tail(42).as_bytes(), b"PROGR~42   ");
+        let (short, _) = short_name("a b.txt");
+        assert_eq!(short.with_numeric_tail(3).as_bytes(), b"AB~3    TXT");
+    }
+}
@@ -0,0 +1,7 @@
+//! String protocols.
+//!
+//! The protocols provide some string operations like
+//! lexical comparison.
+
+pub mod fat;
+pub mod unicode_collation;
@@ -0,0 +1,150 @@
+//! The Unicode Collation Protocol.
+//!
+//! Used to perform case-insensitive comparisons of strings, and to convert
+//! file names to and from the character set used by the FAT file system.
+
+use crate::data_types::{CStr16, CStr8, Char16, Char8};
+use crate::proto::Protocol;
+use crate::unsafe_guid;
+use core::cmp::Ordering;
+
+/// The Unicode Collation Protocol.
+///
+/// Used to perform case-insensitive comparisons of strings, and to convert
+/// between strings and FAT file names. The conversions depend on the
+/// language of the protocol, which firmwares may provide several instances of.
+#[repr(C)]
+#[unsafe_guid("a4c751fc-23ae-4c3e-92e9-4964cf63f349")]
+#[derive(Protocol)]
+pub struct UnicodeCollation {
+    stri_coll: extern "efiapi" fn(this: &Self, s1: *const Char16, s2: *const Char16) -> isize,
+    metai_match:
+        extern "efiapi" fn(this: &Self, string: *const Char16, pattern: *const Char16) -> bool,
+    str_lwr: extern "efiapi" fn(this: &Self, s: *mut Char16),
+    str_upr: extern "efiapi" fn(this: &Self, s: *mut Char16),
+    fat_to_str: extern "efiapi" fn(this: &Self, fat_size: usize, fat: *const Char8, s: *mut Char16),
+    str_to_fat:
+        extern "efiapi" fn(this: &Self, s: *const Char16, fat_size: usize, fat: *mut Char8) -> bool,
+    supported_languages: *const Char8,
+}
+
+impl UnicodeCollation {
+    /// Performs a case insensitive comparison of two
+    /// null-terminated strings.
+    pub fn stri_coll(&self, s1: &CStr16, s2: &CStr16) -> Ordering {
+        let order = (self.stri_coll)(self, s1.as_ptr(), s2.as_ptr());
+        order.cmp(&0)
+    }
+
+    /// Performs a case insensitive comparison between a null terminated
+    /// pattern string and a null terminated string.
+    ///
+    /// This function checks if character pattern described in `pattern`
+    /// is found in `string`. If the pattern match succeeds, true is returned.
+    /// Otherwise, false is returned.
+    ///
+    /// The following syntax can be used to build the string `pattern`:
+    ///
+    /// |Pattern Character            |Meaning                                           |
+    /// |-----------------------------|--------------------------------------------------|
+    /// |*                            | Match 0 or more characters                       |
+    /// |?                            | Match any one character                          |
+    /// |[`char1` `char2`...`charN`]| Match any character in the set                   |
+    /// |[`char1`-`char2`]          | Match any character between `char1` and `char2`|
+    /// |`char`                       | Match the character `char`                       |
+    ///
+    /// For example, the pattern "*.Fw" will match all strings that end
+    /// in ".FW", ".fw", ".Fw" or ".fW". The pattern "[a-z]" will match any
+    /// letter in the alphabet. The pattern "z" will match the letter "z".
+    /// The pattern "d?.*" will match the character "D" or "d" followed by
+    /// any single character followed by a "." followed by any string.
+    pub fn metai_match(&self, s: &CStr16, pattern: &CStr16) -> bool {
+        (self.metai_match)(self, s.as_ptr(), pattern.as_ptr())
+    }
+
+    /// Converts the characters in `s` to lower case characters, and stores
+    /// the result in `buf`, which must be large enough to hold `s` and its
+    /// null terminator.
+    pub fn str_lwr<'a>(
+        &self,
+        s: &CStr16,
+        buf: &'a mut [u16],
+    ) -> Result<&'a CStr16, StrConversionError> {
+        let converted = copy_str(s, buf)?;
+        (self.str_lwr)(self, converted.as_mut_ptr() as *mut Char16);
+        Ok(unsafe { CStr16::from_u16_with_nul_unchecked(converted) })
+    }
+
+    /// Converts the characters in `s` to upper case characters, and stores
+    /// the result in `buf`, which must be large enough to hold `s` and its
+    /// null terminator.
+    pub fn str_upr<'a>(
+        &self,
+        s: &CStr16,
+        buf: &'a mut [u16],
+    ) -> Result<&'a CStr16, StrConversionError> {
+        let converted = copy_str(s, buf)?;
+        (self.str_upr)(self, converted.as_mut_ptr() as *mut Char16);
+        Ok(unsafe { CStr16::from_u16_with_nul_unchecked(converted) })
+    }
+
+    /// Converts the 8.3 FAT file name `fat` to a null terminated string,
+    /// stored in `buf`.
+    ///
+    /// `fat` ends at its first null character, if any, and `buf` must be one
+    /// character longer than `fat` to hold the null terminator.
+    pub fn fat_to_str<'a>(
+        &self,
+        fat: &[u8],
+        buf: &'a mut [u16],
+    ) -> Result<&'a CStr16, StrConversionError> {
+        if buf.len() <= fat.len() {
+            return Err(StrConversionError::BufferTooSmall);
+        }
+        (self.fat_to_str)(
+            self,
+            fat.len(),
+            fat.as_ptr() as *const Char8,
+            buf.as_mut_ptr() as *mut Char16,
+        );
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(fat.len());
+        Ok(unsafe { CStr16::from_u16_with_nul_unchecked(&buf[..=len]) })
+    }
+
+    /// Converts the null terminated string `s` to legal characters in a FAT
+    /// file name, stored in `fat`.
+    ///
+    /// Periods and spaces are dropped, other invalid characters are replaced
+    /// with underscores, and the conversion stops when `fat` is full. The
+    /// remainder of `fat` is filled with null characters.
+    ///
+    /// Returns `true` if some characters had to be replaced.
+    pub fn str_to_fat(&self, s: &CStr16, fat: &mut [u8]) -> bool {
+        fat.fill(0);
+        (self.str_to_fat)(self, s.as_ptr(), fat.len(), fat.as_mut_ptr() as *mut Char8)
+    }
+
+    /// The languages supported by this instance of the protocol, as a
+    /// list of RFC 4646 language codes separated by semicolons.
+    pub fn supported_languages(&self) -> &CStr8 {
+        unsafe { CStr8::from_ptr(self.supported_languages) }
+    }
+}
+
+/// Copy `s` and its null terminator to the start of `buf`
+fn copy_str<'a>(s: &CStr16, buf: &'a mut [u16]) -> Result<&'a mut [u16], StrConversionError> {
+    let s = s.to_u16_slice_with_nul();
+    let buf = buf
+        .get_mut(..s.len())
+        .ok_or(StrConversionError::BufferTooSmall)?;
+    buf.copy_from_slice(s);
+    Ok(buf)
+}
+
+/// Errors returned by [`UnicodeCollation::str_lwr`], [`UnicodeCollation::str_upr`]
+/// and [`UnicodeCollation::fat_to_str`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrConversionError {
+    /// The output buffer is too small to hold the converted string.
+    BufferTooSmall,
+}
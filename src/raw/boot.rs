@@ -0,0 +1,304 @@
+//! Raw boot services table.
+
+use super::Header;
+use crate::proto::device_path::DevicePath;
+use crate::table::boot::{MemoryDescriptor, MemoryType, Tpl};
+use crate::{Char16, Event, Guid, Handle, Status};
+use core::ffi::c_void;
+
+/// Notification function of an event
+pub type EventNotifyFn = unsafe extern "efiapi" fn(event: Event, context: *mut c_void);
+
+/// Table of the boot services, as laid out by the firmware.
+///
+/// The services which were added after EFI 1.02, from `ConnectController()`
+/// on, may be left null by older firmware, hence the `Option`s. Their
+/// entries are not even part of the table if `header.size` is too small for
+/// them, which must be checked along with `header.revision` before they are
+/// read; the safe wrappers in `table::boot` do so.
+#[repr(C)]
+pub struct BootServices {
+    /// Table header
+    pub header: Header,
+
+    // Task Priority services
+    /// `EFI_BOOT_SERVICES.RaiseTPL()`
+    pub raise_tpl: unsafe extern "efiapi" fn(new_tpl: Tpl) -> Tpl,
+    /// `EFI_BOOT_SERVICES.RestoreTPL()`
+    pub restore_tpl: unsafe extern "efiapi" fn(old_tpl: Tpl),
+
+    // Memory allocation functions
+    /// `EFI_BOOT_SERVICES.AllocatePages()`
+    pub allocate_pages: unsafe extern "efiapi" fn(
+        alloc_ty: u32,
+        mem_ty: MemoryType,
+        count: usize,
+        addr: *mut u64,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.FreePages()`
+    pub free_pages: unsafe extern "efiapi" fn(addr: u64, pages: usize) -> Status,
+    /// `EFI_BOOT_SERVICES.GetMemoryMap()`
+    pub get_memory_map: unsafe extern "efiapi" fn(
+        size: *mut usize,
+        map: *mut MemoryDescriptor,
+        key: *mut usize,
+        desc_size: *mut usize,
+        desc_version: *mut u32,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.AllocatePool()`
+    pub allocate_pool: unsafe extern "efiapi" fn(
+        pool_type: MemoryType,
+        size: usize,
+        buffer: *mut *mut u8,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.FreePool()`
+    pub free_pool: unsafe extern "efiapi" fn(buffer: *mut u8) -> Status,
+
+    // Event & timer functions
+    /// `EFI_BOOT_SERVICES.CreateEvent()`
+    pub create_event: unsafe extern "efiapi" fn(
+        ty: u32,
+        notify_tpl: Tpl,
+        notify_func: Option<EventNotifyFn>,
+        notify_ctx: *mut c_void,
+        event: *mut Event,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.SetTimer()`
+    pub set_timer: unsafe extern "efiapi" fn(event: Event, ty: u32, trigger_time: u64) -> Status,
+    /// `EFI_BOOT_SERVICES.WaitForEvent()`
+    pub wait_for_event: unsafe extern "efiapi" fn(
+        number_of_events: usize,
+        events: *mut Event,
+        out_index: *mut usize,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.SignalEvent()`
+    pub signal_event: unsafe extern "efiapi" fn(event: Event) -> Status,
+    /// `EFI_BOOT_SERVICES.CloseEvent()`
+    pub close_event: unsafe extern "efiapi" fn(event: Event) -> Status,
+    /// `EFI_BOOT_SERVICES.CheckEvent()`
+    pub check_event: unsafe extern "efiapi" fn(event: Event) -> Status,
+
+    // Protocol handlers
+    /// `EFI_BOOT_SERVICES.InstallProtocolInterface()`
+    pub install_protocol_interface: unsafe extern "efiapi" fn(
+        handle: *mut Handle,
+        protocol: *const Guid,
+        interface_type: u32,
+        interface: *mut c_void,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.ReinstallProtocolInterface()`
+    pub reinstall_protocol_interface: unsafe extern "efiapi" fn(
+        handle: Handle,
+        protocol: *const Guid,
+        old_interface: *mut c_void,
+        new_interface: *mut c_void,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.UninstallProtocolInterface()`
+    pub uninstall_protocol_interface: unsafe extern "efiapi" fn(
+        handle: Handle,
+        protocol: *const Guid,
+        interface: *mut c_void,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.HandleProtocol()`
+    pub handle_protocol: unsafe extern "efiapi" fn(
+        handle: Handle,
+        protocol: *const Guid,
+        interface: *mut *mut c_void,
+    ) -> Status,
+    /// Reserved, must be null
+    pub reserved: *mut c_void,
+    /// `EFI_BOOT_SERVICES.RegisterProtocolNotify()`
+    pub register_protocol_notify: unsafe extern "efiapi" fn(
+        protocol: *const Guid,
+        event: Event,
+        registration: *mut *mut c_void,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.LocateHandle()`
+    pub locate_handle: unsafe extern "efiapi" fn(
+        search_ty: u32,
+        protocol: *const Guid,
+        search_key: *mut c_void,
+        buffer_size: *mut usize,
+        buffer: *mut Handle,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.LocateDevicePath()`
+    pub locate_device_path: unsafe extern "efiapi" fn(
+        protocol: *const Guid,
+        device_path: *mut *mut DevicePath,
+        device: *mut Handle,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.InstallConfigurationTable()`
+    pub install_configuration_table:
+        unsafe extern "efiapi" fn(guid: *const Guid, table: *mut c_void) -> Status,
+
+    // Image services
+    /// `EFI_BOOT_SERVICES.LoadImage()`
+    pub load_image: unsafe extern "efiapi" fn(
+        boot_policy: bool,
+        parent_image_handle: Handle,
+        device_path: *const DevicePath,
+        source_buffer: *const u8,
+        source_size: usize,
+        image_handle: *mut Handle,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.StartImage()`
+    pub start_image: unsafe extern "efiapi" fn(
+        image_handle: Handle,
+        exit_data_size: *mut usize,
+        exit_data: *mut *mut Char16,
+    ) -> Status,
+    /// `EFI_BOOT_SERVICES.Exit()`
+    pub exit: unsafe extern "efiapi" fn(
+        image_handle: Handle,
+        exit_status: Status,
+        exit_data_size: usize,
+        exit_data: *mut Char16,
+    ) -> !,
+    /// `EFI_BOOT_SERVICES.UnloadImage()`
+    pub unload_image: unsafe extern "efiapi" fn(image_handle: Handle) -> Status,
+    /// `EFI_BOOT_SERVICES.ExitBootServices()`
+    pub exit_boot_services:
+        unsafe extern "efiapi" fn(image_handle: Handle, map_key: usize) -> Status,
+
+    // Misc services
+    /// `EFI_BOOT_SERVICES.GetNextMonotonicCount()`
+    pub get_next_monotonic_count: unsafe extern "efiapi" fn(count: *mut u64) -> Status,
+    /// `EFI_BOOT_SERVICES.Stall()`
+    pub stall: unsafe extern "efiapi" fn(microseconds: usize) -> Status,
+    /// `EFI_BOOT_SERVICES.SetWatchdogTimer()`
+    pub set_watchdog_timer: unsafe extern "efiapi" fn(
+        timeout: usize,
+        watchdog_code: u64,
+        data_size: usize,
+        watchdog_data: *const Char16,
+    ) -> Status,
+
+    // Driver support services
+    /// `EFI_BOOT_SERVICES.ConnectController()`
+    pub connect_controller: Option<
+        unsafe extern "efiapi" fn(
+            controller: Handle,
+            driver_image: *mut Handle,
+            remaining_device_path: *mut DevicePath,
+            recursive: bool,
+        ) -> Status,
+    >,
+    /// `EFI_BOOT_SERVICES.DisconnectController()`
+    pub disconnect_controller: Option<
+        unsafe extern "efiapi" fn(
+            controller: Handle,
+            driver_image: Handle,
+            child: Handle,
+        ) -> Status,
+    >,
+
+    // Protocol open / close services
+    /// `EFI_BOOT_SERVICES.OpenProtocol()`
+    pub open_protocol: Option<
+        unsafe extern "efiapi" fn(
+            handle: Handle,
+            protocol: *const Guid,
+            interface: *mut *mut c_void,
+            agent_handle: Handle,
+            controller_handle: Handle,
+            attributes: u32,
+        ) -> Status,
+    >,
+    /// `EFI_BOOT_SERVICES.CloseProtocol()`
+    pub close_protocol: Option<
+        unsafe extern "efiapi" fn(
+            handle: Handle,
+            protocol: *const Guid,
+            agent_handle: Handle,
+            controller_handle: Handle,
+        ) -> Status,
+    >,
+    /// `EFI_BOOT_SERVICES.OpenProtocolInformation()`
+    pub open_protocol_information: Option<
+        unsafe extern "efiapi" fn(
+            handle: Handle,
+            protocol: *const Guid,
+            entry_buffer: *mut *mut OpenProtocolInformationEntry,
+            entry_count: *mut usize,
+        ) -> Status,
+    >,
+
+    // Library services
+    /// `EFI_BOOT_SERVICES.ProtocolsPerHandle()`
+    pub protocols_per_handle: Option<
+        unsafe extern "efiapi" fn(
+            handle: Handle,
+            protocol_buffer: *mut *mut *const Guid,
+            protocol_buffer_count: *mut usize,
+        ) -> Status,
+    >,
+    /// `EFI_BOOT_SERVICES.LocateHandleBuffer()`
+    pub locate_handle_buffer: Option<
+        unsafe extern "efiapi" fn(
+            search_ty: u32,
+            protocol: *const Guid,
+            search_key: *mut c_void,
+            no_handles: *mut usize,
+            buffer: *mut *mut Handle,
+        ) -> Status,
+    >,
+    /// `EFI_BOOT_SERVICES.LocateProtocol()`
+    pub locate_protocol: Option<
+        unsafe extern "efiapi" fn(
+            protocol: *const Guid,
+            registration: *mut c_void,
+            interface: *mut *mut c_void,
+        ) -> Status,
+    >,
+    /// `EFI_BOOT_SERVICES.InstallMultipleProtocolInterfaces()`
+    ///
+    /// The variadic arguments are pairs of GUID and interface pointers,
+    /// terminated by a null pointer.
+    pub install_multiple_protocol_interfaces:
+        Option<unsafe extern "C" fn(handle: *mut Handle, ...) -> Status>,
+    /// `EFI_BOOT_SERVICES.UninstallMultipleProtocolInterfaces()`
+    ///
+    /// The variadic arguments are pairs of GUID and interface pointers,
+    /// terminated by a null pointer.
+    pub uninstall_multiple_protocol_interfaces:
+        Option<unsafe extern "C" fn(handle: Handle, ...) -> Status>,
+
+    // CRC services
+    /// `EFI_BOOT_SERVICES.CalculateCrc32()`
+    pub calculate_crc32: Option<
+        unsafe extern "efiapi" fn(data: *const c_void, data_size: usize, crc32: *mut u32) -> Status,
+    >,
+
+    // Misc services
+    /// `EFI_BOOT_SERVICES.CopyMem()`
+    pub copy_mem: Option<unsafe extern "efiapi" fn(dest: *mut u8, src: *const u8, len: usize)>,
+    /// `EFI_BOOT_SERVICES.SetMem()`
+    pub set_mem: Option<unsafe extern "efiapi" fn(buffer: *mut u8, len: usize, value: u8)>,
+
+    // New event functions (UEFI 2.0 or newer)
+    /// `EFI_BOOT_SERVICES.CreateEventEx()`
+    pub create_event_ex: Option<
+        unsafe extern "efiapi" fn(
+            ty: u32,
+            notify_tpl: Tpl,
+            notify_func: Option<EventNotifyFn>,
+            notify_ctx: *const c_void,
+            event_group: *const Guid,
+            event: *mut Event,
+        ) -> Status,
+    >,
+}
+
+/// Entry returned by `OpenProtocolInformation()`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct OpenProtocolInformationEntry {
+    /// Agent which opened the protocol
+    pub agent_handle: Handle,
+    /// Controller which opened the protocol, for drivers
+    pub controller_handle: Handle,
+    /// Attributes the protocol was opened with
+    pub attributes: u32,
+    /// Number of times the protocol was opened
+    pub open_count: u32,
+}
@@ -0,0 +1,308 @@
+//! Compile-time checks of the layout of the FFI structures.
+//!
+//! A field which is missing or has the wrong type shifts all the fields which
+//! follow it, which usually goes unnoticed until the firmware is handed a
+//! corrupted structure. The sizes and offsets below are those given by the
+//! UEFI specification for 64-bit and 32-bit targets, and the build fails if a
+//! structure does not match them.
+//!
+//! The safe wrappers of the protocols are checked against the size of the
+//! corresponding raw structure, which is laid out by the same rules. Those of
+//! the tables are built on the raw structures, so they need no check.
+
+use super::protocol::console::*;
+use super::protocol::loaded_image::LoadedImage;
+use super::protocol::media::*;
+use super::{boot, runtime, BootServices, Header, RuntimeServices, SystemTable};
+use crate::proto;
+use crate::proto::device_path::DevicePathHeader;
+use crate::table::boot::MemoryDescriptor;
+use crate::table::cfg::ConfigTableEntry;
+use crate::table::runtime::{Time, TimeCapabilities};
+use crate::Guid;
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::ptr;
+
+/// Select the expected value for a 64-bit or a 32-bit target
+const fn w(bits64: usize, bits32: usize) -> usize {
+    if cfg!(target_pointer_width = "64") {
+        bits64
+    } else {
+        bits32
+    }
+}
+
+/// Offset of a field in a structure, measured on an uninitialized value
+macro_rules! offset_of {
+    ($ty:ty, $field:ident) => {{
+        let value = MaybeUninit::<$ty>::uninit();
+        let base = value.as_ptr();
+        // The field is only addressed, and never read
+        let field = unsafe { ptr::addr_of!((*base).$field) };
+        unsafe { (field as *const u8).offset_from(base as *const u8) as usize }
+    }};
+}
+
+/// Check the size of a structure and the offsets of some of its fields
+macro_rules! layout {
+    ($ty:ty, size $size:expr $(, $field:ident @ $offset:expr)* $(,)?) => {
+        const _: () = {
+            assert!(
+                size_of::<$ty>() == $size,
+                concat!("unexpected size of ", stringify!($ty)),
+            );
+            $(
+                assert!(
+                    offset_of!($ty, $field) == $offset,
+                    concat!("unexpected offset of ", stringify!($ty), "::", stringify!($field)),
+                );
+            )*
+        };
+    };
+}
+
+/// Check that a safe wrapper has the same size as its raw definition
+macro_rules! same_size {
+    ($($safe:ty => $raw:ty),* $(,)?) => {
+        $(
+            const _: () = assert!(
+                size_of::<$safe>() == size_of::<$raw>(),
+                concat!(stringify!($safe), " does not match ", stringify!($raw)),
+            );
+        )*
+    };
+}
+
+// Common data types
+layout!(Guid, size 16);
+const _: () = assert!(align_of::<Guid>() == 4);
+layout!(Header, size 24, signature @ 0, revision @ 8, size @ 12, crc @ 16);
+layout!(
+    MemoryDescriptor,
+    size 40,
+    ty @ 0,
+    phys_start @ 8,
+    virt_start @ 16,
+    page_count @ 24,
+    att @ 32,
+);
+const _: () = assert!(align_of::<MemoryDescriptor>() == 8);
+layout!(Time, size 16);
+layout!(TimeCapabilities, size 12, resolution @ 0, accuracy @ 4, sets_to_zero @ 8);
+layout!(ConfigTableEntry, size w(24, 20), guid @ 0, address @ 16);
+layout!(DevicePathHeader, size 4, device_type @ 0, sub_type @ 1, length @ 2);
+
+// System table
+layout!(
+    SystemTable,
+    size w(120, 72),
+    header @ 0,
+    firmware_vendor @ 24,
+    firmware_revision @ w(32, 28),
+    stdin_handle @ w(40, 32),
+    stdin @ w(48, 36),
+    stdout_handle @ w(56, 40),
+    stdout @ w(64, 44),
+    stderr_handle @ w(72, 48),
+    stderr @ w(80, 52),
+    runtime_services @ w(88, 56),
+    boot_services @ w(96, 60),
+    number_of_configuration_table_entries @ w(104, 64),
+    configuration_table @ w(112, 68),
+);
+
+// Boot services
+layout!(
+    BootServices,
+    size w(376, 200),
+    raise_tpl @ 24,
+    allocate_pages @ w(40, 32),
+    free_pool @ w(72, 48),
+    create_event @ w(80, 52),
+    check_event @ w(120, 72),
+    install_protocol_interface @ w(128, 76),
+    handle_protocol @ w(152, 88),
+    reserved @ w(160, 92),
+    install_configuration_table @ w(192, 108),
+    load_image @ w(200, 112),
+    exit_boot_services @ w(232, 128),
+    get_next_monotonic_count @ w(240, 132),
+    set_watchdog_timer @ w(256, 140),
+    connect_controller @ w(264, 144),
+    open_protocol @ w(280, 152),
+    open_protocol_information @ w(296, 160),
+    protocols_per_handle @ w(304, 164),
+    locate_protocol @ w(320, 172),
+    install_multiple_protocol_interfaces @ w(328, 176),
+    calculate_crc32 @ w(344, 184),
+    copy_mem @ w(352, 188),
+    create_event_ex @ w(368, 196),
+);
+layout!(
+    boot::OpenProtocolInformationEntry,
+    size w(24, 16),
+    agent_handle @ 0,
+    controller_handle @ w(8, 4),
+    attributes @ w(16, 8),
+    open_count @ w(20, 12),
+);
+
+// Runtime services
+layout!(
+    RuntimeServices,
+    size w(136, 80),
+    get_time @ 24,
+    get_wakeup_time @ w(40, 32),
+    set_virtual_address_map @ w(56, 40),
+    get_variable @ w(72, 48),
+    set_variable @ w(88, 56),
+    get_next_high_monotonic_count @ w(96, 60),
+    reset_system @ w(104, 64),
+    update_capsule @ w(112, 68),
+    query_variable_info @ w(128, 76),
+);
+layout!(
+    runtime::CapsuleHeader,
+    size 28,
+    capsule_guid @ 0,
+    header_size @ 16,
+    flags @ 20,
+    capsule_image_size @ 24,
+);
+layout!(runtime::CapsuleBlockDescriptor, size 16, length @ 0, address @ 8);
+
+// Console protocols
+layout!(InputKey, size 4, scan_code @ 0, unicode_char @ 2);
+layout!(
+    SimpleTextInput,
+    size w(24, 12),
+    reset @ 0,
+    read_key_stroke @ w(8, 4),
+    wait_for_key @ w(16, 8),
+);
+layout!(
+    SimpleTextOutput,
+    size w(80, 40),
+    reset @ 0,
+    output_string @ w(8, 4),
+    query_mode @ w(24, 12),
+    clear_screen @ w(48, 24),
+    enable_cursor @ w(64, 32),
+    mode @ w(72, 36),
+);
+layout!(
+    SimpleTextOutputMode,
+    size 24,
+    max_mode @ 0,
+    attribute @ 8,
+    cursor_row @ 16,
+    cursor_visible @ 20,
+);
+layout!(
+    GraphicsOutput,
+    size w(32, 16),
+    query_mode @ 0,
+    set_mode @ w(8, 4),
+    blt @ w(16, 8),
+    mode @ w(24, 12),
+);
+layout!(
+    GraphicsOutputMode,
+    size w(40, 32),
+    max_mode @ 0,
+    mode @ 4,
+    info @ 8,
+    size_of_info @ w(16, 12),
+    frame_buffer_base @ w(24, 16),
+    frame_buffer_size @ w(32, 24),
+);
+layout!(
+    GraphicsOutputModeInformation,
+    size 36,
+    version @ 0,
+    horizontal_resolution @ 4,
+    vertical_resolution @ 8,
+    pixel_format @ 12,
+    pixel_information @ 16,
+    pixels_per_scan_line @ 32,
+);
+
+// Loaded image protocol
+layout!(
+    LoadedImage,
+    size w(96, 64),
+    revision @ 0,
+    parent_handle @ w(8, 4),
+    system_table @ w(16, 8),
+    device_handle @ w(24, 12),
+    file_path @ w(32, 16),
+    load_options_size @ w(48, 24),
+    load_options @ w(56, 28),
+    image_base @ w(64, 32),
+    image_size @ w(72, 40),
+    image_code_type @ w(80, 48),
+    image_data_type @ w(84, 52),
+    unload @ w(88, 56),
+);
+
+// Media access protocols
+layout!(SimpleFileSystem, size 16, revision @ 0, open_volume @ 8);
+layout!(
+    File,
+    size w(120, 64),
+    revision @ 0,
+    open @ 8,
+    close @ w(16, 12),
+    read @ w(32, 20),
+    get_info @ w(64, 36),
+    flush @ w(80, 44),
+    open_ex @ w(88, 48),
+    flush_ex @ w(112, 60),
+);
+layout!(
+    FileIoToken,
+    size w(32, 16),
+    event @ 0,
+    status @ w(8, 4),
+    buffer_size @ w(16, 8),
+    buffer @ w(24, 12),
+);
+layout!(
+    BlockIo,
+    size w(48, 32),
+    revision @ 0,
+    media @ 8,
+    reset @ w(16, 12),
+    read_blocks @ w(24, 16),
+    write_blocks @ w(32, 20),
+    flush_blocks @ w(40, 24),
+);
+layout!(
+    BlockIoMedia,
+    size 48,
+    media_id @ 0,
+    removable_media @ 4,
+    write_caching @ 8,
+    block_size @ 12,
+    io_align @ 16,
+    last_block @ 24,
+    lowest_aligned_lba @ 32,
+    logical_blocks_per_physical_block @ 40,
+    optimal_transfer_length_granularity @ 44,
+);
+
+// PI protocols, which have no raw definition
+layout!(proto::pi::fv::FirmwareVolume, size w(80, 40));
+layout!(proto::pi::fv::FirmwareVolumeBlock, size w(64, 32));
+
+// Safe wrappers
+same_size! {
+    proto::console::text::Input => SimpleTextInput,
+    proto::console::text::Output<'static> => SimpleTextOutput,
+    proto::console::gop::GraphicsOutput<'static> => GraphicsOutput,
+    proto::console::gop::ModeInfo => GraphicsOutputModeInformation,
+    proto::loaded_image::LoadedImage => LoadedImage,
+    proto::media::fs::SimpleFileSystem => SimpleFileSystem,
+    proto::media::block::BlockIO => BlockIo,
+    proto::media::block::BlockIOMedia => BlockIoMedia,
+}
@@ -0,0 +1,36 @@
+//! Raw definitions of the UEFI tables and protocols.
+//!
+//! The safe wrappers in [`table`](crate::table) and [`proto`](crate::proto)
+//! only expose the services which have been wrapped so far. This module
+//! contains complete `#[repr(C)]` layouts of the same structures, with every
+//! function pointer public, so that the remaining services can be called
+//! without redefining the ABI structures:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # fn f(st: &SystemTable<Boot>) {
+//! let bt = st.boot_services().as_raw();
+//! // `GetNextMonotonicCount` is not wrapped by `BootServices`
+//! let mut count = 0;
+//! let status = unsafe { (bt.get_next_monotonic_count)(&mut count) };
+//! # }
+//! ```
+//!
+//! These definitions follow the specification as closely as possible, and
+//! enforce none of the invariants of the safe wrappers: pointers are raw,
+//! flags and enumerations are plain integers when the crate models them with
+//! types which may not hold every value, and all the functions are `unsafe`.
+//!
+//! The GUIDs of the protocols are those of the corresponding safe types,
+//! provided by the [`Identify`](crate::Identify) trait.
+
+pub mod boot;
+mod layout;
+pub mod protocol;
+pub mod runtime;
+pub mod system;
+
+pub use self::boot::BootServices;
+pub use self::runtime::RuntimeServices;
+pub use self::system::SystemTable;
+pub use crate::table::Header;
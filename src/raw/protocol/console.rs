@@ -0,0 +1,139 @@
+//! Raw console protocols.
+
+use crate::{Char16, Event, Status};
+
+/// `EFI_SIMPLE_TEXT_INPUT_PROTOCOL`
+#[repr(C)]
+pub struct SimpleTextInput {
+    /// `Reset()`
+    pub reset: unsafe extern "efiapi" fn(this: *mut Self, extended_verification: bool) -> Status,
+    /// `ReadKeyStroke()`
+    pub read_key_stroke: unsafe extern "efiapi" fn(this: *mut Self, key: *mut InputKey) -> Status,
+    /// Event signaled when a key is available
+    pub wait_for_key: Event,
+}
+
+/// `EFI_INPUT_KEY`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InputKey {
+    /// Scan code of the key, or 0 if it is printable
+    pub scan_code: u16,
+    /// Character of the key, or 0 if it is not printable
+    pub unicode_char: Char16,
+}
+
+/// `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`
+#[repr(C)]
+pub struct SimpleTextOutput {
+    /// `Reset()`
+    pub reset: unsafe extern "efiapi" fn(this: *mut Self, extended_verification: bool) -> Status,
+    /// `OutputString()`
+    pub output_string: unsafe extern "efiapi" fn(this: *mut Self, string: *const Char16) -> Status,
+    /// `TestString()`
+    pub test_string: unsafe extern "efiapi" fn(this: *mut Self, string: *const Char16) -> Status,
+    /// `QueryMode()`
+    pub query_mode: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        mode_number: usize,
+        columns: *mut usize,
+        rows: *mut usize,
+    ) -> Status,
+    /// `SetMode()`
+    pub set_mode: unsafe extern "efiapi" fn(this: *mut Self, mode_number: usize) -> Status,
+    /// `SetAttribute()`
+    pub set_attribute: unsafe extern "efiapi" fn(this: *mut Self, attribute: usize) -> Status,
+    /// `ClearScreen()`
+    pub clear_screen: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+    /// `SetCursorPosition()`
+    pub set_cursor_position:
+        unsafe extern "efiapi" fn(this: *mut Self, column: usize, row: usize) -> Status,
+    /// `EnableCursor()`
+    pub enable_cursor: unsafe extern "efiapi" fn(this: *mut Self, visible: bool) -> Status,
+    /// Current state of the device
+    pub mode: *mut SimpleTextOutputMode,
+}
+
+/// `SIMPLE_TEXT_OUTPUT_MODE`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SimpleTextOutputMode {
+    /// Number of modes supported by the device
+    pub max_mode: i32,
+    /// Current mode, or -1 if no mode is set
+    pub mode: i32,
+    /// Current character attribute
+    pub attribute: i32,
+    /// Column of the cursor
+    pub cursor_column: i32,
+    /// Row of the cursor
+    pub cursor_row: i32,
+    /// Whether the cursor is visible
+    pub cursor_visible: bool,
+}
+
+/// `EFI_GRAPHICS_OUTPUT_PROTOCOL`
+#[repr(C)]
+pub struct GraphicsOutput {
+    /// `QueryMode()`
+    pub query_mode: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        mode_number: u32,
+        size_of_info: *mut usize,
+        info: *mut *const GraphicsOutputModeInformation,
+    ) -> Status,
+    /// `SetMode()`
+    pub set_mode: unsafe extern "efiapi" fn(this: *mut Self, mode_number: u32) -> Status,
+    /// `Blt()`
+    #[allow(clippy::type_complexity)]
+    pub blt: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        blt_buffer: *mut u32,
+        blt_operation: u32,
+        source_x: usize,
+        source_y: usize,
+        destination_x: usize,
+        destination_y: usize,
+        width: usize,
+        height: usize,
+        delta: usize,
+    ) -> Status,
+    /// Current mode of the device
+    pub mode: *mut GraphicsOutputMode,
+}
+
+/// `EFI_GRAPHICS_OUTPUT_PROTOCOL_MODE`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct GraphicsOutputMode {
+    /// Number of modes supported by the device
+    pub max_mode: u32,
+    /// Current mode
+    pub mode: u32,
+    /// Information about the current mode
+    pub info: *const GraphicsOutputModeInformation,
+    /// Size of `info`
+    pub size_of_info: usize,
+    /// Physical address of the frame buffer
+    pub frame_buffer_base: u64,
+    /// Size of the frame buffer in bytes
+    pub frame_buffer_size: usize,
+}
+
+/// `EFI_GRAPHICS_OUTPUT_MODE_INFORMATION`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct GraphicsOutputModeInformation {
+    /// Version of this structure, 0 in the current specification
+    pub version: u32,
+    /// Horizontal resolution
+    pub horizontal_resolution: u32,
+    /// Vertical resolution
+    pub vertical_resolution: u32,
+    /// `EFI_GRAPHICS_PIXEL_FORMAT`
+    pub pixel_format: u32,
+    /// Red, green, blue and reserved masks, for the bitmask pixel format
+    pub pixel_information: [u32; 4],
+    /// Number of pixels per scan line
+    pub pixels_per_scan_line: u32,
+}
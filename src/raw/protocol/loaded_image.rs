@@ -0,0 +1,37 @@
+//! Raw loaded image protocol.
+
+use crate::proto::device_path::DevicePath;
+use crate::table::boot::MemoryType;
+use crate::{Handle, Status};
+use core::ffi::c_void;
+
+/// `EFI_LOADED_IMAGE_PROTOCOL`
+#[repr(C)]
+pub struct LoadedImage {
+    /// Revision of the structure
+    pub revision: u32,
+    /// Image which loaded this image, or null
+    pub parent_handle: Handle,
+    /// Image's system table
+    pub system_table: *mut super::super::SystemTable,
+    /// Device the image was loaded from
+    pub device_handle: Handle,
+    /// Path of the image file, relative to `device_handle`
+    pub file_path: *mut DevicePath,
+    /// Reserved, must be null
+    pub reserved: *mut c_void,
+    /// Size of the load options in bytes
+    pub load_options_size: u32,
+    /// Load options of the image
+    pub load_options: *mut c_void,
+    /// Address the image was loaded at
+    pub image_base: *mut c_void,
+    /// Size of the loaded image in bytes
+    pub image_size: u64,
+    /// Memory type of the code sections
+    pub image_code_type: MemoryType,
+    /// Memory type of the data sections
+    pub image_data_type: MemoryType,
+    /// Function called by `UnloadImage()`, if the image supports it
+    pub unload: Option<unsafe extern "efiapi" fn(image_handle: Handle) -> Status>,
+}
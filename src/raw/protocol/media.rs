@@ -0,0 +1,156 @@
+//! Raw media access protocols.
+
+use crate::{Char16, Event, Guid, Status};
+use core::ffi::c_void;
+
+/// `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL`
+#[repr(C)]
+pub struct SimpleFileSystem {
+    /// Revision of the protocol
+    pub revision: u64,
+    /// `OpenVolume()`
+    pub open_volume: unsafe extern "efiapi" fn(this: *mut Self, root: *mut *mut File) -> Status,
+}
+
+/// `EFI_FILE_PROTOCOL`
+///
+/// The functions following `flush` were added in revision 2 of the protocol.
+#[repr(C)]
+pub struct File {
+    /// Revision of the protocol
+    pub revision: u64,
+    /// `Open()`
+    pub open: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        new_handle: *mut *mut Self,
+        file_name: *const Char16,
+        open_mode: u64,
+        attributes: u64,
+    ) -> Status,
+    /// `Close()`
+    pub close: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+    /// `Delete()`
+    pub delete: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+    /// `Read()`
+    pub read: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    /// `Write()`
+    pub write: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        buffer_size: *mut usize,
+        buffer: *const c_void,
+    ) -> Status,
+    /// `GetPosition()`
+    pub get_position: unsafe extern "efiapi" fn(this: *mut Self, position: *mut u64) -> Status,
+    /// `SetPosition()`
+    pub set_position: unsafe extern "efiapi" fn(this: *mut Self, position: u64) -> Status,
+    /// `GetInfo()`
+    pub get_info: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        information_type: *const Guid,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    /// `SetInfo()`
+    pub set_info: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        information_type: *const Guid,
+        buffer_size: usize,
+        buffer: *const c_void,
+    ) -> Status,
+    /// `Flush()`
+    pub flush: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+    /// `OpenEx()`
+    pub open_ex: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        new_handle: *mut *mut Self,
+        file_name: *const Char16,
+        open_mode: u64,
+        attributes: u64,
+        token: *mut FileIoToken,
+    ) -> Status,
+    /// `ReadEx()`
+    pub read_ex: unsafe extern "efiapi" fn(this: *mut Self, token: *mut FileIoToken) -> Status,
+    /// `WriteEx()`
+    pub write_ex: unsafe extern "efiapi" fn(this: *mut Self, token: *mut FileIoToken) -> Status,
+    /// `FlushEx()`
+    pub flush_ex: unsafe extern "efiapi" fn(this: *mut Self, token: *mut FileIoToken) -> Status,
+}
+
+/// `EFI_FILE_IO_TOKEN`, used by the asynchronous file functions
+#[repr(C)]
+pub struct FileIoToken {
+    /// Event signaled when the request completes
+    pub event: Event,
+    /// Status of the request, once it completed
+    pub status: Status,
+    /// Number of bytes to transfer, and then transferred
+    pub buffer_size: usize,
+    /// Buffer of the data
+    pub buffer: *mut c_void,
+}
+
+/// `EFI_BLOCK_IO_PROTOCOL`
+#[repr(C)]
+pub struct BlockIo {
+    /// Revision of the protocol
+    pub revision: u64,
+    /// Description of the media
+    pub media: *const BlockIoMedia,
+    /// `Reset()`
+    pub reset: unsafe extern "efiapi" fn(this: *mut Self, extended_verification: bool) -> Status,
+    /// `ReadBlocks()`
+    pub read_blocks: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        media_id: u32,
+        lba: u64,
+        buffer_size: usize,
+        buffer: *mut c_void,
+    ) -> Status,
+    /// `WriteBlocks()`
+    pub write_blocks: unsafe extern "efiapi" fn(
+        this: *mut Self,
+        media_id: u32,
+        lba: u64,
+        buffer_size: usize,
+        buffer: *const c_void,
+    ) -> Status,
+    /// `FlushBlocks()`
+    pub flush_blocks: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+}
+
+/// `EFI_BLOCK_IO_MEDIA`
+///
+/// The fields following `last_block` only exist in revision 2 and 3 of the
+/// block I/O protocol.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct BlockIoMedia {
+    /// Identifier of the media, changed when it is replaced
+    pub media_id: u32,
+    /// Whether the media can be removed
+    pub removable_media: bool,
+    /// Whether there is media in the device
+    pub media_present: bool,
+    /// Whether the device is a partition of another device
+    pub logical_partition: bool,
+    /// Whether the media is read-only
+    pub read_only: bool,
+    /// Whether writes are cached
+    pub write_caching: bool,
+    /// Size of the blocks in bytes
+    pub block_size: u32,
+    /// Required alignment of the buffers
+    pub io_align: u32,
+    /// Last logical block address of the device
+    pub last_block: u64,
+    /// First logical block aligned to a physical block, since revision 2
+    pub lowest_aligned_lba: u64,
+    /// Number of logical blocks per physical block, since revision 2
+    pub logical_blocks_per_physical_block: u32,
+    /// Optimal transfer length granularity in blocks, since revision 3
+    pub optimal_transfer_length_granularity: u32,
+}
@@ -0,0 +1,10 @@
+//! Raw definitions of the most commonly used protocols.
+//!
+//! Each protocol function takes a pointer to the protocol structure as its
+//! first argument, like the firmware expects. Device paths have no member
+//! functions, and are described by
+//! [`DevicePathHeader`](crate::proto::device_path::DevicePathHeader).
+
+pub mod console;
+pub mod loaded_image;
+pub mod media;
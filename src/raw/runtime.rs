@@ -0,0 +1,150 @@
+//! Raw runtime services table.
+
+use super::Header;
+use crate::table::boot::MemoryDescriptor;
+use crate::table::runtime::{Time, TimeCapabilities};
+use crate::{Char16, Guid, Status};
+use core::ffi::c_void;
+
+/// Table of the runtime services, as laid out by the firmware.
+///
+/// Some firmware, such as U-Boot, leaves the services it does not implement
+/// null, hence the `Option`s.
+#[repr(C)]
+pub struct RuntimeServices {
+    /// Table header
+    pub header: Header,
+
+    // Time services
+    /// `EFI_RUNTIME_SERVICES.GetTime()`
+    pub get_time: Option<
+        unsafe extern "efiapi" fn(time: *mut Time, capabilities: *mut TimeCapabilities) -> Status,
+    >,
+    /// `EFI_RUNTIME_SERVICES.SetTime()`
+    pub set_time: Option<unsafe extern "efiapi" fn(time: *const Time) -> Status>,
+    /// `EFI_RUNTIME_SERVICES.GetWakeupTime()`
+    pub get_wakeup_time: Option<
+        unsafe extern "efiapi" fn(
+            enabled: *mut bool,
+            pending: *mut bool,
+            time: *mut Time,
+        ) -> Status,
+    >,
+    /// `EFI_RUNTIME_SERVICES.SetWakeupTime()`
+    pub set_wakeup_time:
+        Option<unsafe extern "efiapi" fn(enable: bool, time: *const Time) -> Status>,
+
+    // Virtual memory services
+    /// `EFI_RUNTIME_SERVICES.SetVirtualAddressMap()`
+    pub set_virtual_address_map: Option<
+        unsafe extern "efiapi" fn(
+            map_size: usize,
+            desc_size: usize,
+            desc_version: u32,
+            virtual_map: *mut MemoryDescriptor,
+        ) -> Status,
+    >,
+    /// `EFI_RUNTIME_SERVICES.ConvertPointer()`
+    pub convert_pointer: Option<
+        unsafe extern "efiapi" fn(debug_disposition: usize, address: *mut *const c_void) -> Status,
+    >,
+
+    // Variable services
+    /// `EFI_RUNTIME_SERVICES.GetVariable()`
+    pub get_variable: Option<
+        unsafe extern "efiapi" fn(
+            variable_name: *const Char16,
+            vendor_guid: *const Guid,
+            attributes: *mut u32,
+            data_size: *mut usize,
+            data: *mut u8,
+        ) -> Status,
+    >,
+    /// `EFI_RUNTIME_SERVICES.GetNextVariableName()`
+    pub get_next_variable_name: Option<
+        unsafe extern "efiapi" fn(
+            variable_name_size: *mut usize,
+            variable_name: *mut Char16,
+            vendor_guid: *mut Guid,
+        ) -> Status,
+    >,
+    /// `EFI_RUNTIME_SERVICES.SetVariable()`
+    pub set_variable: Option<
+        unsafe extern "efiapi" fn(
+            variable_name: *const Char16,
+            vendor_guid: *const Guid,
+            attributes: u32,
+            data_size: usize,
+            data: *const u8,
+        ) -> Status,
+    >,
+
+    // Misc services
+    /// `EFI_RUNTIME_SERVICES.GetNextHighMonotonicCount()`
+    pub get_next_high_monotonic_count:
+        Option<unsafe extern "efiapi" fn(high_count: *mut u32) -> Status>,
+    /// `EFI_RUNTIME_SERVICES.ResetSystem()`
+    pub reset_system: Option<
+        unsafe extern "efiapi" fn(
+            reset_type: u32,
+            status: Status,
+            data_size: usize,
+            data: *const u8,
+        ) -> !,
+    >,
+
+    // UEFI 2.0 capsule services
+    /// `EFI_RUNTIME_SERVICES.UpdateCapsule()`
+    pub update_capsule: Option<
+        unsafe extern "efiapi" fn(
+            capsule_header_array: *const *const CapsuleHeader,
+            capsule_count: usize,
+            scatter_gather_list: u64,
+        ) -> Status,
+    >,
+    /// `EFI_RUNTIME_SERVICES.QueryCapsuleCapabilities()`
+    pub query_capsule_capabilities: Option<
+        unsafe extern "efiapi" fn(
+            capsule_header_array: *const *const CapsuleHeader,
+            capsule_count: usize,
+            maximum_capsule_size: *mut u64,
+            reset_type: *mut u32,
+        ) -> Status,
+    >,
+
+    // Miscellaneous UEFI 2.0 service
+    /// `EFI_RUNTIME_SERVICES.QueryVariableInfo()`
+    pub query_variable_info: Option<
+        unsafe extern "efiapi" fn(
+            attributes: u32,
+            maximum_variable_storage_size: *mut u64,
+            remaining_variable_storage_size: *mut u64,
+            maximum_variable_size: *mut u64,
+        ) -> Status,
+    >,
+}
+
+/// Header of a capsule passed to `UpdateCapsule()`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct CapsuleHeader {
+    /// GUID identifying the format of the capsule
+    pub capsule_guid: Guid,
+    /// Size of this header
+    pub header_size: u32,
+    /// Flags telling how the firmware should process the capsule
+    pub flags: u32,
+    /// Size of the capsule, including this header
+    pub capsule_image_size: u32,
+}
+
+/// Element of the scatter-gather list passed to `UpdateCapsule()`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct CapsuleBlockDescriptor {
+    /// Size of the data block, or 0 for the end of the list
+    pub length: u64,
+    /// Address of the data block, or of the next block of descriptors if
+    /// `length` is 0
+    pub address: u64,
+}
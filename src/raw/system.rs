@@ -0,0 +1,38 @@
+//! Raw system table.
+
+use super::protocol::console::{SimpleTextInput, SimpleTextOutput};
+use super::{BootServices, Header, RuntimeServices};
+use crate::table::cfg::ConfigTableEntry;
+use crate::table::Revision;
+use crate::{Char16, Handle};
+
+/// The system table, as laid out by the firmware.
+#[repr(C)]
+pub struct SystemTable {
+    /// Table header
+    pub header: Header,
+    /// Null-terminated string representing the firmware's vendor
+    pub firmware_vendor: *const Char16,
+    /// Revision of the firmware
+    pub firmware_revision: Revision,
+    /// Handle of the console input device
+    pub stdin_handle: Handle,
+    /// Console input protocol
+    pub stdin: *mut SimpleTextInput,
+    /// Handle of the console output device
+    pub stdout_handle: Handle,
+    /// Console output protocol
+    pub stdout: *mut SimpleTextOutput,
+    /// Handle of the standard error device
+    pub stderr_handle: Handle,
+    /// Standard error protocol
+    pub stderr: *mut SimpleTextOutput,
+    /// Runtime services table
+    pub runtime_services: *mut RuntimeServices,
+    /// Boot services table, null after boot services are exited
+    pub boot_services: *mut BootServices,
+    /// Number of entries in the configuration table
+    pub number_of_configuration_table_entries: usize,
+    /// Pointer to the beginning of the configuration table
+    pub configuration_table: *mut ConfigTableEntry,
+}
@@ -0,0 +1,226 @@
+use super::{Error, Status};
+#[cfg(feature = "exts")]
+use crate::alloc_api::string::String;
+use core::fmt::{self, Debug, Display, Formatter};
+
+/// Description of a step of an operation
+#[derive(Debug, Clone)]
+enum Frame {
+    Static(&'static str),
+    #[cfg(feature = "exts")]
+    Owned(String),
+}
+
+impl Frame {
+    fn as_str(&self) -> &str {
+        match self {
+            Frame::Static(message) => message,
+            #[cfg(feature = "exts")]
+            Frame::Owned(message) => message,
+        }
+    }
+}
+
+/// Error data describing what was being done when an error happened
+///
+/// Each call to `ResultContext::context` on the way up adds a description,
+/// so that the error tells which step of a multi-step operation failed:
+///
+/// ```no_run
+/// # use uefi::prelude::*;
+/// # use uefi::{Context, ResultContext};
+/// # fn open(bt: &BootServices) -> uefi::Result { Ok(().into()) }
+/// fn load_kernel(bt: &BootServices) -> uefi::Result<(), Context> {
+///     open(bt).context("opening kernel image")?.log();
+///     Ok(().into())
+/// }
+///
+/// # fn f(bt: &BootServices) {
+/// if let Err(err) = load_kernel(bt).context("booting Linux") {
+///     // "booting Linux: opening kernel image: NOT_FOUND"
+///     log::error!("{}", err);
+/// }
+/// # }
+/// ```
+///
+/// Up to `Context::MAX_DEPTH` descriptions are kept, the innermost ones
+/// first, so that no allocation is needed and errors stay small.
+#[derive(Clone, Default)]
+pub struct Context {
+    frames: [Option<Frame>; Context::MAX_DEPTH],
+    len: usize,
+    /// Number of descriptions which did not fit
+    dropped: usize,
+}
+
+impl Context {
+    /// Maximum number of descriptions which are kept
+    pub const MAX_DEPTH: usize = 4;
+
+    fn push(&mut self, frame: Frame) {
+        match self.frames.get_mut(self.len) {
+            Some(slot) => {
+                *slot = Some(frame);
+                self.len += 1;
+            }
+            None => self.dropped += 1,
+        }
+    }
+
+    /// The descriptions, from the outermost to the innermost one
+    pub fn frames(&self) -> impl Iterator<Item = &str> {
+        self.frames[..self.len]
+            .iter()
+            .rev()
+            .filter_map(|frame| frame.as_ref().map(Frame::as_str))
+    }
+}
+
+impl From<()> for Context {
+    fn from(_: ()) -> Self {
+        Context::default()
+    }
+}
+
+impl Debug for Context {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.frames()).finish()
+    }
+}
+
+/// The descriptions, separated by colons
+impl Display for Context {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.dropped != 0 {
+            write!(f, "...: ")?;
+        }
+        for (i, frame) in self.frames().enumerate() {
+            if i != 0 {
+                write!(f, ": ")?;
+            }
+            f.write_str(frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// The descriptions, followed by the status
+impl Display for Error<Context> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.data().len != 0 {
+            write!(f, "{}: ", self.data())?;
+        }
+        write!(f, "{:?}", self.status())
+    }
+}
+
+/// Extension trait which describes the errors of a result with a `Context`
+pub trait ResultContext<Output> {
+    /// Add a description of what was being done to the error, if any
+    fn context(self, message: &'static str) -> core::result::Result<Output, Error<Context>>;
+
+    /// Add a description built by `message` to the error, if any.
+    ///
+    /// `message` is only called if there is an error, so that formatting
+    /// costs nothing on success.
+    #[cfg(feature = "exts")]
+    fn with_context<M: Into<String>>(
+        self,
+        message: impl FnOnce() -> M,
+    ) -> core::result::Result<Output, Error<Context>>;
+}
+
+impl<Output, ErrData> ResultContext<Output> for core::result::Result<Output, Error<ErrData>>
+where
+    ErrData: Debug + Into<Context>,
+{
+    fn context(self, message: &'static str) -> core::result::Result<Output, Error<Context>> {
+        self.map_err(|err| wrap(err, Frame::Static(message)))
+    }
+
+    #[cfg(feature = "exts")]
+    fn with_context<M: Into<String>>(
+        self,
+        message: impl FnOnce() -> M,
+    ) -> core::result::Result<Output, Error<Context>> {
+        self.map_err(|err| wrap(err, Frame::Owned(message().into())))
+    }
+}
+
+impl ResultContext<()> for Status {
+    fn context(self, message: &'static str) -> core::result::Result<(), Error<Context>> {
+        if self.is_error() {
+            Err(wrap(Error::from(self), Frame::Static(message)))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "exts")]
+    fn with_context<M: Into<String>>(
+        self,
+        message: impl FnOnce() -> M,
+    ) -> core::result::Result<(), Error<Context>> {
+        if self.is_error() {
+            Err(wrap(Error::from(self), Frame::Owned(message().into())))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn wrap<ErrData: Debug + Into<Context>>(err: Error<ErrData>, frame: Frame) -> Error<Context> {
+    let (status, data) = err.split();
+    let mut context = data.into();
+    context.push(frame);
+    Error::new(status, context)
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::format;
+    use crate::Result;
+
+    fn failing() -> Result {
+        Err(Status::NOT_FOUND.into())
+    }
+
+    fn outer() -> Result<(), Context> {
+        failing().context("opening kernel image")?.log();
+        Ok(().into())
+    }
+
+    #[test]
+    fn chain() {
+        let err = outer().context("booting Linux").unwrap_err();
+        assert_eq!(err.status(), Status::NOT_FOUND);
+        assert_eq!(
+            format!("{}", err),
+            "booting Linux: opening kernel image: NOT_FOUND"
+        );
+        assert_eq!(
+            format!("{:?}", err.data()),
+            r#"["booting Linux", "opening kernel image"]"#
+        );
+
+        let err = failing()
+            .with_context(|| format!("reading {}", "fs0:"))
+            .unwrap_err();
+        assert_eq!(format!("{}", err), "reading fs0:: NOT_FOUND");
+        assert!(Status::WARN_DELETE_FAILURE.context("ignored").is_ok());
+    }
+
+    #[test]
+    fn overflow() {
+        let mut result: core::result::Result<(), Error<Context>> =
+            Status::ABORTED.context("step 0");
+        for _ in 1..Context::MAX_DEPTH + 2 {
+            result = result.context("outer");
+        }
+        let err = result.unwrap_err();
+        assert_eq!(err.data().frames().count(), Context::MAX_DEPTH);
+        assert_eq!(err.data().frames().last(), Some("step 0"));
+        assert!(format!("{}", err).starts_with("...: outer: "));
+    }
+}
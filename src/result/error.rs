@@ -1,5 +1,5 @@
 use super::Status;
-use core::fmt::Debug;
+use core::fmt::{self, Debug, Display};
 
 /// Errors emitted from UEFI entry point must propagate erronerous UEFI statuses,
 /// and may optionally propagate additional entry point-specific data.
@@ -10,14 +10,17 @@ pub struct Error<Data: Debug = ()> {
 }
 
 impl<Data: Debug> Error<Data> {
+    /// Create an error from its status and its data
     pub fn new(status: Status, data: Data) -> Self {
         Self { status, data }
     }
 
+    /// The status of the error
     pub fn status(&self) -> Status {
         self.status
     }
 
+    /// The data which comes with the error
     pub fn data(&self) -> &Data {
         &self.data
     }
@@ -28,6 +31,13 @@ impl<Data: Debug> Error<Data> {
     }
 }
 
+impl Display for Error<()> {
+    /// Print the status of the error
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.status, f)
+    }
+}
+
 // Errors without payloads can be autogenerated from statuses
 
 impl From<Status> for Error<()> {
@@ -25,6 +25,10 @@ pub use self::error::Error;
 mod status;
 pub use self::status::Status;
 
+/// Descriptions of the steps which led to an error
+mod context;
+pub use self::context::{Context, ResultContext};
+
 /// Return type of most UEFI functions. Both success and error payloads are optional.
 pub type Result<Output = (), ErrData = ()> =
     core::result::Result<Completion<Output>, Error<ErrData>>;
@@ -104,3 +108,58 @@ impl<Output, ErrData: Debug> ResultExt<Output, ErrData> for Result<Output, ErrDa
         }
     }
 }
+
+/// What `StatusExt::to_result_with_policy` does with warnings
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WarningPolicy {
+    /// Log the warnings, and otherwise treat them as success.
+    Log,
+    /// Report the warnings as errors.
+    Fail,
+}
+
+/// Extension trait for the raw `Status` values returned by UEFI functions
+pub trait StatusExt {
+    /// Convert this status into a `Result`, keeping warnings in the `Completion`
+    fn to_result(self) -> Result;
+
+    /// Convert this status into a `Result`, handling warnings as `policy`
+    /// says
+    fn to_result_with_policy(self, policy: WarningPolicy) -> core::result::Result<(), Error>;
+}
+
+impl StatusExt for Status {
+    fn to_result(self) -> Result {
+        self.into()
+    }
+
+    fn to_result_with_policy(self, policy: WarningPolicy) -> core::result::Result<(), Error> {
+        match policy {
+            WarningPolicy::Log => self.to_result().log_warning(),
+            WarningPolicy::Fail => self.to_result().warning_as_error(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warning_policy() {
+        let result = |status: Status, policy| status.to_result_with_policy(policy);
+        assert!(result(Status::SUCCESS, WarningPolicy::Log).is_ok());
+        assert!(result(Status::SUCCESS, WarningPolicy::Fail).is_ok());
+        assert!(result(Status::WARN_STALE_DATA, WarningPolicy::Log).is_ok());
+        assert_eq!(
+            result(Status::WARN_STALE_DATA, WarningPolicy::Fail).map_err(|err| err.status()),
+            Err(Status::WARN_STALE_DATA)
+        );
+        for policy in [WarningPolicy::Log, WarningPolicy::Fail] {
+            assert_eq!(
+                result(Status::NOT_FOUND, policy).map_err(|err| err.status()),
+                Err(Status::NOT_FOUND)
+            );
+        }
+    }
+}
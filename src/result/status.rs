@@ -1,9 +1,10 @@
 use super::{Completion, Error, Result};
+use core::fmt::{self, Debug, Display};
+use core::num::NonZeroUsize;
 use core::{
     convert::Infallible,
     ops::{ControlFlow, FromResidual, Try},
 };
-use core::{fmt::Debug, num::NonZeroUsize};
 
 /// Bit indicating that an UEFI status code is an error
 const ERROR_BIT: usize = 1 << (core::mem::size_of::<usize>() * 8 - 1);
@@ -105,6 +106,20 @@ pub enum Status: usize => {
     IP_ADDRESS_CONFLICT     = ERROR_BIT | 34,
     /// A HTTP error occurred during the network operation.
     HTTP_ERROR              = ERROR_BIT | 35,
+    /// The network is unreachable (ICMP).
+    NETWORK_UNREACHABLE     = ERROR_BIT | 100,
+    /// The host is unreachable (ICMP).
+    HOST_UNREACHABLE        = ERROR_BIT | 101,
+    /// The protocol is unreachable (ICMP).
+    PROTOCOL_UNREACHABLE    = ERROR_BIT | 102,
+    /// The port is unreachable (ICMP).
+    PORT_UNREACHABLE        = ERROR_BIT | 103,
+    /// The connection was closed by the peer.
+    CONNECTION_FIN          = ERROR_BIT | 104,
+    /// The connection was reset by the peer.
+    CONNECTION_RESET        = ERROR_BIT | 105,
+    /// The connection was refused by the peer.
+    CONNECTION_REFUSED      = ERROR_BIT | 106,
 }}
 
 impl Status {
@@ -126,6 +141,110 @@ impl Status {
         self.0 & ERROR_BIT != 0
     }
 
+    /// Returns true if the status code reports a problem with a physical
+    /// device or the medium inside of it.
+    pub fn is_device_error(self) -> bool {
+        matches!(
+            self,
+            Status::DEVICE_ERROR
+                | Status::WRITE_PROTECTED
+                | Status::VOLUME_CORRUPTED
+                | Status::VOLUME_FULL
+                | Status::NO_MEDIA
+                | Status::MEDIA_CHANGED
+                | Status::CRC_ERROR
+                | Status::END_OF_MEDIA
+        )
+    }
+
+    /// Returns true if the status code reports a failure of a network
+    /// protocol, or of the remote host it was talking to.
+    pub fn is_protocol_error(self) -> bool {
+        matches!(
+            self,
+            Status::NO_RESPONSE
+                | Status::NO_MAPPING
+                | Status::ICMP_ERROR
+                | Status::TFTP_ERROR
+                | Status::PROTOCOL_ERROR
+                | Status::IP_ADDRESS_CONFLICT
+                | Status::HTTP_ERROR
+                | Status::NETWORK_UNREACHABLE
+                | Status::HOST_UNREACHABLE
+                | Status::PROTOCOL_UNREACHABLE
+                | Status::PORT_UNREACHABLE
+                | Status::CONNECTION_FIN
+                | Status::CONNECTION_RESET
+                | Status::CONNECTION_REFUSED
+        )
+    }
+
+    /// A description of the status code, or `None` for the codes which the
+    /// specification does not define
+    pub fn description(self) -> Option<&'static str> {
+        let description = match self {
+            Status::SUCCESS => "the operation completed successfully",
+            Status::WARN_UNKNOWN_GLYPH => "characters that could not be rendered were skipped",
+            Status::WARN_DELETE_FAILURE => "the handle was closed, but the file was not deleted",
+            Status::WARN_WRITE_FAILURE => {
+                "the handle was closed, but the data of the file was not flushed"
+            }
+            Status::WARN_BUFFER_TOO_SMALL => "the buffer was too small, and the data was truncated",
+            Status::WARN_STALE_DATA => "the data was not updated within the time set by policy",
+            Status::WARN_FILE_SYSTEM => "the buffer contains a UEFI-compliant file system",
+            Status::WARN_RESET_REQUIRED => "the operation will be processed across a reset",
+            Status::LOAD_ERROR => "the image failed to load",
+            Status::INVALID_PARAMETER => "a parameter was incorrect",
+            Status::UNSUPPORTED => "the operation is not supported",
+            Status::BAD_BUFFER_SIZE => "the buffer was not the proper size for the request",
+            Status::BUFFER_TOO_SMALL => "the buffer is not large enough to hold the data",
+            Status::NOT_READY => "there is no data pending",
+            Status::DEVICE_ERROR => "the device reported an error",
+            Status::WRITE_PROTECTED => "the device cannot be written to",
+            Status::OUT_OF_RESOURCES => "a resource has run out",
+            Status::VOLUME_CORRUPTED => "an inconsistency was detected on the file system",
+            Status::VOLUME_FULL => "there is no more space on the file system",
+            Status::NO_MEDIA => "the device does not contain any medium",
+            Status::MEDIA_CHANGED => "the medium has changed since the last access",
+            Status::NOT_FOUND => "the item was not found",
+            Status::ACCESS_DENIED => "access was denied",
+            Status::NO_RESPONSE => "the server was not found or did not respond",
+            Status::NO_MAPPING => "a mapping to a device does not exist",
+            Status::TIMEOUT => "the timeout expired",
+            Status::NOT_STARTED => "the protocol has not been started",
+            Status::ALREADY_STARTED => "the protocol has already been started",
+            Status::ABORTED => "the operation was aborted",
+            Status::ICMP_ERROR => "an ICMP error occurred during the network operation",
+            Status::TFTP_ERROR => "a TFTP error occurred during the network operation",
+            Status::PROTOCOL_ERROR => "a protocol error occurred during the network operation",
+            Status::INCOMPATIBLE_VERSION => "the version is incompatible with the requested one",
+            Status::SECURITY_VIOLATION => "the operation was denied by a security violation",
+            Status::CRC_ERROR => "a CRC error was detected",
+            Status::END_OF_MEDIA => "the beginning or end of the medium was reached",
+            Status::END_OF_FILE => "the end of the file was reached",
+            Status::INVALID_LANGUAGE => "the language is invalid",
+            Status::COMPROMISED_DATA => "the security status of the data is compromised",
+            Status::IP_ADDRESS_CONFLICT => "there is an address conflict",
+            Status::HTTP_ERROR => "an HTTP error occurred during the network operation",
+            Status::NETWORK_UNREACHABLE => "the network is unreachable",
+            Status::HOST_UNREACHABLE => "the host is unreachable",
+            Status::PROTOCOL_UNREACHABLE => "the protocol is unreachable",
+            Status::PORT_UNREACHABLE => "the port is unreachable",
+            Status::CONNECTION_FIN => "the connection was closed by the peer",
+            Status::CONNECTION_RESET => "the connection was reset by the peer",
+            Status::CONNECTION_REFUSED => "the connection was refused by the peer",
+            _ => return None,
+        };
+        Some(description)
+    }
+
+    /// Returns the raw value of the status code, as it is passed across the
+    /// UEFI ABI.
+    #[inline]
+    pub fn into_raw(self) -> usize {
+        self.0
+    }
+
     /// Converts this status code into a result with a given value.
     #[inline]
     #[allow(clippy::result_unit_err)]
@@ -165,6 +284,27 @@ impl Status {
     }
 }
 
+impl Display for Status {
+    /// Print the name of the status code in the specification and its
+    /// description, such as `EFI_NOT_FOUND: the item was not found`, or its
+    /// value if it is not a standard one
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.description() {
+            // `Debug` prints the name of the constant
+            Some(description) => write!(f, "EFI_{:?}: {}", self, description),
+            None if self.is_error() => write!(f, "unknown error {:#x}", self.0 & !ERROR_BIT),
+            None => write!(f, "unknown warning {:#x}", self.0),
+        }
+    }
+}
+
+impl From<usize> for Status {
+    #[inline]
+    fn from(raw: usize) -> Self {
+        Status(raw)
+    }
+}
+
 // An UEFI status is equivalent to a Result with no data or error payload
 impl From<Status> for Result<(), ()> {
     #[inline]
@@ -223,3 +363,25 @@ impl From<ucs2::Error> for Status {
         }
     }
 }
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::string::ToString;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Status::NOT_FOUND.to_string(),
+            "EFI_NOT_FOUND: the item was not found"
+        );
+        assert_eq!(
+            Status::WARN_RESET_REQUIRED.to_string(),
+            "EFI_WARN_RESET_REQUIRED: the operation will be processed across a reset"
+        );
+        assert_eq!(Status(ERROR_BIT | 0x42).to_string(), "unknown error 0x42");
+        assert_eq!(Status(0x42).to_string(), "unknown warning 0x42");
+        let error: Error = Status::ACCESS_DENIED.into();
+        assert_eq!(error.to_string(), "EFI_ACCESS_DENIED: access was denied");
+    }
+}
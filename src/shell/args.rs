@@ -0,0 +1,339 @@
+use crate::alloc_api::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use crate::proto::loaded_image::LoadedImage;
+use crate::proto::shell::ShellParameters;
+use crate::table::boot::BootServices;
+use crate::{Handle, Status};
+use core::fmt;
+use core::str::FromStr;
+
+/// Errors that can occur when parsing command-line arguments
+///
+/// The `Display` implementation gives a message which can be shown to the
+/// user as is.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ArgsError {
+    /// The image has neither shell parameters nor load options.
+    Unavailable(Status),
+    /// An argument contains a character which is not valid UCS-2.
+    InvalidCharacter {
+        /// Index of the argument, the path of the application being 0
+        index: usize,
+    },
+    /// A quote of the command line is not closed.
+    UnterminatedQuote,
+    /// An option which takes a value is the last argument.
+    MissingValue(String),
+    /// The value of an option could not be parsed.
+    InvalidValue {
+        /// Name of the option
+        option: String,
+        /// The value given on the command line
+        value: String,
+        /// Why the value is not valid
+        reason: String,
+    },
+    /// An argument looks like an option, but no option of this name exists.
+    UnknownOption(String),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgsError::Unavailable(status) => {
+                write!(f, "the command line is not available ({:?})", status)
+            }
+            ArgsError::InvalidCharacter { index } => {
+                write!(f, "argument {} contains an invalid character", index)
+            }
+            ArgsError::UnterminatedQuote => write!(f, "a quote of the command line is not closed"),
+            ArgsError::MissingValue(option) => write!(f, "option `{}` expects a value", option),
+            ArgsError::InvalidValue {
+                option,
+                value,
+                reason,
+            } => write!(
+                f,
+                "invalid value `{}` for option `{}`: {}",
+                value, option, reason
+            ),
+            ArgsError::UnknownOption(option) => write!(f, "unknown option `{}`", option),
+        }
+    }
+}
+
+/// Command-line arguments of an application
+///
+/// Options are looked up by name, and removed from the arguments as they are
+/// found, so that the arguments which remain at the end are the positional
+/// ones:
+///
+/// ```no_run
+/// # use uefi::prelude::*;
+/// # use uefi::shell::{Args, ArgsError};
+/// # use alloc::{string::String, vec::Vec};
+/// # extern crate alloc;
+/// # fn f(bt: &BootServices, image: Handle) -> Result<(), ArgsError> {
+/// // hexdump.efi -v --width=32 file.bin
+/// let mut args = Args::from_image(bt, image)?;
+/// let verbose = args.flag(&["-v", "--verbose"]);
+/// let width: u32 = args.parse_value(&["-w", "--width"])?.unwrap_or(16);
+/// let files: Vec<String> = args.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// An option which takes a value accepts it either in the same argument, as
+/// in `--width=32` or `width=32`, or in the next one, as in `--width 32`.
+/// Arguments which follow a `--` argument are always positional.
+#[derive(Debug, Clone)]
+pub struct Args {
+    program: String,
+    args: Vec<String>,
+    used: Vec<bool>,
+    /// Index of the `--` argument, or number of arguments
+    end: usize,
+}
+
+impl Args {
+    /// Build the arguments from the path of the program and the list of its
+    /// arguments
+    pub fn new(program: String, args: Vec<String>) -> Self {
+        let end = args
+            .iter()
+            .position(|arg| arg == "--")
+            .unwrap_or(args.len());
+        let used = args.iter().map(|_| false).collect();
+        Args {
+            program,
+            args,
+            used,
+            end,
+        }
+    }
+
+    /// Get the arguments of an image, from the shell parameters if the image
+    /// was started by the shell, or else from its load options.
+    pub fn from_image(bt: &BootServices, image: Handle) -> Result<Self, ArgsError> {
+        if let Ok(params) = bt.handle_protocol::<ShellParameters>(image) {
+            let params = unsafe { &*params.log().get() };
+            return Self::from_shell_parameters(params);
+        }
+        match bt.handle_protocol::<LoadedImage>(image) {
+            Ok(loaded_image) => {
+                let loaded_image = unsafe { &*loaded_image.log().get() };
+                Self::from_load_options(loaded_image)
+            }
+            Err(err) => Err(ArgsError::Unavailable(err.status())),
+        }
+    }
+
+    /// Get the arguments split by the shell.
+    pub fn from_shell_parameters(params: &ShellParameters) -> Result<Self, ArgsError> {
+        let mut args = params
+            .args()
+            .enumerate()
+            .map(|(index, arg)| decode(arg.to_u16_slice(), index));
+        let program = args.next().transpose()?.unwrap_or_default();
+        let args = args.collect::<Result<_, _>>()?;
+        Ok(Self::new(program, args))
+    }
+
+    /// Get the arguments from the load options of an image.
+    ///
+    /// The load options are expected to be a UCS-2 command line whose first
+    /// word is the path of the application, as passed by the shell.
+    pub fn from_load_options(loaded_image: &LoadedImage) -> Result<Self, ArgsError> {
+        let bytes = loaded_image.load_options_as_bytes().unwrap_or(&[]);
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .take_while(|&unit| unit != 0)
+            .collect();
+        Self::from_command_line(&decode(&units, 0)?)
+    }
+
+    /// Split a command line into arguments, like the shell does.
+    ///
+    /// Arguments are separated by spaces or tabs. Double quotes group words
+    /// into one argument, and a `^` makes the next character lose its special
+    /// meaning. The first argument is the path of the application.
+    pub fn from_command_line(line: &str) -> Result<Self, ArgsError> {
+        let mut args = Vec::new();
+        let mut arg = None;
+        let mut quoted = false;
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '^' => {
+                    let escaped = chars.next().unwrap_or('^');
+                    arg.get_or_insert_with(String::new).push(escaped);
+                }
+                '"' => {
+                    quoted = !quoted;
+                    arg.get_or_insert_with(String::new);
+                }
+                ' ' | '\t' if !quoted => args.extend(arg.take()),
+                c => arg.get_or_insert_with(String::new).push(c),
+            }
+        }
+        if quoted {
+            return Err(ArgsError::UnterminatedQuote);
+        }
+        args.extend(arg);
+
+        let mut args = args.into_iter();
+        let program = args.next().unwrap_or_default();
+        Ok(Self::new(program, args.collect()))
+    }
+
+    /// Path of the application, as typed on the command line
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Whether one of the `names` of a flag was given, such as `-v` or
+    /// `--verbose`
+    pub fn flag(&mut self, names: &[&str]) -> bool {
+        let mut found = false;
+        for index in 0..self.end {
+            if !self.used[index] && names.contains(&self.args[index].as_str()) {
+                self.used[index] = true;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Get the value of an option, if it was given.
+    ///
+    /// If the option is repeated, the last value is returned.
+    pub fn value(&mut self, names: &[&str]) -> Result<Option<String>, ArgsError> {
+        let mut value = None;
+        let mut index = 0;
+        while index < self.end {
+            if self.used[index] {
+                index += 1;
+                continue;
+            }
+            let arg = &self.args[index];
+            if let Some(name) = names.iter().find(|&&name| name == arg) {
+                if index + 1 >= self.end || self.used[index + 1] {
+                    return Err(ArgsError::MissingValue(name.to_string()));
+                }
+                value = Some(self.args[index + 1].clone());
+                self.used[index] = true;
+                self.used[index + 1] = true;
+                index += 2;
+                continue;
+            }
+            let inline = names.iter().find_map(|name| {
+                arg.strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix('='))
+            });
+            if let Some(inline) = inline {
+                value = Some(inline.to_string());
+                self.used[index] = true;
+            }
+            index += 1;
+        }
+        Ok(value)
+    }
+
+    /// Get the value of an option, converted with `FromStr`.
+    pub fn parse_value<T>(&mut self, names: &[&str]) -> Result<Option<T>, ArgsError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.value(names)? {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|err: T::Err| ArgsError::InvalidValue {
+                    option: names.first().copied().unwrap_or_default().to_string(),
+                    value,
+                    reason: err.to_string(),
+                }),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the positional arguments, which were not consumed as options.
+    ///
+    /// Fails if one of them starts with a `-`, and so was probably meant to
+    /// be an option, unless it follows the `--` argument.
+    pub fn finish(self) -> Result<Vec<String>, ArgsError> {
+        let end = self.end;
+        let mut positional = Vec::new();
+        for (index, (arg, used)) in self.args.into_iter().zip(self.used).enumerate() {
+            if used || index == end {
+                continue;
+            }
+            if index < end && arg.len() > 1 && arg.starts_with('-') {
+                return Err(ArgsError::UnknownOption(arg));
+            }
+            positional.push(arg);
+        }
+        Ok(positional)
+    }
+}
+
+/// Convert a UCS-2 argument to a Rust string
+fn decode(units: &[u16], index: usize) -> Result<String, ArgsError> {
+    units
+        .iter()
+        .map(|&unit| char::from_u32(unit.into()).ok_or(ArgsError::InvalidCharacter { index }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_api::vec;
+
+    #[test]
+    fn split_command_line() {
+        let args = Args::from_command_line(r#"fs0:\app.efi  -v "two words" a^"b """#).unwrap();
+        assert_eq!(args.program(), r"fs0:\app.efi");
+        assert_eq!(
+            args.finish().unwrap_err(),
+            ArgsError::UnknownOption("-v".into())
+        );
+
+        let args = Args::from_command_line(r#"app "two words" a^"b """#).unwrap();
+        assert_eq!(args.finish().unwrap(), ["two words", "a\"b", ""]);
+
+        assert_eq!(
+            Args::from_command_line(r#"app "open"#).unwrap_err(),
+            ArgsError::UnterminatedQuote
+        );
+    }
+
+    #[test]
+    fn options() {
+        let args = ["-v", "--width", "32", "mode=fast", "file", "--", "-x"];
+        let mut args = Args::new("app".into(), args.iter().map(|&a| a.into()).collect());
+        assert!(args.flag(&["-v", "--verbose"]));
+        assert!(!args.flag(&["-q"]));
+        assert_eq!(args.parse_value::<u32>(&["--width"]), Ok(Some(32)));
+        assert_eq!(args.value(&["mode"]), Ok(Some("fast".into())));
+        assert_eq!(args.value(&["--out"]), Ok(None));
+        // Options are not looked up after `--`
+        assert!(!args.flag(&["-x"]));
+        assert_eq!(args.finish().unwrap(), ["file", "-x"]);
+
+        let mut args = Args::new("app".into(), vec!["--width=abc".into(), "-n".into()]);
+        let err = args.parse_value::<u32>(&["--width"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value `abc` for option `--width`: invalid digit found in string"
+        );
+        assert_eq!(
+            args.value(&["-n"]),
+            Err(ArgsError::MissingValue("-n".into()))
+        );
+    }
+}
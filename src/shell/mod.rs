@@ -0,0 +1,12 @@
+//! Helpers for applications started from the EFI Shell.
+//!
+//! The protocols installed by the shell are in [`proto::shell`]. This module
+//! builds on them, and on the load options of the image when the shell
+//! protocols are missing, to provide the facilities that command-line tools
+//! need.
+//!
+//! [`proto::shell`]: crate::proto::shell
+
+mod args;
+
+pub use self::args::{Args, ArgsError};
@@ -0,0 +1,131 @@
+//! Measurement of the stack usage.
+//!
+//! UEFI applications run on the stack of the firmware, which is small: OVMF
+//! gives them 128 KiB, and overflows corrupt the memory below it silently.
+//! The stack below the current frame is painted with a pattern at the entry
+//! point, and the depth at which the pattern was overwritten tells how much
+//! of it was used since:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # fn f() {
+//! // At the start of the entry point
+//! unsafe { uefi::stack::paint(64 * 1024) };
+//! // ...
+//! if let Some(usage) = uefi::stack::usage() {
+//!     log::info!("Used {} of {} bytes of stack", usage.used, usage.size);
+//! }
+//! # }
+//! ```
+//!
+//! Interrupt handlers of the firmware run on the same stack, so their usage
+//! is counted too.
+
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Pattern painted on the unused stack
+const PATTERN: u64 = 0x5354_4143_4b5f_5354;
+/// Bytes below the frame of `paint` which are left untouched, since they
+/// may be used by the painting itself
+const MARGIN: usize = 256;
+
+/// Highest address of the painted region
+static TOP: AtomicUsize = AtomicUsize::new(0);
+/// Lowest address of the painted region
+static BOTTOM: AtomicUsize = AtomicUsize::new(0);
+
+/// Stack usage since the stack was painted
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StackUsage {
+    /// Largest number of bytes which were used below the painted frame
+    pub used: usize,
+    /// Number of bytes which were painted
+    pub size: usize,
+}
+
+impl StackUsage {
+    /// Whether the stack was used beyond the painted region, which means
+    /// that the measurement is incomplete, and that the stack may have
+    /// overflowed.
+    pub fn exhausted(&self) -> bool {
+        self.used >= self.size
+    }
+}
+
+/// Paint `size` bytes of stack below the caller, to measure the usage with
+/// `usage`.
+///
+/// A previous measurement is discarded.
+///
+/// # Safety
+///
+/// The stack must extend `size` bytes below the caller, and the memory below
+/// the current stack pointer must not be in use, which holds on UEFI and for
+/// the threads of hosted programs.
+#[inline(never)]
+pub unsafe fn paint(size: usize) {
+    let frame = 0u64;
+    let top = (&frame as *const u64 as usize - MARGIN) & !(mem::align_of::<u64>() - 1);
+    let bottom = top - size;
+    let mut word = bottom as *mut u64;
+    while (word as usize) < top {
+        ptr::write_volatile(word, PATTERN);
+        word = word.add(1);
+    }
+    BOTTOM.store(bottom, Ordering::Relaxed);
+    TOP.store(top, Ordering::Relaxed);
+}
+
+/// The stack usage since `paint` was called, or `None` if it was not.
+pub fn usage() -> Option<StackUsage> {
+    let top = TOP.load(Ordering::Relaxed);
+    let bottom = BOTTOM.load(Ordering::Relaxed);
+    if top == 0 {
+        return None;
+    }
+    // Find the lowest word of the region which was overwritten
+    let mut word = bottom as *const u64;
+    while (word as usize) < top && unsafe { ptr::read_volatile(word) } == PATTERN {
+        word = unsafe { word.add(1) };
+    }
+    Some(StackUsage {
+        used: top - word as usize,
+        size: top - bottom,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Use 512 bytes of stack per level, and return the sum of the depths
+    #[inline(never)]
+    fn recurse(depth: usize) -> u8 {
+        let mut buffer = [0u8; 512];
+        // Volatile accesses keep the buffer from being optimized out
+        for byte in buffer.iter_mut() {
+            unsafe { ptr::write_volatile(byte, depth as u8) };
+        }
+        let value = unsafe { ptr::read_volatile(&buffer[511]) };
+        if depth == 0 {
+            value
+        } else {
+            recurse(depth - 1).wrapping_add(value)
+        }
+    }
+
+    #[test]
+    fn usage() {
+        unsafe { paint(64 * 1024) };
+        let before = super::usage().unwrap();
+        assert_eq!(before.size, 64 * 1024);
+        assert!(!before.exhausted());
+
+        assert_eq!(recurse(16), 136);
+        let after = super::usage().unwrap();
+        assert!(after.used >= before.used + 16 * 512);
+        assert!(!after.exhausted());
+    }
+}
@@ -0,0 +1,373 @@
+//! Synchronization with event callbacks.
+//!
+//! Boot services run on a single processor, but the notification functions
+//! of events interrupt the code which runs at a lower task priority level.
+//! Data shared with them is protected by raising the TPL while it is used,
+//! which masks the callbacks of the same or lower TPL:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::table::boot::Tpl;
+//! # use uefi::sync::TplMutex;
+//! # use uefi::Event;
+//! static QUEUE: TplMutex<[u8; 16]> = TplMutex::new(Tpl::NOTIFY, [0; 16]);
+//!
+//! fn callback(_event: Event) {
+//!     let bt = uefi::table::boot::system_table().unwrap().boot_services();
+//!     QUEUE.lock(bt)[0] += 1;
+//! }
+//!
+//! # fn f(bt: &BootServices) {
+//! let mut queue = QUEUE.lock(bt);
+//! // `callback` cannot run until `queue` is dropped
+//! queue[0] = 0;
+//! # }
+//! ```
+//!
+//! `OnceCell` and `Lazy` raise the TPL in the same way while they initialize
+//! statics, such as protocols found once and used by callbacks.
+
+use crate::table::boot::{BootServices, Tpl, TplGuard};
+use core::cell::UnsafeCell;
+use core::fmt::{self, Debug, Formatter};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// Mutual exclusion with event callbacks, by raising the TPL
+///
+/// The TPL of the mutex must be at least the TPL of all the callbacks which
+/// lock it, usually `Tpl::NOTIFY`, so that they cannot interrupt the code
+/// which holds the lock. `Tpl::HIGH_LEVEL` also masks timer interrupts, and
+/// should only protect very short operations.
+///
+/// Locking the mutex again while it is held panics, instead of deadlocking.
+pub struct TplMutex<T: ?Sized> {
+    tpl: Tpl,
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+// The value is only accessed at the TPL of the mutex, which no other code
+// interrupts
+unsafe impl<T: ?Sized + Send> Sync for TplMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for TplMutex<T> {}
+
+impl<T> TplMutex<T> {
+    /// Create a mutex which protects `value` at `tpl`
+    pub const fn new(tpl: Tpl, value: T) -> Self {
+        TplMutex {
+            tpl,
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// The protected value
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> TplMutex<T> {
+    /// The TPL at which the value is used
+    pub fn tpl(&self) -> Tpl {
+        self.tpl
+    }
+
+    /// Raise the TPL to the one of the mutex, and give access to the value
+    /// until the guard is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is already locked, which means that it is locked
+    /// twice by the same code, or by a callback of a TPL above the one of
+    /// the mutex. Also panics if the current TPL is above the one of the
+    /// mutex, since the TPL can not be raised to a lower level.
+    pub fn lock<'mutex, 'boot>(
+        &'mutex self,
+        bt: &'boot BootServices,
+    ) -> TplMutexGuard<'mutex, 'boot, T> {
+        self.acquire(bt).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `lock`, but return `None` if the mutex is already locked, or if
+    /// the current TPL is above the one of the mutex.
+    pub fn try_lock<'mutex, 'boot>(
+        &'mutex self,
+        bt: &'boot BootServices,
+    ) -> Option<TplMutexGuard<'mutex, 'boot, T>> {
+        self.acquire(bt).ok()
+    }
+
+    /// Lock the mutex, or describe why it can not be
+    fn acquire<'mutex, 'boot>(
+        &'mutex self,
+        bt: &'boot BootServices,
+    ) -> Result<TplMutexGuard<'mutex, 'boot, T>, &'static str> {
+        // The effect of raising the TPL to a lower level is undefined
+        if bt.current_tpl().0 > self.tpl.0 {
+            return Err("TplMutex is locked above its TPL");
+        }
+        // Raising the TPL is sound, since it is only restored by the guard
+        let tpl = unsafe { bt.raise_tpl(self.tpl) };
+        if self.locked.swap(true, Ordering::Acquire) {
+            return Err("TplMutex is locked while it is already held");
+        }
+        Ok(TplMutexGuard {
+            mutex: self,
+            _tpl: tpl,
+        })
+    }
+
+    /// Access the value without locking, since the mutex is borrowed
+    /// mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: ?Sized> Debug for TplMutex<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TplMutex")
+            .field("tpl", &self.tpl)
+            .field("locked", &self.locked.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Access to the value of a `TplMutex`
+///
+/// The former TPL is restored when the guard is dropped.
+pub struct TplMutexGuard<'mutex, 'boot, T: ?Sized> {
+    mutex: &'mutex TplMutex<T>,
+    // Dropped after the mutex is unlocked
+    _tpl: TplGuard<'boot>,
+}
+
+impl<T: ?Sized> Deref for TplMutexGuard<'_, '_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for TplMutexGuard<'_, '_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for TplMutexGuard<'_, '_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+    }
+}
+
+/// A value shared with event callbacks, which is copied in and out at a
+/// raised TPL
+///
+/// Unlike with a `TplMutex`, the TPL is only raised for the duration of
+/// each access.
+pub struct TplCell<T: Copy> {
+    mutex: TplMutex<T>,
+}
+
+impl<T: Copy> TplCell<T> {
+    /// Create a cell which protects `value` at `tpl`
+    pub const fn new(tpl: Tpl, value: T) -> Self {
+        TplCell {
+            mutex: TplMutex::new(tpl, value),
+        }
+    }
+
+    /// The value
+    pub fn get(&self, bt: &BootServices) -> T {
+        *self.mutex.lock(bt)
+    }
+
+    /// Set the value
+    pub fn set(&self, bt: &BootServices, value: T) {
+        *self.mutex.lock(bt) = value;
+    }
+
+    /// Set the value, and return the previous one
+    pub fn replace(&self, bt: &BootServices, value: T) -> T {
+        core::mem::replace(&mut *self.mutex.lock(bt), value)
+    }
+
+    /// Set the value to `f` of the previous one, and return the new value.
+    ///
+    /// `f` runs at the TPL of the cell, so it must be short.
+    pub fn update(&self, bt: &BootServices, f: impl FnOnce(T) -> T) -> T {
+        let mut value = self.mutex.lock(bt);
+        *value = f(*value);
+        *value
+    }
+}
+
+impl<T: Copy> Debug for TplCell<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TplCell")
+            .field("tpl", &self.mutex.tpl)
+            .finish()
+    }
+}
+
+/// The value of a `OnceCell` is not set yet
+const UNINIT: u8 = 0;
+/// The value of a `OnceCell` is being initialized
+const RUNNING: u8 = 1;
+/// The value of a `OnceCell` is set
+const READY: u8 = 2;
+
+/// A value which is set once, for example a protocol or a logger stored in
+/// a static
+///
+/// The initialization runs at the TPL of the cell, so that the callbacks of
+/// the same or lower TPL cannot run while the value is half set. Once it is
+/// set, the value is read without raising the TPL.
+pub struct OnceCell<T> {
+    tpl: Tpl,
+    state: AtomicU8,
+    value: UnsafeCell<Option<T>>,
+}
+
+// The value is only written once, before `state` becomes `READY`, and only
+// read after
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    /// Create a cell without value, which is initialized at `tpl`
+    pub const fn new(tpl: Tpl) -> Self {
+        OnceCell {
+            tpl,
+            state: AtomicU8::new(UNINIT),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    /// The value, if it is set
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == READY {
+            unsafe { (*self.value.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+
+    /// Set the value, or give `value` back if it is already set.
+    pub fn set(&self, bt: &BootServices, value: T) -> core::result::Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(bt, || value.take().unwrap());
+        match value {
+            Some(value) => Err(value),
+            None => Ok(()),
+        }
+    }
+
+    /// The value, which is set to the result of `init` if it is not set yet.
+    ///
+    /// `init` runs at the TPL of the cell, so it may only use the boot
+    /// services which are allowed at that TPL.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `init` itself uses the cell, or if a callback of a TPL
+    /// above the one of the cell uses it during the initialization. Also
+    /// panics if the value is not set yet and the current TPL is above the
+    /// one of the cell.
+    pub fn get_or_init(&self, bt: &BootServices, init: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        // The effect of raising the TPL to a lower level is undefined
+        assert!(
+            bt.current_tpl().0 <= self.tpl.0,
+            "OnceCell is initialized above its TPL"
+        );
+        let _tpl = unsafe { bt.raise_tpl(self.tpl) };
+        match self
+            .state
+            .compare_exchange(UNINIT, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                unsafe { *self.value.get() = Some(init()) };
+                self.state.store(READY, Ordering::Release);
+            }
+            Err(RUNNING) => panic!("OnceCell is used during its initialization"),
+            // Set by a callback between the check and the raise of the TPL
+            Err(_) => {}
+        }
+        self.get().unwrap()
+    }
+
+    /// The value, if it is set
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Debug> Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceCell").field(&self.get()).finish()
+    }
+}
+
+/// A value which is computed the first time it is used
+///
+/// ```no_run
+/// # use uefi::prelude::*;
+/// # use uefi::table::boot::Tpl;
+/// # use uefi::sync::Lazy;
+/// fn count_handles() -> usize {
+///     let bt = uefi::table::boot::system_table().unwrap().boot_services();
+///     // ...
+/// #   0
+/// }
+///
+/// static HANDLES: Lazy<usize> = Lazy::new(Tpl::NOTIFY, count_handles);
+///
+/// # fn f(bt: &BootServices) {
+/// log::info!("{} handles", HANDLES.force(bt));
+/// # }
+/// ```
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: UnsafeCell<Option<F>>,
+}
+
+// `init` is only taken by the initialization of `cell`, which happens once
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Create a value which is computed by `init` at `tpl`
+    pub const fn new(tpl: Tpl, init: F) -> Self {
+        Lazy {
+            cell: OnceCell::new(tpl),
+            init: UnsafeCell::new(Some(init)),
+        }
+    }
+
+    /// The value, which is computed if it is not yet.
+    ///
+    /// See `OnceCell::get_or_init`.
+    pub fn force(&self, bt: &BootServices) -> &T {
+        self.cell.get_or_init(bt, || {
+            let init = unsafe { (*self.init.get()).take() };
+            init.expect("Lazy is used after its initialization panicked")()
+        })
+    }
+
+    /// The value, if it was computed
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+}
+
+impl<T: Debug, F> Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Lazy").field(&self.cell.get()).finish()
+    }
+}
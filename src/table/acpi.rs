@@ -0,0 +1,177 @@
+//! Lookup of the ACPI tables published by the firmware.
+//!
+//! The configuration table points to the ACPI RSDP, which in turn points to
+//! the root table (XSDT, or RSDT before ACPI 2.0) listing the addresses of all
+//! the other system description tables. This module only walks this
+//! structure, the tables themselves are left to the code that uses them.
+
+use super::cfg::{ConfigTableEntry, ACPI2_GUID, ACPI_GUID};
+use crate::crc;
+use core::slice;
+
+/// Size of the header common to all the system description tables
+pub const SDT_HEADER_SIZE: usize = 36;
+
+/// Offset of the RSDT address in the RSDP
+const RSDP_RSDT_OFFSET: usize = 16;
+/// Offset of the XSDT address in the RSDP, since ACPI 2.0
+const RSDP_XSDT_OFFSET: usize = 24;
+
+/// Errors that can occur when accessing a system description table
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SdtError {
+    /// The table is smaller than its header.
+    Truncated,
+}
+
+/// A system description table, such as the FADT or the MADT
+#[derive(Debug, Copy, Clone)]
+pub struct Sdt<'table> {
+    bytes: &'table [u8],
+}
+
+impl<'table> Sdt<'table> {
+    /// Access the table at a physical address.
+    ///
+    /// # Safety
+    ///
+    /// The address must point to a table, which is identity-mapped and stays
+    /// valid for `'table`. The length in its header is checked against the
+    /// size of the header, but must otherwise be right.
+    pub unsafe fn from_address(address: u64) -> core::result::Result<Self, SdtError> {
+        let base = address as usize as *const u8;
+        let length = (base.add(4) as *const u32).read_unaligned();
+        Self::from_bytes(slice::from_raw_parts(base, length as usize))
+    }
+
+    /// Wrap the bytes of a table, which must be at least as large as the
+    /// header.
+    pub fn from_bytes(bytes: &'table [u8]) -> core::result::Result<Self, SdtError> {
+        if bytes.len() < SDT_HEADER_SIZE {
+            return Err(SdtError::Truncated);
+        }
+        Ok(Sdt { bytes })
+    }
+
+    /// Four-character signature of the table, like `b"APIC"`
+    pub fn signature(&self) -> [u8; 4] {
+        [self.bytes[0], self.bytes[1], self.bytes[2], self.bytes[3]]
+    }
+
+    /// Revision of the structure of the table
+    pub fn revision(&self) -> u8 {
+        self.bytes[8]
+    }
+
+    /// Identifier of the vendor of the table
+    pub fn oem_id(&self) -> &'table [u8] {
+        &self.bytes[10..16]
+    }
+
+    /// Whether the bytes of the table sum to zero, as they must
+    pub fn checksum_is_valid(&self) -> bool {
+        crc::sum8(self.bytes) == 0
+    }
+
+    /// All the bytes of the table, header included
+    pub fn bytes(&self) -> &'table [u8] {
+        self.bytes
+    }
+
+    /// The contents of the table, which follow the header
+    pub fn data(&self) -> &'table [u8] {
+        &self.bytes[SDT_HEADER_SIZE..]
+    }
+}
+
+/// Offset of the checksum in the header of the system description tables
+const SDT_CHECKSUM_OFFSET: usize = 9;
+
+/// Update the checksum of the table in `bytes`, after it has been modified,
+/// so that its bytes sum to zero again.
+///
+/// # Panics
+///
+/// Panics if `bytes` is smaller than the header.
+pub fn update_checksum(bytes: &mut [u8]) {
+    assert!(bytes.len() >= SDT_HEADER_SIZE, "truncated ACPI table");
+    bytes[SDT_CHECKSUM_OFFSET] = 0;
+    bytes[SDT_CHECKSUM_OFFSET] = 0u8.wrapping_sub(crc::sum8(bytes));
+}
+
+/// Iterate over the system description tables listed by the root table.
+///
+/// The XSDT of the ACPI 2.0 RSDP is preferred over the RSDT. Nothing is
+/// returned if the configuration table contains no RSDP, and tables which are
+/// smaller than their header are skipped.
+///
+/// # Safety
+///
+/// The RSDP and the tables must be valid and identity-mapped, which is the
+/// case until boot services are exited.
+pub unsafe fn tables<'table>(
+    config_table: &[ConfigTableEntry],
+) -> impl Iterator<Item = Sdt<'table>> {
+    let rsdp = |guid| {
+        config_table
+            .iter()
+            .find(|entry| entry.guid == guid)
+            .map(|entry| entry.address as *const u8)
+    };
+    let root = if let Some(rsdp) = rsdp(ACPI2_GUID) {
+        let xsdt = (rsdp.add(RSDP_XSDT_OFFSET) as *const u64).read_unaligned();
+        Sdt::from_address(xsdt).ok().map(|root| (root, 8))
+    } else if let Some(rsdp) = rsdp(ACPI_GUID) {
+        let rsdt = (rsdp.add(RSDP_RSDT_OFFSET) as *const u32).read_unaligned();
+        Sdt::from_address(u64::from(rsdt))
+            .ok()
+            .map(|root| (root, 4))
+    } else {
+        None
+    };
+
+    root.into_iter().flat_map(|(root, entry_size)| {
+        root.data()
+            .chunks_exact(entry_size)
+            .filter_map(move |entry| {
+                let mut address = [0; 8];
+                address[..entry_size].copy_from_slice(entry);
+                Sdt::from_address(u64::from_le_bytes(address)).ok()
+            })
+    })
+}
+
+/// Find the first system description table with the given signature.
+///
+/// # Safety
+///
+/// See `tables`.
+pub unsafe fn find_table<'table>(
+    config_table: &[ConfigTableEntry],
+    signature: &[u8; 4],
+) -> Option<Sdt<'table>> {
+    tables(config_table).find(|table| table.signature() == *signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        let mut table = [0; SDT_HEADER_SIZE + 4];
+        table[..4].copy_from_slice(b"SSDT");
+        table[4] = table.len() as u8;
+        table[SDT_HEADER_SIZE..].copy_from_slice(&[0x10, 0x20, 0x30, 0x40]);
+        assert!(!Sdt::from_bytes(&table).unwrap().checksum_is_valid());
+        update_checksum(&mut table);
+        assert!(Sdt::from_bytes(&table).unwrap().checksum_is_valid());
+        table[SDT_HEADER_SIZE] = 0x11;
+        update_checksum(&mut table);
+        assert!(Sdt::from_bytes(&table).unwrap().checksum_is_valid());
+        assert_eq!(
+            Sdt::from_bytes(&table[..SDT_HEADER_SIZE - 1]).err(),
+            Some(SdtError::Truncated)
+        );
+    }
+}
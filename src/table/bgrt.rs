@@ -0,0 +1,380 @@
+//! Boot Graphics Resource Table (BGRT).
+//!
+//! The BGRT is an ACPI table through which the firmware tells the OS which
+//! logo it drew during the boot, and where. OS loaders use it to keep the
+//! vendor logo on screen while they run, without flickering:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::proto::console::gop::GraphicsOutput;
+//! # use uefi::table::bgrt::BootLogo;
+//! # fn f(st: &SystemTable<Boot>, gop: &mut GraphicsOutput) {
+//! let logo = unsafe { BootLogo::find(st.config_table()) };
+//! // Switch to another mode of the GOP, which clears the screen...
+//! if let Ok(logo) = logo {
+//!     logo.redraw(gop, Some((1024, 768))).expect_success("Failed to draw the logo");
+//! }
+//! # }
+//! ```
+//!
+//! The table only references the image, which must be read before the boot
+//! services are exited: it is stored in boot services memory.
+
+use super::acpi::{self, Sdt, SDT_HEADER_SIZE};
+use super::cfg::ConfigTableEntry;
+use crate::graphics::bmp::{self, Bmp, BmpError};
+use crate::proto::console::gop::{BltPixel, GraphicsOutput};
+use crate::Result;
+use core::slice;
+
+/// Signature of the BGRT
+pub const BGRT_SIGNATURE: [u8; 4] = *b"BGRT";
+
+/// Size of a BGRT, header included
+const BGRT_SIZE: usize = SDT_HEADER_SIZE + 20;
+/// Size of the BITMAPFILEHEADER, which holds the size of the BMP file
+const BMP_FILE_HEADER_SIZE: usize = 14;
+
+/// Errors that can occur when looking for the boot logo
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BgrtError {
+    /// The firmware did not publish a BGRT.
+    NotFound,
+    /// The BGRT is truncated, has an invalid checksum, or an unknown version.
+    InvalidTable,
+    /// The image is not a BMP image, the only type defined by ACPI.
+    UnsupportedImage,
+    /// The BMP image could not be parsed.
+    Image(BmpError),
+}
+
+newtype_enum! {
+    /// Rotation of the boot logo, clockwise from the native orientation of
+    /// the screen
+    pub enum Orientation: u8 => {
+        /// Not rotated
+        DEGREES_0   = 0,
+        /// Rotated by 90 degrees
+        DEGREES_90  = 1,
+        /// Rotated by 180 degrees
+        DEGREES_180 = 2,
+        /// Rotated by 270 degrees
+        DEGREES_270 = 3,
+    }
+}
+
+/// The Boot Graphics Resource Table
+#[derive(Debug, Copy, Clone)]
+pub struct Bgrt<'table> {
+    table: Sdt<'table>,
+}
+
+impl<'table> Bgrt<'table> {
+    /// Find the BGRT among the ACPI tables.
+    ///
+    /// # Safety
+    ///
+    /// See `acpi::tables`.
+    pub unsafe fn find(config_table: &[ConfigTableEntry]) -> Option<Self> {
+        acpi::find_table(config_table, &BGRT_SIGNATURE).map(|table| Bgrt { table })
+    }
+
+    /// Wrap an ACPI table, if it is a BGRT
+    pub fn from_sdt(table: Sdt<'table>) -> Option<Self> {
+        if table.signature() == BGRT_SIGNATURE {
+            Some(Bgrt { table })
+        } else {
+            None
+        }
+    }
+
+    /// Check that the table is complete, that its checksum is valid, and that
+    /// its version is the one defined by ACPI.
+    pub fn validate(&self) -> core::result::Result<(), BgrtError> {
+        let bytes = self.table.bytes();
+        if bytes.len() < BGRT_SIZE || !self.table.checksum_is_valid() || self.version() != 1 {
+            return Err(BgrtError::InvalidTable);
+        }
+        Ok(())
+    }
+
+    fn field(&self, offset: usize, size: usize) -> u64 {
+        let mut value = [0; 8];
+        if let Some(bytes) = self.table.data().get(offset..offset + size) {
+            value[..size].copy_from_slice(bytes);
+        }
+        u64::from_le_bytes(value)
+    }
+
+    /// Version of the structure of the table, which must be 1
+    pub fn version(&self) -> u16 {
+        self.field(0, 2) as u16
+    }
+
+    /// Whether the image is currently displayed.
+    ///
+    /// The firmware clears this bit when something else is drawn over the
+    /// logo, such as a setup menu.
+    pub fn is_displayed(&self) -> bool {
+        self.field(2, 1) & 1 != 0
+    }
+
+    /// Rotation of the image on the screen
+    pub fn orientation(&self) -> Orientation {
+        Orientation(((self.field(2, 1) >> 1) & 3) as u8)
+    }
+
+    /// Type of the image, 0 standing for a BMP image
+    pub fn image_type(&self) -> u8 {
+        self.field(3, 1) as u8
+    }
+
+    /// Physical address of the image
+    pub fn image_address(&self) -> u64 {
+        self.field(4, 8)
+    }
+
+    /// Position of the top-left corner of the image on the screen, in the
+    /// graphics mode used by the firmware
+    pub fn image_offset(&self) -> (usize, usize) {
+        (self.field(12, 4) as usize, self.field(16, 4) as usize)
+    }
+
+    /// Parse the image referenced by the table.
+    ///
+    /// # Safety
+    ///
+    /// The image must be identity-mapped, and stay valid for `'table`: it is
+    /// in boot services memory, and is freed when they are exited.
+    pub unsafe fn image(&self) -> core::result::Result<Bmp<'table>, BgrtError> {
+        self.validate()?;
+        if self.image_type() != 0 || self.image_address() == 0 {
+            return Err(BgrtError::UnsupportedImage);
+        }
+        let base = self.image_address() as usize as *const u8;
+        let header = slice::from_raw_parts(base, BMP_FILE_HEADER_SIZE);
+        if &header[..2] != b"BM" {
+            return Err(BgrtError::Image(BmpError::InvalidHeader));
+        }
+        let size = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+        let data = slice::from_raw_parts(base, size.max(BMP_FILE_HEADER_SIZE));
+        Bmp::parse(data).map_err(BgrtError::Image)
+    }
+}
+
+/// The boot logo drawn by the firmware, as described by the BGRT
+#[derive(Debug, Copy, Clone)]
+pub struct BootLogo<'table> {
+    /// The image of the logo
+    pub image: Bmp<'table>,
+    /// Position of the top-left corner of the logo, in the graphics mode
+    /// used by the firmware
+    pub offset: (usize, usize),
+    /// Rotation of the logo on the screen, which `redraw` applies to the
+    /// image
+    pub orientation: Orientation,
+    /// Whether the logo is still displayed
+    pub displayed: bool,
+}
+
+impl<'table> BootLogo<'table> {
+    /// Find and validate the BGRT, and parse the logo it references.
+    ///
+    /// # Safety
+    ///
+    /// See `acpi::tables` and `Bgrt::image`.
+    pub unsafe fn find(config_table: &[ConfigTableEntry]) -> core::result::Result<Self, BgrtError> {
+        let bgrt = Bgrt::find(config_table).ok_or(BgrtError::NotFound)?;
+        Ok(BootLogo {
+            image: bgrt.image()?,
+            offset: bgrt.image_offset(),
+            orientation: bgrt.orientation(),
+            displayed: bgrt.is_displayed(),
+        })
+    }
+
+    /// Size of the logo on the screen, once rotated
+    pub fn size(&self) -> (usize, usize) {
+        let (width, height) = (self.image.width(), self.image.height());
+        match self.orientation {
+            Orientation::DEGREES_90 | Orientation::DEGREES_270 => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// Color of the pixel at `(x, y)` of the logo on the screen, once rotated
+    ///
+    /// # Panics
+    ///
+    /// Panics if the coordinates are outside of `size`.
+    pub fn pixel(&self, x: usize, y: usize) -> BltPixel {
+        let (width, height) = (self.image.width(), self.image.height());
+        let (x, y) = match self.orientation {
+            // The left column of the image becomes the top row
+            Orientation::DEGREES_90 => (y, height.wrapping_sub(x + 1)),
+            Orientation::DEGREES_180 => (width.wrapping_sub(x + 1), height.wrapping_sub(y + 1)),
+            // The right column of the image becomes the top row
+            Orientation::DEGREES_270 => (width.wrapping_sub(y + 1), x),
+            _ => (x, y),
+        };
+        self.image.pixel(x, y)
+    }
+
+    /// Position of the logo on a screen of the given `resolution`.
+    ///
+    /// The offsets of the BGRT are only valid in the graphics mode used by
+    /// the firmware, whose `boot_resolution` may be known to the caller. If
+    /// it is, the center of the logo is kept at the same relative position on
+    /// the screen, otherwise the offsets are used as they are, as long as the
+    /// logo fits.
+    pub fn position(
+        &self,
+        boot_resolution: Option<(usize, usize)>,
+        resolution: (usize, usize),
+    ) -> (usize, usize) {
+        let size = self.size();
+        let place = |offset: usize, size: usize, boot: Option<usize>, screen: usize| {
+            let offset = match boot {
+                Some(boot) if boot != 0 && boot != screen => {
+                    let center = (offset + size / 2) as u128 * screen as u128 / boot as u128;
+                    (center as usize).saturating_sub(size / 2)
+                }
+                _ => offset,
+            };
+            offset.min(screen.saturating_sub(size))
+        };
+        (
+            place(
+                self.offset.0,
+                size.0,
+                boot_resolution.map(|r| r.0),
+                resolution.0,
+            ),
+            place(
+                self.offset.1,
+                size.1,
+                boot_resolution.map(|r| r.1),
+                resolution.1,
+            ),
+        )
+    }
+
+    /// Draw the logo again, rotated, for example after a change of graphics
+    /// mode has cleared the screen.
+    ///
+    /// See `position` for the meaning of `boot_resolution`.
+    pub fn redraw(
+        &self,
+        gop: &mut GraphicsOutput,
+        boot_resolution: Option<(usize, usize)>,
+    ) -> Result {
+        let resolution = gop.current_mode_info().resolution();
+        let dest = self.position(boot_resolution, resolution);
+        bmp::draw_with(gop, dest, self.size(), |x, y| self.pixel(x, y))
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::vec::Vec;
+
+    fn bmp(width: u32, height: u32) -> Vec<u8> {
+        let row_size = (width * 3 + 3) / 4 * 4;
+        let size = 54 + row_size * height;
+        let mut bmp = Vec::new();
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&size.to_le_bytes());
+        bmp.extend_from_slice(&[0; 4]);
+        bmp.extend_from_slice(&54u32.to_le_bytes());
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&width.to_le_bytes());
+        bmp.extend_from_slice(&height.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&24u16.to_le_bytes());
+        bmp.resize(size as usize, 0);
+        bmp
+    }
+
+    fn bgrt(status: u8, image: &[u8], offset: (u32, u32)) -> Vec<u8> {
+        let mut table = Vec::new();
+        table.extend_from_slice(&BGRT_SIGNATURE);
+        table.extend_from_slice(&(BGRT_SIZE as u32).to_le_bytes());
+        table.resize(SDT_HEADER_SIZE, 0);
+        table.extend_from_slice(&1u16.to_le_bytes());
+        table.push(status);
+        table.push(0);
+        table.extend_from_slice(&(image.as_ptr() as u64).to_le_bytes());
+        table.extend_from_slice(&offset.0.to_le_bytes());
+        table.extend_from_slice(&offset.1.to_le_bytes());
+        let sum = table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        table[9] = sum.wrapping_neg();
+        table
+    }
+
+    #[test]
+    fn parse_table() {
+        let image = bmp(200, 100);
+        let table = bgrt(0b011, &image, (412, 200));
+        let bgrt = Bgrt::from_sdt(Sdt::from_bytes(&table).unwrap()).unwrap();
+        assert_eq!(bgrt.validate(), Ok(()));
+        assert!(bgrt.is_displayed());
+        assert_eq!(bgrt.orientation(), Orientation::DEGREES_90);
+        assert_eq!(bgrt.image_offset(), (412, 200));
+        let bmp = unsafe { bgrt.image() }.unwrap();
+        assert_eq!((bmp.width(), bmp.height()), (200, 100));
+
+        let mut corrupted = table.clone();
+        corrupted[SDT_HEADER_SIZE + 12] ^= 1;
+        let bgrt = Bgrt::from_sdt(Sdt::from_bytes(&corrupted).unwrap()).unwrap();
+        assert_eq!(bgrt.validate(), Err(BgrtError::InvalidTable));
+    }
+
+    #[test]
+    fn logo_position() {
+        let image = bmp(200, 100);
+        let logo = BootLogo {
+            image: Bmp::parse(&image).unwrap(),
+            offset: (412, 200),
+            orientation: Orientation::DEGREES_0,
+            displayed: true,
+        };
+        assert_eq!(logo.position(None, (1024, 768)), (412, 200));
+        assert_eq!(logo.position(Some((1024, 768)), (1024, 768)), (412, 200));
+        // The center of the logo stays at 50% and 32.6% of the screen
+        assert_eq!(logo.position(Some((1024, 768)), (2048, 1536)), (924, 450));
+        // The logo is kept on screen
+        assert_eq!(logo.position(None, (300, 150)), (100, 50));
+        let rotated = BootLogo {
+            orientation: Orientation::DEGREES_90,
+            ..logo
+        };
+        assert_eq!(rotated.size(), (100, 200));
+        assert_eq!(rotated.position(None, (300, 150)), (200, 0));
+    }
+
+    #[test]
+    fn rotation() {
+        // A 2x1 image, with a white pixel on the left
+        let mut image = bmp(2, 1);
+        image[54..57].copy_from_slice(&[0xff; 3]);
+        let (white, black) = (0xff, 0);
+        let logo = |orientation| BootLogo {
+            image: Bmp::parse(&image).unwrap(),
+            offset: (0, 0),
+            orientation,
+            displayed: true,
+        };
+        let pixels = |logo: BootLogo<'_>| {
+            let (width, height) = logo.size();
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| logo.pixel(x, y).red)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pixels(logo(Orientation::DEGREES_0)), [white, black]);
+        assert_eq!(pixels(logo(Orientation::DEGREES_90)), [white, black]);
+        assert_eq!(pixels(logo(Orientation::DEGREES_180)), [black, white]);
+        assert_eq!(pixels(logo(Orientation::DEGREES_270)), [black, white]);
+    }
+}
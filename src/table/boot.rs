@@ -1,163 +1,67 @@
 //! UEFI services available during boot.
 
-use super::Header;
+use super::{Boot, Revision, SystemTable};
 use crate::data_types::Align;
 use crate::proto::{device_path::DevicePath, Protocol};
 #[cfg(feature = "exts")]
 use crate::proto::{loaded_image::LoadedImage, media::fs::SimpleFileSystem};
+use crate::raw::boot::EventNotifyFn;
 use crate::{Char16, Event, Guid, Handle, Result, Status};
 #[cfg(feature = "exts")]
 use alloc_api::vec::Vec;
 use bitflags::bitflags;
+#[cfg(feature = "exts")]
+use core::alloc::Layout;
 use core::cell::UnsafeCell;
 use core::ffi::c_void;
 use core::fmt::{Debug, Formatter};
 use core::mem::{self, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 use core::{ptr, slice};
 
+/// Call a boot service which was added in the `$revision` of the
+/// specification with `call_service!`, or return `Status::UNSUPPORTED` if the
+/// table predates it.
+macro_rules! call_boot {
+    ($revision:ident, $bt:ident . $service:ident ( $($arg:expr),* $(,)? )) => {
+        if $bt.available(Revision::$revision, &$bt.raw.$service) {
+            call_service!($bt.raw.$service($($arg),*))
+        } else {
+            Status::UNSUPPORTED
+        }
+    };
+}
+
 /// Contains pointers to all of the boot services.
-#[repr(C)]
+#[repr(transparent)]
 pub struct BootServices {
-    header: Header,
-
-    // Task Priority services
-    raise_tpl: unsafe extern "efiapi" fn(new_tpl: Tpl) -> Tpl,
-    restore_tpl: unsafe extern "efiapi" fn(old_tpl: Tpl),
-
-    // Memory allocation functions
-    allocate_pages: extern "efiapi" fn(
-        alloc_ty: u32,
-        mem_ty: MemoryType,
-        count: usize,
-        addr: &mut u64,
-    ) -> Status,
-    free_pages: extern "efiapi" fn(addr: u64, pages: usize) -> Status,
-    get_memory_map: unsafe extern "efiapi" fn(
-        size: &mut usize,
-        map: *mut MemoryDescriptor,
-        key: &mut MemoryMapKey,
-        desc_size: &mut usize,
-        desc_version: &mut u32,
-    ) -> Status,
-    allocate_pool:
-        extern "efiapi" fn(pool_type: MemoryType, size: usize, buffer: &mut *mut u8) -> Status,
-    free_pool: extern "efiapi" fn(buffer: *mut u8) -> Status,
-
-    // Event & timer functions
-    create_event: unsafe extern "efiapi" fn(
-        ty: EventType,
-        notify_tpl: Tpl,
-        notify_func: Option<EventNotifyFn>,
-        notify_ctx: *mut c_void,
-        event: *mut Event,
-    ) -> Status,
-    set_timer: unsafe extern "efiapi" fn(event: Event, ty: u32, trigger_time: u64) -> Status,
-    wait_for_event: unsafe extern "efiapi" fn(
-        number_of_events: usize,
-        events: *mut Event,
-        out_index: *mut usize,
-    ) -> Status,
-    signal_event: usize,
-    close_event: usize,
-    check_event: unsafe extern "efiapi" fn(event: Event) -> Status,
-
-    // Protocol handlers
-    install_protocol_interface: usize,
-    reinstall_protocol_interface: usize,
-    uninstall_protocol_interface: usize,
-    handle_protocol:
-        extern "efiapi" fn(handle: Handle, proto: &Guid, out_proto: &mut *mut c_void) -> Status,
-    _reserved: usize,
-    register_protocol_notify: usize,
-    locate_handle: unsafe extern "efiapi" fn(
-        search_ty: i32,
-        proto: *const Guid,
-        key: *mut c_void,
-        buf_sz: &mut usize,
-        buf: *mut Handle,
-    ) -> Status,
-    locate_device_path: unsafe extern "efiapi" fn(
-        proto: &Guid,
-        device_path: &mut *mut DevicePath,
-        out_handle: *mut Handle,
-    ) -> Status,
-    install_configuration_table: usize,
-
-    // Image services
-    load_image: unsafe extern "efiapi" fn(
-        boot_policy: u8,
-        parent_image_handle: Handle,
-        device_path: *const DevicePath,
-        source_buffer: *const u8,
-        source_size: usize,
-        *mut Handle,
-    ) -> Status,
-    start_image: unsafe extern "efiapi" fn(
-        image_handle: Handle,
-        exit_data_size: *mut usize,
-        exit_data: &mut *mut Char16,
-    ) -> Status,
-    exit: extern "efiapi" fn(
-        image_handle: Handle,
-        exit_status: Status,
-        exit_data_size: usize,
-        exit_data: *mut Char16,
-    ) -> !,
-    unload_image: extern "efiapi" fn(image_handle: Handle) -> Status,
-    exit_boot_services:
-        unsafe extern "efiapi" fn(image_handle: Handle, map_key: MemoryMapKey) -> Status,
-
-    // Misc services
-    get_next_monotonic_count: usize,
-    stall: extern "efiapi" fn(microseconds: usize) -> Status,
-    set_watchdog_timer: unsafe extern "efiapi" fn(
-        timeout: usize,
-        watchdog_code: u64,
-        data_size: usize,
-        watchdog_data: *const u16,
-    ) -> Status,
+    raw: crate::raw::BootServices,
+}
 
-    // Driver support services
-    connect_controller: usize,
-    disconnect_controller: usize,
+impl BootServices {
+    /// Revision of the specification which the table conforms to
+    pub fn revision(&self) -> Revision {
+        self.raw.header.revision
+    }
 
-    // Protocol open / close services
-    open_protocol: usize,
-    close_protocol: usize,
-    open_protocol_information: usize,
+    /// Return the raw definition of this table, which gives access to the
+    /// services which have no safe wrapper
+    pub fn as_raw(&self) -> &crate::raw::BootServices {
+        &self.raw
+    }
 
-    // Library services
-    protocols_per_handle: unsafe extern "efiapi" fn(
-        handle: Handle,
-        protocol_buffer: *mut *mut *const Guid,
-        protocol_buffer_count: *mut usize,
-    ) -> Status,
-    locate_handle_buffer: usize,
-    locate_protocol: extern "efiapi" fn(
-        proto: &Guid,
-        registration: *mut c_void,
-        out_proto: &mut *mut c_void,
-    ) -> Status,
-    install_multiple_protocol_interfaces: usize,
-    uninstall_multiple_protocol_interfaces: usize,
-
-    // CRC services
-    calculate_crc32: usize,
-
-    // Misc services
-    copy_mem: unsafe extern "efiapi" fn(dest: *mut u8, src: *const u8, len: usize),
-    set_mem: unsafe extern "efiapi" fn(buffer: *mut u8, len: usize, value: u8),
-
-    // New event functions (UEFI 2.0 or newer)
-    create_event_ex: usize,
-}
+    /// Whether the table has the `entry` added in `revision`
+    fn available<T>(&self, revision: Revision, entry: &T) -> bool {
+        self.raw.header.require(revision, entry).is_ok()
+    }
 
-impl BootServices {
     /// Raises a task's priority level and returns its previous level.
     ///
     /// The effect of calling `raise_tpl` with a `Tpl` that is below the current
-    /// one (which, sadly, cannot be queried) is undefined by the UEFI spec,
-    /// which also warns against remaining at high `Tpl`s for a long time.
+    /// one (which has no service of its own, see `current_tpl`) is undefined by
+    /// the UEFI spec, which also warns against remaining at high `Tpl`s for a
+    /// long time.
     ///
     /// This function outputs an RAII guard that will automatically restore the
     /// original `Tpl` when dropped.
@@ -168,12 +72,38 @@ impl BootServices {
     /// critical processes run by UEFI. The highest priority level is the
     /// most dangerous, since it disables interrupts.
     pub unsafe fn raise_tpl(&self, tpl: Tpl) -> TplGuard<'_> {
+        assert_active();
         TplGuard {
             boot_services: self,
-            old_tpl: (self.raise_tpl)(tpl),
+            old_tpl: (self.raw.raise_tpl)(tpl),
         }
     }
 
+    /// The current task priority level
+    ///
+    /// It is read by raising the TPL to `Tpl::HIGH_LEVEL`, which is valid at
+    /// any level, and restoring it right away.
+    pub fn current_tpl(&self) -> Tpl {
+        let guard = unsafe { self.raise_tpl(Tpl::HIGH_LEVEL) };
+        guard.old_tpl
+    }
+
+    /// Restores the task priority level to `tpl`, without a guard.
+    ///
+    /// This is for code which never returns to the owners of the guards, such
+    /// as a panic hook which must call services that are not allowed at the
+    /// current level. Otherwise, dropping the guard of `raise_tpl` does this.
+    ///
+    /// # Safety
+    ///
+    /// `tpl` must not be above the current level. The code which raised the
+    /// level must not run anymore, since the state that it protects may be
+    /// accessed by event callbacks once the level is lowered.
+    pub unsafe fn restore_tpl(&self, tpl: Tpl) {
+        assert_active();
+        (self.raw.restore_tpl)(tpl)
+    }
+
     /// Allocates memory pages from the system.
     ///
     /// UEFI OS loaders should allocate memory of the type `LoaderData`. An `u64`
@@ -185,17 +115,56 @@ impl BootServices {
         mem_ty: MemoryType,
         count: usize,
     ) -> Result<u64> {
+        assert_active();
         let (ty, mut addr) = match ty {
-            AllocateType::AnyPages => (0, 0),
-            AllocateType::MaxAddress(addr) => (1, addr as u64),
-            AllocateType::Address(addr) => (2, addr as u64),
+            AllocateType::AnyPages => (ALLOCATE_ANY_PAGES, 0),
+            AllocateType::MaxAddress(addr) => (ALLOCATE_MAX_ADDRESS, addr as u64),
+            AllocateType::Address(addr) => (ALLOCATE_ADDRESS, addr as u64),
         };
-        (self.allocate_pages)(ty, mem_ty, count, &mut addr).into_with_val(|| addr)
+        unsafe { (self.raw.allocate_pages)(ty, mem_ty, count, &mut addr) }.into_with_val(|| addr)
+    }
+
+    /// Allocates `count` pages of `LOADER_DATA` memory starting at the
+    /// physical address `addr`, which must be page-aligned.
+    ///
+    /// This is typically used to place a kernel image at the address it was
+    /// linked for. Use `size_to_pages` to compute `count` from a byte size.
+    ///
+    /// # Errors
+    /// * `uefi::Status::INVALID_PARAMETER`  `addr` is not page-aligned
+    /// * `uefi::Status::NOT_FOUND`          The requested pages are not available
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The pages could not be allocated
+    pub fn allocate_pages_at(&self, addr: usize, count: usize) -> Result<u64> {
+        assert_active();
+        if addr % PAGE_SIZE != 0 {
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        self.allocate_pages(AllocateType::Address(addr), MemoryType::LOADER_DATA, count)
+    }
+
+    /// Allocates `count` pages of `LOADER_DATA` memory, all of which are
+    /// located at or below the physical address `max_addr`.
+    ///
+    /// This is useful for buffers which must be reachable by devices with a
+    /// limited DMA range, or by code running in 32-bit mode. Use
+    /// `size_to_pages` to compute `count` from a byte size.
+    ///
+    /// # Errors
+    /// * `uefi::Status::NOT_FOUND`          No suitable range of pages is available
+    /// * `uefi::Status::OUT_OF_RESOURCES`   The pages could not be allocated
+    pub fn allocate_pages_below(&self, max_addr: usize, count: usize) -> Result<u64> {
+        assert_active();
+        self.allocate_pages(
+            AllocateType::MaxAddress(max_addr),
+            MemoryType::LOADER_DATA,
+            count,
+        )
     }
 
     /// Frees memory pages allocated by UEFI.
     pub fn free_pages(&self, addr: u64, count: usize) -> Result {
-        (self.free_pages)(addr, count).into()
+        assert_active();
+        unsafe { (self.raw.free_pages)(addr, count) }.into()
     }
 
     /// Retrieves the size, in bytes, of the current memory map.
@@ -204,16 +173,17 @@ impl BootServices {
     /// including padding. Note, however, that allocations will increase the size of the
     /// memory map, therefore it is better to allocate some extra space.
     pub fn memory_map_size(&self) -> usize {
+        assert_active();
         let mut map_size = 0;
         let mut map_key = MemoryMapKey(0);
         let mut entry_size = 0;
         let mut entry_version = 0;
 
         let status = unsafe {
-            (self.get_memory_map)(
+            (self.raw.get_memory_map)(
                 &mut map_size,
                 ptr::null_mut(),
-                &mut map_key,
+                &mut map_key.0,
                 &mut entry_size,
                 &mut entry_version,
             )
@@ -242,6 +212,7 @@ impl BootServices {
         MemoryMapKey,
         impl ExactSizeIterator<Item = &'buf MemoryDescriptor> + Clone,
     )> {
+        assert_active();
         let mut map_size = buffer.len();
         MemoryDescriptor::assert_aligned(buffer);
         #[allow(clippy::cast_ptr_alignment)]
@@ -257,10 +228,10 @@ impl BootServices {
         );
 
         unsafe {
-            (self.get_memory_map)(
+            (self.raw.get_memory_map)(
                 &mut map_size,
                 map_buffer,
-                &mut map_key,
+                &mut map_key.0,
                 &mut entry_size,
                 &mut entry_version,
             )
@@ -278,14 +249,81 @@ impl BootServices {
     }
 
     /// Allocates from a memory pool. The pointer will be 8-byte aligned.
+    ///
+    /// See `allocate_pool_typed` and `allocate_pool_slice` for allocations
+    /// which are freed automatically.
     pub fn allocate_pool(&self, mem_ty: MemoryType, size: usize) -> Result<*mut u8> {
+        assert_active();
         let mut buffer = ptr::null_mut();
-        (self.allocate_pool)(mem_ty, size, &mut buffer).into_with_val(|| buffer)
+        unsafe { (self.raw.allocate_pool)(mem_ty, size, &mut buffer) }.into_with_val(|| buffer)
     }
 
     /// Frees memory allocated from a pool.
+    // The firmware validates the pointer, rather than dereferencing it
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn free_pool(&self, addr: *mut u8) -> Result {
-        (self.free_pool)(addr).into()
+        assert_active();
+        unsafe { (self.raw.free_pool)(addr) }.into()
+    }
+
+    /// Moves a value to memory allocated from a pool, which is freed when the
+    /// returned box is dropped.
+    ///
+    /// Fails with `UNSUPPORTED` if `T` requires an alignment larger than the
+    /// 8 bytes guaranteed by pool allocations. The value is dropped on failure.
+    pub fn allocate_pool_typed<T>(&self, mem_ty: MemoryType, value: T) -> Result<PoolBox<'_, T>> {
+        assert_active();
+        let ptr = self.allocate_pool_array::<T>(mem_ty, 1)?;
+        Ok(ptr.map(|ptr| unsafe {
+            ptr.write(value);
+            PoolBox {
+                boot_services: self,
+                ptr,
+            }
+        }))
+    }
+
+    /// Allocates an array of `len` copies of `value` from a pool, which is
+    /// freed when the returned slice is dropped.
+    ///
+    /// Fails with `UNSUPPORTED` if `T` requires an alignment larger than the
+    /// 8 bytes guaranteed by pool allocations, and with `OUT_OF_RESOURCES` if
+    /// the size of the array overflows.
+    pub fn allocate_pool_slice<T: Clone>(
+        &self,
+        mem_ty: MemoryType,
+        len: usize,
+        value: T,
+    ) -> Result<PoolSlice<'_, T>> {
+        assert_active();
+        let ptr = self.allocate_pool_array::<T>(mem_ty, len)?;
+        Ok(ptr.map(|ptr| unsafe {
+            for i in 0..len {
+                ptr.add(i).write(value.clone());
+            }
+            PoolSlice {
+                boot_services: self,
+                ptr,
+                len,
+            }
+        }))
+    }
+
+    /// Allocates uninitialized pool memory for `len` values of type `T`.
+    ///
+    /// Zero-sized allocations are not forwarded to the firmware, and return a
+    /// dangling pointer instead.
+    fn allocate_pool_array<T>(&self, mem_ty: MemoryType, len: usize) -> Result<*mut T> {
+        if mem::align_of::<T>() > POOL_ALIGNMENT {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        let size = match mem::size_of::<T>().checked_mul(len) {
+            Some(0) => return Ok(ptr::NonNull::<T>::dangling().as_ptr().into()),
+            Some(size) => size,
+            None => return Err(Status::OUT_OF_RESOURCES.into()),
+        };
+        let ptr = self.allocate_pool(mem_ty, size)?;
+        Ok(ptr.map(|ptr| ptr as *mut T))
     }
 
     /// Creates an event
@@ -309,26 +347,15 @@ impl BootServices {
         notify_tpl: Tpl,
         notify_fn: Option<fn(Event)>,
     ) -> Result<Event> {
+        assert_active();
         // Prepare storage for the output Event
         let mut event = MaybeUninit::<Event>::uninit();
 
-        // Use a trampoline to handle the impedance mismatch between Rust & C
-        unsafe extern "efiapi" fn notify_trampoline(e: Event, ctx: *mut c_void) {
-            let notify_fn: fn(Event) = mem::transmute(ctx);
-            notify_fn(e); // SAFETY: Aborting panics are assumed here
-        }
-        let (notify_func, notify_ctx) = notify_fn
-            .map(|notify_fn| {
-                (
-                    Some(notify_trampoline as EventNotifyFn),
-                    notify_fn as fn(Event) as *mut c_void,
-                )
-            })
-            .unwrap_or((None, ptr::null_mut()));
+        let (notify_func, notify_ctx) = notify_parts(notify_fn);
 
         // Now we're ready to call UEFI
-        (self.create_event)(
-            event_ty,
+        (self.raw.create_event)(
+            event_ty.bits(),
             notify_tpl,
             notify_func,
             notify_ctx,
@@ -337,14 +364,103 @@ impl BootServices {
         .into_with_val(|| event.assume_init())
     }
 
+    /// Creates an event, which belongs to `event_group` if it is set.
+    ///
+    /// All the events of a group are signaled when one of them is. The
+    /// firmware signals the groups of `event_group` at the corresponding
+    /// boot phases. The event types `SIGNAL_EXIT_BOOT_SERVICES` and
+    /// `SIGNAL_VIRTUAL_ADDRESS_CHANGE` are not allowed here, the groups
+    /// `event_group::EXIT_BOOT_SERVICES` and
+    /// `event_group::VIRTUAL_ADDRESS_CHANGE` are used instead.
+    ///
+    /// Fails with `UNSUPPORTED` on firmware older than UEFI 2.0.
+    ///
+    /// # Safety
+    ///
+    /// See `create_event`.
+    pub unsafe fn create_event_ex(
+        &self,
+        event_ty: EventType,
+        notify_tpl: Tpl,
+        notify_fn: Option<fn(Event)>,
+        event_group: Option<&Guid>,
+    ) -> Result<Event> {
+        assert_active();
+        let mut event = MaybeUninit::<Event>::uninit();
+        let (notify_func, notify_ctx) = notify_parts(notify_fn);
+        let event_group = event_group.map_or(ptr::null(), |guid| guid as *const Guid);
+        call_boot!(
+            EFI_2_00,
+            self.create_event_ex(
+                event_ty.bits(),
+                notify_tpl,
+                notify_func,
+                notify_ctx,
+                event_group,
+                event.as_mut_ptr(),
+            )
+        )
+        .into_with_val(|| event.assume_init())
+    }
+
+    /// Call `notify_fn` at `Tpl::CALLBACK` whenever `event_group` is
+    /// signaled, until the returned event is closed.
+    ///
+    /// # Safety
+    ///
+    /// See `create_event`. In particular, the notification functions of
+    /// `event_group::EXIT_BOOT_SERVICES` may not use memory allocation
+    /// services, nor timers.
+    pub unsafe fn subscribe_event_group(
+        &self,
+        event_group: &Guid,
+        notify_fn: fn(Event),
+    ) -> Result<Event> {
+        assert_active();
+        self.create_event_ex(
+            EventType::NOTIFY_SIGNAL,
+            Tpl::CALLBACK,
+            Some(notify_fn),
+            Some(event_group),
+        )
+    }
+
+    /// Signal all the events of `event_group`, like the boot manager does for
+    /// `event_group::READY_TO_BOOT` before starting a boot option.
+    pub fn signal_event_group(&self, event_group: &Guid) -> Result {
+        assert_active();
+        fn ignore(_event: Event) {}
+        // A group is signaled through any event which belongs to it
+        let event = unsafe { self.subscribe_event_group(event_group, ignore) }?.log();
+        let status = self.signal_event(event);
+        self.close_event(event)?.log();
+        status
+    }
+
+    /// Signal `event`, queueing its notification function if it has the
+    /// type `NOTIFY_SIGNAL`, and signaling the other events of its group.
+    pub fn signal_event(&self, event: Event) -> Result {
+        assert_active();
+        unsafe { (self.raw.signal_event)(event) }.into()
+    }
+
     /// Sets the trigger for `EventType::TIMER` event.
     pub fn set_timer(&self, event: Event, trigger_time: TimerTrigger) -> Result {
+        assert_active();
         let (ty, time) = match trigger_time {
             TimerTrigger::Cancel => (0, 0),
             TimerTrigger::Periodic(hundreds_ns) => (1, hundreds_ns),
             TimerTrigger::Relative(hundreds_ns) => (2, hundreds_ns),
         };
-        unsafe { (self.set_timer)(event, ty, time) }.into()
+        unsafe { (self.raw.set_timer)(event, ty, time) }.into()
+    }
+
+    /// Closes an event, cancelling its timer and any pending notification.
+    ///
+    /// The event must not be used anymore once it has been closed.
+    pub fn close_event(&self, event: Event) -> Result {
+        assert_active();
+        unsafe { (self.raw.close_event)(event) }.into()
     }
 
     /// Stops execution until an event is signaled.
@@ -375,18 +491,20 @@ impl BootServices {
     /// event can be used as the last event in the slice being checked, or the
     /// check_event() interface may be used.
     pub fn wait_for_event(&self, events: &mut [Event]) -> Result<usize, Option<usize>> {
+        assert_active();
         let (number_of_events, events) = (events.len(), events.as_mut_ptr());
         let mut index = MaybeUninit::<usize>::uninit();
-        unsafe { (self.wait_for_event)(number_of_events, events, index.as_mut_ptr()) }.into_with(
-            || unsafe { index.assume_init() },
-            |s| {
-                if s == Status::INVALID_PARAMETER {
-                    unsafe { Some(index.assume_init()) }
-                } else {
-                    None
-                }
-            },
-        )
+        unsafe { (self.raw.wait_for_event)(number_of_events, events, index.as_mut_ptr()) }
+            .into_with(
+                || unsafe { index.assume_init() },
+                |s| {
+                    if s == Status::INVALID_PARAMETER {
+                        unsafe { Some(index.assume_init()) }
+                    } else {
+                        None
+                    }
+                },
+            )
     }
 
     /// Checks to see if an event is signaled, without blocking execution to wait for it.
@@ -394,7 +512,8 @@ impl BootServices {
     /// The returned value will be `true` if the event is in the signaled state,
     /// otherwise `false` is returned.
     pub fn check_event(&self, event: Event) -> Result<bool> {
-        let status = unsafe { (self.check_event)(event) };
+        assert_active();
+        let status = unsafe { (self.raw.check_event)(event) };
         match status {
             Status::SUCCESS => Ok(true.into()),
             Status::NOT_READY => Ok(false.into()),
@@ -402,6 +521,88 @@ impl BootServices {
         }
     }
 
+    /// Install a protocol interface on a handle.
+    ///
+    /// If `handle` is `None`, a new handle is created and returned.
+    ///
+    /// # Safety
+    ///
+    /// The interface must implement the protocol identified by `protocol`, and
+    /// stay valid until it is uninstalled, since the firmware hands it out to
+    /// any code which looks the protocol up.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::OUT_OF_RESOURCES`   A new handle could not be allocated
+    /// * `uefi::Status::INVALID_PARAMETER`  The protocol is already installed
+    ///                                      on the handle
+    pub unsafe fn install_protocol_interface(
+        &self,
+        handle: Option<Handle>,
+        protocol: &Guid,
+        interface: *mut c_void,
+    ) -> Result<Handle> {
+        assert_active();
+        let mut handle = handle.unwrap_or_else(|| Handle::uninitialized());
+        (self.raw.install_protocol_interface)(
+            &mut handle,
+            protocol,
+            InterfaceType::NATIVE_INTERFACE.0,
+            interface,
+        )
+        .into_with_val(|| handle)
+    }
+
+    /// Replace a protocol interface installed on a handle by another one.
+    ///
+    /// The drivers which use the old interface are disconnected, and
+    /// reconnected to the new one.
+    ///
+    /// # Safety
+    ///
+    /// See `install_protocol_interface`.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`          The old interface is not installed
+    ///                                      on the handle
+    /// * `uefi::Status::ACCESS_DENIED`      The old interface is still in use
+    pub unsafe fn reinstall_protocol_interface(
+        &self,
+        handle: Handle,
+        protocol: &Guid,
+        old_interface: *mut c_void,
+        new_interface: *mut c_void,
+    ) -> Result {
+        assert_active();
+        (self.raw.reinstall_protocol_interface)(handle, protocol, old_interface, new_interface)
+            .into()
+    }
+
+    /// Remove a protocol interface from a handle.
+    ///
+    /// The handle is destroyed once its last protocol is removed.
+    ///
+    /// # Safety
+    ///
+    /// No code may keep using the interface after it has been removed, which
+    /// the firmware only guarantees for the drivers that opened it.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`          The interface is not installed on
+    ///                                      the handle
+    /// * `uefi::Status::ACCESS_DENIED`      The interface is still in use
+    pub unsafe fn uninstall_protocol_interface(
+        &self,
+        handle: Handle,
+        protocol: &Guid,
+        interface: *mut c_void,
+    ) -> Result {
+        assert_active();
+        (self.raw.uninstall_protocol_interface)(handle, protocol, interface).into()
+    }
+
     /// Query a handle for a certain protocol.
     ///
     /// This function attempts to get the protocol implementation of a handle,
@@ -412,8 +613,9 @@ impl BootServices {
     /// protections must be implemented by user-level code, for example via a
     /// global `HashSet`.
     pub fn handle_protocol<P: Protocol>(&self, handle: Handle) -> Result<&UnsafeCell<P>> {
+        assert_active();
         let mut ptr = ptr::null_mut();
-        (self.handle_protocol)(handle, &P::GUID, &mut ptr).into_with_val(|| {
+        unsafe { (self.raw.handle_protocol)(handle, &P::GUID, &mut ptr) }.into_with_val(|| {
             let ptr = ptr as *mut P as *mut UnsafeCell<P>;
             unsafe { &*ptr }
         })
@@ -430,6 +632,7 @@ impl BootServices {
         search_ty: SearchType,
         output: Option<&mut [Handle]>,
     ) -> Result<usize> {
+        assert_active();
         let handle_size = mem::size_of::<Handle>();
 
         const NULL_BUFFER: *mut Handle = ptr::null_mut();
@@ -445,7 +648,7 @@ impl BootServices {
             SearchType::ByProtocol(guid) => (2, guid as *const _, ptr::null_mut()),
         };
 
-        let status = unsafe { (self.locate_handle)(ty, guid, key, &mut buffer_size, buffer) };
+        let status = unsafe { (self.raw.locate_handle)(ty, guid, key, &mut buffer_size, buffer) };
 
         // Must convert the returned size (in bytes) to length (number of elements).
         let buffer_len = buffer_size / handle_size;
@@ -458,26 +661,73 @@ impl BootServices {
 
     /// Locates the handle to a device on the device path that supports the specified protocol.
     pub fn locate_device_path<P: Protocol>(&self, device_path: &mut DevicePath) -> Result<Handle> {
+        assert_active();
         unsafe {
             let mut handle = Handle::uninitialized();
             let mut device_path_ptr = device_path as *mut DevicePath;
-            (self.locate_device_path)(&P::GUID, &mut device_path_ptr, &mut handle)
+            (self.raw.locate_device_path)(&P::GUID, &mut device_path_ptr, &mut handle)
                 .into_with_val(|| handle)
         }
     }
 
+    /// Adds, replaces or removes an entry of the configuration table.
+    ///
+    /// The entry identified by `guid` points to `table` afterwards, or is
+    /// removed if `table` is null.
+    ///
+    /// # Safety
+    ///
+    /// `table` must stay valid as long as it is installed, in memory which
+    /// is not freed before its consumers are done with it, like
+    /// `RUNTIME_SERVICES_DATA` for the tables used by the OS.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::NOT_FOUND`         There is no entry to remove
+    /// * `uefi::Status::OUT_OF_RESOURCES`  The table could not be extended
+    pub unsafe fn install_configuration_table(&self, guid: &Guid, table: *const c_void) -> Result {
+        assert_active();
+        (self.raw.install_configuration_table)(guid, table as *mut c_void).into()
+    }
+
     /// Load an EFI image from a buffer.
     pub fn load_image_from_buffer(
         &self,
         parent_image_handle: Handle,
         source_buffer: &[u8],
     ) -> Result<Handle> {
+        assert_active();
         unsafe {
-            let boot_policy = 0;
+            let boot_policy = false;
             let device_path = ptr::null();
             let source_size = source_buffer.len();
             let mut image_handle = Handle::uninitialized();
-            (self.load_image)(
+            (self.raw.load_image)(
+                boot_policy,
+                parent_image_handle,
+                device_path,
+                source_buffer.as_ptr(),
+                source_size,
+                &mut image_handle,
+            )
+            .into_with_val(|| image_handle)
+        }
+    }
+
+    /// Load an EFI image from a buffer, giving the device path it was read
+    /// from to the security policy of the platform.
+    pub fn load_image_from_device_path(
+        &self,
+        parent_image_handle: Handle,
+        device_path: &DevicePath,
+        source_buffer: &[u8],
+    ) -> Result<Handle> {
+        assert_active();
+        unsafe {
+            let boot_policy = false;
+            let source_size = source_buffer.len();
+            let mut image_handle = Handle::uninitialized();
+            (self.raw.load_image)(
                 boot_policy,
                 parent_image_handle,
                 device_path,
@@ -491,16 +741,18 @@ impl BootServices {
 
     /// Unload an EFI image.
     pub fn unload_image(&self, image_handle: Handle) -> Result {
-        (self.unload_image)(image_handle).into()
+        assert_active();
+        unsafe { (self.raw.unload_image)(image_handle) }.into()
     }
 
     /// Transfer control to a loaded image's entry point.
     pub fn start_image(&self, image_handle: Handle) -> Result {
+        assert_active();
         unsafe {
             // TODO: implement returning exit data to the caller.
             let mut exit_data_size: usize = 0;
             let mut exit_data: *mut Char16 = ptr::null_mut();
-            (self.start_image)(image_handle, &mut exit_data_size, &mut exit_data).into()
+            (self.raw.start_image)(image_handle, &mut exit_data_size, &mut exit_data).into()
         }
     }
 
@@ -520,7 +772,8 @@ impl BootServices {
         exit_data_size: usize,
         exit_data: *mut Char16,
     ) -> ! {
-        (self.exit)(image_handle, exit_status, exit_data_size, exit_data)
+        assert_active();
+        (self.raw.exit)(image_handle, exit_status, exit_data_size, exit_data)
     }
 
     /// Exits the UEFI boot services
@@ -538,14 +791,19 @@ impl BootServices {
         image: Handle,
         mmap_key: MemoryMapKey,
     ) -> Result {
-        (self.exit_boot_services)(image, mmap_key).into()
+        let status = (self.raw.exit_boot_services)(image, mmap_key.0);
+        if status.is_success() {
+            BOOT_SERVICES_ACTIVE.store(false, Ordering::Relaxed);
+        }
+        status.into()
     }
 
     /// Stalls the processor for an amount of time.
     ///
     /// The time is in microseconds.
     pub fn stall(&self, time: usize) {
-        assert_eq!((self.stall)(time), Status::SUCCESS);
+        assert_active();
+        assert_eq!(unsafe { (self.raw.stall)(time) }, Status::SUCCESS);
     }
 
     /// Set the watchdog timer.
@@ -572,6 +830,7 @@ impl BootServices {
         watchdog_code: u64,
         data: Option<&mut [u16]>,
     ) -> Result {
+        assert_active();
         assert!(
             watchdog_code > 0xffff,
             "Invalid use of a reserved firmware watchdog code"
@@ -583,20 +842,28 @@ impl BootServices {
                     d.contains(&0),
                     "Watchdog data must start with a null-terminated string"
                 );
-                (d.len(), d.as_mut_ptr())
+                (d.len(), d.as_ptr().cast())
             })
-            .unwrap_or((0, ptr::null_mut()));
+            .unwrap_or((0, ptr::null()));
 
-        unsafe { (self.set_watchdog_timer)(timeout, watchdog_code, data_len, data) }.into()
+        unsafe { (self.raw.set_watchdog_timer)(timeout, watchdog_code, data_len, data) }.into()
     }
 
     /// Get the list of protocol interface [`Guids`][Guid] that are installed
     /// on a [`Handle`].
+    ///
+    /// Fails with `UNSUPPORTED` on firmware older than EFI 1.10.
     pub fn protocols_per_handle(&self, handle: Handle) -> Result<ProtocolsPerHandle> {
+        assert_active();
         let mut protocols = ptr::null_mut();
         let mut count = 0;
 
-        let mut status = unsafe { (self.protocols_per_handle)(handle, &mut protocols, &mut count) };
+        let mut status = unsafe {
+            call_boot!(
+                EFI_1_10,
+                self.protocols_per_handle(handle, &mut protocols, &mut count)
+            )
+        };
 
         if !status.is_error() {
             // Ensure that protocols isn't null, and that none of the GUIDs
@@ -623,32 +890,79 @@ impl BootServices {
     /// Returns a protocol implementation, if present on the system.
     ///
     /// The caveats of `BootServices::handle_protocol()` also apply here.
+    /// Fails with `UNSUPPORTED` on firmware older than EFI 1.10.
     pub fn locate_protocol<P: Protocol>(&self) -> Result<&UnsafeCell<P>> {
+        assert_active();
         let mut ptr = ptr::null_mut();
-        (self.locate_protocol)(&P::GUID, ptr::null_mut(), &mut ptr).into_with_val(|| {
+        unsafe {
+            call_boot!(
+                EFI_1_10,
+                self.locate_protocol(&P::GUID, ptr::null_mut(), &mut ptr)
+            )
+        }
+        .into_with_val(|| {
             let ptr = ptr as *mut P as *mut UnsafeCell<P>;
             unsafe { &*ptr }
         })
     }
 
+    /// Computes the CRC-32 of `data`, like the one of the table headers.
+    ///
+    /// `uefi::crc::crc32` gives the same result, also when boot services are
+    /// not available.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::INVALID_PARAMETER`  `data` is empty
+    /// * `uefi::Status::UNSUPPORTED`        The firmware is older than EFI 1.10
+    pub fn calculate_crc32(&self, data: &[u8]) -> Result<u32> {
+        assert_active();
+        let mut crc = 0;
+        unsafe {
+            call_boot!(
+                EFI_1_10,
+                self.calculate_crc32(data.as_ptr().cast(), data.len(), &mut crc)
+            )
+        }
+        .into_with_val(|| crc)
+    }
+
     /// Copies memory from source to destination. The buffers can overlap.
     ///
+    /// The copy is made by the CPU if the firmware does not provide the
+    /// service.
+    ///
     /// # Safety
     ///
     /// This function is unsafe as it can be used to violate most safety
     /// invariants of the Rust type system.
     pub unsafe fn memmove(&self, dest: *mut u8, src: *const u8, size: usize) {
-        (self.copy_mem)(dest, src, size);
+        assert_active();
+        match self.raw.copy_mem {
+            Some(copy_mem) if self.available(Revision::EFI_1_10, &self.raw.copy_mem) => {
+                copy_mem(dest, src, size)
+            }
+            _ => ptr::copy(src, dest, size),
+        }
     }
 
     /// Sets a buffer to a certain value.
     ///
+    /// The buffer is filled by the CPU if the firmware does not provide the
+    /// service.
+    ///
     /// # Safety
     ///
     /// This function is unsafe as it can be used to violate most safety
     /// invariants of the Rust type system.
     pub unsafe fn set_mem(&self, buffer: *mut u8, size: usize, value: u8) {
-        (self.set_mem)(buffer, size, value);
+        assert_active();
+        match self.raw.set_mem {
+            Some(set_mem) if self.available(Revision::EFI_1_10, &self.raw.set_mem) => {
+                set_mem(buffer, size, value)
+            }
+            _ => ptr::write_bytes(buffer, value, size),
+        }
     }
 }
 
@@ -656,6 +970,7 @@ impl BootServices {
 impl BootServices {
     /// Returns all the handles implementing a certain protocol.
     pub fn find_handles<P: Protocol>(&self) -> Result<Vec<Handle>> {
+        assert_active();
         // Search by protocol.
         let search_type = SearchType::from_proto::<P>();
 
@@ -692,6 +1007,7 @@ impl BootServices {
         &self,
         image_handle: Handle,
     ) -> Result<&UnsafeCell<SimpleFileSystem>> {
+        assert_active();
         let loaded_image = self
             .handle_protocol::<LoadedImage>(image_handle)?
             .expect("Failed to retrieve `LoadedImage` protocol from handle");
@@ -710,6 +1026,82 @@ impl BootServices {
 
         self.handle_protocol::<SimpleFileSystem>(device_handle)
     }
+
+    /// Retrieves an owned copy of the current memory map.
+    ///
+    /// Unlike `memory_map`, this function takes care of allocating a large
+    /// enough (and correctly aligned) buffer, and the returned `MemoryMap`
+    /// can be freely post-processed without keeping that buffer around.
+    pub fn memory_map_owned(&self) -> Result<MemoryMap> {
+        assert_active();
+        // Allocating the buffer can itself grow the memory map, so leave room
+        // for a few extra descriptors.
+        let size = self.memory_map_size() + 8 * mem::size_of::<MemoryDescriptor>();
+        let layout = Layout::from_size_align(size, MemoryDescriptor::alignment()).unwrap();
+        let mut buffer = crate::exts::allocate_buffer(layout);
+
+        self.memory_map(&mut buffer).map(|completion| {
+            completion.map(|(key, iter)| MemoryMap {
+                key,
+                descriptors: iter.copied().collect(),
+            })
+        })
+    }
+}
+
+/// Whether boot services have not been exited yet
+static BOOT_SERVICES_ACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// The system table registered with `set_system_table`
+static SYSTEM_TABLE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Whether boot services are still available.
+///
+/// This turns false once `SystemTable::exit_boot_services` succeeds. Debug
+/// builds check it on every call to `BootServices`, which catches runtime
+/// code paths that still use boot services.
+pub fn boot_services_active() -> bool {
+    BOOT_SERVICES_ACTIVE.load(Ordering::Relaxed)
+}
+
+#[inline]
+fn assert_active() {
+    debug_assert!(
+        boot_services_active(),
+        "boot services are used after exit_boot_services"
+    );
+}
+
+/// Register the system table returned by `system_table`.
+///
+/// This is opt-in: the crate does not keep the system table otherwise, and
+/// code which is given the system table does not need it.
+///
+/// # Safety
+///
+/// `st` must be the system table of the running image. It is not used
+/// after boot services are exited, but references returned by
+/// `system_table` must not be kept across the exit.
+pub unsafe fn set_system_table(st: &SystemTable<Boot>) {
+    SYSTEM_TABLE.store(st.as_ptr() as *mut c_void, Ordering::Relaxed);
+}
+
+/// The system table registered with `set_system_table`, or `None` if there
+/// is none or if boot services were exited.
+///
+/// Only a shared reference to the boot view is given, so that boot services
+/// cannot be exited through it, nor the console taken from its owner.
+pub fn system_table() -> Option<&'static SystemTable<Boot>> {
+    if !boot_services_active() {
+        return None;
+    }
+    let table = SYSTEM_TABLE.load(Ordering::Relaxed);
+    if table.is_null() {
+        return None;
+    }
+    // A `SystemTable` is a pointer to the table, so the static which holds
+    // the pointer can be viewed as one
+    Some(unsafe { &*(&SYSTEM_TABLE as *const AtomicPtr<c_void> as *const SystemTable<Boot>) })
 }
 
 impl super::Table for BootServices {
@@ -719,125 +1111,197 @@ impl super::Table for BootServices {
 impl Debug for BootServices {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("BootServices")
-            .field("header", &self.header)
-            .field("raise_tpl (fn ptr)", &(self.raise_tpl as *const usize))
-            .field("restore_tpl (fn ptr)", &(self.restore_tpl as *const usize))
+            .field("header", &self.raw.header)
+            .field("raise_tpl (fn ptr)", &(self.raw.raise_tpl as *const usize))
+            .field(
+                "restore_tpl (fn ptr)",
+                &(self.raw.restore_tpl as *const usize),
+            )
             .field(
                 "allocate_pages (fn ptr)",
-                &(self.allocate_pages as *const usize),
+                &(self.raw.allocate_pages as *const usize),
+            )
+            .field(
+                "free_pages (fn ptr)",
+                &(self.raw.free_pages as *const usize),
             )
-            .field("free_pages (fn ptr)", &(self.free_pages as *const usize))
             .field(
                 "get_memory_map (fn ptr)",
-                &(self.get_memory_map as *const usize),
+                &(self.raw.get_memory_map as *const usize),
             )
             .field(
                 "allocate_pool (fn ptr)",
-                &(self.allocate_pool as *const usize),
+                &(self.raw.allocate_pool as *const usize),
             )
-            .field("free_pool (fn ptr)", &(self.free_pool as *const usize))
+            .field("free_pool (fn ptr)", &(self.raw.free_pool as *const usize))
             .field(
                 "create_event (fn ptr)",
-                &(self.create_event as *const usize),
+                &(self.raw.create_event as *const usize),
             )
-            .field("set_timer (fn ptr)", &(self.set_timer as *const usize))
+            .field("set_timer (fn ptr)", &(self.raw.set_timer as *const usize))
             .field(
                 "wait_for_event (fn ptr)",
-                &(self.wait_for_event as *const usize),
+                &(self.raw.wait_for_event as *const usize),
             )
-            .field("signal_event", &(self.signal_event as *const usize))
-            .field("close_event", &(self.close_event as *const usize))
-            .field("check_event", &(self.check_event as *const usize))
+            .field(
+                "signal_event (fn ptr)",
+                &(self.raw.signal_event as *const usize),
+            )
+            .field(
+                "close_event (fn ptr)",
+                &(self.raw.close_event as *const usize),
+            )
+            .field("check_event", &(self.raw.check_event as *const usize))
             .field(
                 "install_protocol_interface",
-                &(self.install_protocol_interface as *const usize),
+                &(self.raw.install_protocol_interface as *const usize),
             )
             .field(
                 "reinstall_protocol_interface",
-                &(self.reinstall_protocol_interface as *const usize),
+                &(self.raw.reinstall_protocol_interface as *const usize),
             )
             .field(
                 "uninstall_protocol_interface",
-                &(self.uninstall_protocol_interface as *const usize),
+                &(self.raw.uninstall_protocol_interface as *const usize),
             )
             .field(
                 "handle_protocol (fn ptr)",
-                &(self.handle_protocol as *const usize),
+                &(self.raw.handle_protocol as *const usize),
             )
             .field(
                 "register_protocol_notify",
-                &(self.register_protocol_notify as *const usize),
+                &(self.raw.register_protocol_notify as *const usize),
             )
             .field(
                 "locate_handle (fn ptr)",
-                &(self.locate_handle as *const usize),
+                &(self.raw.locate_handle as *const usize),
             )
             .field(
                 "locate_device_path (fn ptr)",
-                &(self.locate_device_path as *const usize),
+                &(self.raw.locate_device_path as *const usize),
+            )
+            .field(
+                "install_configuration_table (fn ptr)",
+                &(self.raw.install_configuration_table as *const usize),
+            )
+            .field(
+                "load_image (fn ptr)",
+                &(self.raw.load_image as *const usize),
             )
             .field(
-                "install_configuration_table",
-                &(self.install_configuration_table as *const usize),
+                "start_image (fn ptr)",
+                &(self.raw.start_image as *const usize),
             )
-            .field("load_image (fn ptr)", &(self.load_image as *const usize))
-            .field("start_image (fn ptr)", &(self.start_image as *const usize))
-            .field("exit", &(self.exit as *const usize))
+            .field("exit", &(self.raw.exit as *const usize))
             .field(
                 "unload_image (fn ptr)",
-                &(self.unload_image as *const usize),
+                &(self.raw.unload_image as *const usize),
             )
             .field(
                 "exit_boot_services (fn ptr)",
-                &(self.exit_boot_services as *const usize),
+                &(self.raw.exit_boot_services as *const usize),
             )
             .field(
                 "get_next_monotonic_count",
-                &(self.get_next_monotonic_count as *const usize),
+                &(self.raw.get_next_monotonic_count as *const usize),
             )
-            .field("stall (fn ptr)", &(self.stall as *const usize))
+            .field("stall (fn ptr)", &(self.raw.stall as *const usize))
             .field(
                 "set_watchdog_timer (fn ptr)",
-                &(self.set_watchdog_timer as *const usize),
+                &(self.raw.set_watchdog_timer as *const usize),
             )
             .field(
                 "connect_controller",
-                &(self.connect_controller as *const usize),
+                &self
+                    .raw
+                    .connect_controller
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
             .field(
                 "disconnect_controller",
-                &(self.disconnect_controller as *const usize),
+                &self
+                    .raw
+                    .disconnect_controller
+                    .map_or(ptr::null(), |f| f as *const usize),
+            )
+            .field(
+                "open_protocol",
+                &self
+                    .raw
+                    .open_protocol
+                    .map_or(ptr::null(), |f| f as *const usize),
+            )
+            .field(
+                "close_protocol",
+                &self
+                    .raw
+                    .close_protocol
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
-            .field("open_protocol", &(self.open_protocol as *const usize))
-            .field("close_protocol", &(self.close_protocol as *const usize))
             .field(
                 "open_protocol_information",
-                &(self.open_protocol_information as *const usize),
+                &self
+                    .raw
+                    .open_protocol_information
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
             .field(
                 "protocols_per_handle",
-                &(self.protocols_per_handle as *const usize),
+                &self
+                    .raw
+                    .protocols_per_handle
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
             .field(
                 "locate_handle_buffer",
-                &(self.locate_handle_buffer as *const usize),
+                &self
+                    .raw
+                    .locate_handle_buffer
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
             .field(
                 "locate_protocol (fn ptr)",
-                &(self.locate_protocol as *const usize),
+                &self
+                    .raw
+                    .locate_protocol
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
             .field(
                 "install_multiple_protocol_interfaces",
-                &(self.install_multiple_protocol_interfaces as *const usize),
+                &self
+                    .raw
+                    .install_multiple_protocol_interfaces
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
             .field(
                 "uninstall_multiple_protocol_interfaces",
-                &(self.uninstall_multiple_protocol_interfaces as *const usize),
+                &self
+                    .raw
+                    .uninstall_multiple_protocol_interfaces
+                    .map_or(ptr::null(), |f| f as *const usize),
+            )
+            .field(
+                "calculate_crc32 (fn ptr)",
+                &self
+                    .raw
+                    .calculate_crc32
+                    .map_or(ptr::null(), |f| f as *const usize),
+            )
+            .field(
+                "copy_mem (fn ptr)",
+                &self.raw.copy_mem.map_or(ptr::null(), |f| f as *const usize),
+            )
+            .field(
+                "set_mem (fn ptr)",
+                &self.raw.set_mem.map_or(ptr::null(), |f| f as *const usize),
+            )
+            .field(
+                "create_event_ex (fn ptr)",
+                &self
+                    .raw
+                    .create_event_ex
+                    .map_or(ptr::null(), |f| f as *const usize),
             )
-            .field("calculate_crc32", &(self.calculate_crc32 as *const usize))
-            .field("copy_mem (fn ptr)", &(self.copy_mem as *const usize))
-            .field("set_mem (fn ptr)", &(self.set_mem as *const usize))
-            .field("create_event_ex", &(self.create_event_ex as *const usize))
             .finish()
     }
 }
@@ -878,11 +1342,135 @@ pub struct TplGuard<'boot> {
 impl Drop for TplGuard<'_> {
     fn drop(&mut self) {
         unsafe {
-            (self.boot_services.restore_tpl)(self.old_tpl);
+            (self.boot_services.raw.restore_tpl)(self.old_tpl);
         }
     }
 }
 
+/// Alignment of the memory returned by `allocate_pool`
+const POOL_ALIGNMENT: usize = 8;
+
+/// A value stored in pool memory, which is freed when the box is dropped
+///
+/// Created by [`BootServices::allocate_pool_typed`].
+pub struct PoolBox<'boot, T> {
+    boot_services: &'boot BootServices,
+    ptr: *mut T,
+}
+
+impl<'boot, T> PoolBox<'boot, T> {
+    /// Consumes the box without freeing the memory, which must then be freed
+    /// with `free_pool` after dropping the value.
+    pub fn into_raw(self) -> *mut T {
+        let ptr = self.ptr;
+        mem::forget(self);
+        ptr
+    }
+}
+
+impl<T> Deref for PoolBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T> DerefMut for PoolBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl<T> Drop for PoolBox<'_, T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.ptr) };
+        if mem::size_of::<T>() != 0 {
+            // Ignore the result, we can't do anything about an error here.
+            let _ = self.boot_services.free_pool(self.ptr as *mut u8);
+        }
+    }
+}
+
+impl<T: Debug> Debug for PoolBox<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+/// An array stored in pool memory, which is freed when the slice is dropped
+///
+/// Created by [`BootServices::allocate_pool_slice`].
+pub struct PoolSlice<'boot, T> {
+    boot_services: &'boot BootServices,
+    ptr: *mut T,
+    len: usize,
+}
+
+impl<'boot, T> PoolSlice<'boot, T> {
+    /// Take ownership of an array which the firmware allocated from pool
+    /// memory for the caller.
+    ///
+    /// # Safety
+    ///
+    /// If `len` is not 0, `ptr` must point to `len` initialized values in
+    /// pool memory, which is not freed elsewhere.
+    pub(crate) unsafe fn from_raw(
+        boot_services: &'boot BootServices,
+        ptr: *mut T,
+        len: usize,
+    ) -> Self {
+        let ptr = if len == 0 {
+            ptr::NonNull::dangling().as_ptr()
+        } else {
+            ptr
+        };
+        PoolSlice {
+            boot_services,
+            ptr,
+            len,
+        }
+    }
+
+    /// Consumes the slice without freeing the memory, which must then be
+    /// freed with `free_pool` after dropping the values.
+    pub fn into_raw(self) -> *mut [T] {
+        let slice = ptr::slice_from_raw_parts_mut(self.ptr, self.len);
+        mem::forget(self);
+        slice
+    }
+}
+
+impl<T> Deref for PoolSlice<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> DerefMut for PoolSlice<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for PoolSlice<'_, T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.ptr, self.len)) };
+        if mem::size_of::<T>() != 0 && self.len != 0 {
+            // Ignore the result, we can't do anything about an error here.
+            let _ = self.boot_services.free_pool(self.ptr as *mut u8);
+        }
+    }
+}
+
+impl<T: Debug> Debug for PoolSlice<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
 /// Type of allocation to perform.
 #[derive(Debug, Copy, Clone)]
 pub enum AllocateType {
@@ -894,6 +1482,11 @@ pub enum AllocateType {
     Address(usize),
 }
 
+// Raw `EFI_ALLOCATE_TYPE` values, as expected by the `AllocatePages` service.
+const ALLOCATE_ANY_PAGES: u32 = 0;
+const ALLOCATE_MAX_ADDRESS: u32 = 1;
+const ALLOCATE_ADDRESS: u32 = 2;
+
 newtype_enum! {
 /// The type of a memory range.
 ///
@@ -938,20 +1531,53 @@ pub enum MemoryType: u32 => {
     PAL_CODE                = 13,
     /// Memory region which is usable and is also non-volatile.
     PERSISTENT_MEMORY       = 14,
+    /// Memory which must be accepted by the boot target before it can be used.
+    UNACCEPTED              = 15,
 }}
 
 impl MemoryType {
-    /// Construct a custom `MemoryType`. Values in the range `0x80000000..=0xffffffff` are free for use if you are
-    /// an OS loader.
+    /// First value of the range `0x70000000..=0x7fffffff`, which is reserved for
+    /// OEM-defined memory types.
+    pub const OEM_RANGE_START: u32 = 0x7000_0000;
+
+    /// First value of the range `0x80000000..=0xffffffff`, which is reserved for
+    /// memory types defined by OS loaders and operating systems.
+    pub const OS_RANGE_START: u32 = 0x8000_0000;
+
+    /// Construct a custom `MemoryType`.
+    ///
+    /// Values in the range `0x70000000..=0x7fffffff` are reserved for OEMs, and
+    /// values in the range `0x80000000..=0xffffffff` are free for use if you are
+    /// an OS loader. Any other value will cause a panic, since it would clash
+    /// with a memory type defined by the UEFI specification.
     pub const fn custom(value: u32) -> MemoryType {
-        assert!(value >= 0x80000000);
+        assert!(value >= Self::OEM_RANGE_START);
         MemoryType(value)
     }
+
+    /// Returns true if this memory type lies in the OEM-defined range.
+    pub const fn is_oem_defined(self) -> bool {
+        self.0 >= Self::OEM_RANGE_START && self.0 < Self::OS_RANGE_START
+    }
+
+    /// Returns true if this memory type lies in the OS-defined range.
+    pub const fn is_os_defined(self) -> bool {
+        self.0 >= Self::OS_RANGE_START
+    }
 }
 
 /// Memory descriptor version number
 pub const MEMORY_DESCRIPTOR_VERSION: u32 = 1;
 
+/// Size of the pages in which UEFI allocates and describes memory.
+pub const PAGE_SIZE: usize = 4096;
+
+/// Returns the number of pages needed to hold `size` bytes.
+pub const fn size_to_pages(size: usize) -> usize {
+    // Rounding up by adding `PAGE_SIZE - 1` would overflow for huge sizes
+    size / PAGE_SIZE + (size % PAGE_SIZE != 0) as usize
+}
+
 /// A structure describing a region of memory.
 #[derive(Debug, Copy, Clone)]
 #[repr(C)]
@@ -983,6 +1609,18 @@ impl Default for MemoryDescriptor {
     }
 }
 
+impl MemoryDescriptor {
+    /// Returns true if the range is persistent memory, either because of its
+    /// type or because it is marked as non-volatile.
+    ///
+    /// Such ranges are backed by NVDIMMs, whose content survives reboots, and
+    /// must be reserved for the pmem drivers of the OS rather than used as
+    /// RAM.
+    pub fn is_persistent(&self) -> bool {
+        self.ty == MemoryType::PERSISTENT_MEMORY || self.att.contains(MemoryAttribute::NON_VOLATILE)
+    }
+}
+
 impl Align for MemoryDescriptor {
     fn alignment() -> usize {
         mem::align_of::<Self>()
@@ -1015,6 +1653,16 @@ bitflags! {
         const MORE_RELIABLE = 0x10000;
         /// This memory range can be set as read-only.
         const READ_ONLY = 0x20000;
+        /// This memory is earmarked for specific purposes, such as for
+        /// specific device drivers or applications.
+        const SPECIAL_PURPOSE = 0x40000;
+        /// This memory region is capable of being protected with the CPU's
+        /// memory cryptographic capabilities.
+        const CPU_CRYPTO = 0x80000;
+        /// The bits in `ISA_MASK` hold ISA-specific cacheability attributes.
+        const ISA_VALID = 0x4000_0000_0000_0000;
+        /// Mask of the bits reserved for ISA-specific cacheability attributes.
+        const ISA_MASK = 0x0FFF_F000_0000_0000;
         /// This memory must be mapped by the OS when a runtime service is called.
         const RUNTIME = 0x8000_0000_0000_0000;
     }
@@ -1062,6 +1710,269 @@ impl<'buf> Iterator for MemoryMapIter<'buf> {
 
 impl ExactSizeIterator for MemoryMapIter<'_> {}
 
+/// An owned copy of the UEFI memory map.
+///
+/// This is returned by `BootServices::memory_map_owned`, and provides the
+/// post-processing operations that OS loaders typically need before handing
+/// the memory map over to a kernel.
+#[cfg(feature = "exts")]
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    key: MemoryMapKey,
+    descriptors: Vec<MemoryDescriptor>,
+}
+
+#[cfg(feature = "exts")]
+impl MemoryMap {
+    /// The key of the memory map at the time it was retrieved.
+    ///
+    /// This is only valid as long as the firmware's memory map did not change.
+    pub fn key(&self) -> MemoryMapKey {
+        self.key
+    }
+
+    /// The memory descriptors making up this memory map.
+    pub fn entries(&self) -> &[MemoryDescriptor] {
+        &self.descriptors
+    }
+
+    /// Number of descriptors in the memory map.
+    pub fn len(&self) -> usize {
+        self.descriptors.len()
+    }
+
+    /// Returns true if the memory map contains no descriptor.
+    pub fn is_empty(&self) -> bool {
+        self.descriptors.is_empty()
+    }
+
+    /// Sorts the descriptors by ascending physical address.
+    pub fn sort(&mut self) {
+        self.descriptors
+            .sort_unstable_by_key(|desc| desc.phys_start);
+    }
+
+    /// Merges physically contiguous descriptors which have the same type
+    /// and attributes into a single descriptor.
+    ///
+    /// The memory map is sorted first. The virtual address of a merged
+    /// descriptor is the one of its lowest part, so this should only be used
+    /// before a virtual address map has been set up.
+    pub fn merge(&mut self) {
+        self.sort();
+        self.descriptors.dedup_by(|next, prev| {
+            let prev_end = prev.phys_start + prev.page_count * PAGE_SIZE as u64;
+            let mergeable =
+                next.ty == prev.ty && next.att == prev.att && next.phys_start == prev_end;
+            if mergeable {
+                prev.page_count += next.page_count;
+            }
+            mergeable
+        });
+    }
+
+    /// Iterates over the descriptors of free, conventional memory.
+    pub fn conventional(&self) -> impl Iterator<Item = &MemoryDescriptor> + Clone {
+        self.descriptors
+            .iter()
+            .filter(|desc| desc.ty == MemoryType::CONVENTIONAL)
+    }
+
+    /// Iterates over the descriptors of persistent memory.
+    pub fn persistent(&self) -> impl Iterator<Item = &MemoryDescriptor> + Clone {
+        self.descriptors.iter().filter(|desc| desc.is_persistent())
+    }
+
+    /// Total number of pages of conventional memory.
+    pub fn conventional_pages(&self) -> u64 {
+        self.conventional().map(|desc| desc.page_count).sum()
+    }
+
+    /// Total amount of conventional memory, in bytes.
+    pub fn conventional_bytes(&self) -> u64 {
+        self.conventional_pages() * PAGE_SIZE as u64
+    }
+
+    /// Number of pages of each memory type, in the order in which the types
+    /// first appear in the memory map.
+    pub fn pages_by_type(&self) -> Vec<(MemoryType, u64)> {
+        let mut pages: Vec<(MemoryType, u64)> = Vec::new();
+        for desc in &self.descriptors {
+            match pages.iter_mut().find(|(ty, _)| *ty == desc.ty) {
+                Some((_, count)) => *count += desc.page_count,
+                None => pages.push((desc.ty, desc.page_count)),
+            }
+        }
+        pages
+    }
+
+    /// The descriptor of the page which holds the physical address `addr`
+    pub fn descriptor_of(&self, addr: u64) -> Option<&MemoryDescriptor> {
+        self.descriptors
+            .iter()
+            .find(|desc| addr >= desc.phys_start && addr - desc.phys_start < desc_size(desc))
+    }
+
+    /// The type of the memory at the physical address `addr`, or `None` if
+    /// the memory map does not describe it, as for most MMIO regions
+    pub fn type_of(&self, addr: u64) -> Option<MemoryType> {
+        self.descriptor_of(addr).map(|desc| desc.ty)
+    }
+
+    /// Returns true if the `len` bytes at the physical address `addr` are
+    /// all described by the memory map, whatever their type.
+    ///
+    /// An empty range is checked as the byte at `addr`.
+    pub fn owns(&self, addr: u64, len: u64) -> bool {
+        self.covers(addr, len, |_| true)
+    }
+
+    /// Returns true if the `len` bytes at the physical address `addr` are
+    /// all memory of type `ty`, possibly spanning several descriptors.
+    pub fn is_of_type(&self, addr: u64, len: u64, ty: MemoryType) -> bool {
+        self.covers(addr, len, |desc| desc.ty == ty)
+    }
+
+    /// Returns true if the `len` bytes at the physical address `addr` are
+    /// all free, conventional memory.
+    ///
+    /// This should be checked before a range which the caller provides is
+    /// used for DMA or to place a kernel, since writing to the memory of the
+    /// runtime services, of ACPI or of a device corrupts it silently.
+    pub fn is_conventional(&self, addr: u64, len: u64) -> bool {
+        self.is_of_type(addr, len, MemoryType::CONVENTIONAL)
+    }
+
+    /// Whether descriptors selected by `filter` cover the range, which the
+    /// descriptors may split at any page
+    fn covers(&self, addr: u64, len: u64, filter: impl Fn(&MemoryDescriptor) -> bool) -> bool {
+        // Inclusive bounds, so that the range may reach the end of the
+        // address space
+        let last = match addr.checked_add(len.max(1) - 1) {
+            Some(last) => last,
+            None => return false,
+        };
+        let mut next = addr;
+        loop {
+            let desc = match self.descriptor_of(next) {
+                Some(desc) if filter(desc) => desc,
+                _ => return false,
+            };
+            // `descriptor_of` only returns descriptors which are not empty,
+            // and those which overflow end with the address space
+            let desc_last = desc.phys_start.saturating_add(desc_size(desc) - 1);
+            if desc_last >= last {
+                return true;
+            }
+            next = desc_last + 1;
+        }
+    }
+
+    /// Compare this memory map to one retrieved earlier, to find the memory
+    /// which was allocated or freed in between.
+    ///
+    /// This is meant for leak checks: taking the memory map before and after
+    /// an operation shows the pages which it left allocated. Pool
+    /// allocations only show up when the pool grows by whole pages.
+    pub fn diff(&self, before: &MemoryMap) -> MemoryMapDiff {
+        let mut pages: Vec<(MemoryType, i64)> = Vec::new();
+        let mut count = |ty, delta| match pages.iter_mut().find(|(t, _)| *t == ty) {
+            Some((_, total)) => *total += delta,
+            None => pages.push((ty, delta)),
+        };
+        for (ty, count_before) in before.pages_by_type() {
+            count(ty, -(count_before as i64));
+        }
+        for (ty, count_after) in self.pages_by_type() {
+            count(ty, count_after as i64);
+        }
+        pages.retain(|(_, delta)| *delta != 0);
+
+        let is_free = |desc: &MemoryDescriptor| desc.ty == MemoryType::CONVENTIONAL;
+        MemoryMapDiff {
+            pages,
+            allocated: changed_ranges(self, before, |desc| !is_free(desc)),
+            freed: changed_ranges(self, before, is_free),
+        }
+    }
+}
+
+/// Size of the memory of a descriptor, in bytes
+#[cfg(feature = "exts")]
+fn desc_size(desc: &MemoryDescriptor) -> u64 {
+    desc.page_count.saturating_mul(PAGE_SIZE as u64)
+}
+
+/// The parts of the descriptors of `after` selected by `filter` which are not
+/// covered by a descriptor of the same type in `before`
+#[cfg(feature = "exts")]
+fn changed_ranges(
+    after: &MemoryMap,
+    before: &MemoryMap,
+    filter: impl Fn(&MemoryDescriptor) -> bool,
+) -> Vec<MemoryDescriptor> {
+    let end = |desc: &MemoryDescriptor| desc.phys_start + desc.page_count * PAGE_SIZE as u64;
+    let mut changed = Vec::new();
+    for desc in after.descriptors.iter().filter(|desc| filter(desc)) {
+        let mut same: Vec<&MemoryDescriptor> = before
+            .descriptors
+            .iter()
+            .filter(|old| {
+                old.ty == desc.ty && old.phys_start < end(desc) && end(old) > desc.phys_start
+            })
+            .collect();
+        same.sort_unstable_by_key(|old| old.phys_start);
+
+        // Emit the gaps between the old descriptors
+        let mut start = desc.phys_start;
+        let mut emit = |from: u64, to: u64| {
+            if from < to {
+                let mut part = *desc;
+                part.phys_start = from;
+                part.virt_start = desc.virt_start + (from - desc.phys_start);
+                part.page_count = (to - from) / PAGE_SIZE as u64;
+                changed.push(part);
+            }
+        };
+        for old in same {
+            emit(start, old.phys_start);
+            start = start.max(end(old));
+        }
+        emit(start, end(desc));
+    }
+    changed
+}
+
+/// The changes between two memory maps, returned by `MemoryMap::diff`
+#[cfg(feature = "exts")]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMapDiff {
+    /// Change of the number of pages of each memory type, for the types
+    /// whose number of pages changed.
+    pub pages: Vec<(MemoryType, i64)>,
+    /// Memory ranges which have a type other than conventional memory, and
+    /// had another type before.
+    pub allocated: Vec<MemoryDescriptor>,
+    /// Memory ranges which are conventional memory, and were not before.
+    pub freed: Vec<MemoryDescriptor>,
+}
+
+#[cfg(feature = "exts")]
+impl MemoryMapDiff {
+    /// Whether the memory maps are the same
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty() && self.allocated.is_empty() && self.freed.is_empty()
+    }
+
+    /// Change of the number of pages of memory of type `ty`
+    pub fn pages_of(&self, ty: MemoryType) -> i64 {
+        self.pages
+            .iter()
+            .find(|(t, _)| *t == ty)
+            .map_or(0, |(_, delta)| *delta)
+    }
+}
+
 /// The type of handle search to perform.
 #[derive(Debug, Copy, Clone)]
 pub enum SearchType<'guid> {
@@ -1082,6 +1993,15 @@ impl<'guid> SearchType<'guid> {
     }
 }
 
+newtype_enum! {
+/// Type of a protocol interface installed with
+/// `BootServices::install_protocol_interface`
+pub enum InterfaceType: u32 => {
+    /// An interface using the native calling convention of the platform, the
+    /// only type defined so far.
+    NATIVE_INTERFACE = 0,
+}}
+
 bitflags! {
     /// Flags describing the type of an UEFI event and its attributes.
     pub struct EventType: u32 {
@@ -1115,8 +2035,51 @@ bitflags! {
     }
 }
 
-/// Raw event notification function
-type EventNotifyFn = unsafe extern "efiapi" fn(event: Event, context: *mut c_void);
+/// The raw notification function and context which call `notify_fn`
+fn notify_parts(notify_fn: Option<fn(Event)>) -> (Option<EventNotifyFn>, *mut c_void) {
+    // Use a trampoline to handle the impedance mismatch between Rust & C
+    unsafe extern "efiapi" fn notify_trampoline(e: Event, ctx: *mut c_void) {
+        let notify_fn: fn(Event) = mem::transmute(ctx);
+        notify_fn(e); // SAFETY: Aborting panics are assumed here
+    }
+    notify_fn
+        .map(|notify_fn| {
+            (
+                Some(notify_trampoline as EventNotifyFn),
+                notify_fn as fn(Event) as *mut c_void,
+            )
+        })
+        .unwrap_or((None, ptr::null_mut()))
+}
+
+/// GUIDs of the event groups signaled by the firmware, for
+/// `BootServices::create_event_ex`
+pub mod event_group {
+    use crate::Guid;
+
+    /// Signaled by `ExitBootServices`, after `BEFORE_EXIT_BOOT_SERVICES`.
+    pub const EXIT_BOOT_SERVICES: Guid =
+        Guid::from_values(0x27abf055, 0xb1b8, 0x4c26, 0x8048, 0x748f37baa2df);
+    /// Signaled by `ExitBootServices`, while boot services may still be used.
+    pub const BEFORE_EXIT_BOOT_SERVICES: Guid =
+        Guid::from_values(0x8be0e274, 0x3970, 0x4b44, 0x80c5, 0x1ab9502f3bfc);
+    /// Signaled by `SetVirtualAddressMap`.
+    pub const VIRTUAL_ADDRESS_CHANGE: Guid =
+        Guid::from_values(0x13fa7698, 0xc831, 0x49c7, 0x87ea, 0x8f43fcc25196);
+    /// Signaled when the memory map changes.
+    pub const MEMORY_MAP_CHANGE: Guid =
+        Guid::from_values(0x78bee926, 0x692f, 0x48fd, 0x9edb, 0x01422ef0d7ab);
+    /// Signaled by the boot manager before it starts a boot option.
+    pub const READY_TO_BOOT: Guid =
+        Guid::from_values(0x7ce88fb3, 0x4bd7, 0x4679, 0x87a8, 0xa8d8dee50d2b);
+    /// Signaled by the boot manager after `READY_TO_BOOT`, once its
+    /// notification functions have run.
+    pub const AFTER_READY_TO_BOOT: Guid =
+        Guid::from_values(0x3a2a00ad, 0x98b9, 0x4cdf, 0xa478, 0x702777f1c10b);
+    /// Signaled by `ResetSystem`, before the platform is reset.
+    pub const RESET_SYSTEM: Guid =
+        Guid::from_values(0x62da6a56, 0x13fb, 0x485a, 0xa8da, 0xa3dd7912cb6b);
+}
 
 /// Timer events manipulation
 pub enum TimerTrigger {
@@ -1161,3 +2124,103 @@ impl<'a> ProtocolsPerHandle<'a> {
         self.protocols
     }
 }
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages() {
+        assert_eq!(size_to_pages(0), 0);
+        assert_eq!(size_to_pages(1), 1);
+        assert_eq!(size_to_pages(PAGE_SIZE), 1);
+        assert_eq!(size_to_pages(PAGE_SIZE + 1), 2);
+        assert_eq!(size_to_pages(usize::MAX), usize::MAX / PAGE_SIZE + 1);
+    }
+
+    fn desc(ty: MemoryType, phys_start: u64, page_count: u64) -> MemoryDescriptor {
+        MemoryDescriptor {
+            ty,
+            phys_start,
+            virt_start: phys_start,
+            page_count,
+            ..MemoryDescriptor::default()
+        }
+    }
+
+    fn map(descriptors: &[MemoryDescriptor]) -> MemoryMap {
+        MemoryMap {
+            key: MemoryMapKey(0),
+            descriptors: descriptors.to_vec(),
+        }
+    }
+
+    #[test]
+    fn memory_map_diff() {
+        let before = map(&[
+            desc(MemoryType::CONVENTIONAL, 0x10_0000, 16),
+            desc(MemoryType::LOADER_DATA, 0x11_0000, 2),
+            desc(MemoryType::LOADER_CODE, 0x12_0000, 2),
+        ]);
+        // 4 pages were allocated in the middle of the free memory, the first
+        // loader page was freed, and the loader code changed type
+        let after = map(&[
+            desc(MemoryType::CONVENTIONAL, 0x10_0000, 4),
+            desc(MemoryType::BOOT_SERVICES_DATA, 0x10_4000, 4),
+            desc(MemoryType::CONVENTIONAL, 0x10_8000, 9),
+            desc(MemoryType::LOADER_DATA, 0x11_1000, 1),
+            desc(MemoryType::BOOT_SERVICES_CODE, 0x12_0000, 2),
+        ]);
+        let diff = after.diff(&before);
+        assert_eq!(diff.pages_of(MemoryType::CONVENTIONAL), -3);
+        assert_eq!(diff.pages_of(MemoryType::BOOT_SERVICES_DATA), 4);
+        assert_eq!(diff.pages_of(MemoryType::LOADER_DATA), -1);
+        assert_eq!(diff.pages_of(MemoryType::LOADER_CODE), -2);
+        assert_eq!(diff.pages_of(MemoryType::BOOT_SERVICES_CODE), 2);
+        assert_eq!(diff.pages_of(MemoryType::ACPI_NON_VOLATILE), 0);
+
+        assert_eq!(diff.allocated.len(), 2);
+        assert_eq!(diff.allocated[0].phys_start, 0x10_4000);
+        assert_eq!(diff.allocated[0].page_count, 4);
+        assert_eq!(diff.allocated[1].ty, MemoryType::BOOT_SERVICES_CODE);
+        assert_eq!(diff.allocated[1].phys_start, 0x12_0000);
+        assert_eq!(diff.allocated[1].page_count, 2);
+        assert_eq!(diff.freed.len(), 1);
+        assert_eq!(diff.freed[0].phys_start, 0x11_0000);
+        assert_eq!(diff.freed[0].page_count, 1);
+
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn address_ranges() {
+        let map = map(&[
+            desc(MemoryType::CONVENTIONAL, 0x10_2000, 2),
+            desc(MemoryType::CONVENTIONAL, 0x10_0000, 2),
+            desc(MemoryType::RUNTIME_SERVICES_DATA, 0x10_4000, 1),
+        ]);
+        assert_eq!(map.type_of(0x10_1fff), Some(MemoryType::CONVENTIONAL));
+        assert_eq!(
+            map.type_of(0x10_4000),
+            Some(MemoryType::RUNTIME_SERVICES_DATA)
+        );
+        assert_eq!(map.type_of(0x10_5000), None);
+        assert_eq!(map.descriptor_of(0x10_3000).unwrap().phys_start, 0x10_2000);
+
+        // Ranges may span several descriptors, in any order
+        assert!(map.is_conventional(0x10_0800, 0x3000));
+        assert!(map.is_conventional(0x10_3fff, 0));
+        assert!(!map.is_conventional(0x10_3000, 0x1001));
+        assert!(map.is_of_type(0x10_4000, 0x1000, MemoryType::RUNTIME_SERVICES_DATA));
+        assert!(map.owns(0x10_0000, 0x5000));
+        assert!(!map.owns(0x10_0000, 0x5001));
+        assert!(!map.owns(0xf_f000, 0x2000));
+        assert!(!map.owns(u64::MAX, 2));
+
+        // The last descriptor may end at the top of the address space
+        let top = self::map(&[desc(MemoryType::RESERVED, u64::MAX - 0x1fff, 2)]);
+        assert!(top.owns(u64::MAX - 0x1fff, 0x2000));
+        assert!(top.is_of_type(u64::MAX, 0, MemoryType::RESERVED));
+        assert!(!top.owns(u64::MAX, 2));
+    }
+}
@@ -0,0 +1,222 @@
+//! Capsule updates delivered on disk, and the `OsIndications` variable.
+//!
+//! Instead of passing a capsule to `UpdateCapsule()`, which needs the capsule
+//! to be in memory when the system resets, a firmware update tool can write
+//! it to the `\EFI\UpdateCapsule` directory of the EFI system partition and
+//! set a bit of `OsIndications`. The firmware then processes the capsules of
+//! that directory at the next boot, and deletes them:
+//!
+//! ```no_run
+//! # use core::convert::TryFrom;
+//! # use uefi::prelude::*;
+//! # use uefi::proto::media::file::Directory;
+//! # use uefi::table::capsule;
+//! # fn f(rt: &RuntimeServices, esp: &mut Directory, capsule: &[u8]) -> uefi::Result {
+//! let name = uefi::CString16::try_from("firmware.cap").unwrap();
+//! capsule::stage_capsule_on_disk(rt, esp, &name, capsule)?.log();
+//! rt.reset(uefi::table::runtime::ResetType::Warm, Status::SUCCESS, None);
+//! # }
+//! ```
+
+use super::load_option::{global_variables, variable_name};
+use super::runtime::RuntimeServices;
+#[cfg(feature = "exts")]
+use crate::proto::media::file::Directory;
+#[cfg(feature = "exts")]
+use crate::raw::runtime::CapsuleHeader;
+#[cfg(feature = "exts")]
+use crate::{CStr16, Status};
+use crate::{Completion, Result};
+use bitflags::bitflags;
+#[cfg(feature = "exts")]
+use core::convert::TryFrom;
+#[cfg(feature = "exts")]
+use core::mem;
+
+bitflags! {
+    /// Features of the firmware which the OS may use, listed in
+    /// `OsIndicationsSupported`, and requests to the firmware for the next
+    /// boot, in `OsIndications`.
+    pub struct OsIndications: u64 {
+        /// Stop in the setup menu of the firmware.
+        const BOOT_TO_FW_UI = 0x0000_0001;
+        /// Timestamp based revocation of certificates is supported.
+        const TIMESTAMP_REVOCATION = 0x0000_0002;
+        /// Process the capsules of the `\EFI\UpdateCapsule` directory.
+        const FILE_CAPSULE_DELIVERY_SUPPORTED = 0x0000_0004;
+        /// Firmware management protocol capsules are supported.
+        const FMP_CAPSULE_SUPPORTED = 0x0000_0008;
+        /// The results of capsules are reported in `Capsule####` variables.
+        const CAPSULE_RESULT_VAR_SUPPORTED = 0x0000_0010;
+        /// Start the OS recovery options, from `OsRecoveryOrder`.
+        const START_OS_RECOVERY = 0x0000_0020;
+        /// Start the platform recovery options.
+        const START_PLATFORM_RECOVERY = 0x0000_0040;
+        /// Collect the current configuration in a JSON capsule.
+        const JSON_CONFIG_DATA_REFRESH = 0x0000_0080;
+    }
+}
+
+/// Directory of the EFI system partition from which the firmware loads the
+/// capsules, without its leading backslash
+pub const CAPSULE_DIRECTORY: &str = "EFI\\UpdateCapsule";
+
+/// The indications which the firmware supports, from `OsIndicationsSupported`
+///
+/// Bits which this crate does not know of are dropped. Firmware which lacks
+/// the variable supports none of the indications.
+pub fn os_indications_supported(rt: &RuntimeServices) -> Result<OsIndications> {
+    read_indications(rt, b"OsIndicationsSupported\0")
+}
+
+/// The indications requested for the next boot, from `OsIndications`
+pub fn os_indications(rt: &RuntimeServices) -> Result<OsIndications> {
+    read_indications(rt, b"OsIndications\0")
+}
+
+/// Request `indications` for the next boot, replacing all of the bits of
+/// `OsIndications`
+pub fn set_os_indications(rt: &RuntimeServices, indications: OsIndications) -> Result {
+    let mut buf = [0; 32];
+    let name = variable_name(b"OsIndications\0", &mut buf);
+    global_variables(rt).set_u64(name, indications.bits())
+}
+
+/// Whether the firmware processes the capsules of `CAPSULE_DIRECTORY`
+pub fn supports_capsule_on_disk(rt: &RuntimeServices) -> Result<bool> {
+    let supported = os_indications_supported(rt)?;
+    Ok(supported
+        .map(|supported| supported.contains(OsIndications::FILE_CAPSULE_DELIVERY_SUPPORTED)))
+}
+
+fn read_indications(rt: &RuntimeServices, name: &[u8]) -> Result<OsIndications> {
+    let mut buf = [0; 32];
+    let name = variable_name(name, &mut buf);
+    let (status, bits) = global_variables(rt).get_u64(name)?.split();
+    Ok(Completion::new(
+        status,
+        OsIndications::from_bits_truncate(bits.unwrap_or(0)),
+    ))
+}
+
+/// Stage `capsule` for the firmware to process it at the next boot
+///
+/// The capsule is written as the file `name` of `CAPSULE_DIRECTORY`, which is
+/// created as needed in `esp`, the root directory of the EFI system
+/// partition, and `FILE_CAPSULE_DELIVERY_SUPPORTED` is added to the
+/// indications. The system must then be reset for the capsule to take
+/// effect.
+///
+/// # Errors
+///
+/// * `uefi::Status::UNSUPPORTED`        The firmware does not process the
+///                                      capsules on disk
+/// * `uefi::Status::INVALID_PARAMETER`  `name` is not a plain file name, or
+///                                      `capsule` does not start with a
+///                                      capsule header of its size
+/// * Any error of `Directory::create_dir_all`, `Directory::write_file`, and
+///   of reading and setting variables
+#[cfg(feature = "exts")]
+pub fn stage_capsule_on_disk(
+    rt: &RuntimeServices,
+    esp: &mut Directory,
+    name: &CStr16,
+    capsule: &[u8],
+) -> Result {
+    if !supports_capsule_on_disk(rt)?.log() {
+        return Err(Status::UNSUPPORTED.into());
+    }
+    let name_chars = name.to_u16_slice();
+    if name_chars.is_empty() || name_chars.contains(&u16::from(b'\\')) {
+        return Err(Status::INVALID_PARAMETER.into());
+    }
+    check_capsule(capsule)?.log();
+
+    let directory = crate::CString16::try_from(CAPSULE_DIRECTORY).unwrap();
+    let mut directory = esp.create_dir_all(&directory)?.log();
+    directory.write_file(name, capsule)?.log();
+
+    // The other requests, including those this crate does not know of, are
+    // kept
+    let mut buf = [0; 32];
+    let name = variable_name(b"OsIndications\0", &mut buf);
+    let store = global_variables(rt);
+    let bits = store.get_u64(name)?.log().unwrap_or(0);
+    store.set_u64(
+        name,
+        bits | OsIndications::FILE_CAPSULE_DELIVERY_SUPPORTED.bits(),
+    )
+}
+
+/// Check that `capsule` starts with a header which gives its size
+#[cfg(feature = "exts")]
+fn check_capsule(capsule: &[u8]) -> Result {
+    if capsule.len() < mem::size_of::<CapsuleHeader>() {
+        return Err(Status::INVALID_PARAMETER.into());
+    }
+    let header = unsafe { (capsule.as_ptr() as *const CapsuleHeader).read_unaligned() };
+    let header_size = header.header_size as usize;
+    let image_size = header.capsule_image_size as usize;
+    if header_size < mem::size_of::<CapsuleHeader>()
+        || header_size > image_size
+        || image_size != capsule.len()
+    {
+        return Err(Status::INVALID_PARAMETER.into());
+    }
+    Status::SUCCESS.into()
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::vec::Vec;
+    use crate::ResultExt;
+
+    #[test]
+    fn indications() {
+        let st = crate::mock::MockSystemTable::new().system_table();
+        let rt = st.runtime_services();
+        assert!(os_indications_supported(rt).unwrap_success().is_empty());
+        assert!(!supports_capsule_on_disk(rt).unwrap_success());
+
+        let mut buf = [0; 32];
+        let name = variable_name(b"OsIndicationsSupported\0", &mut buf);
+        let supported =
+            OsIndications::BOOT_TO_FW_UI | OsIndications::FILE_CAPSULE_DELIVERY_SUPPORTED;
+        // Unknown bits are dropped
+        global_variables(rt)
+            .set_u64(name, supported.bits() | 1 << 63)
+            .unwrap_success();
+        assert_eq!(os_indications_supported(rt).unwrap_success(), supported);
+        assert!(supports_capsule_on_disk(rt).unwrap_success());
+
+        set_os_indications(rt, OsIndications::BOOT_TO_FW_UI).unwrap_success();
+        assert_eq!(
+            os_indications(rt).unwrap_success(),
+            OsIndications::BOOT_TO_FW_UI
+        );
+    }
+
+    #[test]
+    fn capsule_header() {
+        let mut capsule = Vec::new();
+        capsule.extend_from_slice(&[0; 16]);
+        capsule.extend_from_slice(&28u32.to_le_bytes());
+        capsule.extend_from_slice(&0u32.to_le_bytes());
+        capsule.extend_from_slice(&32u32.to_le_bytes());
+        capsule.extend_from_slice(b"data");
+        assert_eq!(check_capsule(&capsule).status(), Status::SUCCESS);
+
+        // The size does not match the data
+        assert_eq!(
+            check_capsule(&capsule[..30]).status(),
+            Status::INVALID_PARAMETER
+        );
+        assert_eq!(
+            check_capsule(&capsule[..20]).status(),
+            Status::INVALID_PARAMETER
+        );
+        capsule[16] = 40;
+        assert_eq!(check_capsule(&capsule).status(), Status::INVALID_PARAMETER);
+    }
+}
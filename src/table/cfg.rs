@@ -9,6 +9,7 @@
 
 #![allow(clippy::unreadable_literal)]
 
+use super::runtime::RtService;
 use crate::Guid;
 use bitflags::bitflags;
 use core::ffi::c_void;
@@ -70,6 +71,57 @@ bitflags! {
     }
 }
 
+/// GUID of the runtime properties table.
+pub const RT_PROPERTIES_TABLE_GUID: Guid =
+    Guid::from_values(0xeb66918a, 0x7eef, 0x402a, 0x842e, 0x931d21c38ae9);
+
+/// This table lists the runtime services which the firmware still supports
+/// after boot services are exited.
+///
+/// `RuntimeServices::supports` tells which ones are, once they are exited.
+#[derive(Debug)]
+#[repr(C)]
+pub struct RtPropertiesTable {
+    /// Version of the runtime properties table.
+    ///
+    /// The only valid version currently is 1.
+    pub version: u16,
+    /// Length in bytes of this table.
+    ///
+    /// The initial version's length is 8.
+    pub length: u16,
+    /// The runtime services which are supported.
+    pub runtime_services_supported: RtService,
+}
+
+impl RtPropertiesTable {
+    /// The version of the table defined by UEFI 2.8
+    pub const VERSION: u16 = 1;
+
+    /// Find the runtime properties table in the configuration table, or
+    /// return `None` if the firmware does not publish one of a known
+    /// version.
+    ///
+    /// # Safety
+    ///
+    /// The configuration table must be the one of the system table, and
+    /// boot services must not be exited, since the table may be stored in
+    /// boot services memory.
+    pub unsafe fn find(config_table: &[ConfigTableEntry]) -> Option<&Self> {
+        let entry = config_table
+            .iter()
+            .find(|entry| entry.guid == RT_PROPERTIES_TABLE_GUID)?;
+        let table = &*(entry.address as *const Self);
+        let known = table.version == Self::VERSION
+            && usize::from(table.length) >= core::mem::size_of::<Self>();
+        if known {
+            Some(table)
+        } else {
+            None
+        }
+    }
+}
+
 /// Hand-off Blocks are used to pass data from the early pre-UEFI environment to the UEFI drivers.
 ///
 /// Most OS loaders or applications should not mess with this.
@@ -100,3 +152,42 @@ pub const TIANO_COMPRESS_GUID: Guid =
 /// Pointer to the debug image info table.
 pub const DEBUG_IMAGE_INFO_GUID: Guid =
     Guid::from_values(0x49152e77, 0x1ada, 0x4764, 0xb7a2, 0x7afefed95e8b);
+
+/// Flattened device tree (DTB) describing the hardware, on ARM and RISC-V.
+pub const DEVICE_TREE_GUID: Guid =
+    Guid::from_values(0xb1b621d5, 0xf19c, 0x41a5, 0x830b, 0xd9152c69aae0);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rt_properties_table() {
+        let mut table = RtPropertiesTable {
+            version: 1,
+            length: 8,
+            runtime_services_supported: RtService::GET_TIME | RtService::RESET_SYSTEM,
+        };
+        let entry = |guid, table: &RtPropertiesTable| ConfigTableEntry {
+            guid,
+            address: table as *const RtPropertiesTable as *const c_void,
+        };
+
+        let config_table = [
+            entry(ACPI2_GUID, &table),
+            entry(RT_PROPERTIES_TABLE_GUID, &table),
+        ];
+        let found = unsafe { RtPropertiesTable::find(&config_table) }.unwrap();
+        assert!(found
+            .runtime_services_supported
+            .contains(RtService::RESET_SYSTEM));
+        assert!(!found
+            .runtime_services_supported
+            .contains(RtService::SET_VARIABLE));
+
+        table.version = 2;
+        let config_table = [entry(RT_PROPERTIES_TABLE_GUID, &table)];
+        assert!(unsafe { RtPropertiesTable::find(&config_table) }.is_none());
+        assert!(unsafe { RtPropertiesTable::find(&[]) }.is_none());
+    }
+}
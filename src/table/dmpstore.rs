@@ -0,0 +1,325 @@
+//! Backup and restore of UEFI variables, in the format of the EDK2 shell.
+//!
+//! The `dmpstore -s` command of the EDK2 shell saves variables to a file,
+//! which `dmpstore -l` loads back. The file is a plain sequence of records,
+//! one per variable, with every integer stored in little endian:
+//!
+//! | Field        | Size          |
+//! |--------------|---------------|
+//! | `NameSize`   | 4             |
+//! | `DataSize`   | 4             |
+//! | `Name`       | `NameSize`    |
+//! | `VendorGuid` | 16            |
+//! | `Attributes` | 4             |
+//! | `Data`       | `DataSize`    |
+//! | `Crc32`      | 4             |
+//!
+//! The name is a null-terminated UCS-2 string, and its size includes the
+//! terminator. The CRC covers all the preceding fields of the record.
+//!
+//! The helpers of this module produce and consume the same data, so backups
+//! can be exchanged with the shell:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::table::dmpstore;
+//! # fn f(rt: &RuntimeServices) -> uefi::Result {
+//! let variables = dmpstore::dump(rt, |_name, _vendor| true).log_warning()?;
+//! let file = dmpstore::serialize(&variables);
+//! // ...
+//! let variables = dmpstore::parse(&file, true).map_err(|_| Status::VOLUME_CORRUPTED)?;
+//! dmpstore::restore(rt, &variables)
+//! # }
+//! ```
+
+use super::runtime::{RuntimeServices, VariableAttributes, VariableVendor};
+use crate::alloc_api::{vec, vec::Vec};
+use crate::crc::crc32;
+use crate::{CStr16, CString16, Guid, Result, ResultExt, Status};
+use core::{mem, ptr, slice};
+
+/// Errors that can occur when parsing saved variables
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DumpError {
+    /// The data ends in the middle of a record.
+    Truncated,
+    /// The name of a variable is not a valid null-terminated UCS-2 string.
+    InvalidName,
+    /// The checksum of a record does not match its contents.
+    CrcMismatch,
+}
+
+/// A variable, as saved by `dmpstore -s`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DumpedVariable {
+    /// Name of the variable
+    pub name: CString16,
+    /// Vendor GUID of the variable
+    pub vendor: VariableVendor,
+    /// Attributes of the variable
+    pub attributes: VariableAttributes,
+    /// Contents of the variable
+    pub data: Vec<u8>,
+}
+
+impl DumpedVariable {
+    /// Read a variable from the firmware, or `None` if it does not exist.
+    pub fn read(
+        rt: &RuntimeServices,
+        name: &CStr16,
+        vendor: &VariableVendor,
+    ) -> Result<Option<DumpedVariable>> {
+        let size = match rt.get_variable_size(name, vendor) {
+            Ok(size) => size.log(),
+            Err(err) if err.status() == Status::NOT_FOUND => return Ok(None.into()),
+            Err(err) => return Err(err),
+        };
+        let mut data = vec![0; size];
+        let (len, attributes) = match rt.get_variable(name, vendor, &mut data) {
+            Ok(completion) => {
+                let (value, attributes) = completion.log();
+                (value.len(), attributes)
+            }
+            Err(err) if err.status() == Status::NOT_FOUND => return Ok(None.into()),
+            Err(err) => return Err(err),
+        };
+        data.truncate(len);
+        Ok(Some(DumpedVariable {
+            name: CString16::from(name),
+            vendor: *vendor,
+            attributes,
+            data,
+        })
+        .into())
+    }
+
+    /// Write the variable back to the firmware.
+    ///
+    /// Like `dmpstore -l`, any existing variable with the same name and vendor
+    /// is deleted first, so that its attributes may change. Variables with
+    /// authenticated write access can only be restored if their data carries
+    /// a valid signature, which is usually not the case of a plain backup.
+    pub fn restore(&self, rt: &RuntimeServices) -> Result {
+        match rt.set_variable(&self.name, &self.vendor, VariableAttributes::empty(), &[]) {
+            Ok(completion) => completion.log(),
+            Err(err) if err.status() == Status::NOT_FOUND => {}
+            Err(err) => return Err(err),
+        }
+        rt.set_variable(&self.name, &self.vendor, self.attributes, &self.data)
+    }
+
+    /// Append the saved form of the variable to `out`.
+    pub fn serialize_into(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        let name = self.name.to_u16_slice_with_nul();
+        out.extend_from_slice(&(mem::size_of_val(name) as u32).to_le_bytes());
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        for &c in name {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+        let vendor: &Guid = &self.vendor.0;
+        out.extend_from_slice(unsafe {
+            slice::from_raw_parts(vendor as *const Guid as *const u8, mem::size_of::<Guid>())
+        });
+        out.extend_from_slice(&self.attributes.bits().to_le_bytes());
+        out.extend_from_slice(&self.data);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Parse the record at the start of `data`, returning the variable and
+    /// the size of the record.
+    fn parse(data: &[u8], verify_crc: bool) -> core::result::Result<(Self, usize), DumpError> {
+        let mut reader = Reader { data, offset: 0 };
+        let name_size = reader.u32()? as usize;
+        let data_size = reader.u32()? as usize;
+
+        let name = reader.bytes(name_size)?;
+        if name_size % 2 != 0 {
+            return Err(DumpError::InvalidName);
+        }
+        let name: Vec<u16> = name
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        let name = CStr16::from_u16_with_nul(&name).map_err(|_| DumpError::InvalidName)?;
+
+        let vendor = reader.bytes(mem::size_of::<Guid>())?;
+        let vendor = unsafe { ptr::read_unaligned(vendor.as_ptr() as *const Guid) };
+        let attributes = VariableAttributes::from_bits_truncate(reader.u32()?);
+        let contents = reader.bytes(data_size)?.to_vec();
+
+        let checked = reader.offset;
+        let crc = reader.u32()?;
+        if verify_crc && crc != crc32(&data[..checked]) {
+            return Err(DumpError::CrcMismatch);
+        }
+
+        let variable = DumpedVariable {
+            name: CString16::from(name),
+            vendor: VariableVendor(vendor),
+            attributes,
+            data: contents,
+        };
+        Ok((variable, reader.offset))
+    }
+}
+
+/// Cursor over the bytes of a record
+struct Reader<'data> {
+    data: &'data [u8],
+    offset: usize,
+}
+
+impl<'data> Reader<'data> {
+    fn bytes(&mut self, len: usize) -> core::result::Result<&'data [u8], DumpError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(DumpError::Truncated)?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> core::result::Result<u32, DumpError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Read all the variables for which `filter` returns true.
+///
+/// Variables which are deleted while the dump is in progress are skipped.
+pub fn dump(
+    rt: &RuntimeServices,
+    mut filter: impl FnMut(&CStr16, &VariableVendor) -> bool,
+) -> Result<Vec<DumpedVariable>> {
+    let mut variables = Vec::new();
+    for key in rt.iter_variable_keys() {
+        let (name, vendor) = key.log_warning()?;
+        let vendor = VariableVendor(vendor);
+        if !filter(&name, &vendor) {
+            continue;
+        }
+        if let Some(variable) = DumpedVariable::read(rt, &name, &vendor).log_warning()? {
+            variables.push(variable);
+        }
+    }
+    Ok(variables.into())
+}
+
+/// Write variables back to the firmware, stopping at the first failure.
+///
+/// See `DumpedVariable::restore` for details.
+pub fn restore(rt: &RuntimeServices, variables: &[DumpedVariable]) -> Result {
+    for variable in variables {
+        variable.restore(rt).log_warning()?;
+    }
+    Status::SUCCESS.into()
+}
+
+/// Save variables in the format of `dmpstore -s`.
+pub fn serialize(variables: &[DumpedVariable]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for variable in variables {
+        variable.serialize_into(&mut out);
+    }
+    out
+}
+
+/// Parse variables saved by `dmpstore -s`.
+///
+/// If `verify_crc` is false, the checksums of the records are ignored, which
+/// allows recovering data from files that were edited by hand.
+pub fn parse(
+    mut data: &[u8],
+    verify_crc: bool,
+) -> core::result::Result<Vec<DumpedVariable>, DumpError> {
+    let mut variables = Vec::new();
+    while !data.is_empty() {
+        let (variable, size) = DumpedVariable::parse(data, verify_crc)?;
+        variables.push(variable);
+        data = &data[size..];
+    }
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use core::convert::TryFrom;
+
+    fn variable(name: &str, data: &[u8]) -> DumpedVariable {
+        DumpedVariable {
+            name: CString16::try_from(name).unwrap(),
+            vendor: VariableVendor(Guid::from_values(1, 2, 3, 4, 5)),
+            attributes: VariableAttributes::NON_VOLATILE
+                | VariableAttributes::BOOTSERVICE_ACCESS
+                | VariableAttributes::RUNTIME_ACCESS,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn shell_format() {
+        // Record produced by the EDK2 shell for the same variable
+        let record = [
+            0x12, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x42, 0x00, 0x6f, 0x00, 0x6f, 0x00,
+            0x74, 0x00, 0x30, 0x00, 0x30, 0x00, 0x30, 0x00, 0x30, 0x00, 0x00, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05,
+            0x07, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0xcd, 0x95, 0x29, 0xa2,
+        ];
+        let boot = variable("Boot0000", &[1, 2, 3]);
+        assert_eq!(serialize(&[boot.clone()]), record);
+        assert_eq!(parse(&record, true), Ok(vec![boot]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut file = serialize(&[variable("A", &[1]), variable("Bc", &[])]);
+        assert_eq!(parse(&file, true).unwrap().len(), 2);
+        assert_eq!(
+            parse(&file[..file.len() - 1], true),
+            Err(DumpError::Truncated)
+        );
+
+        // Corrupt the data of the first variable
+        file[8 + 4 + 16 + 4] ^= 0xff;
+        assert_eq!(parse(&file, true), Err(DumpError::CrcMismatch));
+        assert_eq!(parse(&file, false).unwrap()[0].data, [0xfe]);
+
+        // Name without terminator
+        file[8 + 2] = b'x';
+        assert_eq!(parse(&file, false), Err(DumpError::InvalidName));
+    }
+
+    #[test]
+    fn dump_and_restore() {
+        let st = MockSystemTable::new().system_table();
+        let rt = st.runtime_services();
+        let vendor = VariableVendor(Guid::from_values(0xd3a5_0001, 0, 0, 0, 0));
+        let mut saved = variable("Saved", &[4, 5, 6]);
+        saved.vendor = vendor;
+        saved.restore(rt).unwrap_success();
+
+        let file = serialize(&dump(rt, |_, v| *v == vendor).unwrap_success());
+        assert_eq!(parse(&file, true), Ok(vec![saved.clone()]));
+
+        // Changing the attributes needs the variable to be deleted first
+        rt.set_variable(
+            &saved.name,
+            &vendor,
+            VariableAttributes::BOOTSERVICE_ACCESS,
+            &[9],
+        )
+        .unwrap_success();
+        restore(rt, &parse(&file, true).unwrap()).unwrap_success();
+        assert_eq!(
+            DumpedVariable::read(rt, &saved.name, &vendor).unwrap_success(),
+            Some(saved)
+        );
+    }
+}
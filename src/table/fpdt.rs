@@ -0,0 +1,298 @@
+//! Firmware Performance Data Table (FPDT).
+//!
+//! The FPDT is an ACPI table through which the firmware reports how long the
+//! boot took. It points to the Firmware Basic Boot Performance Table (FBPT),
+//! which records when the firmware finished its reset sequence, when the OS
+//! loader was loaded and started, and when it exited the boot services:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::table::fpdt::Fpdt;
+//! # fn f(st: &SystemTable<Boot>) {
+//! if let Some(fpdt) = unsafe { Fpdt::find(st.config_table()) } {
+//!     if let Some(perf) = unsafe { fpdt.basic_boot_performance() } {
+//!         log::info!("Firmware took {} ns to load the OS loader", perf.os_loader_load_image_start);
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! All the timestamps are in nanoseconds, counted from the reset of the
+//! machine. A timestamp of zero means that the firmware did not record the
+//! event; the exit of the boot services is only recorded once it happens, so
+//! it is zero while the OS loader runs.
+
+use super::acpi::{self, Sdt};
+use super::cfg::ConfigTableEntry;
+
+/// Signature of the FPDT
+pub const FPDT_SIGNATURE: [u8; 4] = *b"FPDT";
+
+/// Signature of the Firmware Basic Boot Performance Table
+pub const FBPT_SIGNATURE: [u8; 4] = *b"FBPT";
+
+/// Type of the record of the FPDT pointing to the FBPT
+pub const RECORD_TYPE_FBPT_POINTER: u16 = 0x0000;
+/// Type of the record of the FPDT pointing to the S3 performance table
+pub const RECORD_TYPE_S3PT_POINTER: u16 = 0x0001;
+/// Type of the record of the FBPT holding the basic boot timestamps
+pub const RECORD_TYPE_BASIC_BOOT: u16 = 0x0002;
+
+/// Size of the header of the FBPT
+const FBPT_HEADER_SIZE: usize = 8;
+
+/// A performance record, of the FPDT or of one of the tables it points to
+#[derive(Debug, Copy, Clone)]
+pub struct PerformanceRecord<'table> {
+    /// Type of the record
+    pub record_type: u16,
+    /// Revision of the structure of the record
+    pub revision: u8,
+    /// Contents of the record, after the common header
+    pub data: &'table [u8],
+}
+
+impl<'table> PerformanceRecord<'table> {
+    fn u64_at(&self, offset: usize) -> Option<u64> {
+        let bytes = self.data.get(offset..offset + 8)?;
+        let mut value = [0; 8];
+        value.copy_from_slice(bytes);
+        Some(u64::from_le_bytes(value))
+    }
+}
+
+/// Iterator over a sequence of performance records
+///
+/// Iteration stops at the first malformed record.
+#[derive(Debug, Clone)]
+pub struct PerformanceRecords<'table> {
+    bytes: &'table [u8],
+}
+
+impl<'table> PerformanceRecords<'table> {
+    /// Parse the records contained in `bytes`
+    pub fn new(bytes: &'table [u8]) -> Self {
+        PerformanceRecords { bytes }
+    }
+}
+
+impl<'table> Iterator for PerformanceRecords<'table> {
+    type Item = PerformanceRecord<'table>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.len() < 4 {
+            return None;
+        }
+        let length = usize::from(self.bytes[2]);
+        if length < 4 || length > self.bytes.len() {
+            self.bytes = &[];
+            return None;
+        }
+        let record = PerformanceRecord {
+            record_type: u16::from_le_bytes([self.bytes[0], self.bytes[1]]),
+            revision: self.bytes[3],
+            data: &self.bytes[4..length],
+        };
+        self.bytes = &self.bytes[length..];
+        Some(record)
+    }
+}
+
+/// Timestamps of the Firmware Basic Boot Performance Data Record, in
+/// nanoseconds since the reset of the machine
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct BasicBootPerformance {
+    /// End of the reset sequence, when the firmware started running
+    pub reset_end: u64,
+    /// When the OS loader started being loaded
+    pub os_loader_load_image_start: u64,
+    /// When the OS loader was started
+    pub os_loader_start_image_start: u64,
+    /// When the OS loader called `ExitBootServices`
+    pub exit_boot_services_entry: u64,
+    /// When `ExitBootServices` returned
+    pub exit_boot_services_exit: u64,
+}
+
+impl BasicBootPerformance {
+    /// Decode a record of type `RECORD_TYPE_BASIC_BOOT`
+    pub fn from_record(record: &PerformanceRecord) -> Option<Self> {
+        if record.record_type != RECORD_TYPE_BASIC_BOOT {
+            return None;
+        }
+        // The timestamps follow 4 reserved bytes
+        Some(BasicBootPerformance {
+            reset_end: record.u64_at(4)?,
+            os_loader_load_image_start: record.u64_at(12)?,
+            os_loader_start_image_start: record.u64_at(20)?,
+            exit_boot_services_entry: record.u64_at(28)?,
+            exit_boot_services_exit: record.u64_at(36)?,
+        })
+    }
+}
+
+/// The Firmware Performance Data Table
+#[derive(Debug, Copy, Clone)]
+pub struct Fpdt<'table> {
+    table: Sdt<'table>,
+}
+
+impl<'table> Fpdt<'table> {
+    /// Find the FPDT among the ACPI tables.
+    ///
+    /// # Safety
+    ///
+    /// See `acpi::tables`.
+    pub unsafe fn find(config_table: &[ConfigTableEntry]) -> Option<Self> {
+        acpi::find_table(config_table, &FPDT_SIGNATURE).map(|table| Fpdt { table })
+    }
+
+    /// Wrap an ACPI table, if it is an FPDT
+    pub fn from_sdt(table: Sdt<'table>) -> Option<Self> {
+        if table.signature() == FPDT_SIGNATURE {
+            Some(Fpdt { table })
+        } else {
+            None
+        }
+    }
+
+    /// The records of the FPDT, which point to other performance tables
+    pub fn records(&self) -> PerformanceRecords<'table> {
+        PerformanceRecords::new(self.table.data())
+    }
+
+    /// Physical address of the Firmware Basic Boot Performance Table
+    pub fn fbpt_address(&self) -> Option<u64> {
+        self.records()
+            .find(|record| record.record_type == RECORD_TYPE_FBPT_POINTER)
+            .and_then(|record| record.u64_at(4))
+    }
+
+    /// The records of the Firmware Basic Boot Performance Table.
+    ///
+    /// Besides the basic boot record, firmwares may add records of their own
+    /// to this table, such as the per-module timings of EDK2.
+    ///
+    /// # Safety
+    ///
+    /// The FBPT must be identity-mapped, and stay valid for `'table`.
+    pub unsafe fn fbpt_records(&self) -> Option<PerformanceRecords<'table>> {
+        let base = self.fbpt_address()? as usize as *const u8;
+        if core::slice::from_raw_parts(base, 4) != FBPT_SIGNATURE {
+            return None;
+        }
+        let length = (base.add(4) as *const u32).read_unaligned() as usize;
+        let bytes = core::slice::from_raw_parts(base, length.max(FBPT_HEADER_SIZE));
+        Some(PerformanceRecords::new(&bytes[FBPT_HEADER_SIZE..]))
+    }
+
+    /// The timestamps of the basic boot record of the FBPT.
+    ///
+    /// # Safety
+    ///
+    /// See `fbpt_records`.
+    pub unsafe fn basic_boot_performance(&self) -> Option<BasicBootPerformance> {
+        self.fbpt_records()?
+            .find_map(|record| BasicBootPerformance::from_record(&record))
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::vec::Vec;
+    use crate::table::acpi::SDT_HEADER_SIZE;
+    use crate::table::cfg::{ConfigTableEntry, ACPI2_GUID};
+
+    fn sdt(signature: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut table = Vec::new();
+        table.extend_from_slice(signature);
+        table.extend_from_slice(&((SDT_HEADER_SIZE + data.len()) as u32).to_le_bytes());
+        table.resize(SDT_HEADER_SIZE, 0);
+        table.extend_from_slice(data);
+        let sum = table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        table[9] = sum.wrapping_neg();
+        table
+    }
+
+    fn record(record_type: u16, revision: u8, data: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&record_type.to_le_bytes());
+        record.push((4 + data.len()) as u8);
+        record.push(revision);
+        record.extend_from_slice(data);
+        record
+    }
+
+    #[test]
+    fn find_boot_performance() {
+        let mut basic = Vec::new();
+        basic.extend_from_slice(&[0; 4]);
+        for timestamp in &[10u64, 20, 30, 0, 0] {
+            basic.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        let records = [
+            record(0x1011, 1, &[0; 8]),
+            record(RECORD_TYPE_BASIC_BOOT, 2, &basic),
+        ];
+        let mut fbpt = Vec::new();
+        fbpt.extend_from_slice(&FBPT_SIGNATURE);
+        let length = FBPT_HEADER_SIZE + records.iter().map(Vec::len).sum::<usize>();
+        fbpt.extend_from_slice(&(length as u32).to_le_bytes());
+        records
+            .iter()
+            .for_each(|record| fbpt.extend_from_slice(record));
+
+        let mut pointer = Vec::new();
+        pointer.extend_from_slice(&[0; 4]);
+        pointer.extend_from_slice(&(fbpt.as_ptr() as u64).to_le_bytes());
+        let fpdt = sdt(
+            &FPDT_SIGNATURE,
+            &record(RECORD_TYPE_FBPT_POINTER, 1, &pointer),
+        );
+        let facp = sdt(b"FACP", &[]);
+
+        let mut entries = Vec::new();
+        entries.extend_from_slice(&(facp.as_ptr() as u64).to_le_bytes());
+        entries.extend_from_slice(&(fpdt.as_ptr() as u64).to_le_bytes());
+        let xsdt = sdt(b"XSDT", &entries);
+        let mut rsdp = [0u8; 36];
+        rsdp[..8].copy_from_slice(b"RSD PTR ");
+        rsdp[24..32].copy_from_slice(&(xsdt.as_ptr() as u64).to_le_bytes());
+        let config_table = [ConfigTableEntry {
+            guid: ACPI2_GUID,
+            address: rsdp.as_ptr().cast(),
+        }];
+
+        unsafe {
+            let signatures: Vec<_> = acpi::tables(&config_table)
+                .map(|table| table.signature())
+                .collect();
+            assert_eq!(signatures, [*b"FACP", FPDT_SIGNATURE]);
+
+            let table = Fpdt::find(&config_table).unwrap();
+            assert!(table.table.checksum_is_valid());
+            assert_eq!(table.fbpt_address(), Some(fbpt.as_ptr() as u64));
+            assert_eq!(table.fbpt_records().unwrap().count(), 2);
+            assert_eq!(
+                table.basic_boot_performance(),
+                Some(BasicBootPerformance {
+                    reset_end: 10,
+                    os_loader_load_image_start: 20,
+                    os_loader_start_image_start: 30,
+                    exit_boot_services_entry: 0,
+                    exit_boot_services_exit: 0,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_records() {
+        let mut bytes = record(RECORD_TYPE_BASIC_BOOT, 2, &[0; 8]);
+        bytes.extend_from_slice(&[0xff, 0xff, 2, 0]);
+        let records: Vec<_> = PerformanceRecords::new(&bytes).collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(BasicBootPerformance::from_record(&records[0]), None);
+    }
+}
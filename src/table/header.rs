@@ -1,5 +1,7 @@
 use super::Revision;
+use crate::{Result, Status};
 use core::fmt::{Debug, Formatter};
+use core::mem;
 
 /// All standard UEFI tables begin with a common header.
 #[repr(C)]
@@ -27,3 +29,60 @@ impl Debug for Header {
             .finish()
     }
 }
+
+impl Header {
+    /// Check that the table which starts with this header has the entry
+    /// `entry`, which was added in `revision`, or fail with `UNSUPPORTED`.
+    ///
+    /// The tables of older firmware are shorter, so that their newer entries
+    /// would be read past their end.
+    pub(crate) fn require<T>(&self, revision: Revision, entry: &T) -> Result {
+        let end = entry as *const T as usize + mem::size_of::<T>() - self as *const Self as usize;
+        if self.revision < revision || (self.size as usize) < end {
+            return Err(Status::UNSUPPORTED.into());
+        }
+        Status::SUCCESS.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Table {
+        header: Header,
+        old: usize,
+        new: usize,
+    }
+
+    #[test]
+    fn require() {
+        let mut table = Table {
+            header: Header {
+                signature: 0,
+                revision: Revision::EFI_2_00,
+                size: mem::size_of::<Table>() as u32,
+                crc: 0,
+                _reserved: 0,
+            },
+            old: 0,
+            new: 0,
+        };
+        assert!(table.header.require(Revision::EFI_1_10, &table.new).is_ok());
+        assert!(table.header.require(Revision::EFI_2_00, &table.new).is_ok());
+        let err = table
+            .header
+            .require(Revision::EFI_2_10, &table.old)
+            .unwrap_err();
+        assert_eq!(err.status(), Status::UNSUPPORTED);
+
+        // The revision is right, but the table ends before the entry
+        table.header.size -= mem::size_of::<usize>() as u32;
+        assert!(table.header.require(Revision::EFI_2_00, &table.old).is_ok());
+        assert!(table
+            .header
+            .require(Revision::EFI_2_00, &table.new)
+            .is_err());
+    }
+}
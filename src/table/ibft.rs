@@ -0,0 +1,453 @@
+//! iSCSI Boot Firmware Table (iBFT).
+//!
+//! Firmware which boots from an iSCSI disk publishes the configuration it
+//! used in this ACPI table: the name of the initiator, the network
+//! interfaces, and the targets with their credentials. The OS reads it to
+//! reconnect to its root disk, and provisioning tools to check what the
+//! firmware will boot from:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::table::ibft::Ibft;
+//! # fn f(st: &SystemTable<Boot>) {
+//! if let Some(ibft) = unsafe { Ibft::find(st.config_table()) } {
+//!     for target in ibft.targets().filter(|target| target.flags.is_boot_selected()) {
+//!         log::info!("Booting from {:?} port {}", target.ip_address, target.port);
+//!     }
+//! }
+//! # }
+//! ```
+
+use super::acpi::{self, Sdt, SDT_HEADER_SIZE};
+use super::cfg::ConfigTableEntry;
+use core::convert::TryInto;
+
+/// Signature of the iBFT
+pub const IBFT_SIGNATURE: [u8; 4] = *b"iBFT";
+/// Signature used by some firmware instead of `IBFT_SIGNATURE`
+const IBFT_ALT_SIGNATURE: [u8; 4] = *b"IBFT";
+
+/// Offset of the control structure, after 12 reserved bytes
+const CONTROL_OFFSET: usize = SDT_HEADER_SIZE + 12;
+/// Size of the header common to all the structures
+const STRUCTURE_HEADER_SIZE: usize = 6;
+
+/// Identifiers of the structures
+const CONTROL_ID: u8 = 1;
+const INITIATOR_ID: u8 = 2;
+const NIC_ID: u8 = 3;
+const TARGET_ID: u8 = 4;
+
+/// Errors that can occur when parsing the iBFT
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IbftError {
+    /// The table is truncated, or has an invalid checksum.
+    InvalidTable,
+    /// The control structure is missing, or a structure it references is
+    /// truncated or of the wrong type.
+    InvalidStructure,
+}
+
+/// Flags of an iBFT structure
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StructureFlags(pub u8);
+
+impl StructureFlags {
+    /// Whether the structure holds valid data
+    pub fn is_valid(self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    /// Whether the firmware booted with this structure
+    pub fn is_boot_selected(self) -> bool {
+        self.0 & 2 != 0
+    }
+}
+
+/// An IP address of the iBFT
+///
+/// IPv4 addresses are stored as IPv4-mapped IPv6 addresses, like
+/// `::ffff:192.168.0.1`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IbftAddress(pub [u8; 16]);
+
+impl IbftAddress {
+    /// Whether the address is unset
+    pub fn is_unspecified(&self) -> bool {
+        self.0 == [0; 16]
+    }
+
+    /// The IPv4 address, if it is one
+    pub fn ipv4(&self) -> Option<[u8; 4]> {
+        if self.0[..10] == [0; 10] && self.0[10..12] == [0xff; 2] {
+            Some([self.0[12], self.0[13], self.0[14], self.0[15]])
+        } else {
+            None
+        }
+    }
+}
+
+/// The initiator structure, describing the client
+#[derive(Debug, Copy, Clone)]
+pub struct Initiator<'table> {
+    /// Flags of the structure
+    pub flags: StructureFlags,
+    /// The iSNS server
+    pub isns_server: IbftAddress,
+    /// The SLP server
+    pub slp_server: IbftAddress,
+    /// The primary RADIUS server
+    pub primary_radius_server: IbftAddress,
+    /// The secondary RADIUS server
+    pub secondary_radius_server: IbftAddress,
+    /// The iSCSI qualified name of the initiator
+    pub name: &'table [u8],
+}
+
+/// A NIC structure, describing a network interface
+#[derive(Debug, Copy, Clone)]
+pub struct Nic<'table> {
+    /// Index of the structure, referenced by targets
+    pub index: u8,
+    /// Flags of the structure
+    pub flags: StructureFlags,
+    /// Address of the interface
+    pub ip_address: IbftAddress,
+    /// Length of the subnet prefix
+    pub subnet_prefix: u8,
+    /// How the address was obtained, as an RFC 4293 `IpAddressOriginTC`
+    pub origin: u8,
+    /// Default gateway
+    pub gateway: IbftAddress,
+    /// Primary DNS server
+    pub primary_dns: IbftAddress,
+    /// Secondary DNS server
+    pub secondary_dns: IbftAddress,
+    /// DHCP server which gave the address
+    pub dhcp: IbftAddress,
+    /// VLAN identifier, 0 if none
+    pub vlan: u16,
+    /// MAC address of the interface
+    pub mac_address: [u8; 6],
+    /// PCI bus, device and function of the interface, as `bus << 8 |
+    /// device << 3 | function`
+    pub pci_bdf: u16,
+    /// Host name of the interface
+    pub host_name: &'table [u8],
+}
+
+newtype_enum! {
+    /// Authentication of a target
+    pub enum ChapType: u8 => {
+        /// No authentication
+        NONE   = 0,
+        /// The initiator authenticates with CHAP
+        CHAP   = 1,
+        /// The initiator and the target authenticate each other
+        MUTUAL = 2,
+    }
+}
+
+/// A target structure, describing an iSCSI disk
+#[derive(Debug, Copy, Clone)]
+pub struct Target<'table> {
+    /// Index of the structure
+    pub index: u8,
+    /// Flags of the structure
+    pub flags: StructureFlags,
+    /// Address of the target
+    pub ip_address: IbftAddress,
+    /// TCP port of the target
+    pub port: u16,
+    /// Logical unit number, in the SAM format
+    pub lun: [u8; 8],
+    /// Authentication of the target
+    pub chap_type: ChapType,
+    /// Index of the NIC through which the target is reached
+    pub nic_index: u8,
+    /// The iSCSI qualified name of the target
+    pub name: &'table [u8],
+    /// CHAP name of the initiator
+    pub chap_name: &'table [u8],
+    /// CHAP secret of the initiator
+    pub chap_secret: &'table [u8],
+    /// CHAP name of the target, for mutual authentication
+    pub reverse_chap_name: &'table [u8],
+    /// CHAP secret of the target, for mutual authentication
+    pub reverse_chap_secret: &'table [u8],
+}
+
+/// The iSCSI Boot Firmware Table
+#[derive(Debug, Copy, Clone)]
+pub struct Ibft<'table> {
+    table: Sdt<'table>,
+}
+
+/// A structure of the table
+#[derive(Copy, Clone)]
+struct Structure<'table> {
+    table: &'table [u8],
+    bytes: &'table [u8],
+}
+
+impl<'table> Structure<'table> {
+    fn index(&self) -> u8 {
+        self.bytes[4]
+    }
+
+    fn flags(&self) -> StructureFlags {
+        StructureFlags(self.bytes[5])
+    }
+
+    fn u8(&self, offset: usize) -> u8 {
+        self.bytes[offset]
+    }
+
+    fn u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.bytes[offset], self.bytes[offset + 1]])
+    }
+
+    fn array<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.bytes[offset..offset + N].try_into().unwrap()
+    }
+
+    fn address(&self, offset: usize) -> IbftAddress {
+        IbftAddress(self.array(offset))
+    }
+
+    /// The string whose length and offset in the table are at `offset`
+    fn string(&self, offset: usize) -> &'table [u8] {
+        let length = usize::from(self.u16(offset));
+        let start = usize::from(self.u16(offset + 2));
+        if start == 0 {
+            return &[];
+        }
+        self.table.get(start..start + length).unwrap_or(&[])
+    }
+}
+
+impl<'table> Ibft<'table> {
+    /// Find the iBFT among the ACPI tables.
+    ///
+    /// # Safety
+    ///
+    /// See `acpi::tables`.
+    pub unsafe fn find(config_table: &[ConfigTableEntry]) -> Option<Self> {
+        acpi::tables(config_table).find_map(Self::from_sdt)
+    }
+
+    /// Wrap an ACPI table, if it is an iBFT
+    pub fn from_sdt(table: Sdt<'table>) -> Option<Self> {
+        let signature = table.signature();
+        if signature == IBFT_SIGNATURE || signature == IBFT_ALT_SIGNATURE {
+            Some(Ibft { table })
+        } else {
+            None
+        }
+    }
+
+    /// Check that the checksum of the table is valid, and that its
+    /// structures are complete.
+    pub fn validate(&self) -> core::result::Result<(), IbftError> {
+        if !self.table.checksum_is_valid() {
+            return Err(IbftError::InvalidTable);
+        }
+        let control = self
+            .structure(CONTROL_OFFSET, CONTROL_ID, 18)
+            .ok_or(IbftError::InvalidStructure)?;
+        for (offset, id, size) in self.structure_offsets(control) {
+            if offset != 0 && self.structure(offset, id, size).is_none() {
+                return Err(IbftError::InvalidStructure);
+            }
+        }
+        Ok(())
+    }
+
+    /// The structure of type `id` at `offset`, if it is at least `size`
+    /// bytes large
+    fn structure(&self, offset: usize, id: u8, size: usize) -> Option<Structure<'table>> {
+        let table = self.table.bytes();
+        let header = table.get(offset..offset + STRUCTURE_HEADER_SIZE)?;
+        let length = usize::from(u16::from_le_bytes([header[2], header[3]]));
+        if header[0] != id || length < size {
+            return None;
+        }
+        Some(Structure {
+            table,
+            bytes: table.get(offset..offset + length)?,
+        })
+    }
+
+    /// The offsets, types and sizes of the structures referenced by the
+    /// control structure
+    fn structure_offsets(
+        &self,
+        control: Structure<'table>,
+    ) -> impl Iterator<Item = (usize, u8, usize)> + 'table {
+        let initiator = usize::from(control.u16(8));
+        // The pairs of NIC and target structures follow, at least two of
+        // them
+        let pairs = (control.bytes.len() - 10) / 4;
+        core::iter::once((initiator, INITIATOR_ID, 74)).chain((0..pairs).flat_map(move |pair| {
+            let nic = usize::from(control.u16(10 + pair * 4));
+            let target = usize::from(control.u16(12 + pair * 4));
+            [(nic, NIC_ID, 102), (target, TARGET_ID, 54)]
+        }))
+    }
+
+    fn structures(&self, id: u8) -> impl Iterator<Item = Structure<'table>> + 'table {
+        let this = *self;
+        self.structure(CONTROL_OFFSET, CONTROL_ID, 18)
+            .into_iter()
+            .flat_map(move |control| this.structure_offsets(control))
+            .filter(move |&(offset, structure_id, _)| offset != 0 && structure_id == id)
+            .filter_map(move |(offset, id, size)| this.structure(offset, id, size))
+    }
+
+    /// The initiator structure
+    pub fn initiator(&self) -> Option<Initiator<'table>> {
+        self.structures(INITIATOR_ID).next().map(|s| Initiator {
+            flags: s.flags(),
+            isns_server: s.address(6),
+            slp_server: s.address(22),
+            primary_radius_server: s.address(38),
+            secondary_radius_server: s.address(54),
+            name: s.string(70),
+        })
+    }
+
+    /// Iterate over the NIC structures
+    pub fn nics(&self) -> impl Iterator<Item = Nic<'table>> + 'table {
+        self.structures(NIC_ID).map(|s| Nic {
+            index: s.index(),
+            flags: s.flags(),
+            ip_address: s.address(6),
+            subnet_prefix: s.u8(22),
+            origin: s.u8(23),
+            gateway: s.address(24),
+            primary_dns: s.address(40),
+            secondary_dns: s.address(56),
+            dhcp: s.address(72),
+            vlan: s.u16(88),
+            mac_address: s.array(90),
+            pci_bdf: s.u16(96),
+            host_name: s.string(98),
+        })
+    }
+
+    /// Iterate over the target structures
+    pub fn targets(&self) -> impl Iterator<Item = Target<'table>> + 'table {
+        self.structures(TARGET_ID).map(|s| Target {
+            index: s.index(),
+            flags: s.flags(),
+            ip_address: s.address(6),
+            port: s.u16(22),
+            lun: s.array(24),
+            chap_type: ChapType(s.u8(32)),
+            nic_index: s.u8(33),
+            name: s.string(34),
+            chap_name: s.string(38),
+            chap_secret: s.string(42),
+            reverse_chap_name: s.string(46),
+            reverse_chap_secret: s.string(50),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::alloc_api::vec::Vec;
+
+    fn structure(table: &mut Vec<u8>, id: u8, length: usize, index: u8, flags: u8) -> usize {
+        let offset = table.len();
+        table.extend_from_slice(&[id, 1]);
+        table.extend_from_slice(&(length as u16).to_le_bytes());
+        table.extend_from_slice(&[index, flags]);
+        table.resize(offset + length, 0);
+        offset
+    }
+
+    fn set_u16(table: &mut [u8], offset: usize, value: usize) {
+        table[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+    }
+
+    fn string(table: &mut Vec<u8>, field: usize, value: &[u8]) {
+        let offset = table.len();
+        table.extend_from_slice(value);
+        table.push(0);
+        set_u16(table, field, value.len());
+        set_u16(table, field + 2, offset);
+    }
+
+    fn ibft() -> Vec<u8> {
+        let mut table = Vec::new();
+        table.extend_from_slice(&IBFT_SIGNATURE);
+        table.resize(CONTROL_OFFSET, 0);
+        let control = structure(&mut table, CONTROL_ID, 18, 0, 0);
+        let initiator = structure(&mut table, INITIATOR_ID, 74, 0, 0b11);
+        let nic = structure(&mut table, NIC_ID, 102, 0, 0b11);
+        let target = structure(&mut table, TARGET_ID, 54, 0, 0b11);
+        set_u16(&mut table, control + 8, initiator);
+        set_u16(&mut table, control + 10, nic);
+        set_u16(&mut table, control + 12, target);
+
+        string(
+            &mut table,
+            initiator + 70,
+            b"iqn.2021-01.org.example:client",
+        );
+        table[nic + 6..nic + 22]
+            .copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 10, 0, 0, 2]);
+        table[nic + 22] = 24;
+        set_u16(&mut table, nic + 88, 42);
+        table[nic + 90..nic + 96].copy_from_slice(&[0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+        set_u16(&mut table, target + 22, 3260);
+        table[target + 32] = ChapType::CHAP.0;
+        string(&mut table, target + 34, b"iqn.2021-01.org.example:disk");
+        string(&mut table, target + 38, b"user");
+
+        let length = table.len() as u32;
+        table[4..8].copy_from_slice(&length.to_le_bytes());
+        let sum = table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        table[9] = sum.wrapping_neg();
+        table
+    }
+
+    #[test]
+    fn parse_table() {
+        let table = ibft();
+        let ibft = Ibft::from_sdt(Sdt::from_bytes(&table).unwrap()).unwrap();
+        assert_eq!(ibft.validate(), Ok(()));
+
+        let initiator = ibft.initiator().unwrap();
+        assert_eq!(initiator.name, b"iqn.2021-01.org.example:client");
+        assert!(initiator.flags.is_boot_selected());
+
+        let nics: Vec<_> = ibft.nics().collect();
+        assert_eq!(nics.len(), 1);
+        assert_eq!(nics[0].ip_address.ipv4(), Some([10, 0, 0, 2]));
+        assert_eq!(nics[0].subnet_prefix, 24);
+        assert_eq!(nics[0].vlan, 42);
+        assert!(nics[0].gateway.is_unspecified());
+
+        let target = ibft.targets().next().unwrap();
+        assert_eq!(target.port, 3260);
+        assert_eq!(target.chap_type, ChapType::CHAP);
+        assert_eq!(target.name, b"iqn.2021-01.org.example:disk");
+        assert_eq!(target.chap_name, b"user");
+        assert_eq!(target.chap_secret, b"");
+    }
+
+    #[test]
+    fn invalid_structure() {
+        let mut table = ibft();
+        // Make the target reference point into the initiator structure
+        set_u16(&mut table, CONTROL_OFFSET + 12, CONTROL_OFFSET + 18);
+        let sum = table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        table[9] = table[9].wrapping_sub(sum);
+        let ibft = Ibft::from_sdt(Sdt::from_bytes(&table).unwrap()).unwrap();
+        assert_eq!(ibft.validate(), Err(IbftError::InvalidStructure));
+        assert_eq!(ibft.targets().count(), 0);
+    }
+}
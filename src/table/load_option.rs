@@ -0,0 +1,359 @@
+//! Boot options, and the variables of the boot manager.
+//!
+//! The boot manager of the firmware starts the boot options stored in the
+//! `Boot####` variables, `####` being the hexadecimal number of the option,
+//! in the order given by `BootOrder`. `BootNext` overrides this order for the
+//! next boot only, and `BootCurrent` tells which option was started:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::table::load_option;
+//! # fn f(rt: &RuntimeServices) -> uefi::Result {
+//! for entry in load_option::get_boot_entries(rt)?.log() {
+//!     let entry = entry?.log();
+//!     if let Some(option) = entry.option() {
+//!         let description: String = option.description().collect();
+//!         log::info!("Boot{:04X}: {}", entry.number(), description);
+//!     }
+//! }
+//! // Reboot into the first option once
+//! load_option::boot_next(rt, 0)?.log();
+//! # Ok(().into())
+//! # }
+//! ```
+
+use super::runtime::{RuntimeServices, VariableVendor};
+use super::var_store::{Persistence, VarStore};
+use crate::proto::device_path::DevicePath;
+#[cfg(feature = "exts")]
+use crate::{alloc_api::vec::Vec, Completion, Status};
+use crate::{CStr16, Result};
+use bitflags::bitflags;
+use core::char;
+use core::convert::TryInto;
+
+bitflags! {
+    /// Attributes of a load option.
+    pub struct LoadOptionAttributes: u32 {
+        /// The boot manager may start the option.
+        const ACTIVE = 0x0000_0001;
+        /// The drivers are reconnected after the option is loaded, for
+        /// `Driver####` options.
+        const FORCE_RECONNECT = 0x0000_0002;
+        /// The option is not shown in the menu of the boot manager.
+        const HIDDEN = 0x0000_0008;
+        /// The option is an application, which is only started on request
+        /// of the user, instead of a boot option.
+        const CATEGORY_APP = 0x0000_0100;
+    }
+}
+
+/// A load option (`EFI_LOAD_OPTION`), as stored in the `Boot####` variables
+#[derive(Debug, Copy, Clone)]
+pub struct LoadOption<'a> {
+    attributes: LoadOptionAttributes,
+    description: &'a [u8],
+    file_path_list: &'a [u8],
+    optional_data: &'a [u8],
+}
+
+impl<'a> LoadOption<'a> {
+    /// Parse a load option, or return `None` if it is truncated
+    pub fn from_bytes(data: &'a [u8]) -> Option<Self> {
+        let attributes = u32::from_le_bytes(data.get(..4)?.try_into().unwrap());
+        let file_path_list_length = u16::from_le_bytes(data.get(4..6)?.try_into().unwrap());
+        let rest = data.get(6..)?;
+        // The description is a nul-terminated UCS-2 string
+        let description_len = rest
+            .chunks_exact(2)
+            .position(|c| c == [0, 0])
+            .map(|chars| 2 * chars)?;
+        let description = &rest[..description_len];
+        let rest = &rest[description_len + 2..];
+        let file_path_list = rest.get(..file_path_list_length as usize)?;
+        Some(LoadOption {
+            attributes: LoadOptionAttributes::from_bits_truncate(attributes),
+            description,
+            file_path_list,
+            optional_data: &rest[file_path_list.len()..],
+        })
+    }
+
+    /// Attributes of the option
+    pub fn attributes(&self) -> LoadOptionAttributes {
+        self.attributes
+    }
+
+    /// Whether the boot manager may start the option
+    pub fn is_active(&self) -> bool {
+        self.attributes.contains(LoadOptionAttributes::ACTIVE)
+    }
+
+    /// The description of the option which is shown to the user, with the
+    /// invalid characters replaced
+    pub fn description(&self) -> impl Iterator<Item = char> + 'a {
+        let units = self
+            .description
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]));
+        char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    /// The raw list of device paths, the first of which locates the image to
+    /// load
+    pub fn file_path_list(&self) -> &'a [u8] {
+        self.file_path_list
+    }
+
+    /// The first device path of the list, or `None` if it is malformed
+    pub fn file_path(&self) -> Option<&'a DevicePath> {
+        // Check that the nodes, up to the end of the path, are in bounds
+        let mut offset = 0;
+        loop {
+            let node = self.file_path_list.get(offset..offset + 4)?;
+            let length = u16::from_le_bytes([node[2], node[3]]) as usize;
+            if length < 4 || offset + length > self.file_path_list.len() {
+                return None;
+            }
+            if node[0] == 0x7f && node[1] == 0xff {
+                break;
+            }
+            offset += length;
+        }
+        let path = self.file_path_list.as_ptr().cast::<DevicePath>();
+        Some(unsafe { &*path })
+    }
+
+    /// Data passed to the image as its load options
+    pub fn optional_data(&self) -> &'a [u8] {
+        self.optional_data
+    }
+}
+
+/// The name of the `Boot####` variable of the option `number`, with its nul
+/// terminator
+pub fn boot_variable_name(number: u16) -> [u16; 9] {
+    let mut name = [0; 9];
+    for (c, &b) in name.iter_mut().zip(b"Boot") {
+        *c = u16::from(b);
+    }
+    for (i, c) in name[4..8].iter_mut().enumerate() {
+        let digit = (number >> (12 - 4 * i)) & 0xf;
+        *c = u16::from(b"0123456789ABCDEF"[usize::from(digit)]);
+    }
+    name
+}
+
+/// The variables of the boot manager, which are written with the attributes
+/// required by the specification
+pub(super) fn global_variables(rt: &RuntimeServices) -> VarStore<'_> {
+    VarStore::new(rt, VariableVendor::GLOBAL_VARIABLE).persistence(Persistence::NonVolatile)
+}
+
+/// Name of a global variable, given as a nul-terminated ASCII string which
+/// fits in `buf`
+pub(super) fn variable_name<'buf>(name: &[u8], buf: &'buf mut [u16]) -> &'buf CStr16 {
+    for (c, &b) in buf.iter_mut().zip(name) {
+        *c = u16::from(b);
+    }
+    CStr16::from_u16_with_nul(&buf[..name.len()]).unwrap()
+}
+
+/// The option which was started by the boot manager, from `BootCurrent`, or
+/// `None` if the current image was not started as a boot option
+pub fn boot_current(rt: &RuntimeServices) -> Result<Option<u16>> {
+    global_variables(rt).get_u16(variable_name(b"BootCurrent\0", &mut [0; 16]))
+}
+
+/// Start the option `number` at the next boot only, by setting `BootNext`
+pub fn boot_next(rt: &RuntimeServices, number: u16) -> Result {
+    global_variables(rt).set_u16(variable_name(b"BootNext\0", &mut [0; 16]), number)
+}
+
+/// Undo `boot_next`, starting the options in their usual order again
+pub fn clear_boot_next(rt: &RuntimeServices) -> Result {
+    global_variables(rt).remove(variable_name(b"BootNext\0", &mut [0; 16]))
+}
+
+/// The numbers of the boot options, in the order in which they are tried
+///
+/// If `BootOrder` does not exist, no option is returned. It fails with
+/// `BAD_BUFFER_SIZE` if `BootOrder` has an odd size.
+#[cfg(feature = "exts")]
+pub fn boot_order(rt: &RuntimeServices) -> Result<Vec<u16>> {
+    let mut buf = [0; 16];
+    let name = variable_name(b"BootOrder\0", &mut buf);
+    let (status, data) = global_variables(rt).get_vec(name)?.split();
+    let data = data.unwrap_or_default();
+    if data.len() % 2 != 0 {
+        return Err(Status::BAD_BUFFER_SIZE.into());
+    }
+    let order = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    Ok(Completion::new(status, order))
+}
+
+/// Replace `BootOrder` with `order`, with `var_journal` so that an
+/// interrupted update is completed by `var_journal::recover`
+#[cfg(feature = "exts")]
+pub fn set_boot_order(rt: &RuntimeServices, order: &[u16]) -> Result {
+    let mut buf = [0; 16];
+    let name = variable_name(b"BootOrder\0", &mut buf);
+    let data: Vec<u8> = order
+        .iter()
+        .flat_map(|number| number.to_le_bytes())
+        .collect();
+    let vendor = VariableVendor::GLOBAL_VARIABLE;
+    let attributes = Persistence::NonVolatile.attributes();
+    super::var_journal::write(rt, name, &vendor, attributes, &data)
+}
+
+/// A boot option, read from its `Boot####` variable
+#[cfg(feature = "exts")]
+#[derive(Debug, Clone)]
+pub struct BootEntry {
+    number: u16,
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "exts")]
+impl BootEntry {
+    /// Read the option `number`, which may not exist
+    pub fn read(rt: &RuntimeServices, number: u16) -> Result<Option<Self>> {
+        let name = boot_variable_name(number);
+        let name = CStr16::from_u16_with_nul(&name).unwrap();
+        let data = global_variables(rt).get_vec(name)?;
+        Ok(data.map(|data| data.map(|data| BootEntry { number, data })))
+    }
+
+    /// The number of the option
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+
+    /// The raw contents of the variable
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The parsed option, or `None` if it is malformed
+    pub fn option(&self) -> Option<LoadOption<'_>> {
+        LoadOption::from_bytes(&self.data)
+    }
+}
+
+/// Iterator over the boot options in `BootOrder`, returned by
+/// `get_boot_entries`
+#[cfg(feature = "exts")]
+#[derive(Debug)]
+pub struct BootEntries<'rt> {
+    rt: &'rt RuntimeServices,
+    order: Vec<u16>,
+    next: usize,
+}
+
+#[cfg(feature = "exts")]
+impl Iterator for BootEntries<'_> {
+    type Item = Result<BootEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&number) = self.order.get(self.next) {
+            self.next += 1;
+            // Options listed in `BootOrder` but missing are skipped, like the
+            // boot manager does
+            match BootEntry::read(self.rt, number) {
+                Ok(entry) => match entry.split() {
+                    (_, None) => continue,
+                    (status, Some(entry)) => return Some(Ok(Completion::new(status, entry))),
+                },
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        None
+    }
+}
+
+/// Iterate over the boot options, in the order given by `BootOrder`
+///
+/// See `boot_order` for the errors.
+#[cfg(feature = "exts")]
+pub fn get_boot_entries(rt: &RuntimeServices) -> Result<BootEntries<'_>> {
+    let order = boot_order(rt)?;
+    Ok(order.map(|order| BootEntries { rt, order, next: 0 }))
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::ResultExt;
+
+    #[test]
+    fn parse_option() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x0000_0109u32.to_le_bytes());
+        data.extend_from_slice(&8u16.to_le_bytes());
+        for c in "Disk é\0".encode_utf16() {
+            data.extend_from_slice(&c.to_le_bytes());
+        }
+        // A hard drive node, then the end of the path
+        data.extend_from_slice(&[0x04, 0x01, 4, 0, 0x7f, 0xff, 4, 0]);
+        data.extend_from_slice(b"args");
+
+        let option = LoadOption::from_bytes(&data).unwrap();
+        assert!(option.is_active());
+        assert!(option.attributes().contains(LoadOptionAttributes::HIDDEN));
+        assert!(option
+            .attributes()
+            .contains(LoadOptionAttributes::CATEGORY_APP));
+        assert!(option.description().eq("Disk é".chars()));
+        assert_eq!(option.file_path_list().len(), 8);
+        assert!(option.file_path().is_some());
+        assert_eq!(option.optional_data(), b"args");
+
+        assert!(LoadOption::from_bytes(&data[..20]).is_none());
+        // The end node is missing from the list
+        data[4] = 4;
+        assert!(LoadOption::from_bytes(&data).unwrap().file_path().is_none());
+    }
+
+    #[test]
+    fn boot_manager() {
+        let st = crate::mock::MockSystemTable::new().system_table();
+        let rt = st.runtime_services();
+        let store = global_variables(rt);
+        let option = [1, 0, 0, 0, 4, 0, b'A', 0, 0, 0, 0x7f, 0xff, 4, 0];
+        let name = boot_variable_name(2);
+        store
+            .set_raw(CStr16::from_u16_with_nul(&name).unwrap(), &option)
+            .unwrap_success();
+        // The missing option 1 is skipped
+        let mut buf = [0; 16];
+        let name = variable_name(b"BootOrder\0", &mut buf);
+        store.set_raw(name, &[1, 0, 2, 0]).unwrap_success();
+
+        assert_eq!(boot_order(rt).unwrap_success(), [1, 2]);
+        let entries = get_boot_entries(rt)
+            .unwrap_success()
+            .map(|entry| entry.unwrap_success())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].number(), 2);
+        assert!(entries[0].option().unwrap().description().eq("A".chars()));
+
+        assert_eq!(boot_current(rt).unwrap_success(), None);
+        boot_next(rt, 2).unwrap_success();
+        let mut buf = [0; 16];
+        let name = variable_name(b"BootNext\0", &mut buf);
+        assert_eq!(store.get_u16(name).unwrap_success(), Some(2));
+        clear_boot_next(rt).unwrap_success();
+        assert_eq!(store.get_u16(name).unwrap_success(), None);
+    }
+
+    #[test]
+    fn variable_names() {
+        let name = boot_variable_name(0x00af);
+        assert_eq!(&name[..], "Boot00AF\0".encode_utf16().collect::<Vec<_>>());
+    }
+}
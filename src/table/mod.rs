@@ -7,6 +7,20 @@ pub trait Table {
     const SIGNATURE: u64;
 }
 
+/// Call the service `$name` of a table, with the arguments `$arg`, or
+/// return `Status::UNSUPPORTED` if the firmware left it null.
+///
+/// The service must be declared as an `Option` of a function pointer, which
+/// is null when it is `None`.
+macro_rules! call_service {
+    ($table:ident $(. $path:ident)* ( $($arg:expr),* $(,)? )) => {
+        match $table $(. $path)* {
+            Some(service) => service($($arg),*),
+            None => $crate::Status::UNSUPPORTED,
+        }
+    };
+}
+
 mod header;
 pub use self::header::Header;
 
@@ -18,5 +32,20 @@ pub use self::system::{Boot, Runtime, SystemTable};
 
 pub mod boot;
 pub mod runtime;
+#[cfg(feature = "exts")]
+pub mod var_journal;
+pub mod var_store;
+
+pub mod load_option;
+
+pub mod capsule;
+
+#[cfg(feature = "exts")]
+pub mod dmpstore;
 
 pub mod cfg;
+
+pub mod acpi;
+pub mod bgrt;
+pub mod fpdt;
+pub mod ibft;
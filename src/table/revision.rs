@@ -10,6 +10,31 @@ use core::fmt;
 pub struct Revision(u32);
 
 impl Revision {
+    /// EFI 1.02
+    pub const EFI_1_02: Revision = Revision((1 << 16) | 2);
+    /// EFI 1.10, which added services such as `OpenProtocol`
+    pub const EFI_1_10: Revision = Revision((1 << 16) | 10);
+    /// UEFI 2.0, which added `CreateEventEx` and the capsule services
+    pub const EFI_2_00: Revision = Revision(2 << 16);
+    /// UEFI 2.1
+    pub const EFI_2_10: Revision = Revision((2 << 16) | 10);
+    /// UEFI 2.2
+    pub const EFI_2_20: Revision = Revision((2 << 16) | 20);
+    /// UEFI 2.3
+    pub const EFI_2_30: Revision = Revision((2 << 16) | 30);
+    /// UEFI 2.3.1
+    pub const EFI_2_31: Revision = Revision((2 << 16) | 31);
+    /// UEFI 2.4
+    pub const EFI_2_40: Revision = Revision((2 << 16) | 40);
+    /// UEFI 2.5
+    pub const EFI_2_50: Revision = Revision((2 << 16) | 50);
+    /// UEFI 2.6
+    pub const EFI_2_60: Revision = Revision((2 << 16) | 60);
+    /// UEFI 2.7
+    pub const EFI_2_70: Revision = Revision((2 << 16) | 70);
+    /// UEFI 2.8
+    pub const EFI_2_80: Revision = Revision((2 << 16) | 80);
+
     /// Creates a new revision.
     pub fn new(major: u16, minor: u16) -> Self {
         let (major, minor) = (u32::from(major), u32::from(minor));
@@ -1,81 +1,93 @@
 //! UEFI services available at runtime, even after the OS boots.
 
-use super::Header;
+use super::Revision;
 #[cfg(feature = "exts")]
 use crate::data_types::FromSliceWithNulError;
 use crate::result::Error;
-use crate::table::boot::MemoryDescriptor;
-use crate::{CStr16, Char16, Guid, Result, Status};
+use crate::table::boot::{boot_services_active, MemoryDescriptor};
+use crate::table::cfg::{ConfigTableEntry, RtPropertiesTable};
+use crate::{CStr16, Guid, Result, Status};
 #[cfg(feature = "exts")]
-use alloc_api::{vec, vec::Vec};
+use crate::{CString16, ResultExt};
+#[cfg(feature = "exts")]
+use alloc_api::{collections::BTreeSet, vec, vec::Vec};
 use bitflags::bitflags;
 use core::fmt::{Debug, Formatter};
 #[cfg(feature = "exts")]
 use core::mem;
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, Ordering};
 use core::{fmt, ptr};
+
+/// Call a runtime service with `call_service!`, or return
+/// `Status::UNSUPPORTED` if the firmware declared it unsupported at runtime.
+macro_rules! call_runtime {
+    ($service:ident, $($call:tt)*) => {
+        if runtime_supports(RtService::$service) {
+            call_service!($($call)*)
+        } else {
+            Status::UNSUPPORTED
+        }
+    };
+}
+
 /// Contains pointers to all of the runtime services.
 ///
 /// This table, and the function pointers it contains are valid
 /// even after the UEFI OS loader and OS have taken control of the platform.
-#[repr(C)]
+///
+/// Some firmware, such as U-Boot, leaves the services it does not implement
+/// null, so the wrappers fail with `UNSUPPORTED` for them.
+#[repr(transparent)]
 pub struct RuntimeServices {
-    header: Header,
-    get_time:
-        unsafe extern "efiapi" fn(time: *mut Time, capabilities: *mut TimeCapabilities) -> Status,
-    set_time: unsafe extern "efiapi" fn(time: &Time) -> Status,
-    // Skip some useless functions.
-    _pad: [usize; 2],
-    set_virtual_address_map: unsafe extern "efiapi" fn(
-        map_size: usize,
-        desc_size: usize,
-        desc_version: u32,
-        virtual_map: *mut MemoryDescriptor,
-    ) -> Status,
-    _pad2: usize,
-    get_variable: unsafe extern "efiapi" fn(
-        variable_name: *const Char16,
-        vendor_guid: *const Guid,
-        attributes: *mut VariableAttributes,
-        data_size: *mut usize,
-        data: *mut u8,
-    ) -> Status,
-    get_next_variable_name: unsafe extern "efiapi" fn(
-        variable_name_size: *mut usize,
-        variable_name: *mut u16,
-        vendor_guid: *mut Guid,
-    ) -> Status,
-    set_variable: unsafe extern "efiapi" fn(
-        variable_name: *const Char16,
-        vendor_guid: *const Guid,
-        attributes: VariableAttributes,
-        data_size: usize,
-        data: *const u8,
-    ) -> Status,
-    _pad3: usize,
-    reset: unsafe extern "efiapi" fn(
-        rt: ResetType,
-
-        status: Status,
-        data_size: usize,
-        data: *const u8,
-    ) -> !,
+    raw: crate::raw::RuntimeServices,
 }
 
 impl RuntimeServices {
+    /// Revision of the specification which the table conforms to
+    pub fn revision(&self) -> Revision {
+        self.raw.header.revision
+    }
+
+    /// Whether the firmware supports `services` in the current phase.
+    ///
+    /// All the services are supported while boot services are active. Once
+    /// `SystemTable::exit_boot_services` succeeds, the firmware may only
+    /// support those listed by its `RtPropertiesTable`, and the wrappers of
+    /// the other ones fail with `UNSUPPORTED` instead of calling them.
+    pub fn supports(&self, services: RtService) -> bool {
+        runtime_supports(services)
+    }
+
+    /// Return the raw definition of this table, which gives access to the
+    /// services which have no safe wrapper
+    pub fn as_raw(&self) -> &crate::raw::RuntimeServices {
+        &self.raw
+    }
+
     /// Query the current time and date information
     pub fn get_time(&self) -> Result<Time> {
         let mut time = MaybeUninit::<Time>::uninit();
-        unsafe { (self.get_time)(time.as_mut_ptr(), ptr::null_mut()) }
-            .into_with_val(|| unsafe { time.assume_init() })
+        unsafe {
+            call_runtime!(
+                GET_TIME,
+                self.raw.get_time(time.as_mut_ptr(), ptr::null_mut())
+            )
+        }
+        .into_with_val(|| unsafe { time.assume_init() })
     }
 
     /// Query the current time and date information and the RTC capabilities
     pub fn get_time_and_caps(&self) -> Result<(Time, TimeCapabilities)> {
         let mut time = MaybeUninit::<Time>::uninit();
         let mut caps = MaybeUninit::<TimeCapabilities>::uninit();
-        unsafe { (self.get_time)(time.as_mut_ptr(), caps.as_mut_ptr()) }
-            .into_with_val(|| unsafe { (time.assume_init(), caps.assume_init()) })
+        unsafe {
+            call_runtime!(
+                GET_TIME,
+                self.raw.get_time(time.as_mut_ptr(), caps.as_mut_ptr())
+            )
+        }
+        .into_with_val(|| unsafe { (time.assume_init(), caps.assume_init()) })
     }
 
     /// Sets the current local time and date information
@@ -88,7 +100,7 @@ impl RuntimeServices {
     /// Undefined behavior could happen if multiple tasks try to
     /// use this function at the same time without synchronisation.
     pub unsafe fn set_time(&mut self, time: &Time) -> Result {
-        (self.set_time)(time).into()
+        call_runtime!(SET_TIME, self.raw.set_time(time)).into()
     }
 
     /// Changes the runtime addressing mode of EFI firmware from physical to virtual.
@@ -105,7 +117,12 @@ impl RuntimeServices {
         let entry_size = core::mem::size_of::<MemoryDescriptor>();
         let entry_version = crate::table::boot::MEMORY_DESCRIPTOR_VERSION;
         let map_ptr = map.as_mut_ptr();
-        (self.set_virtual_address_map)(map_size, entry_size, entry_version, map_ptr).into()
+        call_runtime!(
+            SET_VIRTUAL_ADDRESS_MAP,
+            self.raw
+                .set_virtual_address_map(map_size, entry_size, entry_version, map_ptr)
+        )
+        .into()
     }
 
     /// Get the size (in bytes) of a variable. This can be used to find out how
@@ -113,12 +130,15 @@ impl RuntimeServices {
     pub fn get_variable_size(&self, name: &CStr16, vendor: &VariableVendor) -> Result<usize> {
         let mut data_size = 0;
         let status = unsafe {
-            (self.get_variable)(
-                name.as_ptr(),
-                &vendor.0,
-                ptr::null_mut(),
-                &mut data_size,
-                ptr::null_mut(),
+            call_runtime!(
+                GET_VARIABLE,
+                self.raw.get_variable(
+                    name.as_ptr(),
+                    &vendor.0,
+                    ptr::null_mut(),
+                    &mut data_size,
+                    ptr::null_mut(),
+                )
             )
         };
 
@@ -141,17 +161,23 @@ impl RuntimeServices {
         vendor: &VariableVendor,
         buf: &'a mut [u8],
     ) -> Result<(&'a [u8], VariableAttributes)> {
-        let mut attributes = VariableAttributes::empty();
+        let mut attributes = 0;
         let mut data_size = buf.len();
         unsafe {
-            (self.get_variable)(
-                name.as_ptr(),
-                &vendor.0,
-                &mut attributes,
-                &mut data_size,
-                buf.as_mut_ptr(),
+            call_runtime!(
+                GET_VARIABLE,
+                self.raw.get_variable(
+                    name.as_ptr(),
+                    &vendor.0,
+                    &mut attributes,
+                    &mut data_size,
+                    buf.as_mut_ptr(),
+                )
             )
-            .into_with_val(move || (&buf[..data_size], attributes))
+            .into_with_val(move || {
+                let attributes = VariableAttributes::from_bits_truncate(attributes);
+                (&buf[..data_size], attributes)
+            })
         }
     }
 
@@ -159,61 +185,37 @@ impl RuntimeServices {
     #[cfg(feature = "exts")]
     pub fn variable_keys(&self) -> Result<Vec<VariableKey>> {
         let mut all_variables = Vec::new();
-
-        // The initial value of name must start with a null character. Start
-        // out with a reasonable size that likely won't need to be increased.
-        let mut name = vec![0u16; 32];
-        // The initial value of vendor is ignored.
-        let mut vendor = Guid::default();
-
-        let mut status;
-        loop {
-            let mut name_size_in_bytes = name.len() * mem::size_of::<u16>();
-            status = unsafe {
-                (self.get_next_variable_name)(
-                    &mut name_size_in_bytes,
-                    name.as_mut_ptr(),
-                    &mut vendor,
-                )
-            };
-
-            match status {
-                Status::SUCCESS => {
-                    // CStr16::from_u16_with_nul does not allow interior nulls,
-                    // so make the copy exactly the right size.
-                    let name = if let Some(nul_pos) = name.iter().position(|c| *c == 0) {
-                        name[..=nul_pos].to_vec()
-                    } else {
-                        status = Status::ABORTED;
-                        break;
-                    };
-
-                    all_variables.push(VariableKey {
-                        name,
-                        vendor: VariableVendor(vendor),
-                    });
-                }
-                Status::BUFFER_TOO_SMALL => {
-                    // The name buffer passed in was too small, resize it to be
-                    // big enough for the next variable name.
-                    name.resize(name_size_in_bytes / 2, 0);
-                }
-                Status::NOT_FOUND => {
-                    // This status indicates the end of the list. The final
-                    // variable has already been received at this point, so
-                    // no new variable should be added to the output.
-                    status = Status::SUCCESS;
-                    break;
-                }
-                _ => {
-                    // For anything else, an error has occurred so break out of
-                    // the loop and return it.
-                    break;
-                }
-            }
+        for key in self.iter_variable_keys() {
+            let (name, vendor) = key.log_warning()?;
+            all_variables.push(VariableKey {
+                name: name.to_u16_slice_with_nul().to_vec(),
+                vendor: VariableVendor(vendor),
+            });
         }
+        Ok(all_variables.into())
+    }
 
-        status.into_with_val(|| all_variables)
+    /// Iterate over the names and vendor GUIDs of the variables.
+    ///
+    /// The variables are enumerated lazily, so the set of variables may
+    /// change during the iteration. If the variable returned last is deleted,
+    /// the enumeration starts over, and the variables which were already
+    /// returned are skipped. Variables which are created during the iteration
+    /// may or may not be returned.
+    #[cfg(feature = "exts")]
+    pub fn iter_variable_keys(&self) -> VariableKeys<'_> {
+        VariableKeys {
+            rt: self,
+            // The initial value of name must start with a null character.
+            // Start out with a reasonable size that likely won't need to be
+            // increased.
+            name: vec![0; 32],
+            // The initial value of vendor is ignored.
+            vendor: Guid::default(),
+            seen: BTreeSet::new(),
+            restarts: 0,
+            done: false,
+        }
     }
 
     /// Set the value of a variable. This can be used to create a new variable,
@@ -227,19 +229,35 @@ impl RuntimeServices {
         data: &[u8],
     ) -> Result {
         unsafe {
-            (self.set_variable)(
-                name.as_ptr(),
-                &vendor.0,
-                attributes,
-                data.len(),
-                data.as_ptr(),
+            call_runtime!(
+                SET_VARIABLE,
+                self.raw.set_variable(
+                    name.as_ptr(),
+                    &vendor.0,
+                    attributes.bits(),
+                    data.len(),
+                    data.as_ptr(),
+                )
             )
             .into()
         }
     }
 
     /// Resets the computer.
+    ///
+    /// If the firmware does not implement the service, or declared it
+    /// unsupported at runtime, this spins forever instead, since it cannot
+    /// return. Use `try_reset` to try another way in that case.
     pub fn reset(&self, rt: ResetType, status: Status, data: Option<&[u8]>) -> ! {
+        let _ = self.try_reset(rt, status, data);
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Resets the computer, or returns `UNSUPPORTED` if the firmware does not
+    /// implement the service, or declared it unsupported at runtime.
+    pub fn try_reset(&self, rt: ResetType, status: Status, data: Option<&[u8]>) -> Status {
         let (size, data) = match data {
             // FIXME: The UEFI spec states that the data must start with a NUL-
             //        terminated string, which we should check... but it does not
@@ -251,10 +269,74 @@ impl RuntimeServices {
             None => (0, ptr::null()),
         };
 
-        unsafe { (self.reset)(rt, status, size, data) }
+        match self.raw.reset_system {
+            Some(reset) if self.supports(RtService::RESET_SYSTEM) => unsafe {
+                reset(rt as u32, status, size, data)
+            },
+            _ => Status::UNSUPPORTED,
+        }
+    }
+}
+
+bitflags! {
+    /// Runtime services, as listed by the `RtPropertiesTable`
+    pub struct RtService: u32 {
+        #[allow(missing_docs)]
+        const GET_TIME = 0x0001;
+        #[allow(missing_docs)]
+        const SET_TIME = 0x0002;
+        #[allow(missing_docs)]
+        const GET_WAKEUP_TIME = 0x0004;
+        #[allow(missing_docs)]
+        const SET_WAKEUP_TIME = 0x0008;
+        #[allow(missing_docs)]
+        const GET_VARIABLE = 0x0010;
+        #[allow(missing_docs)]
+        const GET_NEXT_VARIABLE_NAME = 0x0020;
+        #[allow(missing_docs)]
+        const SET_VARIABLE = 0x0040;
+        #[allow(missing_docs)]
+        const SET_VIRTUAL_ADDRESS_MAP = 0x0080;
+        #[allow(missing_docs)]
+        const CONVERT_POINTER = 0x0100;
+        #[allow(missing_docs)]
+        const GET_NEXT_HIGH_MONOTONIC_COUNT = 0x0200;
+        #[allow(missing_docs)]
+        const RESET_SYSTEM = 0x0400;
+        #[allow(missing_docs)]
+        const UPDATE_CAPSULE = 0x0800;
+        #[allow(missing_docs)]
+        const QUERY_CAPSULE_CAPABILITIES = 0x1000;
+        #[allow(missing_docs)]
+        const QUERY_VARIABLE_INFO = 0x2000;
     }
 }
 
+/// Services which the firmware supports after boot services are exited, as
+/// recorded by `record_runtime_services`
+static RUNTIME_SUPPORTED: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Record the services which stay supported after boot services are exited,
+/// from the `RtPropertiesTable` of `config_table`.
+///
+/// The table is read before the exit, since it may be stored in boot
+/// services memory. Firmware which publishes none supports all services.
+///
+/// # Safety
+///
+/// The configuration table must be the one of the system table.
+pub(crate) unsafe fn record_runtime_services(config_table: &[ConfigTableEntry]) {
+    let supported = RtPropertiesTable::find(config_table)
+        .map_or(RtService::all(), |table| table.runtime_services_supported);
+    RUNTIME_SUPPORTED.store(supported.bits(), Ordering::Relaxed);
+}
+
+fn runtime_supports(services: RtService) -> bool {
+    boot_services_active()
+        || RtService::from_bits_truncate(RUNTIME_SUPPORTED.load(Ordering::Relaxed))
+            .contains(services)
+}
+
 impl super::Table for RuntimeServices {
     const SIGNATURE: u64 = 0x5652_4553_544e_5552;
 }
@@ -262,14 +344,29 @@ impl super::Table for RuntimeServices {
 impl Debug for RuntimeServices {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("RuntimeServices")
-            .field("header", &self.header)
-            .field("get_time", &(self.get_time as *const u64))
-            .field("set_time", &(self.set_time as *const u64))
+            .field("header", &self.raw.header)
+            .field(
+                "get_time",
+                &self.raw.get_time.map_or(ptr::null(), |f| f as *const u64),
+            )
+            .field(
+                "set_time",
+                &self.raw.set_time.map_or(ptr::null(), |f| f as *const u64),
+            )
             .field(
                 "set_virtual_address_map",
-                &(self.set_virtual_address_map as *const u64),
+                &self
+                    .raw
+                    .set_virtual_address_map
+                    .map_or(ptr::null(), |f| f as *const u64),
+            )
+            .field(
+                "reset",
+                &self
+                    .raw
+                    .reset_system
+                    .map_or(ptr::null(), |f| f as *const u64),
             )
-            .field("reset", &(self.reset as *const u64))
             .finish()
     }
 }
@@ -341,6 +438,39 @@ impl Time {
         }
     }
 
+    /// Build an all-zero time
+    ///
+    /// This is not a valid time. Some interfaces, like `FileInfo`, use it to
+    /// mean that a time is not set.
+    pub fn zero() -> Self {
+        Self {
+            year: 0,
+            month: 0,
+            day: 0,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            _pad1: 0,
+            nanosecond: 0,
+            time_zone: 0,
+            daylight: Daylight::empty(),
+            _pad2: 0,
+        }
+    }
+
+    /// Query whether all the fields of this time are zero
+    pub fn is_zero(&self) -> bool {
+        self.year == 0
+            && self.month == 0
+            && self.day == 0
+            && self.hour == 0
+            && self.minute == 0
+            && self.second == 0
+            && self.nanosecond == 0
+            && self.time_zone == 0
+            && self.daylight.is_empty()
+    }
+
     /// Query the year
     pub fn year(&self) -> u16 {
         self.year
@@ -516,6 +646,98 @@ newtype_enum! {
     }
 }
 
+/// Iterator over the names and vendor GUIDs of the variables, returned by
+/// `RuntimeServices::iter_variable_keys`
+///
+/// Iteration stops after the last variable, or after the first error.
+#[cfg(feature = "exts")]
+pub struct VariableKeys<'a> {
+    rt: &'a RuntimeServices,
+    /// Name of the variable returned last, which grows as needed
+    name: Vec<u16>,
+    vendor: Guid,
+    /// Variables returned so far, which are skipped after a restart
+    seen: BTreeSet<(CString16, Guid)>,
+    restarts: usize,
+    done: bool,
+}
+
+#[cfg(feature = "exts")]
+impl VariableKeys<'_> {
+    /// Give up on firmware which keeps deleting variables
+    const MAX_RESTARTS: usize = 8;
+
+    fn current_key(&self) -> Option<CString16> {
+        let nul_pos = self.name.iter().position(|c| *c == 0)?;
+        let name = CStr16::from_u16_with_nul(&self.name[..=nul_pos]).ok()?;
+        Some(CString16::from(name))
+    }
+}
+
+#[cfg(feature = "exts")]
+impl Iterator for VariableKeys<'_> {
+    type Item = Result<(CString16, Guid)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let mut name_size_in_bytes = self.name.len() * mem::size_of::<u16>();
+            let status = unsafe {
+                call_runtime!(
+                    GET_NEXT_VARIABLE_NAME,
+                    self.rt.raw.get_next_variable_name(
+                        &mut name_size_in_bytes,
+                        self.name.as_mut_ptr().cast(),
+                        &mut self.vendor,
+                    )
+                )
+            };
+
+            match status {
+                Status::SUCCESS => {
+                    let name = match self.current_key() {
+                        Some(name) => name,
+                        None => {
+                            self.done = true;
+                            return Some(Err(Status::ABORTED.into()));
+                        }
+                    };
+                    let key = (name, self.vendor);
+                    if !self.seen.insert(key.clone()) && self.restarts != 0 {
+                        continue;
+                    }
+                    return Some(Ok(key.into()));
+                }
+                Status::BUFFER_TOO_SMALL if name_size_in_bytes / 2 > self.name.len() => {
+                    // The name buffer passed in was too small, resize it to be
+                    // big enough for the next variable name. This keeps the
+                    // name of the previous variable, which is still needed.
+                    self.name.resize(name_size_in_bytes / 2, 0);
+                }
+                Status::NOT_FOUND => {
+                    // This status indicates the end of the list.
+                    self.done = true;
+                    return None;
+                }
+                Status::INVALID_PARAMETER
+                    if self.name[0] != 0 && self.restarts < Self::MAX_RESTARTS =>
+                {
+                    // The variable returned last does not exist anymore, so
+                    // there is no way to find the next one but to start over.
+                    self.restarts += 1;
+                    self.name[0] = 0;
+                }
+                _ => {
+                    self.done = true;
+                    return Some(Err(status.into()));
+                }
+            }
+        }
+    }
+}
+
 /// Unique key for a variable.
 #[cfg(feature = "exts")]
 #[derive(Debug)]
@@ -1,12 +1,14 @@
+use core::ffi::c_void;
 use core::marker::PhantomData;
-use core::{ptr, slice};
+use core::slice;
 
 use crate::proto::console::text;
-use crate::{CStr16, Char16, Handle, Result, ResultExt, Status};
+use crate::raw;
+use crate::{CStr16, Handle, Result, ResultExt, Status};
 
 use super::boot::{BootServices, MemoryDescriptor};
 use super::runtime::RuntimeServices;
-use super::{cfg, Header, Revision};
+use super::{cfg, Revision};
 use core::fmt::{Debug, Formatter};
 
 /// Marker trait used to provide different views of the UEFI System Table
@@ -41,23 +43,65 @@ impl SystemTableView for Runtime {}
 /// table will be destroyed (which conveniently invalidates all references to
 /// UEFI boot services in the eye of the Rust borrow checker) and a runtime view
 /// will be provided to replace it.
+///
+/// Neither view implements `Clone` or `Copy`, so that the boot view cannot
+/// outlive the call to `exit_boot_services` which consumes it. Code which
+/// really needs a second handle to the boot view can use the `unsafe_clone`
+/// method, and take responsibility for not using it after boot services are
+/// exited.
 #[repr(transparent)]
-#[derive(Debug)]
 pub struct SystemTable<View: SystemTableView> {
-    table: &'static SystemTableImpl,
+    table: &'static raw::SystemTable,
     _marker: PhantomData<View>,
 }
 
 // These parts of the UEFI System Table interface will always be available
 impl<View: SystemTableView> SystemTable<View> {
+    /// Create a `SystemTable` view from a raw pointer to the UEFI System Table
+    ///
+    /// Returns `None` if the pointer is null or does not point to a table with
+    /// the System Table signature.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to a valid UEFI System Table which remains valid
+    /// for the lifetime of the program. In addition, the `View` must match the
+    /// current state of the firmware: a `SystemTable<Boot>` may only be created
+    /// before boot services are exited, and must not coexist with another boot
+    /// view that could be used to exit boot services behind its back.
+    pub unsafe fn from_ptr(ptr: *mut c_void) -> Option<Self> {
+        let table = (ptr as *const raw::SystemTable).as_ref()?;
+        if table.header.signature != <Self as super::Table>::SIGNATURE {
+            return None;
+        }
+        Some(SystemTable {
+            table,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Get the underlying raw pointer to the UEFI System Table
+    ///
+    /// This is useful to hand the System Table over to code which is not
+    /// written in Rust, such as an OS kernel or a chainloaded image.
+    pub fn as_ptr(&self) -> *const c_void {
+        self.table as *const raw::SystemTable as *const c_void
+    }
+
+    /// Return the raw definition of the System Table, which gives access to
+    /// the services and protocols which have no safe wrapper
+    pub fn as_raw(&self) -> &raw::SystemTable {
+        self.table
+    }
+
     /// Return the firmware vendor string
     pub fn firmware_vendor(&self) -> &CStr16 {
-        unsafe { CStr16::from_ptr(self.table.fw_vendor) }
+        unsafe { CStr16::from_ptr(self.table.firmware_vendor) }
     }
 
     /// Return the firmware revision
     pub fn firmware_revision(&self) -> Revision {
-        self.table.fw_revision
+        self.table.firmware_revision
     }
 
     /// Returns the revision of this table, which is defined to be
@@ -69,7 +113,12 @@ impl<View: SystemTableView> SystemTable<View> {
     /// Returns the config table entries, a linear array of structures
     /// pointing to other system-specific tables.
     pub fn config_table(&self) -> &[cfg::ConfigTableEntry] {
-        unsafe { slice::from_raw_parts(self.table.cfg_table, self.table.nr_cfg) }
+        // The pointer may be null when there are no entries
+        let len = self.table.number_of_configuration_table_entries;
+        if len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.table.configuration_table, len) }
     }
 }
 
@@ -78,7 +127,7 @@ impl<View: SystemTableView> SystemTable<View> {
 impl SystemTable<Boot> {
     /// Returns the standard input protocol.
     pub fn stdin(&mut self) -> &mut text::Input {
-        unsafe { &mut *self.table.stdin }
+        unsafe { &mut *self.table.stdin.cast() }
     }
 
     /// Returns the standard output protocol.
@@ -93,12 +142,12 @@ impl SystemTable<Boot> {
 
     /// Access runtime services
     pub fn runtime_services(&self) -> &RuntimeServices {
-        self.table.runtime
+        unsafe { &*self.table.runtime_services.cast() }
     }
 
     /// Access boot services
     pub fn boot_services(&self) -> &BootServices {
-        unsafe { &*self.table.boot }
+        unsafe { &*self.table.boot_services.cast() }
     }
 
     /// Exit the UEFI boot services
@@ -142,6 +191,7 @@ impl SystemTable<Boot> {
     )> {
         unsafe {
             let boot_services = self.boot_services();
+            super::runtime::record_runtime_services(self.config_table());
 
             loop {
                 // Fetch a memory map, propagate errors and split the completion
@@ -190,7 +240,22 @@ impl SystemTable<Boot> {
 
 impl Debug for SystemTable<Boot> {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        self.table.fmt(f)
+        let table = self.table;
+        f.debug_struct("UefiSystemTable")
+            .field("header", &table.header)
+            .field("fw_vendor", &self.firmware_vendor())
+            .field("fw_revision", &table.firmware_revision)
+            .field("stdin_handle", &table.stdin_handle)
+            .field("stdin", &table.stdin)
+            .field("stdout_handle", &table.stdout_handle)
+            .field("stdout", &table.stdout)
+            .field("stderr_handle", &table.stderr_handle)
+            .field("stderr", &table.stderr)
+            .field("runtime", self.runtime_services())
+            .field("boot", self.boot_services())
+            .field("nf_cfg", &table.number_of_configuration_table_entries)
+            .field("cfg_table", &table.configuration_table)
+            .finish()
     }
 }
 
@@ -206,56 +271,10 @@ impl SystemTable<Runtime> {
     /// CPU configuration which may not be preserved by OS loaders. See the
     /// "Calling Conventions" chapter of the UEFI specification for details.
     pub unsafe fn runtime_services(&self) -> &RuntimeServices {
-        self.table.runtime
+        &*self.table.runtime_services.cast()
     }
 }
 
-/// The actual UEFI system table
-#[repr(C)]
-struct SystemTableImpl {
-    header: Header,
-    /// Null-terminated string representing the firmware's vendor.
-    fw_vendor: *const Char16,
-    /// Revision of the UEFI specification the firmware conforms to.
-    fw_revision: Revision,
-    stdin_handle: Handle,
-    stdin: *mut text::Input,
-    stdout_handle: Handle,
-    stdout: *mut text::Output<'static>,
-    stderr_handle: Handle,
-    stderr: *mut text::Output<'static>,
-    /// Runtime services table.
-    runtime: &'static RuntimeServices,
-    /// Boot services table.
-    boot: *const BootServices,
-    /// Number of entries in the configuration table.
-    nr_cfg: usize,
-    /// Pointer to beginning of the array.
-    cfg_table: *const cfg::ConfigTableEntry,
-}
-
 impl<View: SystemTableView> super::Table for SystemTable<View> {
     const SIGNATURE: u64 = 0x5453_5953_2049_4249;
 }
-
-impl Debug for SystemTableImpl {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("UefiSystemTable")
-            .field("header", &self.header)
-            .field("fw_vendor", &(unsafe { CStr16::from_ptr(self.fw_vendor) }))
-            .field("fw_revision", &self.fw_revision)
-            .field("stdin_handle", &self.stdin_handle)
-            .field("stdin", &self.stdin)
-            .field("stdout_handle", &self.stdout_handle)
-            .field("stdout", &self.stdout)
-            .field("stderr_handle", &self.stderr_handle)
-            .field("stderr", &self.stderr)
-            .field("runtime", &self.runtime)
-            // a little bit of extra work needed to call debug-fmt on the BootServices
-            // instead of printing the raw pointer
-            .field("boot", &(unsafe { ptr::read(self.boot) }))
-            .field("nf_cfg", &self.nr_cfg)
-            .field("cfg_table", &self.cfg_table)
-            .finish()
-    }
-}
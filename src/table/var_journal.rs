@@ -0,0 +1,284 @@
+//! Fault-tolerant updates of critical variables.
+//!
+//! A machine whose `BootOrder` or `Boot####` variables are left half written
+//! may not boot anymore. The firmware writes each variable atomically, but an
+//! update which spans several steps, or which must be verified, can still be
+//! interrupted by a power loss. The helpers of this module update a variable
+//! in three steps:
+//!
+//! 1. The new value, with the vendor and the attributes of the variable, is
+//!    written to a staging variable of the same name, under
+//!    `JOURNAL_VENDOR`, and read back.
+//! 2. The variable itself is written, and read back.
+//! 3. The staging variable is deleted.
+//!
+//! If the machine loses power before the end, `recover` completes the
+//! update from the staging variable at the next boot. A staging variable
+//! which was not completely written is detected with its checksum, and
+//! discarded, so that the variable keeps its old value:
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::table::runtime::{VariableAttributes, VariableVendor};
+//! # use uefi::table::var_journal;
+//! # fn f(rt: &RuntimeServices, name: &uefi::CStr16, order: &[u8]) -> uefi::Result {
+//! // Early at boot, before updating any variable
+//! let recovered = var_journal::recover(rt)?.log();
+//! if recovered != 0 {
+//!     log::warn!("Completed {} interrupted variable updates", recovered);
+//! }
+//! let attributes = VariableAttributes::NON_VOLATILE
+//!     | VariableAttributes::BOOTSERVICE_ACCESS
+//!     | VariableAttributes::RUNTIME_ACCESS;
+//! var_journal::write(rt, name, &VariableVendor::GLOBAL_VARIABLE, attributes, order)
+//! # }
+//! ```
+
+use super::runtime::{RuntimeServices, VariableAttributes, VariableVendor};
+use super::var_store::Persistence;
+use crate::alloc_api::vec::Vec;
+use crate::crc::crc32;
+use crate::{CStr16, Guid, Result, ResultExt, Status};
+use core::{mem, ptr, slice};
+
+/// Vendor of the staging variables
+pub const JOURNAL_VENDOR: VariableVendor = VariableVendor(Guid::from_values(
+    0x3f4c_9a1e,
+    0x5b27,
+    0x4d80,
+    0x9e63,
+    0x0c7a_b2f5_d418,
+));
+
+/// Size of the header of a staging variable: the CRC-32 of the rest of the
+/// record, the vendor and the attributes of the target variable
+const HEADER_SIZE: usize = 4 + mem::size_of::<Guid>() + 4;
+
+/// Set the variable `name` of `vendor` to `data`, or delete it if `data` is
+/// empty, so that the update completes even if the machine loses power
+///
+/// An interrupted update of the same variable is completed first.
+///
+/// # Errors
+///
+/// * `uefi::Status::VOLUME_CORRUPTED`  The variable, or its staging variable,
+///                                     does not hold the written value when
+///                                     read back. The staging variable is
+///                                     kept, so that `recover` tries again.
+/// * Any error of `RuntimeServices::set_variable`, in which case the
+///   variable was not changed
+pub fn write(
+    rt: &RuntimeServices,
+    name: &CStr16,
+    vendor: &VariableVendor,
+    attributes: VariableAttributes,
+    data: &[u8],
+) -> Result {
+    if let Some(record) = read_value(rt, name, &JOURNAL_VENDOR).log_warning()? {
+        complete(rt, name, &record).log_warning()?;
+    }
+
+    let record = encode(vendor, attributes, data);
+    rt.set_variable(name, &JOURNAL_VENDOR, staging_attributes(), &record)
+        .log_warning()?;
+    if read_value(rt, name, &JOURNAL_VENDOR)
+        .log_warning()?
+        .as_deref()
+        != Some(&record)
+    {
+        return Err(Status::VOLUME_CORRUPTED.into());
+    }
+
+    complete(rt, name, &record)
+}
+
+/// Complete the updates which were interrupted, and return their number
+///
+/// This should run at every boot, before the variables are used or updated.
+pub fn recover(rt: &RuntimeServices) -> Result<usize> {
+    let names: Vec<_> = rt
+        .variable_keys()
+        .log_warning()?
+        .into_iter()
+        .filter(|key| key.vendor == JOURNAL_VENDOR)
+        .filter_map(|key| key.name().ok().map(crate::CString16::from))
+        .collect();
+
+    let mut recovered = 0;
+    for name in names {
+        if let Some(record) = read_value(rt, &name, &JOURNAL_VENDOR).log_warning()? {
+            if decode(&record).is_some() {
+                recovered += 1;
+            }
+            complete(rt, &name, &record).log_warning()?;
+        }
+    }
+    Ok(recovered.into())
+}
+
+/// Apply a staging variable, and delete it
+///
+/// A record which does not decode was not completely written, and is only
+/// deleted.
+fn complete(rt: &RuntimeServices, name: &CStr16, record: &[u8]) -> Result {
+    if let Some((vendor, attributes, data)) = decode(record) {
+        match rt.set_variable(name, &vendor, attributes, data) {
+            Ok(completion) => completion.log(),
+            // Deleting a variable which was already deleted
+            Err(err) if data.is_empty() && err.status() == Status::NOT_FOUND => {}
+            Err(err) => {
+                // The variable was not changed, so the update is abandoned
+                delete_staging(rt, name).log_warning()?;
+                return Err(err);
+            }
+        }
+        let written = read_value(rt, name, &vendor).log_warning()?;
+        if written.as_deref().unwrap_or(&[]) != data {
+            return Err(Status::VOLUME_CORRUPTED.into());
+        }
+    }
+    delete_staging(rt, name)
+}
+
+fn delete_staging(rt: &RuntimeServices, name: &CStr16) -> Result {
+    match rt.set_variable(name, &JOURNAL_VENDOR, staging_attributes(), &[]) {
+        Err(err) if err.status() == Status::NOT_FOUND => Status::SUCCESS.into(),
+        other => other,
+    }
+}
+
+/// Staging variables must survive a power loss
+fn staging_attributes() -> VariableAttributes {
+    Persistence::NonVolatile.attributes()
+}
+
+/// Read a variable, or return `None` if it does not exist
+fn read_value(
+    rt: &RuntimeServices,
+    name: &CStr16,
+    vendor: &VariableVendor,
+) -> Result<Option<Vec<u8>>> {
+    super::var_store::VarStore::new(rt, *vendor).get_vec(name)
+}
+
+/// Record of a staging variable
+fn encode(vendor: &VariableVendor, attributes: VariableAttributes, data: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_SIZE + data.len());
+    record.extend_from_slice(&[0; 4]);
+    let vendor: &Guid = &vendor.0;
+    record.extend_from_slice(unsafe {
+        slice::from_raw_parts(vendor as *const Guid as *const u8, mem::size_of::<Guid>())
+    });
+    record.extend_from_slice(&attributes.bits().to_le_bytes());
+    record.extend_from_slice(data);
+    let crc = crc32(&record[4..]);
+    record[..4].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// Vendor, attributes and data of a staging variable, if its checksum is
+/// valid
+fn decode(record: &[u8]) -> Option<(VariableVendor, VariableAttributes, &[u8])> {
+    if record.len() < HEADER_SIZE {
+        return None;
+    }
+    let (crc, rest) = record.split_at(4);
+    if crc32(rest).to_le_bytes() != crc {
+        return None;
+    }
+    let (vendor, rest) = rest.split_at(mem::size_of::<Guid>());
+    let vendor = unsafe { ptr::read_unaligned(vendor.as_ptr() as *const Guid) };
+    let (attributes, data) = rest.split_at(4);
+    let attributes =
+        u32::from_le_bytes([attributes[0], attributes[1], attributes[2], attributes[3]]);
+    Some((
+        VariableVendor(vendor),
+        VariableAttributes::from_bits_truncate(attributes),
+        data,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::CString16;
+    use core::convert::TryFrom;
+
+    fn name(name: &str) -> CString16 {
+        CString16::try_from(name).unwrap()
+    }
+
+    const VENDOR: VariableVendor = VariableVendor(Guid::from_values(0x6a11_0001, 0, 0, 0, 0));
+
+    #[test]
+    fn records() {
+        let attributes = Persistence::Volatile.attributes();
+        let record = encode(&VENDOR, attributes, b"order");
+        let (vendor, decoded, data) = decode(&record).unwrap();
+        assert_eq!(vendor, VENDOR);
+        assert_eq!(decoded, attributes);
+        assert_eq!(data, b"order");
+
+        // A partial or damaged record is rejected
+        assert!(decode(&record[..record.len() - 1]).is_none());
+        assert!(decode(&record[..10]).is_none());
+        let mut damaged = record.clone();
+        damaged[HEADER_SIZE] ^= 1;
+        assert!(decode(&damaged).is_none());
+    }
+
+    #[test]
+    fn interrupted_writes() {
+        let st = MockSystemTable::new().system_table();
+        let rt = st.runtime_services();
+        let attributes = Persistence::NonVolatile.attributes();
+
+        write(rt, &name("JournalA"), &VENDOR, attributes, b"old").unwrap_success();
+        assert_eq!(
+            read_value(rt, &name("JournalA"), &VENDOR).unwrap_success(),
+            Some(b"old".to_vec())
+        );
+        assert_eq!(
+            read_value(rt, &name("JournalA"), &JOURNAL_VENDOR).unwrap_success(),
+            None
+        );
+
+        // Power was lost after the staging variable was written
+        let record = encode(&VENDOR, attributes, b"new");
+        rt.set_variable(
+            &name("JournalA"),
+            &JOURNAL_VENDOR,
+            staging_attributes(),
+            &record,
+        )
+        .unwrap_success();
+        // and while the staging variable of another update was written
+        let record = encode(&VENDOR, attributes, b"lost");
+        rt.set_variable(
+            &name("JournalB"),
+            &JOURNAL_VENDOR,
+            staging_attributes(),
+            &record[..record.len() - 1],
+        )
+        .unwrap_success();
+
+        assert_eq!(recover(rt).unwrap_success(), 1);
+        assert_eq!(
+            read_value(rt, &name("JournalA"), &VENDOR).unwrap_success(),
+            Some(b"new".to_vec())
+        );
+        assert_eq!(
+            read_value(rt, &name("JournalB"), &VENDOR).unwrap_success(),
+            None
+        );
+        assert_eq!(recover(rt).unwrap_success(), 0);
+
+        // An empty value deletes the variable
+        write(rt, &name("JournalA"), &VENDOR, attributes, &[]).unwrap_success();
+        assert_eq!(
+            read_value(rt, &name("JournalA"), &VENDOR).unwrap_success(),
+            None
+        );
+    }
+}
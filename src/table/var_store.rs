@@ -0,0 +1,338 @@
+//! Typed key/value storage on top of UEFI variables.
+//!
+//! UEFI variables are untyped blobs, identified by a name and a vendor GUID.
+//! A [`VarStore`] uses one vendor GUID as a namespace and provides typed
+//! accessors for the values that applications usually persist, such as
+//! integers, booleans and strings.
+
+use super::runtime::{RuntimeServices, VariableAttributes, VariableVendor};
+use crate::result::Error;
+#[cfg(feature = "exts")]
+use crate::{
+    alloc_api::{vec, vec::Vec},
+    CString16,
+};
+use crate::{CStr16, Completion, Result, Status};
+use core::mem;
+
+/// Size limit applied to the values written by a store, unless configured
+/// otherwise
+///
+/// Non-volatile storage is scarce on many machines, and some implementations
+/// reject variables which are larger than a few kilobytes.
+pub const DEFAULT_MAX_SIZE: usize = 1024;
+
+/// How long the values written by a store are kept
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Persistence {
+    /// Values are kept until the next reset.
+    Volatile,
+    /// Values are written to non-volatile storage, and survive resets.
+    NonVolatile,
+}
+
+impl Persistence {
+    /// Attributes of the variables written with this persistence, which are
+    /// accessible both before and after boot services are exited
+    pub fn attributes(self) -> VariableAttributes {
+        let access = VariableAttributes::BOOTSERVICE_ACCESS | VariableAttributes::RUNTIME_ACCESS;
+        match self {
+            Persistence::Volatile => access,
+            Persistence::NonVolatile => access | VariableAttributes::NON_VOLATILE,
+        }
+    }
+}
+
+/// A namespace of UEFI variables, with typed accessors
+///
+/// Reading a value which does not exist is not an error, and returns `None`.
+/// Reading a value with the wrong type, or rather the wrong size, fails with
+/// `BAD_BUFFER_SIZE`. Integers are stored in little endian order, booleans as
+/// a single byte, and strings as null-terminated UCS-2.
+#[derive(Debug, Copy, Clone)]
+pub struct VarStore<'rt> {
+    rt: &'rt RuntimeServices,
+    vendor: VariableVendor,
+    attributes: VariableAttributes,
+    max_size: usize,
+}
+
+macro_rules! int_accessors {
+    ($($ty:ty, $get:ident, $set:ident;)*) => {
+        $(
+            #[doc = concat!("Read a `", stringify!($ty), "` value")]
+            pub fn $get(&self, name: &CStr16) -> Result<Option<$ty>> {
+                let mut buf = [0; mem::size_of::<$ty>()];
+                self.get_exact(name, &mut buf)
+                    .map(|completion| completion.map(|found| found.map(|()| <$ty>::from_le_bytes(buf))))
+            }
+
+            #[doc = concat!("Write a `", stringify!($ty), "` value")]
+            pub fn $set(&self, name: &CStr16, value: $ty) -> Result {
+                self.set_raw(name, &value.to_le_bytes())
+            }
+        )*
+    };
+}
+
+impl<'rt> VarStore<'rt> {
+    /// Create a store for the variables of `vendor`, which writes
+    /// non-volatile values of up to `DEFAULT_MAX_SIZE` bytes
+    pub fn new(rt: &'rt RuntimeServices, vendor: VariableVendor) -> Self {
+        VarStore {
+            rt,
+            vendor,
+            attributes: Persistence::NonVolatile.attributes(),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Use one of the attribute presets for the values written by the store
+    pub fn persistence(self, persistence: Persistence) -> Self {
+        self.attributes(persistence.attributes())
+    }
+
+    /// Use custom attributes for the values written by the store
+    pub fn attributes(mut self, attributes: VariableAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Reject values larger than `max_size` bytes with `BAD_BUFFER_SIZE`
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Vendor GUID of the variables of the store
+    pub fn vendor(&self) -> VariableVendor {
+        self.vendor
+    }
+
+    /// Read a raw value into `buf`, returning the part of `buf` that it fills
+    ///
+    /// Fails with `BUFFER_TOO_SMALL` if the value does not fit in `buf`.
+    pub fn get_raw<'buf>(&self, name: &CStr16, buf: &'buf mut [u8]) -> Result<Option<&'buf [u8]>> {
+        match self.rt.get_variable(name, &self.vendor, buf) {
+            Ok(completion) => Ok(completion.map(|(data, _)| Some(data))),
+            Err(err) if err.status() == Status::NOT_FOUND => Ok(None.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Read a raw value into a newly allocated buffer
+    #[cfg(feature = "exts")]
+    pub fn get_vec(&self, name: &CStr16) -> Result<Option<Vec<u8>>> {
+        let size = match self.rt.get_variable_size(name, &self.vendor) {
+            Ok(size) => size.log(),
+            Err(err) if err.status() == Status::NOT_FOUND => return Ok(None.into()),
+            Err(err) => return Err(err),
+        };
+        let mut buf = vec![0; size];
+        let completion = self.get_raw(name, &mut buf)?;
+        let (status, len) = completion.map(|data| data.map(|data| data.len())).split();
+        Ok(Completion::new(
+            status,
+            len.map(|len| {
+                buf.truncate(len);
+                buf
+            }),
+        ))
+    }
+
+    /// Write a raw value
+    pub fn set_raw(&self, name: &CStr16, data: &[u8]) -> Result {
+        if data.len() > self.max_size {
+            return Err(Status::BAD_BUFFER_SIZE.into());
+        }
+        if data.is_empty() {
+            // Writing an empty value would delete the variable
+            return Err(Status::INVALID_PARAMETER.into());
+        }
+        self.rt
+            .set_variable(name, &self.vendor, self.attributes, data)
+    }
+
+    /// Whether a value exists
+    pub fn contains(&self, name: &CStr16) -> Result<bool> {
+        match self.rt.get_variable_size(name, &self.vendor) {
+            Ok(completion) => Ok(completion.map(|_| true)),
+            Err(err) if err.status() == Status::NOT_FOUND => Ok(false.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Delete a value, if it exists
+    pub fn remove(&self, name: &CStr16) -> Result {
+        match self
+            .rt
+            .set_variable(name, &self.vendor, self.attributes, &[])
+        {
+            Err(err) if err.status() == Status::NOT_FOUND => Status::SUCCESS.into(),
+            other => other,
+        }
+    }
+
+    int_accessors! {
+        u8, get_u8, set_u8;
+        u16, get_u16, set_u16;
+        u32, get_u32, set_u32;
+        u64, get_u64, set_u64;
+        i32, get_i32, set_i32;
+        i64, get_i64, set_i64;
+    }
+
+    /// Read a boolean value
+    pub fn get_bool(&self, name: &CStr16) -> Result<Option<bool>> {
+        let value = self.get_u8(name)?;
+        Ok(value.map(|value| value.map(|value| value != 0)))
+    }
+
+    /// Write a boolean value
+    pub fn set_bool(&self, name: &CStr16, value: bool) -> Result {
+        self.set_u8(name, value as u8)
+    }
+
+    /// Read a string value into `buf`
+    ///
+    /// Fails with `BUFFER_TOO_SMALL` if the string does not fit in `buf`, and
+    /// with `BAD_BUFFER_SIZE` if the value is not a valid string.
+    pub fn get_str<'buf>(
+        &self,
+        name: &CStr16,
+        buf: &'buf mut [u16],
+    ) -> Result<Option<&'buf CStr16>> {
+        // u8 has a smaller alignment than u16, so the cast is fine
+        let bytes =
+            unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 2) };
+        let completion = self.get_raw(name, bytes)?;
+        let (status, len) = completion.map(|data| data.map(|data| data.len())).split();
+        match len {
+            None => Ok(None.into()),
+            Some(len) if len % 2 == 0 => match CStr16::from_u16_with_nul(&buf[..len / 2]) {
+                Ok(string) => Ok(Completion::new(status, Some(string))),
+                Err(_) => Err(Status::BAD_BUFFER_SIZE.into()),
+            },
+            Some(_) => Err(Status::BAD_BUFFER_SIZE.into()),
+        }
+    }
+
+    /// Read a string value into a newly allocated string
+    #[cfg(feature = "exts")]
+    pub fn get_string(&self, name: &CStr16) -> Result<Option<CString16>> {
+        let size = match self.rt.get_variable_size(name, &self.vendor) {
+            Ok(size) => size.log(),
+            Err(err) if err.status() == Status::NOT_FOUND => return Ok(None.into()),
+            Err(err) => return Err(err),
+        };
+        let mut buf = vec![0; (size + 1) / 2];
+        let completion = self.get_str(name, &mut buf)?;
+        Ok(completion.map(|string| string.map(CString16::from)))
+    }
+
+    /// Write a string value
+    pub fn set_str(&self, name: &CStr16, value: &CStr16) -> Result {
+        let chars = value.to_u16_slice_with_nul();
+        let bytes =
+            unsafe { core::slice::from_raw_parts(chars.as_ptr() as *const u8, chars.len() * 2) };
+        self.set_raw(name, bytes)
+    }
+
+    /// Read a value which must have the exact size of `buf`
+    fn get_exact(&self, name: &CStr16, buf: &mut [u8]) -> Result<Option<()>> {
+        let expected = buf.len();
+        match self.get_raw(name, buf) {
+            Ok(completion) => {
+                let (status, data) = completion.split();
+                match data {
+                    Some(data) if data.len() != expected => Err(Status::BAD_BUFFER_SIZE.into()),
+                    data => Ok(Completion::new(status, data.map(|_| ()))),
+                }
+            }
+            Err(err) if err.status() == Status::BUFFER_TOO_SMALL => {
+                Err(Error::from(Status::BAD_BUFFER_SIZE))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "exts"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::{Guid, ResultExt};
+    use core::convert::TryFrom;
+
+    fn name(name: &str) -> CString16 {
+        CString16::try_from(name).unwrap()
+    }
+
+    #[test]
+    fn typed_values() {
+        let st = MockSystemTable::new().system_table();
+        let vendor = VariableVendor(Guid::from_values(0x7a85_0001, 0, 0, 0, 0));
+        let store = VarStore::new(st.runtime_services(), vendor);
+
+        assert_eq!(store.get_u32(&name("Count")).unwrap_success(), None);
+        store.set_u32(&name("Count"), 0xdead_beef).unwrap_success();
+        assert_eq!(
+            store.get_u32(&name("Count")).unwrap_success(),
+            Some(0xdead_beef)
+        );
+        assert!(store.contains(&name("Count")).unwrap_success());
+        assert_eq!(
+            store.get_u16(&name("Count")).unwrap_err().status(),
+            Status::BAD_BUFFER_SIZE
+        );
+        assert_eq!(
+            store.get_u64(&name("Count")).unwrap_err().status(),
+            Status::BAD_BUFFER_SIZE
+        );
+
+        store.set_bool(&name("Enabled"), true).unwrap_success();
+        assert_eq!(
+            store.get_bool(&name("Enabled")).unwrap_success(),
+            Some(true)
+        );
+
+        store
+            .set_str(&name("Label"), &name("Boot menu"))
+            .unwrap_success();
+        assert_eq!(
+            store.get_string(&name("Label")).unwrap_success(),
+            Some(name("Boot menu"))
+        );
+
+        store.remove(&name("Count")).unwrap_success();
+        store.remove(&name("Count")).unwrap_success();
+        assert!(!store.contains(&name("Count")).unwrap_success());
+    }
+
+    #[test]
+    fn size_limit() {
+        let st = MockSystemTable::new().system_table();
+        let vendor = VariableVendor(Guid::from_values(0x7a85_0002, 0, 0, 0, 0));
+        let store = VarStore::new(st.runtime_services(), vendor)
+            .persistence(Persistence::Volatile)
+            .max_size(4);
+
+        store.set_raw(&name("Blob"), &[1, 2, 3, 4]).unwrap_success();
+        assert_eq!(
+            store.set_raw(&name("Blob"), &[0; 5]).unwrap_err().status(),
+            Status::BAD_BUFFER_SIZE
+        );
+        assert_eq!(
+            store.get_vec(&name("Blob")).unwrap_success(),
+            Some(vec![1, 2, 3, 4])
+        );
+        let mut small = [0; 2];
+        assert_eq!(
+            store
+                .get_raw(&name("Blob"), &mut small)
+                .unwrap_err()
+                .status(),
+            Status::BUFFER_TOO_SMALL
+        );
+    }
+}
@@ -0,0 +1,759 @@
+//! Text user interface widgets.
+//!
+//! Boot menus need the same few widgets, drawn on the text console with the
+//! `Output` protocol and driven by the keys of the `Input` protocol:
+//!
+//! - `Menu` lets the user choose an entry with the arrow keys, optionally
+//!   choosing the default one after a countdown.
+//! - `List` shows lines of text which do not fit on the screen, such as a
+//!   log, and scrolls through them.
+//! - `message_box` shows a message until a key is pressed.
+//!
+//! ```no_run
+//! # use uefi::prelude::*;
+//! # use uefi::tui::Menu;
+//! # fn f(st: &mut SystemTable<Boot>) -> uefi::Result {
+//! let st_bt = unsafe { st.unsafe_clone() };
+//! let bt = st_bt.boot_services();
+//! let mut st_in = unsafe { st.unsafe_clone() };
+//! let entries = ["Linux", "Linux (recovery)", "Firmware setup"];
+//! let choice = Menu::new(&entries)
+//!     .title("Boot menu")
+//!     .timeout(Some(5))
+//!     .run(bt, st_in.stdin(), st.stdout())?
+//!     .log();
+//! # Ok(().into())
+//! # }
+//! ```
+//!
+//! The widgets are enabled by the `tui` feature.
+
+use crate::proto::console::text::{Color, Input, Key, Output, ScanCode};
+use crate::table::boot::{BootServices, EventType, TimerTrigger, Tpl};
+use crate::{CStr16, Event, Result, ResultExt, Status};
+use core::convert::TryFrom;
+
+/// Carriage return, sent by the Enter key
+const CHAR_CARRIAGE_RETURN: u16 = 0x0D;
+
+/// Size of the screen when the output device does not report its mode
+const DEFAULT_SCREEN: (usize, usize) = (80, 25);
+
+/// Characters of the frames of the widgets
+const FRAME_HORIZONTAL: char = '─';
+const FRAME_VERTICAL: char = '│';
+const FRAME_TOP_LEFT: char = '┌';
+const FRAME_TOP_RIGHT: char = '┐';
+const FRAME_BOTTOM_LEFT: char = '└';
+const FRAME_BOTTOM_RIGHT: char = '┘';
+
+/// Colors of the widgets, as foreground and background pairs
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// Text of the widgets
+    pub normal: (Color, Color),
+    /// The selected entry of a menu
+    pub highlight: (Color, Color),
+    /// Titles and frames
+    pub frame: (Color, Color),
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            normal: (Color::LightGray, Color::Black),
+            highlight: (Color::Black, Color::LightGray),
+            frame: (Color::White, Color::Blue),
+        }
+    }
+}
+
+/// Menu of entries, from which the user chooses one with the arrow keys
+/// and Enter
+///
+/// With a timeout, a countdown is shown below the entries, and the default
+/// entry is chosen when it expires. Pressing any key stops it.
+#[derive(Debug, Clone)]
+pub struct Menu<'a> {
+    title: Option<&'a str>,
+    items: &'a [&'a str],
+    default: usize,
+    timeout: Option<u32>,
+    theme: Theme,
+}
+
+impl<'a> Menu<'a> {
+    /// Create a menu of `items`, without title nor timeout, whose first
+    /// entry is the default one
+    pub fn new(items: &'a [&'a str]) -> Self {
+        Menu {
+            title: None,
+            items,
+            default: 0,
+            timeout: None,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Show `title` above the entries
+    pub fn title(mut self, title: &'a str) -> Self {
+        self.title = Some(title);
+        self
+    }
+
+    /// Select entry `index` first, and choose it when the timeout expires
+    pub fn default_item(mut self, index: usize) -> Self {
+        self.default = index.min(self.items.len().saturating_sub(1));
+        self
+    }
+
+    /// Choose the default entry if no key is pressed for this many seconds
+    pub fn timeout(mut self, seconds: Option<u32>) -> Self {
+        self.timeout = seconds;
+        self
+    }
+
+    /// Draw the menu with `theme`
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Clear the screen, show the menu, and return the index of the entry
+    /// which was chosen.
+    ///
+    /// # Errors
+    ///
+    /// * `uefi::Status::ABORTED`   The user pressed Escape
+    /// * `uefi::Status::NOT_FOUND` The menu has no entries
+    /// * Errors of the input and output devices are passed through
+    pub fn run(&self, bt: &BootServices, input: &mut Input, output: &mut Output) -> Result<usize> {
+        if self.items.is_empty() {
+            return Err(Status::NOT_FOUND.into());
+        }
+        let screen = screen_size(output);
+        let width = self
+            .items
+            .iter()
+            .chain(self.title.iter())
+            .map(|item| item.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(COUNTDOWN_WIDTH);
+        // Room for the countdown, below the entries
+        let reserved = if self.timeout.is_some() { 2 } else { 0 };
+        let frame = Frame::centered(screen, width, self.items.len() + reserved);
+        let visible = frame.inner_height().saturating_sub(reserved);
+        let mut selection = Selection::new(self.items.len(), self.default, visible);
+
+        let cursor = output.cursor_visible();
+        let _ = output.enable_cursor(false);
+        let result = self.interact(bt, input, output, &frame, &mut selection);
+        let _ = output.enable_cursor(cursor);
+        result?.log();
+        restore(output, &self.theme)?.log();
+        Ok(selection.selected.into())
+    }
+
+    fn interact(
+        &self,
+        bt: &BootServices,
+        input: &mut Input,
+        output: &mut Output,
+        frame: &Frame,
+        selection: &mut Selection,
+    ) -> Result {
+        set_color(output, self.theme.normal)?.log();
+        output.clear().log_warning()?;
+        frame.draw(output, &self.theme, self.title)?.log();
+        self.draw_items(output, frame, selection)?.log();
+
+        let timer = match self.timeout {
+            Some(_) => {
+                let timer = unsafe { bt.create_event(EventType::TIMER, Tpl::APPLICATION, None) }
+                    .log_warning()?;
+                Some(timer)
+            }
+            None => None,
+        };
+        let result = self.wait_for_choice(bt, input, output, frame, selection, timer);
+        if let Some(timer) = timer {
+            // Failing to close the timer must not hide the choice
+            let _ = bt.close_event(timer);
+        }
+        result
+    }
+
+    fn wait_for_choice(
+        &self,
+        bt: &BootServices,
+        input: &mut Input,
+        output: &mut Output,
+        frame: &Frame,
+        selection: &mut Selection,
+        timer: Option<Event>,
+    ) -> Result {
+        let mut remaining = self.timeout;
+        if let Some(timer) = timer {
+            // Ticks every second
+            bt.set_timer(timer, TimerTrigger::Periodic(10_000_000))
+                .log_warning()?;
+        }
+        loop {
+            if let Some(seconds) = remaining {
+                if seconds == 0 {
+                    selection.select(self.default);
+                    return Status::SUCCESS.into();
+                }
+                self.draw_countdown(output, frame, Some(seconds))?.log();
+            }
+
+            let key_event = input.wait_for_key_event();
+            let signaled = match (timer, remaining) {
+                (Some(timer), Some(_)) => bt.wait_for_event(&mut [key_event, timer]),
+                _ => bt.wait_for_event(&mut [key_event]),
+            };
+            if signaled.discard_errdata().log_warning()? == 1 {
+                remaining = remaining.map(|seconds| seconds - 1);
+                continue;
+            }
+
+            let key = match input.read_key().log_warning()? {
+                Some(key) => key,
+                None => continue,
+            };
+            // Any key stops the countdown
+            if remaining.take().is_some() {
+                self.draw_countdown(output, frame, None)?.log();
+            }
+            match key {
+                Key::Printable(c) if u16::from(c) == CHAR_CARRIAGE_RETURN => {
+                    return Status::SUCCESS.into();
+                }
+                Key::Special(ScanCode::ESCAPE) => return Err(Status::ABORTED.into()),
+                key => {
+                    if selection.handle(key) {
+                        self.draw_items(output, frame, selection)?.log();
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_items(&self, output: &mut Output, frame: &Frame, selection: &Selection) -> Result {
+        for row in 0..selection.visible {
+            let index = selection.offset + row;
+            let colors = if index == selection.selected {
+                self.theme.highlight
+            } else {
+                self.theme.normal
+            };
+            let item = self.items.get(index).copied().unwrap_or("");
+            frame.write_line(output, row, item, colors)?.log();
+        }
+        Status::SUCCESS.into()
+    }
+
+    fn draw_countdown(&self, output: &mut Output, frame: &Frame, seconds: Option<u32>) -> Result {
+        let row = frame.inner_height() - 1;
+        let mut text = Text::new();
+        if let Some(seconds) = seconds {
+            let _ = core::fmt::write(&mut text, format_args!("Default entry in {} s", seconds));
+        }
+        frame.write_line(output, row, text.as_str(), self.theme.normal)
+    }
+}
+
+/// Width of the countdown of a menu with its longest number of seconds
+const COUNTDOWN_WIDTH: usize = 28;
+
+/// Scrollable view of lines of text, which is closed with Enter or Escape
+///
+/// The arrow keys, Page Up, Page Down, Home and End scroll through the
+/// lines.
+#[derive(Debug, Clone)]
+pub struct List<'a> {
+    title: Option<&'a str>,
+    lines: &'a [&'a str],
+    theme: Theme,
+}
+
+impl<'a> List<'a> {
+    /// Create a view of `lines`, without title
+    pub fn new(lines: &'a [&'a str]) -> Self {
+        List {
+            title: None,
+            lines,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Show `title` above the lines
+    pub fn title(mut self, title: &'a str) -> Self {
+        self.title = Some(title);
+        self
+    }
+
+    /// Draw the list with `theme`
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Clear the screen, and show the lines until Enter or Escape is
+    /// pressed.
+    ///
+    /// Lines which are too long for the screen are cut.
+    pub fn run(&self, bt: &BootServices, input: &mut Input, output: &mut Output) -> Result {
+        let screen = screen_size(output);
+        let frame = Frame::centered(screen, screen.0, screen.1);
+        let visible = frame.inner_height();
+        let mut scroll = Scroll {
+            len: self.lines.len(),
+            offset: 0,
+            visible,
+        };
+
+        let cursor = output.cursor_visible();
+        let _ = output.enable_cursor(false);
+        let result = self.interact(bt, input, output, &frame, &mut scroll);
+        let _ = output.enable_cursor(cursor);
+        result?.log();
+        restore(output, &self.theme)
+    }
+
+    fn interact(
+        &self,
+        bt: &BootServices,
+        input: &mut Input,
+        output: &mut Output,
+        frame: &Frame,
+        scroll: &mut Scroll,
+    ) -> Result {
+        set_color(output, self.theme.normal)?.log();
+        output.clear().log_warning()?;
+        frame.draw(output, &self.theme, self.title)?.log();
+        loop {
+            for row in 0..scroll.visible {
+                let line = self.lines.get(scroll.offset + row).copied().unwrap_or("");
+                frame
+                    .write_line(output, row, line, self.theme.normal)?
+                    .log();
+            }
+            loop {
+                match read_key(bt, input)?.log() {
+                    Key::Printable(c) if u16::from(c) == CHAR_CARRIAGE_RETURN => {
+                        return Status::SUCCESS.into();
+                    }
+                    Key::Special(ScanCode::ESCAPE) => return Status::SUCCESS.into(),
+                    key => {
+                        if scroll.handle(key) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Show `message` in a box in the middle of the screen, above what is
+/// already shown, until a key is pressed.
+///
+/// Lines of the message are separated by `\n`.
+pub fn message_box(
+    bt: &BootServices,
+    input: &mut Input,
+    output: &mut Output,
+    title: &str,
+    message: &str,
+) -> Result {
+    let theme = Theme::default();
+    let screen = screen_size(output);
+    let width = message
+        .lines()
+        .chain(core::iter::once(title))
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let height = message.lines().count();
+    let frame = Frame::centered(screen, width, height);
+
+    let cursor = output.cursor_visible();
+    let _ = output.enable_cursor(false);
+    let result = (|| {
+        frame.draw(output, &theme, Some(title))?.log();
+        for (row, line) in message.lines().take(frame.inner_height()).enumerate() {
+            frame.write_line(output, row, line, theme.normal)?.log();
+        }
+        read_key(bt, input).map_inner(|_| ())
+    })();
+    let _ = output.enable_cursor(cursor);
+    result?.log();
+    restore(output, &theme)
+}
+
+/// Wait for a key, and read it
+fn read_key(bt: &BootServices, input: &mut Input) -> Result<Key> {
+    loop {
+        bt.wait_for_event(&mut [input.wait_for_key_event()])
+            .discard_errdata()
+            .log_warning()?;
+        if let Some(key) = input.read_key().log_warning()? {
+            return Ok(key.into());
+        }
+    }
+}
+
+/// Size of the screen, in columns and rows
+fn screen_size(output: &Output) -> (usize, usize) {
+    match output.current_mode() {
+        Ok(mode) => match mode.log() {
+            Some(mode) => (mode.columns(), mode.rows()),
+            None => DEFAULT_SCREEN,
+        },
+        Err(_) => DEFAULT_SCREEN,
+    }
+}
+
+fn set_color(output: &mut Output, (foreground, background): (Color, Color)) -> Result {
+    output.set_color(foreground, background)
+}
+
+/// Restore the normal colors, and clear the screen
+fn restore(output: &mut Output, theme: &Theme) -> Result {
+    set_color(output, theme.normal)?.log();
+    output.clear()
+}
+
+/// Position of a framed widget on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    column: usize,
+    row: usize,
+    /// Outer size, including the frame
+    width: usize,
+    height: usize,
+}
+
+impl Frame {
+    /// Frame around `width` columns and `height` rows of content, with one
+    /// column of margin on each side, in the middle of `screen`
+    ///
+    /// The frame is shrunk to fit on the screen, without using its last
+    /// row, since writing to the last column of the last row scrolls.
+    fn centered(screen: (usize, usize), width: usize, height: usize) -> Self {
+        let width = (width + 4).min(screen.0).max(5);
+        let height = (height + 2).min(screen.1.saturating_sub(1)).max(3);
+        Frame {
+            column: screen.0.saturating_sub(width) / 2,
+            row: screen.1.saturating_sub(height + 1) / 2,
+            width,
+            height,
+        }
+    }
+
+    fn inner_width(&self) -> usize {
+        self.width - 4
+    }
+
+    fn inner_height(&self) -> usize {
+        self.height - 2
+    }
+
+    /// Draw the frame, with `title` in its top border, and clear its inside
+    fn draw(&self, output: &mut Output, theme: &Theme, title: Option<&str>) -> Result {
+        set_color(output, theme.frame)?.log();
+        output
+            .set_cursor_position(self.column, self.row)
+            .log_warning()?;
+        let mut top = Buffer::new();
+        top.push(FRAME_TOP_LEFT);
+        let title = title.unwrap_or("");
+        let title_len = title.chars().count().min(self.width - 2);
+        for c in title.chars().take(title_len) {
+            top.push(c);
+        }
+        for _ in title_len..self.width - 2 {
+            top.push(FRAME_HORIZONTAL);
+        }
+        top.push(FRAME_TOP_RIGHT);
+        top.write(output)?.log();
+
+        for row in 1..self.height - 1 {
+            output
+                .set_cursor_position(self.column, self.row + row)
+                .log_warning()?;
+            let mut border = Buffer::new();
+            border.push(FRAME_VERTICAL);
+            border.write(output)?.log();
+            output
+                .set_cursor_position(self.column + self.width - 1, self.row + row)
+                .log_warning()?;
+            border.write(output)?.log();
+            self.write_line(output, row - 1, "", theme.normal)?.log();
+            set_color(output, theme.frame)?.log();
+        }
+
+        output
+            .set_cursor_position(self.column, self.row + self.height - 1)
+            .log_warning()?;
+        let mut bottom = Buffer::new();
+        bottom.push(FRAME_BOTTOM_LEFT);
+        for _ in 0..self.width - 2 {
+            bottom.push(FRAME_HORIZONTAL);
+        }
+        bottom.push(FRAME_BOTTOM_RIGHT);
+        bottom.write(output)
+    }
+
+    /// Write `text` on row `row` of the inside of the frame, cut or padded
+    /// with spaces to its width
+    fn write_line(
+        &self,
+        output: &mut Output,
+        row: usize,
+        text: &str,
+        colors: (Color, Color),
+    ) -> Result {
+        set_color(output, colors)?.log();
+        output
+            .set_cursor_position(self.column + 1, self.row + 1 + row)
+            .log_warning()?;
+        let width = self.inner_width() + 2;
+        let mut line = Buffer::new();
+        line.push(' ');
+        let mut len = 1;
+        for c in text.chars().take(width - 2) {
+            line.push(c);
+            len += 1;
+        }
+        while len < width {
+            line.push(' ');
+            len += 1;
+        }
+        line.write(output)
+    }
+}
+
+/// Maximum number of characters of a line written at once
+const BUFFER_SIZE: usize = 256;
+
+/// Line of UCS-2 text which is built on the stack
+struct Buffer {
+    chars: [u16; BUFFER_SIZE + 1],
+    len: usize,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Buffer {
+            chars: [0; BUFFER_SIZE + 1],
+            len: 0,
+        }
+    }
+
+    /// Append `c`, replacing it with `?` if it is not a printable UCS-2
+    /// character, and ignoring it if the line is full
+    fn push(&mut self, c: char) {
+        let c = match u16::try_from(u32::from(c)) {
+            Ok(c) if c >= 0x20 && !(0xd800..0xe000).contains(&c) => c,
+            _ => u16::from(b'?'),
+        };
+        if self.len < BUFFER_SIZE {
+            self.chars[self.len] = c;
+            self.len += 1;
+        }
+    }
+
+    fn write(&mut self, output: &mut Output) -> Result {
+        self.chars[self.len] = 0;
+        let text = unsafe { CStr16::from_u16_with_nul_unchecked(&self.chars[..=self.len]) };
+        output.output_string(text)
+    }
+}
+
+/// Short text which is formatted on the stack
+struct Text {
+    bytes: [u8; COUNTDOWN_WIDTH],
+    len: usize,
+}
+
+impl Text {
+    fn new() -> Self {
+        Text {
+            bytes: [0; COUNTDOWN_WIDTH],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl core::fmt::Write for Text {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(core::fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Selected entry of a menu, and the entries which are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Selection {
+    len: usize,
+    selected: usize,
+    /// First entry which is shown
+    offset: usize,
+    /// Number of entries which are shown
+    visible: usize,
+}
+
+impl Selection {
+    fn new(len: usize, selected: usize, visible: usize) -> Self {
+        let mut selection = Selection {
+            len,
+            selected: 0,
+            offset: 0,
+            visible: visible.max(1),
+        };
+        selection.select(selected);
+        selection
+    }
+
+    /// Select entry `index`, scrolling so that it is shown
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.len.saturating_sub(1));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + self.visible {
+            self.offset = self.selected + 1 - self.visible;
+        }
+    }
+
+    /// Move the selection as asked by `key`, and return whether it moved
+    fn handle(&mut self, key: Key) -> bool {
+        let last = self.len.saturating_sub(1);
+        let index = match key {
+            // Wrap around at both ends
+            Key::Special(ScanCode::UP) if self.selected == 0 => last,
+            Key::Special(ScanCode::UP) => self.selected - 1,
+            Key::Special(ScanCode::DOWN) if self.selected == last => 0,
+            Key::Special(ScanCode::DOWN) => self.selected + 1,
+            Key::Special(ScanCode::PAGE_UP) => self.selected.saturating_sub(self.visible),
+            Key::Special(ScanCode::PAGE_DOWN) => self.selected + self.visible,
+            Key::Special(ScanCode::HOME) => 0,
+            Key::Special(ScanCode::END) => last,
+            _ => return false,
+        };
+        let previous = *self;
+        self.select(index);
+        *self != previous
+    }
+}
+
+/// Lines of a list which are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Scroll {
+    len: usize,
+    /// First line which is shown
+    offset: usize,
+    /// Number of lines which are shown
+    visible: usize,
+}
+
+impl Scroll {
+    /// Scroll as asked by `key`, and return whether the view moved
+    fn handle(&mut self, key: Key) -> bool {
+        let last = self.len.saturating_sub(self.visible);
+        let offset = match key {
+            Key::Special(ScanCode::UP) => self.offset.saturating_sub(1),
+            Key::Special(ScanCode::DOWN) => self.offset + 1,
+            Key::Special(ScanCode::PAGE_UP) => self.offset.saturating_sub(self.visible),
+            Key::Special(ScanCode::PAGE_DOWN) => self.offset + self.visible,
+            Key::Special(ScanCode::HOME) => 0,
+            Key::Special(ScanCode::END) => last,
+            _ => return false,
+        }
+        .min(last);
+        let moved = offset != self.offset;
+        self.offset = offset;
+        moved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UP: Key = Key::Special(ScanCode::UP);
+    const DOWN: Key = Key::Special(ScanCode::DOWN);
+
+    #[test]
+    fn selection() {
+        let mut selection = Selection::new(10, 7, 4);
+        assert_eq!((selection.selected, selection.offset), (7, 4));
+        assert!(selection.handle(UP));
+        assert!(selection.handle(UP));
+        assert!(selection.handle(UP));
+        assert!(selection.handle(UP));
+        assert_eq!((selection.selected, selection.offset), (3, 3));
+        assert!(selection.handle(Key::Special(ScanCode::END)));
+        assert_eq!((selection.selected, selection.offset), (9, 6));
+        assert!(selection.handle(DOWN));
+        assert_eq!((selection.selected, selection.offset), (0, 0));
+        assert!(selection.handle(UP));
+        assert_eq!(selection.selected, 9);
+        assert!(!selection.handle(Key::Special(ScanCode::PAGE_DOWN)));
+        assert!(!selection.handle(Key::Special(ScanCode::FUNCTION_1)));
+    }
+
+    #[test]
+    fn scroll() {
+        let mut scroll = Scroll {
+            len: 10,
+            offset: 0,
+            visible: 4,
+        };
+        assert!(!scroll.handle(UP));
+        assert!(scroll.handle(Key::Special(ScanCode::PAGE_DOWN)));
+        assert_eq!(scroll.offset, 4);
+        assert!(scroll.handle(Key::Special(ScanCode::PAGE_DOWN)));
+        assert_eq!(scroll.offset, 6);
+        assert!(!scroll.handle(DOWN));
+
+        // Nothing to scroll when all the lines are shown
+        let mut scroll = Scroll {
+            len: 2,
+            offset: 0,
+            visible: 4,
+        };
+        assert!(!scroll.handle(Key::Special(ScanCode::END)));
+    }
+
+    #[test]
+    fn frame() {
+        assert_eq!(
+            Frame::centered((80, 25), 20, 5),
+            Frame {
+                column: 28,
+                row: 8,
+                width: 24,
+                height: 7
+            }
+        );
+        // Shrunk to the screen, keeping the last row free
+        let frame = Frame::centered((80, 25), 200, 100);
+        assert_eq!(
+            (frame.column, frame.row, frame.width, frame.height),
+            (0, 0, 80, 24)
+        );
+        assert_eq!(frame.inner_height(), 22);
+    }
+}
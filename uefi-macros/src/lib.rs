@@ -159,6 +159,13 @@ pub fn derive_protocol(item: TokenStream) -> TokenStream {
 }
 
 /// Custom attribute for a UEFI executable entrypoint
+///
+/// The attribute accepts one flag:
+///
+/// - `#[entry(enable_simd)]` enables the SSE and AVX states of the CPU with
+///   `uefi::arch::x86::enable_simd` before the body of the entry point runs,
+///   since firmware does not always enable AVX. It has no effect on other
+///   architectures.
 #[proc_macro_attribute]
 pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
     // This code is inspired by the approach in this embedded Rust crate:
@@ -166,11 +173,16 @@ pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
 
     let mut errors = TokenStream2::new();
 
+    let mut enable_simd = false;
     if !args.is_empty() {
-        errors.append_all(err!(
-            TokenStream2::from(args),
-            "Entry attribute accepts no arguments"
-        ));
+        let args = TokenStream2::from(args);
+        match syn::parse2::<Ident>(args.clone()) {
+            Ok(flag) if flag == "enable_simd" => enable_simd = true,
+            _ => errors.append_all(err!(
+                args,
+                "Entry attribute only accepts the `enable_simd` flag"
+            )),
+        }
     }
 
     let mut f = parse_macro_input!(input as ItemFn);
@@ -201,6 +213,19 @@ pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
     // strip any visibility modifiers
     f.vis = Visibility::Inherited;
 
+    // Enable the states before any code which the compiler may vectorize
+    if enable_simd {
+        f.block.stmts.insert(
+            0,
+            syn::parse_quote! {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                unsafe {
+                    ::uefi::arch::x86::enable_simd();
+                }
+            },
+        );
+    }
+
     let ident = &f.sig.ident;
 
     let result = quote! {
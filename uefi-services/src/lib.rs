@@ -39,6 +39,13 @@ static mut SYSTEM_TABLE: Option<SystemTable<Boot>> = None;
 /// Global logger object
 static mut LOGGER: Option<uefi::logger::Logger> = None;
 
+/// Function called by the panic handler, see `set_panic_hook`
+static mut PANIC_HOOK: Option<fn(&core::panic::PanicInfo)> = None;
+
+/// Logger which receives the records instead of `LOGGER`, see
+/// `set_log_capture`
+static mut LOG_CAPTURE: Option<&'static dyn log::Log> = None;
+
 /// Obtains a pointer to the system table.
 ///
 /// This is meant to be used by higher-level libraries,
@@ -69,6 +76,7 @@ pub fn init(st: &mut SystemTable<Boot>) -> Result {
 
         // Setup the system table singleton
         SYSTEM_TABLE = Some(st.unsafe_clone());
+        uefi::table::boot::set_system_table(st);
 
         // Setup logging and memory allocation
         init_logger(st);
@@ -86,6 +94,63 @@ pub fn init(st: &mut SystemTable<Boot>) -> Result {
     }
 }
 
+/// Register a function which the panic handler calls after it logged the
+/// panic, or remove it with `None`.
+///
+/// The hook may report the panic elsewhere, or reset the machine instead of
+/// returning, for example when a test runner expected the panic. If it
+/// returns, the panic handler goes on as usual.
+pub fn set_panic_hook(hook: Option<fn(&core::panic::PanicInfo)>) {
+    unsafe { PANIC_HOOK = hook };
+}
+
+/// Send the log records to `capture` instead of the console, or to the
+/// console again with `None`, and return the previous capture.
+///
+/// Test runners use this to keep the output of a test until they know
+/// whether it failed. The panic handler removes the capture and calls its
+/// `flush` method before logging the panic, so that it can print what it
+/// kept. The capture is removed when boot services are exited.
+pub fn set_log_capture(capture: Option<&'static dyn log::Log>) -> Option<&'static dyn log::Log> {
+    unsafe { core::mem::replace(&mut LOG_CAPTURE, capture) }
+}
+
+/// Global logger, which sends the records to the capture if there is one,
+/// and to `LOGGER` otherwise
+struct Dispatch;
+
+impl Dispatch {
+    fn target(&self) -> Option<&'static dyn log::Log> {
+        unsafe {
+            match LOG_CAPTURE {
+                Some(capture) => Some(capture),
+                None => LOGGER.as_ref().map(|logger| logger as &dyn log::Log),
+            }
+        }
+    }
+}
+
+impl log::Log for Dispatch {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.target()
+            .map_or(false, |target| target.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if let Some(target) = self.target() {
+            target.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(target) = self.target() {
+            target.flush();
+        }
+    }
+}
+
+static DISPATCH: Dispatch = Dispatch;
+
 /// Set up logging
 ///
 /// This is unsafe because you must arrange for the logger to be reset with
@@ -94,13 +159,10 @@ unsafe fn init_logger(st: &mut SystemTable<Boot>) {
     let stdout = st.stdout();
 
     // Construct the logger.
-    let logger = {
-        LOGGER = Some(uefi::logger::Logger::new(stdout));
-        LOGGER.as_ref().unwrap()
-    };
+    LOGGER = Some(uefi::logger::Logger::new(stdout));
 
     // Set the logger.
-    log::set_logger(logger).unwrap(); // Can only fail if already initialized.
+    log::set_logger(&DISPATCH).unwrap(); // Can only fail if already initialized.
 
     // Log everything.
     log::set_max_level(log::LevelFilter::Info);
@@ -116,6 +178,7 @@ fn exit_boot_services(_e: Event) {
     // info!("Shutting down the UEFI utility library");
     unsafe {
         SYSTEM_TABLE = None;
+        LOG_CAPTURE = None;
         if let Some(ref mut logger) = LOGGER {
             logger.disable();
         }
@@ -129,6 +192,11 @@ fn eh_personality() {}
 #[cfg(not(feature = "no_panic_handler"))]
 #[panic_handler]
 fn panic_handler(info: &core::panic::PanicInfo) -> ! {
+    // Print what the capture kept before the panic, which goes to the console
+    if let Some(capture) = set_log_capture(None) {
+        capture.flush();
+    }
+
     if let Some(location) = info.location() {
         error!(
             "Panic in {} at ({}, {}):",
@@ -141,6 +209,15 @@ fn panic_handler(info: &core::panic::PanicInfo) -> ! {
         }
     }
 
+    #[cfg(feature = "backtrace")]
+    error!("Backtrace:\n{}", unsafe {
+        uefi::backtrace::Backtrace::capture()
+    });
+
+    if let Some(hook) = unsafe { PANIC_HOOK } {
+        hook(info);
+    }
+
     // Give the user some time to read the message
     if let Some(st) = unsafe { SYSTEM_TABLE.as_ref() } {
         st.boot_services().stall(10_000_000);
@@ -161,19 +238,41 @@ fn panic_handler(info: &core::panic::PanicInfo) -> ! {
             let custom_exit_success = 3;
             let qemu_exit_handle = qemu_exit::X86::new(0xF4, custom_exit_success);
             qemu_exit_handle.exit_failure();
+        } else if #[cfg(all(target_arch = "x86", feature = "qemu"))] {
+            // If running in QEMU, use the f4 exit port to signal the error and exit.
+            // `qemu_exit` only supports x86_64, but the isa-debug-exit device
+            // works the same way on IA-32: writing 0 makes QEMU exit with 1.
+            unsafe {
+                asm!("out dx, eax", in("dx") 0xf4u16, in("eax") 0u32, options(nomem, nostack));
+            }
+            loop {
+                unsafe {
+                    asm!("hlt", options(nomem, nostack));
+                }
+            }
+        } else if #[cfg(all(target_arch = "aarch64", feature = "qemu"))] {
+            // If running in QEMU, use semihosting to signal the error and exit
+            use qemu_exit::QEMUExit;
+            qemu_exit::AArch64::new().exit_failure();
+        } else if #[cfg(all(target_arch = "riscv64", feature = "qemu"))] {
+            // If running in QEMU, use the sifive_test device of the virt machine,
+            // mapped at 0x100000, to signal the error and exit
+            use qemu_exit::QEMUExit;
+            qemu_exit::RISCV64::new(0x10_0000).exit_failure();
         } else {
             // If the system table is available, use UEFI's standard shutdown mechanism
             if let Some(st) = unsafe { SYSTEM_TABLE.as_ref() } {
                 use uefi::table::runtime::ResetType;
-                st.runtime_services()
-                    .reset(ResetType::Shutdown, uefi::Status::ABORTED, None);
+                let _ = st
+                    .runtime_services()
+                    .try_reset(ResetType::Shutdown, uefi::Status::ABORTED, None);
             }
 
             // If we don't have any shutdown mechanism handy, the best we can do is loop
             error!("Could not shut down, please power off the system manually...");
 
             cfg_if! {
-                if #[cfg(target_arch = "x86_64")] {
+                if #[cfg(any(target_arch = "x86", target_arch = "x86_64"))] {
                     loop {
                         unsafe {
                             // Try to at least keep CPU from running at 100%
@@ -187,6 +286,13 @@ fn panic_handler(info: &core::panic::PanicInfo) -> ! {
                             asm!("hlt 420", options(nomem, nostack));
                         }
                     }
+                } else if #[cfg(target_arch = "riscv64")] {
+                    loop {
+                        unsafe {
+                            // Try to at least keep CPU from running at 100%
+                            asm!("wfi", options(nomem, nostack));
+                        }
+                    }
                 } else {
                     loop {
                         // just run forever dammit how do you return never anyway
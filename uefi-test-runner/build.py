@@ -3,7 +3,7 @@
 'Script used to build, run, and test the code on all supported platforms.'
 
 import argparse
-import filecmp
+import difflib
 import json
 import os
 from pathlib import Path
@@ -12,6 +12,8 @@ import shutil
 import subprocess as sp
 import sys
 
+import progress
+
 ## Configurable settings
 # Path to workspace directory (which contains the top-level `Cargo.toml`)
 WORKSPACE_DIR = Path(__file__).resolve().parents[1]
@@ -32,12 +34,55 @@ SETTINGS = {
     # Indexed by the `arch` setting
     'qemu_binary': {
         'x86_64': 'qemu-system-x86_64',
+        'i686': 'qemu-system-i386',
         'aarch64': 'qemu-system-aarch64',
+        'riscv64': 'qemu-system-riscv64',
     },
     # Path to directory containing `OVMF_{CODE/VARS}.fd` (for x86_64),
-    # or `*-pflash.raw` (for AArch64).
+    # or `OVMF32_{CODE/VARS}.fd` (for IA-32),
+    # or `*-pflash.raw` (for AArch64), or `RISCV_VIRT_{CODE/VARS}.fd` (for RISC-V).
     # `find_ovmf` function will try to find one if this isn't specified.
     'ovmf_dir': None,
+    # Which of the ignored tests run: 'include' for all of them, along with the
+    # others, 'only' for only them, or none of them if `None`
+    'ignored': None,
+    # Only run the tests whose name contains this text, if not `None`
+    'filter': None,
+    # Only run the tests of this group, if not `None`
+    'group': None,
+    # Part of the tests to run, as `<k>/<n>`, or all of them if `None`
+    'shard': None,
+    # Run the tests in a random order
+    'shuffle': False,
+    # Seed of the order of the tests, instead of a random one, if not `None`
+    'shuffle_seed': None,
+    # Print the names of the tests instead of running them
+    'list': False,
+    # Only run the benchmarks, and measure them
+    'bench': False,
+    # Run the tests which may run on the application processors in parallel
+    # on them
+    'parallel': False,
+    # Print the log of the tests which pass too
+    'nocapture': False,
+    # Print one character per test, and the output of the failed tests only
+    'quiet': False,
+    # Color the outcomes of the tests: 'auto' when printing to a terminal,
+    # 'always' or 'never'
+    'color': 'auto',
+    # Let the test runner resume the tests after one of them resets the machine
+    'resume': False,
+    # File to which the results are written as libtest JSON lines, if not
+    # `None`
+    'json': None,
+    # Seconds after which the test runner considers a test hung, or `None`
+    'test_timeout': 300,
+    # Number of times the tests run again if the machine crashed before the
+    # end of the suite
+    'retries': 0,
+    # Overwrite the reference screenshots and transcripts with the output
+    # of the run, instead of comparing them
+    'bless': False,
 }
 
 # Path to target directory. If None, it will be initialized with information
@@ -55,6 +100,9 @@ def target_dir():
 
 def get_target_triple():
     arch = SETTINGS['arch']
+    if arch == 'riscv64':
+        # Rust names the RISC-V target after the extensions it requires
+        return 'riscv64gc-unknown-uefi'
     return f'{arch}-unknown-uefi'
 
 def build_dir():
@@ -98,21 +146,49 @@ def build(*test_flags):
     if SETTINGS['ci']:
         build_args.extend(['--features', 'ci'])
 
+    # The runner reads which tests to run when it is built, since it is
+    # booted without load options
+    for variable, value in (('UEFI_TEST_IGNORED', SETTINGS['ignored']),
+                            ('UEFI_TEST_FILTER', SETTINGS['filter']),
+                            ('UEFI_TEST_GROUP', SETTINGS['group']),
+                            ('UEFI_TEST_SHARD', SETTINGS['shard']),
+                            ('UEFI_TEST_SHUFFLE', '1' if SETTINGS['shuffle'] else None),
+                            ('UEFI_TEST_SHUFFLE_SEED',
+                             None if SETTINGS['shuffle_seed'] is None else str(SETTINGS['shuffle_seed'])),
+                            ('UEFI_TEST_LIST', '1' if SETTINGS['list'] else None),
+                            ('UEFI_TEST_BENCH', '1' if SETTINGS['bench'] else None),
+                            ('UEFI_TEST_PARALLEL', '1' if SETTINGS['parallel'] else None),
+                            ('UEFI_TEST_NOCAPTURE', '1' if SETTINGS['nocapture'] else None),
+                            ('UEFI_TEST_TIMEOUT', None if SETTINGS['test_timeout'] is None
+                                                  else str(SETTINGS['test_timeout']))):
+        if value is not None:
+            os.environ[variable] = value
+        else:
+            os.environ.pop(variable, None)
+
     run_build(*build_args)
 
     # Copy the built test runner file to the right directory for running tests.
-    built_file = build_dir() / 'uefi-test-runner.efi'
-
-    boot_dir = esp_dir() / 'EFI' / 'Boot'
-    boot_dir.mkdir(parents=True, exist_ok=True)
+    install_efi(build_dir() / 'uefi-test-runner.efi', esp_dir())
 
+def boot_file_name():
+    'Returns the name of the removable media boot file for the target arch'
     arch = SETTINGS['arch']
     if arch == 'x86_64':
-        output_file = boot_dir / 'BootX64.efi'
-    elif arch == 'aarch64':
-        output_file = boot_dir / 'BootAA64.efi'
+        return 'BootX64.efi'
+    if arch == 'i686':
+        return 'BootIA32.efi'
+    if arch == 'aarch64':
+        return 'BootAA64.efi'
+    if arch == 'riscv64':
+        return 'BootRISCV64.efi'
+    raise NotImplementedError('Target arch not supported')
 
-    shutil.copy2(built_file, output_file)
+def install_efi(efi_file, esp):
+    'Copies an EFI application to the directory of a system partition, so that the firmware boots it'
+    boot_dir = Path(esp) / 'EFI' / 'Boot'
+    boot_dir.mkdir(parents=True, exist_ok=True)
+    shutil.copy2(efi_file, boot_dir / boot_file_name())
 
 def clippy():
     'Runs Clippy on all projects'
@@ -174,8 +250,12 @@ def ovmf_files(ovmf_dir):
     'Returns the tuple of paths to the OVMF code and vars firmware files, given the directory'
     if SETTINGS['arch'] == 'x86_64':
         return ovmf_dir / 'OVMF_CODE.fd', ovmf_dir / 'OVMF_VARS.fd'
+    if SETTINGS['arch'] == 'i686':
+        return ovmf_dir / 'OVMF32_CODE.fd', ovmf_dir / 'OVMF32_VARS.fd'
     if SETTINGS['arch'] == 'aarch64':
         return ovmf_dir / 'QEMU_EFI-pflash.raw', ovmf_dir / 'vars-template-pflash.raw'
+    if SETTINGS['arch'] == 'riscv64':
+        return ovmf_dir / 'RISCV_VIRT_CODE.fd', ovmf_dir / 'RISCV_VIRT_VARS.fd'
     raise NotImplementedError('Target arch not supported')
 
 def check_ovmf_dir(ovmf_dir):
@@ -215,7 +295,70 @@ def run_qemu():
     'Runs the code in QEMU.'
 
     # Rebuild all the changes.
-    build('--features', 'qemu')
+    build('--features', 'qemu,resume' if SETTINGS['resume'] else 'qemu')
+
+    runner_dir = WORKSPACE_DIR / 'uefi-test-runner'
+    for attempt in range(SETTINGS['retries'] + 1):
+        try:
+            run_esp(esp_dir(), runner_dir / 'screenshots', runner_dir / 'transcripts')
+            return
+        except SuiteCrashed as error:
+            if attempt == SETTINGS['retries']:
+                raise
+            print(f'{error}, running the tests again', file=sys.stderr)
+
+class SuiteCrashed(Exception):
+    'The machine crashed, or hung, before the end of the test suite'
+
+def run_efi(efi_file, screenshot_dir=None, transcript_dir=None):
+    '''Boots an EFI application in QEMU, and raises `CalledProcessError` if it fails.
+
+    This is the entry point for other crates which run their own tests under
+    QEMU: the application is put alone on a system partition in the target
+    directory, and is run as `run` runs the test runner. It reports success
+    through the QEMU exit device, as the `qemu` feature of the test runner
+    does, or by shutting down the machine.'''
+    esp = target_dir() / f'esp-{Path(efi_file).stem}'
+    shutil.rmtree(esp, ignore_errors=True)
+    install_efi(efi_file, esp)
+    run_esp(esp, screenshot_dir, transcript_dir)
+
+# Matches the source location which the logger of `uefi` prints after the level
+LOG_LOCATION = re.compile(r'^(\[\s*[A-Z]+\]): +\S+@\d+: ')
+
+def normalize_transcript_line(line):
+    'Removes the parts of a line of output which change from build to build'
+    return LOG_LOCATION.sub(r'\1: ', line)
+
+def check_reference(reference_file, actual, diff):
+    '''Checks the output of a test against its reference, or replaces the
+    reference in bless mode. `diff` describes a mismatch.'''
+    if SETTINGS['bless']:
+        reference_file.parent.mkdir(parents=True, exist_ok=True)
+        reference_file.write_bytes(actual)
+        return
+    if not reference_file.is_file():
+        raise AssertionError(f'Missing reference `{reference_file}`, run with `--bless` to create it')
+    expected = reference_file.read_bytes()
+    if expected != actual:
+        raise AssertionError(f'Output differs from `{reference_file}`:\n{diff(expected)}')
+
+def check_transcript(transcript_dir, name, lines):
+    'Compares the lines printed during a transcript to the reference file'
+    assert transcript_dir is not None, 'No reference transcripts to compare to'
+    reference_file = Path(transcript_dir) / (name + '.txt')
+    actual = ''.join(line + '\n' for line in lines)
+    def diff(expected):
+        return ''.join(difflib.unified_diff(
+            expected.decode().splitlines(keepends=True),
+            actual.splitlines(keepends=True),
+            fromfile=str(reference_file), tofile='output'))
+    check_reference(reference_file, actual.encode(), diff)
+
+def run_esp(esp, screenshot_dir, transcript_dir):
+    '''Boots QEMU from the directory `esp`, comparing the screenshots and
+    transcripts the application asks for to those in `screenshot_dir` and
+    `transcript_dir`.'''
 
     ovmf_code, ovmf_vars = ovmf_files(find_ovmf())
 
@@ -230,12 +373,19 @@ def run_qemu():
     ]
 
     ovmf_vars_readonly = 'on'
-    if arch == 'aarch64':
-        # The OVMF implementation for AArch64 won't boot unless the
-        # vars file is writeable.
+    if arch in ('aarch64', 'riscv64'):
+        # The OVMF implementations for AArch64 and RISC-V won't boot unless
+        # the vars file is writeable.
+        ovmf_vars_readonly = 'off'
+    if SETTINGS['resume']:
+        # The progress of the tests is stored in a variable which must survive
+        # resets, so write to a copy of the vars file
+        vars_copy = target_dir() / ovmf_vars.name
+        shutil.copyfile(ovmf_vars, vars_copy)
+        ovmf_vars = vars_copy
         ovmf_vars_readonly = 'off'
 
-    if arch == 'x86_64':
+    if arch in ('x86_64', 'i686'):
         qemu_flags.extend([
             # Use a modern machine,.
             '-machine', 'q35',
@@ -249,7 +399,7 @@ def run_qemu():
         if not SETTINGS['ci']:
             # Enable acceleration if possible.
             qemu_flags.append('--enable-kvm')
-        else:
+        elif not SETTINGS['resume']:
             # Exit instead of rebooting
             qemu_flags.append('-no-reboot')
     elif arch == 'aarch64':
@@ -260,6 +410,15 @@ def run_qemu():
             # A72 is a very generic 64-bit ARM CPU in the wild
             '-cpu', 'cortex-a72',
         ])
+    elif arch == 'riscv64':
+        qemu_flags.extend([
+            # The virt machine also provides the `sifive_test` device used to
+            # report the outcome of the tests
+            '-machine', 'virt',
+
+            # Allocate some memory.
+            '-m', '256M',
+        ])
     else:
         raise NotImplementedError('Unknown arch')
 
@@ -269,7 +428,7 @@ def run_qemu():
         '-drive', f'if=pflash,format=raw,file={ovmf_vars},readonly={ovmf_vars_readonly}',
 
         # Mount a local directory as a FAT partition.
-        '-drive', f'format=raw,file=fat:rw:{esp_dir()}',
+        '-drive', f'format=raw,file=fat:rw:{esp}',
 
         # Connect the serial port to the host. OVMF is kind enough to connect
         # the UEFI stdout and stdin to that port too.
@@ -279,8 +438,9 @@ def run_qemu():
         '-qmp', f'pipe:{qemu_monitor_pipe}',
     ])
 
-    # For now these only work on x86_64
-    if arch == 'x86_64':
+    # Set up the devices through which the test runner exits QEMU with a status
+    # telling whether the tests passed
+    if arch in ('x86_64', 'i686'):
         # Enable debug features
         qemu_flags.extend([
             # Map the QEMU exit signal to port f4
@@ -290,6 +450,11 @@ def run_qemu():
             # Only enable when debugging UEFI boot:
             #'-debugcon', 'file:debug.log', '-global', 'isa-debugcon.iobase=0x402',
         ])
+    elif arch == 'aarch64':
+        qemu_flags.extend([
+            # Let the test runner exit QEMU through the semihosting interface
+            '-semihosting',
+        ])
 
     # When running in headless mode we don't have video, but we can still have
     # QEMU emulate a display and take screenshots from it.
@@ -314,6 +479,18 @@ def run_qemu():
     monitor_output_path = f'{qemu_monitor_pipe}.out'
     os.mkfifo(monitor_output_path)
 
+    # Results which the test runner sends as libtest JSON lines
+    json_lines = []
+
+    # Progress of the tests which the application reports. With `--resume`,
+    # the watchdog of the firmware resets the machine once a test times out,
+    # so only a test which hangs for longer than that is killed.
+    hang_timeout = SETTINGS['test_timeout']
+    if hang_timeout is not None and SETTINGS['resume']:
+        hang_timeout *= 2
+    color = SETTINGS['color'] == 'always' or (SETTINGS['color'] == 'auto' and sys.stdout.isatty())
+    tests = progress.Progress(hang_timeout, quiet=SETTINGS['quiet'], color=color)
+
     # Start QEMU
     qemu = sp.Popen(cmd, stdin=sp.PIPE, stdout=sp.PIPE, universal_newlines=True)
     try:
@@ -325,6 +502,15 @@ def run_qemu():
             print('{"execute": "qmp_capabilities"}', file=monitor_input, flush=True)
             assert monitor_output.readline() == '{"return": {}}\n'
 
+            # Name and lines of the transcript being recorded
+            transcript = None
+
+            # Kill QEMU if a test hangs, instead of waiting forever
+            def on_hang(name):
+                print(f'Test `{name}` is hung, killing QEMU', file=sys.stderr)
+                qemu.kill()
+            tests.watch(on_hang)
+
             # Iterate over stdout...
             for line in qemu.stdout:
                 # Strip ending and trailing whitespace + ANSI escape codes
@@ -335,8 +521,34 @@ def run_qemu():
                 if not stripped:
                     continue
 
+                # Keep the results for the JSON report, instead of printing them
+                if stripped.startswith('JSON: '):
+                    json_lines.append(stripped[6:])
+                    continue
+
+                # Track the progress of the tests
+                event = progress.parse_event(stripped)
+                if event is not None:
+                    tests.handle(event)
+                    continue
+
                 # Print out the processed QEMU output for logging & inspection
-                print(stripped)
+                tests.record_output(stripped)
+
+                # Record the transcript requested by the app
+                if stripped.startswith('TRANSCRIPT: '):
+                    transcript = (stripped[12:], [])
+                    print('OK', file=qemu.stdin, flush=True)
+                    continue
+                if stripped.startswith('TRANSCRIPT END: '):
+                    name, lines = transcript
+                    assert name == stripped[16:], 'Mismatched transcript end'
+                    transcript = None
+                    print('OK', file=qemu.stdin, flush=True)
+                    check_transcript(transcript_dir, name, lines)
+                    continue
+                if transcript is not None:
+                    transcript[1].append(normalize_transcript_line(stripped))
 
                 # If the app requests a screenshot, take it
                 if stripped.startswith("SCREENSHOT: "):
@@ -356,9 +568,10 @@ def run_qemu():
                     print('OK', file=qemu.stdin, flush=True)
 
                     # Compare screenshot to the reference file specified by the user
-                    # TODO: Add an operating mode where the reference is created if it doesn't exist
-                    reference_file = WORKSPACE_DIR / 'uefi-test-runner' / 'screenshots' / (reference_name + '.ppm')
-                    assert filecmp.cmp('screenshot.ppm', reference_file)
+                    assert screenshot_dir is not None, 'No reference screenshots to compare to'
+                    reference_file = Path(screenshot_dir) / (reference_name + '.ppm')
+                    check_reference(reference_file, Path('screenshot.ppm').read_bytes(),
+                                    lambda _: 'The screenshots differ')
 
                     # Delete the screenshot once done
                     os.remove('screenshot.ppm')
@@ -375,15 +588,40 @@ def run_qemu():
         os.remove(monitor_input_path)
         os.remove(monitor_output_path)
 
-        # Throw an exception if QEMU failed
+        # Write the results of the tests which ran, even if QEMU failed
+        if SETTINGS['json'] is not None:
+            Path(SETTINGS['json']).write_text(''.join(line + '\n' for line in json_lines))
+
+        summary = tests.finish()
+        for group_summary in tests.group_summaries():
+            print(group_summary)
+        if tests.suite is not None:
+            print(f'Suite `{tests.suite}`: {summary}')
+        # Unless the script itself failed, as when a screenshot differs
+        if tests.crashed() and sys.exc_info()[1] is None:
+            raise SuiteCrashed(f'Suite `{tests.suite}` did not end')
+
+        # Throw an exception if QEMU failed. The application exits QEMU with
+        # status 3 through the debug exit device on x86, since the device
+        # turns code 1 into `(1 << 1) | 1`, or shuts the machine down.
         if status != 0 and status != 3:
             raise sp.CalledProcessError(cmd=cmd, returncode=status)
+        if not tests.succeeded():
+            raise AssertionError('Some tests did not pass')
+
+def shard(value):
+    'Checks that `value` is a shard of the tests, as `<k>/<n>`'
+    match = re.fullmatch(r'(\d+)/(\d+)', value)
+    if match is None or not 1 <= int(match[1]) <= int(match[2]):
+        raise argparse.ArgumentTypeError(f'invalid shard {value!r}, expected K/N with 1 <= K <= N')
+    return value
 
 def main():
     'Runs the user-requested actions.'
 
-    # Clear any Rust flags which might affect the build.
-    os.environ['RUSTFLAGS'] = ''
+    # Clear any Rust flags which might affect the build, but keep the frame
+    # pointers which the backtraces of panics follow.
+    os.environ['RUSTFLAGS'] = '-C force-frame-pointers=yes'
 
     desc = 'Build script for UEFI programs'
 
@@ -393,7 +631,7 @@ def main():
                         choices=['build', 'run', 'doc', 'clippy', 'test'])
 
     parser.add_argument('--target', help='target to build for (default: %(default)s)', type=str,
-                        choices=['x86_64', 'aarch64'], default='x86_64')
+                        choices=['x86_64', 'i686', 'aarch64', 'riscv64'], default='x86_64')
 
     parser.add_argument('--verbose', '-v', help='print commands before executing them',
                         action='store_true')
@@ -407,6 +645,60 @@ def main():
     parser.add_argument('--ci', help='disables some tests which currently break CI',
                         action='store_true')
 
+    parser.add_argument('--ignored', help='only run the ignored tests',
+                        action='store_true')
+
+    parser.add_argument('--include-ignored', help='run the ignored tests along with the others',
+                        action='store_true')
+
+    parser.add_argument('--filter', help='only run the tests whose name contains this text',
+                        type=str, default=SETTINGS['filter'])
+
+    parser.add_argument('--group', help='only run the tests of this group, such as `proto`',
+                        type=str, default=SETTINGS['group'])
+
+    parser.add_argument('--shard', help='split the tests in N parts by their name, and only run the K-th one',
+                        type=shard, metavar='K/N', default=SETTINGS['shard'])
+
+    parser.add_argument('--shuffle', help='run the tests in a random order, and print its seed',
+                        action='store_true')
+
+    parser.add_argument('--shuffle-seed', help='run the tests in the order given by this seed',
+                        type=int, default=SETTINGS['shuffle_seed'])
+
+    parser.add_argument('--list', help='print the names of the tests instead of running them',
+                        action='store_true')
+
+    parser.add_argument('--bench', help='only run the benchmarks, and measure how long an iteration of each takes',
+                        action='store_true')
+
+    parser.add_argument('--parallel', help='run the tests which allow it on the application processors, in parallel',
+                        action='store_true')
+
+    parser.add_argument('--nocapture', help='print the log of the tests which pass too',
+                        action='store_true')
+
+    parser.add_argument('--quiet', '-q', help='print one character per test, and the output of the failed tests',
+                        action='store_true')
+
+    parser.add_argument('--color', help='color the test results (default: %(default)s)',
+                        choices=['auto', 'always', 'never'], default=SETTINGS['color'])
+
+    parser.add_argument('--resume', help='resume the tests after one of them resets the machine',
+                        action='store_true')
+
+    parser.add_argument('--test-timeout', help='seconds after which a test is considered hung (default: %(default)s)',
+                        type=int, default=SETTINGS['test_timeout'])
+
+    parser.add_argument('--retries', help='run the tests again up to this many times if the machine crashes',
+                        type=int, default=SETTINGS['retries'])
+
+    parser.add_argument('--json', help='write the test results to this file as libtest JSON lines',
+                        type=str, default=SETTINGS['json'])
+
+    parser.add_argument('--bless', help='overwrite the reference screenshots and transcripts',
+                        action='store_true')
+
     opts = parser.parse_args()
 
     SETTINGS['arch'] = opts.target
@@ -415,6 +707,26 @@ def main():
     SETTINGS['headless'] = opts.headless
     SETTINGS['config'] = 'release' if opts.release else 'debug'
     SETTINGS['ci'] = opts.ci
+    if opts.ignored:
+        SETTINGS['ignored'] = 'only'
+    elif opts.include_ignored:
+        SETTINGS['ignored'] = 'include'
+    SETTINGS['filter'] = opts.filter
+    SETTINGS['group'] = opts.group
+    SETTINGS['shard'] = opts.shard
+    SETTINGS['shuffle'] = opts.shuffle
+    SETTINGS['shuffle_seed'] = opts.shuffle_seed
+    SETTINGS['list'] = opts.list
+    SETTINGS['bench'] = opts.bench
+    SETTINGS['parallel'] = opts.parallel
+    SETTINGS['nocapture'] = opts.nocapture
+    SETTINGS['quiet'] = opts.quiet
+    SETTINGS['color'] = opts.color
+    SETTINGS['resume'] = opts.resume
+    SETTINGS['json'] = opts.json
+    SETTINGS['test_timeout'] = opts.test_timeout
+    SETTINGS['retries'] = opts.retries
+    SETTINGS['bless'] = opts.bless
 
     verb = opts.verb
 
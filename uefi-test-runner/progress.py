@@ -0,0 +1,270 @@
+'''Parser of the progress messages which the test runner sends over serial.
+
+Each message is a line of the form `TEST: <crc> <kind> <fields>`, in which
+`<crc>` is the CRC32 of `<kind> <fields>` in hexadecimal. OVMF mirrors the
+console on the same serial port, so lines which the console output got mixed
+into are told apart by their checksum, and ignored. The kinds of messages are:
+
+- `SUITE-BEGIN <count> <name>` before the first test of a suite
+- `SUITE-RESUME <index> <name>` instead of `SUITE-BEGIN` when the suite
+  resumes after the machine was reset during the test `<index>`, whose result
+  it then reports
+- `GROUP <name>` before `TEST-START`, or before the result of the test which
+  a resumed suite reports, if the test belongs to a group
+- `TEST-START <index> <name>` before a test runs
+- `TEST-RESULT <index> <outcome>` after it ran, in which `<outcome>` is `ok`,
+  `failed`, `ignored` or `allowed_failure`, as in the JSON format of libtest,
+  or `flaky <attempts>` if it passed on a retry
+- `SUITE-END <count>` after the last test, with the number of tests which
+  reported their result
+
+A test which panics, hangs or resets the machine without the suite resuming
+never reports its result, and is counted as failed.'''
+
+import re
+import sys
+import threading
+import time
+import zlib
+
+# Matches a message, and captures its checksum and its contents
+MESSAGE = re.compile(r'^TEST: ([0-9a-f]{8}) (\S+)(?: (.*))?$')
+
+class Event:
+    'A message of the test runner'
+
+    def __init__(self, kind, fields):
+        self.kind = kind
+        self.fields = fields
+
+    def __repr__(self):
+        return f'Event({self.kind!r}, {self.fields!r})'
+
+# Number of fields of each kind of message, the last one of which may hold
+# spaces
+FIELDS = {
+    'SUITE-BEGIN': 2,
+    'SUITE-RESUME': 2,
+    'GROUP': 1,
+    'TEST-START': 2,
+    'TEST-RESULT': 2,
+    'SUITE-END': 1,
+}
+
+def parse_event(line):
+    '''Parses a line of serial output, and returns the `Event` it holds, or
+    `None` if it is not a message or has a wrong checksum.'''
+    match = MESSAGE.match(line)
+    if match is None:
+        return None
+    crc, kind, rest = match.groups()
+    contents = kind if rest is None else f'{kind} {rest}'
+    if int(crc, 16) != zlib.crc32(contents.encode()):
+        return None
+    if kind not in FIELDS:
+        return None
+    fields = [] if rest is None else rest.split(' ', FIELDS[kind] - 1)
+    if len(fields) != FIELDS[kind]:
+        return None
+    return Event(kind, fields)
+
+# ANSI escape codes of the colors of the outcomes
+COLORS = {
+    'ok': '\x1b[32m',
+    'failed': '\x1b[31m',
+    'ignored': '\x1b[33m',
+    'allowed_failure': '\x1b[33m',
+    'flaky': '\x1b[33m',
+}
+RESET = '\x1b[0m'
+
+# Character printed for each outcome in quiet mode, as libtest does
+SYMBOLS = {
+    'ok': '.',
+    'failed': 'F',
+    'ignored': 'i',
+    'allowed_failure': 'x',
+    'flaky': 'f',
+}
+
+class Progress:
+    '''Tracks the progress of a test suite from the events of the runner.
+
+    `test_timeout` is the number of seconds after which a running test is
+    considered hung, or `None` to wait forever. In `quiet` mode, one character
+    is printed per test instead of its name, and the output of a test is only
+    printed if it fails. `color` colors the outcomes with ANSI escape codes.'''
+
+    def __init__(self, test_timeout=None, quiet=False, color=False):
+        self.test_timeout = test_timeout
+        self.quiet = quiet
+        self.color = color
+        # Output of the running test, printed if it fails in quiet mode
+        self.output = []
+        self.suite = None
+        self.count = None
+        # Names of the tests which started, by index
+        self.names = {}
+        # Names of the tests by outcome
+        self.outcomes = {'ok': [], 'failed': [], 'ignored': [], 'allowed_failure': [], 'flaky': []}
+        # Group of the next test, and groups of the tests which started, by
+        # index
+        self.group = None
+        self.groups = {}
+        # Number of the tests of each group by outcome, in the order of the
+        # groups
+        self.group_outcomes = {}
+        self.ended = False
+        # Whether QEMU exited
+        self.closed = False
+        # Index and start time of the running test
+        self.current = None
+        self.lock = threading.Lock()
+
+    def _fail_current(self):
+        if self.current is not None:
+            self._record(self.current[0], 'failed')
+
+    def _record(self, index, outcome, attempts=None):
+        '''Records the `outcome` of the test at `index`, which passed after
+        `attempts` if it is flaky'''
+        name = self.names.get(index, f'test {index + 1}')
+        self.outcomes[outcome].append(name if attempts is None else f'{name} ({attempts} attempts)')
+        group = self.groups.get(index, self.group)
+        self.group = None
+        if group is not None:
+            counts = self.group_outcomes.setdefault(group, dict.fromkeys(self.outcomes, 0))
+            counts[outcome] += 1
+        self.current = None
+        if self.quiet:
+            print(self.paint(SYMBOLS[outcome], outcome), end='', flush=True)
+            if outcome == 'failed' and self.output:
+                print(f'\n--- Output of `{name}` ---', file=sys.stderr)
+                for line in self.output:
+                    print(line, file=sys.stderr)
+        self.output = []
+
+    def paint(self, text, outcome):
+        'Colors `text` as the `outcome` of a test if colors are enabled'
+        if not self.color:
+            return text
+        return f'{COLORS[outcome]}{text}{RESET}'
+
+    def record_output(self, line):
+        '''Records a line of output of the runner, which is printed as it
+        comes unless in quiet mode.'''
+        with self.lock:
+            if self.quiet:
+                self.output.append(line)
+            else:
+                print(line)
+
+    def handle(self, event):
+        'Updates the progress with `event`'
+        with self.lock:
+            if event.kind == 'SUITE-BEGIN':
+                # The tests start over if the machine was reset
+                self._fail_current()
+                for names in self.outcomes.values():
+                    names.clear()
+                self.group_outcomes.clear()
+                self.count = int(event.fields[0])
+                self.suite = event.fields[1]
+            elif event.kind == 'SUITE-RESUME':
+                # The runner reports the test which was running
+                print(f'Resuming after a reset during test {int(event.fields[0]) + 1}')
+                self.current = None
+                self.suite = event.fields[1]
+            elif event.kind == 'GROUP':
+                self.group = event.fields[0]
+            elif event.kind == 'TEST-START':
+                index = int(event.fields[0])
+                self.names[index] = event.fields[1]
+                if self.group is not None:
+                    self.groups[index] = self.group
+                    self.group = None
+                self.current = (index, time.monotonic())
+                self.output = []
+                if not self.quiet:
+                    print(f'[{index + 1}/{self.count}] {event.fields[1]}')
+            elif event.kind == 'TEST-RESULT':
+                outcome, *attempts = event.fields[1].split(' ')
+                assert outcome in self.outcomes, f'Unknown outcome `{outcome}`'
+                self._record(int(event.fields[0]), outcome, *attempts)
+            elif event.kind == 'SUITE-END':
+                if self.quiet:
+                    print()
+                ran = sum(len(names) for names in self.outcomes.values())
+                assert int(event.fields[0]) == ran, 'Mismatched test count'
+                self.ended = True
+
+    def hung_test(self):
+        'Returns the name of the running test if it exceeded the timeout'
+        with self.lock:
+            if self.current is None or self.test_timeout is None:
+                return None
+            index, start = self.current
+            if time.monotonic() - start > self.test_timeout:
+                return self.names[index]
+            return None
+
+    def watch(self, on_hang, interval=1):
+        '''Starts a thread which calls `on_hang` with the name of the running
+        test once it exceeds the timeout.'''
+        def watchdog():
+            while not self.closed:
+                name = self.hung_test()
+                if name is not None:
+                    on_hang(name)
+                    return
+                time.sleep(interval)
+        thread = threading.Thread(target=watchdog, daemon=True)
+        thread.start()
+        return thread
+
+    def finish(self):
+        '''Counts the running test as failed, since it will never report its
+        result, and returns a summary of the suite.'''
+        with self.lock:
+            self.closed = True
+            self._fail_current()
+            if self.quiet and not self.ended:
+                # The output which led to an early exit, such as a panic
+                # outside of the tests
+                print()
+                for line in self.output:
+                    print(line, file=sys.stderr)
+            failed = self.outcomes['failed']
+            counts = [(f"{len(self.outcomes[outcome])} {label}", outcome)
+                      for outcome, label in (('ok', 'passed'), ('failed', 'failed'),
+                                             ('ignored', 'ignored'),
+                                             ('allowed_failure', 'expected failures'),
+                                             ('flaky', 'flaky'))]
+            summary = ', '.join(self.paint(text, outcome) if self.outcomes[outcome] else text
+                                for text, outcome in counts)
+            if failed:
+                summary += ': ' + ', '.join(failed)
+            if self.outcomes['flaky']:
+                summary += '; flaky: ' + ', '.join(self.outcomes['flaky'])
+            if self.count is not None and not self.ended:
+                missing = self.count - sum(len(names) for names in self.outcomes.values())
+                summary += f' ({missing} did not run)'
+            return summary
+
+    def group_summaries(self):
+        'Returns a summary of the results of each group, in their order'
+        with self.lock:
+            return [f"group {group}: {counts['ok'] + counts['flaky']} passed, "
+                    f"{counts['failed']} failed, {counts['ignored']} ignored, "
+                    f"{counts['allowed_failure']} expected failures"
+                    for group, counts in self.group_outcomes.items()]
+
+    def crashed(self):
+        'Whether the suite began, but stopped before its end'
+        with self.lock:
+            return self.suite is not None and not self.ended
+
+    def succeeded(self):
+        'Whether the suite ended without failures, or sent no events at all'
+        with self.lock:
+            return not self.outcomes['failed'] and (self.ended or self.suite is None)
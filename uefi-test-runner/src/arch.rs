@@ -0,0 +1,85 @@
+use crate::suite::ProcessorLog;
+use core::fmt::Write;
+use uefi::arch::{self, interrupts};
+
+pub fn test() {
+    info!("Testing CPU intrinsics");
+    #[cfg(target_arch = "x86_64")]
+    test_control_registers();
+    #[cfg(target_arch = "x86_64")]
+    test_cpu_features();
+    test_interrupts();
+    test_cache_flush();
+}
+
+#[cfg(target_arch = "x86_64")]
+fn test_control_registers() {
+    use uefi::arch::x86::{self, Cr0};
+
+    let cr0 = x86::read_cr0();
+    assert!(cr0.contains(Cr0::PROTECTED_MODE | Cr0::PAGING));
+    // UEFI identity-maps memory, so the page tables are at a physical address
+    let page_table = x86::read_cr3() & !0xfff;
+    assert_ne!(page_table, 0);
+    assert!(x86::cache_line_size().is_power_of_two());
+}
+
+/// Check the control registers of the processor which runs it, which may be
+/// an application processor
+#[cfg(target_arch = "x86_64")]
+pub fn check_control_registers(log: &mut ProcessorLog) -> Result<(), &'static str> {
+    use uefi::arch::x86::{self, Cr0};
+
+    if !x86::read_cr0().contains(Cr0::PROTECTED_MODE | Cr0::PAGING) {
+        return Err("Paging is disabled");
+    }
+    let page_table = x86::read_cr3() & !0xfff;
+    if page_table == 0 {
+        return Err("There are no page tables");
+    }
+    let _ = writeln!(log, "Page tables at {:#x}", page_table);
+    Ok(())
+}
+
+/// Check that the timestamp counter of the processor which runs it, which
+/// may be an application processor, advances
+pub fn check_timestamp(log: &mut ProcessorLog) -> Result<(), &'static str> {
+    let start = arch::read_timestamp();
+    for spins in 0..1_000_000 {
+        if arch::read_timestamp() != start {
+            let _ = writeln!(log, "The timestamp counter advanced after {} spins", spins);
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err("The timestamp counter does not advance")
+}
+
+#[cfg(target_arch = "x86_64")]
+fn test_cpu_features() {
+    use uefi::arch::x86::{self, CpuFeatures, Xcr0};
+
+    let features = x86::cpu_features();
+    let usable = x86::usable_features();
+    info!("CPU features: {:?}", features);
+    assert!(features.contains(usable));
+    // All the states were enabled by the `enable_simd` flag of the entry point
+    assert_eq!(usable, features);
+    // x86_64 implies SSE2, and the compiler already relies on it
+    assert!(usable.contains(CpuFeatures::SSE | CpuFeatures::SSE2));
+    if let Some(xcr0) = x86::read_xcr0() {
+        assert!(xcr0.contains(Xcr0::X87));
+    }
+}
+
+fn test_interrupts() {
+    let enabled = interrupts::are_enabled();
+    let inside = interrupts::without_interrupts(interrupts::are_enabled);
+    assert!(!inside, "Interrupts were not disabled");
+    assert_eq!(interrupts::are_enabled(), enabled);
+}
+
+fn test_cache_flush() {
+    let buffer = [0u8; 256];
+    arch::flush_cache_range(&buffer);
+}
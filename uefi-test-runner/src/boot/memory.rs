@@ -1,18 +1,54 @@
 use uefi::prelude::*;
-use uefi::table::boot::{AllocateType, BootServices, MemoryDescriptor, MemoryType};
+use uefi::table::boot::{
+    self, AllocateType, BootServices, MemoryDescriptor, MemoryType, PAGE_SIZE,
+};
 
 use crate::alloc::vec::Vec;
+use crate::suite::{Fixture, TestContext};
 use core::mem;
 
 pub fn test(bt: &BootServices) {
     info!("Testing memory functions");
 
     allocate_pages(bt);
+    allocate_pages_constrained(bt);
+    pool_wrappers(bt);
     vec_alloc();
     alloc_alignment();
     memmove(bt);
 
     memory_map(bt);
+    owned_memory_map(bt);
+    memory_map_ranges(bt);
+}
+
+fn pool_wrappers(bt: &BootServices) {
+    info!("Allocating pool memory with RAII wrappers");
+
+    let mut value = bt
+        .allocate_pool_typed(MemoryType::LOADER_DATA, [1u64; 4])
+        .expect_success("Failed to allocate a pool value");
+    value[3] = 7;
+    assert_eq!(*value, [1, 1, 1, 7]);
+
+    let mut slice = bt
+        .allocate_pool_slice(MemoryType::LOADER_DATA, 1000, 0xAAu8)
+        .expect_success("Failed to allocate a pool slice");
+    assert_eq!(slice.len(), 1000);
+    assert!(slice.iter().all(|&byte| byte == 0xAA));
+    slice[999] = 0;
+
+    let empty = bt
+        .allocate_pool_slice(MemoryType::LOADER_DATA, 0, 0u32)
+        .expect_success("Failed to allocate an empty pool slice");
+    assert!(empty.is_empty());
+
+    #[repr(align(16))]
+    struct OverAligned;
+    let err = bt
+        .allocate_pool_typed(MemoryType::LOADER_DATA, OverAligned)
+        .expect_error("Pool allocations should not support 16-byte alignment");
+    assert_eq!(err.status(), Status::UNSUPPORTED);
 }
 
 fn allocate_pages(bt: &BootServices) {
@@ -37,6 +73,39 @@ fn allocate_pages(bt: &BootServices) {
     bt.free_pages(pgs, 1).unwrap_success();
 }
 
+fn allocate_pages_constrained(bt: &BootServices) {
+    info!("Allocating pages at constrained addresses");
+
+    assert_eq!(boot::size_to_pages(0), 0);
+    assert_eq!(boot::size_to_pages(1), 1);
+    assert_eq!(boot::size_to_pages(PAGE_SIZE), 1);
+    assert_eq!(boot::size_to_pages(PAGE_SIZE + 1), 2);
+
+    let count = boot::size_to_pages(3 * PAGE_SIZE / 2);
+    let max_addr = 0xffff_ffff;
+    let pgs = bt
+        .allocate_pages_below(max_addr, count)
+        .expect_success("Failed to allocate pages below 4 GiB");
+    assert!(
+        pgs + (count * PAGE_SIZE) as u64 - 1 <= max_addr as u64,
+        "Pages were allocated above the maximum address"
+    );
+
+    // Free the pages, then claim them back at their exact address.
+    bt.free_pages(pgs, count).unwrap_success();
+    let fixed = bt
+        .allocate_pages_at(pgs as usize, count)
+        .expect_success("Failed to allocate pages at a fixed address");
+    assert_eq!(
+        fixed, pgs,
+        "Pages were not allocated at the requested address"
+    );
+    bt.free_pages(fixed, count).unwrap_success();
+
+    let misaligned = bt.allocate_pages_at(pgs as usize + 1, count);
+    assert_eq!(misaligned.status(), Status::INVALID_PARAMETER);
+}
+
 // Simple test to ensure our custom allocator works with the `alloc` crate.
 fn vec_alloc() {
     info!("Allocating a vector through the `alloc` crate");
@@ -119,3 +188,124 @@ fn memory_map(bt: &BootServices) {
     let page_count = first_desc.page_count;
     assert!(page_count != 0, "Memory map entry has zero size");
 }
+
+/// A page of `LOADER_DATA` memory, by address
+pub const PAGE: Fixture<u64> = Fixture {
+    setup: |bt| {
+        bt.allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, 1)
+            .expect_success("Failed to allocate the page")
+    },
+    teardown: |bt, page| {
+        bt.free_pages(page, 1)
+            .expect_success("Failed to free the page")
+    },
+};
+
+/// Fill the page of the `PAGE` fixture, and read it back
+pub fn fill_page(page: &mut u64) -> TestContext {
+    let page = unsafe { core::slice::from_raw_parts_mut(*page as *mut u8, PAGE_SIZE) };
+    page.fill(0xa5);
+    let mut cx = TestContext::default();
+    uefi_assert_eq!(cx, page[0], 0xa5);
+    uefi_assert_eq!(cx, page[PAGE_SIZE - 1], 0xa5);
+    uefi_assert!(cx, page.iter().all(|&byte| byte == 0xa5));
+    cx
+}
+
+/// Allocate all the free memory, in smaller and smaller blocks, and check
+/// that all of it is free again once the blocks are freed
+///
+/// The firmware hands out the memory it has left in ever smaller blocks, so
+/// this takes a while.
+pub fn exhaust(bt: &BootServices) {
+    info!("Allocating all the free memory");
+
+    let free_pages = || {
+        bt.memory_map_owned()
+            .expect_success("Failed to retrieve owned UEFI memory map")
+            .conventional_pages()
+    };
+    let free = free_pages();
+
+    // The blocks are recorded without allocating once the memory is exhausted
+    let mut blocks = Vec::with_capacity(256);
+    let mut count = free as usize;
+    while count > 0 && blocks.len() < blocks.capacity() {
+        match bt.allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, count) {
+            Ok(pages) => blocks.push((pages.unwrap(), count)),
+            Err(error) => {
+                assert_eq!(error.status(), Status::OUT_OF_RESOURCES);
+                count /= 2;
+            }
+        }
+    }
+
+    let allocated: usize = blocks.iter().map(|&(_, count)| count).sum();
+    info!("Allocated {} of {} free pages", allocated, free);
+    for (pages, count) in blocks {
+        bt.free_pages(pages, count).unwrap_success();
+    }
+    assert_eq!(free_pages(), free, "Some of the memory was not freed");
+}
+
+fn owned_memory_map(bt: &BootServices) {
+    info!("Testing owned memory map post-processing");
+
+    let mut mmap = bt
+        .memory_map_owned()
+        .expect_success("Failed to retrieve owned UEFI memory map");
+    assert!(!mmap.is_empty(), "Memory map is empty");
+
+    let conventional_pages = mmap.conventional_pages();
+    assert!(conventional_pages != 0, "No conventional memory available");
+
+    for desc in mmap.persistent() {
+        info!(
+            "Persistent memory at {:#x}, {} pages",
+            desc.phys_start, desc.page_count
+        );
+    }
+
+    mmap.sort();
+    assert!(
+        mmap.entries()
+            .windows(2)
+            .all(|pair| pair[0].phys_start <= pair[1].phys_start),
+        "Memory map is not sorted"
+    );
+
+    let len_before_merge = mmap.len();
+    mmap.merge();
+    assert!(mmap.len() <= len_before_merge, "Merging added descriptors");
+    assert_eq!(
+        mmap.conventional_pages(),
+        conventional_pages,
+        "Merging changed the amount of conventional memory"
+    );
+    assert_eq!(
+        mmap.conventional_bytes(),
+        conventional_pages * PAGE_SIZE as u64
+    );
+}
+
+fn memory_map_ranges(bt: &BootServices) {
+    info!("Testing address range queries of the memory map");
+
+    let pages = bt
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, 2)
+        .expect_success("Failed to allocate pages");
+    let mmap = bt
+        .memory_map_owned()
+        .expect_success("Failed to retrieve owned UEFI memory map");
+    bt.free_pages(pages, 2).unwrap_success();
+
+    let size = 2 * PAGE_SIZE as u64;
+    assert_eq!(mmap.type_of(pages), Some(MemoryType::LOADER_DATA));
+    assert!(mmap.owns(pages, size));
+    assert!(mmap.is_of_type(pages, size, MemoryType::LOADER_DATA));
+    assert!(!mmap.is_conventional(pages, size));
+
+    let free = mmap.conventional().next().expect("No conventional memory");
+    assert!(mmap.is_conventional(free.phys_start, free.page_count * PAGE_SIZE as u64));
+    assert!(!mmap.owns(u64::MAX - 1, 2));
+}
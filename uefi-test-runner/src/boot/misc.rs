@@ -1,5 +1,7 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use uefi::sync::{Lazy, OnceCell, TplCell, TplMutex};
 use uefi::table::boot::{BootServices, EventType, TimerTrigger, Tpl};
-use uefi::{prelude::*, Event};
+use uefi::{prelude::*, Event, Guid};
 
 pub fn test(bt: &BootServices) {
     info!("Testing timer...");
@@ -8,6 +10,75 @@ pub fn test(bt: &BootServices) {
     test_event_callback(bt);
     info!("Testing watchdog...");
     test_watchdog(bt);
+    info!("Testing raw services...");
+    test_raw_services(bt);
+    info!("Testing CRC-32...");
+    test_crc32(bt);
+    info!("Testing the global system table...");
+    test_global_system_table(bt);
+    info!("Testing TPL synchronization...");
+    test_tpl_mutex(bt);
+    test_once_cell(bt);
+}
+
+fn test_once_cell(bt: &BootServices) {
+    static CELL: OnceCell<u32> = OnceCell::new(Tpl::NOTIFY);
+    static LAZY: Lazy<u32> = Lazy::new(Tpl::NOTIFY, || 42);
+    assert_eq!(CELL.get(), None);
+    assert_eq!(*CELL.get_or_init(bt, || 1), 1);
+    assert_eq!(CELL.set(bt, 2), Err(2));
+    assert_eq!(CELL.get(), Some(&1));
+    assert_eq!(LAZY.get(), None);
+    assert_eq!(*LAZY.force(bt), 42);
+    assert_eq!(LAZY.get(), Some(&42));
+}
+
+fn test_tpl_mutex(bt: &BootServices) {
+    static COUNTER: TplCell<u32> = TplCell::new(Tpl::NOTIFY, 0);
+    static LOCK: TplMutex<()> = TplMutex::new(Tpl::NOTIFY, ());
+    fn callback(_event: Event) {
+        let bt = uefi::table::boot::system_table().unwrap().boot_services();
+        let _lock = LOCK.lock(bt);
+        COUNTER.update(bt, |count| count + 1);
+    }
+    let event = unsafe { bt.create_event(EventType::NOTIFY_SIGNAL, Tpl::CALLBACK, Some(callback)) }
+        .expect_success("Failed to create event");
+    {
+        let _lock = LOCK.lock(bt);
+        bt.signal_event(event)
+            .expect_success("Failed to signal event");
+        // The callback is pending until the TPL is restored
+        assert_eq!(COUNTER.get(bt), 0);
+        assert!(LOCK.try_lock(bt).is_none());
+    }
+    assert_eq!(COUNTER.get(bt), 1);
+    {
+        // The TPL can not be raised to the one of the mutex from above it
+        let _tpl = unsafe { bt.raise_tpl(Tpl::HIGH_LEVEL) };
+        assert_eq!(bt.current_tpl(), Tpl::HIGH_LEVEL);
+        assert!(LOCK.try_lock(bt).is_none());
+    }
+    assert!(LOCK.try_lock(bt).is_some());
+    bt.close_event(event)
+        .expect_success("Failed to close event");
+}
+
+fn test_global_system_table(bt: &BootServices) {
+    // Registered by `uefi_services::init`
+    let st = uefi::table::boot::system_table().expect("The system table is not registered");
+    assert_eq!(
+        st.boot_services() as *const BootServices,
+        bt as *const BootServices
+    );
+    assert!(uefi::table::boot::boot_services_active());
+}
+
+fn test_crc32(bt: &BootServices) {
+    let data = b"123456789";
+    let crc = bt
+        .calculate_crc32(data)
+        .expect_success("Failed to compute CRC-32");
+    assert_eq!(crc, uefi::crc::crc32(data));
 }
 
 fn test_timer(bt: &BootServices) {
@@ -30,8 +101,41 @@ fn test_event_callback(bt: &BootServices) {
         .expect_success("Failed to check event");
 }
 
+/// `CreateEventEx` is only available since UEFI 2.0
+pub fn test_event_group(bt: &BootServices) {
+    static NOTIFIED: AtomicBool = AtomicBool::new(false);
+    fn callback(_event: Event) {
+        NOTIFIED.store(true, Ordering::Relaxed);
+    }
+    // A group of our own, so that no firmware handler runs
+    let group = Guid::from_values(0x3ed5_4bc2, 0x1b8a, 0x4c05, 0x9a8e, 0x27d6_0c2f_e4b1);
+    let event = unsafe { bt.subscribe_event_group(&group, callback) }
+        .expect_success("Failed to subscribe to the event group");
+    bt.signal_event_group(&group)
+        .expect_success("Failed to signal the event group");
+    assert!(
+        NOTIFIED.load(Ordering::Relaxed),
+        "Group notification not called"
+    );
+    bt.close_event(event)
+        .expect_success("Failed to close event");
+}
+
 fn test_watchdog(bt: &BootServices) {
     // Disable the UEFI watchdog timer
     bt.set_watchdog_timer(0, 0x10000, None)
         .expect_success("Could not set watchdog timer");
 }
+
+fn test_raw_services(bt: &BootServices) {
+    // `GetNextMonotonicCount` has no safe wrapper
+    let raw = bt.as_raw();
+    let mut count = 0;
+    let first = unsafe { (raw.get_next_monotonic_count)(&mut count) }
+        .into_with_val(|| count)
+        .expect_success("Failed to get monotonic count");
+    let second = unsafe { (raw.get_next_monotonic_count)(&mut count) }
+        .into_with_val(|| count)
+        .expect_success("Failed to get monotonic count");
+    assert!(second > first, "Monotonic count did not increase");
+}
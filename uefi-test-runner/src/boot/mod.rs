@@ -6,5 +6,5 @@ pub fn test(bt: &BootServices) {
     misc::test(bt);
 }
 
-mod memory;
-mod misc;
+pub mod memory;
+pub mod misc;
@@ -0,0 +1,99 @@
+//! Capture of the log of the running test, which is only printed if the test
+//! fails, as libtest captures the output of the tests
+
+use alloc::string::String;
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+
+/// Size of the log which is kept for a test, past which the oldest bytes are
+/// overwritten
+const CAPACITY: usize = 0x10000;
+
+/// Log of the running test
+pub struct Capture(UnsafeCell<Ring>);
+
+// The tests run on a single processor, which logs one record at a time.
+unsafe impl Sync for Capture {}
+
+/// Bytes of the log, of which `len` start at `start`, wrapping around
+struct Ring {
+    data: [u8; CAPACITY],
+    start: usize,
+    len: usize,
+}
+
+impl fmt::Write for Ring {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.data[(self.start + self.len) % CAPACITY] = byte;
+            if self.len < CAPACITY {
+                self.len += 1;
+            } else {
+                self.start = (self.start + 1) % CAPACITY;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub static CAPTURE: Capture = Capture(UnsafeCell::new(Ring {
+    data: [0; CAPACITY],
+    start: 0,
+    len: 0,
+}));
+
+impl Capture {
+    /// Start capturing the log, forgetting what was kept before
+    pub fn start(&'static self) {
+        let ring = unsafe { &mut *self.0.get() };
+        ring.start = 0;
+        ring.len = 0;
+        uefi_services::set_log_capture(Some(self));
+    }
+
+    /// Stop capturing the log, and print what was kept if `print`
+    pub fn stop(&'static self, print: bool) {
+        uefi_services::set_log_capture(None);
+        if print {
+            log::Log::flush(self);
+        }
+    }
+}
+
+impl log::Log for Capture {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let ring = unsafe { &mut *self.0.get() };
+        let _ = writeln!(
+            ring,
+            "[{:>5}]: {:>12}@{:03}: {}",
+            record.level(),
+            record.file().unwrap_or("<unknown file>"),
+            record.line().unwrap_or(0),
+            record.args()
+        );
+    }
+
+    /// Print the log on the console, as the panic handler does before it logs
+    /// the panic
+    fn flush(&self) {
+        let ring = unsafe { &mut *self.0.get() };
+        let end = ring.start + ring.len;
+        let (first, second) = if end <= CAPACITY {
+            (&ring.data[ring.start..end], &[][..])
+        } else {
+            (&ring.data[ring.start..], &ring.data[..end - CAPACITY])
+        };
+        let mut st = unsafe { uefi_services::system_table().as_ref().unsafe_clone() };
+        for part in &[first, second] {
+            // The oldest bytes may have been overwritten in the middle of a
+            // character
+            let _ = st.stdout().write_str(&String::from_utf8_lossy(part));
+        }
+        ring.start = 0;
+        ring.len = 0;
+    }
+}
@@ -0,0 +1,74 @@
+//! Messages to the QEMU-based test runner, over the serial port
+
+use core::fmt::{self, Write};
+use uefi::prelude::*;
+use uefi::proto::console::serial::Serial;
+
+/// Send the result of a test, or of the suite, to the QEMU-based test runner,
+/// as a line of the JSON format of libtest, which it writes to the file given
+/// to `--json`
+pub fn send_json(line: fmt::Arguments) {
+    if cfg!(not(feature = "qemu")) {
+        return;
+    }
+    send_line(&format!("JSON: {}\n", line));
+}
+
+/// Send a progress message to the QEMU-based test runner, which does not
+/// reply
+///
+/// The message is prefixed with its CRC32, so that the runner can tell it
+/// apart from the console output which OVMF mirrors on the serial port. See
+/// `progress.py` for the messages.
+pub fn send_event(event: fmt::Arguments) {
+    if cfg!(not(feature = "qemu")) {
+        return;
+    }
+    let bt = unsafe { uefi_services::system_table().as_ref() }.boot_services();
+    let event = format!("{}", event);
+    let crc = bt
+        .calculate_crc32(event.as_bytes())
+        .expect_success("Failed to compute the message checksum");
+    send_line(&format!("TEST: {:08x} {}\n", crc, event));
+}
+
+/// Write `line` to the serial port (in a QEMU environment, it should always
+/// be there)
+fn send_line(line: &str) {
+    let bt = unsafe { uefi_services::system_table().as_ref() }.boot_services();
+    let serial = bt
+        .locate_protocol::<Serial>()
+        .expect_success("Could not find serial port");
+    let serial = unsafe { &mut *serial.get() };
+    serial
+        .write(line.as_bytes())
+        .expect_success("Failed to send test result");
+}
+
+/// A string, quoted and escaped as in JSON
+pub struct JsonString<'a>(pub &'a str);
+
+impl fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// A duration in microseconds, printed in seconds as in JSON
+pub struct JsonSeconds(pub u64);
+
+impl fmt::Display for JsonSeconds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:06}", self.0 / 1_000_000, self.0 % 1_000_000)
+    }
+}
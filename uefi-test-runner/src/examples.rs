@@ -0,0 +1,130 @@
+//! Small examples of the API, which run as smoke tests.
+//!
+//! Each example uses a wrapper from start to end, the way an application
+//! would, and is listed in `EXAMPLES`. The runner runs every example as a
+//! test of its suite, after checking its requirements, so that the common
+//! uses of the API are verified on every run.
+
+use crate::requirement::Requirement;
+use core::convert::TryFrom;
+use uefi::prelude::*;
+use uefi::proto::console::gop::GraphicsOutput;
+use uefi::proto::media::file::{File, FileAttribute, FileMode, FileType};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::table::runtime::{VariableAttributes, VariableVendor};
+use uefi::{CString16, Guid, Identify};
+
+/// A runnable example
+pub struct Example {
+    /// Name of the test which runs the example
+    pub name: &'static str,
+    /// Conditions for the example to run
+    pub requirements: &'static [Requirement],
+    /// The example itself
+    pub run: fn(&SystemTable<Boot>),
+}
+
+/// The examples, in the order in which they run
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        name: "Example: variable round trip",
+        requirements: &[],
+        run: variable_round_trip,
+    },
+    Example {
+        name: "Example: file round trip",
+        requirements: &[Requirement::Protocol(
+            "SimpleFileSystem",
+            SimpleFileSystem::GUID,
+        )],
+        run: file_round_trip,
+    },
+    Example {
+        name: "Example: graphics mode",
+        requirements: &[Requirement::Protocol(
+            "GraphicsOutput",
+            GraphicsOutput::GUID,
+        )],
+        run: graphics_mode,
+    },
+];
+
+/// Set a variable, read it back, and delete it
+fn variable_round_trip(st: &SystemTable<Boot>) {
+    let rt = st.runtime_services();
+    let name = CString16::try_from("UefiRsExampleVar").unwrap();
+    // Arbitrary GUID generated for the examples.
+    let vendor = VariableVendor(Guid::from_values(
+        0x1c6e_43b0,
+        0x7a2f,
+        0x4d51,
+        0x8b0e,
+        0x6f93_d2a4_c71e,
+    ));
+    let attributes = VariableAttributes::BOOTSERVICE_ACCESS;
+
+    rt.set_variable(&name, &vendor, attributes, b"example")
+        .expect_success("Failed to set the variable");
+    let mut buffer = [0; 16];
+    let (value, _) = rt
+        .get_variable(&name, &vendor, &mut buffer)
+        .expect_success("Failed to read the variable");
+    assert_eq!(value, b"example");
+
+    // An empty value deletes the variable
+    rt.set_variable(&name, &vendor, attributes, &[])
+        .expect_success("Failed to delete the variable");
+    assert_eq!(
+        rt.get_variable_size(&name, &vendor).status(),
+        Status::NOT_FOUND
+    );
+}
+
+/// Write a file on the boot volume, read it back, and delete it
+fn file_round_trip(st: &SystemTable<Boot>) {
+    let sfs = st
+        .boot_services()
+        .locate_protocol::<SimpleFileSystem>()
+        .expect_success("Failed to open the file system");
+    let sfs = unsafe { &mut *sfs.get() };
+    let mut root = sfs
+        .open_volume()
+        .expect_success("Failed to open the root directory");
+
+    let path = CString16::try_from("example.txt").unwrap();
+    root.write_file(&path, b"Hello, file!")
+        .expect_success("Failed to write the file");
+    let content = root
+        .read_to_vec(&path)
+        .expect_success("Failed to read the file");
+    assert_eq!(content, b"Hello, file!");
+
+    let file = root
+        .open("example.txt", FileMode::ReadWrite, FileAttribute::empty())
+        .expect_success("Failed to open the file");
+    match file.into_type().unwrap_success() {
+        FileType::Regular(file) => file.delete().expect_success("Failed to delete the file"),
+        FileType::Dir(_) => panic!("example.txt is a directory"),
+    }
+    assert!(root.read_to_vec(&path).is_err());
+}
+
+/// Switch to the current graphics mode again, which the firmware supports
+/// by definition
+fn graphics_mode(st: &SystemTable<Boot>) {
+    let gop = st
+        .boot_services()
+        .locate_protocol::<GraphicsOutput>()
+        .expect_success("Failed to open the graphics output");
+    let gop = unsafe { &mut *gop.get() };
+
+    let current = gop.current_mode_info();
+    let mode = gop
+        .modes()
+        .map(|mode| mode.log())
+        .find(|mode| mode.info().resolution() == current.resolution())
+        .expect("The current mode is not listed");
+    gop.set_mode(&mode)
+        .expect_success("Failed to set the graphics mode");
+    assert_eq!(gop.current_mode_info().resolution(), current.resolution());
+}
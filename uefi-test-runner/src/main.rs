@@ -15,17 +15,45 @@ use alloc::string::String;
 use core::mem;
 use uefi::prelude::*;
 use uefi::proto::console::serial::Serial;
-use uefi::table::boot::MemoryDescriptor;
+use uefi::proto::console::text::Output;
+use uefi::proto::pi::mp::MpServices;
+use uefi::table::boot::{MemoryDescriptor, MemoryType, SearchType, Tpl, PAGE_SIZE};
+use uefi::table::Revision;
+use uefi::{Completion, Identify};
 
+use options::Options;
+use policy::{KnownIssue, SkipPolicy};
+use requirement::Requirement;
+use suite::Suite;
+
+// Declared first, for its assertion macros
+#[macro_use]
+mod suite;
+
+mod arch;
 mod boot;
+mod capture;
+mod events;
+mod examples;
+mod options;
+mod policy;
 mod proto;
+mod requirement;
+mod resume;
 mod runtime;
 
-#[entry]
+#[entry(enable_simd)]
 fn efi_main(image: Handle, mut st: SystemTable<Boot>) -> Status {
+    // Measure how much of the firmware's stack the tests use
+    unsafe { uefi::stack::paint(64 * 1024) };
+
     // Initialize utilities (logging, memory allocation...)
     uefi_services::init(&mut st).expect_success("Failed to initialize utilities");
 
+    // Report processor faults as test failures, instead of hanging
+    #[cfg(target_arch = "x86_64")]
+    catch_faults(st.boot_services());
+
     // unit tests here
 
     // output firmware-vendor (CStr16 to Rust string)
@@ -33,6 +61,14 @@ fn efi_main(image: Handle, mut st: SystemTable<Boot>) -> Status {
     st.firmware_vendor().as_str_in_buf(&mut buf).unwrap();
     info!("Firmware Vendor: {}", buf.as_str());
 
+    // Choose which tests to run from the load options
+    let options = Options::from_image(st.boot_services(), image);
+    log::set_max_level(options.log_level());
+
+    // Print the offsets of the backtraces in the image, to symbolize them
+    uefi::backtrace::register_image(st.boot_services(), image)
+        .expect_success("Failed to register the image for backtraces");
+
     // Reset the console before running all the other tests.
     st.stdout()
         .reset(false)
@@ -41,26 +77,122 @@ fn efi_main(image: Handle, mut st: SystemTable<Boot>) -> Status {
     // Ensure the tests are run on a version of UEFI we support.
     check_revision(st.uefi_revision());
 
+    // The tests are kept until the suite runs them, so the protocol tests
+    // cannot borrow the system table mutably while the others borrow it
+    let mut proto_st = unsafe { st.unsafe_clone() };
+
+    let mut suite = Suite::begin("uefi-test-runner", &st, options);
+
+    let mut policy = SkipPolicy::default();
+    if cfg!(feature = "ci") {
+        policy.add(CI_KNOWN_ISSUES);
+    }
+    if cfg!(feature = "qemu") {
+        policy.add(OVMF_KNOWN_ISSUES);
+    }
+    suite.set_policy(policy);
+
     // Test all the boot services.
+    suite.group("boot");
     let bt = st.boot_services();
 
     // Try retrieving a handle to the file system the image was booted from.
-    bt.get_image_file_system(image)
-        .expect("Failed to retrieve boot file system")
-        .unwrap();
+    suite.run("Boot file system", || {
+        bt.get_image_file_system(image).log_warning()
+    });
+
+    suite.run("Boot services", || check_leaks(0, 0, || boot::test(bt)));
+    suite.run_if(
+        "Event groups",
+        &[Requirement::Revision(Revision::EFI_2_00)],
+        || boot::misc::test_event_group(bt),
+    );
+    suite.bench("Raise and restore the TPL", || unsafe {
+        bt.raise_tpl(Tpl::NOTIFY)
+    });
+    suite.run_with_fixture("Fill a page", &boot::memory::PAGE, boot::memory::fill_page);
+    suite.run_ignored(
+        "Exhaust the free memory",
+        "slow, allocates all the free memory",
+        || boot::memory::exhaust(bt),
+    );
+    suite.run_should_panic(
+        "Unwrap a completion with a warning",
+        "Called `Completion::unwrap()` with a warning status",
+        || {
+            Completion::new(Status::WARN_DELETE_FAILURE, ()).unwrap();
+        },
+    );
 
-    boot::test(bt);
+    // Test the CPU intrinsics.
+    suite.group("arch");
+    suite.run("CPU intrinsics", arch::test);
+    suite.run_on_processors("Timestamp counter", arch::check_timestamp);
+    #[cfg(target_arch = "x86_64")]
+    suite.run_on_processors("Control registers", arch::check_control_registers);
 
     // Test all the supported protocols.
-    proto::test(image, &mut st);
+    suite.group("proto");
+    suite.run("Protocols", move || proto::test(image, &mut proto_st));
+    suite.run("Debug support of the processor", || {
+        proto::debug::test_processor_arch(bt)
+    });
+    // These tests depend on the timing of the application processors.
+    suite.run_with_retries(
+        "Multi-processor services",
+        &[Requirement::Protocol("MpServices", MpServices::GUID)],
+        2,
+        || proto::pi::mp::test(bt),
+    );
+
+    // Run the examples of the API.
+    suite.group("examples");
+    let example_st = &st;
+    for example in examples::EXAMPLES {
+        suite.run_if(example.name, example.requirements, move || {
+            (example.run)(example_st)
+        });
+    }
 
     // TODO: runtime services work before boot services are exited, but we'd
     // probably want to test them after exit_boot_services. However,
     // exit_boot_services is currently called during shutdown.
 
-    runtime::test(st.runtime_services());
+    suite.group("runtime");
+    suite.run("Runtime services", || {
+        check_leaks(0, 0, || runtime::test(st.runtime_services()))
+    });
+
+    let failures = suite.end();
+
+    shutdown(image, st, failures);
+}
+
+/// Known issues of the tests in CI
+const CI_KNOWN_ISSUES: &[KnownIssue] = &[KnownIssue::skip(
+    "Multi-processor services",
+    "breaks in CI",
+    "#103",
+)];
 
-    shutdown(image, st);
+/// Known issues of the tests on the OVMF firmware, which QEMU runs
+const OVMF_KNOWN_ISSUES: &[KnownIssue] = &[KnownIssue::xfail(
+    "Debug support of the processor",
+    "OVMF only implements the debug support of EBC",
+    "upstream OVMF",
+)];
+
+/// Make processor faults panic, so that the panic handler reports them
+#[cfg(target_arch = "x86_64")]
+fn catch_faults(bt: &BootServices) {
+    use uefi::proto::pi::cpu::CpuArch;
+
+    match bt.locate_protocol::<CpuArch>() {
+        Ok(cpu) => unsafe { &mut *cpu.log().get() }
+            .panic_on_faults()
+            .expect_success("Failed to register fault handlers"),
+        Err(_) => warn!("Processor faults cannot be caught"),
+    }
 }
 
 fn check_revision(rev: uefi::table::Revision) {
@@ -75,6 +207,65 @@ fn check_revision(rev: uefi::table::Revision) {
     );
 }
 
+/// Run the test `f`, which fails if it does not free what it allocates with
+/// the global allocator, or leaves pages or handles behind
+///
+/// `allowed` is the number of bytes which `f` may leave allocated, such as
+/// caches which are kept until the end, and `allowed_handles` the number of
+/// handles it may leave, such as those of drivers which stay loaded.
+fn check_leaks(allowed: usize, allowed_handles: usize, f: impl FnOnce()) -> Result<(), String> {
+    let bt = unsafe { uefi_services::system_table().as_ref() }.boot_services();
+    let count_handles = || {
+        bt.locate_handle(SearchType::AllHandles, None)
+            .expect_success("Failed to count the handles")
+    };
+    let handles_before = count_handles();
+    let map_before = bt
+        .memory_map_owned()
+        .expect_success("Failed to retrieve the memory map");
+
+    uefi::alloc::reset_peak();
+    let before = uefi::alloc::stats();
+    f();
+    let after = uefi::alloc::stats();
+
+    info!(
+        "{} allocations, peak of {} bytes",
+        after.allocations - before.allocations,
+        after.peak_bytes - before.current_bytes,
+    );
+    let leaked = after.current_bytes.saturating_sub(before.current_bytes);
+    if leaked > allowed {
+        return Err(format!(
+            "leaked {} bytes in {} allocations",
+            leaked,
+            after
+                .live_allocations()
+                .saturating_sub(before.live_allocations()),
+        ));
+    }
+
+    let map_after = bt
+        .memory_map_owned()
+        .expect_success("Failed to retrieve the memory map");
+    let diff = map_after.diff(&map_before);
+    // The pool may grow by the allowed bytes, and by the buffer of the memory
+    // map retrieved above
+    let buffer = map_after.len() * mem::size_of::<MemoryDescriptor>();
+    let allowed_pages = ((allowed + buffer) / PAGE_SIZE + 1) as i64;
+    for &ty in &[MemoryType::BOOT_SERVICES_DATA, MemoryType::LOADER_DATA] {
+        let pages = diff.pages_of(ty);
+        if pages > allowed_pages {
+            return Err(format!("left {} more pages of {:?}", pages, ty));
+        }
+    }
+    let handles = count_handles();
+    if handles > handles_before + allowed_handles {
+        return Err(format!("left {} more handles", handles - handles_before));
+    }
+    Ok(())
+}
+
 /// Ask the test runner to check the current screen output against a reference
 ///
 /// This functionality is very specific to our QEMU-based test runner. Outside
@@ -82,48 +273,87 @@ fn check_revision(rev: uefi::table::Revision) {
 /// inspection of the output.
 fn check_screenshot(bt: &BootServices, name: &str) {
     if cfg!(feature = "qemu") {
-        // Access the serial port (in a QEMU environment, it should always be there)
-        let serial = bt
-            .locate_protocol::<Serial>()
-            .expect_success("Could not find serial port");
-        let serial = unsafe { &mut *serial.get() };
-
-        // Set a large timeout to avoid problems with Travis
-        let mut io_mode = *serial.io_mode();
-        io_mode.timeout = 10_000_000;
-        serial
-            .set_attributes(&io_mode)
-            .expect_success("Failed to configure serial port timeout");
-
-        // Send a screenshot request to the host
-        serial
-            .write(b"SCREENSHOT: ")
-            .expect_success("Failed to send request");
-        let name_bytes = name.as_bytes();
-        serial
-            .write(name_bytes)
-            .expect_success("Failed to send request");
-        serial.write(b"\n").expect_success("Failed to send request");
-
-        // Wait for the host's acknowledgement before moving forward
-        let mut reply = [0; 3];
-        serial
-            .read(&mut reply[..])
-            .expect_success("Failed to read host reply");
-
-        assert_eq!(&reply[..], b"OK\n", "Unexpected screenshot request reply");
+        send_request(bt, "SCREENSHOT", name);
     } else {
         // Outside of QEMU, give the user some time to inspect the output
         bt.stall(3_000_000);
     }
 }
 
-fn shutdown(image: uefi::Handle, mut st: SystemTable<Boot>) -> ! {
+/// Ask the test runner to check the text which `f` prints on the console
+/// against the reference transcript `name`
+///
+/// The runner compares the serial output, in which OVMF mirrors the console,
+/// after stripping escape codes and the source locations of log lines. Like
+/// screenshots, transcripts are only checked under QEMU.
+fn check_transcript(st: &mut SystemTable<Boot>, name: &str, f: impl FnOnce(&mut Output)) {
+    if cfg!(feature = "qemu") {
+        send_request(st.boot_services(), "TRANSCRIPT", name);
+    }
+    // The log of the test must reach the console to be part of the transcript
+    let capture = uefi_services::set_log_capture(None);
+    f(st.stdout());
+    uefi_services::set_log_capture(capture);
+    if cfg!(feature = "qemu") {
+        send_request(st.boot_services(), "TRANSCRIPT END", name);
+    }
+}
+
+/// Send a request to the QEMU-based test runner, and wait for its reply
+fn send_request(bt: &BootServices, request: &str, name: &str) {
+    // Access the serial port (in a QEMU environment, it should always be there)
+    let serial = bt
+        .locate_protocol::<Serial>()
+        .expect_success("Could not find serial port");
+    let serial = unsafe { &mut *serial.get() };
+
+    // Set a large timeout to avoid problems with Travis
+    let mut io_mode = *serial.io_mode();
+    io_mode.timeout = 10_000_000;
+    serial
+        .set_attributes(&io_mode)
+        .expect_success("Failed to configure serial port timeout");
+
+    // Send the request to the host
+    for part in &[request.as_bytes(), b": ", name.as_bytes(), b"\n"] {
+        serial.write(part).expect_success("Failed to send request");
+    }
+
+    // Wait for the host's acknowledgement before moving forward
+    let mut reply = [0; 3];
+    serial
+        .read(&mut reply[..])
+        .expect_success("Failed to read host reply");
+
+    assert_eq!(&reply[..], b"OK\n", "Unexpected {} request reply", request);
+}
+
+/// Exit boot services, and shut down the machine, telling whether `failures`
+/// is zero to QEMU, or to the firmware otherwise
+fn shutdown(image: uefi::Handle, mut st: SystemTable<Boot>, failures: usize) -> ! {
     use uefi::table::runtime::ResetType;
 
     // Get our text output back.
     st.stdout().reset(false).unwrap_success();
 
+    // The fault handlers are part of this image, which is about to go away
+    #[cfg(target_arch = "x86_64")]
+    {
+        use uefi::proto::pi::cpu::CpuArch;
+        if let Ok(cpu) = st.boot_services().locate_protocol::<CpuArch>() {
+            unsafe { &mut *cpu.log().get() }
+                .restore_faults()
+                .expect_success("Failed to remove fault handlers");
+        }
+    }
+
+    let usage = uefi::stack::usage().unwrap();
+    info!("Stack usage: {} of {} bytes", usage.used, usage.size);
+    assert!(
+        !usage.exhausted(),
+        "The tests may have overflowed the stack"
+    );
+
     // Inform the user, and give him time to read on real hardware
     if cfg!(not(feature = "qemu")) {
         info!("Testing complete, shutting down in 3 seconds...");
@@ -139,6 +369,8 @@ fn shutdown(image: uefi::Handle, mut st: SystemTable<Boot>) -> ! {
     let (st, _iter) = st
         .exit_boot_services(image, &mut mmap_storage[..])
         .expect_success("Failed to exit boot services");
+    assert!(!uefi::table::boot::boot_services_active());
+    assert!(uefi::table::boot::system_table().is_none());
 
     #[cfg(target_arch = "x86_64")]
     {
@@ -146,11 +378,61 @@ fn shutdown(image: uefi::Handle, mut st: SystemTable<Boot>) -> ! {
             use qemu_exit::QEMUExit;
             let custom_exit_success = 3;
             let qemu_exit_handle = qemu_exit::X86::new(0xF4, custom_exit_success);
+            if failures > 0 {
+                qemu_exit_handle.exit_failure();
+            }
             qemu_exit_handle.exit_success();
         }
     }
 
-    // Shut down the system
+    #[cfg(target_arch = "x86")]
+    {
+        if cfg!(feature = "qemu") {
+            // `qemu_exit` only supports x86_64, so write to the isa-debug-exit
+            // port directly. QEMU exits with `(value << 1) | 1`, which is 3
+            // for the value written on success, and 1 on failure.
+            let value: u32 = if failures > 0 { 0 } else { 1 };
+            unsafe {
+                asm!("out dx, eax", in("dx") 0xf4u16, in("eax") value, options(nomem, nostack));
+            }
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        if cfg!(feature = "qemu") {
+            // QEMU is started with semihosting, through which the runner
+            // exits with a status
+            use qemu_exit::QEMUExit;
+            let qemu_exit_handle = qemu_exit::AArch64::new();
+            if failures > 0 {
+                qemu_exit_handle.exit_failure();
+            }
+            qemu_exit_handle.exit_success();
+        }
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        if cfg!(feature = "qemu") {
+            // The virt machine maps a sifive_test device at 0x100000, which
+            // lets us report the outcome of the tests through QEMU's exit code.
+            use qemu_exit::QEMUExit;
+            let qemu_exit_handle = qemu_exit::RISCV64::new(0x10_0000);
+            if failures > 0 {
+                qemu_exit_handle.exit_failure();
+            }
+            qemu_exit_handle.exit_success();
+        }
+    }
+
+    // Shut down the system, with a status which tells whether the tests
+    // passed, as the panic handler does
+    let status = if failures > 0 {
+        Status::ABORTED
+    } else {
+        Status::SUCCESS
+    };
     let rt = unsafe { st.runtime_services() };
-    rt.reset(ResetType::Shutdown, Status::SUCCESS, None);
+    rt.reset(ResetType::Shutdown, status, None);
 }
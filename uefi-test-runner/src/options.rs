@@ -0,0 +1,259 @@
+//! Options of the runner, which choose the tests to run
+
+use alloc::{string::String, vec::Vec};
+use uefi::prelude::*;
+use uefi::proto::loaded_image::LoadedImage;
+
+/// Options of the runner
+///
+/// When started with load options, such as from the UEFI shell, the runner
+/// takes them as arguments. `--list` prints the names of the tests which
+/// would run, without running them, and the other arguments are filters: if
+/// there are any, only the tests whose name contains one of them run.
+/// `--shard <k>/<n>` splits the tests in `n` shards, by a hash of their name,
+/// and only runs the `k`-th one, so that CI can run the shards in parallel.
+/// `--parallel` runs the tests which may run on the application processors
+/// in parallel on them, before the others.
+/// `--shuffle` runs the tests in a random order, to find those which depend on
+/// the others, and prints its seed, which `--shuffle-seed <seed>` takes to run
+/// them in the same order again.
+/// `--bench` only runs the benchmarks, and measures how long an iteration of
+/// each one takes, while they otherwise run once, as tests.
+/// `--group <name>` only runs the tests of a group, such as `proto`.
+///
+/// `--timeout <seconds>` bounds how long each test runs. With the `resume`
+/// feature, the UEFI watchdog timer is armed during each test, so a hung test
+/// resets the machine and is reported as failed, and the suite goes on.
+/// Without it, the suite would start over after the reset, so the timeout is
+/// not enforced.
+///
+/// The log of a test is kept, and only printed if the test fails, unless
+/// `--nocapture` is given. The log of a test which hangs or resets the
+/// machine is lost.
+///
+/// `--quiet` only prints the warnings, and one character per test instead of
+/// its name, since thousands of log lines are slow to capture over serial.
+/// `--verbose` prints the debug and trace logs too. The results are colored
+/// unless `--no-color` is given, since OVMF turns the colors into ANSI escape
+/// codes on the serial port, which not every serial capture handles.
+///
+/// The QEMU-based test runner cannot pass load options, so it chooses the
+/// filter, the group, the shard, the seed and the timeout when building the
+/// runner, which reads them from the `UEFI_TEST_FILTER`, `UEFI_TEST_GROUP`,
+/// `UEFI_TEST_SHARD`, `UEFI_TEST_SHUFFLE_SEED` and `UEFI_TEST_TIMEOUT`
+/// environment variables. It lists the tests if `UEFI_TEST_LIST` is set,
+/// measures the benchmarks if `UEFI_TEST_BENCH` is, runs tests on the
+/// application processors if `UEFI_TEST_PARALLEL` is, shuffles the tests if
+/// `UEFI_TEST_SHUFFLE` is, and prints their log as it comes if
+/// `UEFI_TEST_NOCAPTURE` is. It prints the results itself, from the progress
+/// which the runner reports over serial, so the output options are only read
+/// from the load options: the characters of `--quiet` would be mixed into the
+/// progress messages.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub filters: Vec<String>,
+    /// Group of the tests which run, instead of all of them
+    pub group: Option<String>,
+    /// Part of the tests which runs
+    pub shard: Option<Shard>,
+    pub list: bool,
+    /// Whether only the benchmarks run, and are measured
+    pub bench: bool,
+    /// Whether the tests which may run on the application processors do
+    pub parallel: bool,
+    /// Seconds after which a test is considered hung
+    pub timeout: Option<u64>,
+    /// Which of the ignored tests run
+    pub ignored: Ignored,
+    /// Whether the log of a test is only printed if it fails
+    pub capture: bool,
+    /// Whether the tests run in a random order
+    pub shuffle: bool,
+    /// Seed of the order of the tests, instead of a random one
+    pub shuffle_seed: Option<u64>,
+    /// How much the runner prints
+    pub verbosity: Verbosity,
+    /// Whether the results are colored
+    pub color: bool,
+}
+
+impl Options {
+    /// Parse the options from the load options of `image`, keeping the
+    /// defaults if they cannot be read, as when the firmware passed binary
+    /// data
+    pub fn from_image(bt: &BootServices, image: Handle) -> Self {
+        let mut options = Options {
+            filters: option_env!("UEFI_TEST_FILTER")
+                .map(String::from)
+                .into_iter()
+                .collect(),
+            group: option_env!("UEFI_TEST_GROUP").map(String::from),
+            shard: option_env!("UEFI_TEST_SHARD").and_then(|shard| shard.parse().ok()),
+            list: option_env!("UEFI_TEST_LIST").is_some(),
+            bench: option_env!("UEFI_TEST_BENCH").is_some(),
+            parallel: option_env!("UEFI_TEST_PARALLEL").is_some(),
+            timeout: option_env!("UEFI_TEST_TIMEOUT").and_then(|secs| secs.parse().ok()),
+            ignored: Ignored::from_env(),
+            capture: option_env!("UEFI_TEST_NOCAPTURE").is_none(),
+            shuffle: option_env!("UEFI_TEST_SHUFFLE").is_some(),
+            shuffle_seed: option_env!("UEFI_TEST_SHUFFLE_SEED").and_then(|seed| seed.parse().ok()),
+            verbosity: Verbosity::Normal,
+            color: true,
+        };
+        let loaded_image = bt
+            .handle_protocol::<LoadedImage>(image)
+            .expect_success("Failed to open LoadedImage protocol");
+        let loaded_image = unsafe { &*loaded_image.get() };
+        let mut buffer = [0; 1024];
+        let command_line = match loaded_image.load_options(&mut buffer) {
+            Ok(command_line) => command_line,
+            Err(error) => {
+                warn!("Ignoring the load options of the runner: {:?}", error);
+                return options;
+            }
+        };
+
+        // The first word is the path of the runner, as the shell passes it
+        let mut filters = Vec::new();
+        let mut args = command_line.split_whitespace().skip(1);
+        while let Some(arg) = args.next() {
+            match arg {
+                "--list" => options.list = true,
+                "--bench" => options.bench = true,
+                "--parallel" => options.parallel = true,
+                "--nocapture" => options.capture = false,
+                "--timeout" => match args.next().map(str::parse) {
+                    Some(Ok(timeout)) => options.timeout = Some(timeout),
+                    _ => warn!("Ignoring the timeout of the tests, which is not a number"),
+                },
+                "--shard" => match args.next().map(str::parse) {
+                    Some(Ok(shard)) => options.shard = Some(shard),
+                    Some(Err(error)) => warn!("Ignoring the shard of the tests: {}", error),
+                    None => warn!("Ignoring the shard of the tests, which is missing"),
+                },
+                "--group" => match args.next() {
+                    Some(group) => options.group = Some(String::from(group)),
+                    None => warn!("Ignoring the group of the tests, which is missing"),
+                },
+                "--shuffle" => options.shuffle = true,
+                "--shuffle-seed" => match args.next().map(str::parse) {
+                    Some(Ok(seed)) => options.shuffle_seed = Some(seed),
+                    _ => {
+                        warn!("Ignoring the seed of the order of the tests, which is not a number")
+                    }
+                },
+                "--ignored" => options.ignored = Ignored::Only,
+                "--include-ignored" => options.ignored = Ignored::Include,
+                "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+                "-v" | "--verbose" => options.verbosity = Verbosity::Verbose,
+                "--no-color" => options.color = false,
+                filter => filters.push(String::from(filter)),
+            }
+        }
+        if !filters.is_empty() {
+            options.filters = filters;
+        }
+        options.shuffle |= options.shuffle_seed.is_some();
+        options
+    }
+
+    /// Whether the test `name` is selected by the filters and the shard
+    pub fn matches(&self, name: &str) -> bool {
+        let in_shard = match self.shard {
+            Some(shard) => shard.contains(name),
+            None => true,
+        };
+        (self.filters.is_empty() || self.filters.iter().any(|f| name.contains(&**f))) && in_shard
+    }
+
+    /// Most verbose level of the logs which are printed
+    pub fn log_level(&self) -> log::LevelFilter {
+        match self.verbosity {
+            Verbosity::Quiet => log::LevelFilter::Warn,
+            Verbosity::Normal => log::LevelFilter::Info,
+            Verbosity::Verbose => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// One of the parts in which `--shard` splits the tests
+#[derive(Clone, Copy, Debug)]
+pub struct Shard {
+    /// Index of the part, from 0
+    index: u32,
+    count: u32,
+}
+
+impl Shard {
+    /// Whether the test `name` belongs to the shard
+    ///
+    /// The tests are split by the FNV-1a hash of their name, which does not
+    /// change when other tests are added.
+    fn contains(&self, name: &str) -> bool {
+        let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+        hash % self.count == self.index
+    }
+}
+
+impl core::str::FromStr for Shard {
+    type Err = &'static str;
+
+    /// Parse `<k>/<n>`, in which `k` counts from 1
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let mut number = || {
+            parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or("expected <k>/<n>")
+        };
+        let (k, n): (u32, u32) = (number()?, number()?);
+        if k == 0 || k > n {
+            return Err("the shard must be between 1 and the number of shards");
+        }
+        Ok(Shard {
+            index: k - 1,
+            count: n,
+        })
+    }
+}
+
+/// Which of the tests registered with `Suite::run_ignored` run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ignored {
+    /// None of them, which are reported as ignored
+    Skip,
+    /// `--include-ignored`: all of them, along with the other tests
+    Include,
+    /// `--ignored`: only them
+    Only,
+}
+
+impl Ignored {
+    /// Read which of the ignored tests run from the `UEFI_TEST_IGNORED`
+    /// environment variable, since the QEMU-based test runner cannot pass
+    /// arguments to the runner, and chooses them when building it instead
+    ///
+    /// The ignored tests run along with the others if it is set, or alone if
+    /// it is `only`.
+    fn from_env() -> Self {
+        match option_env!("UEFI_TEST_IGNORED") {
+            Some("only") => Ignored::Only,
+            Some(_) => Ignored::Include,
+            None => Ignored::Skip,
+        }
+    }
+}
+
+/// How much the runner prints on the console
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verbosity {
+    /// `--quiet`: the warnings, and one character per test
+    Quiet,
+    /// The informational logs, and a line per test
+    Normal,
+    /// `--verbose`: the debug and trace logs too
+    Verbose,
+}
@@ -0,0 +1,99 @@
+//! Known issues of the tests, which the application running them configures
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A test which is known to break, and skipped or expected to fail until the
+/// issue which tracks it is fixed
+#[derive(Clone, Copy, Debug)]
+pub struct KnownIssue {
+    /// Name of the tests, in which `*` matches any text
+    pattern: &'static str,
+    /// Whether the tests run, and fail unless they return an error
+    expect_failure: bool,
+    reason: &'static str,
+    /// Issue which tracks the fix
+    tracking: &'static str,
+}
+
+impl KnownIssue {
+    /// Skip the tests matching `pattern`
+    pub const fn skip(pattern: &'static str, reason: &'static str, tracking: &'static str) -> Self {
+        KnownIssue {
+            pattern,
+            expect_failure: false,
+            reason,
+            tracking,
+        }
+    }
+
+    /// Run the tests matching `pattern`, which are reported as expected
+    /// failures if they fail, and as failed if they pass
+    pub const fn xfail(
+        pattern: &'static str,
+        reason: &'static str,
+        tracking: &'static str,
+    ) -> Self {
+        KnownIssue {
+            pattern,
+            expect_failure: true,
+            reason,
+            tracking,
+        }
+    }
+
+    /// Whether the tests run, and are expected to fail
+    pub fn expect_failure(&self) -> bool {
+        self.expect_failure
+    }
+
+    /// Whether the test `name` matches the pattern
+    fn matches(&self, name: &str) -> bool {
+        let mut parts = self.pattern.split('*');
+        // `split` yields at least one part
+        let first = parts.next().unwrap();
+        let mut rest = match name.strip_prefix(first) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        let parts: Vec<&str> = parts.collect();
+        let (last, middle) = match parts.split_last() {
+            Some(split) => split,
+            None => return rest.is_empty(),
+        };
+        for part in middle {
+            match rest.find(part) {
+                Some(offset) => rest = &rest[offset + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+}
+
+impl fmt::Display for KnownIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.reason, self.tracking)
+    }
+}
+
+/// Registry of the known issues of the tests
+#[derive(Debug, Default)]
+pub struct SkipPolicy {
+    issues: Vec<KnownIssue>,
+}
+
+impl SkipPolicy {
+    /// Add the `issues`, of which the first matching a test applies
+    pub fn add(&mut self, issues: &[KnownIssue]) {
+        self.issues.extend_from_slice(issues);
+    }
+
+    /// The known issue of the test `name`, if any
+    pub fn find(&self, name: &str) -> Option<KnownIssue> {
+        self.issues
+            .iter()
+            .find(|issue| issue.matches(name))
+            .copied()
+    }
+}
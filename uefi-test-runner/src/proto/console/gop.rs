@@ -1,6 +1,20 @@
+use core::convert::TryFrom;
+use core::fmt::Write;
+use uefi::graphics::bmp::{self, Bmp};
+use uefi::graphics::console::GraphicsConsole;
+use uefi::graphics::font::Font;
+use uefi::graphics::surface::Surface;
 use uefi::prelude::*;
-use uefi::proto::console::gop::{BltOp, BltPixel, FrameBuffer, GraphicsOutput, PixelFormat};
+use uefi::progress::{GopProgress, ProgressReporter};
+use uefi::proto::console::gop::{
+    BltOp, BltPixel, BltRegion, FrameBuffer, GraphicsOutput, PixelFormat,
+};
+use uefi::proto::console::text::Output;
+use uefi::proto::media::file::{File, FileAttribute, FileMode};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::proto::ProtocolImpl;
 use uefi::table::boot::BootServices;
+use uefi::CString16;
 
 pub fn test(bt: &BootServices) {
     info!("Running graphics output protocol test");
@@ -13,6 +27,14 @@ pub fn test(bt: &BootServices) {
         draw_fb(gop);
 
         crate::check_screenshot(bt, "gop_test");
+
+        // Done after the screenshot, so that the reference image stays valid.
+        draw_bmp(gop);
+        draw_text(gop);
+        flush_surface(gop);
+        draw_console(bt, gop);
+        draw_progress(gop);
+        save_screenshot(bt, gop);
     } else {
         // No tests can be run.
         warn!("UEFI Graphics Output Protocol is not supported");
@@ -22,17 +44,19 @@ pub fn test(bt: &BootServices) {
 // Set a larger graphics mode.
 fn set_graphics_mode(gop: &mut GraphicsOutput) {
     // We know for sure QEMU has a 1024x768 mode.
+    let format = gop.current_mode_info().pixel_format();
     let mode = gop
-        .modes()
-        .map(|mode| mode.expect("Warnings encountered while querying mode"))
-        .find(|mode| {
-            let info = mode.info();
-            info.resolution() == (1024, 768)
-        })
-        .unwrap();
-
-    gop.set_mode(&mode)
+        .set_mode_matching(1024, 768, format)
         .expect_success("Failed to set graphics mode");
+    assert_eq!(gop.current_mode_info().resolution(), (1024, 768));
+
+    let (width, height) = mode.info().resolution();
+    assert!(
+        mode.info().stride() >= width,
+        "Stride of {}x{} mode is smaller than its width",
+        width,
+        height
+    );
 }
 
 // Fill the screen with color.
@@ -51,7 +75,6 @@ fn fill_color(gop: &mut GraphicsOutput) {
 // Draw directly to the frame buffer.
 fn draw_fb(gop: &mut GraphicsOutput) {
     let mi = gop.current_mode_info();
-    let stride = mi.stride();
     let (width, height) = mi.resolution();
 
     let mut fb = gop.frame_buffer();
@@ -78,8 +101,7 @@ fn draw_fb(gop: &mut GraphicsOutput) {
         for row in y1..y2 {
             for column in x1..x2 {
                 unsafe {
-                    let pixel_index = (row * stride) + column;
-                    let pixel_base = 4 * pixel_index;
+                    let pixel_base = mi.pixel_offset(column, row).unwrap();
                     write_pixel(&mut fb, pixel_base, color);
                 }
             }
@@ -89,3 +111,192 @@ fn draw_fb(gop: &mut GraphicsOutput) {
     fill_rectangle((50, 30), (150, 600), [250, 128, 64]);
     fill_rectangle((400, 120), (750, 450), [16, 128, 255]);
 }
+
+// Draw a small BMP image, scaled up, and read it back from the screen.
+fn draw_bmp(gop: &mut GraphicsOutput) {
+    // 2x2 pixels, 24 bits per pixel, stored bottom-up with 2 bytes of padding per row.
+    #[rustfmt::skip]
+    const IMAGE: [u8; 70] = [
+        b'B', b'M', 70, 0, 0, 0, 0, 0, 0, 0, 54, 0, 0, 0,
+        40, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 24, 0,
+        0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+        // Bottom row: blue, white
+        255, 0, 0, 255, 255, 255, 0, 0,
+        // Top row: red, green
+        0, 0, 255, 0, 255, 0, 0, 0,
+    ];
+
+    let bmp = Bmp::parse(&IMAGE).expect("Failed to parse BMP image");
+    assert_eq!((bmp.width(), bmp.height()), (2, 2));
+    bmp.draw_scaled(gop, (0, 0), (4, 4))
+        .expect_success("Failed to draw BMP image");
+
+    let mut pixels = [BltPixel::new(0, 0, 0); 16];
+    gop.blt(BltOp::VideoToBltBuffer {
+        buffer: &mut pixels,
+        src: (0, 0),
+        dest: BltRegion::Full,
+        dims: (4, 4),
+    })
+    .expect_success("Failed to read back BMP image");
+
+    let color = |px: &BltPixel| (px.red, px.green, px.blue);
+    assert_eq!(color(&pixels[0]), (255, 0, 0));
+    assert_eq!(color(&pixels[3]), (0, 255, 0));
+    assert_eq!(color(&pixels[12]), (0, 0, 255));
+    assert_eq!(color(&pixels[15]), (255, 255, 255));
+}
+
+// Draw text straight to the frame buffer and read it back.
+fn draw_text(gop: &mut GraphicsOutput) {
+    // 128 glyphs of 8x2 pixels, with only the top-left pixel set.
+    let mut glyphs = [0; 2 * 128];
+    for glyph in glyphs.chunks_mut(2) {
+        glyph[0] = 0x80;
+    }
+    let font = Font::from_glyphs(&glyphs, 8, 2).unwrap();
+
+    let info = gop.current_mode_info();
+    let (fg, bg) = (BltPixel::new(255, 255, 0), BltPixel::new(0, 0, 0));
+    let end = font
+        .draw_str(&mut gop.frame_buffer(), &info, (100, 100), "ab", fg, bg)
+        .expect_success("Failed to draw text");
+    assert_eq!(end, (116, 100));
+
+    let mut pixels = [BltPixel::new(0, 0, 0); 16];
+    gop.blt(BltOp::VideoToBltBuffer {
+        buffer: &mut pixels,
+        src: (100, 100),
+        dest: BltRegion::Full,
+        dims: (16, 1),
+    })
+    .expect_success("Failed to read back text");
+
+    for (x, px) in pixels.iter().enumerate() {
+        let expected = if x % 8 == 0 { (255, 255, 0) } else { (0, 0, 0) };
+        assert_eq!((px.red, px.green, px.blue), expected);
+    }
+}
+
+// Draw to a back buffer and flush the modified region to the screen.
+fn flush_surface(gop: &mut GraphicsOutput) {
+    let mut surface = Surface::for_mode(&gop.current_mode_info());
+    surface.fill_rect((200, 200), (2, 1), BltPixel::new(0, 255, 255));
+    assert_eq!(surface.dirty_rect(), Some(((200, 200), (2, 1))));
+    surface.flush(gop).expect_success("Failed to flush surface");
+    assert_eq!(surface.dirty_rect(), None);
+
+    let mut pixels = [BltPixel::new(0, 0, 0); 3];
+    gop.blt(BltOp::VideoToBltBuffer {
+        buffer: &mut pixels,
+        src: (200, 200),
+        dest: BltRegion::Full,
+        dims: (3, 1),
+    })
+    .expect_success("Failed to read back surface");
+    assert_eq!(
+        (pixels[1].red, pixels[1].green, pixels[1].blue),
+        (0, 255, 255)
+    );
+    // Pixels outside of the dirty region were left untouched
+    assert_ne!(
+        (pixels[2].red, pixels[2].green, pixels[2].blue),
+        (0, 255, 255)
+    );
+}
+
+// Render text through a Simple Text Output implementation on top of the GOP.
+fn draw_console(bt: &BootServices, gop: &mut GraphicsOutput) {
+    // Same font as `draw_text`.
+    let mut glyphs = [0; 2 * 128];
+    for glyph in glyphs.chunks_mut(2) {
+        glyph[0] = 0x80;
+    }
+    let font = Font::from_glyphs(&glyphs, 8, 2).unwrap();
+
+    let mut console =
+        GraphicsConsole::new(gop, font).expect_success("Failed to create graphics console");
+    assert_eq!(console.size(), (1024 / 8, 768 / 2));
+    write!(console.output(), "a").unwrap();
+    assert_eq!(console.output().cursor_position(), (1, 0));
+
+    let handles = bt.find_handles::<Output>().unwrap_success().len();
+    let handle =
+        unsafe { console.install(bt, None) }.expect_success("Failed to install graphics console");
+    assert_eq!(
+        bt.find_handles::<Output>().unwrap_success().len(),
+        handles + 1
+    );
+    unsafe { console.uninstall(bt, handle) }.expect_success("Failed to uninstall graphics console");
+    drop(console);
+
+    let mut pixels = [BltPixel::new(0, 0, 0); 16];
+    gop.blt(BltOp::VideoToBltBuffer {
+        buffer: &mut pixels,
+        src: (0, 0),
+        dest: BltRegion::Full,
+        dims: (16, 1),
+    })
+    .expect_success("Failed to read back console");
+    let color = |px: &BltPixel| (px.red, px.green, px.blue);
+    // The character, then the cursor drawn with inverted colors
+    assert_eq!(color(&pixels[0]), (0x98, 0x98, 0x98));
+    assert_eq!(color(&pixels[1]), (0, 0, 0));
+    assert_eq!(color(&pixels[8]), (0, 0, 0));
+    assert_eq!(color(&pixels[9]), (0x98, 0x98, 0x98));
+}
+
+// Draw a progress bar, and check that it fills up.
+fn draw_progress(gop: &mut GraphicsOutput) {
+    let mut progress = GopProgress::new(gop, (100, 100), (104, 10));
+    progress
+        .update(1, 2)
+        .expect_success("Failed to draw progress bar");
+
+    let mut pixels = [BltPixel::new(0, 0, 0); 104];
+    gop.blt(BltOp::VideoToBltBuffer {
+        buffer: &mut pixels,
+        src: (100, 105),
+        dest: BltRegion::Full,
+        dims: (104, 1),
+    })
+    .expect_success("Failed to read back progress bar");
+    let color = |px: &BltPixel| (px.red, px.green, px.blue);
+    // The frame, the gap, half of the bar, the empty half, the gap, the frame
+    assert_eq!(color(&pixels[0]), (0xff, 0xff, 0xff));
+    assert_eq!(color(&pixels[1]), (0, 0, 0));
+    assert_eq!(color(&pixels[2]), (0xff, 0xff, 0xff));
+    assert_eq!(color(&pixels[51]), (0xff, 0xff, 0xff));
+    assert_eq!(color(&pixels[52]), (0, 0, 0));
+    assert_eq!(color(&pixels[102]), (0, 0, 0));
+    assert_eq!(color(&pixels[103]), (0xff, 0xff, 0xff));
+}
+
+// Save a screenshot to the first file system, and read it back.
+fn save_screenshot(bt: &BootServices, gop: &mut GraphicsOutput) {
+    let sfs = match bt.locate_protocol::<SimpleFileSystem>() {
+        Ok(sfs) => sfs.expect("Cannot open `SimpleFileSystem` protocol"),
+        Err(_) => return warn!("No file system to save the screenshot to"),
+    };
+    let sfs = unsafe { &mut *sfs.get() };
+    let mut root = sfs.open_volume().expect_success("Failed to open volume");
+    let path = CString16::try_from("gop_screenshot.bmp").unwrap();
+    bmp::save_screenshot(gop, &mut root, &path).expect_success("Failed to save screenshot");
+
+    let data = root
+        .read_to_vec(&path)
+        .expect_success("Failed to read back screenshot");
+    let image = Bmp::parse(&data).expect("Screenshot is not a valid BMP file");
+    let (width, height) = gop.current_mode_info().resolution();
+    assert_eq!((image.width(), image.height()), (width, height));
+
+    root.open(
+        "gop_screenshot.bmp",
+        FileMode::ReadWrite,
+        FileAttribute::empty(),
+    )
+    .expect_success("Failed to open screenshot")
+    .delete()
+    .expect_success("Failed to delete screenshot");
+}
@@ -4,8 +4,12 @@ pub fn test(st: &mut SystemTable<Boot>) {
     info!("Testing console protocols");
 
     stdout::test(st.stdout());
+    stdout::test_transcript(st);
+    stdin::test(st);
+    stdin::test_virtual_keyboard(st);
 
     let bt = st.boot_services();
+    stdin::test_hotkeys(bt);
     serial::test(bt);
     gop::test(bt);
     pointer::test(bt);
@@ -14,4 +18,5 @@ pub fn test(st: &mut SystemTable<Boot>) {
 mod gop;
 mod pointer;
 mod serial;
+mod stdin;
 mod stdout;
@@ -0,0 +1,96 @@
+use core::convert::TryFrom;
+use core::time::Duration;
+use uefi::prelude::*;
+use uefi::proto::console::text::{
+    Input, InputEx, Key, KeyData, LineReader, ScanCode, ShiftState, VirtualKeyboard,
+};
+use uefi::proto::ProtocolImpl;
+
+pub fn test(st: &mut SystemTable<Boot>) {
+    info!("Running line reader test");
+
+    // The boot services are borrowed separately from the console protocols
+    let bt_st = unsafe { st.unsafe_clone() };
+    let bt = bt_st.boot_services();
+    let mut stdout_st = unsafe { st.unsafe_clone() };
+
+    // Nobody types during the tests, so reading must time out
+    let mut buffer = [0; 16];
+    let err = LineReader::new()
+        .echo(false)
+        .timeout(Some(Duration::from_millis(10)))
+        .read_into(bt, st.stdin(), stdout_st.stdout(), &mut buffer)
+        .expect_error("Reading a line should have timed out");
+    assert_eq!(err.status(), Status::TIMEOUT);
+}
+
+/// Type a line on a virtual keyboard, and read it through the protocol
+pub fn test_virtual_keyboard(st: &mut SystemTable<Boot>) {
+    info!("Running virtual keyboard test");
+    let bt_st = unsafe { st.unsafe_clone() };
+    let bt = bt_st.boot_services();
+
+    let mut keyboard =
+        VirtualKeyboard::new(bt, 4).expect_success("Failed to create the virtual keyboard");
+    let handle = unsafe { keyboard.install(bt, None) }
+        .expect_success("Failed to install the virtual keyboard");
+    let input = bt
+        .handle_protocol::<Input>(handle)
+        .expect_success("Failed to open the virtual keyboard");
+    let input = unsafe { &mut *input.get() };
+
+    assert!(!bt.check_event(input.wait_for_key_event()).unwrap_success());
+    assert_eq!(input.read_key().unwrap_success(), None);
+    // The queue holds 4 keys
+    assert_eq!(keyboard.push_str("hello\n"), 4);
+    assert!(bt.check_event(input.wait_for_key_event()).unwrap_success());
+    assert_eq!(
+        input.read_key().unwrap_success(),
+        Some(Key::Printable(uefi::Char16::try_from('h').unwrap()))
+    );
+    input.reset(false).unwrap_success();
+    assert_eq!(keyboard.pending(), 0);
+    assert!(!bt.check_event(input.wait_for_key_event()).unwrap_success());
+
+    keyboard.push_str("hi\n");
+    let mut buffer = [0; 16];
+    let line = LineReader::new()
+        .echo(false)
+        .read_into(bt, input, st.stdout(), &mut buffer)
+        .expect_success("Failed to read a line");
+    assert_eq!(line.to_u16_slice(), [u16::from(b'h'), u16::from(b'i')]);
+
+    unsafe { keyboard.uninstall(bt, handle) }
+        .expect_success("Failed to uninstall the virtual keyboard");
+}
+
+pub fn test_hotkeys(bt: &BootServices) {
+    info!("Running hotkey test");
+    let input = match bt.locate_protocol::<InputEx>() {
+        Ok(input) => input.log(),
+        Err(_) => {
+            warn!("No extended text input protocol, skipping hotkey test");
+            return;
+        }
+    };
+    let input = unsafe { &*input.get() };
+
+    let f12 = KeyData::new(Key::Special(ScanCode::FUNCTION_12));
+    let ctrl_m = KeyData::with_modifiers(
+        Key::Printable(uefi::Char16::try_from('m').unwrap()),
+        ShiftState::LEFT_CONTROL_PRESSED,
+    );
+    let first = input
+        .register_hotkey(bt, f12, |_| {})
+        .expect_success("Failed to register hotkey");
+    let second = input
+        .register_hotkey(bt, f12, |_| {})
+        .expect_success("Failed to register hotkey twice");
+    let third = input
+        .register_hotkey(bt, ctrl_m, |_| {})
+        .expect_success("Failed to register hotkey with modifiers");
+    // Nobody types during the tests, so the closures are never called
+    drop(first);
+    drop(third);
+    drop(second);
+}
@@ -1,3 +1,4 @@
+use core::fmt::Write;
 use uefi::prelude::*;
 use uefi::proto::console::text::{Color, Output};
 
@@ -24,6 +25,21 @@ pub fn test(stdout: &mut Output) {
     stdout.reset(false).unwrap_success();
 }
 
+// Check the text which reaches the serial console against a reference.
+pub fn test_transcript(st: &mut SystemTable<Boot>) {
+    crate::check_transcript(st, "stdout_test", |stdout| {
+        writeln!(stdout, "The quick brown fox").unwrap();
+        stdout
+            .set_color(Color::Yellow, Color::Black)
+            .expect_success("Failed to change console color");
+        writeln!(stdout, "jumps over the lazy dog").unwrap();
+        stdout
+            .set_color(Color::LightGray, Color::Black)
+            .expect_success("Failed to change console color");
+        info!("Logged lines keep their level");
+    });
+}
+
 // Retrieves and prints the current output mode.
 fn get_current_mode(stdout: &mut Output) {
     let current_mode = stdout.current_mode().unwrap_success();
@@ -32,14 +48,19 @@ fn get_current_mode(stdout: &mut Output) {
 
 // Switch to the maximum supported text mode.
 fn change_text_mode(stdout: &mut Output) {
-    let best_mode = stdout
+    let last_mode = stdout
         .modes()
         .last()
         .unwrap()
         .expect("Warnings encountered while querying text mode");
-    stdout
-        .set_mode(best_mode)
+    let best_mode = stdout
+        .set_best_mode()
         .expect_success("Failed to change text mode");
+    assert!(
+        best_mode.columns() * best_mode.rows() >= last_mode.columns() * last_mode.rows(),
+        "The best text mode is smaller than the last one"
+    );
+    assert_eq!(stdout.current_mode().unwrap_success(), Some(best_mode));
 }
 
 // Set a new color, and paint the background with it.
@@ -3,6 +3,37 @@ use uefi::proto::debug::{DebugSupport, ExceptionType, ProcessorArch, SystemConte
 use uefi::table::boot::BootServices;
 use uefi::ResultExt;
 
+/// Check that the firmware can debug the processor which runs the tests, and
+/// not only the EBC virtual machine
+pub fn test_processor_arch(bt: &BootServices) -> Result<(), &'static str> {
+    let arch = if cfg!(target_arch = "x86_64") {
+        ProcessorArch::X86_64
+    } else if cfg!(target_arch = "aarch64") {
+        ProcessorArch::AARCH_64
+    } else if cfg!(target_arch = "riscv64") {
+        ProcessorArch::RISCV_64
+    } else {
+        return Err("the architecture of the processor is not tested");
+    };
+    let handles = bt
+        .find_handles::<DebugSupport>()
+        .map_err(|_| "the debug support protocol is not supported")?
+        .expect("Problem encountered while querying handles for DebugSupport");
+    let supported = handles
+        .into_iter()
+        .filter_map(|handle| bt.handle_protocol::<DebugSupport>(handle).ok())
+        .any(|debug_support| {
+            let debug_support =
+                debug_support.expect("Warnings encountered while opening debug support protocol");
+            unsafe { &*debug_support.get() }.arch() == arch
+        });
+    if supported {
+        Ok(())
+    } else {
+        Err("the processor cannot be debugged")
+    }
+}
+
 pub fn test(bt: &BootServices) {
     info!("Running UEFI debug connection protocol test");
     if let Ok(handles) = bt.find_handles::<DebugSupport>() {
@@ -52,6 +83,21 @@ pub fn test(bt: &BootServices) {
                             )
                             .expect_success("Error while registering exception callback");
                     },
+                    #[cfg(target_arch = "x86")]
+                    ProcessorArch::X86_32 => unsafe {
+                        info!("Registering exception callback");
+                        debug_support
+                            .register_exception_callback(
+                                0,
+                                Some(exception_callback),
+                                ExceptionType::EXCEPT_IA32_DEBUG,
+                            )
+                            .expect_success("Error while registering exception callback");
+                        info!("Deregistering exception callback");
+                        debug_support
+                            .register_exception_callback(0, None, ExceptionType::EXCEPT_IA32_DEBUG)
+                            .expect_success("Error while deregistering exception callback");
+                    },
                     #[cfg(target_arch = "x86_64")]
                     ProcessorArch::X86_64 => unsafe {
                         info!("Registering exception callback");
@@ -86,6 +132,25 @@ pub fn test(bt: &BootServices) {
                             )
                             .expect_success("Error while deregistering exception callback");
                     },
+                    #[cfg(target_arch = "riscv64")]
+                    ProcessorArch::RISCV_64 => unsafe {
+                        info!("Registering exception callback");
+                        debug_support
+                            .register_exception_callback(
+                                0,
+                                Some(exception_callback),
+                                ExceptionType::EXCEPT_RISCV_BREAKPOINT,
+                            )
+                            .expect_success("Error while registering exception callback");
+                        info!("Deregistering exception callback");
+                        debug_support
+                            .register_exception_callback(
+                                0,
+                                None,
+                                ExceptionType::EXCEPT_RISCV_BREAKPOINT,
+                            )
+                            .expect_success("Error while deregistering exception callback");
+                    },
                     // if we reach this, we're running on an arch that `build.py` doesn't support
                     // TODO: Add match arms as we support testing on more archs
                     _ => unreachable!(),
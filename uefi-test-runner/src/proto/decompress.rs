@@ -0,0 +1,22 @@
+use uefi::prelude::*;
+use uefi::proto::decompress::Decompress;
+
+pub fn test(bt: &BootServices) {
+    info!("Running Decompress protocol test");
+    if let Ok(decompress) = bt.locate_protocol::<Decompress>() {
+        let decompress = decompress.expect("Warnings encountered while opening Decompress");
+        let decompress = unsafe { &*decompress.get() };
+
+        // The header gives the compressed and the original sizes
+        let source = [0, 0, 0, 0, 16, 0, 0, 0];
+        let info = decompress
+            .get_info(&source)
+            .expect_success("Failed to get decompression info");
+        info!("Decompression info: {:?}", info);
+        assert_eq!(info.destination_size, 16);
+
+        assert!(decompress.get_info(&source[..4]).is_err());
+    } else {
+        warn!("Decompress protocol is not supported");
+    }
+}
@@ -0,0 +1,100 @@
+use core::convert::TryFrom;
+use uefi::prelude::*;
+use uefi::proto::driver::component_name::ComponentName2;
+use uefi::proto::driver::diagnostics::{
+    Diagnosis, DiagnosticType, Diagnostics, DriverDiagnostics2,
+};
+use uefi::proto::driver::version::DriverSupportedEfiVersion;
+use uefi::proto::ProtocolImpl;
+use uefi::table::boot::BootServices;
+use uefi::table::Revision;
+use uefi::CString16;
+
+pub fn test(image: Handle, bt: &BootServices) {
+    test_component_name(bt);
+    test_diagnostics(image, bt);
+    test_supported_version(bt);
+}
+
+fn test_component_name(bt: &BootServices) {
+    info!("Running Component Name 2 protocol test");
+    if let Ok(handles) = bt.find_handles::<ComponentName2>() {
+        let handles = handles.expect("Warnings encountered while finding drivers");
+        for handle in handles {
+            let names = bt
+                .handle_protocol::<ComponentName2>(handle)
+                .expect_success("Failed to open Component Name 2 protocol");
+            let names = unsafe { &*names.get() };
+            let language = names
+                .select_language(&["en-US", "en"])
+                .expect("Driver names are not available in any language");
+            match names.driver_name(language) {
+                Ok(name) => info!("Driver ({}): {}", language, name.unwrap()),
+                Err(err) => warn!("Driver without name: {:?}", err.status()),
+            }
+        }
+    } else {
+        warn!("Component Name 2 protocol is not supported");
+    }
+}
+
+fn test_diagnostics(image: Handle, bt: &BootServices) {
+    info!("Running Driver Diagnostics 2 protocol test");
+
+    // Diagnose the image, failing the extended tests
+    let mut implementation = Diagnostics::new(bt, &["en-US"], move |_, child, kind, _| {
+        if child.is_some() {
+            return Err(Status::UNSUPPORTED);
+        }
+        Ok(Diagnosis {
+            passed: kind != DiagnosticType::EXTENDED,
+            error_type: None,
+            message: Some(CString16::try_from("Self-test complete").unwrap()),
+        })
+    });
+    let handle = unsafe { implementation.install(bt, None) }
+        .expect_success("Failed to install Driver Diagnostics 2 protocol");
+
+    let diagnostics = bt
+        .handle_protocol::<DriverDiagnostics2>(handle)
+        .expect_success("Failed to open Driver Diagnostics 2 protocol");
+    let diagnostics = unsafe { &mut *diagnostics.get() };
+    assert_eq!(diagnostics.select_language(&["fr"]), Some("en-US"));
+
+    let report = diagnostics
+        .run(bt, image, None, DiagnosticType::STANDARD, "en-US")
+        .expect_success("Standard diagnostics failed");
+    assert_eq!(
+        report
+            .message()
+            .map(|message| message.as_string())
+            .as_deref(),
+        Some("Self-test complete")
+    );
+    let err = diagnostics
+        .run(bt, image, None, DiagnosticType::EXTENDED, "en-US")
+        .unwrap_err();
+    assert_eq!(err.status(), Status::DEVICE_ERROR);
+    assert!(err.data().is_some());
+    let err = diagnostics
+        .run(bt, image, None, DiagnosticType::STANDARD, "fr")
+        .unwrap_err();
+    assert_eq!(err.status(), Status::UNSUPPORTED);
+
+    unsafe { implementation.uninstall(bt, handle) }
+        .expect_success("Failed to uninstall Driver Diagnostics 2 protocol");
+}
+
+fn test_supported_version(bt: &BootServices) {
+    info!("Running Driver Supported EFI Version protocol test");
+    let version = DriverSupportedEfiVersion::new(Revision::new(2, 70));
+    let handle = unsafe { version.install(bt, None) }
+        .expect_success("Failed to install Driver Supported EFI Version protocol");
+    let installed = bt
+        .handle_protocol::<DriverSupportedEfiVersion>(handle)
+        .expect_success("Failed to open Driver Supported EFI Version protocol");
+    let installed = unsafe { &*installed.get() };
+    assert_eq!(installed.firmware_version(), Revision::new(2, 70));
+    unsafe { version.uninstall(bt, handle) }
+        .expect_success("Failed to uninstall Driver Supported EFI Version protocol");
+}
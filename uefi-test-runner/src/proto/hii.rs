@@ -0,0 +1,43 @@
+use uefi::proto::hii::database::HiiDatabase;
+use uefi::proto::hii::keyboard::{EfiKey, ModifierState};
+use uefi::table::boot::BootServices;
+use uefi::{Guid, ResultExt, Status};
+
+pub fn test(bt: &BootServices) {
+    info!("Running HII database protocol test");
+    if let Ok(database) = bt.locate_protocol::<HiiDatabase>() {
+        let database = database.expect("Warnings encountered while opening HII database");
+        let database = unsafe { &*database.get() };
+
+        let mut guids = [Guid::default(); 16];
+        match database.find_keyboard_layouts(&mut guids) {
+            Ok(count) => {
+                let count = count.log();
+                info!("Found {} keyboard layouts", count);
+                for guid in &guids[..count] {
+                    test_layout(database, guid);
+                }
+            }
+            Err(err) if err.status() == Status::NOT_FOUND => {
+                info!("No keyboard layouts are registered");
+            }
+            Err(err) => panic!("Failed to list keyboard layouts: {:?}", err),
+        }
+    } else {
+        warn!("UEFI HII database protocol is not supported");
+    }
+}
+
+fn test_layout(database: &HiiDatabase, guid: &Guid) {
+    let mut buffer = [0; 4096];
+    let layout = database
+        .keyboard_layout(Some(guid), &mut buffer)
+        .expect_success("Failed to read keyboard layout");
+    assert_eq!(layout.guid(), *guid);
+    info!(
+        "Layout {} has {} keys, the space bar produces {:?}",
+        guid,
+        layout.descriptors().len(),
+        layout.decode(EfiKey::SPACE_BAR, ModifierState::empty())
+    );
+}
@@ -1,6 +1,15 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
 use uefi::prelude::*;
+use uefi::proto::media::block::{BlockIO, MemoryBlockDevice};
+use uefi::proto::media::file::{
+    stream_copy, Directory, File, FileAttribute, FileInfo, FileMode, FileType, RegularFile,
+};
 use uefi::proto::media::fs::SimpleFileSystem;
 use uefi::proto::media::partition::PartitionInfo;
+use uefi::proto::ProtocolImpl;
+use uefi::CString16;
 
 pub fn test(bt: &BootServices) {
     info!("Testing Media Access protocols");
@@ -30,6 +39,11 @@ pub fn test(bt: &BootServices) {
             info!("Root directory entry: {:?}", file_info);
         }
         directory.reset_entry_readout().unwrap().unwrap();
+
+        test_recursive_helpers(&mut directory);
+        test_file_metadata(&mut directory);
+        test_positional_io(&mut directory);
+        remove_test_files(&mut directory);
     } else {
         warn!("`SimpleFileSystem` protocol is not available");
     }
@@ -52,4 +66,194 @@ pub fn test(bt: &BootServices) {
             info!("Unknown partition");
         }
     }
+
+    test_memory_block_device(bt);
+}
+
+/// Install a block device backed by memory, and use it through the firmware
+fn test_memory_block_device(bt: &BootServices) {
+    let mut device = MemoryBlockDevice::new(vec![0; 8 * 512], 512, false);
+    let handle = unsafe { device.install(bt, None) }
+        .expect_success("Failed to install the memory block device");
+
+    let block_io = bt
+        .handle_protocol::<BlockIO>(handle)
+        .expect_success("Failed to open the memory block device");
+    let block_io = unsafe { &mut *block_io.get() };
+    assert_eq!(block_io.media().last_block(), 7);
+    block_io
+        .write_blocks(0, 3, &[0x5a; 1024])
+        .expect_success("Failed to write blocks");
+    let mut block = [0; 512];
+    block_io
+        .read_blocks(0, 4, &mut block)
+        .expect_success("Failed to read blocks");
+    assert!(block.iter().all(|&byte| byte == 0x5a));
+
+    unsafe { device.uninstall(bt, handle) }
+        .expect_success("Failed to uninstall the memory block device");
+    assert!(device.data()[3 * 512..5 * 512]
+        .iter()
+        .all(|&byte| byte == 0x5a));
+}
+
+/// Create a small directory tree, then read it back with the recursive helpers
+fn test_recursive_helpers(root: &mut Directory) {
+    let dir_path = CString16::try_from("test_runner\\nested\\dir").unwrap();
+    let mut dir = root
+        .create_dir_all(&dir_path)
+        .expect_success("Failed to create nested directories");
+    // Creating existing directories is not an error
+    root.create_dir_all(&dir_path)
+        .expect_success("Failed to open existing directories");
+
+    let content = b"directory helpers";
+    let handle = dir
+        .open(
+            "file.txt",
+            FileMode::CreateReadWrite,
+            FileAttribute::empty(),
+        )
+        .expect_success("Failed to create file");
+    let mut file = match handle.into_type().unwrap_success() {
+        FileType::Regular(file) => file,
+        FileType::Dir(_) => panic!("file.txt is a directory"),
+    };
+    file.write(content).unwrap_success();
+    file.close();
+
+    let file_path = CString16::try_from("test_runner\\nested\\dir\\file.txt").unwrap();
+    let read = root
+        .read_to_vec(&file_path)
+        .expect_success("Failed to read file");
+    assert_eq!(read, content);
+
+    let mut visited = Vec::new();
+    let mut test_dir = match root
+        .open("test_runner", FileMode::Read, FileAttribute::empty())
+        .unwrap_success()
+        .into_type()
+        .unwrap_success()
+    {
+        FileType::Dir(dir) => dir,
+        FileType::Regular(_) => panic!("test_runner is not a directory"),
+    };
+    test_dir
+        .walk(|path, info| {
+            info!("Walked over {} ({} bytes)", path, info.file_size());
+            visited.push(path.to_string());
+            Ok(().into())
+        })
+        .expect_success("Failed to walk directory");
+    assert_eq!(visited, ["nested", "nested\\dir", "nested\\dir\\file.txt"]);
+
+    test_dir.reset_entry_readout().unwrap_success();
+    let names: Vec<_> = test_dir
+        .entries()
+        .map(|entry| entry.unwrap_success().file_name().to_string())
+        .collect();
+    assert!(names.iter().any(|name| name == "nested"));
+}
+
+/// Change the size and the attributes of the file created by `test_recursive_helpers`
+fn test_file_metadata(root: &mut Directory) {
+    let handle = root
+        .open(
+            "test_runner\\nested\\dir\\file.txt",
+            FileMode::ReadWrite,
+            FileAttribute::empty(),
+        )
+        .expect_success("Failed to open file");
+    let mut file = match handle.into_type().unwrap_success() {
+        FileType::Regular(file) => file,
+        FileType::Dir(_) => panic!("file.txt is a directory"),
+    };
+
+    file.set_len(4).expect_success("Failed to truncate file");
+    let info = file.get_boxed_info::<FileInfo>().unwrap_success();
+    assert_eq!(info.file_size(), 4);
+    file.set_len(16).expect_success("Failed to grow file");
+    let info = file.get_boxed_info::<FileInfo>().unwrap_success();
+    assert_eq!(info.file_size(), 16);
+
+    file.modify_info(|info: &mut FileInfo| {
+        info.set_attribute(info.attribute() | FileAttribute::HIDDEN);
+        info.set_create_time(uefi::table::runtime::Time::zero());
+    })
+    .expect_success("Failed to hide file");
+    let info = file.get_boxed_info::<FileInfo>().unwrap_success();
+    assert!(info.attribute().contains(FileAttribute::HIDDEN));
+    assert!(!info.create_time().is_zero());
+
+    file.modify_info(|info: &mut FileInfo| {
+        info.set_attribute(info.attribute() - FileAttribute::HIDDEN)
+    })
+    .expect_success("Failed to unhide file");
+}
+
+/// Open a regular file below the test directory
+fn open_regular(root: &mut Directory, path: &str, mode: FileMode) -> RegularFile {
+    let handle = root
+        .open(path, mode, FileAttribute::empty())
+        .expect_success("Failed to open file");
+    match handle.into_type().unwrap_success() {
+        FileType::Regular(file) => file,
+        FileType::Dir(_) => panic!("{} is a directory", path),
+    }
+}
+
+/// Use the positional helpers on the file created by `test_recursive_helpers`
+fn test_positional_io(root: &mut Directory) {
+    let mut file = open_regular(
+        root,
+        "test_runner\\nested\\dir\\file.txt",
+        FileMode::ReadWrite,
+    );
+    file.set_len(0).unwrap_success();
+    file.write(b"0123456789").unwrap_success();
+    assert_eq!(file.get_position().unwrap_success(), 10);
+
+    file.write_at(2, b"ab").unwrap_success();
+    let mut buffer = [0; 4];
+    let read = file.read_at(1, &mut buffer).unwrap_success();
+    assert_eq!(&buffer[..read], b"1ab4");
+    assert_eq!(file.get_position().unwrap_success(), 10);
+
+    let mut copy = open_regular(
+        root,
+        "test_runner\\nested\\dir\\copy.txt",
+        FileMode::CreateReadWrite,
+    );
+    copy.set_len(0).unwrap_success();
+    file.set_position(0).unwrap_success();
+    let mut chunk = [0; 3];
+    let copied = stream_copy(&mut file, &mut copy, &mut chunk).unwrap_success();
+    assert_eq!(copied, 10);
+
+    let content = root
+        .read_to_vec(&CString16::try_from("test_runner\\nested\\dir\\copy.txt").unwrap())
+        .unwrap_success();
+    assert_eq!(content, b"01ab456789");
+    copy.delete().unwrap_success();
+}
+
+/// Delete the files and directories created by `test_recursive_helpers`,
+/// deepest first, so that the tests leave the volume as they found it
+fn remove_test_files(root: &mut Directory) {
+    for path in [
+        "test_runner\\nested\\dir\\file.txt",
+        "test_runner\\nested\\dir",
+        "test_runner\\nested",
+        "test_runner",
+    ]
+    .iter()
+    {
+        root.open(path, FileMode::ReadWrite, FileAttribute::empty())
+            .expect_success("Failed to open test file")
+            .delete()
+            .expect_success("Failed to delete test file");
+    }
+    assert!(root
+        .open("test_runner", FileMode::Read, FileAttribute::empty())
+        .is_err());
 }
@@ -1,24 +1,36 @@
 use uefi::prelude::*;
 
+use uefi::pe::RunningImage;
 use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::memory_protection::MemoryProtection;
+use uefi::table::boot::{MemoryAttribute, PAGE_SIZE};
 use uefi::{proto, Identify};
 
 pub fn test(image: Handle, st: &mut SystemTable<Boot>) {
     info!("Testing various protocols");
 
     console::test(st);
+    performance::test(image, st);
 
     let bt = st.boot_services();
     find_protocol(bt);
     test_protocols_per_handle(image, bt);
+    test_running_image(image, bt);
 
     debug::test(bt);
+    decompress::test(bt);
     device_path::test(image, bt);
+    driver::test(image, bt);
+    hii::test(bt);
     media::test(bt);
+    network::test(bt);
     pi::test(bt);
+    security::test(bt);
+    shell::test(image, bt);
+    string::test(bt);
 
     #[cfg(any(
-        target_arch = "i386",
+        target_arch = "x86",
         target_arch = "x86_64",
         target_arch = "arm",
         target_arch = "aarch64"
@@ -53,15 +65,55 @@ fn test_protocols_per_handle(image: Handle, bt: &BootServices) {
         .any(|guid| **guid == LoadedImage::GUID));
 }
 
+fn test_running_image(image: Handle, bt: &BootServices) {
+    let running = RunningImage::new(bt, image).expect_success("Failed to find the running image");
+    let text = running
+        .section(".text")
+        .expect("The image has no code section");
+    assert!(text.is_executable() && !text.is_writable());
+    let here = test_running_image as fn(_, _) as usize as u64;
+    assert!(running.section_range(&text).contains(&here));
+    assert_eq!(running.section_of(here).map(|s| s.name), Some(text.name));
+    info!(
+        "Running image at {:#x}, {} bytes in {} sections",
+        running.base(),
+        running.size(),
+        running.sections().len()
+    );
+
+    if let Ok(protection) = bt.locate_protocol::<MemoryProtection>() {
+        let protection = unsafe { &*protection.expect("Warnings encountered").get() };
+        match running.protect(protection).status() {
+            Status::SUCCESS => {
+                let start = running.section_range(&text).start;
+                let attributes = protection
+                    .get_memory_attributes(start..start + PAGE_SIZE as u64)
+                    .expect_success("Failed to get the attributes of the code");
+                assert!(attributes.contains(MemoryAttribute::READ_ONLY));
+            }
+            Status::UNSUPPORTED => info!("The sections of the image are not page-aligned"),
+            status => panic!("Failed to protect the running image: {:?}", status),
+        }
+    }
+}
+
 mod console;
-mod debug;
+pub mod debug;
+mod decompress;
 mod device_path;
+mod driver;
+mod hii;
 mod media;
-mod pi;
+mod network;
+mod performance;
+pub mod pi;
+mod security;
+mod shell;
 #[cfg(any(
-    target_arch = "i386",
+    target_arch = "x86",
     target_arch = "x86_64",
     target_arch = "arm",
     target_arch = "aarch64"
 ))]
 mod shim;
+mod string;
@@ -0,0 +1,81 @@
+use uefi::prelude::*;
+use uefi::proto::network::adapter_info::{AdapterInformation, MediaState};
+use uefi::proto::network::mtftp::{Mtftp4, Mtftp4ServiceBinding};
+use uefi::proto::network::nii::NetworkInterfaceIdentifier;
+use uefi::proto::network::vlan::VlanConfig;
+use uefi::Identify;
+
+pub fn test(bt: &BootServices) {
+    info!("Running network protocol tests");
+
+    if let Ok(handles) = bt.find_handles::<AdapterInformation>() {
+        for handle in handles.expect("Warnings encountered while finding adapter handles") {
+            let adapter = bt
+                .handle_protocol::<AdapterInformation>(handle)
+                .expect_success("Failed to open Adapter Information protocol");
+            let adapter = unsafe { &mut *adapter.get() };
+            let types = adapter
+                .supported_types(bt)
+                .expect_success("Failed to list the information blocks");
+            info!("Adapter information blocks: {:?}", &*types);
+            if types.contains(&MediaState::GUID) {
+                let state = adapter
+                    .get::<MediaState>(bt)
+                    .expect_success("Failed to get the media state");
+                info!("Media state: {:?}", state.media_state);
+            }
+        }
+    } else {
+        warn!("Adapter Information protocol is not supported");
+    }
+
+    if let Ok(handles) = bt.find_handles::<NetworkInterfaceIdentifier>() {
+        for handle in handles.expect("Warnings encountered while finding NII handles") {
+            let nii = bt
+                .handle_protocol::<NetworkInterfaceIdentifier>(handle)
+                .expect_success("Failed to open Network Interface Identifier protocol");
+            let nii = unsafe { &*nii.get() };
+            info!(
+                "Network interface {:?}, version {:?}",
+                nii.interface_type(),
+                nii.version()
+            );
+        }
+    } else {
+        warn!("Network Interface Identifier protocol is not supported");
+    }
+
+    if let Ok(handles) = bt.find_handles::<VlanConfig>() {
+        for handle in handles.expect("Warnings encountered while finding VLAN handles") {
+            let vlan = bt
+                .handle_protocol::<VlanConfig>(handle)
+                .expect_success("Failed to open VLAN Config protocol");
+            let vlan = unsafe { &mut *vlan.get() };
+            match vlan.find(bt, None) {
+                Ok(vlans) => info!("VLANs: {:?}", &*vlans.unwrap()),
+                Err(err) => assert_eq!(err.status(), Status::NOT_FOUND),
+            }
+        }
+    } else {
+        warn!("VLAN Config protocol is not supported");
+    }
+
+    if let Ok(handles) = bt.find_handles::<Mtftp4ServiceBinding>() {
+        for handle in handles.expect("Warnings encountered while finding MTFTPv4 handles") {
+            let binding = bt
+                .handle_protocol::<Mtftp4ServiceBinding>(handle)
+                .expect_success("Failed to open MTFTPv4 Service Binding protocol");
+            let binding = unsafe { &mut *binding.get() };
+            let child = binding
+                .create_child()
+                .expect_success("Failed to create an MTFTPv4 instance");
+            bt.handle_protocol::<Mtftp4>(child)
+                .expect_success("Failed to open MTFTPv4 protocol");
+            binding
+                .destroy_child(child)
+                .expect_success("Failed to destroy the MTFTPv4 instance");
+        }
+    } else {
+        warn!("MTFTPv4 Service Binding protocol is not supported");
+    }
+}
@@ -0,0 +1,29 @@
+use uefi::prelude::*;
+use uefi::proto::performance::PerformanceMeasurement;
+use uefi::table::fpdt::Fpdt;
+use uefi::CStr8;
+
+pub fn test(image: Handle, st: &SystemTable<Boot>) {
+    info!("Running performance measurement test");
+    match unsafe { Fpdt::find(st.config_table()) } {
+        Some(fpdt) => {
+            let perf = unsafe { fpdt.basic_boot_performance() };
+            info!("Basic boot performance: {:?}", perf);
+        }
+        None => warn!("The firmware does not publish an FPDT"),
+    }
+
+    let bt = st.boot_services();
+    if let Ok(perf) = bt.locate_protocol::<PerformanceMeasurement>() {
+        let perf = perf.expect("Warnings encountered while opening PerformanceMeasurement");
+        let perf = unsafe { &*perf.get() };
+
+        let token = CStr8::from_bytes_with_nul(b"uefi-test\0").unwrap();
+        perf.start(image, token)
+            .expect_success("Failed to start a performance measurement");
+        perf.end(image, token)
+            .expect_success("Failed to end a performance measurement");
+    } else {
+        warn!("Performance measurement protocol is not supported");
+    }
+}
@@ -0,0 +1,26 @@
+use uefi::prelude::*;
+use uefi::proto::pi::cpu::CpuArch;
+
+pub fn test(bt: &BootServices) {
+    info!("Running CPU architectural protocol test");
+    let cpu = bt
+        .locate_protocol::<CpuArch>()
+        .expect_success("Failed to open CPU architectural protocol");
+    let cpu = unsafe { &mut *cpu.get() };
+
+    // Interrupts are only masked above `Tpl::NOTIFY`
+    assert!(cpu.interrupts_enabled().unwrap_success());
+
+    if cpu.number_of_timers() > 0 {
+        let start = cpu.timer_value(0).unwrap_success();
+        let end = cpu.timer_value(0).unwrap_success();
+        assert!(end >= start, "The timer of the processor went backwards");
+    }
+
+    // The test runner already handles faults, see `catch_faults`
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(
+        cpu.panic_on_faults().unwrap_err().status(),
+        Status::ALREADY_STARTED
+    );
+}
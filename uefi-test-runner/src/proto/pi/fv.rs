@@ -0,0 +1,75 @@
+use alloc::string::String;
+use uefi::prelude::*;
+use uefi::proto::pi::fv::{FirmwareVolume, FirmwareVolumeBlock, FvFileType, SectionType};
+use uefi::table::boot::BootServices;
+
+pub fn test(bt: &BootServices) {
+    info!("Running firmware volume protocol tests");
+
+    let handles = bt
+        .find_handles::<FirmwareVolume>()
+        .expect_success("Failed to get handles for `FirmwareVolume` protocol");
+    let mut drivers = 0;
+    for handle in handles {
+        let fv = bt
+            .handle_protocol::<FirmwareVolume>(handle)
+            .expect_success("Failed to open firmware volume");
+        let fv = unsafe { &*fv.get() };
+        info!(
+            "Firmware volume attributes: {:?}",
+            fv.attributes().expect_success("Failed to get attributes")
+        );
+
+        for file in fv.files(FvFileType::DRIVER) {
+            let (name, info) = file.expect_success("Failed to enumerate files");
+            assert_eq!(info.file_type, FvFileType::DRIVER);
+            drivers += 1;
+
+            // Most drivers have a user interface section
+            if let Ok(ui) = fv.read_section_to_vec(&name, SectionType::USER_INTERFACE, 0) {
+                let ui = ui.unwrap();
+                let chars = ui
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .take_while(|&c| c != 0);
+                let ui_name: String = core::char::decode_utf16(chars)
+                    .map(|c| c.unwrap_or('?'))
+                    .collect();
+                if drivers <= 5 {
+                    info!("Driver {}: {}", name, ui_name);
+                }
+            }
+
+            let info_only = fv
+                .file_info(&name)
+                .expect_success("Failed to get file info");
+            assert_eq!(info_only.size, info.size);
+        }
+    }
+    info!("Found {} DXE drivers", drivers);
+
+    let handles = bt
+        .find_handles::<FirmwareVolumeBlock>()
+        .map(|handles| handles.unwrap())
+        .unwrap_or_default();
+    for handle in handles {
+        let fvb = bt
+            .handle_protocol::<FirmwareVolumeBlock>(handle)
+            .expect_success("Failed to open firmware volume block");
+        let fvb = unsafe { &*fvb.get() };
+        let attributes = fvb.attributes().expect_success("Failed to get attributes");
+        let block = fvb.block_info(0).expect_success("Failed to get block size");
+        info!(
+            "Firmware volume block: {:?}, first block {:?}",
+            attributes, block
+        );
+
+        let mut header = [0; 64];
+        let read = fvb
+            .read(0, 0, &mut header)
+            .expect_success("Failed to read volume header");
+        assert_eq!(read, header.len());
+        // The volume header signature, "_FVH", is at offset 40
+        assert_eq!(&header[40..44], b"_FVH");
+    }
+}
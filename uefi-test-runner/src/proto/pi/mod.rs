@@ -3,7 +3,10 @@ use uefi::prelude::*;
 pub fn test(bt: &BootServices) {
     info!("Testing Platform Initialization protocols");
 
-    mp::test(bt);
+    cpu::test(bt);
+    fv::test(bt);
 }
 
-mod mp;
+mod cpu;
+mod fv;
+pub mod mp;
@@ -0,0 +1,46 @@
+use uefi::prelude::*;
+use uefi::proto::security::deferred_image::DeferredImageLoad;
+use uefi::proto::security::user::{UserInfoType, UserManager};
+
+pub fn test(bt: &BootServices) {
+    test_deferred_image(bt);
+    test_user_manager(bt);
+}
+
+fn test_deferred_image(bt: &BootServices) {
+    info!("Running Deferred Image Load protocol test");
+    if let Ok(deferred) = bt.locate_protocol::<DeferredImageLoad>() {
+        let deferred = deferred.expect("Warnings encountered while opening Deferred Image Load");
+        let deferred = unsafe { &*deferred.get() };
+        for image in deferred.images() {
+            info!(
+                "Deferred image: {} bytes, boot option: {}",
+                image.image.len(),
+                image.boot_option
+            );
+        }
+    } else {
+        warn!("Deferred Image Load protocol is not supported");
+    }
+}
+
+fn test_user_manager(bt: &BootServices) {
+    info!("Running User Manager protocol test");
+    if let Ok(manager) = bt.locate_protocol::<UserManager>() {
+        let manager = manager.expect("Warnings encountered while opening User Manager");
+        let manager = unsafe { &*manager.get() };
+        let mut buffer = [0; 256];
+        for user in manager.users() {
+            for info in manager.infos(user) {
+                if let Ok(info) = manager.get_info(user, info, &mut buffer) {
+                    let info = info.unwrap();
+                    if info.info_type() == UserInfoType::NAME {
+                        info!("User: {:?}", info.name());
+                    }
+                }
+            }
+        }
+    } else {
+        warn!("User Manager protocol is not supported");
+    }
+}
@@ -0,0 +1,32 @@
+use uefi::prelude::*;
+use uefi::proto::shell::Shell;
+use uefi::shell::Args;
+use uefi::table::boot::BootServices;
+
+pub fn test(image: Handle, bt: &BootServices) {
+    info!("Running shell arguments test");
+
+    // The test runner is started by the firmware rather than by the shell, so
+    // the arguments come from its load options, which may be empty.
+    let args = Args::from_image(bt, image).expect("Failed to get the arguments of the image");
+    info!("Program: {:?}", args.program());
+    let args = args
+        .finish()
+        .expect("Failed to get the positional arguments");
+    info!("Arguments: {:?}", args);
+
+    if let Ok(shell) = bt.locate_protocol::<Shell>() {
+        let shell = shell.expect("Warnings encountered while opening shell protocol");
+        let shell = unsafe { &*shell.get() };
+
+        info!("Shell version: {:?}", shell.version());
+        info!("Current directory: {:?}", shell.cwd());
+        info!("Path: {:?}", shell.path());
+        assert!(
+            shell.envs().count() > 0,
+            "The shell should define environment variables"
+        );
+    } else {
+        info!("Shell protocol is not supported");
+    }
+}
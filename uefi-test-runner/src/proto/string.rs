@@ -0,0 +1,55 @@
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use uefi::proto::string::fat::ShortName;
+use uefi::proto::string::unicode_collation::{StrConversionError, UnicodeCollation};
+use uefi::table::boot::BootServices;
+use uefi::CString16;
+
+pub fn test(bt: &BootServices) {
+    info!("Running Unicode Collation protocol test");
+    if let Ok(collation) = bt.locate_protocol::<UnicodeCollation>() {
+        let collation = collation.expect("Warnings encountered while opening Unicode Collation");
+        let collation = unsafe { &*collation.get() };
+        let languages = collation.supported_languages().to_bytes();
+        info!(
+            "Supported languages: {}",
+            core::str::from_utf8(languages).unwrap_or("<invalid>")
+        );
+
+        let lower = CString16::try_from("boot.efi").unwrap();
+        let upper = CString16::try_from("BOOT.EFI").unwrap();
+        let other = CString16::try_from("shell.efi").unwrap();
+        assert_eq!(collation.stri_coll(&lower, &upper), Ordering::Equal);
+        assert_eq!(collation.stri_coll(&lower, &other), Ordering::Less);
+        assert!(lower.eq_ignore_case_via(collation, &upper));
+
+        let pattern = CString16::try_from("*.EF?").unwrap();
+        assert!(collation.metai_match(&lower, &pattern));
+        let pattern = CString16::try_from("[a-c]*").unwrap();
+        assert!(!collation.metai_match(&other, &pattern));
+
+        let mut buf = [0; 16];
+        let converted = collation.str_upr(&lower, &mut buf).unwrap();
+        assert_eq!(converted.to_u16_slice(), upper.to_u16_slice());
+        let converted = collation.str_lwr(&upper, &mut buf).unwrap();
+        assert_eq!(converted.to_u16_slice(), lower.to_u16_slice());
+        assert_eq!(
+            collation.str_lwr(&upper, &mut buf[..4]).err(),
+            Some(StrConversionError::BufferTooSmall)
+        );
+
+        let mut fat = [0; 11];
+        assert!(!collation.str_to_fat(&lower, &mut fat));
+        assert_eq!(&fat[..7], b"BOOTEFI");
+        let converted = collation.fat_to_str(b"BOOT.EFI", &mut buf).unwrap();
+        assert_eq!(converted.to_u16_slice(), upper.to_u16_slice());
+
+        let long_name = CString16::try_from("Program Files").unwrap();
+        let (short, lossy) = ShortName::from_long_name(&long_name, Some(collation))
+            .expect("Failed to generate short name");
+        assert!(lossy);
+        assert_eq!(short.with_numeric_tail(1).as_bytes(), b"PROGRA~1   ");
+    } else {
+        warn!("Unicode Collation protocol is not supported");
+    }
+}
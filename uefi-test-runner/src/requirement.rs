@@ -0,0 +1,50 @@
+//! Conditions for the tests to run, which are checked on the firmware which
+//! runs them
+
+use core::fmt;
+use uefi::prelude::*;
+use uefi::table::boot::SearchType;
+use uefi::table::Revision;
+use uefi::Guid;
+
+/// Condition for a test registered with `Suite::run_if`, which is checked
+/// when the test is reached
+///
+/// A test whose condition is not met is reported as skipped, with the
+/// condition, instead of failing on firmware which lacks a feature.
+#[derive(Clone, Copy, Debug)]
+pub enum Requirement {
+    /// The firmware implements this revision of the specification, or a
+    /// later one
+    Revision(Revision),
+    /// The protocol with this name and GUID is installed
+    Protocol(&'static str, Guid),
+    /// The runner is built with the `resume` feature, so that the suite goes
+    /// on after the test resets the machine
+    Resume,
+}
+
+impl Requirement {
+    /// Whether the condition is met
+    pub fn is_met(&self, st: &SystemTable<Boot>) -> bool {
+        match self {
+            Requirement::Revision(revision) => st.uefi_revision() >= *revision,
+            Requirement::Protocol(_, guid) => matches!(
+                st.boot_services()
+                    .locate_handle(SearchType::ByProtocol(guid), None),
+                Ok(count) if count.log() != 0
+            ),
+            Requirement::Resume => cfg!(feature = "resume"),
+        }
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Requirement::Revision(revision) => write!(f, "requires UEFI {:?}", revision),
+            Requirement::Protocol(name, _) => write!(f, "requires the {} protocol", name),
+            Requirement::Resume => write!(f, "requires the resume feature"),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Progress of the suite across resets of the machine, with the `resume`
+//! feature
+//!
+//! Panics cannot be caught, so a test reports its result from a panic hook if
+//! it panics, as expected or not, which stores the progress of the suite in a
+//! non-volatile variable, and resets the machine. The firmware then starts
+//! the runner again, whose suite resumes after that test. The progress is
+//! also stored before each test, so that a test which crashes the machine, or
+//! which the watchdog timer interrupts, is then reported as failed. A test
+//! which may be retried runs again after an attempt of it panics.
+
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::convert::{TryFrom, TryInto};
+use uefi::prelude::*;
+use uefi::table::boot::Tpl;
+use uefi::table::runtime::{ResetType, VariableAttributes, VariableVendor};
+use uefi::{CString16, Guid};
+
+/// State of the test which was running when the progress was stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestState {
+    /// The test did not report its result, and failed if the machine was
+    /// reset
+    Running,
+    /// The test panicked as expected, and then reset the machine
+    Passed,
+    /// The test panicked, and then reset the machine
+    Failed,
+    /// An attempt of the test panicked, and the test runs again
+    Retry,
+}
+
+/// Progress of the suite, stored across resets of the machine
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// Index of the test in the order of the suite
+    pub index: usize,
+    pub state: TestState,
+    /// Number of the tests before it which passed
+    pub passed: usize,
+    /// Number of the benchmarks before it which were measured
+    pub measured: usize,
+    /// Number of the tests before it which were ignored
+    pub ignored: usize,
+    /// Number of the tests before it which were skipped for a known issue
+    pub skipped: usize,
+    /// Number of the tests before it which failed as expected
+    pub expected_failures: usize,
+    /// Number of the tests before it which passed on a retry
+    pub flaky: usize,
+    /// Number of the attempts of the test which failed
+    pub attempts: usize,
+    /// Seed of the order of the tests, if they are shuffled
+    pub seed: Option<u64>,
+    /// Indices of the tests before it which failed
+    pub failed: Vec<usize>,
+}
+
+impl Checkpoint {
+    /// Size of the fields before the indices of the failed tests
+    const HEADER_SIZE: usize = 48;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Self::HEADER_SIZE + 4 * self.failed.len());
+        let state = match self.state {
+            TestState::Running => 0,
+            TestState::Passed => 1,
+            TestState::Failed => 2,
+            TestState::Retry => 3,
+        };
+        let seed = self.seed.unwrap_or(0);
+        for value in [
+            self.index,
+            state,
+            self.passed,
+            self.measured,
+            self.ignored,
+            self.skipped,
+            self.expected_failures,
+            self.flaky,
+            self.attempts,
+            self.seed.is_some() as usize,
+            seed as u32 as usize,
+            (seed >> 32) as usize,
+        ]
+        .iter()
+        .chain(&self.failed)
+        {
+            data.extend_from_slice(&(*value as u32).to_le_bytes());
+        }
+        data
+    }
+
+    fn from_bytes(data: &[u8]) -> Option<Self> {
+        let values = data.chunks_exact(4);
+        if data.len() < Self::HEADER_SIZE || !values.remainder().is_empty() {
+            return None;
+        }
+        let mut values = values.map(|value| u32::from_le_bytes(value.try_into().unwrap()) as usize);
+        Some(Checkpoint {
+            index: values.next()?,
+            state: match values.next()? {
+                1 => TestState::Passed,
+                2 => TestState::Failed,
+                3 => TestState::Retry,
+                _ => TestState::Running,
+            },
+            passed: values.next()?,
+            measured: values.next()?,
+            ignored: values.next()?,
+            skipped: values.next()?,
+            expected_failures: values.next()?,
+            flaky: values.next()?,
+            attempts: values.next()?,
+            seed: match (values.next()?, values.next()?, values.next()?) {
+                (0, _, _) => None,
+                (_, low, high) => Some(low as u64 | (high as u64) << 32),
+            },
+            failed: values.collect(),
+        })
+    }
+}
+
+/// Variable holding the progress of the suite
+fn progress_variable() -> (CString16, VariableVendor) {
+    let name = CString16::try_from("UefiRsTestProgress").unwrap();
+    // Arbitrary GUID generated for the test runner.
+    let vendor = VariableVendor(Guid::from_values(
+        0x5f0c_2a4e,
+        0x86d1,
+        0x4b7b,
+        0x9e1c,
+        0x0a6b_3c8f_52d7,
+    ));
+    (name, vendor)
+}
+
+fn runtime_services() -> &'static RuntimeServices {
+    unsafe { uefi_services::system_table().as_ref() }.runtime_services()
+}
+
+/// The progress of the suite before the machine was reset, if any
+pub fn load_checkpoint() -> Option<Checkpoint> {
+    let rt = runtime_services();
+    let (name, vendor) = progress_variable();
+    let size = match rt.get_variable_size(&name, &vendor) {
+        Ok(size) => size.log(),
+        Err(error) if error.status() == Status::NOT_FOUND => return None,
+        Err(error) => panic!("Failed to read the test progress: {:?}", error),
+    };
+    let mut data = vec![0; size];
+    let (data, _) = rt
+        .get_variable(&name, &vendor, &mut data)
+        .expect_success("Failed to read the test progress");
+    let checkpoint = Checkpoint::from_bytes(data);
+    if checkpoint.is_none() {
+        warn!("Ignoring the malformed test progress");
+    }
+    checkpoint
+}
+
+/// Store the progress of the suite, or clear it at the end of the suite
+pub fn store_checkpoint(checkpoint: Option<&Checkpoint>) {
+    let (name, vendor) = progress_variable();
+    let attributes = VariableAttributes::NON_VOLATILE | VariableAttributes::BOOTSERVICE_ACCESS;
+    // An empty value deletes the variable
+    let data = checkpoint.map_or_else(Vec::new, Checkpoint::to_bytes);
+    match runtime_services().set_variable(&name, &vendor, attributes, &data) {
+        Ok(completion) => completion.log(),
+        // There is no variable to delete
+        Err(error) if checkpoint.is_none() && error.status() == Status::NOT_FOUND => {}
+        Err(error) => panic!("Failed to store the test progress: {:?}", error),
+    }
+}
+
+/// State which the suite gives to a panic hook, which is a plain function
+///
+/// The tests run on a single processor, and the hook only runs during the
+/// test for which the state was set.
+struct HookState<T>(UnsafeCell<Option<T>>);
+
+unsafe impl<T> Sync for HookState<T> {}
+
+impl<T> HookState<T> {
+    const fn new() -> Self {
+        HookState(UnsafeCell::new(None))
+    }
+
+    fn set(&self, value: Option<T>) {
+        unsafe { *self.0.get() = value };
+    }
+
+    fn take(&self) -> Option<T> {
+        unsafe { (*self.0.get()).take() }
+    }
+}
+
+/// Progress of the suite to store if the running test panics, and the text
+/// which the message of an expected panic contains
+static PANIC_CHECKPOINT: HookState<(Checkpoint, Option<&'static str>)> = HookState::new();
+
+/// Store `checkpoint` and reset the machine if the running test panics, or
+/// stop with `None`
+///
+/// The state of `checkpoint` tells whether the test passes if it panics. If
+/// `expected` is set, it only passes if the panic message contains it, as
+/// with `#[should_panic(expected = "...")]`.
+pub fn reset_on_panic(checkpoint: Option<Checkpoint>, expected: Option<&'static str>) {
+    let hook = checkpoint
+        .as_ref()
+        .map(|_| store_on_panic as fn(&core::panic::PanicInfo));
+    PANIC_CHECKPOINT.set(checkpoint.map(|checkpoint| (checkpoint, expected)));
+    uefi_services::set_panic_hook(hook);
+}
+
+/// Panic hook of the running test, which reports its result after the reset
+fn store_on_panic(info: &core::panic::PanicInfo) {
+    let (mut checkpoint, expected) = match PANIC_CHECKPOINT.take() {
+        Some(state) => state,
+        None => return,
+    };
+    if let Some(expected) = expected {
+        // The report holds the message, in a format which depends on the
+        // compiler
+        if !format!("{}", info).contains(expected) {
+            error!("The panic message does not contain {:?}", expected);
+            checkpoint.state = TestState::Failed;
+        }
+    }
+    match checkpoint.state {
+        TestState::Passed => info!("The test panicked as expected, resuming the tests"),
+        TestState::Retry => warn!(
+            "Attempt {} of the test panicked, running it again",
+            checkpoint.attempts
+        ),
+        _ => error!("The test panicked, resuming the tests"),
+    }
+    // The test may have panicked with a `TplMutex` locked, whose guard is not
+    // dropped, and variables cannot be written above `Tpl::CALLBACK`
+    let bt = unsafe { uefi_services::system_table().as_ref() }.boot_services();
+    unsafe { bt.restore_tpl(Tpl::APPLICATION) };
+    store_checkpoint(Some(&checkpoint));
+    runtime_services().reset(ResetType::Warm, Status::SUCCESS, None);
+}
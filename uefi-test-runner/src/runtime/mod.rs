@@ -1,7 +1,9 @@
-use uefi::table::runtime::RuntimeServices;
+use uefi::table::runtime::{RtService, RuntimeServices};
 
 pub fn test(rt: &RuntimeServices) {
     info!("Testing runtime services");
+    // All services are supported until boot services are exited
+    assert!(rt.supports(RtService::all()));
     vars::test(rt);
 }
 
@@ -1,7 +1,9 @@
 use core::convert::TryFrom;
 use log::info;
 use uefi::prelude::*;
+use uefi::table::capsule::{self, OsIndications};
 use uefi::table::runtime::{VariableAttributes, VariableVendor};
+use uefi::table::var_journal;
 use uefi::{CString16, Guid};
 
 fn test_variables(rt: &RuntimeServices) {
@@ -46,8 +48,66 @@ fn test_variables(rt: &RuntimeServices) {
     if let Some(key) = variable_keys.first() {
         info!("First variable: {}", key);
     }
+
+    info!("Testing iter_variable_keys");
+    let mut count = 0;
+    let mut found_test_variable = false;
+    for key in rt.iter_variable_keys() {
+        let (key_name, key_vendor) = key.expect_success("failed to get next variable key");
+        count += 1;
+        found_test_variable |=
+            key_vendor == vendor.0 && key_name.to_u16_slice() == name.to_u16_slice();
+    }
+    assert_eq!(count, variable_keys.len());
+    assert!(found_test_variable);
+}
+
+fn test_journal(rt: &RuntimeServices) {
+    info!("Testing var_journal");
+    let name = CString16::try_from("UefiRsJournalVar").unwrap();
+    // Arbitrary GUID generated for this test.
+    let vendor = VariableVendor(Guid::from_values(
+        0x51c0_7e2d,
+        0x93a4,
+        0x4b6f,
+        0x8d15,
+        0x2e7f_a0c6_b943,
+    ));
+    let attributes = VariableAttributes::BOOTSERVICE_ACCESS | VariableAttributes::RUNTIME_ACCESS;
+
+    var_journal::recover(rt).expect_success("failed to recover the journal");
+    var_journal::write(rt, &name, &vendor, attributes, b"journal")
+        .expect_success("failed to write a variable through the journal");
+    let mut buf = [0; 16];
+    let (data, _) = rt
+        .get_variable(&name, &vendor, &mut buf)
+        .expect_success("failed to get variable");
+    assert_eq!(data, b"journal");
+    // Nothing is left to recover
+    assert_eq!(var_journal::recover(rt).unwrap_success(), 0);
+
+    var_journal::write(rt, &name, &vendor, attributes, &[])
+        .expect_success("failed to delete a variable through the journal");
+    assert_eq!(
+        rt.get_variable_size(&name, &vendor).status(),
+        Status::NOT_FOUND
+    );
+}
+
+fn test_os_indications(rt: &RuntimeServices) {
+    info!("Testing os_indications_supported");
+    let supported = capsule::os_indications_supported(rt)
+        .expect_success("failed to read OsIndicationsSupported");
+    info!("Supported OS indications: {:?}", supported);
+    let on_disk = capsule::supports_capsule_on_disk(rt).unwrap_success();
+    assert_eq!(
+        on_disk,
+        supported.contains(OsIndications::FILE_CAPSULE_DELIVERY_SUPPORTED)
+    );
 }
 
 pub fn test(rt: &RuntimeServices) {
     test_variables(rt);
+    test_journal(rt);
+    test_os_indications(rt);
 }
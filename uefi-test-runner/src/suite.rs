@@ -0,0 +1,1104 @@
+//! Registration and reporting of the tests of the runner, in the spirit of
+//! libtest
+
+use crate::capture::CAPTURE;
+use crate::events::{send_event, send_json, JsonSeconds, JsonString};
+use crate::options::{Ignored, Options, Verbosity};
+use crate::policy::{KnownIssue, SkipPolicy};
+use crate::requirement::Requirement;
+use crate::resume::{self, Checkpoint, TestState};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::ffi::c_void;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use uefi::arch::read_timestamp;
+use uefi::prelude::*;
+use uefi::proto::console::text::Color;
+use uefi::proto::pi::mp::MpServices;
+
+/// Result of a test, as `std::process::Termination` is for libtest
+///
+/// A test may return `()`, or a `Result`, which fails the test if it is an
+/// error. Unlike a panic, such a failure does not end the suite.
+pub trait TestResult {
+    /// Why the test failed, if it did
+    fn failure(self) -> Option<String>;
+}
+
+impl TestResult for () {
+    fn failure(self) -> Option<String> {
+        None
+    }
+}
+
+impl<T, E: fmt::Debug> TestResult for core::result::Result<T, E> {
+    fn failure(self) -> Option<String> {
+        self.err().map(|error| format!("{:?}", error))
+    }
+}
+
+/// Failed assertions of a test, which `uefi_assert!` and `uefi_assert_eq!`
+/// record instead of panicking
+///
+/// Panics cannot be caught, so a failed `assert!` ends the test, and the
+/// suite with it. A test which returns its context instead checks everything,
+/// and fails with all of the failed assertions.
+#[derive(Debug, Default)]
+pub struct TestContext {
+    failures: Vec<String>,
+}
+
+impl TestContext {
+    /// Record `message` as failed at `file:line` unless `ok`
+    pub fn check(&mut self, ok: bool, message: fmt::Arguments, file: &str, line: u32) {
+        if !ok {
+            let failure = format!("{}:{}: {}", file, line, message);
+            error!("{}", failure);
+            self.failures.push(failure);
+        }
+    }
+}
+
+impl TestResult for TestContext {
+    fn failure(self) -> Option<String> {
+        if self.failures.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{} failed assertions:\n{}",
+            self.failures.len(),
+            self.failures.join("\n")
+        ))
+    }
+}
+
+/// Check that a condition holds, as `assert!` does, but record the failure
+/// in the `TestContext` instead of panicking, so that the test goes on
+macro_rules! uefi_assert {
+    ($cx:expr, $cond:expr $(,)?) => {
+        $cx.check(
+            $cond,
+            format_args!("assertion failed: {}", stringify!($cond)),
+            file!(),
+            line!(),
+        )
+    };
+    ($cx:expr, $cond:expr, $($arg:tt)+) => {
+        $cx.check($cond, format_args!($($arg)+), file!(), line!())
+    };
+}
+
+/// Check that two values are equal, as `assert_eq!` does, but record the
+/// failure in the `TestContext` instead of panicking
+macro_rules! uefi_assert_eq {
+    ($cx:expr, $left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => $cx.check(
+                *left == *right,
+                format_args!(
+                    "assertion failed: `(left == right)`\n  left: `{:?}`,\n right: `{:?}`",
+                    left, right
+                ),
+                file!(),
+                line!(),
+            ),
+        }
+    };
+}
+
+/// Setup and teardown shared by tests, as with a `#[fixture]`
+///
+/// `setup` makes the resource which a test registered with
+/// `Suite::run_with_fixture` borrows, and `teardown` releases it once the
+/// test returns, even if it failed. Each test gets its own resource, so the
+/// tests share the initialization but not the state.
+pub struct Fixture<T> {
+    pub setup: fn(&BootServices) -> T,
+    pub teardown: fn(&BootServices, T),
+}
+
+/// Tests of the runner, which are registered first, and run when the suite
+/// ends, as libtest runs the `#[test]` functions which it collected
+///
+/// Each test is logged along with its result, and the suite ends with a
+/// summary of the results. Under QEMU, the results are also sent to the
+/// QEMU-based test runner in the JSON format of libtest. The tests which are
+/// known to break may be skipped or expected to fail with a `SkipPolicy`, as
+/// are those whose `Requirement`s the firmware does not meet, and they are
+/// counted apart.
+///
+/// With the `resume` feature, the suite resumes after a test which reset the
+/// machine, as the tests which are expected to panic and those which time out
+/// do, along with the results of the tests before it.
+///
+/// The tests are sorted in the groups which `Suite::group` starts, whose
+/// results are summed up apart, and of which `--group` only runs one.
+pub struct Suite<'a> {
+    name: &'a str,
+    tests: Vec<Test<'a>>,
+    /// Group of the tests which are registered next
+    group: &'a str,
+    /// Results of the groups of the selected tests, in their order
+    groups: Vec<GroupSummary<'a>>,
+    /// Index in `groups` of the group of each selected test
+    test_groups: Vec<usize>,
+    options: Options,
+    /// Console on which `--list` prints the tests, and `--quiet` the results
+    console: SystemTable<Boot>,
+    /// Progress of the suite before the machine was reset
+    resumed: Option<Checkpoint>,
+    /// Number of tests which passed
+    passed: usize,
+    /// Number of benchmarks which were measured
+    measured: usize,
+    /// Number of tests which were ignored
+    ignored: usize,
+    /// Number of tests which were skipped for a known issue
+    skipped: usize,
+    /// Number of tests which failed as expected for a known issue
+    expected_failures: usize,
+    /// Number of tests which passed on a retry
+    flaky: usize,
+    /// Indices of the tests which failed
+    failed: Vec<usize>,
+    /// Frequency of `read_timestamp`, which times the tests
+    ticks_per_us: u64,
+    /// Indices and durations in microseconds of the tests which ran
+    durations: Vec<(usize, u64)>,
+    /// Tests which are skipped or expected to fail
+    policy: SkipPolicy,
+    /// Seed of the order of the tests, if they are shuffled
+    seed: Option<u64>,
+}
+
+/// A test of a `Suite`, which runs when the suite ends
+struct Test<'a> {
+    name: &'a str,
+    /// Group in which the test was registered, which may be empty
+    group: &'a str,
+    /// Why the test only runs with `--ignored` or `--include-ignored`
+    ignored: Option<&'static str>,
+    /// Text which the panic message contains if the test passes when it
+    /// panics
+    expected_panic: Option<&'static str>,
+    /// Conditions for the test to run, or be skipped
+    requirements: &'a [Requirement],
+    /// The test, which returns why it failed, if it did without panicking
+    f: Box<dyn FnOnce() -> Option<String> + 'a>,
+    /// The iteration of a benchmark, which runs instead of `f`
+    bench: Option<Box<dyn FnMut() + 'a>>,
+    /// An attempt of a test which runs again if it fails, instead of `f`
+    attempt: Option<Box<dyn FnMut() -> Option<String> + 'a>>,
+    /// Number of times the attempt runs again after it failed
+    retries: usize,
+    /// The test, if it may run on an application processor
+    processor_test: Option<ProcessorTest>,
+}
+
+/// A test which may run on an application processor
+///
+/// Application processors cannot call the boot services, so such a test
+/// neither allocates, calls the logger nor panics: it writes its log to a
+/// `ProcessorLog`, and returns why it failed instead.
+pub type ProcessorTest = fn(&mut ProcessorLog) -> core::result::Result<(), &'static str>;
+
+/// Size of the log of a test which runs on an application processor, past
+/// which it is truncated
+const PROCESSOR_LOG_SIZE: usize = 512;
+
+/// Log of a test which may run on an application processor, which the suite
+/// logs when it reports the test, in the order of the tests
+pub struct ProcessorLog {
+    data: [u8; PROCESSOR_LOG_SIZE],
+    len: usize,
+}
+
+impl ProcessorLog {
+    fn new() -> Self {
+        ProcessorLog {
+            data: [0; PROCESSOR_LOG_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Log the lines which the test wrote
+    fn flush(&self) {
+        // The log may have been truncated in the middle of a character
+        for line in String::from_utf8_lossy(&self.data[..self.len]).lines() {
+            info!("{}", line);
+        }
+    }
+}
+
+impl fmt::Write for ProcessorLog {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(PROCESSOR_LOG_SIZE - self.len);
+        self.data[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Results of the tests of a group, which are printed after the summary of
+/// the suite
+///
+/// The counts start over after a reset, so a resumed suite only sums up the
+/// tests which it ran since.
+struct GroupSummary<'a> {
+    name: &'a str,
+    passed: usize,
+    failed: usize,
+    ignored: usize,
+    expected_failures: usize,
+}
+
+/// Number of the slowest tests listed at the end of the suite
+const SLOWEST_TESTS: usize = 5;
+
+/// Code with which the watchdog timer logs a test which timed out
+const WATCHDOG_CODE: u64 = 0x1_0000;
+
+impl<'a> Suite<'a> {
+    /// Start registering the tests of a suite, which may resume after a reset
+    pub fn begin(name: &'a str, st: &SystemTable<Boot>, options: Options) -> Self {
+        let resumed = if cfg!(feature = "resume") {
+            resume::load_checkpoint()
+        } else {
+            None
+        };
+        let start = read_timestamp();
+        st.boot_services().stall(10_000);
+        let ticks_per_us = (read_timestamp() - start) / 10_000;
+        Suite {
+            name,
+            tests: Vec::new(),
+            group: "",
+            groups: Vec::new(),
+            test_groups: Vec::new(),
+            options,
+            // The names of the tests are printed between the logs
+            console: unsafe { st.unsafe_clone() },
+            resumed,
+            passed: 0,
+            measured: 0,
+            ignored: 0,
+            skipped: 0,
+            expected_failures: 0,
+            flaky: 0,
+            failed: Vec::new(),
+            ticks_per_us: ticks_per_us.max(1),
+            durations: Vec::new(),
+            policy: SkipPolicy::default(),
+            seed: None,
+        }
+    }
+
+    /// Skip the tests of `policy`, or expect them to fail
+    pub fn set_policy(&mut self, policy: SkipPolicy) {
+        self.policy = policy;
+    }
+
+    /// Register the tests which follow in the group `name`, until the next
+    /// group starts
+    pub fn group(&mut self, name: &'a str) {
+        self.group = name;
+    }
+
+    /// Run the test `name` when the suite ends
+    pub fn run<R: TestResult>(&mut self, name: &'a str, f: impl FnOnce() -> R + 'a) {
+        self.add(name, None, None, f)
+    }
+
+    /// Run the test `name` if the firmware meets the `requirements`, or
+    /// report it as skipped with the first one which it does not meet
+    pub fn run_if<R: TestResult>(
+        &mut self,
+        name: &'a str,
+        requirements: &'a [Requirement],
+        f: impl FnOnce() -> R + 'a,
+    ) {
+        self.add(name, None, None, f);
+        self.tests.last_mut().unwrap().requirements = requirements;
+    }
+
+    /// Register the test `name`, which is reported as ignored with `reason`,
+    /// as with `#[ignore = "..."]`, unless the runner is asked to run the
+    /// ignored tests
+    pub fn run_ignored<R: TestResult>(
+        &mut self,
+        name: &'a str,
+        reason: &'static str,
+        f: impl FnOnce() -> R + 'a,
+    ) {
+        self.add(name, Some(reason), None, f)
+    }
+
+    /// Run the test `name` like `run_if`, and run it again up to `retries`
+    /// times if it fails, for tests which depend on the timing of the
+    /// hardware
+    ///
+    /// A test which passes on a retry is reported as flaky, with the number of
+    /// attempts. With the `resume` feature, an attempt which panics resets the
+    /// machine, after which the suite runs the test again; without it, the
+    /// panic ends the suite.
+    pub fn run_with_retries<R: TestResult>(
+        &mut self,
+        name: &'a str,
+        requirements: &'a [Requirement],
+        retries: usize,
+        mut f: impl FnMut() -> R + 'a,
+    ) {
+        self.add(name, None, None, || ());
+        let test = self.tests.last_mut().unwrap();
+        test.requirements = requirements;
+        test.attempt = Some(Box::new(move || f().failure()));
+        test.retries = retries;
+    }
+
+    /// Run the test `name`, which passes if it panics with a message that
+    /// contains `expected`, as with `#[should_panic(expected = "...")]`
+    ///
+    /// Panics cannot be caught, so the panic hook compares the message and
+    /// reports the result, and resets the machine, after which the suite
+    /// resumes. This is skipped without the `resume` feature.
+    pub fn run_should_panic(
+        &mut self,
+        name: &'a str,
+        expected: &'static str,
+        f: impl FnOnce() + 'a,
+    ) {
+        self.add(name, None, Some(expected), f);
+        self.tests.last_mut().unwrap().requirements = &[Requirement::Resume];
+    }
+
+    /// Run the test `name` with the resource which `fixture` sets up for it,
+    /// and tears down after it
+    pub fn run_with_fixture<T: 'a, R: TestResult>(
+        &mut self,
+        name: &'a str,
+        fixture: &'a Fixture<T>,
+        f: impl FnOnce(&mut T) -> R + 'a,
+    ) {
+        self.add(name, None, None, move || {
+            let bt = unsafe { uefi_services::system_table().as_ref() }.boot_services();
+            let mut resource = (fixture.setup)(bt);
+            let result = f(&mut resource);
+            (fixture.teardown)(bt, resource);
+            result
+        })
+    }
+
+    /// Run the test `name`, which may run on an application processor, as
+    /// with `--parallel`, or otherwise runs as any other test
+    pub fn run_on_processors(&mut self, name: &'a str, f: ProcessorTest) {
+        self.add(name, None, None, move || {
+            let mut log = ProcessorLog::new();
+            let result = f(&mut log);
+            log.flush();
+            result
+        });
+        self.tests.last_mut().unwrap().processor_test = Some(f);
+    }
+
+    /// Register the benchmark `name`, which runs `iteration` once as a test,
+    /// or measures how long it takes when the runner is given `--bench`, as
+    /// `Bencher::iter` does
+    ///
+    /// The iteration runs in batches which take at least a millisecond, so
+    /// that the timestamp counter times them precisely, and the median of the
+    /// batches is reported in nanoseconds per iteration. The result of the
+    /// iteration is read back, so that the compiler does not optimize it away.
+    pub fn bench<R>(&mut self, name: &'a str, mut iteration: impl FnMut() -> R + 'a) {
+        self.add(name, None, None, || ());
+        self.tests.last_mut().unwrap().bench = Some(Box::new(move || {
+            let result = iteration();
+            core::mem::forget(unsafe { core::ptr::read_volatile(&result) });
+        }));
+    }
+
+    fn add<R: TestResult>(
+        &mut self,
+        name: &'a str,
+        ignored: Option<&'static str>,
+        expected_panic: Option<&'static str>,
+        f: impl FnOnce() -> R + 'a,
+    ) {
+        self.tests.push(Test {
+            name,
+            group: self.group,
+            ignored,
+            expected_panic,
+            requirements: &[],
+            f: Box::new(move || f().failure()),
+            bench: None,
+            attempt: None,
+            retries: 0,
+            processor_test: None,
+        });
+    }
+
+    /// Whether the test runs
+    fn matches(&self, test: &Test) -> bool {
+        (self.options.ignored != Ignored::Only || test.ignored.is_some())
+            && (!self.options.bench || test.bench.is_some())
+            && self.options.matches(test.name)
+            && match &self.options.group {
+                Some(group) => test.group == group,
+                None => true,
+            }
+    }
+
+    /// Tell the QEMU-based test runner the group of the test at `index`, if
+    /// it has one, before the test starts, so that it sums up its result
+    fn start_group(&self, index: usize) {
+        let group = self.groups[self.test_groups[index]].name;
+        if !group.is_empty() {
+            send_event(format_args!("GROUP {}", group));
+        }
+    }
+
+    /// Send the `outcome` of the test at `index` to the QEMU-based test
+    /// runner, and count it in the summary of its group
+    fn send_result(&mut self, index: usize, outcome: fmt::Arguments) {
+        let event = format!("{}", outcome);
+        send_event(format_args!("TEST-RESULT {} {}", index, event));
+        let group = &mut self.groups[self.test_groups[index]];
+        match event.split(' ').next() {
+            Some("ok") | Some("flaky") => group.passed += 1,
+            Some("ignored") => group.ignored += 1,
+            Some("allowed_failure") => group.expected_failures += 1,
+            _ => group.failed += 1,
+        }
+    }
+
+    /// Run the test at position `index` in the order of the suite
+    fn run_test(&mut self, index: usize, test: Test<'a>) {
+        let known_issue = self.policy.find(test.name);
+        let expected_failure = known_issue.filter(KnownIssue::expect_failure);
+        // Number of the attempts of the test which failed before the reset
+        let mut attempts = 0;
+        if let Some(checkpoint) = &self.resumed {
+            // Ran before the reset
+            if index < checkpoint.index {
+                return;
+            }
+            if index == checkpoint.index && checkpoint.state == TestState::Retry {
+                attempts = checkpoint.attempts;
+            } else if index == checkpoint.index {
+                let failure = match checkpoint.state {
+                    // Panicked as expected, and then reset the machine
+                    TestState::Passed => None,
+                    TestState::Failed => Some("the test panicked".into()),
+                    // Reset the machine, as the watchdog timer does once the
+                    // test timed out
+                    TestState::Running | TestState::Retry => {
+                        Some("the machine was reset during the test".into())
+                    }
+                };
+                let attempts = checkpoint.attempts.max(1);
+                self.start_group(index);
+                self.report(index, test.name, failure, None, expected_failure, attempts);
+                return;
+            }
+        }
+
+        self.start_group(index);
+        send_json(format_args!(
+            r#"{{ "type": "test", "event": "started", "name": {} }}"#,
+            JsonString(test.name)
+        ));
+        send_event(format_args!("TEST-START {} {}", index, test.name));
+        if let Some(issue) = known_issue.filter(|issue| !issue.expect_failure()) {
+            info!("test {} ... skipped, {}", test.name, issue);
+            self.report_ignored(index, test.name, &format!("{}", issue));
+            self.skipped += 1;
+            return;
+        }
+        let unmet = test
+            .requirements
+            .iter()
+            .find(|requirement| !requirement.is_met(&self.console));
+        if let Some(requirement) = unmet {
+            info!("test {} ... skipped, {}", test.name, requirement);
+            self.report_ignored(index, test.name, &format!("{}", requirement));
+            self.skipped += 1;
+            return;
+        }
+        let ignored = test
+            .ignored
+            .filter(|_| self.options.ignored == Ignored::Skip);
+        if let Some(reason) = ignored {
+            info!("test {} ... ignored, {}", test.name, reason);
+            self.report_ignored(index, test.name, reason);
+            self.ignored += 1;
+            return;
+        }
+
+        info!("Running the test {}", test.name);
+        let bt = self.console.boot_services();
+        // The suite resumes after the test crashes or resets the machine, as
+        // the watchdog does
+        let watchdog = self.options.timeout.filter(|_| cfg!(feature = "resume"));
+        if cfg!(feature = "resume") {
+            resume::store_checkpoint(Some(&self.checkpoint(index, TestState::Running)));
+        }
+        if let Some(timeout) = watchdog {
+            bt.set_watchdog_timer(timeout as usize, WATCHDOG_CODE, None)
+                .expect_success("Failed to arm the watchdog timer");
+        }
+        if self.options.capture {
+            CAPTURE.start();
+        }
+        let start = read_timestamp();
+        let mut measured = None;
+        let failure = if let Some(expected) = test.expected_panic {
+            let checkpoint = self.checkpoint(index, TestState::Passed);
+            resume::reset_on_panic(Some(checkpoint), Some(expected));
+            (test.f)();
+            resume::reset_on_panic(None, None);
+            Some("the test did not panic".into())
+        } else {
+            // The suite goes on after the test panics
+            if cfg!(feature = "resume") {
+                resume::reset_on_panic(Some(self.checkpoint(index, TestState::Failed)), None);
+            }
+            let failure = if let Some(mut iteration) = test.bench {
+                if self.options.bench {
+                    measured = Some(measure(&mut *iteration, self.ticks_per_us));
+                } else {
+                    iteration();
+                }
+                None
+            } else if let Some(mut attempt) = test.attempt {
+                loop {
+                    if cfg!(feature = "resume") {
+                        // Which attempt panicked, after the reset
+                        let state = if attempts < test.retries {
+                            TestState::Retry
+                        } else {
+                            TestState::Failed
+                        };
+                        let mut checkpoint = self.checkpoint(index, state);
+                        checkpoint.attempts = attempts + 1;
+                        resume::reset_on_panic(Some(checkpoint), None);
+                    }
+                    let failure = attempt();
+                    attempts += 1;
+                    match failure {
+                        Some(failure) if attempts <= test.retries => warn!(
+                            "Attempt {} of the test {} failed, running it again: {}",
+                            attempts, test.name, failure
+                        ),
+                        failure => break failure,
+                    }
+                }
+            } else {
+                (test.f)()
+            };
+            resume::reset_on_panic(None, None);
+            failure
+        };
+        let duration = (read_timestamp() - start) / self.ticks_per_us;
+        if self.options.capture {
+            // The log also shows why a test passed while it was expected to
+            // fail
+            CAPTURE.stop(failure.is_some() || expected_failure.is_some());
+        }
+        if watchdog.is_some() {
+            bt.set_watchdog_timer(0, WATCHDOG_CODE, None)
+                .expect_success("Failed to disarm the watchdog timer");
+        }
+        info!("The test {} took {}", test.name, Duration(duration));
+        self.durations.push((index, duration));
+        match measured {
+            Some((median, deviation)) if expected_failure.is_none() => {
+                info!(
+                    "test {} ... bench: {} ns/iter (+/- {})",
+                    test.name, median, deviation
+                );
+                send_json(format_args!(
+                    r#"{{ "type": "bench", "name": {}, "median": {}, "deviation": {} }}"#,
+                    JsonString(test.name),
+                    median,
+                    deviation
+                ));
+                self.send_result(index, format_args!("ok"));
+                self.print_result('.', Color::Green);
+                self.measured += 1;
+            }
+            _ => self.report(
+                index,
+                test.name,
+                failure,
+                Some(duration),
+                expected_failure,
+                attempts.max(1),
+            ),
+        }
+    }
+
+    /// Report the result of the test at `index`, which ran for `duration`
+    /// microseconds if it was timed
+    ///
+    /// A test which is expected to fail for a known issue is counted apart
+    /// if it fails, and fails if it passes, so that the issue is closed. A
+    /// test which passed after more than one of its `attempts` is flaky.
+    fn report(
+        &mut self,
+        index: usize,
+        name: &str,
+        failure: Option<String>,
+        duration: Option<u64>,
+        expected_failure: Option<KnownIssue>,
+        attempts: usize,
+    ) {
+        let (event, failure) = match (failure, expected_failure) {
+            (None, None) if attempts > 1 => {
+                warn!(
+                    "test {} ... ok, flaky: passed on attempt {}",
+                    name, attempts
+                );
+                self.passed += 1;
+                self.flaky += 1;
+                ("ok", None)
+            }
+            (None, None) => {
+                info!("test {} ... ok", name);
+                self.passed += 1;
+                ("ok", None)
+            }
+            (Some(failure), Some(issue)) => {
+                warn!(
+                    "test {} ... FAILED as expected: {}, {}",
+                    name, failure, issue
+                );
+                self.expected_failures += 1;
+                ("allowed_failure", Some(failure))
+            }
+            (None, Some(issue)) => {
+                let failure = format!("the test passed, but is expected to fail: {}", issue);
+                error!("test {} ... FAILED: {}", name, failure);
+                self.failed.push(index);
+                ("failed", Some(failure))
+            }
+            (Some(failure), None) => {
+                error!("test {} ... FAILED: {}", name, failure);
+                self.failed.push(index);
+                ("failed", Some(failure))
+            }
+        };
+        let mut line = format!(
+            r#"{{ "type": "test", "name": {}, "event": "{}""#,
+            JsonString(name),
+            event
+        );
+        if let Some(duration) = duration {
+            let _ = write!(line, r#", "exec_time": {}"#, JsonSeconds(duration));
+        }
+        if let Some(failure) = &failure {
+            let _ = write!(line, r#", "stdout": {}"#, JsonString(failure));
+        }
+        if attempts > 1 {
+            let _ = write!(line, r#", "attempts": {}"#, attempts);
+        }
+        send_json(format_args!("{} }}", line));
+        // The QEMU-based test runner lists the flaky tests apart
+        let (symbol, color) = match event {
+            "ok" if attempts > 1 => ('f', Color::Yellow),
+            "ok" => ('.', Color::Green),
+            "allowed_failure" => ('x', Color::Yellow),
+            _ => ('F', Color::Red),
+        };
+        if event == "ok" && attempts > 1 {
+            self.send_result(index, format_args!("flaky {}", attempts));
+        } else {
+            self.send_result(index, format_args!("{}", event));
+        }
+        self.print_result(symbol, color);
+    }
+
+    /// Report the test `name` at `index` as ignored for `reason`
+    fn report_ignored(&mut self, index: usize, name: &str, reason: &str) {
+        send_json(format_args!(
+            r#"{{ "type": "test", "name": {}, "event": "ignored", "message": {} }}"#,
+            JsonString(name),
+            JsonString(reason)
+        ));
+        self.send_result(index, format_args!("ignored"));
+        self.print_result('i', Color::Yellow);
+    }
+
+    /// Print the character of a result with `--quiet`, as libtest does, in
+    /// `color` unless `--no-color` is given
+    fn print_result(&mut self, symbol: char, color: Color) {
+        if self.options.verbosity != Verbosity::Quiet {
+            return;
+        }
+        let colored = self.options.color;
+        let stdout = self.console.stdout();
+        if colored {
+            let _ = stdout.set_color(color, Color::Black);
+        }
+        let _ = stdout.write_char(symbol);
+        if colored {
+            let _ = stdout.set_color(Color::LightGray, Color::Black);
+        }
+    }
+
+    /// Progress of the suite at the test at `index`, in `state`
+    fn checkpoint(&self, index: usize, state: TestState) -> Checkpoint {
+        Checkpoint {
+            index,
+            state,
+            passed: self.passed,
+            measured: self.measured,
+            ignored: self.ignored,
+            skipped: self.skipped,
+            expected_failures: self.expected_failures,
+            flaky: self.flaky,
+            attempts: 0,
+            seed: self.seed,
+            failed: self.failed.clone(),
+        }
+    }
+
+    /// Run the tests which may run on the application processors on all of
+    /// them at once, and replace them with their results, which are then
+    /// reported in the order of the tests
+    ///
+    /// The tests which could not run there, as when there are no application
+    /// processors, run on the bootstrap processor as usual. Those which the
+    /// policy skips do not run at all.
+    fn run_in_parallel(&self, tests: &mut [Test]) {
+        let bt = self.console.boot_services();
+        let mp = match bt.locate_protocol::<MpServices>() {
+            Ok(mp) => unsafe { &*mp.log().get() },
+            Err(_) => {
+                warn!("The tests run one by one without multi-processor services");
+                return;
+            }
+        };
+        let runs_on_processors = |test: &Test| {
+            let skipped = self
+                .policy
+                .find(test.name)
+                .filter(|issue| !issue.expect_failure());
+            test.processor_test.is_some() && skipped.is_none()
+        };
+        let processor_tests: Vec<ProcessorTest> = tests
+            .iter()
+            .filter(|test| runs_on_processors(test))
+            .filter_map(|test| test.processor_test)
+            .collect();
+        if processor_tests.is_empty() {
+            return;
+        }
+        let mut results: Vec<_> = processor_tests.iter().map(|_| None).collect();
+        let batch = Batch {
+            tests: &processor_tests,
+            next: AtomicUsize::new(0),
+            results: results.as_mut_ptr(),
+        };
+        info!(
+            "Running {} tests on the application processors",
+            processor_tests.len()
+        );
+        let timeout = self.options.timeout.map(core::time::Duration::from_secs);
+        let arg = &batch as *const Batch as *mut c_void;
+        if let Err(error) = mp.startup_all_aps(false, run_batch, arg, timeout) {
+            warn!(
+                "The application processors did not run the tests: {:?}",
+                error.status()
+            );
+        }
+        // The processors which timed out stopped during the tests they took
+        let started = batch.next.load(Ordering::Acquire).min(results.len());
+        for result in &mut results[..started] {
+            result.get_or_insert((
+                Err("timed out on an application processor"),
+                ProcessorLog::new(),
+            ));
+        }
+
+        let mut results = results.into_iter();
+        for test in tests.iter_mut().filter(|test| runs_on_processors(test)) {
+            if let Some(Some((result, log))) = results.next() {
+                test.f = Box::new(move || {
+                    log.flush();
+                    result.failure()
+                });
+            }
+        }
+    }
+
+    /// Run the selected tests, shuffled if they are, or list them with
+    /// `--list`, and print a summary of their results
+    ///
+    /// Returns the number of tests which failed, with which the runner then
+    /// tells whether it succeeded.
+    pub fn end(mut self) -> usize {
+        let registered = core::mem::take(&mut self.tests);
+        let count = registered.len();
+        let mut tests: Vec<Test> = registered
+            .into_iter()
+            .filter(|test| self.matches(test))
+            .collect();
+        // A resumed suite keeps the order of the tests
+        self.seed = match &self.resumed {
+            Some(checkpoint) => checkpoint.seed,
+            None if self.options.shuffle => Some(
+                self.options
+                    .shuffle_seed
+                    .unwrap_or_else(|| random_seed(&self.console)),
+            ),
+            None => None,
+        };
+        if let Some(seed) = self.seed {
+            info!("Shuffling the tests with the seed {}", seed);
+            shuffle(&mut tests, seed);
+        }
+        if tests.is_empty() && !self.options.filters.is_empty() {
+            warn!("No test matches {:?}", self.options.filters);
+        }
+        if self.options.list {
+            for test in &tests {
+                let _ = writeln!(self.console.stdout(), "{}: test", test.name);
+            }
+            return 0;
+        }
+        let names: Vec<&str> = tests.iter().map(|test| test.name).collect();
+        for test in &tests {
+            let group = match self
+                .groups
+                .iter()
+                .position(|group| group.name == test.group)
+            {
+                Some(group) => group,
+                None => {
+                    self.groups.push(GroupSummary {
+                        name: test.group,
+                        passed: 0,
+                        failed: 0,
+                        ignored: 0,
+                        expected_failures: 0,
+                    });
+                    self.groups.len() - 1
+                }
+            };
+            self.test_groups.push(group);
+        }
+        match &self.resumed {
+            Some(checkpoint) => {
+                info!(
+                    "Resuming the {} tests of {} after the test {}",
+                    tests.len(),
+                    self.name,
+                    names.get(checkpoint.index).unwrap_or(&"?")
+                );
+                self.passed = checkpoint.passed;
+                self.measured = checkpoint.measured;
+                self.ignored = checkpoint.ignored;
+                self.skipped = checkpoint.skipped;
+                self.expected_failures = checkpoint.expected_failures;
+                self.flaky = checkpoint.flaky;
+                self.failed = checkpoint.failed.clone();
+                send_event(format_args!(
+                    "SUITE-RESUME {} {}",
+                    checkpoint.index, self.name
+                ));
+            }
+            None => {
+                info!("Running {} tests of {}", tests.len(), self.name);
+                send_json(format_args!(
+                    r#"{{ "type": "suite", "event": "started", "test_count": {} }}"#,
+                    tests.len()
+                ));
+                send_event(format_args!("SUITE-BEGIN {} {}", tests.len(), self.name));
+            }
+        }
+        let filtered_out = count - tests.len();
+        // After a reset, the tests which were not reported yet run one by one
+        if self.options.parallel && self.resumed.is_none() {
+            self.run_in_parallel(&mut tests);
+        }
+        for (index, test) in tests.into_iter().enumerate() {
+            self.run_test(index, test);
+        }
+
+        self.durations
+            .sort_by_key(|&(_, duration)| core::cmp::Reverse(duration));
+        for &(index, duration) in self.durations.iter().take(SLOWEST_TESTS) {
+            info!("Slow test: {} took {}", names[index], Duration(duration));
+        }
+        let result = if self.failed.is_empty() {
+            "ok"
+        } else {
+            "FAILED"
+        };
+        let summary = format!(
+            concat!(
+                "test result: {}. {} passed; {} failed; {} ignored; {} measured; ",
+                "{} skipped; {} expected failures; {} flaky"
+            ),
+            result,
+            self.passed,
+            self.failed.len(),
+            self.ignored,
+            self.measured,
+            self.skipped,
+            self.expected_failures,
+            self.flaky
+        );
+        if self.options.verbosity == Verbosity::Quiet {
+            // After the characters of the results
+            let _ = writeln!(self.console.stdout(), "\n{}", summary);
+        } else {
+            info!("{}", summary);
+        }
+        for group in self.groups.iter().filter(|group| !group.name.is_empty()) {
+            info!(
+                "group {}: {} passed; {} failed; {} ignored; {} expected failures",
+                group.name, group.passed, group.failed, group.ignored, group.expected_failures
+            );
+        }
+        if !self.failed.is_empty() {
+            let failed: Vec<&str> = self
+                .failed
+                .iter()
+                .filter_map(|&index| names.get(index).copied())
+                .collect();
+            error!("Failed tests: {}", failed.join(", "));
+        }
+        if let Some(seed) = self.seed {
+            info!(
+                "The tests were shuffled with the seed {}, which `--shuffle-seed` takes",
+                seed
+            );
+        }
+        send_json(format_args!(
+            concat!(
+                r#"{{ "type": "suite", "event": {}, "passed": {}, "failed": {}, "#,
+                r#""ignored": {}, "skipped": {}, "expected_failures": {}, "#,
+                r#""flaky": {}, "measured": {}, "filtered_out": {} }}"#
+            ),
+            JsonString(if self.failed.is_empty() {
+                "ok"
+            } else {
+                "failed"
+            }),
+            self.passed,
+            self.failed.len(),
+            self.ignored,
+            self.skipped,
+            self.expected_failures,
+            self.flaky,
+            self.measured,
+            filtered_out
+        ));
+        send_event(format_args!("SUITE-END {}", names.len()));
+        if cfg!(feature = "resume") {
+            resume::store_checkpoint(None);
+        }
+        self.failed.len()
+    }
+}
+
+/// Tests which the application processors take one by one
+struct Batch<'a> {
+    tests: &'a [ProcessorTest],
+    /// Index of the next test to take
+    next: AtomicUsize,
+    /// Result and log of each test, which only the processor that took it
+    /// writes
+    results: *mut Option<(core::result::Result<(), &'static str>, ProcessorLog)>,
+}
+
+/// Run the tests of a `Batch` on an application processor until there are
+/// none left
+extern "efiapi" fn run_batch(arg: *mut c_void) {
+    let batch = unsafe { &*(arg as *const Batch) };
+    loop {
+        let index = batch.next.fetch_add(1, Ordering::AcqRel);
+        if index >= batch.tests.len() {
+            return;
+        }
+        let mut log = ProcessorLog::new();
+        let result = (batch.tests[index])(&mut log);
+        unsafe { *batch.results.add(index) = Some((result, log)) };
+    }
+}
+
+/// A seed for the order of the tests, from the time and the timestamp
+/// counter of the processor, which change from one run to the next
+fn random_seed(st: &SystemTable<Boot>) -> u64 {
+    let time = st.runtime_services().get_time().map_or(0, |time| {
+        let time = time.log();
+        let seconds = (u64::from(time.day()) * 24 + u64::from(time.hour())) * 3600
+            + u64::from(time.minute()) * 60
+            + u64::from(time.second());
+        seconds * 1_000_000_000 + u64::from(time.nanosecond())
+    });
+    time ^ read_timestamp().rotate_left(32)
+}
+
+/// Shuffle `items` with the Fisher-Yates algorithm, with the SplitMix64
+/// sequence which starts at `seed`, so that a seed always gives the same
+/// order
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    for i in (1..items.len()).rev() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        items.swap(i, (z % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Number of batches of iterations which a benchmark runs
+const BENCH_BATCHES: usize = 10;
+
+/// Microseconds for which a batch of iterations of a benchmark runs, at least
+const BENCH_BATCH_US: u64 = 1_000;
+
+/// Measure how long `iteration` takes, and return the median and the range of
+/// the batches, in nanoseconds per iteration
+fn measure(iteration: &mut dyn FnMut(), ticks_per_us: u64) -> (u64, u64) {
+    let mut run_batch = |iterations: u64| {
+        let start = read_timestamp();
+        for _ in 0..iterations {
+            iteration();
+        }
+        read_timestamp() - start
+    };
+
+    // Double the size of the batches until they take long enough
+    let mut iterations = 1;
+    while run_batch(iterations) < BENCH_BATCH_US * ticks_per_us && iterations < 1 << 30 {
+        iterations *= 2;
+    }
+
+    let mut batches = [0; BENCH_BATCHES];
+    for batch in batches.iter_mut() {
+        *batch = run_batch(iterations) * 1000 / ticks_per_us / iterations;
+    }
+    batches.sort_unstable();
+    (
+        batches[BENCH_BATCHES / 2],
+        batches[BENCH_BATCHES - 1] - batches[0],
+    )
+}
+
+/// A duration in microseconds, printed in the most readable unit
+struct Duration(u64);
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            us if us < 1000 => write!(f, "{} us", us),
+            us if us < 1_000_000 => write!(f, "{}.{:03} ms", us / 1000, us % 1000),
+            us => write!(f, "{}.{:03} s", us / 1_000_000, us / 1000 % 1000),
+        }
+    }
+}